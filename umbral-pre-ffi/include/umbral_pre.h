@@ -0,0 +1,332 @@
+#ifndef UMBRAL_PRE_H
+#define UMBRAL_PRE_H
+
+/* Generated with cbindgen. Do not edit manually. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+/**
+ * Version of the C ABI described by this header.
+ * Incremented on every incompatible change to the exported interface;
+ * compare with `umbral_abi_version()` at runtime.
+ */
+#define UMBRAL_PRE_ABI_VERSION 1
+
+/**
+ * Result codes of the API functions.
+ */
+typedef enum UmbralStatus {
+  /**
+   * The operation succeeded.
+   */
+  UMBRAL_STATUS_OK = 0,
+  /**
+   * One of the required pointer arguments is null.
+   */
+  UMBRAL_STATUS_NULL_POINTER = 1,
+  /**
+   * Failed to deserialize an object from the given bytes.
+   */
+  UMBRAL_STATUS_INVALID_BYTES = 2,
+  /**
+   * Verification of a key or capsule fragment failed.
+   */
+  UMBRAL_STATUS_VERIFICATION_FAILED = 3,
+  /**
+   * Encryption failed.
+   */
+  UMBRAL_STATUS_ENCRYPTION_FAILED = 4,
+  /**
+   * Decryption failed.
+   */
+  UMBRAL_STATUS_DECRYPTION_FAILED = 5,
+  /**
+   * One of the arguments is out of its allowed range.
+   */
+  UMBRAL_STATUS_INVALID_ARGUMENT = 6,
+  /**
+   * The library panicked; this is a bug, and should be reported.
+   */
+  UMBRAL_STATUS_INTERNAL_ERROR = 7,
+} UmbralStatus;
+
+/**
+ * An opaque object.
+ */
+typedef struct UmbralCapsule UmbralCapsule;
+
+/**
+ * An opaque object.
+ */
+typedef struct UmbralCapsuleFrag UmbralCapsuleFrag;
+
+/**
+ * An opaque object.
+ */
+typedef struct UmbralKeyFrag UmbralKeyFrag;
+
+/**
+ * An opaque object.
+ */
+typedef struct UmbralPublicKey UmbralPublicKey;
+
+/**
+ * An opaque object.
+ */
+typedef struct UmbralSecretKey UmbralSecretKey;
+
+/**
+ * An opaque object.
+ */
+typedef struct UmbralSigner UmbralSigner;
+
+/**
+ * An opaque object.
+ */
+typedef struct UmbralVerifiedCapsuleFrag UmbralVerifiedCapsuleFrag;
+
+/**
+ * An opaque object.
+ */
+typedef struct UmbralVerifiedKeyFrag UmbralVerifiedKeyFrag;
+
+/**
+ * A byte array allocated by the library.
+ */
+typedef struct UmbralBuffer {
+  /**
+   * Pointer to the data.
+   */
+  uint8_t *data;
+  /**
+   * Length of the data.
+   */
+  uintptr_t len;
+} UmbralBuffer;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+/**
+ * Returns the ABI version the library was built with.
+ * Callers can compare it with `UMBRAL_PRE_ABI_VERSION` from the header they were compiled against.
+ */
+uint32_t umbral_abi_version(void);
+
+/**
+ * Releases a buffer returned by the library.
+ * The contents are zeroized first, since buffers can hold secret keys or plaintexts.
+ */
+void umbral_buffer_free(UmbralBuffer buffer);
+
+/**
+ * Releases the object.
+ */
+void umbral_secret_key_free(UmbralSecretKey *obj);
+
+/**
+ * Serializes the object into a newly allocated buffer.
+ */
+UmbralStatus umbral_secret_key_to_bytes(const UmbralSecretKey *obj, UmbralBuffer *out);
+
+/**
+ * Deserializes the object from the given bytes.
+ */
+UmbralStatus umbral_secret_key_from_bytes(const uint8_t *data,
+                                          uintptr_t len,
+                                          UmbralSecretKey **out);
+
+/**
+ * Generates a secret key using the default RNG.
+ * Returns null if the key could not be generated.
+ */
+UmbralSecretKey *umbral_secret_key_random(void);
+
+/**
+ * Releases the object.
+ */
+void umbral_public_key_free(UmbralPublicKey *obj);
+
+/**
+ * Serializes the object into a newly allocated buffer.
+ */
+UmbralStatus umbral_public_key_to_bytes(const UmbralPublicKey *obj, UmbralBuffer *out);
+
+/**
+ * Deserializes the object from the given bytes.
+ */
+UmbralStatus umbral_public_key_from_bytes(const uint8_t *data,
+                                          uintptr_t len,
+                                          UmbralPublicKey **out);
+
+/**
+ * Creates the public key corresponding to the given secret key.
+ */
+UmbralStatus umbral_public_key_from_secret_key(const UmbralSecretKey *sk, UmbralPublicKey **out);
+
+/**
+ * Releases the object.
+ */
+void umbral_signer_free(UmbralSigner *obj);
+
+/**
+ * Creates a signer out of a secret key.
+ */
+UmbralStatus umbral_signer_new(const UmbralSecretKey *sk, UmbralSigner **out);
+
+/**
+ * Releases the object.
+ */
+void umbral_capsule_free(UmbralCapsule *obj);
+
+/**
+ * Serializes the object into a newly allocated buffer.
+ */
+UmbralStatus umbral_capsule_to_bytes(const UmbralCapsule *obj, UmbralBuffer *out);
+
+/**
+ * Deserializes the object from the given bytes.
+ */
+UmbralStatus umbral_capsule_from_bytes(const uint8_t *data, uintptr_t len, UmbralCapsule **out);
+
+/**
+ * Releases the object.
+ */
+void umbral_key_frag_free(UmbralKeyFrag *obj);
+
+/**
+ * Serializes the object into a newly allocated buffer.
+ */
+UmbralStatus umbral_key_frag_to_bytes(const UmbralKeyFrag *obj, UmbralBuffer *out);
+
+/**
+ * Deserializes the object from the given bytes.
+ */
+UmbralStatus umbral_key_frag_from_bytes(const uint8_t *data, uintptr_t len, UmbralKeyFrag **out);
+
+/**
+ * Releases the object.
+ */
+void umbral_verified_key_frag_free(UmbralVerifiedKeyFrag *obj);
+
+/**
+ * Serializes the object into a newly allocated buffer.
+ */
+UmbralStatus umbral_verified_key_frag_to_bytes(const UmbralVerifiedKeyFrag *obj,
+                                               UmbralBuffer *out);
+
+/**
+ * Verifies the key fragment.
+ * `delegating_pk` and `receiving_pk` can be null.
+ */
+UmbralStatus umbral_key_frag_verify(const UmbralKeyFrag *kfrag,
+                                    const UmbralPublicKey *verifying_pk,
+                                    const UmbralPublicKey *delegating_pk,
+                                    const UmbralPublicKey *receiving_pk,
+                                    UmbralVerifiedKeyFrag **out);
+
+/**
+ * Releases the object.
+ */
+void umbral_capsule_frag_free(UmbralCapsuleFrag *obj);
+
+/**
+ * Serializes the object into a newly allocated buffer.
+ */
+UmbralStatus umbral_capsule_frag_to_bytes(const UmbralCapsuleFrag *obj, UmbralBuffer *out);
+
+/**
+ * Deserializes the object from the given bytes.
+ */
+UmbralStatus umbral_capsule_frag_from_bytes(const uint8_t *data,
+                                            uintptr_t len,
+                                            UmbralCapsuleFrag **out);
+
+/**
+ * Releases the object.
+ */
+void umbral_verified_capsule_frag_free(UmbralVerifiedCapsuleFrag *obj);
+
+/**
+ * Serializes the object into a newly allocated buffer.
+ */
+UmbralStatus umbral_verified_capsule_frag_to_bytes(const UmbralVerifiedCapsuleFrag *obj,
+                                                   UmbralBuffer *out);
+
+/**
+ * Verifies the capsule fragment.
+ * `metadata` can be null; otherwise it is bound to the fragment as is.
+ */
+UmbralStatus umbral_capsule_frag_verify(const UmbralCapsuleFrag *cfrag,
+                                        const UmbralCapsule *capsule,
+                                        const UmbralPublicKey *verifying_pk,
+                                        const UmbralPublicKey *delegating_pk,
+                                        const UmbralPublicKey *receiving_pk,
+                                        const uint8_t *metadata,
+                                        uintptr_t metadata_len,
+                                        UmbralVerifiedCapsuleFrag **out);
+
+/**
+ * Encrypts the plaintext with the given public key.
+ */
+UmbralStatus umbral_encrypt(const UmbralPublicKey *pk,
+                            const uint8_t *plaintext,
+                            uintptr_t plaintext_len,
+                            UmbralCapsule **capsule_out,
+                            UmbralBuffer *ciphertext_out);
+
+/**
+ * Decrypts the ciphertext with the secret key it was encrypted for.
+ */
+UmbralStatus umbral_decrypt_original(const UmbralSecretKey *sk,
+                                     const UmbralCapsule *capsule,
+                                     const uint8_t *ciphertext,
+                                     uintptr_t ciphertext_len,
+                                     UmbralBuffer *plaintext_out);
+
+/**
+ * Generates `num_kfrags` key fragments.
+ * `kfrags_out` must point to an array of at least `num_kfrags` pointers.
+ * `threshold` must be between 1 and `num_kfrags`.
+ */
+UmbralStatus umbral_generate_kfrags(const UmbralSecretKey *delegating_sk,
+                                    const UmbralPublicKey *receiving_pk,
+                                    const UmbralSigner *signer,
+                                    uintptr_t threshold,
+                                    uintptr_t num_kfrags,
+                                    bool sign_delegating_key,
+                                    bool sign_receiving_key,
+                                    UmbralVerifiedKeyFrag **kfrags_out);
+
+/**
+ * Reencrypts the capsule with a verified key fragment.
+ * `metadata` can be null; otherwise it is bound to the fragment as is.
+ */
+UmbralStatus umbral_reencrypt(const UmbralCapsule *capsule,
+                              const UmbralVerifiedKeyFrag *kfrag,
+                              const uint8_t *metadata,
+                              uintptr_t metadata_len,
+                              UmbralVerifiedCapsuleFrag **out);
+
+/**
+ * Decrypts the ciphertext using the verified capsule fragments.
+ * `cfrags` must point to an array of `num_cfrags` pointers.
+ */
+UmbralStatus umbral_decrypt_reencrypted(const UmbralSecretKey *decrypting_sk,
+                                        const UmbralPublicKey *delegating_pk,
+                                        const UmbralCapsule *capsule,
+                                        const UmbralVerifiedCapsuleFrag *const *cfrags,
+                                        uintptr_t num_cfrags,
+                                        const uint8_t *ciphertext,
+                                        uintptr_t ciphertext_len,
+                                        UmbralBuffer *plaintext_out);
+
+#ifdef __cplusplus
+} // extern "C"
+#endif // __cplusplus
+
+#endif /* UMBRAL_PRE_H */
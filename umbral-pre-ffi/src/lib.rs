@@ -0,0 +1,801 @@
+//! C bindings for `umbral-pre`.
+//!
+//! All the objects are passed around as opaque pointers
+//! which must be released with the corresponding `*_free()` function.
+//! Byte arrays returned by the library are wrapped in [`Buffer`]
+//! and must be released with [`umbral_buffer_free()`].
+//!
+//! Functions that can fail return a [`Status`] and write the result into an output pointer,
+//! which is only written to on success.
+//! Panics are caught at the boundary and reported as [`Status::InternalError`].
+//!
+//! Any incompatible change to the exported interface must increment [`ABI_VERSION`]
+//! (along with `UMBRAL_PRE_ABI_VERSION` in `cbindgen.toml`) and update the ABI tests below.
+
+// Unsafe code is unavoidable when dealing with raw pointers;
+// all the unsafe operations are limited to the helpers below and the `*_free()` functions.
+#![allow(clippy::missing_safety_doc)]
+
+use core::ptr;
+use core::slice;
+use std::panic::{self, AssertUnwindSafe};
+
+use umbral_pre::{DeserializableFromArray, RawMetadata, SerializableToArray};
+use zeroize::{Zeroize, Zeroizing};
+
+/// Result codes of the API functions.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Status {
+    /// The operation succeeded.
+    Ok = 0,
+    /// One of the required pointer arguments is null.
+    NullPointer = 1,
+    /// Failed to deserialize an object from the given bytes.
+    InvalidBytes = 2,
+    /// Verification of a key or capsule fragment failed.
+    VerificationFailed = 3,
+    /// Encryption failed.
+    EncryptionFailed = 4,
+    /// Decryption failed.
+    DecryptionFailed = 5,
+    /// One of the arguments is out of its allowed range.
+    InvalidArgument = 6,
+    /// The library panicked; this is a bug, and should be reported.
+    InternalError = 7,
+}
+
+/// A byte array allocated by the library.
+#[repr(C)]
+pub struct Buffer {
+    /// Pointer to the data.
+    pub data: *mut u8,
+    /// Length of the data.
+    pub len: usize,
+}
+
+/// Version of the C ABI, exposed in the header as `UMBRAL_PRE_ABI_VERSION`.
+const ABI_VERSION: u32 = 1;
+
+impl Buffer {
+    fn from_boxed_slice(data: Box<[u8]>) -> Self {
+        let len = data.len();
+        let data = Box::into_raw(data) as *mut u8;
+        Self { data, len }
+    }
+}
+
+/// Returns the ABI version the library was built with.
+/// Callers can compare it with `UMBRAL_PRE_ABI_VERSION` from the header they were compiled against.
+#[no_mangle]
+pub extern "C" fn umbral_abi_version() -> u32 {
+    ABI_VERSION
+}
+
+/// Releases a buffer returned by the library.
+/// The contents are zeroized first, since buffers can hold secret keys or plaintexts.
+#[no_mangle]
+pub unsafe extern "C" fn umbral_buffer_free(buffer: Buffer) {
+    if buffer.data.is_null() {
+        return;
+    }
+    let mut data = Box::from_raw(ptr::slice_from_raw_parts_mut(buffer.data, buffer.len));
+    data.zeroize();
+}
+
+// Interprets a (pointer, length) pair as a slice; a null pointer is treated as an empty slice.
+unsafe fn as_slice<'a>(data: *const u8, len: usize) -> &'a [u8] {
+    if data.is_null() {
+        &[]
+    } else {
+        slice::from_raw_parts(data, len)
+    }
+}
+
+// Interprets a (pointer, length) pair as an optional slice; a null pointer means `None`.
+unsafe fn as_optional_slice<'a>(data: *const u8, len: usize) -> Option<&'a [u8]> {
+    if data.is_null() {
+        None
+    } else {
+        Some(slice::from_raw_parts(data, len))
+    }
+}
+
+// Runs the body of an entry point, reporting a panic as `Status::InternalError`
+// instead of unwinding into the calling code.
+fn catch_panic(body: impl FnOnce() -> Status) -> Status {
+    panic::catch_unwind(AssertUnwindSafe(body)).unwrap_or(Status::InternalError)
+}
+
+unsafe fn write_object<T>(out: *mut *mut T, obj: T) {
+    *out = Box::into_raw(Box::new(obj));
+}
+
+macro_rules! null_check {
+    ($($ptr:expr),+) => {
+        if $($ptr.is_null())||+ {
+            return Status::NullPointer;
+        }
+    };
+}
+
+macro_rules! opaque_type {
+    ($name:ident, $backend:ty, $free:ident) => {
+        /// An opaque object.
+        pub struct $name($backend);
+
+        /// Releases the object.
+        #[no_mangle]
+        pub unsafe extern "C" fn $free(obj: *mut $name) {
+            if !obj.is_null() {
+                drop(Box::from_raw(obj));
+            }
+        }
+    };
+}
+
+macro_rules! serializable {
+    ($name:ident, $to_bytes:ident) => {
+        /// Serializes the object into a newly allocated buffer.
+        #[no_mangle]
+        pub unsafe extern "C" fn $to_bytes(obj: *const $name, out: *mut Buffer) -> Status {
+            catch_panic(|| {
+                null_check!(obj, out);
+                // The temporary copy may hold a secret key.
+                let bytes = Zeroizing::new((*obj).0.to_array());
+                *out = Buffer::from_boxed_slice(bytes.as_slice().into());
+                Status::Ok
+            })
+        }
+    };
+}
+
+macro_rules! deserializable {
+    ($name:ident, $backend:ty, $from_bytes:ident) => {
+        /// Deserializes the object from the given bytes.
+        #[no_mangle]
+        pub unsafe extern "C" fn $from_bytes(
+            data: *const u8,
+            len: usize,
+            out: *mut *mut $name,
+        ) -> Status {
+            catch_panic(|| {
+                null_check!(data, out);
+                match <$backend>::from_bytes(as_slice(data, len)) {
+                    Ok(obj) => {
+                        write_object(out, $name(obj));
+                        Status::Ok
+                    }
+                    Err(_) => Status::InvalidBytes,
+                }
+            })
+        }
+    };
+}
+
+opaque_type!(SecretKey, umbral_pre::SecretKey, umbral_secret_key_free);
+serializable!(SecretKey, umbral_secret_key_to_bytes);
+deserializable!(
+    SecretKey,
+    umbral_pre::SecretKey,
+    umbral_secret_key_from_bytes
+);
+
+/// Generates a secret key using the default RNG.
+/// Returns null if the key could not be generated.
+#[no_mangle]
+pub extern "C" fn umbral_secret_key_random() -> *mut SecretKey {
+    panic::catch_unwind(|| Box::into_raw(Box::new(SecretKey(umbral_pre::SecretKey::random()))))
+        .unwrap_or(ptr::null_mut())
+}
+
+opaque_type!(PublicKey, umbral_pre::PublicKey, umbral_public_key_free);
+serializable!(PublicKey, umbral_public_key_to_bytes);
+deserializable!(
+    PublicKey,
+    umbral_pre::PublicKey,
+    umbral_public_key_from_bytes
+);
+
+/// Creates the public key corresponding to the given secret key.
+#[no_mangle]
+pub unsafe extern "C" fn umbral_public_key_from_secret_key(
+    sk: *const SecretKey,
+    out: *mut *mut PublicKey,
+) -> Status {
+    catch_panic(|| {
+        null_check!(sk, out);
+        write_object(
+            out,
+            PublicKey(umbral_pre::PublicKey::from_secret_key(&(*sk).0)),
+        );
+        Status::Ok
+    })
+}
+
+opaque_type!(Signer, umbral_pre::Signer, umbral_signer_free);
+
+/// Creates a signer out of a secret key.
+#[no_mangle]
+pub unsafe extern "C" fn umbral_signer_new(sk: *const SecretKey, out: *mut *mut Signer) -> Status {
+    catch_panic(|| {
+        null_check!(sk, out);
+        write_object(out, Signer(umbral_pre::Signer::new(&(*sk).0)));
+        Status::Ok
+    })
+}
+
+opaque_type!(Capsule, umbral_pre::Capsule, umbral_capsule_free);
+serializable!(Capsule, umbral_capsule_to_bytes);
+deserializable!(Capsule, umbral_pre::Capsule, umbral_capsule_from_bytes);
+
+opaque_type!(KeyFrag, umbral_pre::KeyFrag, umbral_key_frag_free);
+serializable!(KeyFrag, umbral_key_frag_to_bytes);
+deserializable!(KeyFrag, umbral_pre::KeyFrag, umbral_key_frag_from_bytes);
+
+opaque_type!(
+    VerifiedKeyFrag,
+    umbral_pre::VerifiedKeyFrag,
+    umbral_verified_key_frag_free
+);
+serializable!(VerifiedKeyFrag, umbral_verified_key_frag_to_bytes);
+
+/// Verifies the key fragment.
+/// `delegating_pk` and `receiving_pk` can be null.
+#[no_mangle]
+pub unsafe extern "C" fn umbral_key_frag_verify(
+    kfrag: *const KeyFrag,
+    verifying_pk: *const PublicKey,
+    delegating_pk: *const PublicKey,
+    receiving_pk: *const PublicKey,
+    out: *mut *mut VerifiedKeyFrag,
+) -> Status {
+    catch_panic(|| {
+        null_check!(kfrag, verifying_pk, out);
+        let maybe_delegating_pk = delegating_pk.as_ref().map(|pk| &pk.0);
+        let maybe_receiving_pk = receiving_pk.as_ref().map(|pk| &pk.0);
+        match (*kfrag)
+            .0
+            .verify(&(*verifying_pk).0, maybe_delegating_pk, maybe_receiving_pk)
+        {
+            Ok(vkfrag) => {
+                write_object(out, VerifiedKeyFrag(vkfrag));
+                Status::Ok
+            }
+            Err(_) => Status::VerificationFailed,
+        }
+    })
+}
+
+opaque_type!(
+    CapsuleFrag,
+    umbral_pre::CapsuleFrag,
+    umbral_capsule_frag_free
+);
+serializable!(CapsuleFrag, umbral_capsule_frag_to_bytes);
+deserializable!(
+    CapsuleFrag,
+    umbral_pre::CapsuleFrag,
+    umbral_capsule_frag_from_bytes
+);
+
+opaque_type!(
+    VerifiedCapsuleFrag,
+    umbral_pre::VerifiedCapsuleFrag,
+    umbral_verified_capsule_frag_free
+);
+serializable!(VerifiedCapsuleFrag, umbral_verified_capsule_frag_to_bytes);
+
+/// Verifies the capsule fragment.
+/// `metadata` can be null; otherwise it is bound to the fragment as is.
+#[no_mangle]
+pub unsafe extern "C" fn umbral_capsule_frag_verify(
+    cfrag: *const CapsuleFrag,
+    capsule: *const Capsule,
+    verifying_pk: *const PublicKey,
+    delegating_pk: *const PublicKey,
+    receiving_pk: *const PublicKey,
+    metadata: *const u8,
+    metadata_len: usize,
+    out: *mut *mut VerifiedCapsuleFrag,
+) -> Status {
+    catch_panic(|| {
+        null_check!(
+            cfrag,
+            capsule,
+            verifying_pk,
+            delegating_pk,
+            receiving_pk,
+            out
+        );
+        match (*cfrag).0.verify_canonicalized(
+            &(*capsule).0,
+            &(*verifying_pk).0,
+            &(*delegating_pk).0,
+            &(*receiving_pk).0,
+            as_optional_slice(metadata, metadata_len),
+            &RawMetadata,
+        ) {
+            Ok(vcfrag) => {
+                write_object(out, VerifiedCapsuleFrag(vcfrag));
+                Status::Ok
+            }
+            Err(_) => Status::VerificationFailed,
+        }
+    })
+}
+
+/// Encrypts the plaintext with the given public key.
+#[no_mangle]
+pub unsafe extern "C" fn umbral_encrypt(
+    pk: *const PublicKey,
+    plaintext: *const u8,
+    plaintext_len: usize,
+    capsule_out: *mut *mut Capsule,
+    ciphertext_out: *mut Buffer,
+) -> Status {
+    catch_panic(|| {
+        null_check!(pk, capsule_out, ciphertext_out);
+        match umbral_pre::encrypt(&(*pk).0, as_slice(plaintext, plaintext_len)) {
+            Ok((capsule, ciphertext)) => {
+                write_object(capsule_out, Capsule(capsule));
+                *ciphertext_out = Buffer::from_boxed_slice(ciphertext);
+                Status::Ok
+            }
+            Err(_) => Status::EncryptionFailed,
+        }
+    })
+}
+
+/// Decrypts the ciphertext with the secret key it was encrypted for.
+#[no_mangle]
+pub unsafe extern "C" fn umbral_decrypt_original(
+    sk: *const SecretKey,
+    capsule: *const Capsule,
+    ciphertext: *const u8,
+    ciphertext_len: usize,
+    plaintext_out: *mut Buffer,
+) -> Status {
+    catch_panic(|| {
+        null_check!(sk, capsule, plaintext_out);
+        match umbral_pre::decrypt_original(
+            &(*sk).0,
+            &(*capsule).0,
+            as_slice(ciphertext, ciphertext_len),
+        ) {
+            Ok(plaintext) => {
+                *plaintext_out = Buffer::from_boxed_slice(plaintext);
+                Status::Ok
+            }
+            Err(_) => Status::DecryptionFailed,
+        }
+    })
+}
+
+/// Generates `num_kfrags` key fragments.
+/// `kfrags_out` must point to an array of at least `num_kfrags` pointers.
+/// `threshold` must be between 1 and `num_kfrags`.
+#[no_mangle]
+pub unsafe extern "C" fn umbral_generate_kfrags(
+    delegating_sk: *const SecretKey,
+    receiving_pk: *const PublicKey,
+    signer: *const Signer,
+    threshold: usize,
+    num_kfrags: usize,
+    sign_delegating_key: bool,
+    sign_receiving_key: bool,
+    kfrags_out: *mut *mut VerifiedKeyFrag,
+) -> Status {
+    catch_panic(|| {
+        null_check!(delegating_sk, receiving_pk, signer, kfrags_out);
+        if threshold == 0 || threshold > num_kfrags {
+            return Status::InvalidArgument;
+        }
+        let kfrags = umbral_pre::generate_kfrags(
+            &(*delegating_sk).0,
+            &(*receiving_pk).0,
+            &(*signer).0,
+            threshold,
+            num_kfrags,
+            sign_delegating_key,
+            sign_receiving_key,
+        );
+        for (i, kfrag) in kfrags.iter().cloned().enumerate() {
+            write_object(kfrags_out.add(i), VerifiedKeyFrag(kfrag));
+        }
+        Status::Ok
+    })
+}
+
+/// Reencrypts the capsule with a verified key fragment.
+/// `metadata` can be null; otherwise it is bound to the fragment as is.
+#[no_mangle]
+pub unsafe extern "C" fn umbral_reencrypt(
+    capsule: *const Capsule,
+    kfrag: *const VerifiedKeyFrag,
+    metadata: *const u8,
+    metadata_len: usize,
+    out: *mut *mut VerifiedCapsuleFrag,
+) -> Status {
+    catch_panic(|| {
+        null_check!(capsule, kfrag, out);
+        match umbral_pre::reencrypt_canonicalized(
+            &(*capsule).0,
+            &(*kfrag).0,
+            as_optional_slice(metadata, metadata_len),
+            &RawMetadata,
+        ) {
+            Ok(vcfrag) => {
+                write_object(out, VerifiedCapsuleFrag(vcfrag));
+                Status::Ok
+            }
+            // Not reachable with `RawMetadata`, which accepts any bytes.
+            Err(_) => Status::InvalidBytes,
+        }
+    })
+}
+
+/// Decrypts the ciphertext using the verified capsule fragments.
+/// `cfrags` must point to an array of `num_cfrags` pointers.
+#[no_mangle]
+pub unsafe extern "C" fn umbral_decrypt_reencrypted(
+    decrypting_sk: *const SecretKey,
+    delegating_pk: *const PublicKey,
+    capsule: *const Capsule,
+    cfrags: *const *const VerifiedCapsuleFrag,
+    num_cfrags: usize,
+    ciphertext: *const u8,
+    ciphertext_len: usize,
+    plaintext_out: *mut Buffer,
+) -> Status {
+    catch_panic(|| {
+        null_check!(decrypting_sk, delegating_pk, capsule, cfrags, plaintext_out);
+        let cfrag_ptrs = slice::from_raw_parts(cfrags, num_cfrags);
+        if cfrag_ptrs.iter().any(|cfrag| cfrag.is_null()) {
+            return Status::NullPointer;
+        }
+        let backend_cfrags: Vec<umbral_pre::VerifiedCapsuleFrag> =
+            cfrag_ptrs.iter().map(|cfrag| (**cfrag).0.clone()).collect();
+        match umbral_pre::decrypt_reencrypted(
+            &(*decrypting_sk).0,
+            &(*delegating_pk).0,
+            &(*capsule).0,
+            &backend_cfrags,
+            as_slice(ciphertext, ciphertext_len),
+        ) {
+            Ok(plaintext) => {
+                *plaintext_out = Buffer::from_boxed_slice(plaintext);
+                Status::Ok
+            }
+            Err(_) => Status::DecryptionFailed,
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+
+    use core::mem;
+    use std::collections::BTreeSet;
+
+    use super::*;
+
+    const HEADER: &str = include_str!("../include/umbral_pre.h");
+
+    // The exported interface; removing or renaming any of these breaks downstream users.
+    macro_rules! exported_symbols {
+        ($($func:ident),+ $(,)?) => {
+            // Referencing the functions makes sure they still exist on the Rust side.
+            vec![$({ let _ = $func as *const (); stringify!($func) }),+]
+        };
+    }
+
+    fn exported() -> Vec<&'static str> {
+        exported_symbols![
+            umbral_abi_version,
+            umbral_buffer_free,
+            umbral_secret_key_free,
+            umbral_secret_key_to_bytes,
+            umbral_secret_key_from_bytes,
+            umbral_secret_key_random,
+            umbral_public_key_free,
+            umbral_public_key_to_bytes,
+            umbral_public_key_from_bytes,
+            umbral_public_key_from_secret_key,
+            umbral_signer_free,
+            umbral_signer_new,
+            umbral_capsule_free,
+            umbral_capsule_to_bytes,
+            umbral_capsule_from_bytes,
+            umbral_key_frag_free,
+            umbral_key_frag_to_bytes,
+            umbral_key_frag_from_bytes,
+            umbral_verified_key_frag_free,
+            umbral_verified_key_frag_to_bytes,
+            umbral_key_frag_verify,
+            umbral_capsule_frag_free,
+            umbral_capsule_frag_to_bytes,
+            umbral_capsule_frag_from_bytes,
+            umbral_verified_capsule_frag_free,
+            umbral_verified_capsule_frag_to_bytes,
+            umbral_capsule_frag_verify,
+            umbral_encrypt,
+            umbral_decrypt_original,
+            umbral_generate_kfrags,
+            umbral_reencrypt,
+            umbral_decrypt_reencrypted,
+        ]
+    }
+
+    // Finds the value of `name = <value>` or `#define name <value>` in the header.
+    fn header_value(name: &str) -> i64 {
+        let line = HEADER
+            .lines()
+            .map(|line| line.trim())
+            .find(|line| {
+                line.starts_with(&format!("{} ", name))
+                    || line.starts_with(&format!("#define {} ", name))
+            })
+            .unwrap_or_else(|| panic!("{} is missing from the header", name));
+        line.rsplit(&[' ', '='][..])
+            .find(|token| !token.is_empty())
+            .unwrap()
+            .trim_end_matches(',')
+            .parse()
+            .unwrap()
+    }
+
+    fn null<T>() -> *mut T {
+        ptr::null_mut()
+    }
+
+    #[test]
+    fn test_abi_symbols() {
+        // Every identifier followed by an opening parenthesis is a function declaration
+        // (or a reference to one in a comment, which must be exported as well).
+        let mut declared = BTreeSet::new();
+        for (start, _) in HEADER.match_indices("umbral_") {
+            let rest = &HEADER[start..];
+            let end = rest
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                .unwrap();
+            if rest[end..].starts_with('(') {
+                declared.insert(&rest[..end]);
+            }
+        }
+
+        let symbols = exported();
+        let expected: BTreeSet<&str> = symbols.iter().cloned().collect();
+        assert_eq!(expected.len(), symbols.len());
+        assert_eq!(declared, expected);
+    }
+
+    #[test]
+    fn test_abi_layout() {
+        assert_eq!(mem::size_of::<Status>(), 4);
+        let statuses = [
+            (Status::Ok, "UMBRAL_STATUS_OK"),
+            (Status::NullPointer, "UMBRAL_STATUS_NULL_POINTER"),
+            (Status::InvalidBytes, "UMBRAL_STATUS_INVALID_BYTES"),
+            (
+                Status::VerificationFailed,
+                "UMBRAL_STATUS_VERIFICATION_FAILED",
+            ),
+            (Status::EncryptionFailed, "UMBRAL_STATUS_ENCRYPTION_FAILED"),
+            (Status::DecryptionFailed, "UMBRAL_STATUS_DECRYPTION_FAILED"),
+            (Status::InvalidArgument, "UMBRAL_STATUS_INVALID_ARGUMENT"),
+            (Status::InternalError, "UMBRAL_STATUS_INTERNAL_ERROR"),
+        ];
+        for (status, name) in statuses.iter() {
+            assert_eq!(*status as i64, header_value(name));
+        }
+
+        let word = mem::size_of::<usize>();
+        assert_eq!(mem::size_of::<Buffer>(), 2 * word);
+        assert_eq!(mem::align_of::<Buffer>(), word);
+        let buffer = Buffer {
+            data: null(),
+            len: 0,
+        };
+        let base = &buffer as *const Buffer as usize;
+        assert_eq!(&buffer.data as *const *mut u8 as usize - base, 0);
+        assert_eq!(&buffer.len as *const usize as usize - base, word);
+    }
+
+    #[test]
+    fn test_abi_version() {
+        assert_eq!(
+            i64::from(umbral_abi_version()),
+            header_value("UMBRAL_PRE_ABI_VERSION")
+        );
+    }
+
+    #[test]
+    fn test_round_trip() {
+        unsafe {
+            let alice_sk = umbral_secret_key_random();
+            let mut alice_pk = null();
+            assert_eq!(
+                umbral_public_key_from_secret_key(alice_sk, &mut alice_pk),
+                Status::Ok
+            );
+
+            let signing_sk = umbral_secret_key_random();
+            let mut signer = null();
+            assert_eq!(umbral_signer_new(signing_sk, &mut signer), Status::Ok);
+            let mut verifying_pk = null();
+            assert_eq!(
+                umbral_public_key_from_secret_key(signing_sk, &mut verifying_pk),
+                Status::Ok
+            );
+
+            let bob_sk = umbral_secret_key_random();
+            let mut bob_pk = null();
+            assert_eq!(
+                umbral_public_key_from_secret_key(bob_sk, &mut bob_pk),
+                Status::Ok
+            );
+
+            let plaintext = b"peace at dawn";
+            let mut capsule = null();
+            let mut ciphertext = Buffer {
+                data: null(),
+                len: 0,
+            };
+            assert_eq!(
+                umbral_encrypt(
+                    alice_pk,
+                    plaintext.as_ptr(),
+                    plaintext.len(),
+                    &mut capsule,
+                    &mut ciphertext
+                ),
+                Status::Ok
+            );
+
+            let mut kfrags: [*mut VerifiedKeyFrag; 3] = [null(); 3];
+            assert_eq!(
+                umbral_generate_kfrags(
+                    alice_sk,
+                    bob_pk,
+                    signer,
+                    2,
+                    3,
+                    true,
+                    true,
+                    kfrags.as_mut_ptr()
+                ),
+                Status::Ok
+            );
+
+            // Simulate network transfer of kfrags
+            let mut kfrag_bytes = Buffer {
+                data: null(),
+                len: 0,
+            };
+            assert_eq!(
+                umbral_verified_key_frag_to_bytes(kfrags[0], &mut kfrag_bytes),
+                Status::Ok
+            );
+            let mut kfrag = null();
+            assert_eq!(
+                umbral_key_frag_from_bytes(kfrag_bytes.data, kfrag_bytes.len, &mut kfrag),
+                Status::Ok
+            );
+            let mut vkfrag = null();
+            assert_eq!(
+                umbral_key_frag_verify(kfrag, verifying_pk, alice_pk, bob_pk, &mut vkfrag),
+                Status::Ok
+            );
+
+            let metadata = b"metadata";
+            let mut cfrag0 = null();
+            assert_eq!(
+                umbral_reencrypt(
+                    capsule,
+                    vkfrag,
+                    metadata.as_ptr(),
+                    metadata.len(),
+                    &mut cfrag0
+                ),
+                Status::Ok
+            );
+            let mut cfrag1 = null();
+            assert_eq!(
+                umbral_reencrypt(capsule, kfrags[1], ptr::null(), 0, &mut cfrag1),
+                Status::Ok
+            );
+
+            let cfrags: [*const VerifiedCapsuleFrag; 2] = [cfrag0, cfrag1];
+            let mut plaintext_bob = Buffer {
+                data: null(),
+                len: 0,
+            };
+            assert_eq!(
+                umbral_decrypt_reencrypted(
+                    bob_sk,
+                    alice_pk,
+                    capsule,
+                    cfrags.as_ptr(),
+                    cfrags.len(),
+                    ciphertext.data,
+                    ciphertext.len,
+                    &mut plaintext_bob
+                ),
+                Status::Ok
+            );
+            assert_eq!(as_slice(plaintext_bob.data, plaintext_bob.len), plaintext);
+
+            // Invalid bytes are rejected
+            let mut bad_kfrag = null();
+            assert_eq!(
+                umbral_key_frag_from_bytes(kfrag_bytes.data, kfrag_bytes.len - 1, &mut bad_kfrag),
+                Status::InvalidBytes
+            );
+            assert!(bad_kfrag.is_null());
+
+            umbral_buffer_free(plaintext_bob);
+            umbral_buffer_free(kfrag_bytes);
+            umbral_buffer_free(ciphertext);
+            umbral_verified_capsule_frag_free(cfrag0);
+            umbral_verified_capsule_frag_free(cfrag1);
+            umbral_verified_key_frag_free(vkfrag);
+            umbral_key_frag_free(kfrag);
+            for kfrag in kfrags.iter() {
+                umbral_verified_key_frag_free(*kfrag);
+            }
+            umbral_capsule_free(capsule);
+            umbral_public_key_free(bob_pk);
+            umbral_secret_key_free(bob_sk);
+            umbral_public_key_free(verifying_pk);
+            umbral_signer_free(signer);
+            umbral_secret_key_free(signing_sk);
+            umbral_public_key_free(alice_pk);
+            umbral_secret_key_free(alice_sk);
+        }
+    }
+
+    #[test]
+    fn test_invalid_threshold() {
+        unsafe {
+            let alice_sk = umbral_secret_key_random();
+            let mut signer = null();
+            assert_eq!(umbral_signer_new(alice_sk, &mut signer), Status::Ok);
+            let bob_sk = umbral_secret_key_random();
+            let mut bob_pk = null();
+            assert_eq!(
+                umbral_public_key_from_secret_key(bob_sk, &mut bob_pk),
+                Status::Ok
+            );
+
+            let mut kfrags: [*mut VerifiedKeyFrag; 3] = [null(); 3];
+            for (threshold, num_kfrags) in [(0, 3), (4, 3)].iter() {
+                assert_eq!(
+                    umbral_generate_kfrags(
+                        alice_sk,
+                        bob_pk,
+                        signer,
+                        *threshold,
+                        *num_kfrags,
+                        true,
+                        true,
+                        kfrags.as_mut_ptr()
+                    ),
+                    Status::InvalidArgument
+                );
+            }
+            assert!(kfrags.iter().all(|kfrag| kfrag.is_null()));
+
+            umbral_public_key_free(bob_pk);
+            umbral_secret_key_free(bob_sk);
+            umbral_signer_free(signer);
+            umbral_secret_key_free(alice_sk);
+        }
+    }
+
+    #[test]
+    fn test_catch_panic() {
+        assert_eq!(catch_panic(|| Status::Ok), Status::Ok);
+        assert_eq!(catch_panic(|| panic!("bug")), Status::InternalError);
+    }
+}
@@ -9,8 +9,8 @@ use pyo3::PyObjectProtocol;
 
 use umbral_pre::{
     CapsuleFragVerificationError, DecryptionError, DeserializableFromArray, DeserializationError,
-    EncryptionError, KeyFragVerificationError, OpenReencryptedError, ReencryptionError,
-    SecretKeyFactoryError, SerializableToArray,
+    EncryptionError, KeyFragVerificationError, OpenReencryptedError, RawMetadata,
+    ReencryptionError, SecretKeyFactoryError, SerializableToArray,
 };
 
 // Helper traits to generalize implementing various Python protocol functions for our types.
@@ -49,6 +49,18 @@ fn from_bytes<T: FromSerializableBackend<U> + HasName, U: DeserializableFromArra
             DeserializationError::NotEnoughBytes => {
                 PyValueError::new_err("The given bytestring is too short")
             }
+            DeserializationError::UnsupportedVersion(version) => {
+                PyValueError::new_err(format!("Unsupported format version: {}", version))
+            }
+            DeserializationError::InvalidEncoding => {
+                PyValueError::new_err("The given string is not a valid encoding")
+            }
+            DeserializationError::InvalidPoint => {
+                PyValueError::new_err("The given bytestring contains an invalid curve point")
+            }
+            DeserializationError::InvalidScalar => {
+                PyValueError::new_err("The given bytestring contains an invalid scalar")
+            }
         })
 }
 
@@ -192,6 +204,12 @@ impl SecretKeyFactory {
             })
     }
 
+    pub fn make_subfactory(&self, label: &[u8]) -> Self {
+        Self {
+            backend: self.backend.make_subfactory(label),
+        }
+    }
+
     #[staticmethod]
     pub fn from_bytes(bytes: &[u8]) -> PyResult<Self> {
         from_bytes(bytes)
@@ -450,6 +468,19 @@ fn map_decryption_err(err: DecryptionError) -> PyErr {
             either someone tampered with the ciphertext or \
             you are using an incorrect decryption key.",
         ),
+        DecryptionError::PlaintextTooLarge => {
+            PyValueError::new_err("The plaintext exceeds the given size limit")
+        }
+        DecryptionError::WrongKey => {
+            GenericError::new_err("The secret key does not match the capsule")
+        }
+        DecryptionError::UnknownAlgorithm(id) => PyValueError::new_err(format!(
+            "The ciphertext uses an unsupported DEM algorithm ({})",
+            id
+        )),
+        DecryptionError::IncorrectSignature => {
+            GenericError::new_err("The plaintext is not signed by the given key")
+        }
     }
 }
 
@@ -507,6 +538,10 @@ impl KeyFrag {
             KeyFragVerificationError::DelegatingKeyNotProvided => VerificationError::new_err("A signature of a delegating key was included in this kfrag but the key is not provided"),
             KeyFragVerificationError::ReceivingKeyNotProvided => VerificationError::new_err("A signature of a receiving key was included in this kfrag, but the key is not provided"),
             KeyFragVerificationError::IncorrectSignature => VerificationError::new_err("Failed to verify the kfrag signature"),
+            KeyFragVerificationError::PolicyFingerprintMismatch => VerificationError::new_err("The keys do not match the policy fingerprint"),
+            KeyFragVerificationError::TimeNotProvided => VerificationError::new_err("The kfrag has a validity window, but no time was provided"),
+            KeyFragVerificationError::NotYetValid => VerificationError::new_err("The kfrag is not valid yet"),
+            KeyFragVerificationError::Expired => VerificationError::new_err("The kfrag has expired"),
         })
         .map(|backend_vkfrag| VerifiedKeyFrag { backend: backend_vkfrag })
     }
@@ -635,20 +670,34 @@ impl CapsuleFrag {
         receiving_pk: &PublicKey,
         metadata: Option<&[u8]>,
     ) -> PyResult<VerifiedCapsuleFrag> {
+        // The metadata is passed as raw bytes, so it is bound to the cfrag as is.
         self.backend
-            .verify(
+            .verify_canonicalized(
                 &capsule.backend,
                 &verifying_pk.backend,
                 &delegating_pk.backend,
                 &receiving_pk.backend,
                 metadata,
+                &RawMetadata,
             )
             .map_err(|err| match err {
                 CapsuleFragVerificationError::IncorrectKeyFragSignature => {
                     VerificationError::new_err("Invalid KeyFrag signature")
                 }
-                CapsuleFragVerificationError::IncorrectReencryption => {
-                    VerificationError::new_err("Failed to verify reencryption proof")
+                CapsuleFragVerificationError::IncorrectReencryption(check) => {
+                    VerificationError::new_err(format!(
+                        "Failed to verify reencryption proof ({})",
+                        check
+                    ))
+                }
+                CapsuleFragVerificationError::DelegatingKeyNotProvided => {
+                    VerificationError::new_err("Delegating key not provided")
+                }
+                CapsuleFragVerificationError::ReceivingKeyNotProvided => {
+                    VerificationError::new_err("Receiving key not provided")
+                }
+                CapsuleFragVerificationError::MalformedMetadata(err) => {
+                    PyValueError::new_err(format!("Malformed metadata ({})", err))
                 }
             })
             .map(|backend_vcfrag| VerifiedCapsuleFrag {
@@ -724,7 +773,14 @@ pub fn reencrypt(
     kfrag: &VerifiedKeyFrag,
     metadata: Option<&[u8]>,
 ) -> VerifiedCapsuleFrag {
-    let backend_vcfrag = umbral_pre::reencrypt(&capsule.backend, &kfrag.backend, metadata);
+    // `RawMetadata` accepts any bytes, so this cannot fail.
+    let backend_vcfrag = umbral_pre::reencrypt_canonicalized(
+        &capsule.backend,
+        &kfrag.backend,
+        metadata,
+        &RawMetadata,
+    )
+    .unwrap();
     VerifiedCapsuleFrag {
         backend: backend_vcfrag,
     }
@@ -763,6 +819,19 @@ pub fn decrypt_reencrypted(
             OpenReencryptedError::RepeatingCapsuleFrags => {
                 PyValueError::new_err("Some of the CapsuleFrags are repeated")
             }
+            OpenReencryptedError::MismatchedCapsule => {
+                PyValueError::new_err("Some of the CapsuleFrags were verified for another Capsule")
+            }
+            OpenReencryptedError::MismatchedDelegatingKey => {
+                PyValueError::new_err("The Capsule was created for another delegating key")
+            }
+            OpenReencryptedError::ForeignCapsuleFrag(position) => PyValueError::new_err(format!(
+                "CapsuleFrag {} was verified for another Capsule",
+                position
+            )),
+            OpenReencryptedError::NotEnoughCapsuleFrags => {
+                PyValueError::new_err("Not enough CapsuleFrags")
+            }
             // Will be removed when #39 is fixed
             OpenReencryptedError::ZeroHash => {
                 GenericError::new_err("An internally hashed value is zero")
@@ -16,6 +16,9 @@ class SecretKeyFactory:
     def secret_key_by_label(self, label: bytes) -> SecretKey:
         ...
 
+    def make_subfactory(self, label: bytes) -> SecretKeyFactory:
+        ...
+
 
 class PublicKey:
     @staticmethod
@@ -12,6 +12,8 @@ use wasm_bindgen::prelude::{wasm_bindgen, JsValue};
 use alloc::boxed::Box;
 use alloc::{vec, vec::Vec};
 
+use umbral_pre::RawMetadata;
+
 #[wasm_bindgen]
 pub struct SecretKey(umbral_pre::SecretKey);
 
@@ -101,12 +103,13 @@ impl CapsuleFrag {
         let metadata_ref: Option<&[u8]> = metadata.as_ref().map(|s| s.as_ref());
         VerifiedCapsuleFrag(
             self.0
-                .verify(
+                .verify_canonicalized(
                     &capsule.0,
                     &verifying_pk.0,
                     &delegating_pk.0,
                     &receiving_pk.0,
                     metadata_ref,
+                    &RawMetadata,
                 )
                 .unwrap(),
         )
@@ -302,6 +305,9 @@ pub fn reencrypt(
     metadata: Option<Box<[u8]>>,
 ) -> VerifiedCapsuleFrag {
     let metadata_slice = metadata.as_ref().map(|x| x.as_ref());
-    let backend_cfrag = umbral_pre::reencrypt(&capsule.0, &kfrag.0, metadata_slice);
+    // `RawMetadata` accepts any bytes, so this cannot fail.
+    let backend_cfrag =
+        umbral_pre::reencrypt_canonicalized(&capsule.0, &kfrag.0, metadata_slice, &RawMetadata)
+            .unwrap();
     VerifiedCapsuleFrag(backend_cfrag)
 }
@@ -1,7 +1,80 @@
+//! The benchmark suite.
+//!
+//! Run with `cargo bench`; a subset can be selected by the group name,
+//! e.g. `cargo bench -- decrypt_reencrypted`.
+//!
+//! Along with the usual Criterion reports, the point estimates of all the benchmarks
+//! are collected in `criterion/summary.json` in the target directory
+//! (a single JSON object mapping benchmark IDs to the contents of Criterion's `estimates.json`),
+//! so that the numbers can be compared between runs and machines.
+//!
+//! The arithmetic backend and the object sizes of the build are printed before the benchmarks;
+//! with `UMBRAL_REPORT_ONLY` set in the environment, only this report is printed
+//! (e.g. `UMBRAL_REPORT_ONLY=1 cargo bench --features force-32-bit`).
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
 use criterion::measurement::Measurement;
-use criterion::{criterion_group, criterion_main, BenchmarkGroup, Criterion};
+use criterion::{criterion_group, BenchmarkGroup, BenchmarkId, Criterion, Throughput};
+
+use umbral_pre::bench::{unsafe_hash_to_point, ArithmeticBackend, ObjectSizes};
+use umbral_pre::{
+    decrypt_reencrypted, encrypt, generate_kfrags, reencrypt, Capsule, CapsuleFrag, PublicKey,
+    SecretKey, Signer, VerifiedCapsuleFrag,
+};
 
-use umbral_pre::bench::unsafe_hash_to_point;
+// The thresholds (and the numbers of fragments) the per-fragment operations are measured with.
+const THRESHOLDS: &[(usize, usize)] = &[(2, 3), (4, 6), (8, 12), (16, 24), (32, 48)];
+
+const PLAINTEXT_SIZES: &[usize] = &[32, 1024, 65536];
+
+struct Keys {
+    delegating_sk: SecretKey,
+    delegating_pk: PublicKey,
+    receiving_sk: SecretKey,
+    receiving_pk: PublicKey,
+    signer: Signer,
+    verifying_pk: PublicKey,
+}
+
+impl Keys {
+    fn new() -> Self {
+        let delegating_sk = SecretKey::random();
+        let receiving_sk = SecretKey::random();
+        let signing_sk = SecretKey::random();
+        Self {
+            delegating_pk: PublicKey::from_secret_key(&delegating_sk),
+            receiving_pk: PublicKey::from_secret_key(&receiving_sk),
+            verifying_pk: PublicKey::from_secret_key(&signing_sk),
+            signer: Signer::new(&signing_sk),
+            delegating_sk,
+            receiving_sk,
+        }
+    }
+
+    fn cfrags(
+        &self,
+        capsule: &Capsule,
+        threshold: usize,
+        num_kfrags: usize,
+    ) -> Vec<VerifiedCapsuleFrag> {
+        generate_kfrags(
+            &self.delegating_sk,
+            &self.receiving_pk,
+            &self.signer,
+            threshold,
+            num_kfrags,
+            true,
+            true,
+        )
+        .iter()
+        .take(threshold)
+        .map(|kfrag| reencrypt(capsule, kfrag, None))
+        .collect()
+    }
+}
 
 fn bench_unsafe_hash_to_point<'a, M: Measurement>(group: &mut BenchmarkGroup<'a, M>) {
     let data = b"abcdefg";
@@ -11,11 +84,235 @@ fn bench_unsafe_hash_to_point<'a, M: Measurement>(group: &mut BenchmarkGroup<'a,
     });
 }
 
-fn bench_all(c: &mut Criterion) {
+fn bench_internals(c: &mut Criterion) {
     let mut group = c.benchmark_group("internals");
     bench_unsafe_hash_to_point(&mut group);
     group.finish();
 }
 
-criterion_group!(benches, bench_all);
-criterion_main!(benches);
+fn bench_encrypt(c: &mut Criterion) {
+    let keys = Keys::new();
+    let mut group = c.benchmark_group("encrypt");
+    for size in PLAINTEXT_SIZES.iter() {
+        let plaintext = vec![0u8; *size];
+        group.throughput(Throughput::Bytes(*size as u64));
+        group.bench_with_input(
+            BenchmarkId::from_parameter(size),
+            &plaintext,
+            |b, plaintext| b.iter(|| encrypt(&keys.delegating_pk, plaintext).unwrap()),
+        );
+    }
+    group.finish();
+}
+
+fn bench_generate_kfrags(c: &mut Criterion) {
+    let keys = Keys::new();
+    let mut group = c.benchmark_group("generate_kfrags");
+    group.sample_size(20);
+    for (threshold, num_kfrags) in THRESHOLDS.iter() {
+        group.bench_function(
+            BenchmarkId::from_parameter(format!("{}-of-{}", threshold, num_kfrags)),
+            |b| {
+                b.iter(|| {
+                    generate_kfrags(
+                        &keys.delegating_sk,
+                        &keys.receiving_pk,
+                        &keys.signer,
+                        *threshold,
+                        *num_kfrags,
+                        true,
+                        true,
+                    )
+                })
+            },
+        );
+    }
+    group.finish();
+}
+
+fn bench_reencrypt(c: &mut Criterion) {
+    let keys = Keys::new();
+    let (capsule, _ciphertext) = encrypt(&keys.delegating_pk, b"peace at dawn").unwrap();
+    let kfrags = generate_kfrags(
+        &keys.delegating_sk,
+        &keys.receiving_pk,
+        &keys.signer,
+        2,
+        3,
+        true,
+        true,
+    );
+
+    let mut group = c.benchmark_group("reencrypt");
+    group.bench_function("reencrypt", |b| {
+        b.iter(|| reencrypt(&capsule, &kfrags[0], None))
+    });
+    group.finish();
+}
+
+fn bench_verify(c: &mut Criterion) {
+    let keys = Keys::new();
+    let (capsule, _ciphertext) = encrypt(&keys.delegating_pk, b"peace at dawn").unwrap();
+
+    let mut group = c.benchmark_group("verify");
+    for (threshold, num_kfrags) in THRESHOLDS.iter() {
+        let cfrags: Vec<_> = keys
+            .cfrags(&capsule, *threshold, *num_kfrags)
+            .iter()
+            .map(|vcfrag| vcfrag.clone().unverify())
+            .collect();
+        group.throughput(Throughput::Elements(*threshold as u64));
+
+        group.bench_with_input(
+            BenchmarkId::new("single", threshold),
+            &cfrags,
+            |b, cfrags| {
+                b.iter(|| {
+                    for cfrag in cfrags.iter() {
+                        cfrag
+                            .verify(
+                                &capsule,
+                                &keys.verifying_pk,
+                                &keys.delegating_pk,
+                                &keys.receiving_pk,
+                                None,
+                            )
+                            .unwrap();
+                    }
+                })
+            },
+        );
+        group.bench_with_input(
+            BenchmarkId::new("batch", threshold),
+            &cfrags,
+            |b, cfrags| {
+                b.iter(|| {
+                    CapsuleFrag::verify_batch(
+                        cfrags,
+                        &capsule,
+                        &keys.verifying_pk,
+                        &keys.delegating_pk,
+                        &keys.receiving_pk,
+                        None,
+                    )
+                    .unwrap()
+                })
+            },
+        );
+    }
+    group.finish();
+}
+
+fn bench_decrypt_reencrypted(c: &mut Criterion) {
+    let keys = Keys::new();
+    let (capsule, ciphertext) = encrypt(&keys.delegating_pk, &[0u8; 1024]).unwrap();
+
+    let mut group = c.benchmark_group("decrypt_reencrypted");
+    for (threshold, num_kfrags) in THRESHOLDS.iter() {
+        let vcfrags = keys.cfrags(&capsule, *threshold, *num_kfrags);
+        group.bench_with_input(
+            BenchmarkId::from_parameter(threshold),
+            &vcfrags,
+            |b, vcfrags| {
+                b.iter(|| {
+                    decrypt_reencrypted(
+                        &keys.receiving_sk,
+                        &keys.delegating_pk,
+                        &capsule,
+                        vcfrags,
+                        &ciphertext,
+                    )
+                    .unwrap()
+                })
+            },
+        );
+    }
+    group.finish();
+}
+
+// Same as Criterion's default, but with a fixed warm-up and measurement time,
+// so that the runs on different machines take comparable time.
+fn config() -> Criterion {
+    Criterion::default()
+        .warm_up_time(Duration::from_secs(1))
+        .measurement_time(Duration::from_secs(5))
+}
+
+criterion_group!(
+    name = benches;
+    config = config();
+    targets = bench_internals,
+    bench_encrypt,
+    bench_generate_kfrags,
+    bench_reencrypt,
+    bench_verify,
+    bench_decrypt_reencrypted
+);
+
+// Follows the lookup of the output directory in Criterion.
+fn criterion_dir() -> PathBuf {
+    if let Some(dir) = std::env::var_os("CRITERION_HOME") {
+        return PathBuf::from(dir);
+    }
+    let target_dir = std::env::var_os("CARGO_TARGET_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| Path::new(env!("CARGO_MANIFEST_DIR")).join("../target"));
+    target_dir.join("criterion")
+}
+
+// Collects `<id>/new/estimates.json` files under `dir` (the ID can contain slashes).
+fn collect_estimates(dir: &Path, id: &str, out: &mut Vec<(String, String)>) {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return,
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if !path.is_dir() || name == "report" || name == "base" || name == "change" {
+            continue;
+        }
+        if name == "new" {
+            if let Ok(estimates) = fs::read_to_string(path.join("estimates.json")) {
+                out.push((id.to_string(), estimates));
+            }
+        } else if id.is_empty() {
+            collect_estimates(&path, &name, out);
+        } else {
+            collect_estimates(&path, &format!("{}/{}", id, name), out);
+        }
+    }
+}
+
+fn export_summary() {
+    let dir = criterion_dir();
+    let mut estimates = Vec::new();
+    collect_estimates(&dir, "", &mut estimates);
+    estimates.sort();
+
+    let entries: Vec<_> = estimates
+        .iter()
+        .map(|(id, estimates)| format!("  {:?}: {}", id, estimates.trim()))
+        .collect();
+    let summary = format!("{{\n{}\n}}\n", entries.join(",\n"));
+    let path = dir.join("summary.json");
+    match fs::write(&path, summary) {
+        Ok(()) => println!("Summary written to {}", path.display()),
+        Err(err) => eprintln!("Failed to write {}: {}", path.display(), err),
+    }
+}
+
+fn print_build_report() {
+    println!("Arithmetic backend: {}", ArithmeticBackend::current());
+    println!("Object sizes, bytes:\n{}", ObjectSizes::current());
+}
+
+fn main() {
+    print_build_report();
+    if std::env::var_os("UMBRAL_REPORT_ONLY").is_some() {
+        return;
+    }
+    benches();
+    Criterion::default().configure_from_args().final_summary();
+    export_summary();
+}
@@ -0,0 +1,17 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use umbral_pre::{Capsule, DeserializableFromArray, SerializableToArray, VersionedSerialization};
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(capsule) = Capsule::from_bytes(data) {
+        // The encoding is canonical.
+        assert_eq!(capsule.to_array().as_slice(), data);
+    }
+    let _ = Capsule::assert_well_formed(data);
+    // The standalone check accepts exactly the capsules the deserializer does.
+    assert_eq!(
+        Capsule::verify_self_consistency(data).is_ok(),
+        Capsule::from_bytes(data).is_ok()
+    );
+    let _ = Capsule::from_versioned_bytes(data);
+});
@@ -0,0 +1,20 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use umbral_pre::{
+    encapsulate_with_seed, AuditableCapsuleFrag, CapsuleFrag, DeserializableFromArray, PublicKey,
+    SecretKey, SerializableToArray, VersionedSerialization,
+};
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(cfrag) = CapsuleFrag::from_bytes(data) {
+        // The encoding is canonical.
+        assert_eq!(cfrag.to_array().as_slice(), data);
+
+        let sk = SecretKey::from_bytes(&[1u8; 32]).unwrap();
+        let pk = PublicKey::from_secret_key(&sk);
+        let (capsule, _key_seed) = encapsulate_with_seed(b"fuzz", &pk);
+        let _ = cfrag.verify(&capsule, &pk, &pk, &pk, None);
+    }
+    let _ = AuditableCapsuleFrag::from_bytes(data);
+    let _ = CapsuleFrag::from_versioned_bytes(data);
+});
@@ -0,0 +1,15 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use umbral_pre::{
+    decrypt_original, decrypt_original_limited, encapsulate_with_seed, DeserializableFromArray,
+    PublicKey, SecretKey,
+};
+
+fuzz_target!(|data: &[u8]| {
+    let sk = SecretKey::from_bytes(&[1u8; 32]).unwrap();
+    let (capsule, _key_seed) = encapsulate_with_seed(b"fuzz", &PublicKey::from_secret_key(&sk));
+    // Arbitrary ciphertexts (including the truncated ones, and the ones with unknown headers)
+    // must be rejected with an error.
+    assert!(decrypt_original(&sk, &capsule, data).is_err());
+    assert!(decrypt_original_limited(&sk, &capsule, data, 16).is_err());
+});
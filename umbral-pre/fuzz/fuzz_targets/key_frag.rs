@@ -0,0 +1,25 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use umbral_pre::{
+    DeserializableFromArray, KeyFrag, PublicKey, SecretKey, SerializableToArray, VerifiedKeyFrag,
+    VersionedSerialization,
+};
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(kfrag) = KeyFrag::from_bytes(data) {
+        // The encoding is canonical.
+        assert_eq!(kfrag.to_array().as_slice(), data);
+
+        let pk = PublicKey::from_secret_key(&SecretKey::from_bytes(&[1u8; 32]).unwrap());
+        let _ = kfrag.verify(&pk, Some(&pk), Some(&pk));
+    }
+    // Skips the verification, so that the reencryption is reached with arbitrary values.
+    if let Ok(vkfrag) = VerifiedKeyFrag::from_verified_bytes(data, &Default::default()) {
+        let (capsule, _key_seed) = umbral_pre::encapsulate_with_seed(
+            b"fuzz",
+            &PublicKey::from_secret_key(&SecretKey::from_bytes(&[1u8; 32]).unwrap()),
+        );
+        let _ = umbral_pre::reencrypt(&capsule, &vkfrag, None);
+    }
+    let _ = KeyFrag::from_versioned_bytes(data);
+});
@@ -0,0 +1,15 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use umbral_pre::{DeserializableFromArray, PublicKey, SerializableToArray};
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(pk) = PublicKey::from_bytes(data) {
+        // The encoding is canonical.
+        assert_eq!(pk.to_array().as_slice(), data);
+    }
+    let _ = umbral_pre::compat::js::public_key_from_bytes(data);
+    if let Ok(s) = core::str::from_utf8(data) {
+        let _ = s.parse::<PublicKey>();
+        let _ = PublicKey::from_base64(s);
+    }
+});
@@ -0,0 +1,674 @@
+//! A self-describing container for long-term storage of encrypted data.
+//!
+//! An [`Archive`] holds everything needed to recover the plaintext
+//! besides the secret keys: the capsule, the delegating public key,
+//! optionally the rest of the policy (the receiving and verifying keys,
+//! and a Merkle root of the key fragments), and the ciphertext.
+//! The serialized archive has the following layout:
+//!
+//! | Field                             | Size (bytes)     |
+//! | --------------------------------- | ---------------- |
+//! | Magic (`UMBA`)                    | 4                |
+//! | Format version (`1`)              | 1                |
+//! | Policy flag (`0` or `1`)          | 1                |
+//! | Capsule                           | 98               |
+//! | Delegating public key             | 33               |
+//! | Receiving public key              | 33 (if policy)   |
+//! | Verifying public key              | 33 (if policy)   |
+//! | Key fragments Merkle root         | 32 (if policy)   |
+//! | DEM ciphertext                    | the rest − 4     |
+//! | CRC-32 of all the preceding bytes | 4 (big-endian)   |
+//!
+//! Everything before the ciphertext is used as the authenticated data for the DEM,
+//! so the metadata cannot be modified without failing decryption.
+//! The checksum allows one to tell accidental corruption of the storage
+//! from a wrong key or a deliberate modification without attempting decryption.
+//!
+//! A [`KeyFragArchive`] (created by [`export_kfrags_encrypted()`]) packs key fragments
+//! for distribution to the proxies, each encrypted for its proxy,
+//! along with a manifest of the recipients:
+//!
+//! | Field                             | Size (bytes)     |
+//! | --------------------------------- | ---------------- |
+//! | Magic (`UMBK`)                    | 4                |
+//! | Format version (`1`)              | 1                |
+//! | Number of bundles `n`             | 2 (big-endian)   |
+//! | Manifest: `n` times               |                  |
+//! | - Proxy public key                | 33               |
+//! | - Bundle size                     | 4 (big-endian)   |
+//! | Bundles: `n` times                |                  |
+//! | - Capsule                         | 98               |
+//! | - DEM ciphertext                  | the rest         |
+//! | CRC-32 of all the preceding bytes | 4 (big-endian)   |
+//!
+//! A bundle can also be extracted and sent on its own with [`KeyFragBundle::to_bytes()`].
+
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use core::fmt;
+
+use generic_array::GenericArray;
+use rand_core::{CryptoRng, OsRng, RngCore};
+use typenum::{Unsigned, U32};
+use zeroize::Zeroizing;
+
+use crate::capsule::Capsule;
+#[cfg(feature = "role-bob")]
+use crate::capsule_frag::VerifiedCapsuleFrag;
+use crate::dem::{DecryptionError, Dem, EncryptionError, XChaCha20Poly1305Dem};
+use crate::hashing_ds::{hash_to_merkle_leaf, hash_to_merkle_node};
+use crate::key_frag::{KeyFrag, VerifiedKeyFrag};
+use crate::keys::{PublicKey, SecretKey};
+use crate::pre::{decapsulate_original, encapsulate_with_rng};
+#[cfg(feature = "role-bob")]
+use crate::pre::{decapsulate_reencrypted, ReencryptionError};
+use crate::traits::{DeserializableFromArray, RepresentableAsArray, SerializableToArray};
+
+const MAGIC: &[u8; 4] = b"UMBA";
+const FORMAT_VERSION: u8 = 1;
+const CHECKSUM_SIZE: usize = 4;
+const KFRAGS_MAGIC: &[u8; 4] = b"UMBK";
+
+/// The Merkle root of a set of key fragments (see [`ArchivePolicy::new()`]).
+pub type KeyFragsRoot = GenericArray<u8, U32>;
+
+/// Errors that can happen when deserializing an [`Archive`]
+/// or creating and opening a [`KeyFragArchive`].
+#[derive(Debug, PartialEq)]
+pub enum ArchiveError {
+    /// The archive is truncated or its fields cannot be parsed.
+    InvalidFormat,
+    /// The archive has a format version this library does not support.
+    UnsupportedVersion(u8),
+    /// The checksum does not match (the archive was corrupted).
+    ChecksumMismatch,
+    /// The numbers of key fragments and recipients are different.
+    RecipientCountMismatch,
+    /// The archive has no bundle for the given recipient.
+    UnknownRecipient,
+    /// Failed to encrypt a bundle.
+    Encryption(EncryptionError),
+    /// Failed to decrypt a bundle.
+    Decryption(DecryptionError),
+}
+
+impl fmt::Display for ArchiveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidFormat => write!(f, "invalid archive format"),
+            Self::UnsupportedVersion(version) => {
+                write!(f, "unsupported archive version {}", version)
+            }
+            Self::ChecksumMismatch => write!(f, "archive checksum mismatch"),
+            Self::RecipientCountMismatch => {
+                write!(f, "the numbers of key fragments and recipients differ")
+            }
+            Self::UnknownRecipient => write!(f, "no bundle for the recipient"),
+            Self::Encryption(err) => write!(f, "failed to encrypt a bundle: {}", err),
+            Self::Decryption(err) => write!(f, "failed to decrypt a bundle: {}", err),
+        }
+    }
+}
+
+// CRC-32 (IEEE 802.3), bitwise; archives are read rarely, so speed is not a concern.
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xffff_ffffu32;
+    for byte in data {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xedb8_8320 & mask);
+        }
+    }
+    !crc
+}
+
+fn take<T: DeserializableFromArray>(rest: &mut &[u8]) -> Result<T, ArchiveError> {
+    let size = <T as RepresentableAsArray>::Size::to_usize();
+    if rest.len() < size {
+        return Err(ArchiveError::InvalidFormat);
+    }
+    let (head, tail) = rest.split_at(size);
+    *rest = tail;
+    T::from_bytes(head).or(Err(ArchiveError::InvalidFormat))
+}
+
+fn merkle_root(kfrags: &[VerifiedKeyFrag]) -> KeyFragsRoot {
+    let mut level: Vec<KeyFragsRoot> = kfrags
+        .iter()
+        .map(|kfrag| hash_to_merkle_leaf(&kfrag.to_array()))
+        .collect();
+    if level.is_empty() {
+        return hash_to_merkle_leaf(&[]);
+    }
+    while level.len() > 1 {
+        // An odd node at the end is promoted to the next level as is.
+        level = level
+            .chunks(2)
+            .map(|pair| match pair {
+                [left, right] => hash_to_merkle_node(left, right),
+                _ => pair[0],
+            })
+            .collect();
+    }
+    level[0]
+}
+
+/// The keys of the policy the archived data was shared under.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ArchivePolicy {
+    /// The public key of the party the decryption rights were delegated to.
+    pub receiving_pk: PublicKey,
+    /// The public key the key fragments can be verified with.
+    pub verifying_pk: PublicKey,
+    /// The Merkle root of the key fragments.
+    pub kfrags_root: KeyFragsRoot,
+}
+
+impl ArchivePolicy {
+    /// Creates the policy description out of the keys and the key fragments
+    /// returned by [`generate_kfrags()`](crate::generate_kfrags).
+    pub fn new(
+        receiving_pk: &PublicKey,
+        verifying_pk: &PublicKey,
+        kfrags: &[VerifiedKeyFrag],
+    ) -> Self {
+        Self {
+            receiving_pk: *receiving_pk,
+            verifying_pk: *verifying_pk,
+            kfrags_root: merkle_root(kfrags),
+        }
+    }
+
+    /// Checks that the given key fragments (in the original order) are the ones of this policy.
+    pub fn matches_kfrags(&self, kfrags: &[VerifiedKeyFrag]) -> bool {
+        merkle_root(kfrags) == self.kfrags_root
+    }
+}
+
+/// Encrypted data along with the metadata needed to recover it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Archive {
+    capsule: Capsule,
+    delegating_pk: PublicKey,
+    policy: Option<ArchivePolicy>,
+    ciphertext: Box<[u8]>,
+}
+
+impl Archive {
+    fn header(&self) -> Vec<u8> {
+        let mut header = Vec::new();
+        header.extend_from_slice(MAGIC);
+        header.push(FORMAT_VERSION);
+        header.push(self.policy.is_some() as u8);
+        header.extend_from_slice(&self.capsule.to_array());
+        header.extend_from_slice(&self.delegating_pk.to_array());
+        if let Some(policy) = &self.policy {
+            header.extend_from_slice(&policy.receiving_pk.to_array());
+            header.extend_from_slice(&policy.verifying_pk.to_array());
+            header.extend_from_slice(&policy.kfrags_root);
+        }
+        header
+    }
+
+    /// Encrypts the plaintext with `delegating_pk` and creates an archive.
+    pub fn new(
+        delegating_pk: &PublicKey,
+        plaintext: &[u8],
+        policy: Option<&ArchivePolicy>,
+    ) -> Result<Self, EncryptionError> {
+        Self::new_with_rng(&mut OsRng, delegating_pk, plaintext, policy)
+    }
+
+    /// Same as [`new()`](`Self::new`), but uses the given RNG instead of the default one.
+    pub fn new_with_rng(
+        rng: &mut (impl CryptoRng + RngCore),
+        delegating_pk: &PublicKey,
+        plaintext: &[u8],
+        policy: Option<&ArchivePolicy>,
+    ) -> Result<Self, EncryptionError> {
+        let (capsule, key_seed) = encapsulate_with_rng(rng, delegating_pk);
+        let mut archive = Self {
+            capsule,
+            delegating_pk: *delegating_pk,
+            policy: policy.cloned(),
+            ciphertext: Box::new([]),
+        };
+        archive.ciphertext =
+            XChaCha20Poly1305Dem::new(&key_seed).encrypt(rng, plaintext, &archive.header())?;
+        Ok(archive)
+    }
+
+    /// Returns the capsule (to be reencrypted by the proxies).
+    pub fn capsule(&self) -> &Capsule {
+        &self.capsule
+    }
+
+    /// Returns the public key the data was encrypted with.
+    pub fn delegating_pk(&self) -> &PublicKey {
+        &self.delegating_pk
+    }
+
+    /// Returns the policy the data was shared under, if it was recorded.
+    pub fn policy(&self) -> Option<&ArchivePolicy> {
+        self.policy.as_ref()
+    }
+
+    /// Decrypts the data with the secret key corresponding to the delegating key.
+    pub fn decrypt_original(
+        &self,
+        decrypting_sk: &SecretKey,
+    ) -> Result<Box<[u8]>, DecryptionError> {
+        let key_seed = decapsulate_original(decrypting_sk, &self.capsule);
+        XChaCha20Poly1305Dem::new(&key_seed).decrypt(&self.ciphertext, &self.header())
+    }
+
+    /// Decrypts the data with reencrypted capsule fragments.
+    #[cfg(feature = "role-bob")]
+    pub fn decrypt_reencrypted(
+        &self,
+        decrypting_sk: &SecretKey,
+        verified_cfrags: &[VerifiedCapsuleFrag],
+    ) -> Result<Box<[u8]>, ReencryptionError> {
+        let key_seed = decapsulate_reencrypted(
+            decrypting_sk,
+            &self.delegating_pk,
+            &self.capsule,
+            verified_cfrags,
+        )
+        .map_err(ReencryptionError::OnOpen)?;
+        XChaCha20Poly1305Dem::new(&key_seed)
+            .decrypt(&self.ciphertext, &self.header())
+            .map_err(ReencryptionError::OnDecryption)
+    }
+
+    /// Serializes the archive.
+    pub fn to_bytes(&self) -> Box<[u8]> {
+        let mut bytes = self.header();
+        bytes.extend_from_slice(&self.ciphertext);
+        let checksum = crc32(&bytes);
+        bytes.extend_from_slice(&checksum.to_be_bytes());
+        bytes.into_boxed_slice()
+    }
+
+    /// Deserializes the archive, checking its integrity.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, ArchiveError> {
+        if bytes.len() < MAGIC.len() + 2 + CHECKSUM_SIZE || &bytes[..MAGIC.len()] != MAGIC {
+            return Err(ArchiveError::InvalidFormat);
+        }
+        let version = bytes[MAGIC.len()];
+        if version != FORMAT_VERSION {
+            return Err(ArchiveError::UnsupportedVersion(version));
+        }
+
+        let (body, checksum) = bytes.split_at(bytes.len() - CHECKSUM_SIZE);
+        let mut checksum_bytes = [0u8; CHECKSUM_SIZE];
+        checksum_bytes.copy_from_slice(checksum);
+        if crc32(body) != u32::from_be_bytes(checksum_bytes) {
+            return Err(ArchiveError::ChecksumMismatch);
+        }
+
+        let has_policy = match body[MAGIC.len() + 1] {
+            0 => false,
+            1 => true,
+            _ => return Err(ArchiveError::InvalidFormat),
+        };
+
+        let mut rest = &body[MAGIC.len() + 2..];
+
+        let capsule = take::<Capsule>(&mut rest)?;
+        let delegating_pk = take::<PublicKey>(&mut rest)?;
+        let policy = if has_policy {
+            let receiving_pk = take::<PublicKey>(&mut rest)?;
+            let verifying_pk = take::<PublicKey>(&mut rest)?;
+            if rest.len() < 32 {
+                return Err(ArchiveError::InvalidFormat);
+            }
+            let (root, tail) = rest.split_at(32);
+            rest = tail;
+            Some(ArchivePolicy {
+                receiving_pk,
+                verifying_pk,
+                kfrags_root: KeyFragsRoot::clone_from_slice(root),
+            })
+        } else {
+            None
+        };
+
+        Ok(Self {
+            capsule,
+            delegating_pk,
+            policy,
+            ciphertext: rest.into(),
+        })
+    }
+}
+
+/// A key fragment encrypted for a single proxy.
+#[derive(Clone, Debug, PartialEq)]
+pub struct KeyFragBundle {
+    recipient_pk: PublicKey,
+    capsule: Capsule,
+    ciphertext: Box<[u8]>,
+}
+
+impl KeyFragBundle {
+    fn new(
+        rng: &mut (impl CryptoRng + RngCore),
+        kfrag: &VerifiedKeyFrag,
+        recipient_pk: &PublicKey,
+    ) -> Result<Self, EncryptionError> {
+        let (capsule, key_seed) = encapsulate_with_rng(rng, recipient_pk);
+        let mut bundle = Self {
+            recipient_pk: *recipient_pk,
+            capsule,
+            ciphertext: Box::new([]),
+        };
+        let kfrag_bytes = Zeroizing::new(kfrag.to_array());
+        bundle.ciphertext =
+            XChaCha20Poly1305Dem::new(&key_seed).encrypt(rng, &kfrag_bytes, &bundle.header())?;
+        Ok(bundle)
+    }
+
+    // Binds the ciphertext to the recipient, so that a bundle cannot be redirected.
+    fn header(&self) -> Vec<u8> {
+        let mut header = Vec::new();
+        header.extend_from_slice(&self.recipient_pk.to_array());
+        header.extend_from_slice(&self.capsule.to_array());
+        header
+    }
+
+    /// Returns the public key of the proxy the bundle is intended for.
+    pub fn recipient_pk(&self) -> &PublicKey {
+        &self.recipient_pk
+    }
+
+    /// Decrypts the key fragment with the secret key of the recipient.
+    ///
+    /// The fragment still has to be verified with [`KeyFrag::verify()`].
+    pub fn open(&self, recipient_sk: &SecretKey) -> Result<KeyFrag, ArchiveError> {
+        let key_seed = decapsulate_original(recipient_sk, &self.capsule);
+        let kfrag_bytes = Zeroizing::new(
+            XChaCha20Poly1305Dem::new(&key_seed)
+                .decrypt(&self.ciphertext, &self.header())
+                .map_err(ArchiveError::Decryption)?,
+        );
+        KeyFrag::from_bytes(&kfrag_bytes[..]).or(Err(ArchiveError::InvalidFormat))
+    }
+
+    fn body(&self) -> Vec<u8> {
+        let mut body = self.capsule.to_array().to_vec();
+        body.extend_from_slice(&self.ciphertext);
+        body
+    }
+
+    fn from_body(recipient_pk: PublicKey, mut body: &[u8]) -> Result<Self, ArchiveError> {
+        let capsule = take::<Capsule>(&mut body)?;
+        Ok(Self {
+            recipient_pk,
+            capsule,
+            ciphertext: body.into(),
+        })
+    }
+
+    /// Serializes the bundle (to be sent to the proxy on its own).
+    pub fn to_bytes(&self) -> Box<[u8]> {
+        let mut bytes = self.recipient_pk.to_array().to_vec();
+        bytes.extend_from_slice(&self.body());
+        bytes.into_boxed_slice()
+    }
+
+    /// Deserializes the bundle.
+    pub fn from_bytes(mut bytes: &[u8]) -> Result<Self, ArchiveError> {
+        let recipient_pk = take::<PublicKey>(&mut bytes)?;
+        Self::from_body(recipient_pk, bytes)
+    }
+}
+
+/// Key fragments of a policy, each encrypted for its proxy,
+/// created by [`export_kfrags_encrypted()`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct KeyFragArchive {
+    bundles: Box<[KeyFragBundle]>,
+}
+
+impl KeyFragArchive {
+    /// Returns the bundles in the order of the exported key fragments.
+    pub fn bundles(&self) -> &[KeyFragBundle] {
+        &self.bundles
+    }
+
+    /// Returns the bundle intended for the given proxy.
+    pub fn bundle_for(&self, recipient_pk: &PublicKey) -> Option<&KeyFragBundle> {
+        self.bundles
+            .iter()
+            .find(|bundle| bundle.recipient_pk == *recipient_pk)
+    }
+
+    /// Finds the bundle for the proxy with the given secret key and decrypts the key fragment.
+    pub fn open(&self, recipient_sk: &SecretKey) -> Result<KeyFrag, ArchiveError> {
+        self.bundle_for(&PublicKey::from_secret_key(recipient_sk))
+            .ok_or(ArchiveError::UnknownRecipient)?
+            .open(recipient_sk)
+    }
+
+    /// Serializes the archive.
+    pub fn to_bytes(&self) -> Box<[u8]> {
+        let bodies: Vec<_> = self.bundles.iter().map(|bundle| bundle.body()).collect();
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(KFRAGS_MAGIC);
+        bytes.push(FORMAT_VERSION);
+        bytes.extend_from_slice(&(self.bundles.len() as u16).to_be_bytes());
+        for (bundle, body) in self.bundles.iter().zip(bodies.iter()) {
+            bytes.extend_from_slice(&bundle.recipient_pk.to_array());
+            bytes.extend_from_slice(&(body.len() as u32).to_be_bytes());
+        }
+        for body in bodies.iter() {
+            bytes.extend_from_slice(body);
+        }
+        let checksum = crc32(&bytes);
+        bytes.extend_from_slice(&checksum.to_be_bytes());
+        bytes.into_boxed_slice()
+    }
+
+    /// Deserializes the archive, checking its integrity.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, ArchiveError> {
+        let header_size = KFRAGS_MAGIC.len() + 3;
+        if bytes.len() < header_size + CHECKSUM_SIZE || &bytes[..KFRAGS_MAGIC.len()] != KFRAGS_MAGIC
+        {
+            return Err(ArchiveError::InvalidFormat);
+        }
+        let version = bytes[KFRAGS_MAGIC.len()];
+        if version != FORMAT_VERSION {
+            return Err(ArchiveError::UnsupportedVersion(version));
+        }
+
+        let (body, checksum) = bytes.split_at(bytes.len() - CHECKSUM_SIZE);
+        let mut checksum_bytes = [0u8; CHECKSUM_SIZE];
+        checksum_bytes.copy_from_slice(checksum);
+        if crc32(body) != u32::from_be_bytes(checksum_bytes) {
+            return Err(ArchiveError::ChecksumMismatch);
+        }
+
+        let num_bundles =
+            u16::from_be_bytes([body[KFRAGS_MAGIC.len() + 1], body[KFRAGS_MAGIC.len() + 2]]);
+        let mut rest = &body[header_size..];
+
+        let mut manifest = Vec::with_capacity(num_bundles as usize);
+        for _ in 0..num_bundles {
+            let recipient_pk = take::<PublicKey>(&mut rest)?;
+            if rest.len() < 4 {
+                return Err(ArchiveError::InvalidFormat);
+            }
+            let (size, tail) = rest.split_at(4);
+            rest = tail;
+            let size = u32::from_be_bytes([size[0], size[1], size[2], size[3]]) as usize;
+            manifest.push((recipient_pk, size));
+        }
+
+        let mut bundles = Vec::with_capacity(manifest.len());
+        for (recipient_pk, size) in manifest {
+            if rest.len() < size {
+                return Err(ArchiveError::InvalidFormat);
+            }
+            let (bundle_body, tail) = rest.split_at(size);
+            rest = tail;
+            bundles.push(KeyFragBundle::from_body(recipient_pk, bundle_body)?);
+        }
+        if !rest.is_empty() {
+            return Err(ArchiveError::InvalidFormat);
+        }
+
+        Ok(Self {
+            bundles: bundles.into_boxed_slice(),
+        })
+    }
+}
+
+/// Encrypts each of `kfrags` for the proxy with the public key at the same position
+/// in `recipient_pks`, producing a single archive to be distributed to the proxies.
+pub fn export_kfrags_encrypted(
+    kfrags: &[VerifiedKeyFrag],
+    recipient_pks: &[PublicKey],
+) -> Result<KeyFragArchive, ArchiveError> {
+    export_kfrags_encrypted_with_rng(&mut OsRng, kfrags, recipient_pks)
+}
+
+/// Same as [`export_kfrags_encrypted()`], but uses the given RNG instead of the default one.
+pub fn export_kfrags_encrypted_with_rng(
+    rng: &mut (impl CryptoRng + RngCore),
+    kfrags: &[VerifiedKeyFrag],
+    recipient_pks: &[PublicKey],
+) -> Result<KeyFragArchive, ArchiveError> {
+    if kfrags.len() != recipient_pks.len() || kfrags.len() > u16::MAX as usize {
+        return Err(ArchiveError::RecipientCountMismatch);
+    }
+    let bundles = kfrags
+        .iter()
+        .zip(recipient_pks.iter())
+        .map(|(kfrag, recipient_pk)| KeyFragBundle::new(rng, kfrag, recipient_pk))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(ArchiveError::Encryption)?;
+    Ok(KeyFragArchive {
+        bundles: bundles.into_boxed_slice(),
+    })
+}
+
+#[cfg(all(
+    test,
+    feature = "role-alice",
+    feature = "role-ursula",
+    feature = "role-bob"
+))]
+mod tests {
+
+    use alloc::vec::Vec;
+
+    use super::{
+        crc32, export_kfrags_encrypted, Archive, ArchiveError, ArchivePolicy, KeyFragArchive,
+        KeyFragBundle,
+    };
+    use crate::{
+        generate_kfrags, reencrypt, DecryptionError, PublicKey, SecretKey, SerializableToArray,
+        Signer,
+    };
+
+    #[test]
+    fn test_crc32() {
+        assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+    }
+
+    #[test]
+    fn test_archive() {
+        let delegating_sk = SecretKey::random();
+        let delegating_pk = PublicKey::from_secret_key(&delegating_sk);
+        let receiving_sk = SecretKey::random();
+        let receiving_pk = PublicKey::from_secret_key(&receiving_sk);
+        let signer = Signer::new(&SecretKey::random());
+
+        let kfrags = generate_kfrags(&delegating_sk, &receiving_pk, &signer, 2, 3, true, true);
+        let policy = ArchivePolicy::new(&receiving_pk, &signer.verifying_key(), &kfrags);
+        assert!(policy.matches_kfrags(&kfrags));
+        assert!(!policy.matches_kfrags(&kfrags[..2]));
+
+        let plaintext = b"peace at dawn";
+        let archive = Archive::new(&delegating_pk, plaintext, Some(&policy)).unwrap();
+        let bytes = archive.to_bytes();
+        let archive_back = Archive::from_bytes(&bytes).unwrap();
+        assert_eq!(archive_back, archive);
+        assert_eq!(archive_back.policy(), Some(&policy));
+
+        assert_eq!(
+            &archive_back.decrypt_original(&delegating_sk).unwrap() as &[u8],
+            plaintext
+        );
+
+        let cfrags: Vec<_> = kfrags[..2]
+            .iter()
+            .map(|kfrag| reencrypt(archive_back.capsule(), kfrag, None))
+            .collect();
+        assert_eq!(
+            &archive_back
+                .decrypt_reencrypted(&receiving_sk, &cfrags)
+                .unwrap() as &[u8],
+            plaintext
+        );
+
+        // Corruption is detected by the checksum.
+        let mut corrupted = bytes.to_vec();
+        corrupted[10] ^= 1;
+        assert_eq!(
+            Archive::from_bytes(&corrupted),
+            Err(ArchiveError::ChecksumMismatch)
+        );
+
+        // A modified policy with a recomputed checksum fails authentication.
+        let mut modified = Archive::new(&delegating_pk, plaintext, None).unwrap();
+        modified.policy = Some(policy);
+        let modified = Archive::from_bytes(&modified.to_bytes()).unwrap();
+        assert_eq!(
+            modified.decrypt_original(&delegating_sk),
+            Err(DecryptionError::AuthenticationFailed)
+        );
+    }
+
+    #[test]
+    fn test_export_kfrags() {
+        let delegating_sk = SecretKey::random();
+        let receiving_pk = PublicKey::from_secret_key(&SecretKey::random());
+        let signer = Signer::new(&SecretKey::random());
+        let verifying_pk = signer.verifying_key();
+        let kfrags = generate_kfrags(&delegating_sk, &receiving_pk, &signer, 2, 3, false, false);
+
+        let ursula_sks: Vec<_> = (0..3).map(|_| SecretKey::random()).collect();
+        let ursula_pks: Vec<_> = ursula_sks.iter().map(PublicKey::from_secret_key).collect();
+
+        let archive = export_kfrags_encrypted(&kfrags, &ursula_pks).unwrap();
+        let archive = KeyFragArchive::from_bytes(&archive.to_bytes()).unwrap();
+        for (i, ursula_sk) in ursula_sks.iter().enumerate() {
+            let kfrag = archive.open(ursula_sk).unwrap();
+            assert_eq!(kfrag.to_array(), kfrags[i].to_array());
+            assert!(kfrag.verify(&verifying_pk, None, None).is_ok());
+        }
+        assert_eq!(
+            archive.open(&SecretKey::random()),
+            Err(ArchiveError::UnknownRecipient)
+        );
+
+        // A bundle can be sent on its own, but only opened by its recipient.
+        let bundle = KeyFragBundle::from_bytes(&archive.bundles()[1].to_bytes()).unwrap();
+        assert_eq!(bundle.recipient_pk(), &ursula_pks[1]);
+        assert!(bundle.open(&ursula_sks[1]).is_ok());
+        assert_eq!(
+            bundle.open(&ursula_sks[0]),
+            Err(ArchiveError::Decryption(
+                DecryptionError::AuthenticationFailed
+            ))
+        );
+
+        assert_eq!(
+            export_kfrags_encrypted(&kfrags, &ursula_pks[..2]),
+            Err(ArchiveError::RecipientCountMismatch)
+        );
+    }
+}
@@ -1,5 +1,351 @@
 //! Rust benchmarks are located in an external module, and cannot access private functions.
 //! This module re-exports some internals for the purposes of benchmarking.
 //! Should not be used by regular users.
+//!
+//! With the `std` feature and all the protocol role features enabled, it also provides [`calibrate()`] and [`assert_within()`]
+//! to measure the main operations on the current host, so that an application can detect
+//! at startup a build that is too slow for it (e.g. a debug one).
+//!
+//! [`ArithmeticBackend::current()`] and [`ObjectSizes::current()`] describe the build itself,
+//! so that the measurements made with different arithmetic features (see "Embedded targets" in the README)
+//! can be told apart.
+
+use core::fmt;
+use core::mem::size_of;
+
+use typenum::Unsigned;
+
+use crate::traits::RepresentableAsArray;
+use crate::{Capsule, CapsuleFrag, KeyFrag, PublicKey, SecretKey};
 
 pub use crate::hashing::unsafe_hash_to_point;
+
+/// The implementation of the secp256k1 field arithmetic selected at compile time.
+///
+/// Follows the selection made by `k256` for the features enabled through this crate
+/// (a feature enabled on `k256` directly elsewhere in the dependency tree is not seen here).
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ArithmeticBackend {
+    /// Montgomery form (the `field-montgomery` feature).
+    Montgomery,
+    /// 10 limbs of 26 bits, with 32-bit scalars
+    /// (the default on 32-bit targets, or the `force-32-bit` feature).
+    Limbs10x26,
+    /// 5 limbs of 52 bits, with 64-bit scalars (the default on 64-bit targets).
+    Limbs5x52,
+}
+
+impl ArithmeticBackend {
+    /// Returns the backend this crate was compiled with.
+    pub fn current() -> Self {
+        if cfg!(feature = "field-montgomery") {
+            Self::Montgomery
+        } else if cfg!(any(target_pointer_width = "32", feature = "force-32-bit")) {
+            Self::Limbs10x26
+        } else {
+            Self::Limbs5x52
+        }
+    }
+}
+
+impl fmt::Display for ArithmeticBackend {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Self::Montgomery => "montgomery",
+            Self::Limbs10x26 => "10x26",
+            Self::Limbs5x52 => "5x52",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// The sizes (in bytes) of the main objects, serialized and in memory.
+///
+/// The in-memory sizes depend on the arithmetic backend and the target,
+/// and are relevant for the stack usage on embedded targets.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ObjectSizes {
+    /// Serialized [`SecretKey`].
+    pub secret_key: usize,
+    /// Serialized [`PublicKey`].
+    pub public_key: usize,
+    /// Serialized [`Capsule`].
+    pub capsule: usize,
+    /// Serialized [`KeyFrag`].
+    pub kfrag: usize,
+    /// Serialized [`CapsuleFrag`].
+    pub cfrag: usize,
+    /// [`Capsule`] in memory.
+    pub capsule_in_memory: usize,
+    /// [`KeyFrag`] in memory.
+    pub kfrag_in_memory: usize,
+    /// [`CapsuleFrag`] in memory.
+    pub cfrag_in_memory: usize,
+}
+
+impl ObjectSizes {
+    /// Returns the sizes for the current build.
+    pub fn current() -> Self {
+        Self {
+            secret_key: <SecretKey as RepresentableAsArray>::Size::USIZE,
+            public_key: <PublicKey as RepresentableAsArray>::Size::USIZE,
+            capsule: <Capsule as RepresentableAsArray>::Size::USIZE,
+            kfrag: <KeyFrag as RepresentableAsArray>::Size::USIZE,
+            cfrag: <CapsuleFrag as RepresentableAsArray>::Size::USIZE,
+            capsule_in_memory: size_of::<Capsule>(),
+            kfrag_in_memory: size_of::<KeyFrag>(),
+            cfrag_in_memory: size_of::<CapsuleFrag>(),
+        }
+    }
+}
+
+impl fmt::Display for ObjectSizes {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "secret key: {}", self.secret_key)?;
+        writeln!(f, "public key: {}", self.public_key)?;
+        writeln!(
+            f,
+            "capsule: {} (in memory: {})",
+            self.capsule, self.capsule_in_memory
+        )?;
+        writeln!(
+            f,
+            "kfrag: {} (in memory: {})",
+            self.kfrag, self.kfrag_in_memory
+        )?;
+        write!(
+            f,
+            "cfrag: {} (in memory: {})",
+            self.cfrag, self.cfrag_in_memory
+        )
+    }
+}
+
+#[cfg(all(
+    feature = "std",
+    feature = "role-alice",
+    feature = "role-ursula",
+    feature = "role-bob"
+))]
+pub use self::timing::{assert_within, calibrate, BudgetExceeded, Primitive, Timings};
+
+#[cfg(all(
+    feature = "std",
+    feature = "role-alice",
+    feature = "role-ursula",
+    feature = "role-bob"
+))]
+mod timing {
+    use alloc::vec::Vec;
+    use core::fmt;
+    use std::time::{Duration, Instant};
+
+    use crate::{
+        decapsulate_reencrypted, encapsulate, generate_kfrags, reencrypt, PublicKey, SecretKey,
+        Signer,
+    };
+
+    const ITERATIONS: usize = 11;
+
+    /// The operations measured by [`calibrate()`].
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    pub enum Primitive {
+        /// [`encapsulate()`](crate::encapsulate).
+        Encapsulate,
+        /// [`generate_kfrags()`](crate::generate_kfrags) for a single fragment.
+        GenerateKeyFrag,
+        /// [`reencrypt()`](crate::reencrypt).
+        Reencrypt,
+        /// [`CapsuleFrag::verify()`](crate::CapsuleFrag::verify).
+        VerifyCapsuleFrag,
+        /// [`decapsulate_reencrypted()`](crate::decapsulate_reencrypted) with two fragments.
+        DecapsulateReencrypted,
+    }
+
+    impl fmt::Display for Primitive {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            let name = match self {
+                Self::Encapsulate => "encapsulate",
+                Self::GenerateKeyFrag => "generate_kfrag",
+                Self::Reencrypt => "reencrypt",
+                Self::VerifyCapsuleFrag => "verify_cfrag",
+                Self::DecapsulateReencrypted => "decapsulate_reencrypted",
+            };
+            write!(f, "{}", name)
+        }
+    }
+
+    /// Median running times of the main operations.
+    ///
+    /// Also used as a budget in [`Timings::check_within()`] and [`assert_within()`].
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    pub struct Timings {
+        /// See [`Primitive::Encapsulate`].
+        pub encapsulate: Duration,
+        /// See [`Primitive::GenerateKeyFrag`].
+        pub generate_kfrag: Duration,
+        /// See [`Primitive::Reencrypt`].
+        pub reencrypt: Duration,
+        /// See [`Primitive::VerifyCapsuleFrag`].
+        pub verify_cfrag: Duration,
+        /// See [`Primitive::DecapsulateReencrypted`].
+        pub decapsulate_reencrypted: Duration,
+    }
+
+    /// The error returned when a measured time exceeds the budget.
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    pub struct BudgetExceeded {
+        /// The operation that was too slow.
+        pub primitive: Primitive,
+        /// The measured time.
+        pub measured: Duration,
+        /// The budgeted time.
+        pub budget: Duration,
+    }
+
+    impl fmt::Display for BudgetExceeded {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(
+                f,
+                "{} took {:?}, exceeding the budget of {:?}",
+                self.primitive, self.measured, self.budget
+            )
+        }
+    }
+
+    impl std::error::Error for BudgetExceeded {}
+
+    impl Timings {
+        /// Returns the time measured for the given operation.
+        pub fn get(&self, primitive: Primitive) -> Duration {
+            match primitive {
+                Primitive::Encapsulate => self.encapsulate,
+                Primitive::GenerateKeyFrag => self.generate_kfrag,
+                Primitive::Reencrypt => self.reencrypt,
+                Primitive::VerifyCapsuleFrag => self.verify_cfrag,
+                Primitive::DecapsulateReencrypted => self.decapsulate_reencrypted,
+            }
+        }
+
+        /// Checks that every operation fits in the corresponding time of `budget`,
+        /// returning the first one that does not.
+        pub fn check_within(&self, budget: &Timings) -> Result<(), BudgetExceeded> {
+            let primitives = [
+                Primitive::Encapsulate,
+                Primitive::GenerateKeyFrag,
+                Primitive::Reencrypt,
+                Primitive::VerifyCapsuleFrag,
+                Primitive::DecapsulateReencrypted,
+            ];
+            for primitive in primitives.iter() {
+                let measured = self.get(*primitive);
+                let budgeted = budget.get(*primitive);
+                if measured > budgeted {
+                    return Err(BudgetExceeded {
+                        primitive: *primitive,
+                        measured,
+                        budget: budgeted,
+                    });
+                }
+            }
+            Ok(())
+        }
+    }
+
+    fn median_time<T>(mut f: impl FnMut() -> T) -> Duration {
+        let mut times: Vec<Duration> = (0..ITERATIONS)
+            .map(|_| {
+                let start = Instant::now();
+                let result = f();
+                let elapsed = start.elapsed();
+                drop(result);
+                elapsed
+            })
+            .collect();
+        times.sort();
+        times[ITERATIONS / 2]
+    }
+
+    /// Measures the main operations on the current host.
+    ///
+    /// Takes a number of iterations of each operation,
+    /// so it may take a noticeable time in a debug build.
+    pub fn calibrate() -> Timings {
+        let delegating_sk = SecretKey::random();
+        let delegating_pk = PublicKey::from_secret_key(&delegating_sk);
+        let signing_sk = SecretKey::random();
+        let signer = Signer::new(&signing_sk);
+        let verifying_pk = PublicKey::from_secret_key(&signing_sk);
+        let receiving_sk = SecretKey::random();
+        let receiving_pk = PublicKey::from_secret_key(&receiving_sk);
+
+        let (capsule, _key_seed) = encapsulate(&delegating_pk);
+        let kfrags = generate_kfrags(&delegating_sk, &receiving_pk, &signer, 2, 2, true, true);
+        let cfrags: Vec<_> = kfrags
+            .iter()
+            .map(|kfrag| reencrypt(&capsule, kfrag, None))
+            .collect();
+
+        Timings {
+            encapsulate: median_time(|| encapsulate(&delegating_pk)),
+            generate_kfrag: median_time(|| {
+                generate_kfrags(&delegating_sk, &receiving_pk, &signer, 2, 1, true, true)
+            }),
+            reencrypt: median_time(|| reencrypt(&capsule, &kfrags[0], None)),
+            verify_cfrag: median_time(|| {
+                cfrags[0].cfrag.clone().verify(
+                    &capsule,
+                    &verifying_pk,
+                    &delegating_pk,
+                    &receiving_pk,
+                    None,
+                )
+            }),
+            decapsulate_reencrypted: median_time(|| {
+                decapsulate_reencrypted(&receiving_sk, &delegating_pk, &capsule, &cfrags)
+            }),
+        }
+    }
+
+    /// Measures the main operations with [`calibrate()`] and panics
+    /// if any of them exceeds the corresponding time in `budget`.
+    ///
+    /// Returns the measured timings otherwise.
+    pub fn assert_within(budget: &Timings) -> Timings {
+        let timings = calibrate();
+        if let Err(err) = timings.check_within(budget) {
+            panic!("{}", err);
+        }
+        timings
+    }
+
+    #[cfg(test)]
+    mod tests {
+
+        use core::time::Duration;
+
+        use super::{assert_within, Primitive, Timings};
+
+        #[test]
+        fn test_calibrate() {
+            let hour = Duration::from_secs(3600);
+            let budget = Timings {
+                encapsulate: hour,
+                generate_kfrag: hour,
+                reencrypt: hour,
+                verify_cfrag: hour,
+                decapsulate_reencrypted: hour,
+            };
+            let timings = assert_within(&budget);
+            assert!(timings.reencrypt > Duration::from_secs(0));
+
+            let tight_budget = Timings {
+                reencrypt: Duration::from_secs(0),
+                ..budget
+            };
+            let err = timings.check_within(&tight_budget).unwrap_err();
+            assert_eq!(err.primitive, Primitive::Reencrypt);
+            assert_eq!(err.measured, timings.reencrypt);
+        }
+    }
+}
@@ -0,0 +1,1050 @@
+//! An experimental variant of the scheme over BLS12-381 (enabled by the `bls` feature),
+//! where the correctness of a reencryption is checked with pairings.
+//!
+//! The capsules, the fragments and the keys live in the group `G1`,
+//! and the KEM, the key fragments and the combination of the capsule fragments
+//! are the same as in the crate root.
+//! The difference is in the commitment to a key fragment `rk`:
+//! instead of `U * rk` with a zero-knowledge proof of the reencryption attached to each capsule fragment,
+//! the fragment carries `W = G2 * rk` (signed by the delegating party along with the fragment),
+//! and a capsule fragment `(E1, V1)` of a capsule `(E, V)` is correct if
+//!
+//! ```text
+//! e(E1, G2) == e(E, W) and e(V1, G2) == e(V, W)
+//! ```
+//!
+//! This check only involves public values and standard pairing operations,
+//! so it can be performed by anyone, including a smart contract
+//! (e.g. with the BLS12-381 precompiles of EIP-2537).
+//!
+//! Sizes: `G1` points take 48 bytes (compressed), `G2` points 96 bytes,
+//! scalars 32 bytes (little-endian); the signatures are Schnorr signatures over `G1`.
+//! The objects of this module cannot be mixed with the ones of the crate root.
+//! The format may change in the future versions.
+
+#[cfg(any(feature = "dem", feature = "role-alice"))]
+use alloc::boxed::Box;
+#[cfg(any(feature = "role-alice", feature = "role-bob"))]
+use alloc::vec::Vec;
+use core::fmt;
+
+use bls12_381::{pairing, G1Affine, G1Projective, G2Affine, G2Projective, Scalar};
+use digest::Digest;
+use generic_array::sequence::Concat;
+use generic_array::GenericArray;
+use rand_core::{CryptoRng, OsRng, RngCore};
+use sha2::Sha512;
+use subtle::{Choice, ConstantTimeEq};
+use typenum::{op, U32, U48, U96};
+use zeroize::Zeroize;
+
+#[cfg(feature = "role-bob")]
+use crate::capsule::OpenReencryptedError;
+use crate::capsule_frag::{CapsuleFragVerificationError, ReencryptionCheck};
+#[cfg(feature = "dem")]
+use crate::dem::{
+    decrypt_with_header, encrypt_with_header, DecryptionError, DemAlgorithm, EncryptionError,
+};
+use crate::hashing::Hash;
+use crate::key_frag::{KeyFragID, KeyFragVerificationError};
+#[cfg(all(feature = "dem", feature = "role-bob"))]
+use crate::pre::ReencryptionError;
+use crate::traits::{
+    DeserializableFromArray, DeserializationError, RepresentableAsArray, SerializableToArray,
+};
+
+type ScalarSize = U32;
+type PointSize = U48;
+type CommitmentSize = U96;
+
+impl RepresentableAsArray for Scalar {
+    type Size = ScalarSize;
+}
+
+impl SerializableToArray for Scalar {
+    fn to_array(&self) -> GenericArray<u8, Self::Size> {
+        GenericArray::<u8, Self::Size>::from(self.to_bytes())
+    }
+}
+
+impl DeserializableFromArray for Scalar {
+    fn from_array(arr: &GenericArray<u8, Self::Size>) -> Result<Self, DeserializationError> {
+        let mut bytes = [0u8; 32];
+        bytes.copy_from_slice(arr);
+        Option::from(Scalar::from_bytes(&bytes)).ok_or(DeserializationError::InvalidScalar)
+    }
+}
+
+impl RepresentableAsArray for G1Affine {
+    type Size = PointSize;
+}
+
+impl SerializableToArray for G1Affine {
+    fn to_array(&self) -> GenericArray<u8, Self::Size> {
+        GenericArray::<u8, Self::Size>::clone_from_slice(&self.to_compressed())
+    }
+}
+
+impl DeserializableFromArray for G1Affine {
+    fn from_array(arr: &GenericArray<u8, Self::Size>) -> Result<Self, DeserializationError> {
+        let mut bytes = [0u8; 48];
+        bytes.copy_from_slice(arr);
+        // Checks that the point is in the prime-order subgroup.
+        let maybe_point: Option<G1Affine> = G1Affine::from_compressed(&bytes).into();
+        match maybe_point {
+            Some(point) if !bool::from(point.is_identity()) => Ok(point),
+            _ => Err(DeserializationError::InvalidPoint),
+        }
+    }
+}
+
+impl RepresentableAsArray for G2Affine {
+    type Size = CommitmentSize;
+}
+
+impl SerializableToArray for G2Affine {
+    fn to_array(&self) -> GenericArray<u8, Self::Size> {
+        GenericArray::<u8, Self::Size>::clone_from_slice(&self.to_compressed())
+    }
+}
+
+impl DeserializableFromArray for G2Affine {
+    fn from_array(arr: &GenericArray<u8, Self::Size>) -> Result<Self, DeserializationError> {
+        let mut bytes = [0u8; 96];
+        bytes.copy_from_slice(arr);
+        // Checks that the point is in the prime-order subgroup.
+        let maybe_point: Option<G2Affine> = G2Affine::from_compressed(&bytes).into();
+        match maybe_point {
+            Some(point) if !bool::from(point.is_identity()) => Ok(point),
+            _ => Err(DeserializationError::InvalidPoint),
+        }
+    }
+}
+
+fn mul_generator(scalar: &Scalar) -> G1Affine {
+    G1Affine::from(G1Projective::generator() * scalar)
+}
+
+fn random_nonzero_scalar(rng: &mut (impl CryptoRng + RngCore)) -> Scalar {
+    loop {
+        let mut bytes = [0u8; 64];
+        rng.fill_bytes(&mut bytes);
+        let scalar = Scalar::from_bytes_wide(&bytes);
+        bytes.zeroize();
+        // Variable-time: a retry happens with a negligible probability.
+        if scalar != Scalar::zero() {
+            return scalar;
+        }
+    }
+}
+
+// Same as `ScalarDigest` of the crate root, with a wide SHA-512 digest.
+struct ScalarDigest(Hash<Sha512>);
+
+impl ScalarDigest {
+    fn new_with_dst(dst: &[u8]) -> Self {
+        Self(Hash::from_digest_with_dst(Sha512::new(), dst))
+    }
+
+    fn chain_bytes<T: AsRef<[u8]>>(self, bytes: T) -> Self {
+        Self(self.0.chain_bytes(bytes))
+    }
+
+    fn chain_point(self, point: &G1Affine) -> Self {
+        self.chain_bytes(point.to_compressed())
+    }
+
+    fn finalize(self) -> Scalar {
+        let mut bytes = [0u8; 64];
+        bytes.copy_from_slice(&self.0.digest().finalize());
+        Scalar::from_bytes_wide(&bytes)
+    }
+}
+
+fn hash_capsule_points(capsule_e: &G1Affine, capsule_v: &G1Affine) -> Scalar {
+    ScalarDigest::new_with_dst(b"CAPSULE_POINTS")
+        .chain_point(capsule_e)
+        .chain_point(capsule_v)
+        .finalize()
+}
+
+#[cfg(any(feature = "role-alice", feature = "role-bob"))]
+fn hash_to_polynomial_arg(
+    precursor: &G1Affine,
+    pubkey: &G1Affine,
+    dh_point: &G1Affine,
+    kfrag_id: &KeyFragID,
+) -> Scalar {
+    ScalarDigest::new_with_dst(b"POLYNOMIAL_ARG")
+        .chain_point(precursor)
+        .chain_point(pubkey)
+        .chain_point(dh_point)
+        .chain_bytes(kfrag_id)
+        .finalize()
+}
+
+#[cfg(any(feature = "role-alice", feature = "role-bob"))]
+fn hash_to_shared_secret(precursor: &G1Affine, pubkey: &G1Affine, dh_point: &G1Affine) -> Scalar {
+    ScalarDigest::new_with_dst(b"SHARED_SECRET")
+        .chain_point(precursor)
+        .chain_point(pubkey)
+        .chain_point(dh_point)
+        .finalize()
+}
+
+/// A secret key.
+#[derive(Clone)] // Debug is implemented manually, to avoid exposing the key accidentally.
+pub struct SecretKey(Scalar);
+
+impl fmt::Debug for SecretKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("SecretKey(<redacted>)")
+    }
+}
+
+impl ConstantTimeEq for SecretKey {
+    fn ct_eq(&self, other: &Self) -> Choice {
+        self.0.ct_eq(&other.0)
+    }
+}
+
+impl PartialEq for SecretKey {
+    fn eq(&self, other: &Self) -> bool {
+        self.ct_eq(other).into()
+    }
+}
+
+impl Drop for SecretKey {
+    fn drop(&mut self) {
+        // `bls12_381::Scalar` does not implement `Zeroize`.
+        self.0 = Scalar::zero();
+    }
+}
+
+impl SecretKey {
+    /// Generates a secret key using the default RNG and returns it.
+    pub fn random() -> Self {
+        Self::random_with_rng(&mut OsRng)
+    }
+
+    /// Generates a secret key using the given RNG and returns it.
+    pub fn random_with_rng(rng: &mut (impl CryptoRng + RngCore)) -> Self {
+        Self(random_nonzero_scalar(rng))
+    }
+}
+
+impl RepresentableAsArray for SecretKey {
+    type Size = ScalarSize;
+}
+
+impl SerializableToArray for SecretKey {
+    fn to_array(&self) -> GenericArray<u8, Self::Size> {
+        self.0.to_array()
+    }
+}
+
+impl DeserializableFromArray for SecretKey {
+    fn from_array(arr: &GenericArray<u8, Self::Size>) -> Result<Self, DeserializationError> {
+        // Rejects zero and the non-canonical values.
+        let scalar = Scalar::from_array(arr)?;
+        if scalar == Scalar::zero() {
+            return Err(DeserializationError::InvalidScalar);
+        }
+        Ok(Self(scalar))
+    }
+}
+
+/// A public key (a point of `G1`).
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PublicKey(G1Affine);
+
+impl PublicKey {
+    /// Creates a public key from a secret key.
+    pub fn from_secret_key(secret_key: &SecretKey) -> Self {
+        Self(mul_generator(&secret_key.0))
+    }
+}
+
+impl RepresentableAsArray for PublicKey {
+    type Size = PointSize;
+}
+
+impl SerializableToArray for PublicKey {
+    fn to_array(&self) -> GenericArray<u8, Self::Size> {
+        self.0.to_array()
+    }
+}
+
+impl DeserializableFromArray for PublicKey {
+    fn from_array(arr: &GenericArray<u8, Self::Size>) -> Result<Self, DeserializationError> {
+        G1Affine::from_array(arr).map(Self)
+    }
+}
+
+/// A Schnorr signature over `G1`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Signature {
+    point_r: G1Affine,
+    scalar_s: Scalar,
+}
+
+impl RepresentableAsArray for Signature {
+    type Size = op!(PointSize + ScalarSize);
+}
+
+impl SerializableToArray for Signature {
+    fn to_array(&self) -> GenericArray<u8, Self::Size> {
+        self.point_r.to_array().concat(self.scalar_s.to_array())
+    }
+}
+
+impl DeserializableFromArray for Signature {
+    fn from_array(arr: &GenericArray<u8, Self::Size>) -> Result<Self, DeserializationError> {
+        let (point_r, rest) = G1Affine::take(*arr)?;
+        let scalar_s = Scalar::take_last(rest)?;
+        Ok(Self { point_r, scalar_s })
+    }
+}
+
+fn signature_challenge(point_r: &G1Affine, verifying_pk: &PublicKey, message: &[u8]) -> Scalar {
+    ScalarDigest::new_with_dst(b"SIGNATURE_CHALLENGE")
+        .chain_point(point_r)
+        .chain_point(&verifying_pk.0)
+        .chain_bytes(message)
+        .finalize()
+}
+
+impl Signature {
+    /// Verifies that the given message was signed with the secret counterpart of the given key.
+    pub fn verify(&self, verifying_pk: &PublicKey, message: &[u8]) -> bool {
+        let e = signature_challenge(&self.point_r, verifying_pk, message);
+        G1Projective::generator() * self.scalar_s
+            == G1Projective::from(self.point_r) + verifying_pk.0 * e
+    }
+}
+
+/// An object used to sign messages.
+/// For security reasons cannot be serialized.
+#[derive(Clone)] // No Debug derivation, to avoid exposing the key accidentally.
+pub struct Signer(SecretKey);
+
+impl Signer {
+    /// Creates a new signer out of a secret key.
+    pub fn new(sk: &SecretKey) -> Self {
+        Self(sk.clone())
+    }
+
+    /// Signs the given message.
+    pub fn sign(&self, message: &[u8]) -> Signature {
+        self.sign_with_rng(&mut OsRng, message)
+    }
+
+    /// Signs the given message using the given RNG.
+    pub fn sign_with_rng(&self, rng: &mut (impl CryptoRng + RngCore), message: &[u8]) -> Signature {
+        // The nonce is derived from the key and the message along with fresh randomness,
+        // so that a weak RNG does not leak the key.
+        let SecretKey(secret) = &self.0;
+        let mut aux_rand = [0u8; 32];
+        rng.fill_bytes(&mut aux_rand);
+        let k = ScalarDigest::new_with_dst(b"SIGNATURE_NONCE")
+            .chain_bytes(secret.to_bytes())
+            .chain_bytes(aux_rand)
+            .chain_bytes(message)
+            .finalize();
+        let point_r = mul_generator(&k);
+        let e = signature_challenge(&point_r, &self.verifying_key(), message);
+        Signature {
+            point_r,
+            scalar_s: k + e * secret,
+        }
+    }
+
+    /// Returns the public key that can be used to verify the signatures produced by this signer.
+    pub fn verifying_key(&self) -> PublicKey {
+        PublicKey::from_secret_key(&self.0)
+    }
+}
+
+/// Encapsulated symmetric key used to encrypt the plaintext.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Capsule {
+    point_e: G1Affine,
+    point_v: G1Affine,
+    signature: Scalar,
+}
+
+impl RepresentableAsArray for Capsule {
+    type Size = op!(PointSize + PointSize + ScalarSize);
+}
+
+impl SerializableToArray for Capsule {
+    fn to_array(&self) -> GenericArray<u8, Self::Size> {
+        self.point_e
+            .to_array()
+            .concat(self.point_v.to_array())
+            .concat(self.signature.to_array())
+    }
+}
+
+impl DeserializableFromArray for Capsule {
+    fn from_array(arr: &GenericArray<u8, Self::Size>) -> Result<Self, DeserializationError> {
+        let (point_e, rest) = G1Affine::take(*arr)?;
+        let (point_v, rest) = G1Affine::take(rest)?;
+        let signature = Scalar::take_last(rest)?;
+        let capsule = Self {
+            point_e,
+            point_v,
+            signature,
+        };
+        if !capsule.verify() {
+            return Err(DeserializationError::ConstructionFailure);
+        }
+        Ok(capsule)
+    }
+}
+
+impl Capsule {
+    fn verify(&self) -> bool {
+        let h = hash_capsule_points(&self.point_e, &self.point_v);
+        G1Projective::generator() * self.signature
+            == G1Projective::from(self.point_v) + self.point_e * h
+    }
+
+    fn from_public_key(rng: &mut (impl CryptoRng + RngCore), pk: &PublicKey) -> (Self, G1Affine) {
+        let priv_r = random_nonzero_scalar(rng);
+        let priv_u = random_nonzero_scalar(rng);
+
+        let point_e = mul_generator(&priv_r);
+        let point_v = mul_generator(&priv_u);
+        let h = hash_capsule_points(&point_e, &point_v);
+        let signature = priv_u + priv_r * h;
+
+        let shared_key = G1Affine::from(pk.0 * (priv_r + priv_u));
+
+        let capsule = Self {
+            point_e,
+            point_v,
+            signature,
+        };
+        (capsule, shared_key)
+    }
+
+    fn open_original(&self, sk: &SecretKey) -> G1Affine {
+        G1Affine::from((G1Projective::from(self.point_e) + self.point_v) * sk.0)
+    }
+
+    #[cfg(feature = "role-bob")]
+    fn open_reencrypted(
+        &self,
+        receiving_sk: &SecretKey,
+        delegating_pk: &PublicKey,
+        cfrags: &[&CapsuleFrag],
+    ) -> Result<G1Affine, OpenReencryptedError> {
+        if cfrags.is_empty() {
+            return Err(OpenReencryptedError::NoCapsuleFrags);
+        }
+
+        let precursor = cfrags[0].precursor;
+        if !cfrags.iter().all(|cfrag| cfrag.precursor == precursor) {
+            return Err(OpenReencryptedError::MismatchedCapsuleFrags);
+        }
+        for (i, cfrag) in cfrags.iter().enumerate() {
+            if cfrags[..i]
+                .iter()
+                .any(|other| other.kfrag_id == cfrag.kfrag_id)
+            {
+                return Err(OpenReencryptedError::RepeatingCapsuleFrags);
+            }
+        }
+
+        let pub_key = PublicKey::from_secret_key(receiving_sk).0;
+        let dh_point = G1Affine::from(precursor * receiving_sk.0);
+
+        let xs: Vec<_> = cfrags
+            .iter()
+            .map(|cfrag| hash_to_polynomial_arg(&precursor, &pub_key, &dh_point, &cfrag.kfrag_id))
+            .collect();
+        let lambdas = lambda_coeffs(&xs).ok_or(OpenReencryptedError::RepeatingCapsuleFrags)?;
+
+        let mut e_prime = G1Projective::identity();
+        let mut v_prime = G1Projective::identity();
+        for (lambda, cfrag) in lambdas.iter().zip(cfrags.iter()) {
+            e_prime += cfrag.point_e1 * lambda;
+            v_prime += cfrag.point_v1 * lambda;
+        }
+
+        let d = hash_to_shared_secret(&precursor, &pub_key, &dh_point);
+        // Variable-time: only exits early if `d` is zero, which happens with a negligible probability.
+        let inv_d: Option<Scalar> = d.invert().into();
+        let inv_d = inv_d.ok_or(OpenReencryptedError::ZeroHash)?;
+
+        let h = hash_capsule_points(&self.point_e, &self.point_v);
+        if delegating_pk.0 * (self.signature * inv_d) != e_prime * h + v_prime {
+            return Err(OpenReencryptedError::ValidationFailed);
+        }
+
+        Ok(G1Affine::from((e_prime + v_prime) * d))
+    }
+}
+
+/// Computes the Lagrange coefficients `prod_{j != i} xs[j] / (xs[j] - xs[i])`
+/// for the interpolation at zero, for all `i`.
+/// Returns `None` if some of `xs` are equal.
+#[cfg(feature = "role-bob")]
+fn lambda_coeffs(xs: &[Scalar]) -> Option<Vec<Scalar>> {
+    let mut result = Vec::with_capacity(xs.len());
+    for (i, x_i) in xs.iter().enumerate() {
+        let mut numerator = Scalar::one();
+        let mut denominator = Scalar::one();
+        for (j, x_j) in xs.iter().enumerate() {
+            // The branch depends on the positions only.
+            if j != i {
+                numerator *= x_j;
+                denominator *= x_j - x_i;
+            }
+        }
+        // Variable-time: only exits early if some of the points are equal.
+        let inv_denominator: Option<Scalar> = denominator.invert().into();
+        result.push(numerator * inv_denominator?);
+    }
+    Some(result)
+}
+
+type KeyFragIDSize = <KeyFragID as RepresentableAsArray>::Size;
+type SignatureSize = <Signature as RepresentableAsArray>::Size;
+
+/// The message signed in a key fragment.
+fn kfrag_signature_message(
+    kfrag_id: &KeyFragID,
+    commitment: &G2Affine,
+    precursor: &G1Affine,
+    delegating_pk: &PublicKey,
+    receiving_pk: &PublicKey,
+) -> GenericArray<u8, op!(KeyFragIDSize + CommitmentSize + PointSize + PointSize + PointSize)> {
+    kfrag_id
+        .to_array()
+        .concat(commitment.to_array())
+        .concat(precursor.to_array())
+        .concat(delegating_pk.to_array())
+        .concat(receiving_pk.to_array())
+}
+
+/// A fragment of the encrypting party's key used to create a [`CapsuleFrag`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct KeyFrag {
+    id: KeyFragID,
+    key: Scalar,
+    precursor: G1Affine,
+    commitment: G2Affine,
+    signature: Signature,
+}
+
+impl RepresentableAsArray for KeyFrag {
+    type Size = op!(KeyFragIDSize + ScalarSize + PointSize + CommitmentSize + SignatureSize);
+}
+
+impl SerializableToArray for KeyFrag {
+    fn to_array(&self) -> GenericArray<u8, Self::Size> {
+        self.id
+            .to_array()
+            .concat(self.key.to_array())
+            .concat(self.precursor.to_array())
+            .concat(self.commitment.to_array())
+            .concat(self.signature.to_array())
+    }
+}
+
+impl DeserializableFromArray for KeyFrag {
+    fn from_array(arr: &GenericArray<u8, Self::Size>) -> Result<Self, DeserializationError> {
+        let (id, rest) = KeyFragID::take(*arr)?;
+        let (key, rest) = Scalar::take(rest)?;
+        let (precursor, rest) = G1Affine::take(rest)?;
+        let (commitment, rest) = G2Affine::take(rest)?;
+        let signature = Signature::take_last(rest)?;
+        Ok(Self {
+            id,
+            key,
+            precursor,
+            commitment,
+            signature,
+        })
+    }
+}
+
+impl KeyFrag {
+    /// Verifies the integrity of the key fragment, given the signing key,
+    /// the delegating key and the receiving key (always included in the signature).
+    pub fn verify(
+        &self,
+        verifying_pk: &PublicKey,
+        delegating_pk: &PublicKey,
+        receiving_pk: &PublicKey,
+    ) -> Result<VerifiedKeyFrag, KeyFragVerificationError> {
+        if G2Affine::from(G2Projective::generator() * self.key) != self.commitment {
+            return Err(KeyFragVerificationError::IncorrectCommitment);
+        }
+
+        let message = kfrag_signature_message(
+            &self.id,
+            &self.commitment,
+            &self.precursor,
+            delegating_pk,
+            receiving_pk,
+        );
+        if !self.signature.verify(verifying_pk, &message) {
+            return Err(KeyFragVerificationError::IncorrectSignature);
+        }
+
+        Ok(VerifiedKeyFrag(self.clone()))
+    }
+
+    /// Returns the identifier of the fragment.
+    pub fn id(&self) -> &KeyFragID {
+        &self.id
+    }
+}
+
+/// Verified key fragment, good for reencryption.
+#[derive(Clone, Debug, PartialEq)]
+pub struct VerifiedKeyFrag(KeyFrag);
+
+impl RepresentableAsArray for VerifiedKeyFrag {
+    type Size = <KeyFrag as RepresentableAsArray>::Size;
+}
+
+impl SerializableToArray for VerifiedKeyFrag {
+    fn to_array(&self) -> GenericArray<u8, Self::Size> {
+        self.0.to_array()
+    }
+}
+
+impl VerifiedKeyFrag {
+    /// Returns the identifier of the fragment.
+    pub fn id(&self) -> &KeyFragID {
+        &self.0.id
+    }
+
+    /// Returns the unverified fragment, e.g. to send it over to a proxy.
+    pub fn unverify(self) -> KeyFrag {
+        self.0
+    }
+}
+
+/// A reencrypted fragment of a [`Capsule`] created by a proxy.
+///
+/// Carries the commitment to the key fragment and its signature,
+/// so that it can be verified without the key fragment.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CapsuleFrag {
+    point_e1: G1Affine,
+    point_v1: G1Affine,
+    kfrag_id: KeyFragID,
+    precursor: G1Affine,
+    kfrag_commitment: G2Affine,
+    kfrag_signature: Signature,
+}
+
+impl RepresentableAsArray for CapsuleFrag {
+    type Size =
+        op!(PointSize + PointSize + KeyFragIDSize + PointSize + CommitmentSize + SignatureSize);
+}
+
+impl SerializableToArray for CapsuleFrag {
+    fn to_array(&self) -> GenericArray<u8, Self::Size> {
+        self.point_e1
+            .to_array()
+            .concat(self.point_v1.to_array())
+            .concat(self.kfrag_id.to_array())
+            .concat(self.precursor.to_array())
+            .concat(self.kfrag_commitment.to_array())
+            .concat(self.kfrag_signature.to_array())
+    }
+}
+
+impl DeserializableFromArray for CapsuleFrag {
+    fn from_array(arr: &GenericArray<u8, Self::Size>) -> Result<Self, DeserializationError> {
+        let (point_e1, rest) = G1Affine::take(*arr)?;
+        let (point_v1, rest) = G1Affine::take(rest)?;
+        let (kfrag_id, rest) = KeyFragID::take(rest)?;
+        let (precursor, rest) = G1Affine::take(rest)?;
+        let (kfrag_commitment, rest) = G2Affine::take(rest)?;
+        let kfrag_signature = Signature::take_last(rest)?;
+        Ok(Self {
+            point_e1,
+            point_v1,
+            kfrag_id,
+            precursor,
+            kfrag_commitment,
+            kfrag_signature,
+        })
+    }
+}
+
+impl CapsuleFrag {
+    #[cfg(feature = "role-ursula")]
+    fn reencrypted(capsule: &Capsule, kfrag: &KeyFrag) -> Self {
+        Self {
+            point_e1: G1Affine::from(capsule.point_e * kfrag.key),
+            point_v1: G1Affine::from(capsule.point_v * kfrag.key),
+            kfrag_id: kfrag.id,
+            precursor: kfrag.precursor,
+            kfrag_commitment: kfrag.commitment,
+            kfrag_signature: kfrag.signature,
+        }
+    }
+
+    /// Checks the pairing equations of the reencryption
+    /// (see the [module-level documentation](self)).
+    ///
+    /// Only proves that the fragment was created with the key fragment committed to;
+    /// [`verify()`](`Self::verify`) also checks that the commitment was signed by the delegating party.
+    pub fn verify_reencryption(&self, capsule: &Capsule) -> Result<(), ReencryptionCheck> {
+        let g2 = G2Affine::generator();
+        if pairing(&self.point_e1, &g2) != pairing(&capsule.point_e, &self.kfrag_commitment) {
+            return Err(ReencryptionCheck::PointE);
+        }
+        if pairing(&self.point_v1, &g2) != pairing(&capsule.point_v, &self.kfrag_commitment) {
+            return Err(ReencryptionCheck::PointV);
+        }
+        Ok(())
+    }
+
+    /// Verifies the integrity of the capsule fragment, given the original capsule,
+    /// the encrypting party's key, the decrypting party's key, and the signing key.
+    pub fn verify(
+        &self,
+        capsule: &Capsule,
+        verifying_pk: &PublicKey,
+        delegating_pk: &PublicKey,
+        receiving_pk: &PublicKey,
+    ) -> Result<VerifiedCapsuleFrag, CapsuleFragVerificationError> {
+        let message = kfrag_signature_message(
+            &self.kfrag_id,
+            &self.kfrag_commitment,
+            &self.precursor,
+            delegating_pk,
+            receiving_pk,
+        );
+        if !self.kfrag_signature.verify(verifying_pk, &message) {
+            return Err(CapsuleFragVerificationError::IncorrectKeyFragSignature);
+        }
+
+        self.verify_reencryption(capsule)
+            .map_err(CapsuleFragVerificationError::IncorrectReencryption)?;
+
+        Ok(VerifiedCapsuleFrag(self.clone()))
+    }
+
+    /// Returns the identifier of the key fragment this fragment was created with.
+    pub fn kfrag_id(&self) -> &KeyFragID {
+        &self.kfrag_id
+    }
+}
+
+/// Verified capsule fragment, good for decryption.
+#[derive(Clone, Debug, PartialEq)]
+pub struct VerifiedCapsuleFrag(CapsuleFrag);
+
+impl RepresentableAsArray for VerifiedCapsuleFrag {
+    type Size = <CapsuleFrag as RepresentableAsArray>::Size;
+}
+
+impl SerializableToArray for VerifiedCapsuleFrag {
+    fn to_array(&self) -> GenericArray<u8, Self::Size> {
+        self.0.to_array()
+    }
+}
+
+impl VerifiedCapsuleFrag {
+    /// Returns the identifier of the key fragment this fragment was created with.
+    pub fn kfrag_id(&self) -> &KeyFragID {
+        &self.0.kfrag_id
+    }
+
+    /// Returns the unverified fragment, e.g. to send it over to another party.
+    pub fn unverify(self) -> CapsuleFrag {
+        self.0
+    }
+}
+
+/// The key seed produced by the KEM, from which the DEM key is derived.
+pub type KeySeed = GenericArray<u8, PointSize>;
+
+/// Generates a random key seed and encapsulates it for later reencryption.
+/// Returns the KEM [`Capsule`] and the key seed.
+pub fn encapsulate(pk: &PublicKey) -> (Capsule, KeySeed) {
+    encapsulate_with_rng(&mut OsRng, pk)
+}
+
+/// Same as [`encapsulate()`], but uses the given RNG instead of the default one.
+pub fn encapsulate_with_rng(
+    rng: &mut (impl CryptoRng + RngCore),
+    pk: &PublicKey,
+) -> (Capsule, KeySeed) {
+    let (capsule, key_seed) = Capsule::from_public_key(rng, pk);
+    (capsule, key_seed.to_array())
+}
+
+/// Attempts to open the capsule using the original encryptor's secret key.
+pub fn decapsulate_original(decrypting_sk: &SecretKey, capsule: &Capsule) -> KeySeed {
+    capsule.open_original(decrypting_sk).to_array()
+}
+
+/// Attempts to open the capsule using the capsule fragments created by the proxies
+/// (at least `threshold` of them, see [`generate_kfrags()`]).
+#[cfg(feature = "role-bob")]
+pub fn decapsulate_reencrypted(
+    receiving_sk: &SecretKey,
+    delegating_pk: &PublicKey,
+    capsule: &Capsule,
+    verified_cfrags: &[VerifiedCapsuleFrag],
+) -> Result<KeySeed, OpenReencryptedError> {
+    let cfrags: Vec<_> = verified_cfrags.iter().map(|vcfrag| &vcfrag.0).collect();
+    capsule
+        .open_reencrypted(receiving_sk, delegating_pk, &cfrags)
+        .map(|key_seed| key_seed.to_array())
+}
+
+/// Creates `num_kfrags` fragments of `delegating_sk`,
+/// `threshold` of which are needed to open a capsule reencrypted for `receiving_pk`.
+///
+/// The fragments are signed with `signer`, along with the delegating and the receiving keys.
+///
+/// Panics if `threshold` is zero.
+#[cfg(feature = "role-alice")]
+pub fn generate_kfrags(
+    delegating_sk: &SecretKey,
+    receiving_pk: &PublicKey,
+    signer: &Signer,
+    threshold: usize,
+    num_kfrags: usize,
+) -> Box<[VerifiedKeyFrag]> {
+    generate_kfrags_with_rng(
+        &mut OsRng,
+        delegating_sk,
+        receiving_pk,
+        signer,
+        threshold,
+        num_kfrags,
+    )
+}
+
+/// Same as [`generate_kfrags()`], but uses the given RNG instead of the default one.
+#[cfg(feature = "role-alice")]
+pub fn generate_kfrags_with_rng(
+    rng: &mut (impl CryptoRng + RngCore),
+    delegating_sk: &SecretKey,
+    receiving_pk: &PublicKey,
+    signer: &Signer,
+    threshold: usize,
+    num_kfrags: usize,
+) -> Box<[VerifiedKeyFrag]> {
+    assert!(threshold > 0, "the threshold must be positive");
+
+    let delegating_pk = PublicKey::from_secret_key(delegating_sk);
+
+    let (inv_d, precursor, dh_point) = loop {
+        // The precursor point is used as an ephemeral public key in a DH key exchange,
+        // and the resulting shared secret 'dh_point' is used to derive other secret values
+        let private_precursor = random_nonzero_scalar(rng);
+        let precursor = mul_generator(&private_precursor);
+        let dh_point = G1Affine::from(receiving_pk.0 * private_precursor);
+
+        let d = hash_to_shared_secret(&precursor, &receiving_pk.0, &dh_point);
+        // Variable-time: a retry happens with a negligible probability.
+        let inv_d: Option<Scalar> = d.invert().into();
+        if let Some(inv_d) = inv_d {
+            break (inv_d, precursor, dh_point);
+        }
+    };
+
+    // Coefficients of the generating polynomial
+    let mut coefficients = Vec::<Scalar>::with_capacity(threshold);
+    coefficients.push(delegating_sk.0 * inv_d);
+    for _i in 1..threshold {
+        coefficients.push(random_nonzero_scalar(rng));
+    }
+
+    let mut kfrags = Vec::with_capacity(num_kfrags);
+    for _ in 0..num_kfrags {
+        let id = KeyFragID::random(rng);
+        let share_index = hash_to_polynomial_arg(&precursor, &receiving_pk.0, &dh_point, &id);
+
+        // Evaluates the polynomial at the share index (Horner's method).
+        let mut key = Scalar::zero();
+        for coefficient in coefficients.iter().rev() {
+            key = key * share_index + coefficient;
+        }
+
+        let commitment = G2Affine::from(G2Projective::generator() * key);
+        let message =
+            kfrag_signature_message(&id, &commitment, &precursor, &delegating_pk, receiving_pk);
+        let signature = signer.sign_with_rng(rng, &message);
+
+        kfrags.push(VerifiedKeyFrag(KeyFrag {
+            id,
+            key,
+            precursor,
+            commitment,
+            signature,
+        }));
+    }
+
+    kfrags.into_boxed_slice()
+}
+
+/// Reencrypts a [`Capsule`] object with a key fragment, creating a capsule fragment.
+///
+/// Unlike in the crate root, the reencryption is deterministic,
+/// since the capsule fragment does not include a zero-knowledge proof.
+#[cfg(feature = "role-ursula")]
+pub fn reencrypt(capsule: &Capsule, verified_kfrag: &VerifiedKeyFrag) -> VerifiedCapsuleFrag {
+    VerifiedCapsuleFrag(CapsuleFrag::reencrypted(capsule, &verified_kfrag.0))
+}
+
+/// Encrypts the given plaintext message using the default DEM scheme,
+/// and encapsulates the key for later reencryption.
+/// Returns the KEM [`Capsule`] and the ciphertext.
+#[cfg(feature = "dem")]
+pub fn encrypt(pk: &PublicKey, plaintext: &[u8]) -> Result<(Capsule, Box<[u8]>), EncryptionError> {
+    encrypt_with_rng(&mut OsRng, pk, plaintext)
+}
+
+/// Same as [`encrypt()`], but uses the given RNG instead of the default one.
+#[cfg(feature = "dem")]
+pub fn encrypt_with_rng(
+    rng: &mut (impl CryptoRng + RngCore),
+    pk: &PublicKey,
+    plaintext: &[u8],
+) -> Result<(Capsule, Box<[u8]>), EncryptionError> {
+    let (capsule, key_seed) = encapsulate_with_rng(rng, pk);
+    encrypt_with_header(
+        DemAlgorithm::DEFAULT,
+        rng,
+        &key_seed,
+        plaintext,
+        &capsule.to_array(),
+    )
+    .map(|ciphertext| (capsule, ciphertext))
+}
+
+/// Attempts to decrypt the ciphertext using the original encryptor's secret key.
+#[cfg(feature = "dem")]
+pub fn decrypt_original(
+    decrypting_sk: &SecretKey,
+    capsule: &Capsule,
+    ciphertext: impl AsRef<[u8]>,
+) -> Result<Box<[u8]>, DecryptionError> {
+    let key_seed = decapsulate_original(decrypting_sk, capsule);
+    decrypt_with_header(&key_seed, ciphertext.as_ref(), &capsule.to_array(), None)
+}
+
+/// Decrypts the ciphertext using previously reencrypted capsule fragments.
+#[cfg(all(feature = "dem", feature = "role-bob"))]
+pub fn decrypt_reencrypted(
+    receiving_sk: &SecretKey,
+    delegating_pk: &PublicKey,
+    capsule: &Capsule,
+    verified_cfrags: &[VerifiedCapsuleFrag],
+    ciphertext: impl AsRef<[u8]>,
+) -> Result<Box<[u8]>, ReencryptionError> {
+    let key_seed = decapsulate_reencrypted(receiving_sk, delegating_pk, capsule, verified_cfrags)
+        .map_err(ReencryptionError::OnOpen)?;
+    decrypt_with_header(&key_seed, ciphertext.as_ref(), &capsule.to_array(), None)
+        .map_err(ReencryptionError::OnDecryption)
+}
+
+#[cfg(all(
+    test,
+    feature = "role-alice",
+    feature = "role-ursula",
+    feature = "role-bob"
+))]
+mod tests {
+
+    use alloc::vec::Vec;
+
+    use super::{
+        decapsulate_original, decapsulate_reencrypted, encapsulate, generate_kfrags, reencrypt,
+        Capsule, CapsuleFrag, PublicKey, SecretKey, Signer,
+    };
+    use crate::{
+        CapsuleFragVerificationError, DeserializableFromArray, OpenReencryptedError,
+        ReencryptionCheck, SerializableToArray,
+    };
+
+    #[test]
+    fn test_reencryption() {
+        let delegating_sk = SecretKey::random();
+        let delegating_pk = PublicKey::from_secret_key(&delegating_sk);
+        let signer = Signer::new(&SecretKey::random());
+        let verifying_pk = signer.verifying_key();
+
+        let receiving_sk = SecretKey::random();
+        let receiving_pk = PublicKey::from_secret_key(&receiving_sk);
+
+        let (capsule, key_seed) = encapsulate(&delegating_pk);
+        assert_eq!(decapsulate_original(&delegating_sk, &capsule), key_seed);
+        assert_eq!(Capsule::from_array(&capsule.to_array()), Ok(capsule));
+
+        let kfrags = generate_kfrags(&delegating_sk, &receiving_pk, &signer, 2, 3);
+        assert!(kfrags[0]
+            .clone()
+            .unverify()
+            .verify(&verifying_pk, &delegating_pk, &receiving_pk)
+            .is_ok());
+
+        let cfrags: Vec<_> = kfrags
+            .iter()
+            .map(|kfrag| reencrypt(&capsule, kfrag).unverify())
+            .collect();
+        let cfrag = CapsuleFrag::from_array(&cfrags[0].to_array()).unwrap();
+        assert_eq!(cfrag, cfrags[0]);
+
+        // The pairing check does not need any keys,
+        // but fails for a fragment of another capsule.
+        assert_eq!(cfrag.verify_reencryption(&capsule), Ok(()));
+        let (other_capsule, _key_seed) = encapsulate(&delegating_pk);
+        let other_cfrag = reencrypt(&other_capsule, &kfrags[0]).unverify();
+        assert_eq!(
+            other_cfrag.verify_reencryption(&capsule),
+            Err(ReencryptionCheck::PointE)
+        );
+        assert_eq!(
+            other_cfrag
+                .verify(&capsule, &verifying_pk, &delegating_pk, &receiving_pk)
+                .map(|_| ()),
+            Err(CapsuleFragVerificationError::IncorrectReencryption(
+                ReencryptionCheck::PointE
+            ))
+        );
+
+        let verified_cfrags: Vec<_> = cfrags
+            .iter()
+            .map(|cfrag| {
+                cfrag
+                    .verify(&capsule, &verifying_pk, &delegating_pk, &receiving_pk)
+                    .unwrap()
+            })
+            .collect();
+
+        assert_eq!(
+            decapsulate_reencrypted(
+                &receiving_sk,
+                &delegating_pk,
+                &capsule,
+                &verified_cfrags[1..]
+            ),
+            Ok(key_seed)
+        );
+        assert_eq!(
+            decapsulate_reencrypted(
+                &receiving_sk,
+                &delegating_pk,
+                &capsule,
+                &verified_cfrags[..1]
+            ),
+            Err(OpenReencryptedError::ValidationFailed)
+        );
+    }
+}
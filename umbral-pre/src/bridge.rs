@@ -0,0 +1,163 @@
+//! Bridging of delegations to a different encryption scheme.
+//!
+//! This crate only implements Umbral over secp256k1, so the data cannot be reencrypted
+//! directly for a party that can only use another curve (e.g. P-256).
+//! Instead, a trusted bridge is made the receiving party of the delegation:
+//! it decrypts the data and encrypts it again with the target scheme,
+//! given as an implementation of [`TargetScheme`].
+//!
+//! **Warning:** the bridge sees the plaintext, so it must be trusted with the data
+//! just as the delegatee is. The proxies' guarantees do not extend past the bridge.
+
+use alloc::boxed::Box;
+use core::fmt;
+
+use zeroize::Zeroizing;
+
+use crate::capsule::Capsule;
+#[cfg(feature = "role-bob")]
+use crate::capsule_frag::VerifiedCapsuleFrag;
+#[cfg(feature = "role-bob")]
+use crate::keys::PublicKey;
+use crate::keys::SecretKey;
+#[cfg(feature = "role-bob")]
+use crate::pre::decrypt_reencrypted;
+use crate::pre::{decrypt_original, ReencryptionError};
+
+/// An encryption scheme the bridge re-wraps the data for.
+pub trait TargetScheme {
+    /// The ciphertext of the target scheme.
+    type Ciphertext;
+    /// The error of the target scheme.
+    type Error;
+
+    /// Encrypts the plaintext with the target scheme.
+    fn encrypt(&self, plaintext: &[u8]) -> Result<Self::Ciphertext, Self::Error>;
+}
+
+/// Errors that can happen when re-wrapping a ciphertext.
+#[derive(Debug, PartialEq)]
+pub enum RewrapError<E> {
+    /// Failed to decrypt the source ciphertext.
+    Source(ReencryptionError),
+    /// Failed to encrypt with the target scheme.
+    Target(E),
+}
+
+impl<E: fmt::Display> fmt::Display for RewrapError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Source(err) => write!(f, "failed to decrypt the source ciphertext: {}", err),
+            Self::Target(err) => write!(f, "failed to encrypt for the target scheme: {}", err),
+        }
+    }
+}
+
+/// Decrypts a ciphertext reencrypted for the bridge (see [`decrypt_reencrypted()`])
+/// and encrypts the plaintext with the target scheme.
+///
+/// See the [module-level documentation](self) for the trust assumptions.
+#[cfg(feature = "role-bob")]
+pub fn rewrap_reencrypted<T: TargetScheme>(
+    bridge_sk: &SecretKey,
+    delegating_pk: &PublicKey,
+    capsule: &Capsule,
+    verified_cfrags: &[VerifiedCapsuleFrag],
+    ciphertext: impl AsRef<[u8]>,
+    target: &T,
+) -> Result<T::Ciphertext, RewrapError<T::Error>> {
+    let plaintext = Zeroizing::new(
+        decrypt_reencrypted(
+            bridge_sk,
+            delegating_pk,
+            capsule,
+            verified_cfrags,
+            ciphertext,
+        )
+        .map_err(RewrapError::Source)?,
+    );
+    target.encrypt(&plaintext).map_err(RewrapError::Target)
+}
+
+/// Decrypts a ciphertext encrypted for the bridge (see [`decrypt_original()`])
+/// and encrypts the plaintext with the target scheme.
+///
+/// See the [module-level documentation](self) for the trust assumptions.
+pub fn rewrap_original<T: TargetScheme>(
+    bridge_sk: &SecretKey,
+    capsule: &Capsule,
+    ciphertext: impl AsRef<[u8]>,
+    target: &T,
+) -> Result<T::Ciphertext, RewrapError<T::Error>> {
+    let plaintext: Zeroizing<Box<[u8]>> = Zeroizing::new(
+        decrypt_original(bridge_sk, capsule, ciphertext)
+            .map_err(|err| RewrapError::Source(ReencryptionError::OnDecryption(err)))?,
+    );
+    target.encrypt(&plaintext).map_err(RewrapError::Target)
+}
+
+#[cfg(all(test, feature = "role-alice", feature = "role-ursula"))]
+mod tests {
+
+    use alloc::boxed::Box;
+    use alloc::vec::Vec;
+
+    use super::{rewrap_original, rewrap_reencrypted, RewrapError, TargetScheme};
+    use crate::{
+        decrypt_original, encrypt, generate_kfrags, reencrypt, Capsule, DecryptionError,
+        EncryptionError, PublicKey, ReencryptionError, SecretKey, Signer,
+    };
+
+    // Stands in for an instantiation over another curve.
+    struct OtherScheme(PublicKey);
+
+    impl TargetScheme for OtherScheme {
+        type Ciphertext = (Capsule, Box<[u8]>);
+        type Error = EncryptionError;
+
+        fn encrypt(&self, plaintext: &[u8]) -> Result<Self::Ciphertext, Self::Error> {
+            encrypt(&self.0, plaintext)
+        }
+    }
+
+    #[test]
+    fn test_rewrap() {
+        let delegating_sk = SecretKey::random();
+        let delegating_pk = PublicKey::from_secret_key(&delegating_sk);
+        let signer = Signer::new(&SecretKey::random());
+        let bridge_sk = SecretKey::random();
+        let bridge_pk = PublicKey::from_secret_key(&bridge_sk);
+        let target_sk = SecretKey::random();
+        let target = OtherScheme(PublicKey::from_secret_key(&target_sk));
+
+        let plaintext = b"peace at dawn";
+        let (capsule, ciphertext) = encrypt(&delegating_pk, plaintext).unwrap();
+        let kfrags = generate_kfrags(&delegating_sk, &bridge_pk, &signer, 2, 3, true, true);
+        let cfrags: Vec<_> = kfrags[..2]
+            .iter()
+            .map(|kfrag| reencrypt(&capsule, kfrag, None))
+            .collect();
+
+        let (target_capsule, target_ciphertext) = rewrap_reencrypted(
+            &bridge_sk,
+            &delegating_pk,
+            &capsule,
+            &cfrags,
+            &ciphertext,
+            &target,
+        )
+        .unwrap();
+        let plaintext_back =
+            decrypt_original(&target_sk, &target_capsule, &target_ciphertext).unwrap();
+        assert_eq!(&plaintext_back as &[u8], plaintext);
+
+        let (bridge_capsule, bridge_ciphertext) = encrypt(&bridge_pk, plaintext).unwrap();
+        assert!(rewrap_original(&bridge_sk, &bridge_capsule, &bridge_ciphertext, &target).is_ok());
+        assert_eq!(
+            rewrap_original(&bridge_sk, &capsule, &ciphertext, &target),
+            Err(RewrapError::Source(ReencryptionError::OnDecryption(
+                DecryptionError::AuthenticationFailed
+            )))
+        );
+    }
+}
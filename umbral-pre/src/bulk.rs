@@ -0,0 +1,382 @@
+//! Reencryption of many capsules with the group operations offloaded
+//! to an external implementation (e.g. a GPU or another accelerator).
+//!
+//! Reencrypting a capsule takes [`MULTIPLICATIONS_PER_CAPSULE`] point multiplications,
+//! and the rest of the work (hashing and a few scalar operations) is negligible in comparison.
+//! [`BulkReencryption`] collects the multiplications for a batch of capsules,
+//! so that they can be performed by a [`BulkReencryptor`] at once,
+//! and assembles the capsule fragments from the results.
+//! [`reencrypt_bulk()`] does all the steps.
+//!
+//! The points and the scalars are passed to the reencryptor in their canonical byte encodings,
+//! so that it does not depend on the types of this crate.
+//! **Warning:** the scalars include the key fragment itself and the nonces of the proofs
+//! (each of which reveals the key fragment when combined with the resulting capsule fragment),
+//! so the reencryptor must run within the trust boundary of the proxy.
+
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use core::fmt;
+
+use generic_array::GenericArray;
+use rand_core::{CryptoRng, OsRng, RngCore};
+use typenum::U32;
+use zeroize::Zeroize;
+
+use crate::capsule::Capsule;
+use crate::capsule_frag::{ReencryptionProducts, VerifiedCapsuleFrag};
+use crate::curve::{CompressedPoint, CurvePoint, CurveScalar};
+use crate::key_frag::VerifiedKeyFrag;
+use crate::metadata::{encode_metadata, Metadata};
+use crate::traits::{DeserializableFromArray, SerializableToArray};
+
+/// The number of point multiplications needed to reencrypt one capsule.
+pub const MULTIPLICATIONS_PER_CAPSULE: usize = 5;
+
+/// A scalar in the big-endian encoding (a canonical one, that is less than the curve order).
+pub type ScalarBytes = GenericArray<u8, U32>;
+
+/// Errors that can happen during [`reencrypt_bulk()`].
+#[derive(Debug, PartialEq)]
+pub enum BulkReencryptionError {
+    /// The reencryptor failed to perform the multiplications.
+    ReencryptorFailed,
+    /// The reencryptor returned a number of results different from the number of multiplications.
+    WrongResultCount {
+        /// The number of multiplications requested.
+        expected: usize,
+        /// The number of results returned.
+        received: usize,
+    },
+    /// The result with the given index is not a valid encoding of a curve point.
+    InvalidResult(usize),
+}
+
+impl fmt::Display for BulkReencryptionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::ReencryptorFailed => write!(f, "the reencryptor failed"),
+            Self::WrongResultCount { expected, received } => write!(
+                f,
+                "expected {} results from the reencryptor, received {}",
+                expected, received
+            ),
+            Self::InvalidResult(index) => {
+                write!(f, "the result {} is not a valid curve point", index)
+            }
+        }
+    }
+}
+
+/// An implementation of the point multiplications needed to reencrypt many capsules.
+///
+/// An implementation producing wrong results does not compromise the key fragment,
+/// but the resulting capsule fragments will fail verification on the receiving side.
+pub trait BulkReencryptor {
+    /// Computes `points[i] * scalars[i]` for each `i`,
+    /// returning the results in the compressed SEC1 encoding.
+    ///
+    /// The slices have the same length. All the points are on the curve and not the identity,
+    /// and all the scalars are non-zero, so none of the results are the identity.
+    fn multiply(
+        &mut self,
+        points: &[CompressedPoint],
+        scalars: &[ScalarBytes],
+    ) -> Result<Box<[CompressedPoint]>, BulkReencryptionError>;
+}
+
+/// A [`BulkReencryptor`] performing the multiplications on the CPU, one by one.
+///
+/// It is the reference for the external implementations;
+/// reencrypting on the CPU is faster with [`reencrypt_batch()`](crate::reencrypt_batch),
+/// which does not need to encode and decode the intermediate values.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct CpuReencryptor;
+
+impl BulkReencryptor for CpuReencryptor {
+    fn multiply(
+        &mut self,
+        points: &[CompressedPoint],
+        scalars: &[ScalarBytes],
+    ) -> Result<Box<[CompressedPoint]>, BulkReencryptionError> {
+        points
+            .iter()
+            .zip(scalars.iter())
+            .map(|(point, scalar)| {
+                let point = CurvePoint::from_array(point)
+                    .map_err(|_| BulkReencryptionError::ReencryptorFailed)?;
+                let mut scalar = CurveScalar::from_array(scalar)
+                    .map_err(|_| BulkReencryptionError::ReencryptorFailed)?;
+                let result = &point * &scalar;
+                scalar.zeroize();
+                Ok(result.to_array())
+            })
+            .collect()
+    }
+}
+
+/// The state of the reencryption of a batch of capsules with the same key fragment,
+/// between collecting the multiplications and applying their results.
+pub struct BulkReencryption<'a> {
+    capsules: &'a [Capsule],
+    verified_kfrag: &'a VerifiedKeyFrag,
+    metadata: Option<Box<[u8]>>,
+    // The nonces of the proofs, one per capsule.
+    nonces: Vec<CurveScalar>,
+}
+
+impl<'a> BulkReencryption<'a> {
+    /// Prepares the reencryption of the capsules with the key fragment.
+    /// `metadata` is attached to all the capsule fragments, as in [`reencrypt()`](crate::reencrypt).
+    pub fn new(
+        capsules: &'a [Capsule],
+        verified_kfrag: &'a VerifiedKeyFrag,
+        metadata: Option<&Metadata>,
+    ) -> Self {
+        Self::new_with_rng(&mut OsRng, capsules, verified_kfrag, metadata)
+    }
+
+    /// Same as [`new()`](Self::new), but uses the given RNG instead of the default one.
+    pub fn new_with_rng(
+        rng: &mut (impl CryptoRng + RngCore),
+        capsules: &'a [Capsule],
+        verified_kfrag: &'a VerifiedKeyFrag,
+        metadata: Option<&Metadata>,
+    ) -> Self {
+        let nonces = capsules
+            .iter()
+            .map(|_| CurveScalar::random_nonzero(rng))
+            .collect();
+        Self {
+            capsules,
+            verified_kfrag,
+            metadata: encode_metadata(metadata),
+            nonces,
+        }
+    }
+
+    /// Returns the total number of multiplications
+    /// ([`MULTIPLICATIONS_PER_CAPSULE`] per capsule).
+    pub fn len(&self) -> usize {
+        self.capsules.len() * MULTIPLICATIONS_PER_CAPSULE
+    }
+
+    /// Returns `true` if there are no capsules to reencrypt.
+    pub fn is_empty(&self) -> bool {
+        self.capsules.is_empty()
+    }
+
+    /// Returns the points to be multiplied, grouped by capsule.
+    pub fn points(&self) -> Box<[CompressedPoint]> {
+        self.capsules
+            .iter()
+            .flat_map(|capsule| {
+                let (e, v, u) = (capsule.point_e, capsule.point_v, capsule.params.u);
+                // Matches the order of `ReencryptionProducts`.
+                [e, v, e, v, u]
+            })
+            .map(|point| point.to_array())
+            .collect()
+    }
+
+    /// Returns the scalars the points are to be multiplied by, in the same order
+    /// as [`points()`](Self::points).
+    ///
+    /// **Warning:** the scalars are secret (see the module documentation).
+    pub fn scalars(&self) -> Box<[ScalarBytes]> {
+        let rk = self.verified_kfrag.kfrag.key;
+        self.nonces
+            .iter()
+            .flat_map(|t| [rk, rk, *t, *t, *t])
+            .map(|scalar| scalar.to_array())
+            .collect()
+    }
+
+    /// Creates the capsule fragments (in the order of the capsules)
+    /// from the results of the multiplications.
+    pub fn apply(
+        self,
+        results: &[CompressedPoint],
+    ) -> Result<Box<[VerifiedCapsuleFrag]>, BulkReencryptionError> {
+        if results.len() != self.len() {
+            return Err(BulkReencryptionError::WrongResultCount {
+                expected: self.len(),
+                received: results.len(),
+            });
+        }
+
+        let points = results
+            .iter()
+            .enumerate()
+            .map(|(i, result)| {
+                CurvePoint::from_array(result).map_err(|_| BulkReencryptionError::InvalidResult(i))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(self
+            .capsules
+            .iter()
+            .zip(self.nonces.iter())
+            .zip(points.chunks_exact(MULTIPLICATIONS_PER_CAPSULE))
+            .map(|((capsule, t), chunk)| {
+                let mut products: ReencryptionProducts = [CurvePoint::generator(); 5];
+                products.copy_from_slice(chunk);
+                VerifiedCapsuleFrag::from_products(
+                    capsule,
+                    &self.verified_kfrag.kfrag,
+                    t,
+                    &products,
+                    self.metadata.as_deref(),
+                )
+            })
+            .collect::<Vec<_>>()
+            .into_boxed_slice())
+    }
+}
+
+impl<'a> Drop for BulkReencryption<'a> {
+    fn drop(&mut self) {
+        for nonce in self.nonces.iter_mut() {
+            nonce.zeroize();
+        }
+    }
+}
+
+impl<'a> fmt::Debug for BulkReencryption<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BulkReencryption")
+            .field("capsules", &self.capsules.len())
+            .field("kfrag_id", self.verified_kfrag.id())
+            .finish()
+    }
+}
+
+/// Reencrypts the capsules with the same key fragment,
+/// performing the point multiplications with the given reencryptor.
+///
+/// The capsule fragments are returned in the order of the capsules,
+/// and are the same as created by [`reencrypt()`](crate::reencrypt)
+/// (if the reencryptor returns the correct results).
+pub fn reencrypt_bulk(
+    reencryptor: &mut impl BulkReencryptor,
+    capsules: &[Capsule],
+    verified_kfrag: &VerifiedKeyFrag,
+    metadata: Option<&Metadata>,
+) -> Result<Box<[VerifiedCapsuleFrag]>, BulkReencryptionError> {
+    reencrypt_bulk_with_rng(&mut OsRng, reencryptor, capsules, verified_kfrag, metadata)
+}
+
+/// Same as [`reencrypt_bulk()`], but uses the given RNG instead of the default one.
+pub fn reencrypt_bulk_with_rng(
+    rng: &mut (impl CryptoRng + RngCore),
+    reencryptor: &mut impl BulkReencryptor,
+    capsules: &[Capsule],
+    verified_kfrag: &VerifiedKeyFrag,
+    metadata: Option<&Metadata>,
+) -> Result<Box<[VerifiedCapsuleFrag]>, BulkReencryptionError> {
+    let batch = BulkReencryption::new_with_rng(rng, capsules, verified_kfrag, metadata);
+    let mut scalars = batch.scalars();
+    let results = reencryptor.multiply(&batch.points(), &scalars);
+    for scalar in scalars.iter_mut() {
+        scalar.zeroize();
+    }
+    batch.apply(&results?)
+}
+
+#[cfg(all(test, feature = "role-alice", feature = "role-bob"))]
+mod tests {
+
+    use alloc::boxed::Box;
+    use alloc::vec::Vec;
+
+    use super::{
+        reencrypt_bulk, BulkReencryption, BulkReencryptionError, BulkReencryptor, CpuReencryptor,
+        ScalarBytes, MULTIPLICATIONS_PER_CAPSULE,
+    };
+    use crate::curve::CompressedPoint;
+    use crate::{
+        decapsulate_reencrypted, encapsulate, generate_kfrags, PublicKey, SecretKey, Signer,
+    };
+
+    // Returns the points unchanged.
+    struct FaultyReencryptor;
+
+    impl BulkReencryptor for FaultyReencryptor {
+        fn multiply(
+            &mut self,
+            points: &[CompressedPoint],
+            _scalars: &[ScalarBytes],
+        ) -> Result<Box<[CompressedPoint]>, BulkReencryptionError> {
+            Ok(points.into())
+        }
+    }
+
+    #[test]
+    fn test_reencrypt_bulk() {
+        let delegating_sk = SecretKey::random();
+        let delegating_pk = PublicKey::from_secret_key(&delegating_sk);
+        let receiving_sk = SecretKey::random();
+        let receiving_pk = PublicKey::from_secret_key(&receiving_sk);
+        let signer = Signer::new(&SecretKey::random());
+        let verifying_pk = signer.verifying_key();
+
+        let (capsules, key_seeds): (Vec<_>, Vec<_>) =
+            (0..3).map(|_| encapsulate(&delegating_pk)).unzip();
+        let kfrags = generate_kfrags(&delegating_sk, &receiving_pk, &signer, 2, 3, true, true);
+
+        let batches = kfrags[..2]
+            .iter()
+            .map(|kfrag| reencrypt_bulk(&mut CpuReencryptor, &capsules, kfrag, None).unwrap())
+            .collect::<Vec<_>>();
+
+        for (i, (capsule, key_seed)) in capsules.iter().zip(key_seeds.iter()).enumerate() {
+            let vcfrags = batches
+                .iter()
+                .map(|batch| {
+                    batch[i]
+                        .clone()
+                        .unverify()
+                        .verify(capsule, &verifying_pk, &delegating_pk, &receiving_pk, None)
+                        .unwrap()
+                })
+                .collect::<Vec<_>>();
+            assert_eq!(
+                decapsulate_reencrypted(&receiving_sk, &delegating_pk, capsule, &vcfrags),
+                Ok(*key_seed)
+            );
+        }
+
+        // Wrong results produce fragments that do not pass verification.
+        let cfrags = reencrypt_bulk(&mut FaultyReencryptor, &capsules, &kfrags[0], None).unwrap();
+        assert!(cfrags[0]
+            .clone()
+            .unverify()
+            .verify(
+                &capsules[0],
+                &verifying_pk,
+                &delegating_pk,
+                &receiving_pk,
+                None
+            )
+            .is_err());
+
+        let batch = BulkReencryption::new(&capsules, &kfrags[0], None);
+        assert_eq!(batch.len(), capsules.len() * MULTIPLICATIONS_PER_CAPSULE);
+        let points = batch.points();
+        assert_eq!(
+            batch.apply(&points[1..]),
+            Err(BulkReencryptionError::WrongResultCount {
+                expected: points.len(),
+                received: points.len() - 1
+            })
+        );
+
+        let batch = BulkReencryption::new(&capsules, &kfrags[0], None);
+        let mut results = batch.points().to_vec();
+        results[4] = CompressedPoint::default();
+        assert_eq!(
+            batch.apply(&results),
+            Err(BulkReencryptionError::InvalidResult(4))
+        );
+    }
+}
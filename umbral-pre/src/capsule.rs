@@ -1,17 +1,28 @@
+#[cfg(feature = "role-bob")]
 use crate::capsule_frag::CapsuleFrag;
-use crate::curve::{CurvePoint, CurveScalar};
-use crate::hashing_ds::{hash_capsule_points, hash_to_polynomial_arg, hash_to_shared_secret};
-use crate::keys::{PublicKey, SecretKey};
+use crate::curve::{multi_mul, CompressedPoint, CurvePoint, CurveScalar};
+use crate::fingerprint::Fingerprint;
+use crate::hashing_ds::{
+    hash_capsule_points, hash_to_capsule_fingerprint, hash_to_key_hint_challenge,
+};
+#[cfg(feature = "role-bob")]
+use crate::hashing_ds::{hash_to_polynomial_arg, hash_to_shared_secret};
+#[cfg(feature = "role-bob")]
+use crate::key_frag::KeyFragID;
+use crate::keys::{PrecomputedPublicKey, PublicKey, SecretKey};
 use crate::params::Parameters;
 use crate::traits::{
     DeserializableFromArray, DeserializationError, RepresentableAsArray, SerializableToArray,
+    VersionedSerialization,
 };
 
 use alloc::vec::Vec;
+use core::fmt;
 
 use generic_array::sequence::Concat;
 use generic_array::GenericArray;
-use typenum::op;
+use rand_core::{CryptoRng, OsRng, RngCore};
+use typenum::{op, Unsigned};
 
 /// Errors that can happen when opening a `Capsule` using reencrypted `CapsuleFrag` objects.
 #[derive(Debug, PartialEq)]
@@ -21,8 +32,20 @@ pub enum OpenReencryptedError {
     /// Capsule fragments are mismatched (originated from [`KeyFrag`](crate::KeyFrag) objects
     /// generated by different [`generate_kfrags`](crate::generate_kfrags) calls).
     MismatchedCapsuleFrags,
-    /// Some of the given capsule fragments are repeated.
+    /// Some of the given capsule fragments are repeated
+    /// (were created with the same key fragment).
     RepeatingCapsuleFrags,
+    /// Some of the given capsule fragments were verified against a different capsule.
+    MismatchedCapsule,
+    /// The capsule was created for another delegating key than the given one
+    /// (see [`HintedCapsule::check_delegating_key()`]).
+    MismatchedDelegatingKey,
+    /// The capsule fragment at the given position (counting from 0)
+    /// was verified against a different capsule.
+    ForeignCapsuleFrag(usize),
+    /// The capsule fragments are consistent, but there are fewer of them than the threshold
+    /// (or the keys do not match the ones the fragments were created for).
+    NotEnoughCapsuleFrags,
     /// An internally hashed value is zero.
     /// See [rust-umbral#39](https://github.com/nucypher/rust-umbral/issues/39).
     ZeroHash,
@@ -32,6 +55,61 @@ pub enum OpenReencryptedError {
     ValidationFailed,
 }
 
+impl fmt::Display for OpenReencryptedError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NoCapsuleFrags => write!(f, "no capsule fragments given"),
+            Self::MismatchedCapsuleFrags => write!(f, "capsule fragments are mismatched"),
+            Self::RepeatingCapsuleFrags => write!(f, "some of the capsule fragments are repeated"),
+            Self::MismatchedCapsule => {
+                write!(
+                    f,
+                    "capsule fragments were verified against a different capsule"
+                )
+            }
+            Self::MismatchedDelegatingKey => {
+                write!(f, "the capsule was created for another delegating key")
+            }
+            Self::ForeignCapsuleFrag(position) => write!(
+                f,
+                "capsule fragment {} was verified against a different capsule",
+                position
+            ),
+            Self::NotEnoughCapsuleFrags => write!(f, "not enough capsule fragments"),
+            Self::ZeroHash => write!(f, "an internally hashed value is zero"),
+            Self::ValidationFailed => write!(f, "internal validation failed"),
+        }
+    }
+}
+
+/// Reasons a serialized capsule fails [`Capsule::assert_well_formed()`].
+#[derive(Debug, PartialEq)]
+pub enum CapsuleFormatError {
+    /// The bytestring has a wrong length.
+    InvalidLength,
+    /// One of the points is the identity (encoded with a zero tag).
+    IdentityPoint,
+    /// One of the points is not a valid point on the curve.
+    InvalidPoint,
+    /// The scalar is not less than the order of the curve.
+    ScalarOutOfRange,
+    /// The components are well-formed, but do not satisfy the relation between them
+    /// (the capsule was corrupted or tampered with).
+    Inconsistent,
+}
+
+impl fmt::Display for CapsuleFormatError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidLength => write!(f, "invalid capsule length"),
+            Self::IdentityPoint => write!(f, "a capsule point is the identity"),
+            Self::InvalidPoint => write!(f, "a capsule point is not on the curve"),
+            Self::ScalarOutOfRange => write!(f, "the capsule scalar is out of range"),
+            Self::Inconsistent => write!(f, "the capsule components are inconsistent"),
+        }
+    }
+}
+
 /// Encapsulated symmetric key used to encrypt the plaintext.
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub struct Capsule {
@@ -67,7 +145,15 @@ impl DeserializableFromArray for Capsule {
     }
 }
 
+impl VersionedSerialization for Capsule {
+    const TYPE_TAG: u8 = 1;
+}
+
 impl Capsule {
+    /// The size of the byte representation of a capsule
+    /// (returned by [`to_array()`](`SerializableToArray::to_array`)).
+    pub const SERIALIZED_SIZE: usize = <Self as RepresentableAsArray>::Size::USIZE;
+
     fn new(point_e: CurvePoint, point_v: CurvePoint, signature: CurveScalar) -> Self {
         let params = Parameters::new();
         Self {
@@ -90,28 +176,143 @@ impl Capsule {
         }
     }
 
+    /// Checks that `bytes` is a structurally valid serialized capsule:
+    /// both points are on the curve (and hence in the prime-order group,
+    /// since the cofactor of secp256k1 is 1) and are not the identity,
+    /// and the scalar is less than the order of the curve.
+    ///
+    /// Unlike deserialization, does not check the relation between the points and the scalar
+    /// (which takes a hash and several point multiplications),
+    /// so a capsule passing this check may still be rejected by
+    /// [`from_bytes()`](`DeserializableFromArray::from_bytes`).
+    /// Intended for relays that only forward capsules, to drop garbage early.
+    pub fn assert_well_formed(bytes: &[u8]) -> Result<(), CapsuleFormatError> {
+        if bytes.len() != <Self as RepresentableAsArray>::Size::to_usize() {
+            return Err(CapsuleFormatError::InvalidLength);
+        }
+
+        let point_size = PointSize::to_usize();
+        let (points, scalar) = bytes.split_at(2 * point_size);
+        for point in points.chunks(point_size) {
+            if point[0] == 0 {
+                return Err(CapsuleFormatError::IdentityPoint);
+            }
+            CurvePoint::from_bytes(point).or(Err(CapsuleFormatError::InvalidPoint))?;
+        }
+        CurveScalar::from_bytes(scalar).or(Err(CapsuleFormatError::ScalarOutOfRange))?;
+
+        Ok(())
+    }
+
+    /// Checks that `bytes` is a valid serialized capsule: in addition to the checks of
+    /// [`assert_well_formed()`](`Self::assert_well_formed`), verifies the relation
+    /// between the points `E`, `V` and the scalar `s` (`g * s == V + E * h`,
+    /// where `h` is the hash of the points).
+    ///
+    /// These are the same checks [`from_bytes()`](`DeserializableFromArray::from_bytes`) performs,
+    /// but the failure is reported with a specific error.
+    /// Intended for storage layers validating capsules on ingest.
+    pub fn verify_self_consistency(bytes: &[u8]) -> Result<(), CapsuleFormatError> {
+        Self::assert_well_formed(bytes)?;
+
+        let point_size = PointSize::to_usize();
+        let point_e = CurvePoint::from_bytes(&bytes[..point_size])
+            .or(Err(CapsuleFormatError::InvalidPoint))?;
+        let point_v = CurvePoint::from_bytes(&bytes[point_size..2 * point_size])
+            .or(Err(CapsuleFormatError::InvalidPoint))?;
+        let signature = CurveScalar::from_bytes(&bytes[2 * point_size..])
+            .or(Err(CapsuleFormatError::ScalarOutOfRange))?;
+
+        if !Self::new(point_e, point_v, signature).verify() {
+            return Err(CapsuleFormatError::Inconsistent);
+        }
+        Ok(())
+    }
+
+    /// Returns the fingerprint of the capsule, to reference it consistently
+    /// (see [`Fingerprint`] for the derivation).
+    pub fn fingerprint(&self) -> Fingerprint {
+        Fingerprint::new(hash_to_capsule_fingerprint(self))
+    }
+
+    /// Returns the point `E` of the capsule.
+    pub fn e(&self) -> CompressedPoint {
+        self.point_e.to_array()
+    }
+
+    /// Returns the point `V` of the capsule.
+    pub fn v(&self) -> CompressedPoint {
+        self.point_v.to_array()
+    }
+
     /// Verifies the integrity of the capsule.
     fn verify(&self) -> bool {
-        let g = CurvePoint::generator();
         let h = hash_capsule_points(&self.point_e, &self.point_v);
-        &g * &self.signature == &self.point_v + &(&self.point_e * &h)
+        capsule_signature_is_valid(&self.point_e, &self.point_v, &self.signature, &h)
     }
 
     /// Generates a symmetric key and its associated KEM ciphertext
-    pub(crate) fn from_public_key(pk: &PublicKey) -> (Capsule, CurvePoint) {
-        let g = CurvePoint::generator();
+    pub(crate) fn from_public_key(
+        rng: &mut (impl CryptoRng + RngCore),
+        pk: &PublicKey,
+    ) -> (Capsule, CurvePoint) {
+        let priv_r = CurveScalar::random_nonzero(rng);
+        let priv_u = CurveScalar::random_nonzero(rng);
+        Self::from_scalars(pk, &priv_r, &priv_u)
+    }
 
-        let priv_r = CurveScalar::random_nonzero();
-        let pub_r = &g * &priv_r;
+    /// Same as [`from_public_key()`](`Self::from_public_key`),
+    /// but uses the precomputed tables of the key and the generator.
+    pub(crate) fn from_precomputed_public_key(
+        rng: &mut (impl CryptoRng + RngCore),
+        pk: &PrecomputedPublicKey,
+    ) -> (Capsule, CurvePoint) {
+        let priv_r = CurveScalar::random_nonzero(rng);
+        let priv_u = CurveScalar::random_nonzero(rng);
 
-        let priv_u = CurveScalar::random_nonzero();
-        let pub_u = &g * &priv_u;
+        let pub_r = pk.mul_generator(&priv_r);
+        let pub_u = pk.mul_generator(&priv_u);
 
         let h = hash_capsule_points(&pub_r, &pub_u);
 
         let s = &priv_u + &(&priv_r * &h);
 
-        let shared_key = &pk.to_point() * &(&priv_r + &priv_u);
+        let shared_key = pk.mul(&(&priv_r + &priv_u));
+
+        let capsule = Self::new(pub_r, pub_u, s);
+
+        (capsule, shared_key)
+    }
+
+    /// Same as [`from_public_key()`](`Self::from_public_key`),
+    /// but bundles the capsule with the fingerprint of `pk`.
+    pub(crate) fn from_public_key_with_hint(
+        rng: &mut (impl CryptoRng + RngCore),
+        pk: &PublicKey,
+    ) -> (HintedCapsule, CurvePoint) {
+        let priv_r = CurveScalar::random_nonzero(rng);
+        let priv_u = CurveScalar::random_nonzero(rng);
+        let (capsule, shared_key) = Self::from_scalars(pk, &priv_r, &priv_u);
+        let hinted = HintedCapsule::new(rng, capsule, &priv_r, pk.fingerprint());
+        (hinted, shared_key)
+    }
+
+    /// Same as [`from_public_key()`](`Self::from_public_key`),
+    /// but with the given ephemeral scalars.
+    pub(crate) fn from_scalars(
+        pk: &PublicKey,
+        priv_r: &CurveScalar,
+        priv_u: &CurveScalar,
+    ) -> (Capsule, CurvePoint) {
+        let g = CurvePoint::generator();
+        let pub_r = &g * priv_r;
+        let pub_u = &g * priv_u;
+
+        let h = hash_capsule_points(&pub_r, &pub_u);
+
+        let s = priv_u + &(priv_r * &h);
+
+        let shared_key = &pk.to_point() * &(priv_r + priv_u);
 
         let capsule = Self::new(pub_r, pub_u, s);
 
@@ -120,10 +321,14 @@ impl Capsule {
 
     /// Derive the same symmetric key
     pub(crate) fn open_original(&self, private_key: &SecretKey) -> CurvePoint {
-        &(&self.point_e + &self.point_v) * &private_key.to_secret_scalar()
+        original_shared_key(
+            &self.point_e,
+            &self.point_v,
+            &private_key.to_secret_scalar(),
+        )
     }
 
-    #[allow(clippy::many_single_char_names)]
+    #[cfg(feature = "role-bob")]
     pub(crate) fn open_reencrypted(
         &self,
         receiving_sk: &SecretKey,
@@ -140,26 +345,52 @@ impl Capsule {
             return Err(OpenReencryptedError::MismatchedCapsuleFrags);
         }
 
+        let kfrag_ids: Vec<_> = cfrags.iter().map(|cfrag| cfrag.kfrag_id).collect();
+        let mut sorted_ids = kfrag_ids.clone();
+        sorted_ids.sort_unstable_by(|id1, id2| id1.as_ref().cmp(id2.as_ref()));
+        if sorted_ids.windows(2).any(|pair| pair[0] == pair[1]) {
+            return Err(OpenReencryptedError::RepeatingCapsuleFrags);
+        }
+
+        let e1s: Vec<_> = cfrags.iter().map(|cfrag| cfrag.point_e1).collect();
+        let v1s: Vec<_> = cfrags.iter().map(|cfrag| cfrag.point_v1).collect();
+        self.open_reencrypted_parts(
+            receiving_sk,
+            delegating_pk,
+            &precursor,
+            &kfrag_ids,
+            &e1s,
+            &v1s,
+        )
+    }
+
+    /// Same as [`open_reencrypted()`](`Self::open_reencrypted`), but takes the parts
+    /// of the capsule fragments used in the combination (checked to have the same precursor).
+    #[allow(clippy::many_single_char_names)]
+    #[cfg(feature = "role-bob")]
+    pub(crate) fn open_reencrypted_parts(
+        &self,
+        receiving_sk: &SecretKey,
+        delegating_pk: &PublicKey,
+        precursor: &CurvePoint,
+        kfrag_ids: &[KeyFragID],
+        e1s: &[CurvePoint],
+        v1s: &[CurvePoint],
+    ) -> Result<CurvePoint, OpenReencryptedError> {
+        let precursor = *precursor;
         let pub_key = PublicKey::from_secret_key(receiving_sk).to_point();
         let dh_point = &precursor * &receiving_sk.to_secret_scalar();
 
         // Combination of CFrags via Shamir's Secret Sharing reconstruction
-        let mut lc = Vec::<CurveScalar>::with_capacity(cfrags.len());
-        for cfrag in cfrags {
-            let coeff = hash_to_polynomial_arg(&precursor, &pub_key, &dh_point, &cfrag.kfrag_id);
-            lc.push(coeff);
-        }
+        let lc: Vec<_> = kfrag_ids
+            .iter()
+            .map(|kfrag_id| hash_to_polynomial_arg(&precursor, &pub_key, &dh_point, kfrag_id))
+            .collect();
 
-        let mut e_prime = CurvePoint::identity();
-        let mut v_prime = CurvePoint::identity();
-        for (i, cfrag) in (&cfrags).iter().enumerate() {
-            // There is a minuscule probability that coefficients for two different frags are equal,
-            // in which case we'd rather fail gracefully.
-            let lambda_i =
-                lambda_coeff(&lc, i).ok_or(OpenReencryptedError::RepeatingCapsuleFrags)?;
-            e_prime = &e_prime + &(&cfrag.point_e1 * &lambda_i);
-            v_prime = &v_prime + &(&cfrag.point_v1 * &lambda_i);
-        }
+        // There is a minuscule probability that coefficients for two different frags are equal,
+        // in which case we'd rather fail gracefully.
+        let (e_prime, v_prime) =
+            lagrange_combine(&lc, e1s, v1s).ok_or(OpenReencryptedError::RepeatingCapsuleFrags)?;
 
         // Secret value 'd' allows to make Umbral non-interactive
         let d = hash_to_shared_secret(&precursor, &pub_key, &dh_point);
@@ -170,6 +401,7 @@ impl Capsule {
         let orig_pub_key = delegating_pk.to_point();
 
         // Have to convert from subtle::CtOption here.
+        // Variable-time: only exits early if `d` is zero, which happens with a negligible probability.
         let inv_d_opt: Option<CurveScalar> = d.invert().into();
         // At the moment we cannot guarantee statically that the digest `d` is non-zero.
         // Technically, it is supposed to be non-zero by the choice of `precursor`,
@@ -177,36 +409,299 @@ impl Capsule {
         // we'd rather fail gracefully than panic.
         let inv_d = inv_d_opt.ok_or(OpenReencryptedError::ZeroHash)?;
 
-        if &orig_pub_key * &(&s * &inv_d) != &(&e_prime * &h) + &v_prime {
+        if !reencrypted_capsule_is_valid(&orig_pub_key, &s, &inv_d, &h, &e_prime, &v_prime) {
             return Err(OpenReencryptedError::ValidationFailed);
         }
 
-        let shared_key = &(&e_prime + &v_prime) * &d;
-        Ok(shared_key)
+        Ok(original_shared_key(&e_prime, &v_prime, &d))
+    }
+
+    /// Combines the partial decryptions `(E * a_i, V * a_i)` of the threshold mode,
+    /// where `a_i` are the shares of the delegating secret key at the points `xs`.
+    /// Requires `xs` to be distinct and of the same length as `e1s` and `v1s`.
+    ///
+    /// Returns `None` if the combined points do not correspond to the capsule
+    /// (not enough shares, or some of the partial decryptions are incorrect).
+    pub(crate) fn open_threshold_parts(
+        &self,
+        delegating_pk: &PublicKey,
+        xs: &[CurveScalar],
+        e1s: &[CurvePoint],
+        v1s: &[CurvePoint],
+    ) -> Option<CurvePoint> {
+        let (e_prime, v_prime) = lagrange_combine(xs, e1s, v1s)?;
+
+        // Same as the check of the reencrypted capsule, with `d = 1`,
+        // since the shares are taken directly from the delegating key.
+        let h = hash_capsule_points(&self.point_e, &self.point_v);
+        let one = CurveScalar::one();
+        if !reencrypted_capsule_is_valid(
+            &delegating_pk.to_point(),
+            &self.signature,
+            &one,
+            &h,
+            &e_prime,
+            &v_prime,
+        ) {
+            return None;
+        }
+
+        Some(original_shared_key(&e_prime, &v_prime, &one))
+    }
+}
+
+/// A [`Capsule`] bundled with the fingerprint of the delegating public key it was created for
+/// (see [`encapsulate_with_key_hint()`](crate::encapsulate_with_key_hint)).
+///
+/// The hint is signed with the ephemeral key `E` of the capsule,
+/// so it can only be attached by the encrypting party, and cannot be replaced.
+/// The capsule itself is left intact: it is serialized, reencrypted and opened
+/// the same way as any other capsule, and the hint can be dropped by the parties
+/// that do not need it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct HintedCapsule {
+    capsule: Capsule,
+    hint: Fingerprint,
+    commitment: CurvePoint,
+    response: CurveScalar,
+}
+
+type CapsuleSize = <Capsule as RepresentableAsArray>::Size;
+type FingerprintSize = <Fingerprint as RepresentableAsArray>::Size;
+
+impl RepresentableAsArray for HintedCapsule {
+    type Size = op!(CapsuleSize + FingerprintSize + PointSize + ScalarSize);
+}
+
+impl SerializableToArray for HintedCapsule {
+    fn to_array(&self) -> GenericArray<u8, Self::Size> {
+        self.capsule
+            .to_array()
+            .concat(self.hint.to_array())
+            .concat(self.commitment.to_array())
+            .concat(self.response.to_array())
+    }
+}
+
+impl DeserializableFromArray for HintedCapsule {
+    fn from_array(arr: &GenericArray<u8, Self::Size>) -> Result<Self, DeserializationError> {
+        let (capsule, rest) = Capsule::take(*arr)?;
+        let (hint, rest) = Fingerprint::take(rest)?;
+        let (commitment, rest) = CurvePoint::take(rest)?;
+        let response = CurveScalar::take_last(rest)?;
+        let hinted = Self {
+            capsule,
+            hint,
+            commitment,
+            response,
+        };
+        match hinted.verify() {
+            false => Err(DeserializationError::ConstructionFailure),
+            true => Ok(hinted),
+        }
+    }
+}
+
+impl VersionedSerialization for HintedCapsule {
+    const TYPE_TAG: u8 = 5;
+}
+
+impl HintedCapsule {
+    /// The size of the byte representation of a hinted capsule
+    /// (returned by [`to_array()`](`SerializableToArray::to_array`)).
+    pub const SERIALIZED_SIZE: usize = <Self as RepresentableAsArray>::Size::USIZE;
+
+    /// Signs `hint` with the ephemeral scalar `priv_r` of `capsule` (`E == g * priv_r`).
+    fn new(
+        rng: &mut (impl CryptoRng + RngCore),
+        capsule: Capsule,
+        priv_r: &CurveScalar,
+        hint: Fingerprint,
+    ) -> Self {
+        let nonce = CurveScalar::random_nonzero(rng);
+        let commitment = &CurvePoint::generator() * &nonce;
+        let challenge =
+            hash_to_key_hint_challenge(&capsule.point_e, &capsule.point_v, &hint, &commitment);
+        let response = &nonce + &(priv_r * &challenge);
+        Self {
+            capsule,
+            hint,
+            commitment,
+            response,
+        }
+    }
+
+    /// Checks the signature of the hint: `g * response == commitment + E * challenge`.
+    fn verify(&self) -> bool {
+        let challenge = hash_to_key_hint_challenge(
+            &self.capsule.point_e,
+            &self.capsule.point_v,
+            &self.hint,
+            &self.commitment,
+        );
+        &CurvePoint::generator() * &self.response
+            == &self.commitment + &(&self.capsule.point_e * &challenge)
+    }
+
+    /// Returns the capsule.
+    pub fn capsule(&self) -> &Capsule {
+        &self.capsule
     }
+
+    /// Returns the fingerprint of the delegating public key the capsule was created for.
+    pub fn delegating_key_hint(&self) -> Fingerprint {
+        self.hint
+    }
+
+    /// Returns the capsule if it was created for `delegating_pk`,
+    /// and [`OpenReencryptedError::MismatchedDelegatingKey`] otherwise.
+    ///
+    /// Intended to be called by the receiving party before collecting the capsule fragments,
+    /// since with the wrong delegating key the opening would only fail at its final check.
+    pub fn check_delegating_key(
+        &self,
+        delegating_pk: &PublicKey,
+    ) -> Result<&Capsule, OpenReencryptedError> {
+        if self.hint != delegating_pk.fingerprint() {
+            return Err(OpenReencryptedError::MismatchedDelegatingKey);
+        }
+        Ok(&self.capsule)
+    }
+}
+
+// The functions below are pure and free of secret-dependent control flow
+// (except for the early exits on non-invertible values),
+// so that they can be targeted by verification and extraction tools.
+// The arithmetic of the backend is constant-time; the remaining variable-time paths
+// in this module and in `key_frag` only depend on public values, or abort the operation
+// with a negligible probability, and are marked with "Variable-time" comments.
+
+/// Checks the capsule's Schnorr-like signature:
+/// `g * s == V + E * h`.
+fn capsule_signature_is_valid(
+    point_e: &CurvePoint,
+    point_v: &CurvePoint,
+    signature: &CurveScalar,
+    h: &CurveScalar,
+) -> bool {
+    let g = CurvePoint::generator();
+    &g * signature == point_v + &(point_e * h)
+}
+
+/// Computes the shared key `(E + V) * scalar`.
+/// With `scalar` being the delegating secret key, this is the key created during encryption;
+/// with the combined reencrypted points `(E', V')` and `d`, this is the same key.
+fn original_shared_key(
+    point_e: &CurvePoint,
+    point_v: &CurvePoint,
+    scalar: &CurveScalar,
+) -> CurvePoint {
+    &(point_e + point_v) * scalar
+}
+
+/// Checks that the combined reencrypted points correspond to the original capsule:
+/// `P_delegating * (s / d) == E' * h + V'`.
+fn reencrypted_capsule_is_valid(
+    delegating_point: &CurvePoint,
+    signature: &CurveScalar,
+    inv_d: &CurveScalar,
+    h: &CurveScalar,
+    e_prime: &CurvePoint,
+    v_prime: &CurvePoint,
+) -> bool {
+    delegating_point * &(signature * inv_d) == &(e_prime * h) + v_prime
+}
+
+/// Computes `(sum_i lambda_i * e1s[i], sum_i lambda_i * v1s[i])`,
+/// where `lambda_i` is the Lagrange coefficient for the evaluation point `xs[i]`
+/// (see [`lambda_coeffs`]), computed once for both sums.
+/// Requires `xs`, `e1s` and `v1s` to be of the same length.
+/// Returns `None` if some of `xs` are equal.
+#[cfg_attr(hax, hax_lib::requires(xs.len() == e1s.len() && xs.len() == v1s.len()))]
+fn lagrange_combine(
+    xs: &[CurveScalar],
+    e1s: &[CurvePoint],
+    v1s: &[CurvePoint],
+) -> Option<(CurvePoint, CurvePoint)> {
+    debug_assert!(xs.len() == e1s.len() && xs.len() == v1s.len());
+    let lambdas = lambda_coeffs(xs)?;
+    Some((multi_mul(&lambdas, e1s), multi_mul(&lambdas, v1s)))
 }
 
-fn lambda_coeff(xs: &[CurveScalar], i: usize) -> Option<CurveScalar> {
-    let mut res = CurveScalar::one();
-    for j in 0..xs.len() {
-        if j != i {
-            let inv_diff_opt: Option<CurveScalar> = (&xs[j] - &xs[i]).invert().into();
-            let inv_diff = inv_diff_opt?;
-            res = &(&res * &xs[j]) * &inv_diff;
+/// Computes the Lagrange coefficients `prod_{j != i} xs[j] / (xs[j] - xs[i])`
+/// for the interpolation at zero, for all `i`.
+/// Returns `None` if some of `xs` are equal.
+///
+/// The evaluation points are derived from secret values (e.g. the DH point of the receiver),
+/// so the computation does not branch on them: all the denominators are inverted at once
+/// (with Montgomery's trick), and the product being inverted is multiplied
+/// by a random blinding factor, so that even a variable-time inversion
+/// would not reveal anything about the points.
+/// The only data-dependent branch is on whether all the points are distinct.
+#[cfg_attr(
+    hax,
+    hax_lib::ensures(|result| result.as_ref().map_or(true, |lambdas| lambdas.len() == xs.len()))
+)]
+pub(crate) fn lambda_coeffs(xs: &[CurveScalar]) -> Option<Vec<CurveScalar>> {
+    let mut numerators = Vec::with_capacity(xs.len());
+    let mut denominators = Vec::with_capacity(xs.len());
+    for (i, x_i) in xs.iter().enumerate() {
+        let mut numerator = CurveScalar::one();
+        let mut denominator = CurveScalar::one();
+        for (j, x_j) in xs.iter().enumerate() {
+            // The branch depends on the positions only.
+            if j != i {
+                numerator = &numerator * x_j;
+                denominator = &denominator * &(x_j - x_i);
+            }
         }
+        numerators.push(numerator);
+        denominators.push(denominator);
+    }
+
+    // `prefix_products[i]` is the product of the first `i` denominators.
+    let mut prefix_products = Vec::with_capacity(xs.len() + 1);
+    let mut product = CurveScalar::one();
+    for denominator in denominators.iter() {
+        prefix_products.push(product);
+        product = &product * denominator;
+    }
+
+    // The product is zero (and the inversion fails) if any of the denominators is.
+    let blinding = CurveScalar::random_nonzero(&mut OsRng);
+    let inv_blinded: Option<CurveScalar> = (&product * &blinding).invert().into();
+    let mut inv_product = &inv_blinded? * &blinding;
+
+    let mut lambdas = numerators;
+    for i in (0..xs.len()).rev() {
+        let inv_denominator = &inv_product * &prefix_products[i];
+        inv_product = &inv_product * &denominators[i];
+        lambdas[i] = &lambdas[i] * &inv_denominator;
     }
-    Some(res)
+    Some(lambdas)
 }
 
-#[cfg(test)]
+#[cfg(all(
+    test,
+    feature = "role-alice",
+    feature = "role-ursula",
+    feature = "role-bob"
+))]
 mod tests {
 
     use alloc::vec::Vec;
+    use core::convert::TryFrom;
+
+    use rand_core::OsRng;
 
-    use super::{Capsule, OpenReencryptedError};
+    use super::{
+        lagrange_combine, lambda_coeffs, Capsule, CapsuleFormatError, HintedCapsule,
+        OpenReencryptedError,
+    };
+    use crate::curve::{CurvePoint, CurveScalar};
     use crate::{
-        encrypt, generate_kfrags, reencrypt, DeserializableFromArray, PublicKey, SecretKey,
-        SerializableToArray, Signer,
+        encapsulate, generate_kfrags, reencrypt, DeserializableFromArray, DeserializationError,
+        PublicKey, SecretKey, SerializableToArray, Signer, VersionedSerialization,
     };
 
     #[test]
@@ -214,12 +709,84 @@ mod tests {
         let delegating_sk = SecretKey::random();
         let delegating_pk = PublicKey::from_secret_key(&delegating_sk);
 
-        let plaintext = b"peace at dawn";
-        let (capsule, _ciphertext) = encrypt(&delegating_pk, plaintext).unwrap();
+        let (capsule, _key_seed) = encapsulate(&delegating_pk);
 
         let capsule_arr = capsule.to_array();
         let capsule_back = Capsule::from_array(&capsule_arr).unwrap();
         assert_eq!(capsule, capsule_back);
+
+        let capsule_bytes = capsule.to_bytes();
+        assert_eq!(&capsule_bytes as &[u8], capsule_arr.as_slice());
+        assert_eq!(Capsule::try_from(&capsule_bytes[..]), Ok(capsule));
+        assert_eq!(
+            Capsule::try_from(&capsule_bytes[1..]),
+            Err(DeserializationError::NotEnoughBytes)
+        );
+    }
+
+    #[test]
+    fn test_assert_well_formed() {
+        let delegating_pk = PublicKey::from_secret_key(&SecretKey::random());
+        let (capsule, _key_seed) = encapsulate(&delegating_pk);
+        let capsule_arr = capsule.to_array();
+        assert_eq!(Capsule::assert_well_formed(&capsule_arr), Ok(()));
+        assert_eq!(Capsule::verify_self_consistency(&capsule_arr), Ok(()));
+
+        assert_eq!(
+            Capsule::assert_well_formed(&capsule_arr[1..]),
+            Err(CapsuleFormatError::InvalidLength)
+        );
+        assert_eq!(
+            Capsule::verify_self_consistency(&capsule_arr[1..]),
+            Err(CapsuleFormatError::InvalidLength)
+        );
+
+        let mut identity = capsule_arr;
+        for byte in identity[..33].iter_mut() {
+            *byte = 0;
+        }
+        assert_eq!(
+            Capsule::assert_well_formed(&identity),
+            Err(CapsuleFormatError::IdentityPoint)
+        );
+        assert_eq!(
+            Capsule::from_array(&identity),
+            Err(DeserializationError::InvalidPoint)
+        );
+
+        let mut invalid_point = capsule_arr;
+        invalid_point[33] = 4;
+        assert_eq!(
+            Capsule::assert_well_formed(&invalid_point),
+            Err(CapsuleFormatError::InvalidPoint)
+        );
+        assert_eq!(
+            Capsule::from_array(&invalid_point),
+            Err(DeserializationError::InvalidPoint)
+        );
+
+        let mut invalid_scalar = capsule_arr;
+        for byte in invalid_scalar[66..].iter_mut() {
+            *byte = 0xff;
+        }
+        assert_eq!(
+            Capsule::assert_well_formed(&invalid_scalar),
+            Err(CapsuleFormatError::ScalarOutOfRange)
+        );
+        assert_eq!(
+            Capsule::from_array(&invalid_scalar),
+            Err(DeserializationError::InvalidScalar)
+        );
+
+        // Well-formed, but the signature relation does not hold.
+        let mut wrong_scalar = capsule_arr;
+        wrong_scalar[97] ^= 1;
+        assert_eq!(Capsule::assert_well_formed(&wrong_scalar), Ok(()));
+        assert_eq!(
+            Capsule::verify_self_consistency(&wrong_scalar),
+            Err(CapsuleFormatError::Inconsistent)
+        );
+        assert!(Capsule::from_array(&wrong_scalar).is_err());
     }
 
     #[test]
@@ -233,7 +800,7 @@ mod tests {
         let receiving_sk = SecretKey::random();
         let receiving_pk = PublicKey::from_secret_key(&receiving_sk);
 
-        let (capsule, key_seed) = Capsule::from_public_key(&delegating_pk);
+        let (capsule, key_seed) = Capsule::from_public_key(&mut OsRng, &delegating_pk);
 
         let kfrags = generate_kfrags(&delegating_sk, &receiving_pk, &signer, 2, 3, true, true);
 
@@ -276,10 +843,98 @@ mod tests {
         );
 
         // Mismatched capsule
-        let (capsule2, _key_seed) = Capsule::from_public_key(&delegating_pk);
+        let (capsule2, _key_seed) = Capsule::from_public_key(&mut OsRng, &delegating_pk);
         assert_eq!(
             capsule2.open_reencrypted(&receiving_sk, &delegating_pk, &cfrags),
             Err(OpenReencryptedError::ValidationFailed)
         );
     }
+
+    #[test]
+    fn test_delegating_key_hint() {
+        let delegating_sk = SecretKey::random();
+        let delegating_pk = PublicKey::from_secret_key(&delegating_sk);
+        let other_pk = PublicKey::from_secret_key(&SecretKey::random());
+        let signer = Signer::new(&SecretKey::random());
+        let receiving_sk = SecretKey::random();
+        let receiving_pk = PublicKey::from_secret_key(&receiving_sk);
+
+        let (hinted, key_seed) = Capsule::from_public_key_with_hint(&mut OsRng, &delegating_pk);
+        assert_eq!(hinted.delegating_key_hint(), delegating_pk.fingerprint());
+        let capsule = hinted.capsule().clone();
+        assert_eq!(capsule.open_original(&delegating_sk), key_seed);
+
+        // Both the bundle and the capsule by itself survive the serialization
+        let restored = HintedCapsule::from_array(&hinted.to_array()).unwrap();
+        assert_eq!(restored, hinted);
+        let bytes = hinted.to_versioned_bytes();
+        assert_eq!(
+            HintedCapsule::from_versioned_bytes(&bytes),
+            Ok(hinted.clone())
+        );
+        assert_eq!(
+            Capsule::from_array(&capsule.to_array()),
+            Ok(capsule.clone())
+        );
+
+        // The hint is signed
+        let mut tampered = hinted.to_array();
+        tampered[Capsule::SERIALIZED_SIZE] ^= 1;
+        assert_eq!(
+            HintedCapsule::from_array(&tampered),
+            Err(DeserializationError::ConstructionFailure)
+        );
+
+        assert_eq!(
+            hinted.check_delegating_key(&other_pk),
+            Err(OpenReencryptedError::MismatchedDelegatingKey)
+        );
+        let capsule = hinted.check_delegating_key(&delegating_pk).unwrap();
+
+        let kfrags = generate_kfrags(&delegating_sk, &receiving_pk, &signer, 2, 3, true, true);
+        let cfrags: Vec<_> = kfrags
+            .iter()
+            .map(|kfrag| reencrypt(capsule, &kfrag, None).cfrag)
+            .collect();
+        assert_eq!(
+            capsule.open_reencrypted(&receiving_sk, &delegating_pk, &cfrags),
+            Ok(key_seed)
+        );
+    }
+
+    #[test]
+    fn test_lagrange_combine() {
+        // Shares of `f(x) = a0 + a1 * x` "in the exponent" interpolate to `g * a0`.
+        let a0 = CurveScalar::random_nonzero(&mut OsRng);
+        let a1 = CurveScalar::random_nonzero(&mut OsRng);
+        let g = CurvePoint::generator();
+
+        let xs: Vec<_> = (0..3)
+            .map(|_| CurveScalar::random_nonzero(&mut OsRng))
+            .collect();
+        let points: Vec<_> = xs.iter().map(|x| &g * &(&a0 + &(&a1 * x))).collect();
+
+        let expected = Some((&g * &a0, &g * &a0));
+        assert_eq!(
+            lagrange_combine(&xs[..2], &points[..2], &points[..2]),
+            expected
+        );
+        assert_eq!(
+            lagrange_combine(&xs[1..], &points[1..], &points[1..]),
+            expected
+        );
+
+        let repeating_xs = [xs[0], xs[0]];
+        assert_eq!(
+            lagrange_combine(&repeating_xs, &points[..2], &points[..2]),
+            None
+        );
+
+        // The coefficients interpolate the constant polynomial `f(x) = 1` to 1.
+        let lambdas = lambda_coeffs(&xs).unwrap();
+        let sum = lambdas
+            .iter()
+            .fold(CurveScalar::default(), |sum, lambda| &sum + lambda);
+        assert_eq!(sum, CurveScalar::one());
+    }
 }
@@ -1,15 +1,30 @@
 use crate::capsule::Capsule;
-use crate::curve::{CurvePoint, CurveScalar};
-use crate::hashing_ds::{hash_to_cfrag_verification, kfrag_signature_message};
-use crate::key_frag::{KeyFrag, KeyFragID};
+use crate::curve::{multi_mul, CompressedPoint, CurvePoint, CurveScalar};
+use crate::hashing_ds::{
+    hash_to_capsule_fingerprint, hash_to_cfrag_verification, kfrag_signature_message,
+};
+#[cfg(feature = "role-ursula")]
+use crate::key_frag::KeyFrag;
+use crate::key_frag::{KeyFragID, ValidityWindow};
 use crate::keys::{PublicKey, Signature};
+use crate::metadata::{
+    canonicalize, encode_metadata, CanonicalizationError, Metadata, MetadataCanonicalizer,
+};
 use crate::traits::{
     DeserializableFromArray, DeserializationError, RepresentableAsArray, SerializableToArray,
+    VersionedSerialization,
 };
 
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use core::fmt;
+
 use generic_array::sequence::Concat;
 use generic_array::GenericArray;
-use typenum::op;
+use rand_core::{CryptoRng, OsRng, RngCore};
+#[cfg(all(feature = "role-ursula", feature = "parallel"))]
+use rayon::prelude::*;
+use typenum::{op, Unsigned, U32};
 
 #[derive(Clone, Debug, PartialEq)]
 pub(crate) struct CapsuleFragProof {
@@ -62,40 +77,41 @@ impl DeserializableFromArray for CapsuleFragProof {
     }
 }
 
+/// The results of the point multiplications needed to reencrypt a capsule
+/// with a key fragment `rk` and a random nonce `t`:
+/// `[E * rk, V * rk, E * t, V * t, U * t]`.
+#[cfg(feature = "role-ursula")]
+pub(crate) type ReencryptionProducts = [CurvePoint; 5];
+
 impl CapsuleFragProof {
+    #[cfg(feature = "role-ursula")]
     #[allow(clippy::many_single_char_names)]
-    fn from_kfrag_and_cfrag(
+    fn from_products(
         capsule: &Capsule,
         kfrag: &KeyFrag,
-        cfrag_e1: &CurvePoint,
-        cfrag_v1: &CurvePoint,
+        t: &CurveScalar,
+        products: &ReencryptionProducts,
         metadata: Option<&[u8]>,
     ) -> Self {
         let params = capsule.params;
 
         let rk = kfrag.key;
-        let t = CurveScalar::random_nonzero();
 
         // Here are the formulaic constituents shared with `CapsuleFrag::verify()`.
 
         let e = capsule.point_e;
         let v = capsule.point_v;
 
-        let e1 = cfrag_e1;
-        let v1 = cfrag_v1;
+        let [e1, v1, e2, v2, u2] = *products;
 
         let u = params.u;
         let u1 = kfrag.proof.commitment;
 
-        let e2 = &e * &t;
-        let v2 = &v * &t;
-        let u2 = &u * &t;
-
-        let h = hash_to_cfrag_verification(&[e, *e1, e2, v, *v1, v2, u, u1, u2], metadata);
+        let h = hash_to_cfrag_verification(&[e, e1, e2, v, v1, v2, u, u1, u2], metadata);
 
         ////////
 
-        let z3 = &t + &(&rk * &h);
+        let z3 = t + &(&rk * &h);
 
         Self {
             point_e2: e2,
@@ -150,105 +166,553 @@ impl DeserializableFromArray for CapsuleFrag {
     }
 }
 
+impl VersionedSerialization for CapsuleFrag {
+    const TYPE_TAG: u8 = 3;
+}
+
+/// The equations of the reencryption proof checked by [`CapsuleFrag::verify`].
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ReencryptionCheck {
+    /// Reencryption of the capsule's point `E`.
+    PointE,
+    /// Reencryption of the capsule's point `V`.
+    PointV,
+    /// Consistency with the key fragment commitment.
+    KeyFragCommitment,
+}
+
+impl ReencryptionCheck {
+    const ALL: [Self; 3] = [Self::PointE, Self::PointV, Self::KeyFragCommitment];
+
+    /// Returns the 1-based position of the check in the proof.
+    pub fn number(&self) -> usize {
+        match self {
+            Self::PointE => 1,
+            Self::PointV => 2,
+            Self::KeyFragCommitment => 3,
+        }
+    }
+}
+
+impl fmt::Display for ReencryptionCheck {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let description = match self {
+            Self::PointE => "reencryption of E",
+            Self::PointV => "reencryption of V",
+            Self::KeyFragCommitment => "key fragment commitment",
+        };
+        write!(f, "check {} of 3 ({})", self.number(), description)
+    }
+}
+
 /// Possible errors that can be returned by [`CapsuleFrag::verify`].
 #[derive(Debug, Copy, Clone, PartialEq)]
 pub enum CapsuleFragVerificationError {
     /// Inconsistent internal state leading to signature verification failure.
     IncorrectKeyFragSignature,
     /// Inconsistent internal state leading to commitment verification failure.
-    IncorrectReencryption,
+    /// Contains the first of the proof equations that does not hold.
+    IncorrectReencryption(ReencryptionCheck),
+    /// A delegating key was included in the key fragment signature,
+    /// but no delegating key was provided during verification.
+    DelegatingKeyNotProvided,
+    /// A receiving key was included in the key fragment signature,
+    /// but no receiving key was provided during verification.
+    ReceivingKeyNotProvided,
+    /// The metadata could not be canonicalized
+    /// (only returned by [`CapsuleFrag::verify_canonicalized`]).
+    MalformedMetadata(CanonicalizationError),
+}
+
+impl fmt::Display for CapsuleFragVerificationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::IncorrectKeyFragSignature => write!(f, "incorrect key fragment signature"),
+            Self::IncorrectReencryption(check) => {
+                write!(f, "reencryption proof {} failed", check)
+            }
+            Self::DelegatingKeyNotProvided => write!(f, "delegating key not provided"),
+            Self::ReceivingKeyNotProvided => write!(f, "receiving key not provided"),
+            Self::MalformedMetadata(err) => write!(f, "failed to canonicalize metadata: {}", err),
+        }
+    }
+}
+
+/// The outcome of a single check performed by [`CapsuleFrag::verify_partial`].
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum CheckOutcome {
+    /// The check passed.
+    Passed,
+    /// The check failed.
+    Failed,
+    /// The check was not performed because some of the keys were not provided.
+    Skipped,
+}
+
+/// The report returned by [`CapsuleFrag::verify_partial`].
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct PartialVerificationReport {
+    /// The reencryption proof (checked with the capsule and the metadata only).
+    pub reencryption: CheckOutcome,
+    /// The signature of the key fragment
+    /// (needs the verifying, the delegating and the receiving keys).
+    pub kfrag_signature: CheckOutcome,
+}
+
+impl PartialVerificationReport {
+    /// Returns `true` if any of the performed checks failed,
+    /// that is, the fragment is certainly invalid.
+    pub fn is_invalid(&self) -> bool {
+        self.reencryption == CheckOutcome::Failed || self.kfrag_signature == CheckOutcome::Failed
+    }
+
+    /// Returns `true` if all the checks were performed and passed
+    /// (the same outcome as a successful [`CapsuleFrag::verify`]).
+    pub fn is_fully_verified(&self) -> bool {
+        self.reencryption == CheckOutcome::Passed && self.kfrag_signature == CheckOutcome::Passed
+    }
 }
 
 impl CapsuleFrag {
-    fn reencrypted(capsule: &Capsule, kfrag: &KeyFrag, metadata: Option<&[u8]>) -> Self {
+    /// The size of the byte representation of a capsule fragment
+    /// (returned by [`to_array()`](`SerializableToArray::to_array`)).
+    pub const SERIALIZED_SIZE: usize = <Self as RepresentableAsArray>::Size::USIZE;
+
+    /// Returns the identifier of the key fragment this fragment was created with.
+    pub fn kfrag_id(&self) -> &KeyFragID {
+        &self.kfrag_id
+    }
+
+    /// Returns the precursor point of the key fragment this fragment was created with.
+    pub fn precursor(&self) -> CompressedPoint {
+        self.precursor.to_array()
+    }
+
+    #[cfg(feature = "role-ursula")]
+    fn reencrypted(
+        rng: &mut (impl CryptoRng + RngCore),
+        capsule: &Capsule,
+        kfrag: &KeyFrag,
+        metadata: Option<&[u8]>,
+    ) -> Self {
         let rk = kfrag.key;
-        let e1 = &capsule.point_e * &rk;
-        let v1 = &capsule.point_v * &rk;
-        let proof = CapsuleFragProof::from_kfrag_and_cfrag(&capsule, &kfrag, &e1, &v1, metadata);
+        // With the `hardened` feature the key fragment is blinded in each multiplication,
+        // so that the timing or power traces of the proxy do not depend on it directly.
+        #[cfg(feature = "hardened")]
+        let (e1, v1) = (
+            capsule.point_e.mul_blinded(&rk, rng),
+            capsule.point_v.mul_blinded(&rk, rng),
+        );
+        #[cfg(not(feature = "hardened"))]
+        let (e1, v1) = (&capsule.point_e * &rk, &capsule.point_v * &rk);
+
+        let t = CurveScalar::random_nonzero(rng);
+        let products = [
+            e1,
+            v1,
+            &capsule.point_e * &t,
+            &capsule.point_v * &t,
+            &capsule.params.u * &t,
+        ];
+        Self::from_products(capsule, kfrag, &t, &products, metadata)
+    }
+
+    /// Creates the fragment from the results of the point multiplications
+    /// made with the key fragment and the nonce `t` (see [`ReencryptionProducts`]).
+    #[cfg(feature = "role-ursula")]
+    fn from_products(
+        capsule: &Capsule,
+        kfrag: &KeyFrag,
+        t: &CurveScalar,
+        products: &ReencryptionProducts,
+        metadata: Option<&[u8]>,
+    ) -> Self {
+        let proof = CapsuleFragProof::from_products(capsule, kfrag, t, products, metadata);
 
         Self {
-            point_e1: e1,
-            point_v1: v1,
+            point_e1: products[0],
+            point_v1: products[1],
             kfrag_id: kfrag.id,
             precursor: kfrag.precursor,
             proof,
         }
     }
 
-    /// Verifies the integrity of the capsule fragment, given the original capsule,
-    /// the encrypting party's key, the decrypting party's key, and the signing key.
-    #[allow(clippy::many_single_char_names)]
-    pub fn verify(
-        &self,
-        capsule: &Capsule,
-        verifying_pk: &PublicKey,
-        delegating_pk: &PublicKey,
-        receiving_pk: &PublicKey,
-        metadata: Option<&[u8]>,
-    ) -> Result<VerifiedCapsuleFrag, CapsuleFragVerificationError> {
-        let params = capsule.params;
-
-        // Here are the formulaic constituents shared with
-        // `CapsuleFragProof::from_kfrag_and_cfrag`.
-
+    // Here are the formulaic constituents shared with
+    // `CapsuleFragProof::from_products`.
+    fn challenge(&self, capsule: &Capsule, metadata: Option<&[u8]>) -> CurveScalar {
         let e = capsule.point_e;
         let v = capsule.point_v;
 
         let e1 = self.point_e1;
         let v1 = self.point_v1;
 
-        let u = params.u;
+        let u = capsule.params.u;
         let u1 = self.proof.kfrag_commitment;
 
         let e2 = self.proof.point_e2;
         let v2 = self.proof.point_v2;
         let u2 = self.proof.kfrag_pok;
 
-        let h = hash_to_cfrag_verification(&[e, e1, e2, v, v1, v2, u, u1, u2], metadata);
-
-        ///////
-
-        let precursor = self.precursor;
-        let kfrag_id = self.kfrag_id;
+        hash_to_cfrag_verification(&[e, e1, e2, v, v1, v2, u, u1, u2], metadata)
+    }
 
-        if !self.proof.kfrag_signature.verify(
+    fn verify_kfrag_signature(
+        &self,
+        verifying_pk: &PublicKey,
+        delegating_pk: &PublicKey,
+        receiving_pk: &PublicKey,
+    ) -> bool {
+        self.proof.kfrag_signature.verify(
             verifying_pk,
             kfrag_signature_message(
-                &kfrag_id,
-                &u1,
-                &precursor,
+                &self.kfrag_id,
+                &self.proof.kfrag_commitment,
+                &self.precursor,
                 Some(delegating_pk),
                 Some(receiving_pk),
+                None,
             )
             .as_ref(),
-        ) {
-            return Err(CapsuleFragVerificationError::IncorrectKeyFragSignature);
-        }
+        )
+    }
+
+    /// Checks the Chaum-Pedersen proof of the reencryption.
+    /// Only needs public data, but does not tie the commitment to the signer.
+    #[allow(clippy::many_single_char_names)]
+    fn verify_proof(
+        &self,
+        capsule: &Capsule,
+        metadata: Option<&[u8]>,
+    ) -> Result<(), CapsuleFragVerificationError> {
+        let e = capsule.point_e;
+        let v = capsule.point_v;
+        let u = capsule.params.u;
+
+        let e1 = self.point_e1;
+        let v1 = self.point_v1;
+        let u1 = self.proof.kfrag_commitment;
+
+        let e2 = self.proof.point_e2;
+        let v2 = self.proof.point_v2;
+        let u2 = self.proof.kfrag_pok;
+
+        let h = self.challenge(capsule, metadata);
 
         // TODO (#46): if one or more of the values here are incorrect,
         // we'll get the wrong `h` (since they're all hashed into it),
         // so perhaps it's enough to check only one of these equations.
         let z = self.proof.signature;
-        let correct_reencryption_of_e = &e * &z == &e2 + &(&e1 * &h);
-        let correct_reencryption_of_v = &v * &z == &v2 + &(&v1 * &h);
-        let correct_rk_commitment = &u * &z == &u2 + &(&u1 * &h);
+        let correct_reencryption_of_e = proof_equation_holds(&e, &z, &e2, &e1, &h);
+        let correct_reencryption_of_v = proof_equation_holds(&v, &z, &v2, &v1, &h);
+        let correct_rk_commitment = proof_equation_holds(&u, &z, &u2, &u1, &h);
 
-        if !(correct_reencryption_of_e & correct_reencryption_of_v & correct_rk_commitment) {
-            return Err(CapsuleFragVerificationError::IncorrectReencryption);
+        // All the equations are evaluated before reporting, so that the timing
+        // does not depend on which one failed.
+        let checks = [
+            correct_reencryption_of_e,
+            correct_reencryption_of_v,
+            correct_rk_commitment,
+        ];
+        if let Some((_, check)) = checks
+            .iter()
+            .zip(ReencryptionCheck::ALL.iter())
+            .find(|(passed, _)| !**passed)
+        {
+            return Err(CapsuleFragVerificationError::IncorrectReencryption(*check));
         }
 
-        Ok(VerifiedCapsuleFrag {
-            cfrag: self.clone(),
-        })
+        Ok(())
+    }
+
+    /// Verifies the integrity of the capsule fragment, given the original capsule,
+    /// the encrypting party's key, the decrypting party's key, and the signing key.
+    ///
+    /// `metadata` must be the same as given to [`reencrypt()`](`crate::reencrypt`).
+    pub fn verify(
+        &self,
+        capsule: &Capsule,
+        verifying_pk: &PublicKey,
+        delegating_pk: &PublicKey,
+        receiving_pk: &PublicKey,
+        metadata: Option<&Metadata>,
+    ) -> Result<VerifiedCapsuleFrag, CapsuleFragVerificationError> {
+        self.verify_encoded(
+            capsule,
+            verifying_pk,
+            delegating_pk,
+            receiving_pk,
+            encode_metadata(metadata).as_deref(),
+        )
+    }
+
+    /// Same as [`verify()`](`Self::verify`), but takes the metadata as the bytes
+    /// hashed into the proof.
+    pub(crate) fn verify_encoded(
+        &self,
+        capsule: &Capsule,
+        verifying_pk: &PublicKey,
+        delegating_pk: &PublicKey,
+        receiving_pk: &PublicKey,
+        metadata: Option<&[u8]>,
+    ) -> Result<VerifiedCapsuleFrag, CapsuleFragVerificationError> {
+        let correct_signature =
+            self.verify_kfrag_signature(verifying_pk, delegating_pk, receiving_pk);
+
+        // With the `wasm` feature the proof is checked even if the signature is incorrect,
+        // so that the time spent does not depend on which check fails.
+        let proof_result = if correct_signature || cfg!(feature = "wasm") {
+            self.verify_proof(capsule, metadata)
+        } else {
+            Ok(())
+        };
+
+        if !correct_signature {
+            return Err(CapsuleFragVerificationError::IncorrectKeyFragSignature);
+        }
+        proof_result?;
+
+        Ok(VerifiedCapsuleFrag::new(self.clone(), capsule))
+    }
+
+    /// Same as [`verify()`](`Self::verify`), but canonicalizes the metadata
+    /// with the given canonicalizer first
+    /// (for the fragments created with [`reencrypt_canonicalized()`](`crate::reencrypt_canonicalized`)).
+    pub fn verify_canonicalized(
+        &self,
+        capsule: &Capsule,
+        verifying_pk: &PublicKey,
+        delegating_pk: &PublicKey,
+        receiving_pk: &PublicKey,
+        metadata: Option<&[u8]>,
+        canonicalizer: &impl MetadataCanonicalizer,
+    ) -> Result<VerifiedCapsuleFrag, CapsuleFragVerificationError> {
+        let metadata = canonicalize(canonicalizer, metadata)
+            .map_err(CapsuleFragVerificationError::MalformedMetadata)?;
+        self.verify_encoded(
+            capsule,
+            verifying_pk,
+            delegating_pk,
+            receiving_pk,
+            metadata.as_deref(),
+        )
+    }
+
+    /// Performs the checks of [`verify()`](`Self::verify`) that are possible
+    /// with the given keys, and reports which ones passed, failed, or were skipped.
+    ///
+    /// Intended for relayers without the full set of keys,
+    /// to filter out fragments that are certainly invalid.
+    /// A fragment is only safe to use after a full verification.
+    pub fn verify_partial(
+        &self,
+        capsule: &Capsule,
+        maybe_verifying_pk: Option<&PublicKey>,
+        maybe_delegating_pk: Option<&PublicKey>,
+        maybe_receiving_pk: Option<&PublicKey>,
+        metadata: Option<&Metadata>,
+    ) -> PartialVerificationReport {
+        let outcome = |passed: bool| {
+            if passed {
+                CheckOutcome::Passed
+            } else {
+                CheckOutcome::Failed
+            }
+        };
+
+        let kfrag_signature = match (maybe_verifying_pk, maybe_delegating_pk, maybe_receiving_pk) {
+            (Some(verifying_pk), Some(delegating_pk), Some(receiving_pk)) => {
+                outcome(self.verify_kfrag_signature(verifying_pk, delegating_pk, receiving_pk))
+            }
+            _ => CheckOutcome::Skipped,
+        };
+
+        PartialVerificationReport {
+            reencryption: outcome(
+                self.verify_proof(capsule, encode_metadata(metadata).as_deref())
+                    .is_ok(),
+            ),
+            kfrag_signature,
+        }
+    }
+
+    /// Verifies a batch of capsule fragments created for the same capsule and keys.
+    ///
+    /// The reencryption equations of all the fragments are combined
+    /// with random coefficients and checked at once,
+    /// so the multiplications by `E`, `V` and `U` are only performed once per batch
+    /// instead of once per fragment.
+    /// If the batch fails verification, [`find_faulty()`](`Self::find_faulty`)
+    /// can be used to find the faulty fragments.
+    pub fn verify_batch(
+        cfrags: &[CapsuleFrag],
+        capsule: &Capsule,
+        verifying_pk: &PublicKey,
+        delegating_pk: &PublicKey,
+        receiving_pk: &PublicKey,
+        metadata: Option<&Metadata>,
+    ) -> Result<Box<[VerifiedCapsuleFrag]>, CapsuleFragVerificationError> {
+        Self::verify_batch_with_rng(
+            &mut OsRng,
+            cfrags,
+            capsule,
+            verifying_pk,
+            delegating_pk,
+            receiving_pk,
+            metadata,
+        )
+    }
+
+    /// Same as [`verify_batch()`](`Self::verify_batch`),
+    /// but takes the random coefficients from the given RNG instead of the default one.
+    #[allow(clippy::many_single_char_names)]
+    pub fn verify_batch_with_rng(
+        rng: &mut (impl CryptoRng + RngCore),
+        cfrags: &[CapsuleFrag],
+        capsule: &Capsule,
+        verifying_pk: &PublicKey,
+        delegating_pk: &PublicKey,
+        receiving_pk: &PublicKey,
+        metadata: Option<&Metadata>,
+    ) -> Result<Box<[VerifiedCapsuleFrag]>, CapsuleFragVerificationError> {
+        let metadata = encode_metadata(metadata);
+
+        // Given the equations `z_i * P == P2_i + h_i * P1_i` for `P` in `(E, V, U)`,
+        // we check that `sum_i r_i * (P2_i + h_i * P1_i) == (sum_i r_i * z_i) * P`
+        // for random `r_i` (different for each point).
+        // The right-hand sides are computed with a single multi-scalar multiplication per point.
+        let bases = [capsule.point_e, capsule.point_v, capsule.params.u];
+        let mut z_sums = [CurveScalar::default(); 3];
+        let mut rhs_scalars: [Vec<CurveScalar>; 3] = Default::default();
+        let mut rhs_points: [Vec<CurvePoint>; 3] = Default::default();
+
+        for cfrag in cfrags {
+            if !cfrag.verify_kfrag_signature(verifying_pk, delegating_pk, receiving_pk) {
+                return Err(CapsuleFragVerificationError::IncorrectKeyFragSignature);
+            }
+
+            let h = cfrag.challenge(capsule, metadata.as_deref());
+            let z = cfrag.proof.signature;
+
+            let points = [
+                (cfrag.proof.point_e2, cfrag.point_e1),
+                (cfrag.proof.point_v2, cfrag.point_v1),
+                (cfrag.proof.kfrag_pok, cfrag.proof.kfrag_commitment),
+            ];
+            for (i, (p2, p1)) in points.iter().enumerate() {
+                let r = CurveScalar::random_nonzero(rng);
+                z_sums[i] = &z_sums[i] + &(&r * &z);
+                rhs_scalars[i].extend_from_slice(&[r, &r * &h]);
+                rhs_points[i].extend_from_slice(&[*p2, *p1]);
+            }
+        }
+
+        for (i, (base, check)) in bases.iter().zip(ReencryptionCheck::ALL.iter()).enumerate() {
+            if base * &z_sums[i] != multi_mul(&rhs_scalars[i], &rhs_points[i]) {
+                return Err(CapsuleFragVerificationError::IncorrectReencryption(*check));
+            }
+        }
+
+        let capsule_fingerprint = hash_to_capsule_fingerprint(capsule);
+        Ok(cfrags
+            .iter()
+            .map(|cfrag| VerifiedCapsuleFrag {
+                cfrag: cfrag.clone(),
+                capsule_fingerprint,
+            })
+            .collect())
     }
+
+    /// Finds the fragments that fail verification
+    /// (e.g. to report the proxies that produced them after a failed decryption),
+    /// in the order of their positions in `cfrags`.
+    ///
+    /// The fragments are bisected with [`verify_batch()`](`Self::verify_batch`),
+    /// so when only a few of them are faulty, most are checked in batches;
+    /// each faulty fragment is then checked individually to report the reason.
+    pub fn find_faulty(
+        cfrags: &[CapsuleFrag],
+        capsule: &Capsule,
+        verifying_pk: &PublicKey,
+        delegating_pk: &PublicKey,
+        receiving_pk: &PublicKey,
+        metadata: Option<&Metadata>,
+    ) -> Box<[FaultyCapsuleFrag]> {
+        let mut faulty = Vec::new();
+        // The ranges `(start, length)` left to check.
+        let mut ranges = Vec::from([(0, cfrags.len())]);
+        while let Some((start, length)) = ranges.pop() {
+            let batch = &cfrags[start..start + length];
+            if batch.is_empty()
+                || Self::verify_batch(
+                    batch,
+                    capsule,
+                    verifying_pk,
+                    delegating_pk,
+                    receiving_pk,
+                    metadata,
+                )
+                .is_ok()
+            {
+                continue;
+            }
+            if length == 1 {
+                if let Err(error) =
+                    batch[0].verify(capsule, verifying_pk, delegating_pk, receiving_pk, metadata)
+                {
+                    faulty.push(FaultyCapsuleFrag {
+                        position: start,
+                        kfrag_id: batch[0].kfrag_id,
+                        error,
+                    });
+                }
+                continue;
+            }
+            // The second half is pushed first, so that the positions are found in order.
+            let middle = length / 2;
+            ranges.push((start + middle, length - middle));
+            ranges.push((start, middle));
+        }
+        faulty.into_boxed_slice()
+    }
+}
+
+/// A capsule fragment that failed verification, returned by [`CapsuleFrag::find_faulty()`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct FaultyCapsuleFrag {
+    /// The position of the fragment in the given list (counting from 0).
+    pub position: usize,
+    /// The identifier of the key fragment the fragment claims to be created with
+    /// (identifying the proxy that holds it).
+    pub kfrag_id: KeyFragID,
+    /// The reason of the failure.
+    pub error: CapsuleFragVerificationError,
+}
+
+/// Checks one of the equations of the reencryption correctness proof:
+/// `base * z == p2 + p1 * h`.
+///
+/// This function is pure, so that it can be targeted by verification and extraction tools.
+fn proof_equation_holds(
+    base: &CurvePoint,
+    z: &CurveScalar,
+    p2: &CurvePoint,
+    p1: &CurvePoint,
+    h: &CurveScalar,
+) -> bool {
+    base * z == p2 + &(p1 * h)
 }
 
 /// Verified capsule fragment, good for dencryption.
 /// Can be serialized, but cannot be deserialized directly.
-/// It can only be obtained from [`CapsuleFrag::verify`].
+/// It can only be obtained from [`CapsuleFrag::verify`]
+/// (or, for fragments verified earlier, [`VerifiedCapsuleFrag::from_verified_bytes`]).
 #[derive(Debug, Clone, PartialEq)]
 pub struct VerifiedCapsuleFrag {
     pub(crate) cfrag: CapsuleFrag,
+    // The fingerprint of the capsule the fragment was verified against (not serialized),
+    // to catch the fragments of different capsules before the combination.
+    pub(crate) capsule_fingerprint: GenericArray<u8, U32>,
 }
 
 impl RepresentableAsArray for VerifiedCapsuleFrag {
@@ -262,23 +726,226 @@ impl SerializableToArray for VerifiedCapsuleFrag {
 }
 
 impl VerifiedCapsuleFrag {
-    pub(crate) fn reencrypted(capsule: &Capsule, kfrag: &KeyFrag, metadata: Option<&[u8]>) -> Self {
-        VerifiedCapsuleFrag {
-            cfrag: CapsuleFrag::reencrypted(capsule, kfrag, metadata),
+    fn new(cfrag: CapsuleFrag, capsule: &Capsule) -> Self {
+        Self {
+            cfrag,
+            capsule_fingerprint: hash_to_capsule_fingerprint(capsule),
+        }
+    }
+
+    #[cfg(feature = "role-ursula")]
+    pub(crate) fn reencrypted(
+        rng: &mut (impl CryptoRng + RngCore),
+        capsule: &Capsule,
+        kfrag: &KeyFrag,
+        metadata: Option<&[u8]>,
+    ) -> Self {
+        Self::new(
+            CapsuleFrag::reencrypted(rng, capsule, kfrag, metadata),
+            capsule,
+        )
+    }
+
+    #[cfg(feature = "role-ursula")]
+    pub(crate) fn from_products(
+        capsule: &Capsule,
+        kfrag: &KeyFrag,
+        t: &CurveScalar,
+        products: &ReencryptionProducts,
+        metadata: Option<&[u8]>,
+    ) -> Self {
+        Self::new(
+            CapsuleFrag::from_products(capsule, kfrag, t, products, metadata),
+            capsule,
+        )
+    }
+
+    /// Reencrypts the capsules with the same key fragment in parallel.
+    /// Each worker draws randomness from its own handle to the OS RNG,
+    /// since a single `&mut` RNG cannot be shared between threads.
+    #[cfg(all(feature = "role-ursula", feature = "parallel"))]
+    pub(crate) fn reencrypted_parallel(
+        capsules: &[Capsule],
+        kfrag: &KeyFrag,
+        metadata: Option<&[u8]>,
+    ) -> Box<[Self]> {
+        capsules
+            .par_iter()
+            .map(|capsule| Self::reencrypted(&mut OsRng, capsule, kfrag, metadata))
+            .collect::<Vec<_>>()
+            .into_boxed_slice()
+    }
+
+    /// Produces the versioned envelope of the fragment,
+    /// to be restored with [`CapsuleFrag::from_versioned_bytes()`].
+    pub fn to_versioned_bytes(&self) -> Box<[u8]> {
+        self.cfrag.to_versioned_bytes()
+    }
+
+    /// Returns the identifier of the key fragment this fragment was created with.
+    pub fn kfrag_id(&self) -> &KeyFragID {
+        self.cfrag.kfrag_id()
+    }
+
+    /// Restores a fragment verified earlier (e.g. by a previous stage of the application)
+    /// against the given capsule from its byte representation.
+    ///
+    /// **Warning:** no verification is performed; the caller is responsible
+    /// for the bytes coming from a trusted source (such as its own storage).
+    /// Otherwise use [`CapsuleFrag::verify()`].
+    pub fn from_verified_bytes(
+        bytes: impl AsRef<[u8]>,
+        capsule: &Capsule,
+    ) -> Result<Self, DeserializationError> {
+        CapsuleFrag::from_bytes(bytes).map(|cfrag| Self::new(cfrag, capsule))
+    }
+
+    /// Returns the unverified fragment, e.g. to send it over to another party.
+    pub fn unverify(self) -> CapsuleFrag {
+        self.cfrag
+    }
+}
+
+/// A capsule fragment that can be verified by a third party
+/// without the receiving party's key (see [`AuditableCapsuleFrag::verify_public`]).
+///
+/// Along with the fragment, contains the signature of the key fragment intended for the proxy,
+/// which only includes the delegating and receiving keys if the delegating party chose so
+/// in [`generate_kfrags()`](crate::generate_kfrags).
+#[derive(Clone, Debug, PartialEq)]
+pub struct AuditableCapsuleFrag {
+    cfrag: CapsuleFrag,
+    kfrag_signature: Signature,
+    delegating_key_signed: bool,
+    receiving_key_signed: bool,
+    validity: ValidityWindow,
+}
+
+type CapsuleFragSize = <CapsuleFrag as RepresentableAsArray>::Size;
+type BoolSize = <bool as RepresentableAsArray>::Size;
+type ValidityWindowSize = <ValidityWindow as RepresentableAsArray>::Size;
+
+impl RepresentableAsArray for AuditableCapsuleFrag {
+    type Size = op!(CapsuleFragSize + SignatureSize + BoolSize + BoolSize + ValidityWindowSize);
+}
+
+impl SerializableToArray for AuditableCapsuleFrag {
+    fn to_array(&self) -> GenericArray<u8, Self::Size> {
+        self.cfrag
+            .to_array()
+            .concat(self.kfrag_signature.to_array())
+            .concat(self.delegating_key_signed.to_array())
+            .concat(self.receiving_key_signed.to_array())
+            .concat(self.validity.to_array())
+    }
+}
+
+impl DeserializableFromArray for AuditableCapsuleFrag {
+    fn from_array(arr: &GenericArray<u8, Self::Size>) -> Result<Self, DeserializationError> {
+        let (cfrag, rest) = CapsuleFrag::take(*arr)?;
+        let (kfrag_signature, rest) = Signature::take(rest)?;
+        let (delegating_key_signed, rest) = bool::take(rest)?;
+        let (receiving_key_signed, rest) = bool::take(rest)?;
+        let validity = ValidityWindow::take_last(rest)?;
+        Ok(Self {
+            cfrag,
+            kfrag_signature,
+            delegating_key_signed,
+            receiving_key_signed,
+            validity,
+        })
+    }
+}
+
+impl VersionedSerialization for AuditableCapsuleFrag {
+    const TYPE_TAG: u8 = 4;
+}
+
+impl AuditableCapsuleFrag {
+    #[cfg(feature = "role-ursula")]
+    pub(crate) fn reencrypted(
+        rng: &mut (impl CryptoRng + RngCore),
+        capsule: &Capsule,
+        kfrag: &KeyFrag,
+        metadata: Option<&[u8]>,
+    ) -> Self {
+        Self {
+            cfrag: CapsuleFrag::reencrypted(rng, capsule, kfrag, metadata),
+            kfrag_signature: kfrag.proof.signature_for_proxy.clone(),
+            delegating_key_signed: kfrag.proof.delegating_key_signed,
+            receiving_key_signed: kfrag.proof.receiving_key_signed,
+            validity: kfrag.proof.validity,
+        }
+    }
+
+    /// Returns the capsule fragment, to be verified by the receiving party
+    /// with [`CapsuleFrag::verify`].
+    pub fn cfrag(&self) -> &CapsuleFrag {
+        &self.cfrag
+    }
+
+    /// Returns the validity window of the key fragment used for reencryption
+    /// (checked as a part of the signature by [`verify_public()`](Self::verify_public)).
+    pub fn validity(&self) -> ValidityWindow {
+        self.validity
+    }
+
+    /// Verifies that the fragment is a correct reencryption of the capsule
+    /// with a key fragment signed by `verifying_pk`.
+    ///
+    /// The delegating and receiving keys are only needed if they were included
+    /// in the key fragment signature.
+    /// `metadata` must be the same as given to [`reencrypt_auditable()`](crate::reencrypt_auditable).
+    pub fn verify_public(
+        &self,
+        capsule: &Capsule,
+        verifying_pk: &PublicKey,
+        maybe_delegating_pk: Option<&PublicKey>,
+        maybe_receiving_pk: Option<&PublicKey>,
+        metadata: Option<&Metadata>,
+    ) -> Result<(), CapsuleFragVerificationError> {
+        if self.delegating_key_signed && maybe_delegating_pk.is_none() {
+            return Err(CapsuleFragVerificationError::DelegatingKeyNotProvided);
+        }
+        if self.receiving_key_signed && maybe_receiving_pk.is_none() {
+            return Err(CapsuleFragVerificationError::ReceivingKeyNotProvided);
         }
+
+        let message = kfrag_signature_message(
+            &self.cfrag.kfrag_id,
+            &self.cfrag.proof.kfrag_commitment,
+            &self.cfrag.precursor,
+            maybe_delegating_pk.filter(|_| self.delegating_key_signed),
+            maybe_receiving_pk.filter(|_| self.receiving_key_signed),
+            Some(&self.validity),
+        );
+        if !self.kfrag_signature.verify(verifying_pk, message.as_ref()) {
+            return Err(CapsuleFragVerificationError::IncorrectKeyFragSignature);
+        }
+
+        self.cfrag
+            .verify_proof(capsule, encode_metadata(metadata).as_deref())
     }
 }
 
-#[cfg(test)]
+#[cfg(all(test, feature = "role-alice", feature = "role-ursula"))]
 mod tests {
 
     use alloc::boxed::Box;
+    use alloc::collections::{BTreeMap, BTreeSet};
     use alloc::vec::Vec;
 
-    use super::{CapsuleFrag, VerifiedCapsuleFrag};
+    use super::{
+        AuditableCapsuleFrag, CapsuleFrag, CapsuleFragVerificationError, CheckOutcome,
+        PartialVerificationReport, ReencryptionCheck, VerifiedCapsuleFrag,
+    };
+    use crate::curve::CurvePoint;
+    use crate::traits::ENVELOPE_VERSION;
     use crate::{
-        encrypt, generate_kfrags, reencrypt, Capsule, DeserializableFromArray, PublicKey,
-        SecretKey, SerializableToArray, Signer,
+        encapsulate, generate_kfrags, generate_kfrags_with_validity, reencrypt,
+        reencrypt_auditable, Capsule, DeserializableFromArray, DeserializationError, KeyFrag,
+        Metadata, PublicKey, SecretKey, SerializableToArray, Signer, ValidityWindow,
+        VersionedSerialization,
     };
 
     fn prepare_cfrags() -> (
@@ -287,7 +954,7 @@ mod tests {
         PublicKey,
         Capsule,
         Box<[VerifiedCapsuleFrag]>,
-        Box<[u8]>,
+        Metadata,
     ) {
         let delegating_sk = SecretKey::random();
         let delegating_pk = PublicKey::from_secret_key(&delegating_sk);
@@ -299,15 +966,14 @@ mod tests {
         let receiving_sk = SecretKey::random();
         let receiving_pk = PublicKey::from_secret_key(&receiving_sk);
 
-        let plaintext = b"peace at dawn";
-        let (capsule, _ciphertext) = encrypt(&delegating_pk, plaintext).unwrap();
+        let (capsule, _key_seed) = encapsulate(&delegating_pk);
 
         let kfrags = generate_kfrags(&delegating_sk, &receiving_pk, &signer, 2, 3, true, true);
 
-        let metadata = b"metadata";
+        let metadata = Metadata::new().with_extra(b"metadata");
         let verified_cfrags: Vec<_> = kfrags
             .iter()
-            .map(|kfrag| reencrypt(&capsule, &kfrag, Some(metadata)))
+            .map(|kfrag| reencrypt(&capsule, &kfrag, Some(&metadata)))
             .collect();
 
         (
@@ -316,7 +982,7 @@ mod tests {
             verifying_pk,
             capsule,
             verified_cfrags.into_boxed_slice(),
-            Box::new(*metadata),
+            metadata,
         )
     }
 
@@ -344,4 +1010,337 @@ mod tests {
             assert_eq!(verified_cfrag_back, verified_cfrag);
         }
     }
+
+    #[test]
+    fn test_verify_batch() {
+        let (delegating_pk, receiving_pk, verifying_pk, capsule, verified_cfrags, metadata) =
+            prepare_cfrags();
+
+        let cfrags: Vec<_> = verified_cfrags
+            .iter()
+            .map(|vcfrag| CapsuleFrag::from_array(&vcfrag.to_array()).unwrap())
+            .collect();
+
+        let verified = CapsuleFrag::verify_batch(
+            &cfrags,
+            &capsule,
+            &verifying_pk,
+            &delegating_pk,
+            &receiving_pk,
+            Some(&metadata),
+        )
+        .unwrap();
+        assert_eq!(verified, verified_cfrags);
+
+        // Wrong metadata affects the challenges and breaks the combined equation
+        assert_eq!(
+            CapsuleFrag::verify_batch(
+                &cfrags,
+                &capsule,
+                &verifying_pk,
+                &delegating_pk,
+                &receiving_pk,
+                None,
+            ),
+            Err(CapsuleFragVerificationError::IncorrectReencryption(
+                ReencryptionCheck::PointE
+            ))
+        );
+    }
+
+    #[test]
+    fn test_find_faulty() {
+        let (delegating_pk, receiving_pk, verifying_pk, capsule, verified_cfrags, metadata) =
+            prepare_cfrags();
+
+        let mut cfrags: Vec<_> = verified_cfrags
+            .iter()
+            .chain(verified_cfrags.iter())
+            .map(|vcfrag| vcfrag.clone().unverify())
+            .collect();
+        let find_faulty = |cfrags: &[CapsuleFrag]| {
+            CapsuleFrag::find_faulty(
+                cfrags,
+                &capsule,
+                &verifying_pk,
+                &delegating_pk,
+                &receiving_pk,
+                Some(&metadata),
+            )
+        };
+        assert!(find_faulty(&cfrags).is_empty());
+
+        cfrags[1].point_e1 = &cfrags[1].point_e1 + &CurvePoint::generator();
+        cfrags[4].proof.kfrag_signature = cfrags[0].proof.kfrag_signature;
+        let faulty = find_faulty(&cfrags);
+        assert_eq!(
+            faulty.iter().map(|f| f.position).collect::<Vec<_>>(),
+            [1, 4]
+        );
+        assert_eq!(faulty[0].kfrag_id, cfrags[1].kfrag_id);
+        assert_eq!(
+            faulty[0].error,
+            CapsuleFragVerificationError::IncorrectReencryption(ReencryptionCheck::PointE)
+        );
+        assert_eq!(
+            faulty[1].error,
+            CapsuleFragVerificationError::IncorrectKeyFragSignature
+        );
+    }
+
+    #[test]
+    fn test_versioned_bytes() {
+        let (_delegating_pk, _receiving_pk, _verifying_pk, capsule, verified_cfrags, _metadata) =
+            prepare_cfrags();
+
+        let capsule_bytes = capsule.to_versioned_bytes();
+        assert_eq!(capsule_bytes[0], ENVELOPE_VERSION);
+        assert_eq!(&capsule_bytes[2..], &capsule.to_array()[..]);
+        assert_eq!(
+            Capsule::from_versioned_bytes(&capsule_bytes).unwrap(),
+            capsule
+        );
+
+        let cfrag_bytes = verified_cfrags[0].to_versioned_bytes();
+        let cfrag = CapsuleFrag::from_versioned_bytes(&cfrag_bytes).unwrap();
+        assert_eq!(cfrag.to_array(), verified_cfrags[0].to_array());
+
+        // The type tag prevents deserializing as another type.
+        assert_eq!(
+            KeyFrag::from_versioned_bytes(&cfrag_bytes),
+            Err(DeserializationError::ConstructionFailure)
+        );
+
+        let mut future_bytes = capsule_bytes.to_vec();
+        future_bytes[0] = ENVELOPE_VERSION + 1;
+        assert_eq!(
+            Capsule::from_versioned_bytes(&future_bytes),
+            Err(DeserializationError::UnsupportedVersion(
+                ENVELOPE_VERSION + 1
+            ))
+        );
+
+        // The first byte of a bare capsule is the SEC1 tag of a point (2 or 3),
+        // which does not clash with the legacy envelope version.
+        #[cfg(not(feature = "rfc9380"))]
+        assert_eq!(
+            Capsule::from_versioned_bytes(&capsule.to_array()),
+            Err(DeserializationError::UnsupportedVersion(
+                capsule.to_array()[0]
+            ))
+        );
+        #[cfg(feature = "rfc9380")]
+        assert!(Capsule::from_versioned_bytes(&capsule.to_array()).is_err());
+    }
+
+    #[test]
+    fn test_verify_public() {
+        let delegating_sk = SecretKey::random();
+        let delegating_pk = PublicKey::from_secret_key(&delegating_sk);
+        let signing_sk = SecretKey::random();
+        let signer = Signer::new(&signing_sk);
+        let verifying_pk = PublicKey::from_secret_key(&signing_sk);
+        let receiving_pk = PublicKey::from_secret_key(&SecretKey::random());
+
+        let (capsule, _key_seed) = encapsulate(&delegating_pk);
+        let metadata = &Metadata::new().with_extra(b"metadata");
+
+        // The receiving key is not signed, so the auditor does not need it.
+        let kfrags = generate_kfrags(&delegating_sk, &receiving_pk, &signer, 2, 3, true, false);
+        let acfrag = reencrypt_auditable(&capsule, &kfrags[0], Some(metadata));
+        let acfrag = AuditableCapsuleFrag::from_array(&acfrag.to_array()).unwrap();
+
+        assert!(acfrag
+            .verify_public(
+                &capsule,
+                &verifying_pk,
+                Some(&delegating_pk),
+                None,
+                Some(metadata)
+            )
+            .is_ok());
+        assert_eq!(
+            acfrag.verify_public(&capsule, &verifying_pk, None, None, Some(metadata)),
+            Err(CapsuleFragVerificationError::DelegatingKeyNotProvided)
+        );
+        assert_eq!(
+            acfrag.verify_public(
+                &capsule,
+                &delegating_pk,
+                Some(&delegating_pk),
+                None,
+                Some(metadata)
+            ),
+            Err(CapsuleFragVerificationError::IncorrectKeyFragSignature)
+        );
+        assert_eq!(
+            acfrag.verify_public(&capsule, &verifying_pk, Some(&delegating_pk), None, None),
+            Err(CapsuleFragVerificationError::IncorrectReencryption(
+                ReencryptionCheck::PointE
+            ))
+        );
+
+        // The receiving party verifies it as usual.
+        assert!(acfrag
+            .cfrag()
+            .clone()
+            .verify(
+                &capsule,
+                &verifying_pk,
+                &delegating_pk,
+                &receiving_pk,
+                Some(metadata)
+            )
+            .is_ok());
+    }
+
+    #[test]
+    fn test_verify_public_with_validity() {
+        let delegating_sk = SecretKey::random();
+        let delegating_pk = PublicKey::from_secret_key(&delegating_sk);
+        let signing_sk = SecretKey::random();
+        let signer = Signer::new(&signing_sk);
+        let verifying_pk = PublicKey::from_secret_key(&signing_sk);
+        let receiving_pk = PublicKey::from_secret_key(&SecretKey::random());
+
+        let (capsule, _key_seed) = encapsulate(&delegating_pk);
+        let validity = ValidityWindow::new(Some(100), Some(200));
+
+        let kfrags = generate_kfrags_with_validity(
+            &delegating_sk,
+            &receiving_pk,
+            &signer,
+            2,
+            3,
+            true,
+            false,
+            validity,
+        );
+        let acfrag = reencrypt_auditable(&capsule, &kfrags[0], None);
+
+        // The window is carried by the array representation,
+        // so the signature still verifies after a roundtrip.
+        let acfrag = AuditableCapsuleFrag::from_array(&acfrag.to_array()).unwrap();
+        assert_eq!(acfrag.validity(), validity);
+        assert!(acfrag
+            .verify_public(&capsule, &verifying_pk, Some(&delegating_pk), None, None)
+            .is_ok());
+    }
+
+    #[test]
+    fn test_structural_getters() {
+        let delegating_sk = SecretKey::random();
+        let receiving_pk = PublicKey::from_secret_key(&SecretKey::random());
+        let signer = Signer::new(&SecretKey::random());
+        let (capsule, _key_seed) = encapsulate(&PublicKey::from_secret_key(&delegating_sk));
+        let kfrags = generate_kfrags(&delegating_sk, &receiving_pk, &signer, 2, 3, true, true);
+
+        let capsule_arr = capsule.to_array();
+        assert_eq!(&capsule.e()[..], &capsule_arr[..33]);
+        assert_eq!(&capsule.v()[..], &capsule_arr[33..66]);
+
+        let kfrag = KeyFrag::from_array(&kfrags[0].to_array()).unwrap();
+        assert_eq!(kfrag.id(), kfrags[0].id());
+        assert_ne!(kfrags[0].id(), kfrags[1].id());
+        assert_eq!(
+            kfrag.precursor(),
+            KeyFrag::from_array(&kfrags[1].to_array())
+                .unwrap()
+                .precursor()
+        );
+
+        let verified_cfrag = reencrypt(&capsule, &kfrags[0], None);
+        let cfrag = CapsuleFrag::from_array(&verified_cfrag.to_array()).unwrap();
+        assert_eq!(verified_cfrag.kfrag_id(), kfrags[0].id());
+        assert_eq!(cfrag.kfrag_id(), kfrag.id());
+        assert_eq!(cfrag.precursor(), kfrag.precursor());
+
+        assert_eq!(capsule_arr.len(), Capsule::SERIALIZED_SIZE);
+        assert_eq!(kfrags[0].to_array().len(), KeyFrag::SERIALIZED_SIZE);
+        assert_eq!(
+            verified_cfrag.to_array().len(),
+            CapsuleFrag::SERIALIZED_SIZE
+        );
+    }
+
+    #[test]
+    fn test_verify_partial() {
+        let (delegating_pk, receiving_pk, verifying_pk, capsule, verified_cfrags, metadata) =
+            prepare_cfrags();
+        let cfrag = CapsuleFrag::from_array(&verified_cfrags[0].to_array()).unwrap();
+
+        let report = cfrag.verify_partial(
+            &capsule,
+            Some(&verifying_pk),
+            Some(&delegating_pk),
+            Some(&receiving_pk),
+            Some(&metadata),
+        );
+        assert!(report.is_fully_verified());
+
+        let report =
+            cfrag.verify_partial(&capsule, Some(&verifying_pk), None, None, Some(&metadata));
+        assert_eq!(
+            report,
+            PartialVerificationReport {
+                reencryption: CheckOutcome::Passed,
+                kfrag_signature: CheckOutcome::Skipped,
+            }
+        );
+        assert!(!report.is_invalid());
+        assert!(!report.is_fully_verified());
+
+        // Wrong metadata fails the proof even without any keys.
+        let report = cfrag.verify_partial(&capsule, None, None, None, None);
+        assert_eq!(report.reencryption, CheckOutcome::Failed);
+        assert!(report.is_invalid());
+
+        let report = cfrag.verify_partial(
+            &capsule,
+            Some(&delegating_pk),
+            Some(&delegating_pk),
+            Some(&receiving_pk),
+            Some(&metadata),
+        );
+        assert_eq!(report.kfrag_signature, CheckOutcome::Failed);
+    }
+
+    #[test]
+    fn test_ordering() {
+        let (_delegating_pk, _receiving_pk, _verifying_pk, _capsule, verified_cfrags, _metadata) =
+            prepare_cfrags();
+
+        // Collecting the duplicates into a set leaves the distinct fragments,
+        // ordered by their byte representations.
+        let cfrags: BTreeSet<CapsuleFrag> = verified_cfrags
+            .iter()
+            .chain(verified_cfrags.iter())
+            .map(|vcfrag| vcfrag.cfrag.clone())
+            .collect();
+        assert_eq!(cfrags.len(), verified_cfrags.len());
+        let arrays: Vec<_> = cfrags.iter().map(|cfrag| cfrag.to_array()).collect();
+        assert!(arrays.windows(2).all(|pair| pair[0] < pair[1]));
+
+        let mut index = BTreeMap::new();
+        for cfrag in cfrags.iter() {
+            index.insert(cfrag.clone(), cfrag.to_array());
+        }
+        let cfrag = verified_cfrags[0].cfrag.clone();
+        assert_eq!(index.get(&cfrag), Some(&cfrag.to_array()));
+    }
+
+    #[test]
+    fn test_from_verified_bytes() {
+        let (_delegating_pk, _receiving_pk, _verifying_pk, capsule, verified_cfrags, _metadata) =
+            prepare_cfrags();
+
+        let bytes = verified_cfrags[0].to_array();
+        let restored = VerifiedCapsuleFrag::from_verified_bytes(bytes, &capsule).unwrap();
+        assert_eq!(restored, verified_cfrags[0]);
+        assert_eq!(restored.unverify(), CapsuleFrag::from_bytes(bytes).unwrap());
+        assert_eq!(
+            VerifiedCapsuleFrag::from_verified_bytes(&bytes[1..], &capsule),
+            Err(DeserializationError::NotEnoughBytes)
+        );
+    }
 }
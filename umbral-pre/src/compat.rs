@@ -0,0 +1,3 @@
+//! Conversions for exchanging objects with applications using other encodings.
+
+pub mod js;
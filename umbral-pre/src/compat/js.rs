@@ -0,0 +1,152 @@
+//! Conversions for the encodings JS applications usually carry Umbral objects and keys in.
+//!
+//! This is not a compatibility layer with any particular JS implementation:
+//! the objects are exchanged in the byte representations of this crate
+//! (as produced by `umbral-pre-wasm`), and the functions here only handle
+//! the common ways of wrapping those bytes and the keys on the JS side:
+//!
+//! - byte strings are passed as hex, often with the `0x` prefix of the Ethereum libraries
+//!   (see [`to_js`] and [`from_js`] for the objects, and [`to_hex`] and [`from_hex`]
+//!   for the raw bytes);
+//! - public keys exported by `ethers` or `elliptic` are in the uncompressed SEC1 form
+//!   (see [`public_key_from_bytes`]);
+//! - signatures of Ethereum wallets have a trailing recovery byte (`r || s || v`),
+//!   and the ones of WebCrypto are in DER (see [`signature_from_bytes`]).
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt;
+
+use crate::encoding::{decode_hex, encode_hex};
+use crate::keys::{PublicKey, Signature};
+use crate::traits::{DeserializableFromArray, DeserializationError, SerializableToArray};
+
+/// Errors that can happen when converting the values produced by JS applications.
+#[derive(Debug, PartialEq)]
+pub enum JsCompatError {
+    /// The string is not a valid hex encoding.
+    InvalidHex,
+    /// The bytes are not a valid public key in any of the supported forms.
+    InvalidPublicKey,
+    /// The bytes are not a valid signature in any of the supported forms.
+    InvalidSignature,
+    /// The decoded bytes do not represent the requested object.
+    InvalidObject(DeserializationError),
+}
+
+impl fmt::Display for JsCompatError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidHex => write!(f, "invalid hex encoding"),
+            Self::InvalidPublicKey => write!(f, "invalid public key"),
+            Self::InvalidSignature => write!(f, "invalid signature"),
+            Self::InvalidObject(err) => write!(f, "invalid object: {}", err),
+        }
+    }
+}
+
+/// Encodes the bytes as lowercase hex with the `0x` prefix
+/// (same as `ethers.utils.hexlify()`).
+pub fn to_hex(bytes: &[u8]) -> String {
+    ["0x", &encode_hex(bytes)].concat()
+}
+
+/// Decodes a hex string, with or without the `0x` prefix, in any case.
+pub fn from_hex(s: &str) -> Result<Vec<u8>, JsCompatError> {
+    let s = s
+        .strip_prefix("0x")
+        .or_else(|| s.strip_prefix("0X"))
+        .unwrap_or(s);
+    decode_hex(s).ok_or(JsCompatError::InvalidHex)
+}
+
+/// Encodes the object the way JS applications usually pass it around
+/// (the hex of its byte representation, with the `0x` prefix).
+pub fn to_js(obj: &impl SerializableToArray) -> String {
+    to_hex(&obj.to_array())
+}
+
+/// Decodes an object encoded with [`to_js`] (or its JS counterpart).
+pub fn from_js<T: DeserializableFromArray>(s: &str) -> Result<T, JsCompatError> {
+    T::from_bytes(&from_hex(s)?).map_err(JsCompatError::InvalidObject)
+}
+
+/// Restores a public key from either the compressed (33 bytes) SEC1 encoding
+/// used by this crate, or the uncompressed (65 bytes) one used by most JS libraries.
+pub fn public_key_from_bytes(bytes: &[u8]) -> Result<PublicKey, JsCompatError> {
+    PublicKey::from_sec1_bytes(bytes).or(Err(JsCompatError::InvalidPublicKey))
+}
+
+/// Restores a signature from the 64-byte compact encoding (`r || s`),
+/// the 65-byte one with a trailing recovery byte (which is ignored),
+/// or (for ECDSA) the DER encoding.
+///
+/// `s` is normalized the same way as in [`Signature::from_compact`].
+pub fn signature_from_bytes(bytes: &[u8]) -> Result<Signature, JsCompatError> {
+    let result = match bytes.len() {
+        64 => Signature::from_compact(bytes),
+        65 => Signature::from_compact(&bytes[..64]),
+        #[cfg(not(feature = "schnorr"))]
+        _ => Signature::from_der(bytes),
+        #[cfg(feature = "schnorr")]
+        _ => Err(DeserializationError::ConstructionFailure),
+    };
+    result.or(Err(JsCompatError::InvalidSignature))
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::{
+        from_hex, from_js, public_key_from_bytes, signature_from_bytes, to_hex, to_js,
+        JsCompatError,
+    };
+    use crate::{encapsulate, Capsule, PublicKey, SecretKey, SerializableToArray, Signer};
+
+    // The standard encodings of the secp256k1 generator point
+    // (the public key for the secret key `1`), uncompressed and compressed.
+    // These are not outputs of a JS implementation, just well-known constants.
+    const GENERATOR_UNCOMPRESSED: &str = "0x\
+        0479be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798\
+        483ada7726a3c4655da4fbfc0e1108a8fd17b448a68554199c47d08ffb10d4b8";
+    const GENERATOR_COMPRESSED: &str =
+        "0x0279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798";
+
+    #[test]
+    fn test_js_encodings() {
+        let uncompressed = from_hex(GENERATOR_UNCOMPRESSED).unwrap();
+        let pk = public_key_from_bytes(&uncompressed).unwrap();
+        assert_eq!(to_js(&pk), GENERATOR_COMPRESSED);
+        assert_eq!(from_js::<PublicKey>(GENERATOR_COMPRESSED).unwrap(), pk);
+        assert_eq!(
+            from_js::<PublicKey>(&GENERATOR_COMPRESSED.to_uppercase()[2..]).unwrap(),
+            pk
+        );
+
+        assert_eq!(from_hex("0xabc"), Err(JsCompatError::InvalidHex));
+        assert_eq!(from_hex("0xzz"), Err(JsCompatError::InvalidHex));
+        assert!(matches!(
+            from_js::<Capsule>(GENERATOR_COMPRESSED),
+            Err(JsCompatError::InvalidObject(_))
+        ));
+
+        // Round trip of an object through its JS representation.
+        let (capsule, _key_seed) = encapsulate(&pk);
+        assert_eq!(from_js::<Capsule>(&to_js(&capsule)).unwrap(), capsule);
+
+        let signer = Signer::new(&SecretKey::random());
+        let signature = signer.sign(b"message");
+        let compact = signature.to_compact();
+        let mut with_recovery = compact.to_vec();
+        with_recovery.push(27);
+        for bytes in [&compact[..], &with_recovery[..]].iter() {
+            let restored = signature_from_bytes(bytes).unwrap();
+            assert!(restored.verify(&signer.verifying_key(), b"message"));
+            assert_eq!(to_hex(&restored.to_array()), to_hex(&compact));
+        }
+        assert_eq!(
+            signature_from_bytes(&compact[..63]),
+            Err(JsCompatError::InvalidSignature)
+        );
+    }
+}
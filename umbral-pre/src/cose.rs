@@ -0,0 +1,450 @@
+//! CBOR envelopes for capsules and ciphertexts, modeled after COSE
+//! ([RFC 9052](https://www.rfc-editor.org/rfc/rfc9052)).
+//!
+//! Only the envelope format is borrowed from COSE: the protected header is not
+//! fed to the DEM as the authenticated data, so generic COSE libraries
+//! cannot decrypt the contents. Umbral-aware recipients decrypt them as usual
+//! (e.g. with [`decrypt_original()`](crate::decrypt_original)).
+
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use core::fmt;
+
+use crate::capsule::Capsule;
+use crate::dem::DemAlgorithm;
+use crate::traits::{DeserializableFromArray, DeserializationError, SerializableToArray};
+
+const TAG_ENCRYPT0: u64 = 16;
+const LABEL_ALG: i64 = 1;
+const LABEL_KID: i64 = 4;
+const LABEL_CAPSULE: i64 = -65537;
+const PRIVATE_USE_BASE: i64 = -65536;
+const MAX_NESTING: usize = 16;
+
+const MAJOR_UNSIGNED: u8 = 0;
+const MAJOR_NEGATIVE: u8 = 1;
+const MAJOR_BYTES: u8 = 2;
+const MAJOR_TEXT: u8 = 3;
+const MAJOR_ARRAY: u8 = 4;
+const MAJOR_MAP: u8 = 5;
+const MAJOR_TAG: u8 = 6;
+const MAJOR_SIMPLE: u8 = 7;
+const SIMPLE_NIL: u64 = 22;
+
+/// Errors that can happen when creating or decoding a [`CoseEnvelope`].
+#[derive(Debug, PartialEq)]
+pub enum CoseError {
+    /// The CBOR encoding is malformed, or is not a `COSE_Encrypt0` structure.
+    InvalidFormat,
+    /// The algorithm is not one of the known [`DemAlgorithm`] identifiers.
+    UnknownAlgorithm,
+    /// The algorithm in the header does not match the one of the ciphertext.
+    AlgorithmMismatch,
+    /// The unprotected header does not contain a capsule.
+    MissingCapsule,
+    /// The capsule in the header could not be deserialized.
+    Capsule(DeserializationError),
+}
+
+impl fmt::Display for CoseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidFormat => write!(f, "invalid COSE envelope"),
+            Self::UnknownAlgorithm => write!(f, "unknown algorithm"),
+            Self::AlgorithmMismatch => {
+                write!(f, "the algorithm does not match the one of the ciphertext")
+            }
+            Self::MissingCapsule => write!(f, "the envelope does not contain a capsule"),
+            Self::Capsule(err) => write!(f, "failed to deserialize the capsule: {}", err),
+        }
+    }
+}
+
+fn cose_algorithm(algorithm: DemAlgorithm) -> i64 {
+    PRIVATE_USE_BASE - algorithm.id() as i64
+}
+
+fn dem_algorithm(cose_algorithm: i64) -> Option<DemAlgorithm> {
+    let id = PRIVATE_USE_BASE.checked_sub(cose_algorithm)?;
+    if !(0..=u8::MAX as i64).contains(&id) {
+        return None;
+    }
+    DemAlgorithm::from_id(id as u8)
+}
+
+/// A capsule and (optionally) the ciphertext encrypted under it, in a CBOR envelope.
+///
+/// The envelope is encoded as a `COSE_Encrypt0` structure (CBOR tag 16),
+/// so that it can be embedded as is into the CBOR-based message formats:
+///
+/// ```text
+/// 16([
+///     protected: bstr .cbor { 1 (alg): int },
+///     unprotected: { ? 4 (kid): bstr, -65537 (capsule): bstr },
+///     ciphertext: bstr / nil,
+/// ])
+/// ```
+///
+/// The algorithm identifiers are from the private use range:
+/// `-65536 - id`, where `id` is the identifier of the [`DemAlgorithm`]
+/// (so `-65537` for XChaCha20-Poly1305).
+/// The capsule is carried in a private use header parameter as its byte representation,
+/// and the ciphertext is the one produced by [`encrypt()`](crate::encrypt) (with its header).
+/// A `nil` ciphertext means that it is transmitted separately (a "detached" payload).
+#[derive(Clone, Debug, PartialEq)]
+pub struct CoseEnvelope {
+    algorithm: DemAlgorithm,
+    key_id: Option<Box<[u8]>>,
+    capsule: Capsule,
+    ciphertext: Option<Box<[u8]>>,
+}
+
+impl CoseEnvelope {
+    /// Creates an envelope for a capsule and a ciphertext produced by
+    /// [`encrypt()`](crate::encrypt) (or one of its variants).
+    ///
+    /// Returns [`CoseError::UnknownAlgorithm`] if the ciphertext does not start
+    /// with a known algorithm identifier (e.g. for the legacy headerless ciphertexts,
+    /// which have to be brought to the current format
+    /// with [`migrate_ciphertext()`](crate::migrate_ciphertext) first).
+    pub fn new(capsule: &Capsule, ciphertext: &[u8]) -> Result<Self, CoseError> {
+        let algorithm = ciphertext
+            .first()
+            .and_then(|id| DemAlgorithm::from_id(*id))
+            .ok_or(CoseError::UnknownAlgorithm)?;
+        Ok(Self {
+            algorithm,
+            key_id: None,
+            capsule: *capsule,
+            ciphertext: Some(ciphertext.into()),
+        })
+    }
+
+    /// Creates an envelope for a capsule only, with the ciphertext
+    /// (encrypted with the given algorithm) transmitted separately.
+    pub fn detached(capsule: &Capsule, algorithm: DemAlgorithm) -> Self {
+        Self {
+            algorithm,
+            key_id: None,
+            capsule: *capsule,
+            ciphertext: None,
+        }
+    }
+
+    /// Sets the key identifier header (e.g. a hint for the recipient
+    /// which secret key to use).
+    pub fn with_key_id(self, key_id: &[u8]) -> Self {
+        Self {
+            key_id: Some(key_id.into()),
+            ..self
+        }
+    }
+
+    /// Returns the algorithm the ciphertext was encrypted with.
+    pub fn algorithm(&self) -> DemAlgorithm {
+        self.algorithm
+    }
+
+    /// Returns the key identifier, if it was set.
+    pub fn key_id(&self) -> Option<&[u8]> {
+        self.key_id.as_deref()
+    }
+
+    /// Returns the capsule.
+    pub fn capsule(&self) -> &Capsule {
+        &self.capsule
+    }
+
+    /// Returns the ciphertext, or `None` if it is detached.
+    pub fn ciphertext(&self) -> Option<&[u8]> {
+        self.ciphertext.as_deref()
+    }
+
+    /// Encodes the envelope as a tagged `COSE_Encrypt0` structure.
+    pub fn to_cbor(&self) -> Box<[u8]> {
+        let mut protected = Vec::new();
+        write_head(&mut protected, MAJOR_MAP, 1);
+        write_int(&mut protected, LABEL_ALG);
+        write_int(&mut protected, cose_algorithm(self.algorithm));
+
+        let mut result = Vec::new();
+        write_head(&mut result, MAJOR_TAG, TAG_ENCRYPT0);
+        write_head(&mut result, MAJOR_ARRAY, 3);
+        write_bytes(&mut result, &protected);
+
+        // The entries are in the order of the deterministic encoding (RFC 8949, section 4.2.1).
+        write_head(&mut result, MAJOR_MAP, 1 + self.key_id.is_some() as u64);
+        if let Some(key_id) = &self.key_id {
+            write_int(&mut result, LABEL_KID);
+            write_bytes(&mut result, key_id);
+        }
+        write_int(&mut result, LABEL_CAPSULE);
+        write_bytes(&mut result, &self.capsule.to_array());
+
+        match &self.ciphertext {
+            Some(ciphertext) => write_bytes(&mut result, ciphertext),
+            None => write_head(&mut result, MAJOR_SIMPLE, SIMPLE_NIL),
+        }
+        result.into_boxed_slice()
+    }
+
+    /// Decodes a `COSE_Encrypt0` structure (tagged or untagged) produced by
+    /// [`to_cbor()`](Self::to_cbor) or by another implementation of the format.
+    ///
+    /// The header parameters other than the ones of the format are ignored.
+    pub fn from_cbor(bytes: &[u8]) -> Result<Self, CoseError> {
+        let mut rest = bytes;
+
+        let (mut major, mut arg) = read_head(&mut rest)?;
+        if major == MAJOR_TAG {
+            if arg != TAG_ENCRYPT0 {
+                return Err(CoseError::InvalidFormat);
+            }
+            let (next_major, next_arg) = read_head(&mut rest)?;
+            major = next_major;
+            arg = next_arg;
+        }
+        if major != MAJOR_ARRAY || arg != 3 {
+            return Err(CoseError::InvalidFormat);
+        }
+
+        let mut protected = read_bytes(&mut rest)?;
+        let mut algorithm = None;
+        for _ in 0..read_map_head(&mut protected)? {
+            if read_int(&mut protected)? == LABEL_ALG {
+                let cose_alg = read_int(&mut protected)?;
+                algorithm = Some(dem_algorithm(cose_alg).ok_or(CoseError::UnknownAlgorithm)?);
+            } else {
+                skip_item(&mut protected, 0)?;
+            }
+        }
+        if !protected.is_empty() {
+            return Err(CoseError::InvalidFormat);
+        }
+        let algorithm = algorithm.ok_or(CoseError::UnknownAlgorithm)?;
+
+        let mut key_id = None;
+        let mut capsule = None;
+        for _ in 0..read_map_head(&mut rest)? {
+            match read_int(&mut rest)? {
+                LABEL_KID => key_id = Some(read_bytes(&mut rest)?.into()),
+                LABEL_CAPSULE => {
+                    let capsule_bytes = read_bytes(&mut rest)?;
+                    capsule = Some(Capsule::from_bytes(capsule_bytes).map_err(CoseError::Capsule)?)
+                }
+                _ => skip_item(&mut rest, 0)?,
+            }
+        }
+        let capsule = capsule.ok_or(CoseError::MissingCapsule)?;
+
+        let ciphertext = if rest.first() == Some(&(MAJOR_SIMPLE << 5 | SIMPLE_NIL as u8)) {
+            rest = &rest[1..];
+            None
+        } else {
+            let ciphertext = read_bytes(&mut rest)?;
+            if ciphertext.first() != Some(&algorithm.id()) {
+                return Err(CoseError::AlgorithmMismatch);
+            }
+            Some(ciphertext.into())
+        };
+
+        if !rest.is_empty() {
+            return Err(CoseError::InvalidFormat);
+        }
+
+        Ok(Self {
+            algorithm,
+            key_id,
+            capsule,
+            ciphertext,
+        })
+    }
+}
+
+fn write_head(buf: &mut Vec<u8>, major: u8, arg: u64) {
+    let major = major << 5;
+    if arg < 24 {
+        buf.push(major | arg as u8);
+    } else if arg <= u8::MAX as u64 {
+        buf.push(major | 24);
+        buf.push(arg as u8);
+    } else if arg <= u16::MAX as u64 {
+        buf.push(major | 25);
+        buf.extend_from_slice(&(arg as u16).to_be_bytes());
+    } else if arg <= u32::MAX as u64 {
+        buf.push(major | 26);
+        buf.extend_from_slice(&(arg as u32).to_be_bytes());
+    } else {
+        buf.push(major | 27);
+        buf.extend_from_slice(&arg.to_be_bytes());
+    }
+}
+
+fn write_int(buf: &mut Vec<u8>, value: i64) {
+    if value >= 0 {
+        write_head(buf, MAJOR_UNSIGNED, value as u64);
+    } else {
+        write_head(buf, MAJOR_NEGATIVE, !value as u64);
+    }
+}
+
+fn write_bytes(buf: &mut Vec<u8>, bytes: &[u8]) {
+    write_head(buf, MAJOR_BYTES, bytes.len() as u64);
+    buf.extend_from_slice(bytes);
+}
+
+fn take_bytes<'a>(rest: &mut &'a [u8], size: usize) -> Result<&'a [u8], CoseError> {
+    if rest.len() < size {
+        return Err(CoseError::InvalidFormat);
+    }
+    let (head, tail) = rest.split_at(size);
+    *rest = tail;
+    Ok(head)
+}
+
+/// Reads the initial byte and the argument of a data item.
+/// The indefinite-length items are not supported.
+fn read_head(rest: &mut &[u8]) -> Result<(u8, u64), CoseError> {
+    let initial = take_bytes(rest, 1)?[0];
+    let major = initial >> 5;
+    let info = initial & 0x1f;
+    let arg = match info {
+        0..=23 => info as u64,
+        24 => take_bytes(rest, 1)?[0] as u64,
+        25 => {
+            let mut arr = [0u8; 2];
+            arr.copy_from_slice(take_bytes(rest, 2)?);
+            u16::from_be_bytes(arr) as u64
+        }
+        26 => {
+            let mut arr = [0u8; 4];
+            arr.copy_from_slice(take_bytes(rest, 4)?);
+            u32::from_be_bytes(arr) as u64
+        }
+        27 => {
+            let mut arr = [0u8; 8];
+            arr.copy_from_slice(take_bytes(rest, 8)?);
+            u64::from_be_bytes(arr)
+        }
+        _ => return Err(CoseError::InvalidFormat),
+    };
+    Ok((major, arg))
+}
+
+fn read_int(rest: &mut &[u8]) -> Result<i64, CoseError> {
+    match read_head(rest)? {
+        (MAJOR_UNSIGNED, arg) if arg <= i64::MAX as u64 => Ok(arg as i64),
+        (MAJOR_NEGATIVE, arg) if arg <= i64::MAX as u64 => Ok(!(arg as i64)),
+        _ => Err(CoseError::InvalidFormat),
+    }
+}
+
+fn read_bytes<'a>(rest: &mut &'a [u8]) -> Result<&'a [u8], CoseError> {
+    match read_head(rest)? {
+        (MAJOR_BYTES, arg) if arg <= rest.len() as u64 => take_bytes(rest, arg as usize),
+        _ => Err(CoseError::InvalidFormat),
+    }
+}
+
+fn read_map_head(rest: &mut &[u8]) -> Result<u64, CoseError> {
+    match read_head(rest)? {
+        (MAJOR_MAP, arg) => Ok(arg),
+        _ => Err(CoseError::InvalidFormat),
+    }
+}
+
+/// Skips a (possibly nested) data item of any type.
+fn skip_item(rest: &mut &[u8], depth: usize) -> Result<(), CoseError> {
+    if depth > MAX_NESTING {
+        return Err(CoseError::InvalidFormat);
+    }
+    let (major, arg) = read_head(rest)?;
+    match major {
+        MAJOR_BYTES | MAJOR_TEXT => {
+            if arg > rest.len() as u64 {
+                return Err(CoseError::InvalidFormat);
+            }
+            take_bytes(rest, arg as usize)?;
+        }
+        MAJOR_ARRAY | MAJOR_MAP => {
+            let items = if major == MAJOR_MAP {
+                arg.checked_mul(2).ok_or(CoseError::InvalidFormat)?
+            } else {
+                arg
+            };
+            // Every item takes at least a byte, so a bogus count fails without looping long.
+            if items > rest.len() as u64 {
+                return Err(CoseError::InvalidFormat);
+            }
+            for _ in 0..items {
+                skip_item(rest, depth + 1)?;
+            }
+        }
+        MAJOR_TAG => skip_item(rest, depth + 1)?,
+        _ => {}
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::{CoseEnvelope, CoseError};
+    use crate::{decrypt_original, encrypt, DemAlgorithm, PublicKey, SecretKey};
+
+    #[test]
+    fn test_envelope() {
+        let sk = SecretKey::random();
+        let pk = PublicKey::from_secret_key(&sk);
+        let plaintext = b"peace at dawn";
+        let (capsule, ciphertext) = encrypt(&pk, plaintext).unwrap();
+
+        let envelope = CoseEnvelope::new(&capsule, &ciphertext)
+            .unwrap()
+            .with_key_id(b"alice");
+        let encoded = envelope.to_cbor();
+
+        // Tag 16, an array of 3, the protected header `{1: -65537}` as a byte string.
+        assert_eq!(
+            &encoded[..10],
+            &[0xd0, 0x83, 0x47, 0xa1, 0x01, 0x3a, 0x00, 0x01, 0x00, 0x00]
+        );
+
+        let decoded = CoseEnvelope::from_cbor(&encoded).unwrap();
+        assert_eq!(decoded, envelope);
+        assert_eq!(decoded.algorithm(), DemAlgorithm::XChaCha20Poly1305);
+        assert_eq!(decoded.key_id(), Some(&b"alice"[..]));
+        let plaintext_back =
+            decrypt_original(&sk, decoded.capsule(), decoded.ciphertext().unwrap()).unwrap();
+        assert_eq!(&plaintext_back as &[u8], plaintext);
+
+        // The untagged structure is accepted too.
+        assert_eq!(CoseEnvelope::from_cbor(&encoded[1..]).unwrap(), envelope);
+
+        // A detached ciphertext.
+        let detached = CoseEnvelope::detached(&capsule, DemAlgorithm::XChaCha20Poly1305);
+        let decoded = CoseEnvelope::from_cbor(&detached.to_cbor()).unwrap();
+        assert_eq!(decoded.ciphertext(), None);
+        assert_eq!(decoded.capsule(), &capsule);
+
+        // An unknown algorithm.
+        let mut modified = encoded.to_vec();
+        modified[9] = 0x10;
+        assert_eq!(
+            CoseEnvelope::from_cbor(&modified),
+            Err(CoseError::UnknownAlgorithm)
+        );
+
+        // Truncated and extended encodings.
+        assert_eq!(
+            CoseEnvelope::from_cbor(&encoded[..encoded.len() - 1]),
+            Err(CoseError::InvalidFormat)
+        );
+        let mut extended = encoded.to_vec();
+        extended.push(0);
+        assert_eq!(
+            CoseEnvelope::from_cbor(&extended),
+            Err(CoseError::InvalidFormat)
+        );
+    }
+}
@@ -1,27 +1,46 @@
 //! This module is an adapter to the ECC backend.
 //! `elliptic_curves` has a somewhat unstable API,
 //! and we isolate all the related logic here.
+//! The arithmetic itself goes through the `CurveBackend` trait (see the `backend` submodule).
 
+mod backend;
+
+use alloc::boxed::Box;
+use alloc::vec::Vec;
 use core::default::Default;
 use core::ops::{Add, Mul, Sub};
+use core::sync::atomic::{compiler_fence, Ordering};
+#[cfg(any(not(feature = "rfc9380"), feature = "schnorr"))]
 use digest::Digest;
+#[cfg(any(not(feature = "rfc9380"), feature = "schnorr"))]
 use ecdsa::hazmat::FromDigest;
-use elliptic_curve::ff::PrimeField;
-use elliptic_curve::sec1::{CompressedPointSize, EncodedPoint, FromEncodedPoint, ToEncodedPoint};
+use elliptic_curve::sec1::CompressedPointSize;
+#[cfg(feature = "rfc9380")]
+use elliptic_curve::sec1::{EncodedPoint, FromEncodedPoint};
+#[cfg(feature = "rfc9380")]
+use elliptic_curve::FieldBytes;
 use elliptic_curve::NonZeroScalar;
-use elliptic_curve::{AffinePoint, Curve, ProjectiveArithmetic, Scalar};
+use elliptic_curve::{AffinePoint, Curve};
 use generic_array::GenericArray;
 use k256::Secp256k1;
-use rand_core::OsRng;
-use subtle::CtOption;
+use rand_core::{CryptoRng, RngCore};
+use subtle::{Choice, ConditionallySelectable, ConstantTimeEq, CtOption};
+use zeroize::Zeroize;
 
 use crate::traits::{
     DeserializableFromArray, DeserializationError, RepresentableAsArray, SerializableToArray,
 };
+use backend::{CurveBackend, K256};
 
 pub(crate) type CurveType = Secp256k1;
 
-type BackendScalar = Scalar<CurveType>;
+/// The implementation of the arithmetic used by the crate.
+/// An alternative backend is selected here (e.g. with a feature flag);
+/// the conversions to and from the `k256` types below (for the keys, the signatures,
+/// and the hashing) would have to be provided for it as well.
+type Backend = K256;
+
+type BackendScalar = <Backend as CurveBackend>::Scalar;
 pub(crate) type BackendNonZeroScalar = NonZeroScalar<CurveType>;
 
 // We have to define newtypes for scalar and point here because the compiler
@@ -32,9 +51,13 @@ pub(crate) type BackendNonZeroScalar = NonZeroScalar<CurveType>;
 //     type PointSize = <Point as RepresentableAsArray>::Size;
 // isn't leaking the `Point` (probably because type aliases are just inlined).
 
-#[derive(Clone, Copy, Debug, PartialEq)]
+#[derive(Clone, Copy, Debug)]
 pub struct CurveScalar(BackendScalar);
 
+/// A curve point in the compressed SEC1 encoding
+/// (returned by the getters of the structural fields, e.g. [`Capsule::e()`](crate::Capsule::e)).
+pub type CompressedPoint = GenericArray<u8, CompressedPointSize<CurveType>>;
+
 impl CurveScalar {
     pub(crate) fn from_backend_scalar(scalar: &BackendScalar) -> Self {
         Self(*scalar)
@@ -45,22 +68,23 @@ impl CurveScalar {
     }
 
     pub(crate) fn invert(&self) -> CtOption<Self> {
-        self.0.invert().map(Self)
+        Backend::scalar_invert(&self.0).map(Self)
     }
 
     pub(crate) fn one() -> Self {
-        Self(BackendScalar::one())
+        Self(Backend::scalar_one())
     }
 
     pub(crate) fn is_zero(&self) -> bool {
-        self.0.is_zero().into()
+        Backend::scalar_is_zero(&self.0).into()
     }
 
     /// Generates a random non-zero scalar (in nearly constant-time).
-    pub(crate) fn random_nonzero() -> CurveScalar {
-        Self(*BackendNonZeroScalar::random(&mut OsRng))
+    pub(crate) fn random_nonzero(rng: &mut (impl CryptoRng + RngCore)) -> CurveScalar {
+        Self(Backend::scalar_random_nonzero(rng))
     }
 
+    #[cfg(any(not(feature = "rfc9380"), feature = "schnorr"))]
     pub(crate) fn from_digest(
         d: impl Digest<OutputSize = <CurveScalar as RepresentableAsArray>::Size>,
     ) -> Self {
@@ -68,6 +92,31 @@ impl CurveScalar {
     }
 }
 
+impl ConstantTimeEq for CurveScalar {
+    fn ct_eq(&self, other: &Self) -> Choice {
+        self.0.ct_eq(&other.0)
+    }
+}
+
+// Scalars are often secret, so the comparison must not leak their values through timing.
+impl PartialEq for CurveScalar {
+    fn eq(&self, other: &Self) -> bool {
+        self.ct_eq(other).into()
+    }
+}
+
+impl ConditionallySelectable for CurveScalar {
+    fn conditional_select(a: &Self, b: &Self, choice: Choice) -> Self {
+        Self(BackendScalar::conditional_select(&a.0, &b.0, choice))
+    }
+}
+
+impl Zeroize for CurveScalar {
+    fn zeroize(&mut self) {
+        self.0.zeroize()
+    }
+}
+
 impl Default for CurveScalar {
     fn default() -> Self {
         Self(BackendScalar::default())
@@ -82,53 +131,113 @@ impl RepresentableAsArray for CurveScalar {
 
 impl SerializableToArray for CurveScalar {
     fn to_array(&self) -> GenericArray<u8, Self::Size> {
-        self.0.to_bytes()
+        Backend::scalar_to_bytes(&self.0)
     }
 }
 
 impl DeserializableFromArray for CurveScalar {
     fn from_array(arr: &GenericArray<u8, Self::Size>) -> Result<Self, DeserializationError> {
-        Scalar::<CurveType>::from_repr(*arr)
+        // Rejects the values greater than or equal to the curve order.
+        Backend::scalar_from_bytes(arr)
             .map(Self)
-            .ok_or(DeserializationError::ConstructionFailure)
+            .ok_or(DeserializationError::InvalidScalar)
     }
 }
 
-type BackendPoint = <CurveType as ProjectiveArithmetic>::ProjectivePoint;
+type BackendPoint = <Backend as CurveBackend>::Point;
 type BackendPointAffine = AffinePoint<CurveType>;
 
-#[derive(Clone, Copy, Debug, PartialEq)]
+#[derive(Clone, Copy, Debug)]
 pub struct CurvePoint(BackendPoint);
 
+impl ConstantTimeEq for CurvePoint {
+    fn ct_eq(&self, other: &Self) -> Choice {
+        self.0.ct_eq(&other.0)
+    }
+}
+
+// Points can be derived from secret values (e.g. the shared secrets of the KEM).
+impl PartialEq for CurvePoint {
+    fn eq(&self, other: &Self) -> bool {
+        self.ct_eq(other).into()
+    }
+}
+
+// Points can be secret too (e.g. Diffie-Hellman shared secrets).
+// The backend points do not implement `Zeroize`, so the point is overwritten with the identity
+// through `conditional_assign()`, whose condition is opaque to the optimizer.
+impl Zeroize for CurvePoint {
+    fn zeroize(&mut self) {
+        self.0
+            .conditional_assign(&Backend::point_identity(), Choice::from(1));
+        compiler_fence(Ordering::SeqCst);
+    }
+}
+
 impl CurvePoint {
     pub(crate) fn from_backend_point(point: &BackendPoint) -> Self {
         Self(*point)
     }
 
     pub(crate) fn generator() -> Self {
-        Self(BackendPoint::generator())
+        Self(Backend::point_generator())
     }
 
-    pub(crate) fn identity() -> Self {
-        Self(BackendPoint::identity())
+    pub(crate) fn is_identity(&self) -> bool {
+        self.0.ct_eq(&Backend::point_identity()).into()
+    }
+
+    #[cfg(feature = "rfc9380")]
+    pub(crate) fn from_affine_coordinates(
+        x: &FieldBytes<CurveType>,
+        y: &FieldBytes<CurveType>,
+    ) -> Option<Self> {
+        let ep = EncodedPoint::<CurveType>::from_affine_coordinates(x, y, false);
+        let cp_opt: Option<BackendPoint> = BackendPoint::from_encoded_point(&ep);
+        cp_opt.map(Self)
+    }
+
+    /// Multiplies the point by a secret scalar, splitting the scalar into two random shares
+    /// (`P * k == P * r + P * (k - r)` for a random `r`), so that each of the multiplications
+    /// performed by the backend is by a uniformly random scalar independent of `k`.
+    /// The backend does not expose the projective coordinates, so they cannot be randomized directly.
+    #[cfg(feature = "hardened")]
+    pub(crate) fn mul_blinded(
+        &self,
+        scalar: &CurveScalar,
+        rng: &mut (impl CryptoRng + RngCore),
+    ) -> CurvePoint {
+        let share = CurveScalar::random_nonzero(rng);
+        &(self * &share) + &(self * &(scalar - &share))
     }
 
     pub(crate) fn to_affine_point(&self) -> BackendPointAffine {
         self.0.to_affine()
     }
 
+    /// Decodes a point from the compressed SEC1 encoding.
+    ///
+    /// Returns `None` if the tag is not `0x02` or `0x03`, the `x` coordinate is not on the curve,
+    /// or the encoding is not canonical (that is, `x` is not reduced modulo the field order).
+    /// The identity does not have a compressed encoding, so it is rejected too.
     pub(crate) fn from_compressed_array(
         arr: &GenericArray<u8, CompressedPointSize<CurveType>>,
     ) -> Option<Self> {
-        let ep = EncodedPoint::<CurveType>::from_bytes(arr.as_slice()).ok()?;
-        let cp_opt: Option<BackendPoint> = BackendPoint::from_encoded_point(&ep);
-        cp_opt.map(Self)
+        if arr[0] != 0x02 && arr[0] != 0x03 {
+            return None;
+        }
+        let point = Self(Backend::point_from_compressed(arr)?);
+        // Encoding the point back catches a non-reduced `x`,
+        // in case the backend accepts it and reduces it silently.
+        if point.is_identity() || point.to_compressed_array() != *arr {
+            return None;
+        }
+        Some(point)
     }
 
     fn to_compressed_array(&self) -> GenericArray<u8, CompressedPointSize<CurveType>> {
-        *GenericArray::<u8, CompressedPointSize<CurveType>>::from_slice(
-            self.0.to_affine().to_encoded_point(true).as_bytes(),
-        )
+        // The identity is encoded as zeros, which `from_compressed_array()` rejects.
+        Backend::point_to_compressed(&self.0)
     }
 }
 
@@ -136,7 +245,7 @@ impl Add<&CurveScalar> for &CurveScalar {
     type Output = CurveScalar;
 
     fn add(self, other: &CurveScalar) -> CurveScalar {
-        CurveScalar(self.0.add(&(other.0)))
+        CurveScalar(Backend::scalar_add(&self.0, &other.0))
     }
 }
 
@@ -144,7 +253,7 @@ impl Add<&CurvePoint> for &CurvePoint {
     type Output = CurvePoint;
 
     fn add(self, other: &CurvePoint) -> CurvePoint {
-        CurvePoint(self.0.add(&(other.0)))
+        CurvePoint(Backend::point_add(&self.0, &other.0))
     }
 }
 
@@ -152,7 +261,7 @@ impl Sub<&CurveScalar> for &CurveScalar {
     type Output = CurveScalar;
 
     fn sub(self, other: &CurveScalar) -> CurveScalar {
-        CurveScalar(self.0.sub(&(other.0)))
+        CurveScalar(Backend::scalar_sub(&self.0, &other.0))
     }
 }
 
@@ -160,7 +269,7 @@ impl Mul<&CurveScalar> for &CurvePoint {
     type Output = CurvePoint;
 
     fn mul(self, other: &CurveScalar) -> CurvePoint {
-        CurvePoint(self.0.mul(&(other.0)))
+        CurvePoint(Backend::point_mul(&self.0, &other.0))
     }
 }
 
@@ -168,7 +277,7 @@ impl Mul<&CurveScalar> for &CurveScalar {
     type Output = CurveScalar;
 
     fn mul(self, other: &CurveScalar) -> CurveScalar {
-        CurveScalar(self.0.mul(&(other.0)))
+        CurveScalar(Backend::scalar_mul(&self.0, &other.0))
     }
 }
 
@@ -184,6 +293,216 @@ impl SerializableToArray for CurvePoint {
 
 impl DeserializableFromArray for CurvePoint {
     fn from_array(arr: &GenericArray<u8, Self::Size>) -> Result<Self, DeserializationError> {
-        Self::from_compressed_array(arr).ok_or(DeserializationError::ConstructionFailure)
+        Self::from_compressed_array(arr).ok_or(DeserializationError::InvalidPoint)
+    }
+}
+
+// The number of 4-bit windows in a scalar.
+const TABLE_WINDOWS: usize = 64;
+
+/// Multiples of a fixed point for faster multiplication by scalars:
+/// the entry `j` of the row `i` is `j * 16^i * point`.
+///
+/// A multiplication takes one addition per window and no doublings,
+/// at the cost of about 120 KiB per table.
+#[derive(Clone)]
+pub(crate) struct PointTable(Box<[[BackendPoint; 16]]>);
+
+impl PointTable {
+    pub(crate) fn new(point: &CurvePoint) -> Self {
+        let mut rows = Vec::with_capacity(TABLE_WINDOWS);
+        let mut base = point.0;
+        for _ in 0..TABLE_WINDOWS {
+            let row = multiples(&base);
+            base = Backend::point_add(&row[15], &base);
+            rows.push(row);
+        }
+        Self(rows.into_boxed_slice())
+    }
+
+    /// Multiplies the point by `scalar` (in constant time).
+    pub(crate) fn mul(&self, scalar: &CurveScalar) -> CurvePoint {
+        let mut bytes = Backend::scalar_to_bytes(&scalar.0);
+        let mut result = Backend::point_identity();
+        for (i, row) in self.0.iter().enumerate() {
+            result = Backend::point_add(&result, &select_multiple(row, window_digit(&bytes, i)));
+        }
+        bytes.zeroize();
+        CurvePoint(result)
+    }
+}
+
+/// Returns `[0 * point, 1 * point, ..., 15 * point]`.
+fn multiples(point: &BackendPoint) -> [BackendPoint; 16] {
+    let mut result = [Backend::point_identity(); 16];
+    for j in 1..16 {
+        result[j] = Backend::point_add(&result[j - 1], point);
+    }
+    result
+}
+
+/// Returns the 4-bit window `i` (counting from the lowest one) of the big-endian scalar bytes.
+fn window_digit(bytes: &[u8], i: usize) -> u8 {
+    let byte = bytes[bytes.len() - 1 - i / 2];
+    (byte >> (4 * (i % 2))) & 0xf
+}
+
+/// Returns `multiples[digit]`, reading all the entries so that the access is constant-time.
+fn select_multiple(multiples: &[BackendPoint; 16], digit: u8) -> BackendPoint {
+    let mut result = Backend::point_identity();
+    for (j, candidate) in multiples.iter().enumerate() {
+        result.conditional_assign(candidate, (j as u8).ct_eq(&digit));
+    }
+    result
+}
+
+/// Computes `sum_i scalars[i] * points[i]` (in constant time) with the Straus method:
+/// the doublings are shared between all the points,
+/// so for many points it is several times faster than separate multiplications.
+/// Requires `scalars.len() == points.len()`.
+pub(crate) fn multi_mul(scalars: &[CurveScalar], points: &[CurvePoint]) -> CurvePoint {
+    debug_assert!(scalars.len() == points.len());
+    let tables: Vec<_> = points.iter().map(|point| multiples(&point.0)).collect();
+    let mut scalar_bytes: Vec<_> = scalars
+        .iter()
+        .map(|scalar| Backend::scalar_to_bytes(&scalar.0))
+        .collect();
+
+    let mut result = Backend::point_identity();
+    for i in (0..TABLE_WINDOWS).rev() {
+        for _ in 0..4 {
+            result = Backend::point_double(&result);
+        }
+        for (table, bytes) in tables.iter().zip(scalar_bytes.iter()) {
+            result = Backend::point_add(&result, &select_multiple(table, window_digit(bytes, i)));
+        }
+    }
+
+    for bytes in scalar_bytes.iter_mut() {
+        bytes.zeroize();
+    }
+    CurvePoint(result)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use alloc::vec::Vec;
+
+    use rand_core::OsRng;
+
+    use super::{multi_mul, CurvePoint, CurveScalar};
+    use crate::traits::{DeserializableFromArray, DeserializationError, SerializableToArray};
+
+    #[cfg(feature = "hardened")]
+    #[test]
+    fn test_mul_blinded() {
+        let p = &CurvePoint::generator() * &CurveScalar::random_nonzero(&mut OsRng);
+        let k = CurveScalar::random_nonzero(&mut OsRng);
+        assert_eq!(p.mul_blinded(&k, &mut OsRng), &p * &k);
+    }
+
+    #[test]
+    fn test_multi_mul() {
+        let g = CurvePoint::generator();
+        let zero = CurveScalar::default();
+        let identity = &g * &zero;
+        let scalars: Vec<_> = (0..5)
+            .map(|_| CurveScalar::random_nonzero(&mut OsRng))
+            .collect();
+        let points: Vec<_> = scalars.iter().map(|scalar| &g * scalar).collect();
+
+        for n in 0..=points.len() {
+            let expected = scalars[..n]
+                .iter()
+                .zip(points[..n].iter())
+                .fold(identity, |acc, (scalar, point)| &acc + &(point * scalar));
+            assert!(multi_mul(&scalars[..n], &points[..n]) == expected);
+        }
+
+        assert!(multi_mul(&[zero], &[g]) == identity);
+    }
+
+    #[test]
+    fn test_identity_serialization() {
+        // The identity can be the result of arithmetic on untrusted values
+        // (e.g. a zero key in a key fragment), and must serialize without a panic,
+        // into an encoding that is not accepted back.
+        let identity = &CurvePoint::generator() * &CurveScalar::default();
+        let arr = identity.to_array();
+        assert!(arr.iter().all(|byte| *byte == 0));
+        assert_eq!(
+            CurvePoint::from_array(&arr),
+            Err(DeserializationError::InvalidPoint)
+        );
+    }
+
+    #[test]
+    fn test_point_validation() {
+        let point = &CurvePoint::generator() * &CurveScalar::random_nonzero(&mut OsRng);
+        let arr = point.to_array();
+        assert_eq!(CurvePoint::from_array(&arr), Ok(point));
+
+        // Only the compressed tags are accepted.
+        for tag in [0x00, 0x01, 0x04, 0x05, 0x06, 0x07, 0xff].iter() {
+            let mut bad_tag = arr;
+            bad_tag[0] = *tag;
+            assert_eq!(
+                CurvePoint::from_array(&bad_tag),
+                Err(DeserializationError::InvalidPoint)
+            );
+        }
+
+        // `x = 5` is not on the curve (`x^3 + 7 = 132` is not a square modulo `p`).
+        let mut off_curve = arr;
+        off_curve[1..].iter_mut().for_each(|byte| *byte = 0);
+        off_curve[32] = 5;
+        assert_eq!(
+            CurvePoint::from_array(&off_curve),
+            Err(DeserializationError::InvalidPoint)
+        );
+
+        // `x` greater than or equal to the field order `p` is not a canonical encoding.
+        // `p + 1` would be reduced to `x = 1`, which is on the curve.
+        let mut not_reduced = arr;
+        not_reduced[1..].iter_mut().for_each(|byte| *byte = 0xff);
+        assert_eq!(
+            CurvePoint::from_array(&not_reduced),
+            Err(DeserializationError::InvalidPoint)
+        );
+        not_reduced[28..].copy_from_slice(&[0xfe, 0xff, 0xff, 0xfc, 0x30]);
+        assert_eq!(
+            CurvePoint::from_array(&not_reduced),
+            Err(DeserializationError::InvalidPoint)
+        );
+    }
+
+    #[test]
+    fn test_scalar_validation() {
+        let scalar = CurveScalar::random_nonzero(&mut OsRng);
+        assert_eq!(CurveScalar::from_array(&scalar.to_array()), Ok(scalar));
+
+        // The curve order `n` and the values above it are not canonical.
+        let order = [
+            0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+            0xff, 0xfe, 0xba, 0xae, 0xdc, 0xe6, 0xaf, 0x48, 0xa0, 0x3b, 0xbf, 0xd2, 0x5e, 0x8c,
+            0xd0, 0x36, 0x41, 0x41,
+        ];
+        let mut arr = scalar.to_array();
+        arr.copy_from_slice(&order);
+        assert_eq!(
+            CurveScalar::from_array(&arr),
+            Err(DeserializationError::InvalidScalar)
+        );
+        arr.iter_mut().for_each(|byte| *byte = 0xff);
+        assert_eq!(
+            CurveScalar::from_array(&arr),
+            Err(DeserializationError::InvalidScalar)
+        );
+
+        // `n - 1` is the largest valid scalar.
+        arr.copy_from_slice(&order);
+        arr[31] = 0x40;
+        assert!(CurveScalar::from_array(&arr).is_ok());
     }
 }
@@ -0,0 +1,147 @@
+//! The secp256k1 arithmetic [`CurveScalar`](super::CurveScalar)
+//! and [`CurvePoint`](super::CurvePoint) are built upon.
+//!
+//! The operations are collected in [`CurveBackend`], so that an alternative implementation
+//! (e.g. bindings to `libsecp256k1`, or a formally verified field arithmetic)
+//! can be selected at compile time by pointing the `Backend` alias in the parent module to it,
+//! without changing the rest of the crate or its public API.
+//! Keys and signatures (the `ecdsa` crate) and the RFC 9380 hashing still use `k256` directly.
+
+use core::fmt::Debug;
+
+use elliptic_curve::ff::PrimeField;
+use elliptic_curve::sec1::{EncodedPoint, FromEncodedPoint, ToEncodedPoint};
+use elliptic_curve::{NonZeroScalar, ProjectiveArithmetic, Scalar};
+use generic_array::GenericArray;
+use k256::Secp256k1;
+use rand_core::{CryptoRng, RngCore};
+use subtle::{Choice, ConditionallySelectable, ConstantTimeEq, CtOption};
+use typenum::{U32, U33};
+use zeroize::Zeroize;
+
+/// The operations on the scalars and the points of secp256k1 used by the crate.
+///
+/// All of them must be constant-time with respect to their arguments.
+pub(crate) trait CurveBackend {
+    /// An integer modulo the curve order.
+    type Scalar: Copy + Debug + Default + ConditionallySelectable + ConstantTimeEq + Zeroize;
+    /// A curve point (including the identity).
+    type Point: Copy + Debug + ConditionallySelectable + ConstantTimeEq;
+
+    fn scalar_one() -> Self::Scalar;
+
+    fn scalar_add(a: &Self::Scalar, b: &Self::Scalar) -> Self::Scalar;
+
+    fn scalar_sub(a: &Self::Scalar, b: &Self::Scalar) -> Self::Scalar;
+
+    fn scalar_mul(a: &Self::Scalar, b: &Self::Scalar) -> Self::Scalar;
+
+    /// Returns a none value for zero.
+    fn scalar_invert(a: &Self::Scalar) -> CtOption<Self::Scalar>;
+
+    fn scalar_is_zero(a: &Self::Scalar) -> Choice;
+
+    fn scalar_random_nonzero(rng: &mut (impl CryptoRng + RngCore)) -> Self::Scalar;
+
+    /// Returns the big-endian representation of the scalar.
+    fn scalar_to_bytes(a: &Self::Scalar) -> GenericArray<u8, U32>;
+
+    /// Returns `None` if the big-endian value is not less than the curve order.
+    fn scalar_from_bytes(bytes: &GenericArray<u8, U32>) -> Option<Self::Scalar>;
+
+    fn point_identity() -> Self::Point;
+
+    fn point_generator() -> Self::Point;
+
+    fn point_add(p: &Self::Point, q: &Self::Point) -> Self::Point;
+
+    fn point_double(p: &Self::Point) -> Self::Point;
+
+    fn point_mul(p: &Self::Point, a: &Self::Scalar) -> Self::Point;
+
+    /// Returns the compressed SEC1 encoding of the point, or all zeros for the identity.
+    fn point_to_compressed(p: &Self::Point) -> GenericArray<u8, U33>;
+
+    /// Returns `None` if the bytes are not a compressed SEC1 encoding of a point on the curve.
+    fn point_from_compressed(bytes: &GenericArray<u8, U33>) -> Option<Self::Point>;
+}
+
+/// The backend based on the `k256` crate.
+pub(crate) struct K256;
+
+type K256Point = <Secp256k1 as ProjectiveArithmetic>::ProjectivePoint;
+
+impl CurveBackend for K256 {
+    type Scalar = Scalar<Secp256k1>;
+    type Point = K256Point;
+
+    fn scalar_one() -> Self::Scalar {
+        Scalar::<Secp256k1>::one()
+    }
+
+    fn scalar_add(a: &Self::Scalar, b: &Self::Scalar) -> Self::Scalar {
+        *a + b
+    }
+
+    fn scalar_sub(a: &Self::Scalar, b: &Self::Scalar) -> Self::Scalar {
+        *a - b
+    }
+
+    fn scalar_mul(a: &Self::Scalar, b: &Self::Scalar) -> Self::Scalar {
+        *a * b
+    }
+
+    fn scalar_invert(a: &Self::Scalar) -> CtOption<Self::Scalar> {
+        a.invert()
+    }
+
+    fn scalar_is_zero(a: &Self::Scalar) -> Choice {
+        a.is_zero()
+    }
+
+    fn scalar_random_nonzero(rng: &mut (impl CryptoRng + RngCore)) -> Self::Scalar {
+        *NonZeroScalar::<Secp256k1>::random(rng)
+    }
+
+    fn scalar_to_bytes(a: &Self::Scalar) -> GenericArray<u8, U32> {
+        a.to_bytes()
+    }
+
+    fn scalar_from_bytes(bytes: &GenericArray<u8, U32>) -> Option<Self::Scalar> {
+        Scalar::<Secp256k1>::from_repr(*bytes)
+    }
+
+    fn point_identity() -> Self::Point {
+        K256Point::identity()
+    }
+
+    fn point_generator() -> Self::Point {
+        K256Point::generator()
+    }
+
+    fn point_add(p: &Self::Point, q: &Self::Point) -> Self::Point {
+        *p + q
+    }
+
+    fn point_double(p: &Self::Point) -> Self::Point {
+        p.double()
+    }
+
+    fn point_mul(p: &Self::Point, a: &Self::Scalar) -> Self::Point {
+        *p * a
+    }
+
+    fn point_to_compressed(p: &Self::Point) -> GenericArray<u8, U33> {
+        let mut arr = GenericArray::<u8, U33>::default();
+        let ep = p.to_affine().to_encoded_point(true);
+        // The identity is encoded as a single zero byte by `k256`.
+        let bytes = ep.as_bytes();
+        arr[..bytes.len()].copy_from_slice(bytes);
+        arr
+    }
+
+    fn point_from_compressed(bytes: &GenericArray<u8, U33>) -> Option<Self::Point> {
+        let ep = EncodedPoint::<Secp256k1>::from_bytes(bytes.as_slice()).ok()?;
+        K256Point::from_encoded_point(&ep)
+    }
+}
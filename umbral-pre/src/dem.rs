@@ -1,14 +1,18 @@
 use alloc::boxed::Box;
+use alloc::vec::Vec;
+use core::fmt;
 
-use aead::{Aead, AeadCore, Payload};
-use chacha20poly1305::aead::NewAead;
-use chacha20poly1305::{Key, XChaCha20Poly1305, XNonce};
-use generic_array::{ArrayLength, GenericArray};
-use hkdf::Hkdf;
-use rand_core::OsRng;
-use rand_core::RngCore;
-use sha2::Sha256;
+use aead::{Aead, AeadCore, NewAead, Payload};
+#[cfg(feature = "aes-gcm")]
+use aes_gcm::Aes256Gcm;
+use chacha20poly1305::XChaCha20Poly1305;
+use generic_array::GenericArray;
+use rand_core::{CryptoRng, RngCore};
 use typenum::Unsigned;
+use zeroize::Zeroize;
+
+use crate::hashing::kdf;
+use crate::secure_memory::{secret, Secret};
 
 /// Errors that can happen during symmetric encryption.
 #[derive(Debug, PartialEq)]
@@ -17,6 +21,14 @@ pub enum EncryptionError {
     PlaintextTooLarge,
 }
 
+impl fmt::Display for EncryptionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::PlaintextTooLarge => write!(f, "plaintext is too large"),
+        }
+    }
+}
+
 /// Errors that can happend during symmetric decryption.
 #[derive(Debug, PartialEq)]
 pub enum DecryptionError {
@@ -28,108 +40,465 @@ pub enum DecryptionError {
     /// - the ciphertext is modified or cut short,
     /// - an incorrect authentication data is provided on decryption.
     AuthenticationFailed,
+    /// The plaintext contained in the ciphertext would exceed the given size limit.
+    PlaintextTooLarge,
+    /// The secret key does not open the capsule
+    /// (only detected by [`decrypt_original_with_key_check()`](crate::decrypt_original_with_key_check)).
+    WrongKey,
+    /// The ciphertext header names an AEAD algorithm unknown to this build
+    /// (possibly because the corresponding feature is not enabled).
+    UnknownAlgorithm(u8),
+    /// The plaintext is not signed by the given key
+    /// (only detected by the `decrypt_*_verified()` functions,
+    /// see [`encrypt_signed()`](crate::encrypt_signed)).
+    IncorrectSignature,
+}
+
+impl fmt::Display for DecryptionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::CiphertextTooShort => write!(f, "ciphertext is too short"),
+            Self::AuthenticationFailed => write!(f, "authentication failed"),
+            Self::PlaintextTooLarge => write!(f, "plaintext would exceed the size limit"),
+            Self::WrongKey => write!(f, "the key does not open the capsule"),
+            Self::UnknownAlgorithm(id) => write!(f, "unknown DEM algorithm: {}", id),
+            Self::IncorrectSignature => write!(f, "incorrect plaintext signature"),
+        }
+    }
+}
+
+/// The AEAD algorithms used to encrypt the payload under the key seed of a capsule.
+///
+/// The ciphertexts produced by [`encrypt()`](crate::encrypt) start with the identifier
+/// of the algorithm (the discriminant of this enum),
+/// so the decryption functions select the algorithm automatically.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u8)]
+pub enum DemAlgorithm {
+    /// XChaCha20-Poly1305 (the default).
+    XChaCha20Poly1305 = 1,
+    /// AES-256-GCM (requires the `aes-gcm` feature).
+    #[cfg(feature = "aes-gcm")]
+    Aes256Gcm = 2,
+}
+
+impl DemAlgorithm {
+    /// The algorithm used by [`encrypt()`](crate::encrypt).
+    pub const DEFAULT: Self = Self::XChaCha20Poly1305;
+
+    /// Returns the identifier of the algorithm in the ciphertext header.
+    pub fn id(&self) -> u8 {
+        *self as u8
+    }
+
+    /// Returns the algorithm with the given identifier, if it is known to this build.
+    pub fn from_id(id: u8) -> Option<Self> {
+        match id {
+            1 => Some(Self::XChaCha20Poly1305),
+            #[cfg(feature = "aes-gcm")]
+            2 => Some(Self::Aes256Gcm),
+            _ => None,
+        }
+    }
+
+    /// Returns the number of bytes by which the ciphertexts produced with this algorithm
+    /// are longer than the plaintext (the identifier, the nonce and the authentication tag).
+    pub const fn ciphertext_overhead(&self) -> usize {
+        1 + match self {
+            Self::XChaCha20Poly1305 => {
+                <XChaCha20Poly1305Dem as Dem>::NONCE_SIZE + <XChaCha20Poly1305Dem as Dem>::TAG_SIZE
+            }
+            #[cfg(feature = "aes-gcm")]
+            Self::Aes256Gcm => <Aes256GcmDem as Dem>::NONCE_SIZE + <Aes256GcmDem as Dem>::TAG_SIZE,
+        }
+    }
+}
+
+/// Returns the number of bytes by which the ciphertexts produced by
+/// [`encrypt()`](crate::encrypt) are longer than the plaintext.
+pub const fn ciphertext_overhead() -> usize {
+    DemAlgorithm::DEFAULT.ciphertext_overhead()
+}
+
+/// Returns the size of the ciphertext produced by [`encrypt()`](crate::encrypt)
+/// for a plaintext of the given size.
+pub const fn encrypted_size(plaintext_size: usize) -> usize {
+    plaintext_size + ciphertext_overhead()
+}
+
+/// A symmetric authenticated encryption scheme keyed with a key seed.
+pub(crate) trait Dem: Sized {
+    /// The size of the random nonce prepended to the ciphertext.
+    const NONCE_SIZE: usize;
+
+    /// The size of the authentication tag appended to the ciphertext.
+    const TAG_SIZE: usize;
+
+    fn new(key_seed: &[u8]) -> Self;
+
+    fn encrypt(
+        &self,
+        rng: &mut (impl CryptoRng + RngCore),
+        data: &[u8],
+        authenticated_data: &[u8],
+    ) -> Result<Box<[u8]>, EncryptionError>;
+
+    fn decrypt(
+        &self,
+        ciphertext: impl AsRef<[u8]>,
+        authenticated_data: &[u8],
+    ) -> Result<Box<[u8]>, DecryptionError>;
+
+    /// Returns the size of the plaintext contained in a ciphertext of the given size,
+    /// without attempting to decrypt it.
+    fn plaintext_size(ciphertext_size: usize) -> Result<usize, DecryptionError> {
+        ciphertext_size
+            .checked_sub(Self::NONCE_SIZE + Self::TAG_SIZE)
+            .ok_or(DecryptionError::CiphertextTooShort)
+    }
+
+    /// Same as [`decrypt()`](`Self::decrypt()`), but refuses to allocate more than
+    /// `max_plaintext_size` bytes for the plaintext.
+    /// The check is performed before any decryption or authentication takes place.
+    fn decrypt_limited(
+        &self,
+        ciphertext: impl AsRef<[u8]>,
+        authenticated_data: &[u8],
+        max_plaintext_size: usize,
+    ) -> Result<Box<[u8]>, DecryptionError> {
+        if Self::plaintext_size(ciphertext.as_ref().len())? > max_plaintext_size {
+            return Err(DecryptionError::PlaintextTooLarge);
+        }
+        self.decrypt(ciphertext, authenticated_data)
+    }
+}
+
+fn new_cipher<A: NewAead>(key_seed: &[u8]) -> A {
+    let mut key_bytes = kdf::<A::KeySize>(&key_seed, None, None);
+    // The cipher object zeroizes its copy of the key on drop.
+    let cipher = A::new(&key_bytes);
+    key_bytes.zeroize();
+    cipher
 }
 
-pub(crate) fn kdf<T: ArrayLength<u8>>(
-    seed: &[u8],
-    salt: Option<&[u8]>,
-    info: Option<&[u8]>,
-) -> GenericArray<u8, T> {
-    let hk = Hkdf::<Sha256>::new(salt, &seed);
+fn aead_encrypt<A: Aead>(
+    cipher: &A,
+    rng: &mut (impl CryptoRng + RngCore),
+    data: &[u8],
+    authenticated_data: &[u8],
+) -> Result<Box<[u8]>, EncryptionError> {
+    let mut nonce = GenericArray::<u8, A::NonceSize>::default();
+    rng.fill_bytes(&mut nonce);
+    let payload = Payload {
+        msg: data,
+        aad: authenticated_data,
+    };
+
+    let mut result = nonce.to_vec();
+    let enc_data = cipher
+        .encrypt(&nonce, payload)
+        .or(Err(EncryptionError::PlaintextTooLarge))?;
+
+    // Somewhat inefficient, but it doesn't seem that you can pass
+    // a mutable view of a vector to encrypt_in_place().
+    result.extend(enc_data);
+    Ok(result.into_boxed_slice())
+}
 
-    let mut okm = GenericArray::<u8, T>::default();
+fn aead_decrypt<A: Aead>(
+    cipher: &A,
+    ciphertext: &[u8],
+    authenticated_data: &[u8],
+) -> Result<Box<[u8]>, DecryptionError> {
+    let nonce_size = A::NonceSize::to_usize();
+    if ciphertext.len() < nonce_size {
+        return Err(DecryptionError::CiphertextTooShort);
+    }
 
-    let def_info = info.unwrap_or(&[]);
+    let nonce = GenericArray::from_slice(&ciphertext[..nonce_size]);
+    let payload = Payload {
+        msg: &ciphertext[nonce_size..],
+        aad: authenticated_data,
+    };
+    cipher
+        .decrypt(nonce, payload)
+        .map(|pt| pt.into_boxed_slice())
+        .or(Err(DecryptionError::AuthenticationFailed))
+}
 
-    // We can only get an error here if `T` is too large, and it's known at compile-time.
-    hk.expand(&def_info, &mut okm).unwrap();
+pub(crate) type NonceSize = <XChaCha20Poly1305 as AeadCore>::NonceSize;
+pub(crate) type TagSize = <XChaCha20Poly1305 as AeadCore>::TagSize;
 
-    okm
+/// XChaCha20-Poly1305 with the key derived from the key seed with HKDF,
+/// and a random nonce prepended to the ciphertext.
+///
+/// Also used by itself (without the algorithm identifier) in the crate's containers,
+/// like archives and the keystore.
+pub(crate) struct XChaCha20Poly1305Dem {
+    cipher: Secret<XChaCha20Poly1305>,
 }
 
-type NonceSize = <XChaCha20Poly1305 as AeadCore>::NonceSize;
+impl Dem for XChaCha20Poly1305Dem {
+    const NONCE_SIZE: usize = NonceSize::USIZE;
+    const TAG_SIZE: usize = TagSize::USIZE;
+
+    fn new(key_seed: &[u8]) -> Self {
+        Self {
+            cipher: secret(new_cipher(key_seed)),
+        }
+    }
+
+    fn encrypt(
+        &self,
+        rng: &mut (impl CryptoRng + RngCore),
+        data: &[u8],
+        authenticated_data: &[u8],
+    ) -> Result<Box<[u8]>, EncryptionError> {
+        aead_encrypt::<XChaCha20Poly1305>(&self.cipher, rng, data, authenticated_data)
+    }
+
+    fn decrypt(
+        &self,
+        ciphertext: impl AsRef<[u8]>,
+        authenticated_data: &[u8],
+    ) -> Result<Box<[u8]>, DecryptionError> {
+        aead_decrypt::<XChaCha20Poly1305>(&self.cipher, ciphertext.as_ref(), authenticated_data)
+    }
+}
 
-#[allow(clippy::upper_case_acronyms)]
-pub(crate) struct DEM {
-    cipher: XChaCha20Poly1305,
+/// AES-256-GCM with the key derived from the key seed with HKDF,
+/// and a random nonce prepended to the ciphertext.
+#[cfg(feature = "aes-gcm")]
+pub(crate) struct Aes256GcmDem {
+    cipher: Secret<Aes256Gcm>,
 }
 
-impl DEM {
-    pub fn new(key_seed: &[u8]) -> Self {
-        type KeySize = <XChaCha20Poly1305 as NewAead>::KeySize;
-        let key_bytes = kdf::<KeySize>(&key_seed, None, None);
-        let key = Key::from_slice(&key_bytes);
-        let cipher = XChaCha20Poly1305::new(key);
-        Self { cipher }
+#[cfg(feature = "aes-gcm")]
+impl Dem for Aes256GcmDem {
+    const NONCE_SIZE: usize = <Aes256Gcm as AeadCore>::NonceSize::USIZE;
+    const TAG_SIZE: usize = <Aes256Gcm as AeadCore>::TagSize::USIZE;
+
+    fn new(key_seed: &[u8]) -> Self {
+        Self {
+            cipher: secret(new_cipher(key_seed)),
+        }
     }
 
-    pub fn encrypt(
+    fn encrypt(
         &self,
+        rng: &mut (impl CryptoRng + RngCore),
         data: &[u8],
         authenticated_data: &[u8],
     ) -> Result<Box<[u8]>, EncryptionError> {
-        let mut nonce = GenericArray::<u8, NonceSize>::default();
-        OsRng.fill_bytes(&mut nonce);
-        let nonce = XNonce::from_slice(&nonce);
-        let payload = Payload {
-            msg: data,
-            aad: authenticated_data,
-        };
-
-        let mut result = nonce.to_vec();
-        let enc_data = self
-            .cipher
-            .encrypt(nonce, payload)
-            .or(Err(EncryptionError::PlaintextTooLarge))?;
-
-        // Somewhat inefficient, but it doesn't seem that you can pass
-        // a mutable view of a vector to encrypt_in_place().
-        result.extend(enc_data);
-        Ok(result.into_boxed_slice())
-    }
-
-    pub fn decrypt(
+        aead_encrypt::<Aes256Gcm>(&self.cipher, rng, data, authenticated_data)
+    }
+
+    fn decrypt(
         &self,
         ciphertext: impl AsRef<[u8]>,
         authenticated_data: &[u8],
     ) -> Result<Box<[u8]>, DecryptionError> {
-        let nonce_size = <NonceSize as Unsigned>::to_usize();
-        let buf_size = ciphertext.as_ref().len();
+        aead_decrypt::<Aes256Gcm>(&self.cipher, ciphertext.as_ref(), authenticated_data)
+    }
+}
 
-        if buf_size < nonce_size {
-            return Err(DecryptionError::CiphertextTooShort);
+/// Encrypts the data with the given algorithm,
+/// and prepends the identifier of the algorithm to the ciphertext.
+pub(crate) fn encrypt_with_header(
+    algorithm: DemAlgorithm,
+    rng: &mut (impl CryptoRng + RngCore),
+    key_seed: &[u8],
+    data: &[u8],
+    authenticated_data: &[u8],
+) -> Result<Box<[u8]>, EncryptionError> {
+    let ciphertext = match algorithm {
+        DemAlgorithm::XChaCha20Poly1305 => {
+            XChaCha20Poly1305Dem::new(key_seed).encrypt(rng, data, authenticated_data)
+        }
+        #[cfg(feature = "aes-gcm")]
+        DemAlgorithm::Aes256Gcm => {
+            Aes256GcmDem::new(key_seed).encrypt(rng, data, authenticated_data)
         }
+    }?;
+    let mut result = Vec::with_capacity(1 + ciphertext.len());
+    result.push(algorithm.id());
+    result.extend_from_slice(&ciphertext);
+    Ok(result.into_boxed_slice())
+}
+
+/// Decrypts a ciphertext produced by [`encrypt_with_header()`],
+/// selecting the algorithm by the identifier in front of it.
+///
+/// The ciphertexts created before the identifier was introduced
+/// (XChaCha20-Poly1305 without the header) are decrypted as well:
+/// if the header does not check out, the whole ciphertext is tried as a headerless one.
+/// Since the decryption is authenticated, a ciphertext cannot be successfully decrypted
+/// in both ways.
+///
+/// If `max_plaintext_size` is given, the size check is performed before any decryption.
+pub(crate) fn decrypt_with_header(
+    key_seed: &[u8],
+    ciphertext: &[u8],
+    authenticated_data: &[u8],
+    max_plaintext_size: Option<usize>,
+) -> Result<Box<[u8]>, DecryptionError> {
+    let max_plaintext_size = max_plaintext_size.unwrap_or(usize::MAX);
+    let legacy = |err| {
+        // Only report the error of the headerless decryption
+        // if the ciphertext could not be a headered one at all.
+        XChaCha20Poly1305Dem::new(key_seed)
+            .decrypt_limited(ciphertext, authenticated_data, max_plaintext_size)
+            .or(Err(err))
+    };
+
+    let id = match ciphertext.first() {
+        Some(id) => *id,
+        None => return Err(DecryptionError::CiphertextTooShort),
+    };
+    match DemAlgorithm::from_id(id) {
+        Some(algorithm) => decrypt_with_algorithm(
+            algorithm,
+            key_seed,
+            ciphertext,
+            authenticated_data,
+            max_plaintext_size,
+        )
+        .or_else(legacy),
+        None => legacy(DecryptionError::UnknownAlgorithm(id)),
+    }
+}
+
+/// Decrypts a ciphertext produced by [`encrypt_with_header()`] with the given algorithm,
+/// without the fallback to the headerless ciphertexts.
+pub(crate) fn decrypt_with_algorithm(
+    algorithm: DemAlgorithm,
+    key_seed: &[u8],
+    ciphertext: &[u8],
+    authenticated_data: &[u8],
+    max_plaintext_size: usize,
+) -> Result<Box<[u8]>, DecryptionError> {
+    let body = match ciphertext.split_first() {
+        Some((id, body)) if *id == algorithm.id() => body,
+        Some((id, _body)) => return Err(DecryptionError::UnknownAlgorithm(*id)),
+        None => return Err(DecryptionError::CiphertextTooShort),
+    };
+    match algorithm {
+        DemAlgorithm::XChaCha20Poly1305 => XChaCha20Poly1305Dem::new(key_seed).decrypt_limited(
+            body,
+            authenticated_data,
+            max_plaintext_size,
+        ),
+        #[cfg(feature = "aes-gcm")]
+        DemAlgorithm::Aes256Gcm => Aes256GcmDem::new(key_seed).decrypt_limited(
+            body,
+            authenticated_data,
+            max_plaintext_size,
+        ),
+    }
+}
 
-        let nonce = XNonce::from_slice(&ciphertext.as_ref()[..nonce_size]);
-        let payload = Payload {
-            msg: &ciphertext.as_ref()[nonce_size..],
-            aad: authenticated_data,
-        };
-        self.cipher
-            .decrypt(nonce, payload)
-            .map(|pt| pt.into_boxed_slice())
-            .or(Err(DecryptionError::AuthenticationFailed))
+/// Returns the size of the plaintext contained in a ciphertext produced by
+/// [`encrypt_with_header()`], without attempting to decrypt it.
+///
+/// For the headerless ciphertexts the result may be off by one,
+/// so it should only be used for the preliminary checks.
+pub(crate) fn plaintext_size_with_header(ciphertext: &[u8]) -> Result<usize, DecryptionError> {
+    let body_size = ciphertext.len().saturating_sub(1);
+    match ciphertext.first().copied().and_then(DemAlgorithm::from_id) {
+        Some(DemAlgorithm::XChaCha20Poly1305) => XChaCha20Poly1305Dem::plaintext_size(body_size),
+        #[cfg(feature = "aes-gcm")]
+        Some(DemAlgorithm::Aes256Gcm) => Aes256GcmDem::plaintext_size(body_size),
+        None => XChaCha20Poly1305Dem::plaintext_size(ciphertext.len()),
     }
 }
 
 #[cfg(test)]
 mod tests {
 
-    use super::kdf;
-    use crate::curve::CurvePoint;
-    use crate::SerializableToArray;
-    use typenum::U32;
+    use super::{
+        ciphertext_overhead, decrypt_with_header, encrypt_with_header, encrypted_size,
+        plaintext_size_with_header, DecryptionError, Dem, DemAlgorithm, XChaCha20Poly1305Dem,
+    };
+    use rand_core::OsRng;
+
+    #[test]
+    fn test_decrypt_limited() {
+        let dem = XChaCha20Poly1305Dem::new(b"key seed");
+        let plaintext = b"peace at dawn";
+        let aad = b"authenticated data";
+        let ciphertext = dem.encrypt(&mut OsRng, plaintext, aad).unwrap();
+
+        assert_eq!(
+            XChaCha20Poly1305Dem::plaintext_size(ciphertext.len()),
+            Ok(plaintext.len())
+        );
+
+        let decrypted = dem
+            .decrypt_limited(&ciphertext, aad, plaintext.len())
+            .unwrap();
+        assert_eq!(&decrypted as &[u8], plaintext);
+
+        assert_eq!(
+            dem.decrypt_limited(&ciphertext, aad, plaintext.len() - 1),
+            Err(DecryptionError::PlaintextTooLarge)
+        );
+        assert_eq!(
+            dem.decrypt_limited(&ciphertext[..10], aad, plaintext.len()),
+            Err(DecryptionError::CiphertextTooShort)
+        );
+    }
 
     #[test]
-    fn test_kdf() {
-        let p1 = CurvePoint::generator();
-        let salt = b"abcdefg";
-        let info = b"sdasdasd";
-        let key = kdf::<U32>(&p1.to_array(), Some(&salt[..]), Some(&info[..]));
-        let key_same = kdf::<U32>(&p1.to_array(), Some(&salt[..]), Some(&info[..]));
-        assert_eq!(key, key_same);
-
-        let key_diff = kdf::<U32>(&p1.to_array(), None, Some(&info[..]));
-        assert_ne!(key, key_diff);
+    fn test_algorithm_header() {
+        let key_seed = b"key seed";
+        let plaintext = b"peace at dawn";
+        let aad = b"authenticated data";
+
+        let algorithms = [
+            DemAlgorithm::XChaCha20Poly1305,
+            #[cfg(feature = "aes-gcm")]
+            DemAlgorithm::Aes256Gcm,
+        ];
+        for algorithm in algorithms.iter() {
+            assert_eq!(DemAlgorithm::from_id(algorithm.id()), Some(*algorithm));
+            let ciphertext =
+                encrypt_with_header(*algorithm, &mut OsRng, key_seed, plaintext, aad).unwrap();
+            assert_eq!(ciphertext[0], algorithm.id());
+            assert_eq!(
+                ciphertext.len(),
+                plaintext.len() + algorithm.ciphertext_overhead()
+            );
+            assert_eq!(plaintext_size_with_header(&ciphertext), Ok(plaintext.len()));
+            let decrypted = decrypt_with_header(key_seed, &ciphertext, aad, None).unwrap();
+            assert_eq!(&decrypted as &[u8], plaintext);
+            assert_eq!(
+                decrypt_with_header(key_seed, &ciphertext, aad, Some(plaintext.len() - 1)),
+                Err(DecryptionError::PlaintextTooLarge)
+            );
+        }
+
+        assert_eq!(ciphertext_overhead(), 41);
+        assert_eq!(encrypted_size(plaintext.len()), plaintext.len() + 41);
+
+        // Headerless ciphertexts are still decrypted.
+        let legacy = XChaCha20Poly1305Dem::new(key_seed)
+            .encrypt(&mut OsRng, plaintext, aad)
+            .unwrap();
+        let decrypted = decrypt_with_header(key_seed, &legacy, aad, None).unwrap();
+        assert_eq!(&decrypted as &[u8], plaintext);
+
+        let mut unknown = encrypt_with_header(
+            DemAlgorithm::XChaCha20Poly1305,
+            &mut OsRng,
+            key_seed,
+            plaintext,
+            aad,
+        )
+        .unwrap();
+        unknown[0] = 0xff;
+        assert_eq!(
+            decrypt_with_header(key_seed, &unknown, aad, None),
+            Err(DecryptionError::UnknownAlgorithm(0xff))
+        );
     }
 }
@@ -0,0 +1,210 @@
+//! Redacted, human-readable summaries of the public objects, intended for bug reports.
+//!
+//! Points are shown as prefixes of their compressed representation,
+//! scalars and secret values are only shown as truncated hashes,
+//! so the output can be shared between implementations without leaking secrets.
+
+use alloc::string::String;
+use core::fmt::Write;
+
+use crate::capsule::Capsule;
+use crate::capsule_frag::{CapsuleFrag, VerifiedCapsuleFrag};
+use crate::curve::{CurvePoint, CurveScalar};
+use crate::hashing::BytesDigest;
+use crate::key_frag::{KeyFrag, VerifiedKeyFrag};
+use crate::keys::{PublicKey, SecretKey, SecretKeyFactory, Signature, Signer};
+use crate::traits::{RepresentableAsArray, SerializableToArray};
+
+// The number of bytes shown for point prefixes and hashes.
+const PREFIX_SIZE: usize = 4;
+
+fn write_hex(out: &mut String, bytes: &[u8]) {
+    for byte in bytes {
+        // Writing to a `String` cannot fail.
+        write!(out, "{:02x}", byte).unwrap();
+    }
+}
+
+fn point_prefix(point: &CurvePoint) -> String {
+    let mut out = String::new();
+    write_hex(&mut out, &point.to_array()[..PREFIX_SIZE]);
+    out.push_str("..");
+    out
+}
+
+fn bytes_hash(bytes: &[u8]) -> String {
+    let digest = BytesDigest::new_with_dst(b"DIAGNOSTICS")
+        .chain_bytes(bytes)
+        .finalize();
+    let mut out = String::from("#");
+    write_hex(&mut out, &digest[..PREFIX_SIZE]);
+    out
+}
+
+fn scalar_hash(scalar: &CurveScalar) -> String {
+    bytes_hash(&scalar.to_array())
+}
+
+fn header<T: RepresentableAsArray>(name: &str) -> String {
+    let mut out = String::new();
+    write!(
+        out,
+        "{} {{ version: {}, size: {}",
+        name,
+        env!("CARGO_PKG_VERSION"),
+        <T::Size as typenum::Unsigned>::to_usize()
+    )
+    .unwrap();
+    out
+}
+
+fn field(out: &mut String, name: &str, value: &str) {
+    write!(out, ", {}: {}", name, value).unwrap();
+}
+
+fn finish(mut out: String) -> String {
+    out.push_str(" }");
+    out
+}
+
+impl SecretKey {
+    /// Returns a redacted summary of the object (only the public key prefix is shown).
+    pub fn dump(&self) -> String {
+        let mut out = header::<Self>("SecretKey");
+        field(
+            &mut out,
+            "public_key",
+            &point_prefix(&PublicKey::from_secret_key(self).to_point()),
+        );
+        finish(out)
+    }
+}
+
+impl SecretKeyFactory {
+    /// Returns a redacted summary of the object (the seed is not shown).
+    pub fn dump(&self) -> String {
+        finish(header::<Self>("SecretKeyFactory"))
+    }
+}
+
+impl Signer {
+    /// Returns a redacted summary of the object (only the verifying key prefix is shown).
+    pub fn dump(&self) -> String {
+        let mut out = String::new();
+        write!(out, "Signer {{ version: {}", env!("CARGO_PKG_VERSION")).unwrap();
+        field(
+            &mut out,
+            "verifying_key",
+            &point_prefix(&self.verifying_key().to_point()),
+        );
+        finish(out)
+    }
+}
+
+impl PublicKey {
+    /// Returns a redacted summary of the object.
+    pub fn dump(&self) -> String {
+        let mut out = header::<Self>("PublicKey");
+        field(&mut out, "point", &point_prefix(&self.to_point()));
+        finish(out)
+    }
+}
+
+impl Signature {
+    /// Returns a redacted summary of the object.
+    pub fn dump(&self) -> String {
+        let mut out = header::<Self>("Signature");
+        field(&mut out, "hash", &bytes_hash(&self.to_array()));
+        finish(out)
+    }
+}
+
+impl Capsule {
+    /// Returns a redacted summary of the object.
+    pub fn dump(&self) -> String {
+        let mut out = header::<Self>("Capsule");
+        field(&mut out, "e", &point_prefix(&self.point_e));
+        field(&mut out, "v", &point_prefix(&self.point_v));
+        field(&mut out, "s", &scalar_hash(&self.signature));
+        finish(out)
+    }
+}
+
+impl KeyFrag {
+    /// Returns a redacted summary of the object.
+    pub fn dump(&self) -> String {
+        let mut out = header::<Self>("KeyFrag");
+        field(&mut out, "id", &bytes_hash(&self.id.to_array()));
+        field(&mut out, "key", &scalar_hash(&self.key));
+        field(&mut out, "precursor", &point_prefix(&self.precursor));
+        field(
+            &mut out,
+            "commitment",
+            &point_prefix(&self.proof.commitment),
+        );
+        field(&mut out, "proof", &bytes_hash(&self.proof.to_array()));
+        finish(out)
+    }
+}
+
+impl VerifiedKeyFrag {
+    /// Returns a redacted summary of the object.
+    pub fn dump(&self) -> String {
+        let mut out = String::from("Verified");
+        out.push_str(&self.kfrag.dump());
+        out
+    }
+}
+
+impl CapsuleFrag {
+    /// Returns a redacted summary of the object.
+    pub fn dump(&self) -> String {
+        let mut out = header::<Self>("CapsuleFrag");
+        field(&mut out, "e1", &point_prefix(&self.point_e1));
+        field(&mut out, "v1", &point_prefix(&self.point_v1));
+        field(&mut out, "kfrag_id", &bytes_hash(&self.kfrag_id.to_array()));
+        field(&mut out, "precursor", &point_prefix(&self.precursor));
+        field(&mut out, "proof", &bytes_hash(&self.proof.to_array()));
+        finish(out)
+    }
+}
+
+impl VerifiedCapsuleFrag {
+    /// Returns a redacted summary of the object.
+    pub fn dump(&self) -> String {
+        let mut out = String::from("Verified");
+        out.push_str(&self.cfrag.dump());
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use alloc::string::String;
+
+    use crate::{encapsulate, PublicKey, SecretKey, SerializableToArray};
+
+    fn to_hex(bytes: &[u8]) -> String {
+        let mut out = String::new();
+        super::write_hex(&mut out, bytes);
+        out
+    }
+
+    #[test]
+    fn test_redacted() {
+        let sk = SecretKey::random();
+        let pk = PublicKey::from_secret_key(&sk);
+        let (capsule, _key_seed) = encapsulate(&pk);
+
+        let sk_dump = sk.dump();
+        assert!(sk_dump.starts_with("SecretKey { version: "));
+        assert!(!sk_dump.contains(&to_hex(&sk.to_array())[..8]));
+        assert!(sk_dump.contains(&to_hex(&pk.to_array())[..8]));
+
+        let capsule_dump = capsule.dump();
+        assert!(capsule_dump.contains("size: 98"));
+        assert!(capsule_dump.contains(&to_hex(&capsule.point_e.to_array())[..8]));
+        assert!(!capsule_dump.contains(&to_hex(&capsule.signature.to_array())[..8]));
+    }
+}
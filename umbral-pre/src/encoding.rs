@@ -0,0 +1,200 @@
+//! Text encodings (hex and Base64) of the byte representations of objects.
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt;
+use core::str::FromStr;
+
+use crate::traits::{DeserializableFromArray, DeserializationError, SerializableToArray};
+#[cfg(feature = "threshold")]
+use crate::PartialDecryption;
+#[cfg(any(feature = "role-alice", feature = "role-bob"))]
+use crate::ReceptionKey;
+use crate::{Capsule, CapsuleFrag, Fingerprint, KeyFrag, PublicKey};
+
+const HEX_DIGITS: &[u8; 16] = b"0123456789abcdef";
+
+const BASE64_ALPHABET: &[u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Encodes the bytes as lowercase hex (without a prefix).
+pub(crate) fn encode_hex(bytes: &[u8]) -> String {
+    let mut result = String::with_capacity(bytes.len() * 2);
+    for byte in bytes {
+        result.push(HEX_DIGITS[(byte >> 4) as usize] as char);
+        result.push(HEX_DIGITS[(byte & 0xf) as usize] as char);
+    }
+    result
+}
+
+fn hex_digit(c: u8) -> Option<u8> {
+    match c {
+        b'0'..=b'9' => Some(c - b'0'),
+        b'a'..=b'f' => Some(c - b'a' + 10),
+        b'A'..=b'F' => Some(c - b'A' + 10),
+        _ => None,
+    }
+}
+
+/// Decodes a hex string (without a prefix) in any case.
+pub(crate) fn decode_hex(s: &str) -> Option<Vec<u8>> {
+    let pairs = s.as_bytes().chunks_exact(2);
+    if !pairs.remainder().is_empty() {
+        return None;
+    }
+    pairs
+        .map(|pair| Some(hex_digit(pair[0])? << 4 | hex_digit(pair[1])?))
+        .collect()
+}
+
+/// Encodes the bytes as standard Base64 (RFC 4648, with padding).
+pub(crate) fn encode_base64(bytes: &[u8]) -> String {
+    let chunks = bytes.chunks(3);
+    let mut result = String::with_capacity(chunks.len() * 4);
+    for chunk in chunks {
+        let mut buf = [0u8; 3];
+        buf[..chunk.len()].copy_from_slice(chunk);
+        let n = (buf[0] as u32) << 16 | (buf[1] as u32) << 8 | buf[2] as u32;
+        for i in 0..4 {
+            if i <= chunk.len() {
+                result.push(BASE64_ALPHABET[(n >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                result.push('=');
+            }
+        }
+    }
+    result
+}
+
+fn base64_digit(c: u8) -> Option<u32> {
+    match c {
+        b'A'..=b'Z' => Some((c - b'A') as u32),
+        b'a'..=b'z' => Some((c - b'a' + 26) as u32),
+        b'0'..=b'9' => Some((c - b'0' + 52) as u32),
+        b'+' => Some(62),
+        b'/' => Some(63),
+        _ => None,
+    }
+}
+
+/// Decodes standard Base64 (RFC 4648, with padding).
+/// Only the canonical encodings (with the unused bits set to zero) are accepted.
+pub(crate) fn decode_base64(s: &str) -> Option<Vec<u8>> {
+    let chunks = s.as_bytes().chunks_exact(4);
+    if !chunks.remainder().is_empty() {
+        return None;
+    }
+    let num_chunks = chunks.len();
+    let mut result = Vec::with_capacity(num_chunks * 3);
+    for (i, chunk) in chunks.enumerate() {
+        let padding = if i + 1 == num_chunks {
+            chunk.iter().rev().take_while(|c| **c == b'=').count()
+        } else {
+            0
+        };
+        if padding > 2 {
+            return None;
+        }
+        let mut n = 0u32;
+        for c in &chunk[..4 - padding] {
+            n = n << 6 | base64_digit(*c)?;
+        }
+        n <<= 6 * padding as u32;
+        let bytes = [(n >> 16) as u8, (n >> 8) as u8, n as u8];
+        let size = 3 - padding;
+        if bytes[size..].iter().any(|byte| *byte != 0) {
+            return None;
+        }
+        result.extend_from_slice(&bytes[..size]);
+    }
+    Some(result)
+}
+
+// Objects are displayed as the hex of their byte representation,
+// and parsed back from it (in any case, with an optional `0x` prefix).
+macro_rules! impl_hex_display {
+    ($($type:ty),* $(,)?) => {
+        $(
+            impl fmt::Display for $type {
+                fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                    f.write_str(&encode_hex(&self.to_array()))
+                }
+            }
+
+            impl FromStr for $type {
+                type Err = DeserializationError;
+
+                fn from_str(s: &str) -> Result<Self, Self::Err> {
+                    let s = s.strip_prefix("0x").unwrap_or(s);
+                    let bytes = decode_hex(s).ok_or(DeserializationError::InvalidEncoding)?;
+                    Self::from_bytes(&bytes)
+                }
+            }
+        )*
+    };
+}
+
+impl_hex_display!(PublicKey, Capsule, KeyFrag, CapsuleFrag, Fingerprint);
+#[cfg(feature = "threshold")]
+impl_hex_display!(PartialDecryption);
+#[cfg(any(feature = "role-alice", feature = "role-bob"))]
+impl_hex_display!(ReceptionKey);
+
+#[cfg(test)]
+mod tests {
+
+    use alloc::format;
+    use alloc::string::ToString;
+
+    use super::{decode_base64, encode_base64};
+    use crate::{
+        encapsulate, DeserializableFromArray, DeserializationError, PublicKey, SecretKey,
+        SerializableToArray,
+    };
+
+    #[test]
+    fn test_text_encodings() {
+        for (bytes, encoded) in [
+            (&b""[..], ""),
+            (b"f", "Zg=="),
+            (b"fo", "Zm8="),
+            (b"foo", "Zm9v"),
+            (b"foob", "Zm9vYg=="),
+        ]
+        .iter()
+        {
+            assert_eq!(encode_base64(bytes), *encoded);
+            assert_eq!(decode_base64(encoded).as_deref(), Some(*bytes));
+        }
+        for invalid in ["Zg=", "Zh==", "Z===", "Zg==Zg==", "Zm9v!"].iter() {
+            assert_eq!(decode_base64(invalid), None);
+        }
+
+        let sk = SecretKey::random();
+        let pk = PublicKey::from_secret_key(&sk);
+        let (capsule, _key_seed) = encapsulate(&pk);
+
+        let pk_str = pk.to_string();
+        assert_eq!(pk_str.len(), 66);
+        assert_eq!(pk_str.parse::<PublicKey>(), Ok(pk));
+        assert_eq!(
+            ["0x", &pk_str.to_uppercase()].concat().parse::<PublicKey>(),
+            Ok(pk)
+        );
+        assert_eq!(
+            "zz".parse::<PublicKey>(),
+            Err(DeserializationError::InvalidEncoding)
+        );
+
+        assert_eq!(
+            capsule.to_base64().parse::<PublicKey>(),
+            Err(DeserializationError::InvalidEncoding)
+        );
+        assert_eq!(
+            crate::Capsule::from_base64(&capsule.to_base64()),
+            Ok(capsule)
+        );
+
+        assert_eq!(format!("{:?}", sk), "SecretKey(<redacted>)");
+    }
+}
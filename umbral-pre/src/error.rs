@@ -0,0 +1,472 @@
+//! A unified error type, for applications that prefer a single type in their `Result`s.
+//!
+//! All the error types of the crate implement [`Display`](core::fmt::Display),
+//! and, if the `std` feature is enabled, [`std::error::Error`]
+//! (with errors wrapping other errors returning them as the source).
+
+use core::fmt;
+
+#[cfg(feature = "dem")]
+use crate::archive::ArchiveError;
+#[cfg(feature = "role-ursula")]
+use crate::bulk::BulkReencryptionError;
+use crate::capsule::{CapsuleFormatError, OpenReencryptedError};
+use crate::capsule_frag::CapsuleFragVerificationError;
+use crate::compat::js::JsCompatError;
+#[cfg(feature = "cose")]
+use crate::cose::CoseError;
+#[cfg(feature = "dem")]
+use crate::dem::{DecryptionError, EncryptionError};
+#[cfg(all(feature = "dem", feature = "role-bob"))]
+use crate::flow::FlowError;
+use crate::key_frag::KeyFragVerificationError;
+use crate::keys::{SecretKeyFactoryError, SignerError};
+#[cfg(feature = "keystore")]
+use crate::keystore::KeystoreError;
+use crate::metadata::CanonicalizationError;
+#[cfg(feature = "dem")]
+use crate::migration::MigrationError;
+#[cfg(feature = "bip39")]
+use crate::mnemonic::MnemonicError;
+#[cfg(feature = "padding")]
+use crate::padding::PaddingError;
+#[cfg(feature = "pem")]
+use crate::pem::KeyFormatError;
+#[cfg(feature = "policy")]
+use crate::policy::PolicyError;
+#[cfg(feature = "dem")]
+use crate::pre::ReencryptionError;
+use crate::pre::{DeriveKeyError, RefreshError};
+#[cfg(feature = "proxy")]
+use crate::proxy::{ReencryptionMessageError, ReencryptionServiceError};
+#[cfg(feature = "qr")]
+use crate::qr::ChunkError;
+#[cfg(any(feature = "role-ursula", feature = "role-bob"))]
+use crate::receipt::ReencryptionReceiptError;
+#[cfg(any(feature = "role-alice", feature = "role-bob"))]
+use crate::reception::ReceptionKeyVerificationError;
+#[cfg(feature = "role-alice")]
+use crate::signing_policy::SigningPolicyError;
+#[cfg(feature = "threshold")]
+use crate::threshold::{ShareGenerationError, ThresholdDecryptionError};
+use crate::traits::DeserializationError;
+#[cfg(feature = "uri")]
+use crate::uri::UriError;
+
+/// Any error returned by this crate.
+#[derive(Debug, PartialEq)]
+pub enum Error {
+    /// See [`DeserializationError`].
+    Deserialization(DeserializationError),
+    /// See [`CapsuleFormatError`].
+    CapsuleFormat(CapsuleFormatError),
+    /// See [`OpenReencryptedError`].
+    OpenReencrypted(OpenReencryptedError),
+    /// See [`KeyFragVerificationError`].
+    KeyFragVerification(KeyFragVerificationError),
+    /// See [`CapsuleFragVerificationError`].
+    CapsuleFragVerification(CapsuleFragVerificationError),
+    /// See [`SecretKeyFactoryError`].
+    SecretKeyFactory(SecretKeyFactoryError),
+    /// See [`DeriveKeyError`].
+    DeriveKey(DeriveKeyError),
+    /// See [`RefreshError`].
+    Refresh(RefreshError),
+    /// See [`PaddingError`].
+    #[cfg(feature = "padding")]
+    Padding(PaddingError),
+    /// See [`UriError`].
+    #[cfg(feature = "uri")]
+    Uri(UriError),
+    /// See [`ChunkError`].
+    #[cfg(feature = "qr")]
+    Chunk(ChunkError),
+    /// See [`JsCompatError`].
+    JsCompat(JsCompatError),
+    /// See [`CanonicalizationError`].
+    Canonicalization(CanonicalizationError),
+    /// See [`SigningPolicyError`].
+    #[cfg(feature = "role-alice")]
+    SigningPolicy(SigningPolicyError),
+    /// See [`SignerError`].
+    Signer(SignerError),
+    /// See [`PolicyError`].
+    #[cfg(feature = "policy")]
+    Policy(PolicyError),
+    /// See [`ReceptionKeyVerificationError`].
+    #[cfg(any(feature = "role-alice", feature = "role-bob"))]
+    ReceptionKeyVerification(ReceptionKeyVerificationError),
+    /// See [`ReencryptionReceiptError`].
+    #[cfg(any(feature = "role-ursula", feature = "role-bob"))]
+    ReencryptionReceipt(ReencryptionReceiptError),
+    /// See [`ShareGenerationError`].
+    #[cfg(feature = "threshold")]
+    ShareGeneration(ShareGenerationError),
+    /// See [`ThresholdDecryptionError`].
+    #[cfg(feature = "threshold")]
+    ThresholdDecryption(ThresholdDecryptionError),
+    /// See [`EncryptionError`].
+    #[cfg(feature = "dem")]
+    Encryption(EncryptionError),
+    /// See [`DecryptionError`].
+    #[cfg(feature = "dem")]
+    Decryption(DecryptionError),
+    /// See [`ReencryptionError`].
+    #[cfg(feature = "dem")]
+    Reencryption(ReencryptionError),
+    /// See [`FlowError`].
+    #[cfg(all(feature = "dem", feature = "role-bob"))]
+    Flow(FlowError),
+    /// See [`ArchiveError`].
+    #[cfg(feature = "dem")]
+    Archive(ArchiveError),
+    /// See [`MigrationError`].
+    #[cfg(feature = "dem")]
+    Migration(MigrationError),
+    /// See [`KeystoreError`].
+    #[cfg(feature = "keystore")]
+    Keystore(KeystoreError),
+    /// See [`MnemonicError`].
+    #[cfg(feature = "bip39")]
+    Mnemonic(MnemonicError),
+    /// See [`ReencryptionServiceError`].
+    #[cfg(feature = "proxy")]
+    ReencryptionService(ReencryptionServiceError),
+    /// See [`ReencryptionMessageError`].
+    #[cfg(feature = "proxy")]
+    ReencryptionMessage(ReencryptionMessageError),
+    /// See [`CoseError`].
+    #[cfg(feature = "cose")]
+    Cose(CoseError),
+    /// See [`KeyFormatError`].
+    #[cfg(feature = "pem")]
+    KeyFormat(KeyFormatError),
+    /// See [`BulkReencryptionError`].
+    #[cfg(feature = "role-ursula")]
+    BulkReencryption(BulkReencryptionError),
+}
+
+macro_rules! impl_from {
+    ($variant:ident, $error:ty) => {
+        impl From<$error> for Error {
+            fn from(err: $error) -> Self {
+                Self::$variant(err)
+            }
+        }
+    };
+}
+
+impl_from!(Deserialization, DeserializationError);
+impl_from!(CapsuleFormat, CapsuleFormatError);
+impl_from!(OpenReencrypted, OpenReencryptedError);
+impl_from!(KeyFragVerification, KeyFragVerificationError);
+impl_from!(CapsuleFragVerification, CapsuleFragVerificationError);
+impl_from!(SecretKeyFactory, SecretKeyFactoryError);
+impl_from!(DeriveKey, DeriveKeyError);
+impl_from!(Refresh, RefreshError);
+#[cfg(feature = "padding")]
+impl_from!(Padding, PaddingError);
+#[cfg(feature = "uri")]
+impl_from!(Uri, UriError);
+#[cfg(feature = "qr")]
+impl_from!(Chunk, ChunkError);
+impl_from!(JsCompat, JsCompatError);
+impl_from!(Canonicalization, CanonicalizationError);
+#[cfg(feature = "role-alice")]
+impl_from!(SigningPolicy, SigningPolicyError);
+impl_from!(Signer, SignerError);
+#[cfg(feature = "policy")]
+impl_from!(Policy, PolicyError);
+#[cfg(any(feature = "role-alice", feature = "role-bob"))]
+impl_from!(ReceptionKeyVerification, ReceptionKeyVerificationError);
+#[cfg(any(feature = "role-ursula", feature = "role-bob"))]
+impl_from!(ReencryptionReceipt, ReencryptionReceiptError);
+#[cfg(feature = "threshold")]
+impl_from!(ShareGeneration, ShareGenerationError);
+#[cfg(feature = "threshold")]
+impl_from!(ThresholdDecryption, ThresholdDecryptionError);
+#[cfg(feature = "dem")]
+impl_from!(Encryption, EncryptionError);
+#[cfg(feature = "dem")]
+impl_from!(Decryption, DecryptionError);
+#[cfg(feature = "dem")]
+impl_from!(Reencryption, ReencryptionError);
+#[cfg(all(feature = "dem", feature = "role-bob"))]
+impl_from!(Flow, FlowError);
+#[cfg(feature = "dem")]
+impl_from!(Archive, ArchiveError);
+#[cfg(feature = "dem")]
+impl_from!(Migration, MigrationError);
+#[cfg(feature = "keystore")]
+impl_from!(Keystore, KeystoreError);
+#[cfg(feature = "bip39")]
+impl_from!(Mnemonic, MnemonicError);
+#[cfg(feature = "proxy")]
+impl_from!(ReencryptionService, ReencryptionServiceError);
+#[cfg(feature = "proxy")]
+impl_from!(ReencryptionMessage, ReencryptionMessageError);
+#[cfg(feature = "cose")]
+impl_from!(Cose, CoseError);
+#[cfg(feature = "pem")]
+impl_from!(KeyFormat, KeyFormatError);
+#[cfg(feature = "role-ursula")]
+impl_from!(BulkReencryption, BulkReencryptionError);
+
+// The unified error is transparent: it displays as the wrapped error,
+// and has the same source.
+macro_rules! delegate {
+    ($self:ident, $err:ident => $expr:expr) => {
+        match $self {
+            Self::Deserialization($err) => $expr,
+            Self::CapsuleFormat($err) => $expr,
+            Self::OpenReencrypted($err) => $expr,
+            Self::KeyFragVerification($err) => $expr,
+            Self::CapsuleFragVerification($err) => $expr,
+            Self::SecretKeyFactory($err) => $expr,
+            Self::DeriveKey($err) => $expr,
+            Self::Refresh($err) => $expr,
+            #[cfg(feature = "padding")]
+            Self::Padding($err) => $expr,
+            #[cfg(feature = "uri")]
+            Self::Uri($err) => $expr,
+            #[cfg(feature = "qr")]
+            Self::Chunk($err) => $expr,
+            Self::JsCompat($err) => $expr,
+            Self::Canonicalization($err) => $expr,
+            #[cfg(feature = "role-alice")]
+            Self::SigningPolicy($err) => $expr,
+            Self::Signer($err) => $expr,
+            #[cfg(feature = "policy")]
+            Self::Policy($err) => $expr,
+            #[cfg(any(feature = "role-alice", feature = "role-bob"))]
+            Self::ReceptionKeyVerification($err) => $expr,
+            #[cfg(any(feature = "role-ursula", feature = "role-bob"))]
+            Self::ReencryptionReceipt($err) => $expr,
+            #[cfg(feature = "threshold")]
+            Self::ShareGeneration($err) => $expr,
+            #[cfg(feature = "threshold")]
+            Self::ThresholdDecryption($err) => $expr,
+            #[cfg(feature = "dem")]
+            Self::Encryption($err) => $expr,
+            #[cfg(feature = "dem")]
+            Self::Decryption($err) => $expr,
+            #[cfg(feature = "dem")]
+            Self::Reencryption($err) => $expr,
+            #[cfg(all(feature = "dem", feature = "role-bob"))]
+            Self::Flow($err) => $expr,
+            #[cfg(feature = "dem")]
+            Self::Archive($err) => $expr,
+            #[cfg(feature = "dem")]
+            Self::Migration($err) => $expr,
+            #[cfg(feature = "keystore")]
+            Self::Keystore($err) => $expr,
+            #[cfg(feature = "bip39")]
+            Self::Mnemonic($err) => $expr,
+            #[cfg(feature = "proxy")]
+            Self::ReencryptionService($err) => $expr,
+            #[cfg(feature = "proxy")]
+            Self::ReencryptionMessage($err) => $expr,
+            #[cfg(feature = "cose")]
+            Self::Cose($err) => $expr,
+            #[cfg(feature = "pem")]
+            Self::KeyFormat($err) => $expr,
+            #[cfg(feature = "role-ursula")]
+            Self::BulkReencryption($err) => $expr,
+        }
+    };
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        delegate!(self, err => fmt::Display::fmt(err, f))
+    }
+}
+
+#[cfg(feature = "std")]
+mod std_impls {
+    use std::error::Error as StdError;
+
+    use super::*;
+    use crate::capsule_frag::ReencryptionCheck;
+
+    impl StdError for Error {
+        fn source(&self) -> Option<&(dyn StdError + 'static)> {
+            delegate!(self, err => err.source())
+        }
+    }
+
+    impl StdError for DeserializationError {}
+    impl StdError for CapsuleFormatError {}
+    impl StdError for OpenReencryptedError {}
+    impl StdError for KeyFragVerificationError {}
+    impl StdError for CanonicalizationError {}
+    #[cfg(feature = "role-alice")]
+    impl StdError for SigningPolicyError {}
+    impl StdError for ReencryptionCheck {}
+    impl StdError for SecretKeyFactoryError {}
+    impl StdError for DeriveKeyError {}
+    #[cfg(feature = "padding")]
+    impl StdError for PaddingError {}
+    #[cfg(feature = "uri")]
+    impl StdError for UriError {}
+    #[cfg(feature = "qr")]
+    impl StdError for ChunkError {}
+    #[cfg(any(feature = "role-alice", feature = "role-bob"))]
+    impl StdError for ReceptionKeyVerificationError {}
+    #[cfg(any(feature = "role-ursula", feature = "role-bob"))]
+    impl StdError for ReencryptionReceiptError {}
+    #[cfg(feature = "dem")]
+    impl StdError for EncryptionError {}
+    #[cfg(feature = "dem")]
+    impl StdError for DecryptionError {}
+    #[cfg(feature = "dem")]
+    impl StdError for ArchiveError {}
+    #[cfg(feature = "dem")]
+    impl StdError for MigrationError {}
+    #[cfg(feature = "keystore")]
+    impl StdError for KeystoreError {}
+    #[cfg(feature = "bip39")]
+    impl StdError for MnemonicError {}
+    #[cfg(feature = "pem")]
+    impl StdError for KeyFormatError {}
+    #[cfg(feature = "role-ursula")]
+    impl StdError for BulkReencryptionError {}
+
+    #[cfg(feature = "dem")]
+    impl StdError for ReencryptionError {
+        fn source(&self) -> Option<&(dyn StdError + 'static)> {
+            match self {
+                Self::OnOpen(err) => Some(err),
+                Self::OnDecryption(err) => Some(err),
+            }
+        }
+    }
+
+    impl StdError for CapsuleFragVerificationError {
+        fn source(&self) -> Option<&(dyn StdError + 'static)> {
+            match self {
+                Self::MalformedMetadata(err) => Some(err),
+                _ => None,
+            }
+        }
+    }
+
+    impl StdError for SignerError {
+        fn source(&self) -> Option<&(dyn StdError + 'static)> {
+            match self {
+                #[cfg(feature = "role-alice")]
+                Self::Policy(err) => Some(err),
+                Self::External(_) => None,
+            }
+        }
+    }
+
+    #[cfg(feature = "policy")]
+    impl StdError for PolicyError {
+        fn source(&self) -> Option<&(dyn StdError + 'static)> {
+            match self {
+                Self::KeyFragVerification(err) => Some(err),
+                #[cfg(feature = "dem")]
+                Self::Encryption(err) => Some(err),
+                #[cfg(feature = "dem")]
+                Self::Decryption(err) => Some(err),
+                _ => None,
+            }
+        }
+    }
+
+    impl StdError for RefreshError {
+        fn source(&self) -> Option<&(dyn StdError + 'static)> {
+            match self {
+                Self::Signing(err) => Some(err),
+                _ => None,
+            }
+        }
+    }
+
+    #[cfg(feature = "threshold")]
+    impl StdError for ShareGenerationError {}
+
+    #[cfg(feature = "threshold")]
+    impl StdError for ThresholdDecryptionError {
+        fn source(&self) -> Option<&(dyn StdError + 'static)> {
+            match self {
+                #[cfg(feature = "dem")]
+                Self::OnDecryption(err) => Some(err),
+                _ => None,
+            }
+        }
+    }
+
+    #[cfg(feature = "proxy")]
+    impl StdError for ReencryptionMessageError {}
+
+    #[cfg(feature = "proxy")]
+    impl StdError for ReencryptionServiceError {
+        fn source(&self) -> Option<&(dyn StdError + 'static)> {
+            match self {
+                Self::InvalidCapsuleFrag(err) => Some(err),
+                _ => None,
+            }
+        }
+    }
+
+    #[cfg(feature = "cose")]
+    impl StdError for CoseError {
+        fn source(&self) -> Option<&(dyn StdError + 'static)> {
+            match self {
+                Self::Capsule(err) => Some(err),
+                _ => None,
+            }
+        }
+    }
+
+    impl StdError for JsCompatError {
+        fn source(&self) -> Option<&(dyn StdError + 'static)> {
+            match self {
+                Self::InvalidObject(err) => Some(err),
+                _ => None,
+            }
+        }
+    }
+
+    #[cfg(all(feature = "dem", feature = "role-bob"))]
+    impl StdError for FlowError {
+        fn source(&self) -> Option<&(dyn StdError + 'static)> {
+            match self {
+                Self::VerificationFailed(err) => Some(err),
+                Self::OnDecryption(err) => Some(err),
+                _ => None,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use alloc::string::ToString;
+
+    use super::Error;
+    use crate::capsule_frag::{CapsuleFragVerificationError, ReencryptionCheck};
+    use crate::{DeserializableFromArray, DeserializationError, PublicKey};
+
+    fn parse_key(bytes: &[u8]) -> Result<PublicKey, Error> {
+        Ok(PublicKey::from_bytes(bytes)?)
+    }
+
+    #[test]
+    fn test_conversion_and_display() {
+        assert_eq!(
+            parse_key(b"abc"),
+            Err(Error::Deserialization(DeserializationError::NotEnoughBytes))
+        );
+
+        let err: Error =
+            CapsuleFragVerificationError::IncorrectReencryption(ReencryptionCheck::PointV).into();
+        assert_eq!(
+            err.to_string(),
+            "reencryption proof check 2 of 3 (reencryption of V) failed"
+        );
+    }
+}
@@ -0,0 +1,87 @@
+//! Fingerprints referencing public keys and capsules.
+
+use generic_array::GenericArray;
+use typenum::U32;
+
+use crate::traits::{
+    DeserializableFromArray, DeserializationError, RepresentableAsArray, SerializableToArray,
+};
+
+type FingerprintSize = U32;
+
+/// A fingerprint of a public key or a capsule
+/// (see [`PublicKey::fingerprint()`](crate::PublicKey::fingerprint)
+/// and [`Capsule::fingerprint()`](crate::Capsule::fingerprint)),
+/// to reference them in logs, databases or revocation lists.
+///
+/// The fingerprint is the hash (SHA-256, unless another hash function is selected
+/// with a feature, see "Hash function" in the README) of a domain separation tag
+/// (`KEY_FINGERPRINT` or `CAPSULE_FINGERPRINT`, so that the fingerprints of objects
+/// of different types never coincide) and the byte representation of the object.
+/// These are also the values [`CapabilityUri`](crate::CapabilityUri) uses.
+///
+/// Displayed as (and parsed from) hex.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Fingerprint(GenericArray<u8, FingerprintSize>);
+
+impl Fingerprint {
+    pub(crate) fn new(digest: GenericArray<u8, FingerprintSize>) -> Self {
+        Self(digest)
+    }
+}
+
+impl AsRef<[u8]> for Fingerprint {
+    fn as_ref(&self) -> &[u8] {
+        self.0.as_ref()
+    }
+}
+
+impl RepresentableAsArray for Fingerprint {
+    type Size = FingerprintSize;
+}
+
+impl SerializableToArray for Fingerprint {
+    fn to_array(&self) -> GenericArray<u8, Self::Size> {
+        self.0
+    }
+}
+
+impl DeserializableFromArray for Fingerprint {
+    fn from_array(arr: &GenericArray<u8, Self::Size>) -> Result<Self, DeserializationError> {
+        Ok(Self(*arr))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use alloc::string::ToString;
+
+    use super::Fingerprint;
+    #[cfg(feature = "uri")]
+    use crate::CapabilityUri;
+    use crate::{encapsulate, PublicKey, SecretKey};
+
+    #[test]
+    fn test_fingerprints() {
+        let pk = PublicKey::from_secret_key(&SecretKey::random());
+        let other_pk = PublicKey::from_secret_key(&SecretKey::random());
+        let (capsule, _key_seed) = encapsulate(&pk);
+
+        assert_eq!(pk.fingerprint(), pk.fingerprint());
+        assert_ne!(pk.fingerprint(), other_pk.fingerprint());
+        assert_ne!(pk.fingerprint(), capsule.fingerprint());
+
+        let s = capsule.fingerprint().to_string();
+        assert_eq!(s.len(), 64);
+        assert_eq!(s.parse::<Fingerprint>().unwrap(), capsule.fingerprint());
+
+        // Consistent with the references in capability URIs.
+        #[cfg(feature = "uri")]
+        {
+            let uri = CapabilityUri::new(&capsule, b"policy", &pk).to_string();
+            assert!(uri.contains(&capsule.fingerprint().to_string()));
+            assert!(uri.contains(&pk.fingerprint().to_string()));
+        }
+    }
+}
@@ -0,0 +1,235 @@
+//! A typestate API for Bob's side of a full reencryption round.
+//!
+//! The states enforce the protocol ordering at the type level:
+//! cfrags can only be added after the threshold is known,
+//! they are verified on addition,
+//! and decryption is only available once enough distinct verified cfrags are collected.
+
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use core::fmt;
+
+use crate::capsule::Capsule;
+use crate::capsule_frag::{CapsuleFrag, CapsuleFragVerificationError, VerifiedCapsuleFrag};
+use crate::keys::{PublicKey, SecretKey};
+use crate::metadata::Metadata;
+use crate::pre::{decrypt_reencrypted, ReencryptionError};
+
+/// Errors that can happen during the state transitions.
+#[derive(Debug, PartialEq)]
+pub enum FlowError {
+    /// The threshold must be at least 1.
+    ZeroThreshold,
+    /// The added capsule fragment failed verification.
+    VerificationFailed(CapsuleFragVerificationError),
+    /// A capsule fragment originating from the same key fragment was already added.
+    RepeatingCapsuleFrag,
+    /// The given secret key does not correspond to the receiving public key of the round.
+    WrongReceivingKey,
+    /// An error when decrypting the ciphertext.
+    OnDecryption(ReencryptionError),
+}
+
+impl fmt::Display for FlowError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::ZeroThreshold => write!(f, "threshold must be at least 1"),
+            Self::VerificationFailed(err) => {
+                write!(f, "capsule fragment verification failed: {}", err)
+            }
+            Self::RepeatingCapsuleFrag => write!(f, "the capsule fragment was already added"),
+            Self::WrongReceivingKey => write!(f, "the secret key does not match the receiving key"),
+            Self::OnDecryption(err) => write!(f, "decryption failed: {}", err),
+        }
+    }
+}
+
+/// The initial state: the capsule and the keys are known,
+/// but the key fragments have not been issued yet.
+#[derive(Clone, Debug)]
+pub struct AwaitingKfrags {
+    capsule: Capsule,
+    delegating_pk: PublicKey,
+    receiving_pk: PublicKey,
+    verifying_pk: PublicKey,
+}
+
+impl AwaitingKfrags {
+    /// Starts a round for the given capsule and the keys of the policy.
+    pub fn new(
+        capsule: &Capsule,
+        delegating_pk: &PublicKey,
+        receiving_pk: &PublicKey,
+        verifying_pk: &PublicKey,
+    ) -> Self {
+        Self {
+            capsule: *capsule,
+            delegating_pk: *delegating_pk,
+            receiving_pk: *receiving_pk,
+            verifying_pk: *verifying_pk,
+        }
+    }
+
+    /// Transitions to collecting capsule fragments once the key fragments
+    /// were issued with the given `threshold`.
+    pub fn kfrags_issued(self, threshold: usize) -> Result<CollectingCfrags, FlowError> {
+        if threshold == 0 {
+            return Err(FlowError::ZeroThreshold);
+        }
+        Ok(CollectingCfrags {
+            round: Box::new(self),
+            threshold,
+            cfrags: Vec::with_capacity(threshold),
+        })
+    }
+}
+
+/// The state where capsule fragments are being collected and verified.
+#[derive(Clone, Debug)]
+pub struct CollectingCfrags {
+    round: Box<AwaitingKfrags>,
+    threshold: usize,
+    cfrags: Vec<VerifiedCapsuleFrag>,
+}
+
+impl CollectingCfrags {
+    /// Verifies the capsule fragment against the keys of the round and adds it to the collection.
+    pub fn add_cfrag(
+        &mut self,
+        cfrag: &CapsuleFrag,
+        metadata: Option<&Metadata>,
+    ) -> Result<(), FlowError> {
+        if self
+            .cfrags
+            .iter()
+            .any(|vcfrag| vcfrag.cfrag.kfrag_id == cfrag.kfrag_id)
+        {
+            return Err(FlowError::RepeatingCapsuleFrag);
+        }
+
+        let vcfrag = cfrag
+            .verify(
+                &self.round.capsule,
+                &self.round.verifying_pk,
+                &self.round.delegating_pk,
+                &self.round.receiving_pk,
+                metadata,
+            )
+            .map_err(FlowError::VerificationFailed)?;
+        self.cfrags.push(vcfrag);
+        Ok(())
+    }
+
+    /// Returns the number of verified capsule fragments collected so far.
+    pub fn collected(&self) -> usize {
+        self.cfrags.len()
+    }
+
+    /// Returns the threshold of the round.
+    pub fn threshold(&self) -> usize {
+        self.threshold
+    }
+
+    /// Transitions to the decryption state if enough capsule fragments were collected,
+    /// otherwise returns the current state back.
+    pub fn try_finish(self) -> Result<ReadyToDecrypt, Self> {
+        if self.cfrags.len() < self.threshold {
+            return Err(self);
+        }
+        Ok(ReadyToDecrypt {
+            round: self.round,
+            cfrags: self.cfrags,
+        })
+    }
+}
+
+/// The final state: at least a threshold of distinct verified capsule fragments
+/// has been collected.
+#[derive(Clone, Debug)]
+pub struct ReadyToDecrypt {
+    round: Box<AwaitingKfrags>,
+    cfrags: Vec<VerifiedCapsuleFrag>,
+}
+
+impl ReadyToDecrypt {
+    /// Decrypts the ciphertext using the collected capsule fragments.
+    pub fn decrypt(
+        &self,
+        receiving_sk: &SecretKey,
+        ciphertext: impl AsRef<[u8]>,
+    ) -> Result<Box<[u8]>, FlowError> {
+        if PublicKey::from_secret_key(receiving_sk) != self.round.receiving_pk {
+            return Err(FlowError::WrongReceivingKey);
+        }
+        decrypt_reencrypted(
+            receiving_sk,
+            &self.round.delegating_pk,
+            &self.round.capsule,
+            &self.cfrags,
+            ciphertext,
+        )
+        .map_err(FlowError::OnDecryption)
+    }
+}
+
+#[cfg(all(test, feature = "role-alice", feature = "role-ursula"))]
+mod tests {
+
+    use alloc::vec::Vec;
+
+    use super::{AwaitingKfrags, FlowError};
+    use crate::{
+        encrypt, generate_kfrags, reencrypt, CapsuleFrag, DeserializableFromArray, PublicKey,
+        SecretKey, SerializableToArray, Signer,
+    };
+
+    #[test]
+    fn test_flow() {
+        let delegating_sk = SecretKey::random();
+        let delegating_pk = PublicKey::from_secret_key(&delegating_sk);
+
+        let signing_sk = SecretKey::random();
+        let signer = Signer::new(&signing_sk);
+        let verifying_pk = PublicKey::from_secret_key(&signing_sk);
+
+        let receiving_sk = SecretKey::random();
+        let receiving_pk = PublicKey::from_secret_key(&receiving_sk);
+
+        let plaintext = b"peace at dawn";
+        let (capsule, ciphertext) = encrypt(&delegating_pk, plaintext).unwrap();
+
+        let round = AwaitingKfrags::new(&capsule, &delegating_pk, &receiving_pk, &verifying_pk);
+        assert_eq!(
+            round.clone().kfrags_issued(0).unwrap_err(),
+            FlowError::ZeroThreshold
+        );
+
+        let kfrags = generate_kfrags(&delegating_sk, &receiving_pk, &signer, 2, 3, true, true);
+        let cfrags: Vec<_> = kfrags
+            .iter()
+            .map(|vkfrag| reencrypt(&capsule, vkfrag, None))
+            .map(|vcfrag| CapsuleFrag::from_array(&vcfrag.to_array()).unwrap())
+            .collect();
+
+        let mut collecting = round.kfrags_issued(2).unwrap();
+        collecting.add_cfrag(&cfrags[0], None).unwrap();
+        assert_eq!(
+            collecting.add_cfrag(&cfrags[0], None),
+            Err(FlowError::RepeatingCapsuleFrag)
+        );
+
+        // Not enough cfrags yet
+        let mut collecting = collecting.try_finish().unwrap_err();
+        assert_eq!(collecting.collected(), 1);
+
+        collecting.add_cfrag(&cfrags[1], None).unwrap();
+        let ready = collecting.try_finish().unwrap();
+
+        assert_eq!(
+            ready.decrypt(&delegating_sk, &ciphertext),
+            Err(FlowError::WrongReceivingKey)
+        );
+        let plaintext_bob = ready.decrypt(&receiving_sk, &ciphertext).unwrap();
+        assert_eq!(&plaintext_bob as &[u8], plaintext);
+    }
+}
@@ -0,0 +1,331 @@
+//! Hashing to scalars and curve points as standardized in RFC 9380,
+//! used instead of the legacy constructions if the `rfc9380` feature is enabled.
+//!
+//! The suite is `secp256k1_XMD:SHA-256_SSWU_RO_`: the message is expanded with
+//! `expand_message_xmd` (SHA-256), mapped to the 3-isogenous curve with the simplified SWU map,
+//! and then to secp256k1 with the isogeny.
+//! Scalars are obtained with `hash_to_field` (for the curve order as the modulus),
+//! using the same expansion (but with the configured backend digest) and `L = 48` bytes per element.
+
+use digest::{BlockInput, Digest};
+use generic_array::GenericArray;
+use k256::{FieldElement, Scalar};
+use sha2::Sha256;
+use subtle::{Choice, ConditionallySelectable};
+use typenum::U32;
+
+use crate::curve::{CurvePoint, CurveScalar};
+
+// The length of the uniform bytes used for one field element
+// (`L = ceil((ceil(log2(p)) + k) / 8)` for the 128-bit security level).
+const ELEMENT_SIZE: usize = 48;
+
+const fn hex(s: &str) -> [u8; 32] {
+    const fn nibble(c: u8) -> u8 {
+        match c {
+            b'0'..=b'9' => c - b'0',
+            _ => c - b'a' + 10,
+        }
+    }
+    let s = s.as_bytes();
+    let mut result = [0u8; 32];
+    let mut i = 0;
+    while i < 32 {
+        result[i] = (nibble(s[2 * i]) << 4) | nibble(s[2 * i + 1]);
+        i += 1;
+    }
+    result
+}
+
+// The parameters of the isogenous curve `y^2 = x^3 + A' * x + B'` and the SWU map
+// (RFC 9380, section 8.7).
+const ISO_A: [u8; 32] = hex("3f8731abdd661adca08a5558f0f5d272e953d363cb6f0e5d405447c01a444533");
+const ISO_B: u32 = 1771;
+const Z_NEG: u32 = 11;
+
+// The coefficients of the 3-isogeny map (RFC 9380, appendix E.1).
+const K1_0: [u8; 32] = hex("8e38e38e38e38e38e38e38e38e38e38e38e38e38e38e38e38e38e38daaaaa8c7");
+const K1_1: [u8; 32] = hex("07d3d4c80bc321d5b9f315cea7fd44c5d595d2fc0bf63b92dfff1044f17c6581");
+const K1_2: [u8; 32] = hex("534c328d23f234e6e2a413deca25caece4506144037c40314ecbd0b53d9dd262");
+const K1_3: [u8; 32] = hex("8e38e38e38e38e38e38e38e38e38e38e38e38e38e38e38e38e38e38daaaaa88c");
+const K2_0: [u8; 32] = hex("d35771193d94918a9ca34ccbb7b640dd86cd409542f8487d9fe6b745781eb49b");
+const K2_1: [u8; 32] = hex("edadc6f64383dc1df7c4b2d51b54225406d36b641f5e41bbc52a56612a8c6d14");
+const K3_0: [u8; 32] = hex("4bda12f684bda12f684bda12f684bda12f684bda12f684bda12f684b8e38e23c");
+const K3_1: [u8; 32] = hex("c75e0c32d5cb7c0fa9d0a54b12a0a6d5647ab046d686da6fdffc90fc201d71a3");
+const K3_2: [u8; 32] = hex("29a6194691f91a73715209ef6512e576722830a201be2018a765e85a9ecee931");
+const K3_3: [u8; 32] = hex("2f684bda12f684bda12f684bda12f684bda12f684bda12f684bda12f38e38d84");
+const K4_0: [u8; 32] = hex("fffffffffffffffffffffffffffffffffffffffffffffffffffffffefffff93b");
+const K4_1: [u8; 32] = hex("7a06534bb8bdb49fd5e9e6632722c2989467c1bfc8e8d978dfb425d2685c2573");
+const K4_2: [u8; 32] = hex("6484aa716545ca2cf3a70c3fa8fe337e0a3d21162f0d6299a7bf8192bfd2a76f");
+
+fn field_element(bytes: &[u8; 32]) -> FieldElement {
+    // All the constants above are less than the modulus.
+    FieldElement::from_bytes(GenericArray::from_slice(bytes)).unwrap()
+}
+
+fn field_element_from_u32(x: u32) -> FieldElement {
+    let mut bytes = [0u8; 32];
+    bytes[28..].copy_from_slice(&x.to_be_bytes());
+    field_element(&bytes)
+}
+
+/// Returns the digest with the zero padding block of `expand_message_xmd` absorbed;
+/// the message is to be fed into it before calling [`expand_message_xmd`].
+pub(crate) fn expander<D: Digest + BlockInput>(digest: D) -> D {
+    digest.chain(GenericArray::<u8, D::BlockSize>::default())
+}
+
+/// Finishes `expand_message_xmd` (RFC 9380, section 5.3.1) for the message
+/// fed into the digest created by [`expander`], filling `out` with uniform bytes.
+/// Panics if `out` is longer than `255 * 32` bytes.
+pub(crate) fn expand_message_xmd<D: Digest<OutputSize = U32>>(
+    digest: D,
+    dst: &[u8],
+    out: &mut [u8],
+) {
+    let out_len = out.len() as u16;
+    let chunks = out.chunks_mut(32);
+    let ell = chunks.len();
+    assert!(ell <= 255);
+
+    // Overly long DSTs are hashed first (section 5.3.3).
+    let dst_hash;
+    let dst = if dst.len() > 255 {
+        dst_hash = D::new().chain(b"H2C-OVERSIZE-DST-").chain(dst).finalize();
+        &dst_hash[..]
+    } else {
+        dst
+    };
+    let dst_len = [dst.len() as u8];
+
+    let b_0 = digest
+        .chain(&out_len.to_be_bytes())
+        .chain(&[0u8])
+        .chain(dst)
+        .chain(&dst_len)
+        .finalize();
+    let mut b_i = D::new()
+        .chain(&b_0)
+        .chain(&[1u8])
+        .chain(dst)
+        .chain(&dst_len)
+        .finalize();
+
+    for (i, chunk) in chunks.enumerate() {
+        chunk.copy_from_slice(&b_i[..chunk.len()]);
+        if i + 1 < ell {
+            let mut xored = b_0;
+            for (x, b) in xored.iter_mut().zip(b_i.iter()) {
+                *x ^= b;
+            }
+            b_i = D::new()
+                .chain(&xored)
+                .chain(&[(i + 2) as u8])
+                .chain(dst)
+                .chain(&dst_len)
+                .finalize();
+        }
+    }
+}
+
+// Splits a 48-byte big-endian number into three 16-byte limbs,
+// each of them smaller than both the field modulus and the curve order.
+fn limbs(bytes: &[u8]) -> [[u8; 32]; 3] {
+    let mut result = [[0u8; 32]; 3];
+    for (limb, chunk) in result.iter_mut().zip(bytes.chunks(16)) {
+        limb[16..].copy_from_slice(chunk);
+    }
+    result
+}
+
+const TWO_POW_128: [u8; 32] =
+    hex("0000000000000000000000000000000100000000000000000000000000000000");
+
+// `OS2IP(bytes) mod p`
+fn field_element_from_wide(bytes: &[u8]) -> FieldElement {
+    let [hi, mid, lo] = limbs(bytes);
+    let shift = field_element(&TWO_POW_128);
+    ((field_element(&hi) * &shift + &field_element(&mid)) * &shift + &field_element(&lo))
+        .normalize()
+}
+
+// `OS2IP(bytes) mod n`
+fn scalar_from_wide(bytes: &[u8]) -> Scalar {
+    let [hi, mid, lo] = limbs(bytes);
+    let scalar = |limb: &[u8; 32]| Scalar::from_bytes_reduced(GenericArray::from_slice(limb));
+    let shift = scalar(&TWO_POW_128);
+    (scalar(&hi) * shift + scalar(&mid)) * shift + scalar(&lo)
+}
+
+/// `hash_to_field` for the curve order as the modulus (with `count = 1`)
+/// for the message fed into the digest created by [`expander`].
+pub(crate) fn hash_to_scalar<D: Digest<OutputSize = U32>>(digest: D, dst: &[u8]) -> CurveScalar {
+    let mut uniform_bytes = [0u8; ELEMENT_SIZE];
+    expand_message_xmd(digest, dst, &mut uniform_bytes);
+    CurveScalar::from_backend_scalar(&scalar_from_wide(&uniform_bytes))
+}
+
+// `sgn0()` for a prime field.
+fn sgn0(x: &FieldElement) -> Choice {
+    x.normalize().is_odd()
+}
+
+// The simplified SWU map to the isogenous curve (RFC 9380, section 6.6.2),
+// returns the affine coordinates of the point.
+fn map_to_curve_simple_swu(u: &FieldElement) -> (FieldElement, FieldElement) {
+    let a = field_element(&ISO_A);
+    let b = field_element_from_u32(ISO_B);
+    let z = field_element_from_u32(Z_NEG).negate(1);
+
+    let z_u2 = z * &u.square();
+    let tv1 = (z_u2.square() + &z_u2).normalize();
+    let tv1_inv = tv1.invert().unwrap_or(FieldElement::zero());
+
+    // `x1 = (-B / A) * (1 + 1 / tv1)`, or `B / (Z * A)` in the exceptional case `tv1 == 0`.
+    let a_inv = a.invert().unwrap();
+    let x1_regular = b.negate(1) * &a_inv * &(tv1_inv + &FieldElement::one());
+    let x1_exceptional = b * &(z * &a).invert().unwrap();
+    let x1 = FieldElement::conditional_select(&x1_regular, &x1_exceptional, tv1.is_zero());
+
+    let curve_rhs = |x: &FieldElement| (x.square() + &a) * x + &b;
+
+    let x2 = z_u2 * &x1;
+    let y1 = curve_rhs(&x1).sqrt();
+    let y2 = curve_rhs(&x2).sqrt();
+
+    // One of `gx1` and `gx2` is always a square.
+    let gx1_is_square = y1.is_some();
+    let x = FieldElement::conditional_select(&x2, &x1, gx1_is_square);
+    let y = FieldElement::conditional_select(
+        &y2.unwrap_or(FieldElement::zero()),
+        &y1.unwrap_or(FieldElement::zero()),
+        gx1_is_square,
+    );
+
+    let y = FieldElement::conditional_select(&y, &y.negate(1), sgn0(u) ^ sgn0(&y));
+    (x, y)
+}
+
+fn polynomial(x: &FieldElement, coeffs: &[[u8; 32]]) -> FieldElement {
+    // Horner's method, the coefficients go from the highest degree to the lowest.
+    let mut result = FieldElement::zero();
+    for coeff in coeffs {
+        result = result * x + &field_element(coeff);
+    }
+    result
+}
+
+// The 3-isogeny map from the isogenous curve to secp256k1 (RFC 9380, appendix E.1).
+fn iso_map(x: &FieldElement, y: &FieldElement) -> CurvePoint {
+    let x_num = polynomial(x, &[K1_3, K1_2, K1_1, K1_0]);
+    let x_den = (x.square() + &(field_element(&K2_1) * x) + &field_element(&K2_0)).normalize();
+    let y_num = polynomial(x, &[K3_3, K3_2, K3_1, K3_0]);
+    let y_den = (x.square() * x + &polynomial(x, &[K4_2, K4_1, K4_0])).normalize();
+
+    // The denominators are only zero for the points mapped to the identity,
+    // which the SWU map never produces for secp256k1.
+    let x_out = x_num * &x_den.invert().unwrap();
+    let y_out = *y * &y_num * &y_den.invert().unwrap();
+
+    // The result lies on the curve by construction.
+    CurvePoint::from_affine_coordinates(&x_out.to_bytes(), &y_out.to_bytes()).unwrap()
+}
+
+/// `hash_to_curve` for the `secp256k1_XMD:SHA-256_SSWU_RO_` suite.
+///
+/// Unlike [`unsafe_hash_to_point`](crate::hashing::unsafe_hash_to_point),
+/// takes the same time for any input.
+pub(crate) fn hash_to_curve(dst: &[u8], data: &[u8]) -> CurvePoint {
+    let mut uniform_bytes = [0u8; 2 * ELEMENT_SIZE];
+    expand_message_xmd(expander(Sha256::new()).chain(data), dst, &mut uniform_bytes);
+
+    let (u0, u1) = uniform_bytes.split_at(ELEMENT_SIZE);
+    let (x0, y0) = map_to_curve_simple_swu(&field_element_from_wide(u0));
+    let (x1, y1) = map_to_curve_simple_swu(&field_element_from_wide(u1));
+
+    // The cofactor of secp256k1 is 1, so no clearing is needed.
+    &iso_map(&x0, &y0) + &iso_map(&x1, &y1)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use alloc::vec;
+    use alloc::vec::Vec;
+
+    use digest::Digest;
+    use generic_array::GenericArray;
+    use sha2::Sha256;
+
+    use super::{expand_message_xmd, expander, hash_to_curve};
+    use crate::curve::CurvePoint;
+    use crate::SerializableToArray;
+
+    fn from_hex(s: &str) -> Vec<u8> {
+        (0..s.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&s[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    // Test vectors from RFC 9380, appendix K.1.
+    #[test]
+    fn test_expand_message_xmd() {
+        let dst = b"QUUX-V01-CS02-with-expander-SHA256-128";
+        let vectors: [(&[u8], &str); 3] = [
+            (
+                b"",
+                "68a985b87eb6b46952128911f2a4412bbc302a9d759667f87f7a21d803f07235",
+            ),
+            (
+                b"abc",
+                "d8ccab23b5985ccea865c6c97b6e5b8350e794e603b4b97902f53a8a0d605615",
+            ),
+            (
+                b"",
+                "af84c27ccfd45d41914fdff5df25293e221afc53d8ad2ac06d5e3e29485dadbe\
+                 e0d121587713a3e0dd4d5e69e93eb7cd4f5df4cd103e188cf60cb02edc3edf18\
+                 eda8576c412b18ffb658e3dd6ec849469b979d444cf7b26911a08e63cf31f9dc\
+                 c541708d3491184472c2c29bb749d4286b004ceb5ee6b9a7fa5b646c993f0ced",
+            ),
+        ];
+
+        for (msg, expected) in vectors.iter() {
+            let expected = from_hex(&expected.replace(' ', ""));
+            let mut out = vec![0u8; expected.len()];
+            expand_message_xmd(expander(Sha256::new()).chain(msg), dst, &mut out);
+            assert_eq!(out, expected);
+        }
+    }
+
+    // Test vectors from RFC 9380, appendix J.8.1.
+    #[test]
+    fn test_hash_to_curve() {
+        let dst = b"QUUX-V01-CS02-with-secp256k1_XMD:SHA-256_SSWU_RO_";
+        let vectors: [(&[u8], &str, &str); 2] = [
+            (
+                b"",
+                "c1cae290e291aee617ebaef1be6d73861479c48b841eaba9b7b5852ddfeb1346",
+                "64fa678e07ae116126f08b022a94af6de15985c996c3a91b64c406a960e51067",
+            ),
+            (
+                b"abc",
+                "3377e01eab42db296b512293120c6cee72b6ecf9f9205760bd9ff11fb3cb2c4b",
+                "7f95890f33efebd1044d382a01b1bee0900fb6116f94688d487c6c7b9c8371f6",
+            ),
+        ];
+
+        for (msg, x, y) in vectors.iter() {
+            let x = from_hex(x);
+            let y = from_hex(y);
+            let expected = CurvePoint::from_affine_coordinates(
+                GenericArray::from_slice(&x),
+                GenericArray::from_slice(&y),
+            )
+            .unwrap();
+            let point = hash_to_curve(dst, msg);
+            assert_eq!(point, expected);
+            assert_eq!(&point.to_array()[1..], x.as_slice());
+        }
+    }
+}
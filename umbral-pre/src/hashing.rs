@@ -1,10 +1,20 @@
-use digest::Digest;
+#[cfg(feature = "rfc9380")]
+use alloc::vec::Vec;
+
+use digest::{BlockInput, Digest};
 use generic_array::sequence::Concat;
-use generic_array::GenericArray;
+use generic_array::{ArrayLength, GenericArray};
+use hkdf::Hkdf;
+#[cfg(feature = "role-alice")]
+use rand_core::{impls, CryptoRng, Error, RngCore};
 use sha2::Sha256;
-use typenum::U1;
+use typenum::{U1, U32};
+#[cfg(feature = "role-alice")]
+use zeroize::Zeroize;
 
 use crate::curve::{CurvePoint, CurveScalar};
+#[cfg(feature = "rfc9380")]
+use crate::hash_to_curve::{expander, hash_to_scalar};
 use crate::traits::SerializableToArray;
 
 /// Hashes arbitrary data with the given domain separation tag
@@ -13,13 +23,14 @@ use crate::traits::SerializableToArray;
 ///
 /// WARNING: Do not use when the input data is secret, as this implementation is not
 /// in constant time, and hence, it is not safe with respect to timing attacks.
+///
+/// With the `rfc9380` feature the scheme parameters are derived
+/// with the standard `hash_to_curve` instead.
 pub fn unsafe_hash_to_point(dst: &[u8], data: &[u8]) -> Option<CurvePoint> {
     // NOTE: Yes, this function is hacky, but it is the only way
     // to hash to a point with an *unknown* discrete log.
     // Don't replace with hashing to scalar and multiplying by a generator!
 
-    // TODO (#35): use the standard method when it is available in RustCrypto.
-
     // Fixed sign prefix. Halves the range of the generated points, but we only need one,
     // and it is always the same.
     let sign_prefix = GenericArray::<u8, U1>::from_slice(&[2u8]);
@@ -29,7 +40,8 @@ pub fn unsafe_hash_to_point(dst: &[u8], data: &[u8]) -> Option<CurvePoint> {
     // We use an internal 32-bit counter as additional input
     let mut i = 0u32;
     while i < <u32>::MAX {
-        let result = BytesDigest::new_with_dst(dst)
+        // Always SHA-256, so that the parameters do not depend on the choice of `BackendDigest`.
+        let result = BytesDigest::from_digest(Sha256::new(), dst)
             .chain_bytes(&data_len)
             .chain_bytes(data)
             .chain_bytes(&i.to_be_bytes())
@@ -52,42 +64,92 @@ pub fn unsafe_hash_to_point(dst: &[u8], data: &[u8]) -> Option<CurvePoint> {
 }
 
 // Our hash of choice.
+#[cfg(not(any(feature = "sha3", feature = "blake3")))]
 pub(crate) type BackendDigest = Sha256;
 
-// Wraps BackendDigest for easier replacement, and standardizes the use of DST.
-pub(crate) struct Hash(BackendDigest);
+#[cfg(feature = "sha3")]
+pub(crate) type BackendDigest = sha3::Sha3_256;
+
+#[cfg(all(feature = "blake3", not(feature = "sha3")))]
+pub(crate) type BackendDigest = blake3::Hasher;
+
+// The identifier of `BackendDigest` in the envelope version (see `traits::ENVELOPE_VERSION`).
+#[cfg(not(any(feature = "sha3", feature = "blake3")))]
+pub(crate) const HASH_FUNCTION_ID: u8 = 0;
 
-// Can't be put in the `impl` in the current version of Rust.
-pub type HashOutputSize = <BackendDigest as Digest>::OutputSize;
+#[cfg(feature = "sha3")]
+pub(crate) const HASH_FUNCTION_ID: u8 = 1;
+
+#[cfg(all(feature = "blake3", not(feature = "sha3")))]
+pub(crate) const HASH_FUNCTION_ID: u8 = 2;
+
+// Wraps a digest (`BackendDigest` by default) for easier replacement,
+// and standardizes the use of DST.
+pub(crate) struct Hash<D = BackendDigest>(D);
 
 impl Hash {
     pub fn new() -> Self {
-        Self(BackendDigest::new())
+        Self::from_digest(BackendDigest::new())
     }
+}
 
-    pub fn new_with_dst(dst: &[u8]) -> Self {
+impl<D: Digest> Hash<D> {
+    pub fn from_digest(digest: D) -> Self {
+        Self(digest)
+    }
+
+    pub fn from_digest_with_dst(digest: D, dst: &[u8]) -> Self {
         let dst_len = (dst.len() as u32).to_be_bytes();
-        Self::new().chain_bytes(dst_len).chain_bytes(dst)
+        Self::from_digest(digest)
+            .chain_bytes(dst_len)
+            .chain_bytes(dst)
     }
 
     pub fn chain_bytes<T: AsRef<[u8]>>(self, bytes: T) -> Self {
         Self(self.0.chain(bytes.as_ref()))
     }
 
-    pub fn digest(self) -> BackendDigest {
+    pub fn digest(self) -> D {
         self.0
     }
 }
 
-pub(crate) struct ScalarDigest(Hash);
+// With the `rfc9380` feature the DST goes after the message (see `expand_message_xmd`),
+// so it has to be kept until finalization.
+pub(crate) struct ScalarDigest<D = BackendDigest> {
+    hash: Hash<D>,
+    #[cfg(feature = "rfc9380")]
+    dst: Vec<u8>,
+}
 
 impl ScalarDigest {
     pub fn new_with_dst(dst: &[u8]) -> Self {
-        Self(Hash::new_with_dst(dst))
+        Self::from_digest(BackendDigest::new(), dst)
+    }
+}
+
+impl<D: Digest<OutputSize = U32> + BlockInput> ScalarDigest<D> {
+    #[cfg(not(feature = "rfc9380"))]
+    pub fn from_digest(digest: D, dst: &[u8]) -> Self {
+        Self {
+            hash: Hash::from_digest_with_dst(digest, dst),
+        }
+    }
+
+    #[cfg(feature = "rfc9380")]
+    pub fn from_digest(digest: D, dst: &[u8]) -> Self {
+        Self {
+            hash: Hash::from_digest(expander(digest)),
+            dst: dst.to_vec(),
+        }
     }
 
     pub fn chain_bytes<T: AsRef<[u8]>>(self, bytes: T) -> Self {
-        Self(self.0.chain_bytes(bytes))
+        Self {
+            hash: self.hash.chain_bytes(bytes),
+            #[cfg(feature = "rfc9380")]
+            dst: self.dst,
+        }
     }
 
     pub fn chain_point(self, point: &CurvePoint) -> Self {
@@ -102,36 +164,134 @@ impl ScalarDigest {
         digest
     }
 
+    #[cfg(not(feature = "rfc9380"))]
     pub fn finalize(self) -> CurveScalar {
-        // TODO (#35): use the standard method when it is available in RustCrypto.
         // TODO (#39): Ideally this should return a non-zero scalar.
         //     (when it does, the loop in `KeyFragFactory::new()` can be removed)
-        CurveScalar::from_digest(self.0.digest())
+        CurveScalar::from_digest(self.hash.digest())
+    }
+
+    #[cfg(feature = "rfc9380")]
+    pub fn finalize(self) -> CurveScalar {
+        hash_to_scalar(self.hash.digest(), &self.dst)
     }
 }
 
-pub(crate) struct BytesDigest(Hash);
+pub(crate) struct BytesDigest<D = BackendDigest>(Hash<D>);
 
 impl BytesDigest {
     pub fn new_with_dst(dst: &[u8]) -> Self {
-        Self(Hash::new_with_dst(dst))
+        Self::from_digest(BackendDigest::new(), dst)
+    }
+}
+
+impl<D: Digest> BytesDigest<D> {
+    pub fn from_digest(digest: D, dst: &[u8]) -> Self {
+        Self(Hash::from_digest_with_dst(digest, dst))
     }
 
     pub fn chain_bytes<T: AsRef<[u8]>>(self, bytes: T) -> Self {
         Self(self.0.chain_bytes(bytes))
     }
 
-    pub fn finalize(self) -> GenericArray<u8, HashOutputSize> {
+    pub fn finalize(self) -> GenericArray<u8, D::OutputSize> {
         self.0.digest().finalize()
     }
 }
 
+pub(crate) fn kdf<T: ArrayLength<u8>>(
+    seed: &[u8],
+    salt: Option<&[u8]>,
+    info: Option<&[u8]>,
+) -> GenericArray<u8, T> {
+    let hk = Hkdf::<BackendDigest>::new(salt, &seed);
+
+    let mut okm = GenericArray::<u8, T>::default();
+
+    let def_info = info.unwrap_or(&[]);
+
+    // We can only get an error here if `T` is too large, and it's known at compile-time.
+    hk.expand(&def_info, &mut okm).unwrap();
+
+    okm
+}
+
+/// The maximum output size of [`kdf_into`] (limited by HKDF).
+pub(crate) const KDF_MAX_OUTPUT_SIZE: usize = 255 * 32;
+
+/// Same as [`kdf`], but for outputs of size only known at runtime.
+/// Panics if `out` is longer than [`KDF_MAX_OUTPUT_SIZE`].
+pub(crate) fn kdf_into(seed: &[u8], salt: Option<&[u8]>, info: &[u8], out: &mut [u8]) {
+    let hk = Hkdf::<BackendDigest>::new(salt, &seed);
+    hk.expand(info, out).unwrap();
+}
+
+/// A deterministic RNG producing the KDF expansion of a secret seed,
+/// for the values that must be reproducible from the same secret input
+/// (see [`generate_kfrags_with_label()`](crate::generate_kfrags_with_label)).
+///
+/// The key is zeroized when the RNG is dropped.
+#[cfg(feature = "role-alice")]
+pub(crate) struct KdfRng {
+    key: GenericArray<u8, U32>,
+    counter: u64,
+}
+
+#[cfg(feature = "role-alice")]
+impl KdfRng {
+    pub(crate) fn new(seed: &[u8], info: &[u8]) -> Self {
+        Self {
+            key: kdf::<U32>(seed, None, Some(info)),
+            counter: 0,
+        }
+    }
+}
+
+#[cfg(feature = "role-alice")]
+impl RngCore for KdfRng {
+    fn next_u32(&mut self) -> u32 {
+        impls::next_u32_via_fill(self)
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        impls::next_u64_via_fill(self)
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        // Each request gets its own block, so the output for a given request
+        // does not depend on the sizes of the previous ones.
+        for chunk in dest.chunks_mut(KDF_MAX_OUTPUT_SIZE) {
+            kdf_into(&self.key, None, &self.counter.to_be_bytes(), chunk);
+            self.counter += 1;
+        }
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), Error> {
+        self.fill_bytes(dest);
+        Ok(())
+    }
+}
+
+#[cfg(feature = "role-alice")]
+impl CryptoRng for KdfRng {}
+
+#[cfg(feature = "role-alice")]
+impl Drop for KdfRng {
+    fn drop(&mut self) {
+        self.key.zeroize();
+    }
+}
+
 #[cfg(test)]
 mod tests {
 
-    use super::{unsafe_hash_to_point, BytesDigest, HashOutputSize, ScalarDigest};
+    use super::{kdf, unsafe_hash_to_point, BytesDigest, ScalarDigest};
     use crate::curve::{CurvePoint, CurveScalar};
+    use crate::SerializableToArray;
+    use digest::Digest;
     use generic_array::GenericArray;
+    use sha2::{Sha256, Sha512Trunc256};
+    use typenum::U32;
 
     #[test]
     fn test_unsafe_hash_to_point() {
@@ -179,27 +339,58 @@ mod tests {
         assert_ne!(s, s_diff_tag);
     }
 
+    #[test]
+    fn test_scalar_digest_backend() {
+        let bytes: &[u8] = b"foobar";
+
+        let s = ScalarDigest::from_digest(Sha512Trunc256::new(), b"abc")
+            .chain_bytes(bytes)
+            .finalize();
+        let s_same = ScalarDigest::from_digest(Sha512Trunc256::new(), b"abc")
+            .chain_bytes(bytes)
+            .finalize();
+        assert_eq!(s, s_same);
+
+        let s_diff = ScalarDigest::from_digest(Sha256::new(), b"abc")
+            .chain_bytes(bytes)
+            .finalize();
+        assert_ne!(s, s_diff);
+    }
+
     #[test]
     fn test_bytes_digest() {
         let bytes: &[u8] = b"foobar";
         let bytes2: &[u8] = b"barbaz";
 
-        let s: GenericArray<u8, HashOutputSize> = BytesDigest::new_with_dst(b"abc")
+        let s: GenericArray<u8, U32> = BytesDigest::new_with_dst(b"abc")
             .chain_bytes(bytes)
             .finalize();
-        let s_same: GenericArray<u8, HashOutputSize> = BytesDigest::new_with_dst(b"abc")
+        let s_same: GenericArray<u8, U32> = BytesDigest::new_with_dst(b"abc")
             .chain_bytes(bytes)
             .finalize();
         assert_eq!(s, s_same);
 
-        let s_diff: GenericArray<u8, HashOutputSize> = BytesDigest::new_with_dst(b"abc")
+        let s_diff: GenericArray<u8, U32> = BytesDigest::new_with_dst(b"abc")
             .chain_bytes(bytes2)
             .finalize();
         assert_ne!(s, s_diff);
 
-        let s_diff_tag: GenericArray<u8, HashOutputSize> = BytesDigest::new_with_dst(b"def")
+        let s_diff_tag: GenericArray<u8, U32> = BytesDigest::new_with_dst(b"def")
             .chain_bytes(bytes)
             .finalize();
         assert_ne!(s, s_diff_tag);
     }
+
+    #[test]
+    fn test_kdf() {
+        let p1 = CurvePoint::generator();
+        let salt = b"abcdefg";
+        let info = b"sdasdasd";
+        let key = kdf::<U32>(&p1.to_array(), Some(&salt[..]), Some(&info[..]));
+        let key_same = kdf::<U32>(&p1.to_array(), Some(&salt[..]), Some(&info[..]));
+        assert_eq!(key, key_same);
+
+        let key_diff = kdf::<U32>(&p1.to_array(), None, Some(&info[..]));
+        assert_ne!(key, key_diff);
+    }
 }
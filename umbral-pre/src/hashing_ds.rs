@@ -1,16 +1,23 @@
 //! This module contains hashing sequences with included domain separation tags
 //! shared between different parts of the code.
 
-use alloc::boxed::Box;
-use alloc::vec::Vec;
+use generic_array::GenericArray;
+use typenum::{op, U1, U32, U8};
 
+use crate::capsule::Capsule;
+#[cfg(any(feature = "role-ursula", feature = "role-bob", feature = "proxy"))]
+use crate::capsule_frag::CapsuleFrag;
 use crate::curve::{CurvePoint, CurveScalar};
-use crate::hashing::ScalarDigest;
-use crate::key_frag::KeyFragID;
+use crate::fingerprint::Fingerprint;
+use crate::hashing::{BytesDigest, ScalarDigest};
+use crate::key_frag::{KeyFrag, KeyFragID, PolicyFingerprint, ValidityWindow};
 use crate::keys::PublicKey;
-use crate::traits::SerializableToArray;
+#[cfg(feature = "schnorr")]
+use crate::keys::SignatureScheme;
+use crate::traits::{RepresentableAsArray, SerializableToArray};
 
 // TODO (#39): Ideally this should return a non-zero scalar.
+#[cfg(any(feature = "role-alice", feature = "role-bob"))]
 pub(crate) fn hash_to_polynomial_arg(
     precursor: &CurvePoint,
     pubkey: &CurvePoint,
@@ -25,6 +32,7 @@ pub(crate) fn hash_to_polynomial_arg(
         .finalize()
 }
 
+#[cfg(any(feature = "role-alice", feature = "role-bob"))]
 pub(crate) fn hash_to_shared_secret(
     precursor: &CurvePoint,
     pubkey: &CurvePoint,
@@ -44,6 +52,186 @@ pub(crate) fn hash_capsule_points(capsule_e: &CurvePoint, capsule_v: &CurvePoint
         .finalize()
 }
 
+/// Computes the challenge of the signature binding a delegating key hint to a capsule
+/// (see [`HintedCapsule`](crate::HintedCapsule)).
+pub(crate) fn hash_to_key_hint_challenge(
+    capsule_e: &CurvePoint,
+    capsule_v: &CurvePoint,
+    hint: &Fingerprint,
+    commitment: &CurvePoint,
+) -> CurveScalar {
+    ScalarDigest::new_with_dst(b"CAPSULE_KEY_HINT")
+        .chain_point(capsule_e)
+        .chain_point(capsule_v)
+        .chain_bytes(hint)
+        .chain_point(commitment)
+        .finalize()
+}
+
+/// Derives the two ephemeral scalars of a capsule from a seed
+/// (see [`encapsulate_with_seed()`](crate::encapsulate_with_seed)).
+pub(crate) fn hash_to_capsule_scalars(seed: &[u8], pk: &PublicKey) -> (CurveScalar, CurveScalar) {
+    let derive = |dst: &[u8]| {
+        ScalarDigest::new_with_dst(dst)
+            .chain_point(&pk.to_point())
+            .chain_bytes(seed)
+            .finalize()
+    };
+    (derive(b"CAPSULE_SEED_R"), derive(b"CAPSULE_SEED_U"))
+}
+
+pub(crate) fn hash_to_key_check(key_seed: &[u8]) -> GenericArray<u8, U8> {
+    let digest = BytesDigest::new_with_dst(b"KEY_CHECK")
+        .chain_bytes(key_seed)
+        .finalize();
+    GenericArray::<u8, U8>::clone_from_slice(&digest[..8])
+}
+
+#[cfg(feature = "qr")]
+pub(crate) fn hash_to_chunked_payload_digest(payload: &[u8]) -> GenericArray<u8, U8> {
+    let digest = BytesDigest::new_with_dst(b"CHUNKED_PAYLOAD")
+        .chain_bytes(payload)
+        .finalize();
+    GenericArray::<u8, U8>::clone_from_slice(&digest[..8])
+}
+
+pub(crate) fn hash_to_policy_fingerprint(
+    verifying_pk: &PublicKey,
+    maybe_delegating_pk: Option<&PublicKey>,
+    maybe_receiving_pk: Option<&PublicKey>,
+) -> GenericArray<u8, U32> {
+    let mut digest =
+        BytesDigest::new_with_dst(b"POLICY_FINGERPRINT").chain_bytes(verifying_pk.to_array());
+    for maybe_pk in [maybe_delegating_pk, maybe_receiving_pk].iter() {
+        digest = match maybe_pk {
+            Some(pk) => digest
+                .chain_bytes(true.to_array())
+                .chain_bytes(pk.to_array()),
+            None => digest.chain_bytes(false.to_array()),
+        };
+    }
+    digest.finalize()
+}
+
+pub(crate) fn hash_to_kfrag_verification_token(
+    kfrag: &KeyFrag,
+    policy_fingerprint: &PolicyFingerprint,
+) -> GenericArray<u8, U32> {
+    BytesDigest::new_with_dst(b"KFRAG_VERIFICATION_TOKEN")
+        .chain_bytes(kfrag.to_array())
+        .chain_bytes(policy_fingerprint)
+        .finalize()
+}
+
+#[cfg(feature = "policy")]
+pub(crate) fn hash_to_policy_id(
+    delegating_pk: &PublicKey,
+    receiving_pk: &PublicKey,
+    label: &[u8],
+) -> GenericArray<u8, U32> {
+    let label_len = (label.len() as u32).to_be_bytes();
+    BytesDigest::new_with_dst(b"POLICY_ID")
+        .chain_bytes(delegating_pk.to_array())
+        .chain_bytes(receiving_pk.to_array())
+        .chain_bytes(&label_len)
+        .chain_bytes(label)
+        .finalize()
+}
+
+pub(crate) fn hash_to_capsule_fingerprint(capsule: &Capsule) -> GenericArray<u8, U32> {
+    BytesDigest::new_with_dst(b"CAPSULE_FINGERPRINT")
+        .chain_bytes(capsule.to_array())
+        .finalize()
+}
+
+pub(crate) fn hash_to_key_fingerprint(pk: &PublicKey) -> GenericArray<u8, U32> {
+    BytesDigest::new_with_dst(b"KEY_FINGERPRINT")
+        .chain_bytes(pk.to_array())
+        .finalize()
+}
+
+/// The message signed in a [`ReceptionKey`](crate::ReceptionKey).
+#[cfg(any(feature = "role-alice", feature = "role-bob"))]
+pub(crate) fn reception_key_signature_message(reception_pk: &PublicKey) -> GenericArray<u8, U32> {
+    BytesDigest::new_with_dst(b"RECEPTION_KEY")
+        .chain_bytes(reception_pk.to_array())
+        .finalize()
+}
+
+#[cfg(any(feature = "role-ursula", feature = "role-bob"))]
+pub(crate) fn hash_to_cfrag_digest(cfrag: &CapsuleFrag) -> GenericArray<u8, U32> {
+    BytesDigest::new_with_dst(b"CFRAG_DIGEST")
+        .chain_bytes(cfrag.to_array())
+        .finalize()
+}
+
+/// The message signed in a [`ReencryptionReceipt`](crate::ReencryptionReceipt).
+#[cfg(any(feature = "role-ursula", feature = "role-bob"))]
+pub(crate) fn reencryption_receipt_message(
+    capsule_fingerprint: &GenericArray<u8, U32>,
+    kfrag_id: &KeyFragID,
+    cfrag_digest: &GenericArray<u8, U32>,
+    timestamp: u64,
+) -> GenericArray<u8, U32> {
+    BytesDigest::new_with_dst(b"REENCRYPTION_RECEIPT")
+        .chain_bytes(capsule_fingerprint)
+        .chain_bytes(kfrag_id)
+        .chain_bytes(cfrag_digest)
+        .chain_bytes(&timestamp.to_be_bytes())
+        .finalize()
+}
+
+/// The message signed in a [`ReencryptionRequest`](crate::ReencryptionRequest)
+/// (everything but the signature, in the canonical encoding).
+#[cfg(feature = "proxy")]
+pub(crate) fn reencryption_request_message(body: &[u8]) -> GenericArray<u8, U32> {
+    BytesDigest::new_with_dst(b"REENCRYPTION_REQUEST")
+        .chain_bytes(body)
+        .finalize()
+}
+
+/// The message signed in a [`ReencryptionResponse`](crate::ReencryptionResponse).
+#[cfg(feature = "proxy")]
+pub(crate) fn reencryption_response_message(
+    request_message: &GenericArray<u8, U32>,
+    cfrag: &CapsuleFrag,
+) -> GenericArray<u8, U32> {
+    BytesDigest::new_with_dst(b"REENCRYPTION_RESPONSE")
+        .chain_bytes(request_message)
+        .chain_bytes(cfrag.to_array())
+        .finalize()
+}
+
+/// The message signed by [`encrypt_signed()`](crate::encrypt_signed).
+#[cfg(feature = "dem")]
+pub(crate) fn signed_plaintext_message(
+    capsule: &Capsule,
+    plaintext: &[u8],
+) -> GenericArray<u8, U32> {
+    BytesDigest::new_with_dst(b"SIGNED_PLAINTEXT")
+        .chain_bytes(capsule.to_array())
+        .chain_bytes(plaintext)
+        .finalize()
+}
+
+#[cfg(feature = "dem")]
+pub(crate) fn hash_to_merkle_leaf(leaf: &[u8]) -> GenericArray<u8, U32> {
+    BytesDigest::new_with_dst(b"MERKLE_LEAF")
+        .chain_bytes(leaf)
+        .finalize()
+}
+
+#[cfg(feature = "dem")]
+pub(crate) fn hash_to_merkle_node(
+    left: &GenericArray<u8, U32>,
+    right: &GenericArray<u8, U32>,
+) -> GenericArray<u8, U32> {
+    BytesDigest::new_with_dst(b"MERKLE_NODE")
+        .chain_bytes(left)
+        .chain_bytes(right)
+        .finalize()
+}
+
 pub(crate) fn hash_to_cfrag_verification(
     points: &[CurvePoint],
     metadata: Option<&[u8]>,
@@ -58,34 +246,80 @@ pub(crate) fn hash_to_cfrag_verification(
     digest.finalize()
 }
 
+type KeyFragIDSize = <KeyFragID as RepresentableAsArray>::Size;
+type PointSize = <CurvePoint as RepresentableAsArray>::Size;
+type BoolSize = <bool as RepresentableAsArray>::Size;
+type ValiditySize = <ValidityWindow as RepresentableAsArray>::Size;
+type SchemeTagSize = U1;
+type KeyFragSignatureMessageSize = op!(SchemeTagSize
+    + KeyFragIDSize
+    + PointSize
+    + PointSize
+    + BoolSize
+    + PointSize
+    + BoolSize
+    + PointSize
+    + ValiditySize);
+
+/// The message signed in a key fragment.
+/// Its length depends on which keys are included,
+/// so it is kept in a buffer of the maximum size to avoid heap allocations.
+pub(crate) struct KeyFragSignatureMessage {
+    buffer: GenericArray<u8, KeyFragSignatureMessageSize>,
+    length: usize,
+}
+
+impl KeyFragSignatureMessage {
+    fn push(&mut self, bytes: &[u8]) {
+        self.buffer[self.length..self.length + bytes.len()].copy_from_slice(bytes);
+        self.length += bytes.len();
+    }
+
+    fn push_optional_key(&mut self, maybe_pk: Option<&PublicKey>) {
+        match maybe_pk {
+            Some(pk) => {
+                self.push(&true.to_array());
+                self.push(&pk.to_array());
+            }
+            None => self.push(&false.to_array()),
+        }
+    }
+}
+
+impl AsRef<[u8]> for KeyFragSignatureMessage {
+    fn as_ref(&self) -> &[u8] {
+        &self.buffer[..self.length]
+    }
+}
+
 pub(crate) fn kfrag_signature_message(
     kfrag_id: &KeyFragID,
     commitment: &CurvePoint,
     precursor: &CurvePoint,
     maybe_delegating_pk: Option<&PublicKey>,
     maybe_receiving_pk: Option<&PublicKey>,
-) -> Box<[u8]> {
-    let mut result = Vec::<u8>::new();
-
-    result.extend_from_slice(&kfrag_id.to_array());
-    result.extend_from_slice(&commitment.to_array());
-    result.extend_from_slice(&precursor.to_array());
-
-    match maybe_delegating_pk {
-        Some(delegating_pk) => {
-            result.extend_from_slice(&true.to_array());
-            result.extend_from_slice(&delegating_pk.to_array())
-        }
-        None => result.extend_from_slice(&false.to_array()),
+    maybe_validity: Option<&ValidityWindow>,
+) -> KeyFragSignatureMessage {
+    let mut result = KeyFragSignatureMessage {
+        buffer: GenericArray::default(),
+        length: 0,
     };
 
-    match maybe_receiving_pk {
-        Some(receiving_pk) => {
-            result.extend_from_slice(&true.to_array());
-            result.extend_from_slice(&receiving_pk.to_array())
-        }
-        None => result.extend_from_slice(&false.to_array()),
-    };
+    // The scheme tag prevents a Schnorr signature from being valid for the same message
+    // under a different scheme. The ECDSA message is kept untagged,
+    // so that the existing fragments stay valid.
+    #[cfg(feature = "schnorr")]
+    result.push(&[SignatureScheme::Schnorr as u8]);
+    result.push(&kfrag_id.to_array());
+    result.push(&commitment.to_array());
+    result.push(&precursor.to_array());
+    result.push_optional_key(maybe_delegating_pk);
+    result.push_optional_key(maybe_receiving_pk);
+    // An unbounded window is left out, so that the fragments created without one
+    // keep their original signatures.
+    if let Some(validity) = maybe_validity.filter(|validity| !validity.is_unbounded()) {
+        result.push(&validity.to_array());
+    }
 
-    result.into_boxed_slice()
+    result
 }
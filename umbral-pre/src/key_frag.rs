@@ -1,32 +1,71 @@
-use crate::curve::{CurvePoint, CurveScalar};
-use crate::hashing_ds::{hash_to_polynomial_arg, hash_to_shared_secret, kfrag_signature_message};
-use crate::keys::{PublicKey, SecretKey, Signature, Signer};
+#[cfg(feature = "role-alice")]
+use crate::capsule::lambda_coeffs;
+use crate::curve::{CompressedPoint, CurvePoint, CurveScalar};
+#[cfg(feature = "role-alice")]
+use crate::hashing::{kdf, KdfRng};
+use crate::hashing_ds::{
+    hash_to_kfrag_verification_token, hash_to_policy_fingerprint, kfrag_signature_message,
+};
+#[cfg(feature = "role-alice")]
+use crate::hashing_ds::{hash_to_polynomial_arg, hash_to_shared_secret};
+#[cfg(feature = "role-alice")]
+use crate::keys::{expect_signed, SecretKey, Signer, SignerError};
+use crate::keys::{PublicKey, Signature};
 use crate::params::Parameters;
 use crate::traits::{
     DeserializableFromArray, DeserializationError, RepresentableAsArray, SerializableToArray,
+    VersionedSerialization,
 };
 
 use alloc::boxed::Box;
 use alloc::vec::Vec;
+use core::fmt;
 
 use generic_array::sequence::Concat;
 use generic_array::GenericArray;
-use rand_core::{OsRng, RngCore};
-use typenum::{op, U32};
+#[cfg(feature = "role-alice")]
+use rand_core::{CryptoRng, RngCore};
+use subtle::{Choice, ConstantTimeEq};
+use typenum::{op, Unsigned, U16, U32};
+use zeroize::{Zeroize, Zeroizing};
+#[cfg(all(feature = "role-alice", feature = "parallel"))]
+use {rand_core::OsRng, rayon::prelude::*};
 
 #[allow(clippy::upper_case_acronyms)]
 type KeyFragIDSize = U32;
 
+/// The identifier of a key fragment,
+/// shared by the capsule fragments created with it.
 #[allow(clippy::upper_case_acronyms)]
 #[derive(Clone, Copy, Debug, PartialEq)]
-pub(crate) struct KeyFragID(GenericArray<u8, KeyFragIDSize>);
+pub struct KeyFragID(GenericArray<u8, KeyFragIDSize>);
 
 impl KeyFragID {
-    fn random() -> Self {
+    #[cfg(feature = "role-alice")]
+    pub(crate) fn random(rng: &mut (impl CryptoRng + RngCore)) -> Self {
         let mut bytes = GenericArray::<u8, KeyFragIDSize>::default();
-        OsRng.fill_bytes(&mut bytes);
+        rng.fill_bytes(&mut bytes);
         Self(bytes)
     }
+
+    /// Derives the identifier of the `index`-th fragment generated with the given label
+    /// (see [`generate_kfrags_with_label()`](crate::generate_kfrags_with_label)).
+    #[cfg(feature = "role-alice")]
+    pub(crate) fn from_label(
+        label: &[u8],
+        delegating_pk: &PublicKey,
+        receiving_pk: &PublicKey,
+        index: usize,
+    ) -> Self {
+        let info: Vec<u8> = b"KFRAG_ID/"
+            .iter()
+            .cloned()
+            .chain(delegating_pk.to_array())
+            .chain(receiving_pk.to_array())
+            .chain((index as u64).to_be_bytes().iter().cloned())
+            .collect();
+        Self(kdf::<KeyFragIDSize>(label, None, Some(&info)))
+    }
 }
 
 impl AsRef<[u8]> for KeyFragID {
@@ -51,20 +90,121 @@ impl DeserializableFromArray for KeyFragID {
     }
 }
 
+/// A hash of the keys a [`VerifiedKeyFrag`] was verified with
+/// (the verifying key, and the delegating and receiving keys, if they were provided),
+/// returned by [`VerifiedKeyFrag::policy_fingerprint()`].
+pub type PolicyFingerprint = GenericArray<u8, U32>;
+
+/// A hash of a [`VerifiedKeyFrag`] and the keys it was verified with,
+/// returned by [`VerifiedKeyFrag::verification_token()`].
+pub type KeyFragVerificationToken = GenericArray<u8, U32>;
+
+/// The time interval during which a key fragment can be used,
+/// covered by the signature of the delegating party.
+///
+/// The bounds are inclusive timestamps; this library only compares them
+/// with the time given to [`KeyFrag::verify_at()`],
+/// so the unit (e.g. seconds since the Unix epoch) is up to the application.
+///
+/// The window is a part of the serialized fragment (the last 16 bytes, as two big-endian
+/// integers, with `0` and `u64::MAX` for the missing bounds), so it survives any transport.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ValidityWindow {
+    not_before: u64,
+    not_after: u64,
+}
+
+impl ValidityWindow {
+    /// Creates a window with the given bounds (`None` meaning no bound).
+    pub fn new(not_before: Option<u64>, not_after: Option<u64>) -> Self {
+        Self {
+            not_before: not_before.unwrap_or(0),
+            not_after: not_after.unwrap_or(u64::MAX),
+        }
+    }
+
+    /// Creates a window without bounds.
+    pub fn unbounded() -> Self {
+        Self::new(None, None)
+    }
+
+    /// Returns the lower bound of the window, if any.
+    pub fn not_before(&self) -> Option<u64> {
+        Some(self.not_before).filter(|t| *t != 0)
+    }
+
+    /// Returns the upper bound of the window, if any.
+    pub fn not_after(&self) -> Option<u64> {
+        Some(self.not_after).filter(|t| *t != u64::MAX)
+    }
+
+    /// Returns `true` if the window has no bounds.
+    pub fn is_unbounded(&self) -> bool {
+        *self == Self::unbounded()
+    }
+
+    /// Returns `true` if `now` is within the window.
+    pub fn contains(&self, now: u64) -> bool {
+        self.not_before <= now && now <= self.not_after
+    }
+
+    #[cfg(feature = "role-alice")]
+    fn intersection(&self, other: &Self) -> Self {
+        Self {
+            not_before: core::cmp::max(self.not_before, other.not_before),
+            not_after: core::cmp::min(self.not_after, other.not_after),
+        }
+    }
+}
+
+impl Default for ValidityWindow {
+    fn default() -> Self {
+        Self::unbounded()
+    }
+}
+
+impl RepresentableAsArray for ValidityWindow {
+    type Size = U16;
+}
+
+impl SerializableToArray for ValidityWindow {
+    fn to_array(&self) -> GenericArray<u8, Self::Size> {
+        GenericArray::<u8, U16>::clone_from_slice(
+            &[self.not_before.to_be_bytes(), self.not_after.to_be_bytes()].concat(),
+        )
+    }
+}
+
+impl DeserializableFromArray for ValidityWindow {
+    fn from_array(arr: &GenericArray<u8, Self::Size>) -> Result<Self, DeserializationError> {
+        let mut not_before = [0u8; 8];
+        let mut not_after = [0u8; 8];
+        not_before.copy_from_slice(&arr[..8]);
+        not_after.copy_from_slice(&arr[8..]);
+        Ok(Self {
+            not_before: u64::from_be_bytes(not_before),
+            not_after: u64::from_be_bytes(not_after),
+        })
+    }
+}
+
 #[derive(Clone, Debug, PartialEq)]
 pub(crate) struct KeyFragProof {
     pub(crate) commitment: CurvePoint,
-    signature_for_proxy: Signature,
+    pub(crate) signature_for_proxy: Signature,
     signature_for_receiver: Signature,
-    delegating_key_signed: bool,
-    receiving_key_signed: bool,
+    pub(crate) delegating_key_signed: bool,
+    pub(crate) receiving_key_signed: bool,
+    pub(crate) validity: ValidityWindow,
 }
 
 type SignatureSize = <Signature as RepresentableAsArray>::Size;
 type ScalarSize = <CurveScalar as RepresentableAsArray>::Size;
 type PointSize = <CurvePoint as RepresentableAsArray>::Size;
 type BoolSize = <bool as RepresentableAsArray>::Size;
-type KeyFragProofSize = op!(PointSize + SignatureSize + SignatureSize + BoolSize + BoolSize);
+type ValidityWindowSize = <ValidityWindow as RepresentableAsArray>::Size;
+type KeyFragProofSize =
+    op!(PointSize + SignatureSize + SignatureSize + BoolSize + BoolSize + ValidityWindowSize);
 
 impl RepresentableAsArray for KeyFragProof {
     type Size = KeyFragProofSize;
@@ -78,6 +218,7 @@ impl SerializableToArray for KeyFragProof {
             .concat(self.signature_for_receiver.to_array())
             .concat(self.delegating_key_signed.to_array())
             .concat(self.receiving_key_signed.to_array())
+            .concat(self.validity.to_array())
     }
 }
 
@@ -87,13 +228,15 @@ impl DeserializableFromArray for KeyFragProof {
         let (signature_for_proxy, rest) = Signature::take(rest)?;
         let (signature_for_receiver, rest) = Signature::take(rest)?;
         let (delegating_key_signed, rest) = bool::take(rest)?;
-        let receiving_key_signed = bool::take_last(rest)?;
+        let (receiving_key_signed, rest) = bool::take(rest)?;
+        let validity = ValidityWindow::take_last(rest)?;
         Ok(Self {
             commitment,
             signature_for_proxy,
             signature_for_receiver,
             delegating_key_signed,
             receiving_key_signed,
+            validity,
         })
     }
 }
@@ -107,55 +250,66 @@ fn none_unless<T>(x: Option<T>, predicate: bool) -> Option<T> {
 }
 
 impl KeyFragProof {
+    #[cfg(feature = "role-alice")]
     fn from_base(
+        rng: &mut (impl CryptoRng + RngCore),
         base: &KeyFragBase,
         kfrag_id: &KeyFragID,
         kfrag_key: &CurveScalar,
         sign_delegating_key: bool,
         sign_receiving_key: bool,
-    ) -> Self {
+        validity: ValidityWindow,
+    ) -> Result<Self, SignerError> {
         let commitment = &base.params.u * kfrag_key;
 
         let maybe_delegating_pk = Some(&base.delegating_pk);
         let maybe_receiving_pk = Some(&base.receiving_pk);
 
-        let signature_for_receiver = base.signer.sign(
+        let signature_for_receiver = base.signer.sign_kfrag_with_rng(
+            rng,
             kfrag_signature_message(
                 &kfrag_id,
                 &commitment,
                 &base.precursor,
                 maybe_delegating_pk,
                 maybe_receiving_pk,
+                None,
             )
             .as_ref(),
-        );
+        )?;
 
-        let signature_for_proxy = base.signer.sign(
+        let signature_for_proxy = base.signer.sign_kfrag_with_rng(
+            rng,
             kfrag_signature_message(
                 &kfrag_id,
                 &commitment,
                 &base.precursor,
                 none_unless(maybe_delegating_pk, sign_delegating_key),
                 none_unless(maybe_receiving_pk, sign_receiving_key),
+                Some(&validity),
             )
             .as_ref(),
-        );
+        )?;
 
-        Self {
+        Ok(Self {
             commitment,
             signature_for_proxy,
             signature_for_receiver,
             delegating_key_signed: sign_delegating_key,
             receiving_key_signed: sign_receiving_key,
-        }
+            validity,
+        })
     }
 
+    #[cfg(feature = "role-ursula")]
     pub(crate) fn signature_for_receiver(&self) -> Signature {
         self.signature_for_receiver.clone()
     }
 }
 
 /// A fragment of the encrypting party's key used to create a [`CapsuleFrag`](`crate::CapsuleFrag`).
+///
+/// The key share is zeroized when the fragment is dropped.
 #[derive(Clone, Debug, PartialEq)]
 pub struct KeyFrag {
     params: Parameters,
@@ -165,6 +319,12 @@ pub struct KeyFrag {
     pub(crate) proof: KeyFragProof,
 }
 
+impl Drop for KeyFrag {
+    fn drop(&mut self) {
+        self.key.zeroize()
+    }
+}
+
 impl RepresentableAsArray for KeyFrag {
     type Size = op!(ScalarSize + ScalarSize + PointSize + KeyFragProofSize);
 }
@@ -196,6 +356,10 @@ impl DeserializableFromArray for KeyFrag {
     }
 }
 
+impl VersionedSerialization for KeyFrag {
+    const TYPE_TAG: u8 = 2;
+}
+
 /// Possible errors that can be returned by [`KeyFrag::verify`].
 #[derive(Debug, PartialEq)]
 pub enum KeyFragVerificationError {
@@ -209,12 +373,60 @@ pub enum KeyFragVerificationError {
     ReceivingKeyNotProvided,
     /// Inconsistent internal state leading to signature verification failure.
     IncorrectSignature,
+    /// The provided keys do not match the stored policy fingerprint.
+    PolicyFingerprintMismatch,
+    /// The fragment has a validity window, but no time was provided during verification
+    /// (see [`KeyFrag::verify_at`]).
+    TimeNotProvided,
+    /// The validity window of the fragment has not started yet.
+    NotYetValid,
+    /// The validity window of the fragment has ended.
+    Expired,
+}
+
+impl fmt::Display for KeyFragVerificationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::IncorrectCommitment => write!(f, "incorrect key fragment commitment"),
+            Self::DelegatingKeyNotProvided => {
+                write!(f, "the signed delegating key was not provided")
+            }
+            Self::ReceivingKeyNotProvided => write!(f, "the signed receiving key was not provided"),
+            Self::IncorrectSignature => write!(f, "incorrect key fragment signature"),
+            Self::PolicyFingerprintMismatch => {
+                write!(f, "the keys do not match the policy fingerprint")
+            }
+            Self::TimeNotProvided => write!(f, "the key fragment has a validity window"),
+            Self::NotYetValid => write!(f, "the key fragment is not valid yet"),
+            Self::Expired => write!(f, "the key fragment has expired"),
+        }
+    }
 }
 
 impl KeyFrag {
-    fn from_base(base: &KeyFragBase, sign_delegating_key: bool, sign_receiving_key: bool) -> Self {
-        let kfrag_id = KeyFragID::random();
+    /// The size of the byte representation of a key fragment
+    /// (returned by [`to_array()`](`SerializableToArray::to_array`)).
+    pub const SERIALIZED_SIZE: usize = <Self as RepresentableAsArray>::Size::USIZE;
 
+    #[cfg(feature = "role-alice")]
+    fn from_base(
+        rng: &mut (impl CryptoRng + RngCore),
+        base: &KeyFragBase,
+        sign_delegating_key: bool,
+        sign_receiving_key: bool,
+    ) -> Result<Self, SignerError> {
+        let kfrag_id = KeyFragID::random(rng);
+        Self::from_base_with_id(rng, base, kfrag_id, sign_delegating_key, sign_receiving_key)
+    }
+
+    #[cfg(feature = "role-alice")]
+    fn from_base_with_id(
+        rng: &mut (impl CryptoRng + RngCore),
+        base: &KeyFragBase,
+        kfrag_id: KeyFragID,
+        sign_delegating_key: bool,
+        sign_receiving_key: bool,
+    ) -> Result<Self, SignerError> {
         // The index of the re-encryption key share (which in Shamir's Secret
         // Sharing corresponds to x in the tuple (x, f(x)), with f being the
         // generating polynomial), is used to prevent reconstruction of the
@@ -231,20 +443,58 @@ impl KeyFrag {
         let rk = poly_eval(&base.coefficients, &share_index);
 
         let proof = KeyFragProof::from_base(
+            rng,
             &base,
             &kfrag_id,
             &rk,
             sign_delegating_key,
             sign_receiving_key,
-        );
+            base.validity,
+        )?;
 
-        Self {
+        Ok(Self {
             params: base.params,
             id: kfrag_id,
             key: rk,
             precursor: base.precursor,
             proof,
-        }
+        })
+    }
+
+    #[cfg(feature = "role-alice")]
+    fn refreshed(
+        rng: &mut (impl CryptoRng + RngCore),
+        base: &KeyFragBase,
+        kfrag: &KeyFrag,
+    ) -> Result<Self, SignerError> {
+        let share_index = hash_to_polynomial_arg(
+            &base.precursor,
+            &base.receiving_pk.to_point(),
+            &base.dh_point,
+            &kfrag.id,
+        );
+
+        // The refresh polynomial has a zero constant term,
+        // so the new shares interpolate to the same re-encryption key.
+        let rk = &kfrag.key + &poly_eval(&base.coefficients, &share_index);
+
+        let proof = KeyFragProof::from_base(
+            rng,
+            &base,
+            &kfrag.id,
+            &rk,
+            kfrag.proof.delegating_key_signed,
+            kfrag.proof.receiving_key_signed,
+            kfrag.proof.validity,
+        )?;
+
+        Ok(Self {
+            params: base.params,
+            id: kfrag.id,
+            key: rk,
+            precursor: base.precursor,
+            proof,
+        })
     }
 
     /// Verifies the integrity of the key fragment, given the signing key,
@@ -253,11 +503,56 @@ impl KeyFrag {
     /// If [`generate_kfrags()`](`crate::generate_kfrags()`) was called with `true`
     /// for `sign_delegating_key` or `sign_receiving_key`, and the respective key
     /// is not provided, the verification fails.
+    ///
+    /// Fragments with a [`ValidityWindow`] can only be verified with [`verify_at()`](`Self::verify_at`).
     pub fn verify(
         &self,
         verifying_pk: &PublicKey,
         maybe_delegating_pk: Option<&PublicKey>,
         maybe_receiving_pk: Option<&PublicKey>,
+    ) -> Result<VerifiedKeyFrag, KeyFragVerificationError> {
+        self.verify_impl(None, verifying_pk, maybe_delegating_pk, maybe_receiving_pk)
+    }
+
+    /// Same as [`verify()`](`Self::verify`), but additionally checks that `now`
+    /// is within the validity window of the fragment
+    /// (see [`generate_kfrags_with_validity()`](`crate::generate_kfrags_with_validity`)).
+    pub fn verify_at(
+        &self,
+        now: u64,
+        verifying_pk: &PublicKey,
+        maybe_delegating_pk: Option<&PublicKey>,
+        maybe_receiving_pk: Option<&PublicKey>,
+    ) -> Result<VerifiedKeyFrag, KeyFragVerificationError> {
+        self.verify_impl(
+            Some(now),
+            verifying_pk,
+            maybe_delegating_pk,
+            maybe_receiving_pk,
+        )
+    }
+
+    /// Returns the validity window of the fragment.
+    pub fn validity(&self) -> ValidityWindow {
+        self.proof.validity
+    }
+
+    /// Returns the identifier of the fragment.
+    pub fn id(&self) -> &KeyFragID {
+        &self.id
+    }
+
+    /// Returns the precursor point, shared by all the fragments of a policy.
+    pub fn precursor(&self) -> CompressedPoint {
+        self.precursor.to_array()
+    }
+
+    fn verify_impl(
+        &self,
+        maybe_now: Option<u64>,
+        verifying_pk: &PublicKey,
+        maybe_delegating_pk: Option<&PublicKey>,
+        maybe_receiving_pk: Option<&PublicKey>,
     ) -> Result<VerifiedKeyFrag, KeyFragVerificationError> {
         let u = self.params.u;
 
@@ -267,7 +562,25 @@ impl KeyFrag {
         let precursor = self.precursor;
 
         // We check that the commitment is well-formed
-        if commitment != &u * &key {
+        let correct_commitment = commitment == &u * &key;
+
+        // With the `wasm` feature the signature is checked even if the commitment is incorrect,
+        // so that the time spent does not depend on which check fails.
+        let correct_signature = (correct_commitment || cfg!(feature = "wasm"))
+            && self.proof.signature_for_proxy.verify(
+                verifying_pk,
+                kfrag_signature_message(
+                    &kfrag_id,
+                    &commitment,
+                    &precursor,
+                    none_unless(maybe_delegating_pk, self.proof.delegating_key_signed),
+                    none_unless(maybe_receiving_pk, self.proof.receiving_key_signed),
+                    Some(&self.proof.validity),
+                )
+                .as_ref(),
+            );
+
+        if !correct_commitment {
             return Err(KeyFragVerificationError::IncorrectCommitment);
         }
 
@@ -281,34 +594,133 @@ impl KeyFrag {
             return Err(KeyFragVerificationError::ReceivingKeyNotProvided);
         }
 
-        // Check the signature
+        if !correct_signature {
+            return Err(KeyFragVerificationError::IncorrectSignature);
+        }
+
+        // Check the validity window (after the signature, so that it can be trusted)
+        self.check_validity(maybe_now)?;
+
+        Ok(VerifiedKeyFrag {
+            kfrag: self.clone(),
+            policy_fingerprint: hash_to_policy_fingerprint(
+                verifying_pk,
+                maybe_delegating_pk,
+                maybe_receiving_pk,
+            ),
+        })
+    }
 
-        if !self.proof.signature_for_proxy.verify(
+    fn check_validity(&self, maybe_now: Option<u64>) -> Result<(), KeyFragVerificationError> {
+        let validity = self.proof.validity;
+        match maybe_now {
+            None if !validity.is_unbounded() => Err(KeyFragVerificationError::TimeNotProvided),
+            Some(now) if now < validity.not_before => Err(KeyFragVerificationError::NotYetValid),
+            Some(now) if now > validity.not_after => Err(KeyFragVerificationError::Expired),
+            _ => Ok(()),
+        }
+    }
+
+    /// Same as [`verify()`](`Self::verify`), but skips the signature and commitment checks
+    /// if `token` was returned by [`VerifiedKeyFrag::verification_token()`]
+    /// for this fragment and the same keys; otherwise performs the full verification.
+    ///
+    /// Intended for proxies verifying the same fragment on every request.
+    /// The token is a plain hash that anyone can compute, so it only proves
+    /// that the fragment was verified if it comes from a trusted source
+    /// (such as the proxy's own cache), and must never be accepted from the requester.
+    pub fn verify_cached(
+        &self,
+        token: &KeyFragVerificationToken,
+        verifying_pk: &PublicKey,
+        maybe_delegating_pk: Option<&PublicKey>,
+        maybe_receiving_pk: Option<&PublicKey>,
+    ) -> Result<VerifiedKeyFrag, KeyFragVerificationError> {
+        self.verify_cached_impl(
+            None,
+            token,
             verifying_pk,
-            kfrag_signature_message(
-                &kfrag_id,
-                &commitment,
-                &precursor,
-                none_unless(maybe_delegating_pk, self.proof.delegating_key_signed),
-                none_unless(maybe_receiving_pk, self.proof.receiving_key_signed),
-            )
-            .as_ref(),
-        ) {
-            return Err(KeyFragVerificationError::IncorrectSignature);
+            maybe_delegating_pk,
+            maybe_receiving_pk,
+        )
+    }
+
+    /// Same as [`verify_cached()`](`Self::verify_cached`), but additionally checks
+    /// that `now` is within the validity window of the fragment
+    /// (the window is checked even if the token matches).
+    pub fn verify_cached_at(
+        &self,
+        now: u64,
+        token: &KeyFragVerificationToken,
+        verifying_pk: &PublicKey,
+        maybe_delegating_pk: Option<&PublicKey>,
+        maybe_receiving_pk: Option<&PublicKey>,
+    ) -> Result<VerifiedKeyFrag, KeyFragVerificationError> {
+        self.verify_cached_impl(
+            Some(now),
+            token,
+            verifying_pk,
+            maybe_delegating_pk,
+            maybe_receiving_pk,
+        )
+    }
+
+    fn verify_cached_impl(
+        &self,
+        maybe_now: Option<u64>,
+        token: &KeyFragVerificationToken,
+        verifying_pk: &PublicKey,
+        maybe_delegating_pk: Option<&PublicKey>,
+        maybe_receiving_pk: Option<&PublicKey>,
+    ) -> Result<VerifiedKeyFrag, KeyFragVerificationError> {
+        let policy_fingerprint =
+            hash_to_policy_fingerprint(verifying_pk, maybe_delegating_pk, maybe_receiving_pk);
+        if hash_to_kfrag_verification_token(self, &policy_fingerprint) != *token {
+            return self.verify_impl(
+                maybe_now,
+                verifying_pk,
+                maybe_delegating_pk,
+                maybe_receiving_pk,
+            );
         }
 
+        self.check_validity(maybe_now)?;
+
         Ok(VerifiedKeyFrag {
             kfrag: self.clone(),
+            policy_fingerprint,
         })
     }
+
+    /// Same as [`verify()`](`Self::verify`), but additionally checks that the keys
+    /// match the fingerprint returned by [`VerifiedKeyFrag::policy_fingerprint()`]
+    /// when the fragment was originally verified.
+    ///
+    /// Intended for restoring fragments from a storage,
+    /// so that a fragment cannot be silently paired with the keys of another policy.
+    pub fn verify_with_fingerprint(
+        &self,
+        policy_fingerprint: &PolicyFingerprint,
+        verifying_pk: &PublicKey,
+        maybe_delegating_pk: Option<&PublicKey>,
+        maybe_receiving_pk: Option<&PublicKey>,
+    ) -> Result<VerifiedKeyFrag, KeyFragVerificationError> {
+        let verified_kfrag = self.verify(verifying_pk, maybe_delegating_pk, maybe_receiving_pk)?;
+        if verified_kfrag.policy_fingerprint != *policy_fingerprint {
+            return Err(KeyFragVerificationError::PolicyFingerprintMismatch);
+        }
+        Ok(verified_kfrag)
+    }
 }
 
 /// Verified key fragment, good for reencryption.
 /// Can be serialized, but cannot be deserialized directly.
-/// It can only be obtained from [`KeyFrag::verify`].
+/// It can only be obtained from [`KeyFrag::verify`]
+/// (or, for fragments verified earlier, [`VerifiedKeyFrag::from_verified_bytes`]).
 #[derive(Debug, Clone, PartialEq)]
 pub struct VerifiedKeyFrag {
     pub(crate) kfrag: KeyFrag,
+    policy_fingerprint: PolicyFingerprint,
 }
 
 impl RepresentableAsArray for VerifiedKeyFrag {
@@ -322,17 +734,264 @@ impl SerializableToArray for VerifiedKeyFrag {
 }
 
 impl VerifiedKeyFrag {
+    /// Produces the versioned envelope of the fragment,
+    /// to be restored with [`KeyFrag::from_versioned_bytes()`].
+    pub fn to_versioned_bytes(&self) -> Box<[u8]> {
+        self.kfrag.to_versioned_bytes()
+    }
+
+    #[cfg(feature = "role-alice")]
     pub(crate) fn from_base(
+        rng: &mut (impl CryptoRng + RngCore),
+        base: &KeyFragBase,
+        sign_delegating_key: bool,
+        sign_receiving_key: bool,
+    ) -> Result<Self, SignerError> {
+        Ok(Self {
+            kfrag: KeyFrag::from_base(rng, base, sign_delegating_key, sign_receiving_key)?,
+            policy_fingerprint: base.policy_fingerprint(),
+        })
+    }
+
+    #[cfg(feature = "role-alice")]
+    fn from_base_with_id(
+        rng: &mut (impl CryptoRng + RngCore),
         base: &KeyFragBase,
+        kfrag_id: KeyFragID,
+        sign_delegating_key: bool,
+        sign_receiving_key: bool,
+    ) -> Result<Self, SignerError> {
+        Ok(Self {
+            kfrag: KeyFrag::from_base_with_id(
+                rng,
+                base,
+                kfrag_id,
+                sign_delegating_key,
+                sign_receiving_key,
+            )?,
+            policy_fingerprint: base.policy_fingerprint(),
+        })
+    }
+
+    #[cfg(feature = "role-alice")]
+    pub(crate) fn refreshed(
+        rng: &mut (impl CryptoRng + RngCore),
+        base: &KeyFragBase,
+        kfrag: &VerifiedKeyFrag,
+    ) -> Result<Self, SignerError> {
+        Ok(Self {
+            kfrag: KeyFrag::refreshed(rng, base, &kfrag.kfrag)?,
+            policy_fingerprint: base.policy_fingerprint(),
+        })
+    }
+
+    /// Returns the fingerprint of the keys this fragment was verified with.
+    ///
+    /// The fingerprint is not a part of the serialized fragment;
+    /// it should be stored alongside it and checked with
+    /// [`KeyFrag::verify_with_fingerprint()`] when the fragment is loaded.
+    /// Fragments returned by [`generate_kfrags()`](`crate::generate_kfrags`)
+    /// have the fingerprint of all three keys.
+    pub fn policy_fingerprint(&self) -> PolicyFingerprint {
+        self.policy_fingerprint
+    }
+
+    /// Returns a token allowing one to skip the cryptographic checks
+    /// when the same fragment is verified again with the same keys
+    /// (see [`KeyFrag::verify_cached()`]).
+    ///
+    /// Like the fingerprint, the token is not a part of the serialized fragment.
+    pub fn verification_token(&self) -> KeyFragVerificationToken {
+        hash_to_kfrag_verification_token(&self.kfrag, &self.policy_fingerprint)
+    }
+
+    /// Returns the identifier of the fragment.
+    pub fn id(&self) -> &KeyFragID {
+        self.kfrag.id()
+    }
+
+    /// Restores a fragment verified earlier (e.g. by a previous stage of the application)
+    /// from its byte representation and the fingerprint returned by
+    /// [`policy_fingerprint()`](`Self::policy_fingerprint`) at the time.
+    ///
+    /// **Warning:** no verification is performed; the caller is responsible
+    /// for the bytes coming from a trusted source (such as its own storage).
+    /// Otherwise use [`KeyFrag::verify_with_fingerprint()`].
+    pub fn from_verified_bytes(
+        bytes: impl AsRef<[u8]>,
+        policy_fingerprint: &PolicyFingerprint,
+    ) -> Result<Self, DeserializationError> {
+        KeyFrag::from_bytes(bytes).map(|kfrag| Self {
+            kfrag,
+            policy_fingerprint: *policy_fingerprint,
+        })
+    }
+
+    /// Returns the unverified fragment, e.g. to send it over to another party.
+    pub fn unverify(self) -> KeyFrag {
+        self.kfrag
+    }
+
+    /// Creates `num_kfrags` fragments from the same base in parallel.
+    /// Each worker draws randomness from its own handle to the OS RNG,
+    /// since a single `&mut` RNG cannot be shared between threads.
+    #[cfg(all(feature = "role-alice", feature = "parallel"))]
+    pub(crate) fn from_base_parallel(
+        base: &KeyFragBase,
+        num_kfrags: usize,
+        sign_delegating_key: bool,
+        sign_receiving_key: bool,
+    ) -> Result<Box<[Self]>, SignerError> {
+        (0..num_kfrags)
+            .into_par_iter()
+            .map(|_| Self::from_base(&mut OsRng, base, sign_delegating_key, sign_receiving_key))
+            .collect::<Result<Vec<_>, _>>()
+            .map(Vec::into_boxed_slice)
+    }
+}
+
+/// An iterator creating key fragments one at a time,
+/// returned by [`generate_kfrags_iter()`](crate::generate_kfrags_iter).
+///
+/// Only the secret polynomial (of size `threshold`) is kept,
+/// so the memory usage does not depend on the number of fragments.
+#[cfg(feature = "role-alice")]
+pub struct KeyFragGenerator<R: CryptoRng + RngCore> {
+    rng: R,
+    base: KeyFragBase,
+    remaining: usize,
+    sign_delegating_key: bool,
+    sign_receiving_key: bool,
+}
+
+#[cfg(feature = "role-alice")]
+impl<R: CryptoRng + RngCore> KeyFragGenerator<R> {
+    pub(crate) fn new(
+        rng: R,
+        base: KeyFragBase,
+        num_kfrags: usize,
         sign_delegating_key: bool,
         sign_receiving_key: bool,
     ) -> Self {
         Self {
-            kfrag: KeyFrag::from_base(base, sign_delegating_key, sign_receiving_key),
+            rng,
+            base,
+            remaining: num_kfrags,
+            sign_delegating_key,
+            sign_receiving_key,
+        }
+    }
+}
+
+#[cfg(feature = "role-alice")]
+impl<R: CryptoRng + RngCore> Iterator for KeyFragGenerator<R> {
+    type Item = VerifiedKeyFrag;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
         }
+        self.remaining -= 1;
+        Some(expect_signed(VerifiedKeyFrag::from_base(
+            &mut self.rng,
+            &self.base,
+            self.sign_delegating_key,
+            self.sign_receiving_key,
+        )))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
     }
 }
 
+#[cfg(feature = "role-alice")]
+impl<R: CryptoRng + RngCore> ExactSizeIterator for KeyFragGenerator<R> {}
+
+/// The secret needed to refresh a set of key fragments with
+/// [`refresh_kfrags()`](crate::refresh_kfrags), returned by
+/// [`generate_refreshable_kfrags()`](crate::generate_refreshable_kfrags).
+///
+/// Together with `threshold` key fragments it allows one to recover
+/// the delegating secret key (just as the receiving secret key does),
+/// so it must be kept by the delegating party and never given to the proxies.
+/// Can be serialized for storage.
+///
+/// The key is zeroized when dropped, and cannot be cloned,
+/// so that no copies of it are left in memory unintentionally.
+// No Debug derivation, to avoid exposing the key accidentally.
+pub struct RefreshKey {
+    precursor: CurvePoint,
+    dh_point: CurvePoint,
+    delegating_pk: PublicKey,
+    receiving_pk: PublicKey,
+}
+
+impl ConstantTimeEq for RefreshKey {
+    fn ct_eq(&self, other: &Self) -> Choice {
+        self.precursor.ct_eq(&other.precursor)
+            & self.dh_point.ct_eq(&other.dh_point)
+            & self.delegating_pk.ct_eq(&other.delegating_pk)
+            & self.receiving_pk.ct_eq(&other.receiving_pk)
+    }
+}
+
+impl PartialEq for RefreshKey {
+    fn eq(&self, other: &Self) -> bool {
+        self.ct_eq(other).into()
+    }
+}
+
+impl Zeroize for RefreshKey {
+    fn zeroize(&mut self) {
+        // The other fields are public.
+        self.dh_point.zeroize()
+    }
+}
+
+impl Drop for RefreshKey {
+    fn drop(&mut self) {
+        self.zeroize()
+    }
+}
+
+impl RefreshKey {
+    /// Returns `true` if the key fragment was created under this refresh key.
+    pub fn matches(&self, kfrag: &VerifiedKeyFrag) -> bool {
+        kfrag.kfrag.precursor == self.precursor
+    }
+}
+
+impl RepresentableAsArray for RefreshKey {
+    type Size = op!(PointSize + PointSize + PointSize + PointSize);
+}
+
+impl SerializableToArray for RefreshKey {
+    fn to_array(&self) -> GenericArray<u8, Self::Size> {
+        self.precursor
+            .to_array()
+            .concat(self.dh_point.to_array())
+            .concat(self.delegating_pk.to_array())
+            .concat(self.receiving_pk.to_array())
+    }
+}
+
+impl DeserializableFromArray for RefreshKey {
+    fn from_array(arr: &GenericArray<u8, Self::Size>) -> Result<Self, DeserializationError> {
+        let (precursor, rest) = CurvePoint::take(*arr)?;
+        let (dh_point, rest) = CurvePoint::take(rest)?;
+        let (delegating_pk, rest) = PublicKey::take(rest)?;
+        let receiving_pk = PublicKey::take_last(rest)?;
+        Ok(Self {
+            precursor,
+            dh_point,
+            delegating_pk,
+            receiving_pk,
+        })
+    }
+}
+
+#[cfg(feature = "role-alice")]
 pub(crate) struct KeyFragBase {
     signer: Signer,
     precursor: CurvePoint,
@@ -341,10 +1000,13 @@ pub(crate) struct KeyFragBase {
     delegating_pk: PublicKey,
     receiving_pk: PublicKey,
     coefficients: Box<[CurveScalar]>,
+    validity: ValidityWindow,
 }
 
+#[cfg(feature = "role-alice")]
 impl KeyFragBase {
     pub fn new(
+        rng: &mut (impl CryptoRng + RngCore),
         delegating_sk: &SecretKey,
         receiving_pk: &PublicKey,
         signer: &Signer,
@@ -357,19 +1019,21 @@ impl KeyFragBase {
 
         let receiving_pk_point = receiving_pk.to_point();
 
-        let (d, precursor, dh_point) = loop {
+        let (mut d, precursor, dh_point) = loop {
             // The precursor point is used as an ephemeral public key in a DH key exchange,
             // and the resulting shared secret 'dh_point' is used to derive other secret values
-            let private_precursor = CurveScalar::random_nonzero();
+            let mut private_precursor = CurveScalar::random_nonzero(rng);
             let precursor = &g * &private_precursor;
 
             let dh_point = &receiving_pk_point * &private_precursor;
+            private_precursor.zeroize();
 
             // Secret value 'd' allows to make Umbral non-interactive
             let d = hash_to_shared_secret(&precursor, &receiving_pk_point, &dh_point);
 
             // At the moment we cannot statically ensure `d` is a `NonZeroScalar`,
             // but we need it to be non-zero for the algorithm to work.
+            // Variable-time: a retry happens with a negligible probability.
             if !d.is_zero() {
                 break (d, precursor, dh_point);
             }
@@ -377,12 +1041,15 @@ impl KeyFragBase {
 
         // Coefficients of the generating polynomial
         // `invert()` is guaranteed not to panic because `d` is nonzero.
-        let coefficient0 = &delegating_sk.to_secret_scalar() * &(d.invert().unwrap());
+        let mut delegating_scalar = delegating_sk.to_secret_scalar();
+        let coefficient0 = &delegating_scalar * &(d.invert().unwrap());
+        delegating_scalar.zeroize();
+        d.zeroize();
 
         let mut coefficients = Vec::<CurveScalar>::with_capacity(threshold);
         coefficients.push(coefficient0);
         for _i in 1..threshold {
-            coefficients.push(CurveScalar::random_nonzero());
+            coefficients.push(CurveScalar::random_nonzero(rng));
         }
 
         Self {
@@ -393,12 +1060,224 @@ impl KeyFragBase {
             delegating_pk,
             receiving_pk: *receiving_pk,
             coefficients: coefficients.into_boxed_slice(),
+            validity: ValidityWindow::unbounded(),
+        }
+    }
+
+    /// Creates a base for refreshing the fragments created under `refresh_key`:
+    /// the generating polynomial has a zero constant term.
+    pub fn for_refresh(
+        rng: &mut (impl CryptoRng + RngCore),
+        refresh_key: &RefreshKey,
+        signer: &Signer,
+        threshold: usize,
+    ) -> Self {
+        let mut coefficients = Vec::<CurveScalar>::with_capacity(threshold);
+        coefficients.push(CurveScalar::default());
+        for _i in 1..threshold {
+            coefficients.push(CurveScalar::random_nonzero(rng));
+        }
+
+        Self {
+            signer: signer.clone(),
+            precursor: refresh_key.precursor,
+            dh_point: refresh_key.dh_point,
+            params: Parameters::new(),
+            delegating_pk: refresh_key.delegating_pk,
+            receiving_pk: refresh_key.receiving_pk,
+            coefficients: coefficients.into_boxed_slice(),
+            // Refreshed fragments keep their own windows.
+            validity: ValidityWindow::unbounded(),
+        }
+    }
+
+    /// Creates a base for regenerating the re-encryption key held by `kfrags`
+    /// with a new threshold.
+    ///
+    /// Returns `None` if the fragments do not determine the re-encryption key
+    /// (there are fewer of them than the original threshold, or some are repeated).
+    ///
+    /// The interpolation reconstructs the delegating secret key in memory,
+    /// so this must only be called by the delegating party
+    /// (see [`regenerate_kfrags()`](crate::regenerate_kfrags)).
+    pub fn for_regeneration(
+        rng: &mut (impl CryptoRng + RngCore),
+        refresh_key: &RefreshKey,
+        signer: &Signer,
+        kfrags: &[VerifiedKeyFrag],
+        threshold: usize,
+    ) -> Option<Self> {
+        let receiving_pk_point = refresh_key.receiving_pk.to_point();
+        let share_indices: Vec<_> = kfrags
+            .iter()
+            .map(|kfrag| {
+                hash_to_polynomial_arg(
+                    &refresh_key.precursor,
+                    &receiving_pk_point,
+                    &refresh_key.dh_point,
+                    &kfrag.kfrag.id,
+                )
+            })
+            .collect();
+
+        // Interpolating the shares at zero gives the constant term of the generating polynomial.
+        let lambdas = lambda_coeffs(&share_indices)?;
+        let mut coefficient0 = Zeroizing::new(CurveScalar::default());
+        for (lambda_i, kfrag) in lambdas.iter().zip(kfrags.iter()) {
+            *coefficient0 = &*coefficient0 + &(lambda_i * &kfrag.kfrag.key);
+        }
+
+        // The constant term is `delegating_sk / d`, which can be checked against `delegating_pk`.
+        // Note that the product is the delegating secret key itself.
+        // Variable-time: the outcome of the check is public (it is the result of the function).
+        // If there were not enough shares, the interpolation result is random.
+        let d = hash_to_shared_secret(
+            &refresh_key.precursor,
+            &receiving_pk_point,
+            &refresh_key.dh_point,
+        );
+        let delegating_scalar = Zeroizing::new(&*coefficient0 * &d);
+        let g = CurvePoint::generator();
+        if &g * &*delegating_scalar != refresh_key.delegating_pk.to_point() {
+            return None;
+        }
+
+        let mut coefficients = Vec::<CurveScalar>::with_capacity(threshold);
+        coefficients.push(*coefficient0);
+        for _i in 1..threshold {
+            coefficients.push(CurveScalar::random_nonzero(rng));
+        }
+
+        Some(Self {
+            signer: signer.clone(),
+            precursor: refresh_key.precursor,
+            dh_point: refresh_key.dh_point,
+            params: Parameters::new(),
+            delegating_pk: refresh_key.delegating_pk,
+            receiving_pk: refresh_key.receiving_pk,
+            coefficients: coefficients.into_boxed_slice(),
+            // The new fragments must not outlive any of the old ones.
+            validity: kfrags
+                .iter()
+                .fold(ValidityWindow::unbounded(), |window, kfrag| {
+                    window.intersection(&kfrag.kfrag.proof.validity)
+                }),
+        })
+    }
+
+    /// Sets the validity window of the fragments created from this base.
+    pub fn with_validity(mut self, validity: ValidityWindow) -> Self {
+        self.validity = validity;
+        self
+    }
+
+    pub fn refresh_key(&self) -> RefreshKey {
+        RefreshKey {
+            precursor: self.precursor,
+            dh_point: self.dh_point,
+            delegating_pk: self.delegating_pk,
+            receiving_pk: self.receiving_pk,
         }
     }
+
+    fn policy_fingerprint(&self) -> PolicyFingerprint {
+        hash_to_policy_fingerprint(
+            &self.signer.verifying_key(),
+            Some(&self.delegating_pk),
+            Some(&self.receiving_pk),
+        )
+    }
+}
+
+#[cfg(feature = "role-alice")]
+impl Drop for KeyFragBase {
+    fn drop(&mut self) {
+        // The coefficients (in particular, the first one) allow one to reconstruct
+        // the delegating secret key.
+        self.coefficients.zeroize()
+    }
+}
+
+/// A [`KeyFragBase`] with all the randomness derived from the delegating key and a label,
+/// creating the fragments for
+/// [`generate_kfrags_with_label()`](crate::generate_kfrags_with_label)
+/// and [`generate_single_kfrag()`](crate::generate_single_kfrag).
+#[cfg(feature = "role-alice")]
+pub(crate) struct LabeledKeyFragBase<'a> {
+    base: KeyFragBase,
+    seed: GenericArray<u8, U32>,
+    label: &'a [u8],
+}
+
+#[cfg(feature = "role-alice")]
+impl<'a> LabeledKeyFragBase<'a> {
+    pub fn new(
+        delegating_sk: &SecretKey,
+        receiving_pk: &PublicKey,
+        signer: &Signer,
+        threshold: usize,
+        label: &'a [u8],
+    ) -> Self {
+        // The threshold is included, so that the polynomials of the sets
+        // created with the same label but different thresholds are unrelated.
+        let info: Vec<u8> = b"KFRAG_LABEL_SEED/"
+            .iter()
+            .cloned()
+            .chain(receiving_pk.to_array())
+            .chain((threshold as u64).to_be_bytes().iter().cloned())
+            .collect();
+        let mut scalar = delegating_sk.to_secret_scalar();
+        let mut scalar_bytes = scalar.to_array();
+        scalar.zeroize();
+        let seed = kdf::<U32>(&scalar_bytes, Some(label), Some(&info));
+        scalar_bytes.zeroize();
+
+        let mut rng = KdfRng::new(&seed, b"KFRAG_BASE");
+        let base = KeyFragBase::new(&mut rng, delegating_sk, receiving_pk, signer, threshold);
+
+        Self { base, seed, label }
+    }
+
+    /// Creates the `index`-th fragment.
+    ///
+    /// Each fragment has its own RNG, so that it does not depend on the ones created before it.
+    pub fn kfrag(
+        &self,
+        index: usize,
+        sign_delegating_key: bool,
+        sign_receiving_key: bool,
+    ) -> Result<VerifiedKeyFrag, SignerError> {
+        let kfrag_id = KeyFragID::from_label(
+            self.label,
+            &self.base.delegating_pk,
+            &self.base.receiving_pk,
+            index,
+        );
+        let mut rng = KdfRng::new(&self.seed, kfrag_id.as_ref());
+        VerifiedKeyFrag::from_base_with_id(
+            &mut rng,
+            &self.base,
+            kfrag_id,
+            sign_delegating_key,
+            sign_receiving_key,
+        )
+    }
+}
+
+#[cfg(feature = "role-alice")]
+impl Drop for LabeledKeyFragBase<'_> {
+    fn drop(&mut self) {
+        self.seed.zeroize()
+    }
 }
 
-// Coefficients of the generating polynomial
-fn poly_eval(coeffs: &[CurveScalar], x: &CurveScalar) -> CurveScalar {
+/// Evaluates the polynomial `sum_i coeffs[i] * x^i` using Horner's method.
+/// Requires `coeffs` to be non-empty.
+///
+/// This function is pure, so that it can be targeted by verification and extraction tools.
+#[cfg(any(feature = "role-alice", feature = "threshold"))]
+#[cfg_attr(hax, hax_lib::requires(coeffs.len() > 0))]
+pub(crate) fn poly_eval(coeffs: &[CurveScalar], x: &CurveScalar) -> CurveScalar {
     let mut result: CurveScalar = coeffs[coeffs.len() - 1];
     for i in (0..coeffs.len() - 1).rev() {
         result = &(&result * x) + &coeffs[i];
@@ -406,13 +1285,23 @@ fn poly_eval(coeffs: &[CurveScalar], x: &CurveScalar) -> CurveScalar {
     result
 }
 
-#[cfg(test)]
+#[cfg(all(test, feature = "role-alice"))]
 mod tests {
 
     use alloc::boxed::Box;
+    #[cfg(all(feature = "parallel", feature = "role-ursula", feature = "role-bob"))]
+    use alloc::vec::Vec;
 
-    use super::{KeyFrag, KeyFragBase, KeyFragVerificationError, VerifiedKeyFrag};
-    use crate::{DeserializableFromArray, PublicKey, SecretKey, SerializableToArray, Signer};
+    use rand_core::OsRng;
+
+    use super::{KeyFrag, KeyFragBase, KeyFragVerificationError, ValidityWindow, VerifiedKeyFrag};
+    use crate::curve::CurvePoint;
+    #[cfg(all(feature = "parallel", feature = "role-ursula", feature = "role-bob"))]
+    use crate::{decapsulate_reencrypted, encapsulate, reencrypt};
+    use crate::{
+        generate_kfrags_with_validity, DeserializableFromArray, PublicKey, SecretKey,
+        SerializableToArray, Signer, VersionedSerialization,
+    };
 
     fn prepare_kfrags(
         sign_delegating_key: bool,
@@ -428,11 +1317,14 @@ mod tests {
         let receiving_sk = SecretKey::random();
         let receiving_pk = PublicKey::from_secret_key(&receiving_sk);
 
-        let base = KeyFragBase::new(&delegating_sk, &receiving_pk, &signer, 2);
+        let base = KeyFragBase::new(&mut OsRng, &delegating_sk, &receiving_pk, &signer, 2);
         let vkfrags = [
-            VerifiedKeyFrag::from_base(&base, sign_delegating_key, sign_receiving_key),
-            VerifiedKeyFrag::from_base(&base, sign_delegating_key, sign_receiving_key),
-            VerifiedKeyFrag::from_base(&base, sign_delegating_key, sign_receiving_key),
+            VerifiedKeyFrag::from_base(&mut OsRng, &base, sign_delegating_key, sign_receiving_key)
+                .unwrap(),
+            VerifiedKeyFrag::from_base(&mut OsRng, &base, sign_delegating_key, sign_receiving_key)
+                .unwrap(),
+            VerifiedKeyFrag::from_base(&mut OsRng, &base, sign_delegating_key, sign_receiving_key)
+                .unwrap(),
         ];
 
         (delegating_pk, receiving_pk, verifying_pk, Box::new(vkfrags))
@@ -480,4 +1372,197 @@ mod tests {
             }
         }
     }
+
+    #[test]
+    fn test_policy_fingerprint() {
+        let (delegating_pk, receiving_pk, verifying_pk, vkfrags) = prepare_kfrags(false, false);
+        let fingerprint = vkfrags[0].policy_fingerprint();
+
+        let kfrag = KeyFrag::from_array(&vkfrags[0].to_array()).unwrap();
+        let vkfrag = kfrag
+            .verify_with_fingerprint(
+                &fingerprint,
+                &verifying_pk,
+                Some(&delegating_pk),
+                Some(&receiving_pk),
+            )
+            .unwrap();
+        assert_eq!(vkfrag, vkfrags[0]);
+
+        // The keys are not signed, so the verification passes,
+        // but the fingerprint catches the wrong key (or the missing one).
+        let other_pk = PublicKey::from_secret_key(&SecretKey::random());
+        assert!(kfrag
+            .verify(&verifying_pk, Some(&other_pk), Some(&receiving_pk))
+            .is_ok());
+        assert_eq!(
+            kfrag.verify_with_fingerprint(
+                &fingerprint,
+                &verifying_pk,
+                Some(&other_pk),
+                Some(&receiving_pk)
+            ),
+            Err(KeyFragVerificationError::PolicyFingerprintMismatch)
+        );
+        assert_eq!(
+            kfrag.verify_with_fingerprint(&fingerprint, &verifying_pk, None, Some(&receiving_pk)),
+            Err(KeyFragVerificationError::PolicyFingerprintMismatch)
+        );
+
+        // A fragment verified earlier can be restored without the keys.
+        let restored =
+            VerifiedKeyFrag::from_verified_bytes(vkfrags[0].to_array(), &fingerprint).unwrap();
+        assert_eq!(restored, vkfrags[0]);
+        assert_eq!(restored.unverify(), kfrag);
+    }
+
+    #[test]
+    fn test_validity_window() {
+        let delegating_sk = SecretKey::random();
+        let signing_sk = SecretKey::random();
+        let signer = Signer::new(&signing_sk);
+        let verifying_pk = PublicKey::from_secret_key(&signing_sk);
+        let receiving_pk = PublicKey::from_secret_key(&SecretKey::random());
+
+        let validity = ValidityWindow::new(Some(100), Some(200));
+        let vkfrags = generate_kfrags_with_validity(
+            &delegating_sk,
+            &receiving_pk,
+            &signer,
+            2,
+            3,
+            false,
+            false,
+            validity,
+        );
+        let kfrag = KeyFrag::from_versioned_bytes(vkfrags[0].to_versioned_bytes()).unwrap();
+        assert_eq!(kfrag.validity(), validity);
+
+        assert!(kfrag.verify_at(100, &verifying_pk, None, None).is_ok());
+        assert!(kfrag.verify_at(200, &verifying_pk, None, None).is_ok());
+        assert_eq!(
+            kfrag.verify_at(99, &verifying_pk, None, None),
+            Err(KeyFragVerificationError::NotYetValid)
+        );
+        assert_eq!(
+            kfrag.verify_at(201, &verifying_pk, None, None),
+            Err(KeyFragVerificationError::Expired)
+        );
+        assert_eq!(
+            kfrag.verify(&verifying_pk, None, None),
+            Err(KeyFragVerificationError::TimeNotProvided)
+        );
+
+        // The window is covered by the signature.
+        let mut forged = kfrag.clone();
+        forged.proof.validity = ValidityWindow::new(Some(100), None);
+        assert_eq!(
+            forged.verify_at(300, &verifying_pk, None, None),
+            Err(KeyFragVerificationError::IncorrectSignature)
+        );
+
+        // The bare array carries the window as well.
+        let bare = KeyFrag::from_array(&vkfrags[0].to_array()).unwrap();
+        assert_eq!(bare, kfrag);
+        assert!(bare.verify_at(150, &verifying_pk, None, None).is_ok());
+
+        // Fragments without a window verify either way.
+        let (_delegating_pk, _receiving_pk, verifying_pk, vkfrags) = prepare_kfrags(false, false);
+        let kfrag = KeyFrag::from_array(&vkfrags[0].to_array()).unwrap();
+        assert!(kfrag.validity().is_unbounded());
+        assert!(kfrag.verify(&verifying_pk, None, None).is_ok());
+        assert!(kfrag.verify_at(u64::MAX, &verifying_pk, None, None).is_ok());
+    }
+
+    #[test]
+    fn test_verification_token() {
+        let (delegating_pk, receiving_pk, verifying_pk, vkfrags) = prepare_kfrags(true, true);
+        let token = vkfrags[0].verification_token();
+        let kfrag = vkfrags[0].clone().unverify();
+
+        let vkfrag = kfrag
+            .verify_cached(
+                &token,
+                &verifying_pk,
+                Some(&delegating_pk),
+                Some(&receiving_pk),
+            )
+            .unwrap();
+        assert_eq!(vkfrag, vkfrags[0]);
+
+        // The token is bound to the fragment and the keys.
+        let other_kfrag = vkfrags[1].clone().unverify();
+        assert_ne!(vkfrags[1].verification_token(), token);
+        assert!(other_kfrag
+            .verify_cached(
+                &token,
+                &verifying_pk,
+                Some(&delegating_pk),
+                Some(&receiving_pk)
+            )
+            .is_ok());
+        assert_eq!(
+            kfrag.verify_cached(&token, &verifying_pk, None, Some(&receiving_pk)),
+            Err(KeyFragVerificationError::DelegatingKeyNotProvided)
+        );
+
+        // A mismatched token falls back to the full verification.
+        let mut forged = kfrag.clone();
+        forged.proof.commitment = &forged.proof.commitment + &CurvePoint::generator();
+        assert_eq!(
+            forged.verify_cached(
+                &token,
+                &verifying_pk,
+                Some(&delegating_pk),
+                Some(&receiving_pk)
+            ),
+            Err(KeyFragVerificationError::IncorrectCommitment)
+        );
+    }
+
+    #[cfg(all(feature = "parallel", feature = "role-ursula", feature = "role-bob"))]
+    #[test]
+    fn test_from_base_parallel() {
+        let delegating_sk = SecretKey::random();
+        let delegating_pk = PublicKey::from_secret_key(&delegating_sk);
+
+        let signing_sk = SecretKey::random();
+        let signer = Signer::new(&signing_sk);
+        let verifying_pk = PublicKey::from_secret_key(&signing_sk);
+
+        let receiving_sk = SecretKey::random();
+        let receiving_pk = PublicKey::from_secret_key(&receiving_sk);
+
+        let base = KeyFragBase::new(&mut OsRng, &delegating_sk, &receiving_pk, &signer, 2);
+        let serial: Vec<_> = (0..3)
+            .map(|_| VerifiedKeyFrag::from_base(&mut OsRng, &base, true, true).unwrap())
+            .collect();
+        let parallel = VerifiedKeyFrag::from_base_parallel(&base, 3, true, true).unwrap();
+
+        // The fragments are randomized, so the two paths cannot be compared byte-for-byte;
+        // instead, the parallel ones must be valid fragments of the same polynomial.
+        assert_eq!(parallel.len(), serial.len());
+        for vkfrag in parallel.iter() {
+            assert!(vkfrag
+                .clone()
+                .unverify()
+                .verify(&verifying_pk, Some(&delegating_pk), Some(&receiving_pk))
+                .is_ok());
+            assert!(serial.iter().all(|other| other.kfrag.id != vkfrag.kfrag.id));
+        }
+        assert_ne!(parallel[0].kfrag.id, parallel[1].kfrag.id);
+
+        let (capsule, key_seed) = encapsulate(&delegating_pk);
+        let open = |vkfrags: &[&VerifiedKeyFrag]| {
+            let cfrags: Vec<_> = vkfrags
+                .iter()
+                .map(|vkfrag| reencrypt(&capsule, vkfrag, None))
+                .collect();
+            decapsulate_reencrypted(&receiving_sk, &delegating_pk, &capsule, &cfrags).unwrap()
+        };
+        assert_eq!(open(&[&serial[0], &serial[1]]), key_seed);
+        assert_eq!(open(&[&parallel[0], &parallel[1]]), key_seed);
+        // The fragments of both paths can be combined with each other.
+        assert_eq!(open(&[&serial[0], &parallel[1]]), key_seed);
+    }
 }
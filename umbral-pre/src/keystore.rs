@@ -0,0 +1,374 @@
+//! Passphrase-protected storage of secret keys, enabled with the `keystore` feature.
+//!
+//! The secret is encrypted with the crate's DEM under a key derived from the passphrase
+//! with Argon2id. The container is a versioned binary string:
+//!
+//! | Field                   | Size (bytes)       |
+//! | ----------------------- | ------------------ |
+//! | Format version (`1`)    | 1                  |
+//! | Secret type             | 1                  |
+//! | Argon2 memory cost, KiB | 4 (big-endian)     |
+//! | Argon2 iterations       | 4 (big-endian)     |
+//! | Argon2 parallelism      | 4 (big-endian)     |
+//! | Salt                    | 16                 |
+//! | DEM ciphertext          | the rest           |
+//!
+//! The header (everything before the ciphertext) is used as the authenticated data.
+//!
+//! If `serde` is enabled, the container is serialized as a bytestring
+//! (so that it can be embedded in JSON and other formats).
+
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use core::fmt;
+
+use argon2::{Algorithm, Argon2, Params, Version};
+use rand_core::{OsRng, RngCore};
+#[cfg(feature = "serde")]
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use zeroize::Zeroizing;
+
+use crate::dem::{Dem, XChaCha20Poly1305Dem};
+use crate::keys::{SecretKey, SecretKeyFactory};
+use crate::traits::{DeserializableFromArray, SerializableToArray};
+
+const FORMAT_VERSION: u8 = 1;
+const SALT_SIZE: usize = 16;
+const HEADER_SIZE: usize = 2 + 3 * 4 + SALT_SIZE;
+const DERIVED_KEY_SIZE: usize = 32;
+
+const SECRET_KEY_TAG: u8 = 0;
+const SECRET_KEY_FACTORY_TAG: u8 = 1;
+
+/// Errors that can happen when working with an [`EncryptedSecretKey`].
+#[derive(Debug, PartialEq)]
+pub enum KeystoreError {
+    /// The container is too short or its secret type is unknown.
+    InvalidFormat,
+    /// The container has a format version this library does not support.
+    UnsupportedVersion(u8),
+    /// The container holds a different type of secret than the one requested.
+    SecretTypeMismatch,
+    /// The Argon2 parameters are invalid.
+    InvalidParameters,
+    /// The passphrase is incorrect or the container was modified.
+    DecryptionFailed,
+}
+
+impl fmt::Display for KeystoreError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidFormat => write!(f, "invalid container format"),
+            Self::UnsupportedVersion(version) => {
+                write!(f, "unsupported format version {}", version)
+            }
+            Self::SecretTypeMismatch => write!(f, "the container holds a different type of secret"),
+            Self::InvalidParameters => write!(f, "invalid Argon2 parameters"),
+            Self::DecryptionFailed => write!(f, "incorrect passphrase or modified container"),
+        }
+    }
+}
+
+/// Argon2id parameters used to derive the encryption key from a passphrase.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct KeystoreParams {
+    /// Memory cost in KiB.
+    pub memory_cost: u32,
+    /// Number of iterations.
+    pub iterations: u32,
+    /// Degree of parallelism.
+    pub parallelism: u32,
+}
+
+impl Default for KeystoreParams {
+    /// Returns the parameters recommended by RFC 9106 for memory-constrained environments
+    /// (64 MiB, 3 iterations, 4 lanes).
+    fn default() -> Self {
+        Self {
+            memory_cost: 64 * 1024,
+            iterations: 3,
+            parallelism: 4,
+        }
+    }
+}
+
+/// A [`SecretKey`] or a [`SecretKeyFactory`] encrypted under a passphrase.
+#[derive(Clone, Debug, PartialEq)]
+pub struct EncryptedSecretKey {
+    secret_type: u8,
+    params: KeystoreParams,
+    salt: [u8; SALT_SIZE],
+    ciphertext: Box<[u8]>,
+}
+
+fn derive_key(
+    passphrase: &[u8],
+    salt: &[u8],
+    params: &KeystoreParams,
+) -> Result<Zeroizing<[u8; DERIVED_KEY_SIZE]>, KeystoreError> {
+    let argon2_params = Params::new(
+        params.memory_cost,
+        params.iterations,
+        params.parallelism,
+        Some(DERIVED_KEY_SIZE),
+    )
+    .or(Err(KeystoreError::InvalidParameters))?;
+    let argon2 = Argon2::new(Algorithm::Argon2id, Version::V0x13, argon2_params);
+    let mut key = Zeroizing::new([0u8; DERIVED_KEY_SIZE]);
+    argon2
+        .hash_password_into(passphrase, salt, &mut *key)
+        .or(Err(KeystoreError::InvalidParameters))?;
+    Ok(key)
+}
+
+impl EncryptedSecretKey {
+    fn header(&self) -> Vec<u8> {
+        let mut header = Vec::with_capacity(HEADER_SIZE);
+        header.push(FORMAT_VERSION);
+        header.push(self.secret_type);
+        header.extend_from_slice(&self.params.memory_cost.to_be_bytes());
+        header.extend_from_slice(&self.params.iterations.to_be_bytes());
+        header.extend_from_slice(&self.params.parallelism.to_be_bytes());
+        header.extend_from_slice(&self.salt);
+        header
+    }
+
+    fn encrypt(
+        secret_type: u8,
+        secret: &[u8],
+        passphrase: &[u8],
+        params: &KeystoreParams,
+    ) -> Result<Self, KeystoreError> {
+        let mut salt = [0u8; SALT_SIZE];
+        OsRng.fill_bytes(&mut salt);
+        let key = derive_key(passphrase, &salt, params)?;
+
+        let mut result = Self {
+            secret_type,
+            params: *params,
+            salt,
+            ciphertext: Box::new([]),
+        };
+        // The secrets are small enough, so the encryption cannot fail.
+        result.ciphertext = XChaCha20Poly1305Dem::new(&*key)
+            .encrypt(&mut OsRng, secret, &result.header())
+            .unwrap();
+        Ok(result)
+    }
+
+    fn decrypt(
+        &self,
+        secret_type: u8,
+        passphrase: &[u8],
+    ) -> Result<Zeroizing<Box<[u8]>>, KeystoreError> {
+        if self.secret_type != secret_type {
+            return Err(KeystoreError::SecretTypeMismatch);
+        }
+        let key = derive_key(passphrase, &self.salt, &self.params)?;
+        XChaCha20Poly1305Dem::new(&*key)
+            .decrypt(&self.ciphertext, &self.header())
+            .map(Zeroizing::new)
+            .or(Err(KeystoreError::DecryptionFailed))
+    }
+
+    /// Encrypts a secret key under the given passphrase.
+    pub fn from_secret_key(
+        sk: &SecretKey,
+        passphrase: &[u8],
+        params: &KeystoreParams,
+    ) -> Result<Self, KeystoreError> {
+        let secret = Zeroizing::new(sk.to_array());
+        Self::encrypt(SECRET_KEY_TAG, &secret, passphrase, params)
+    }
+
+    /// Encrypts a secret key factory under the given passphrase.
+    pub fn from_secret_key_factory(
+        skf: &SecretKeyFactory,
+        passphrase: &[u8],
+        params: &KeystoreParams,
+    ) -> Result<Self, KeystoreError> {
+        let secret = Zeroizing::new(skf.to_array());
+        Self::encrypt(SECRET_KEY_FACTORY_TAG, &secret, passphrase, params)
+    }
+
+    /// Decrypts a secret key with the given passphrase.
+    pub fn decrypt_secret_key(&self, passphrase: &[u8]) -> Result<SecretKey, KeystoreError> {
+        let secret = self.decrypt(SECRET_KEY_TAG, passphrase)?;
+        SecretKey::from_bytes(&secret[..]).or(Err(KeystoreError::InvalidFormat))
+    }
+
+    /// Decrypts a secret key factory with the given passphrase.
+    pub fn decrypt_secret_key_factory(
+        &self,
+        passphrase: &[u8],
+    ) -> Result<SecretKeyFactory, KeystoreError> {
+        let secret = self.decrypt(SECRET_KEY_FACTORY_TAG, passphrase)?;
+        SecretKeyFactory::from_bytes(&secret[..]).or(Err(KeystoreError::InvalidFormat))
+    }
+
+    /// Returns the Argon2id parameters the container was created with.
+    pub fn params(&self) -> KeystoreParams {
+        self.params
+    }
+
+    /// Serializes the container.
+    pub fn to_bytes(&self) -> Box<[u8]> {
+        let mut bytes = self.header();
+        bytes.extend_from_slice(&self.ciphertext);
+        bytes.into_boxed_slice()
+    }
+
+    /// Deserializes the container.
+    ///
+    /// Note that the Argon2 parameters are taken from the container,
+    /// so an untrusted container can make decryption arbitrarily expensive.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, KeystoreError> {
+        if bytes.is_empty() {
+            return Err(KeystoreError::InvalidFormat);
+        }
+        if bytes[0] != FORMAT_VERSION {
+            return Err(KeystoreError::UnsupportedVersion(bytes[0]));
+        }
+        if bytes.len() < HEADER_SIZE {
+            return Err(KeystoreError::InvalidFormat);
+        }
+
+        let secret_type = bytes[1];
+        if secret_type != SECRET_KEY_TAG && secret_type != SECRET_KEY_FACTORY_TAG {
+            return Err(KeystoreError::InvalidFormat);
+        }
+
+        let read_u32 = |offset: usize| {
+            let mut buf = [0u8; 4];
+            buf.copy_from_slice(&bytes[offset..offset + 4]);
+            u32::from_be_bytes(buf)
+        };
+        let params = KeystoreParams {
+            memory_cost: read_u32(2),
+            iterations: read_u32(6),
+            parallelism: read_u32(10),
+        };
+
+        let mut salt = [0u8; SALT_SIZE];
+        salt.copy_from_slice(&bytes[14..HEADER_SIZE]);
+
+        Ok(Self {
+            secret_type,
+            params,
+            salt,
+            ciphertext: bytes[HEADER_SIZE..].into(),
+        })
+    }
+}
+
+#[cfg(feature = "serde")]
+impl Serialize for EncryptedSecretKey {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_bytes(&self.to_bytes())
+    }
+}
+
+#[cfg(feature = "serde")]
+struct EncryptedSecretKeyVisitor;
+
+#[cfg(feature = "serde")]
+impl<'de> de::Visitor<'de> for EncryptedSecretKeyVisitor {
+    type Value = EncryptedSecretKey;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(formatter, "an encrypted secret key container")
+    }
+
+    fn visit_bytes<E: de::Error>(self, v: &[u8]) -> Result<Self::Value, E> {
+        EncryptedSecretKey::from_bytes(v).map_err(|err| E::custom(alloc::format!("{:?}", err)))
+    }
+
+    fn visit_byte_buf<E: de::Error>(self, v: Vec<u8>) -> Result<Self::Value, E> {
+        self.visit_bytes(&v)
+    }
+
+    // Formats without a native bytestring type (e.g. JSON) represent bytes as a sequence.
+    fn visit_seq<A: de::SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+        let mut bytes = Vec::with_capacity(seq.size_hint().unwrap_or(HEADER_SIZE));
+        while let Some(byte) = seq.next_element()? {
+            bytes.push(byte);
+        }
+        self.visit_bytes(&bytes)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> Deserialize<'de> for EncryptedSecretKey {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_bytes(EncryptedSecretKeyVisitor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::{EncryptedSecretKey, KeystoreError, KeystoreParams};
+    use crate::{SecretKey, SecretKeyFactory, SerializableToArray};
+
+    // Cheap parameters to keep the tests fast.
+    const PARAMS: KeystoreParams = KeystoreParams {
+        memory_cost: 64,
+        iterations: 1,
+        parallelism: 1,
+    };
+
+    #[test]
+    fn test_roundtrip() {
+        let sk = SecretKey::random();
+        let encrypted = EncryptedSecretKey::from_secret_key(&sk, b"passphrase", &PARAMS).unwrap();
+        let encrypted_back = EncryptedSecretKey::from_bytes(&encrypted.to_bytes()).unwrap();
+        assert_eq!(encrypted, encrypted_back);
+        assert!(encrypted_back.decrypt_secret_key(b"passphrase").unwrap() == sk);
+
+        assert_eq!(
+            encrypted.decrypt_secret_key(b"wrong passphrase").err(),
+            Some(KeystoreError::DecryptionFailed)
+        );
+        assert_eq!(
+            encrypted.decrypt_secret_key_factory(b"passphrase").err(),
+            Some(KeystoreError::SecretTypeMismatch)
+        );
+
+        // The header is authenticated.
+        let mut bytes = encrypted.to_bytes().to_vec();
+        bytes[5] ^= 1;
+        let modified = EncryptedSecretKey::from_bytes(&bytes).unwrap();
+        assert_eq!(
+            modified.decrypt_secret_key(b"passphrase").err(),
+            Some(KeystoreError::DecryptionFailed)
+        );
+
+        bytes[0] = 2;
+        assert_eq!(
+            EncryptedSecretKey::from_bytes(&bytes),
+            Err(KeystoreError::UnsupportedVersion(2))
+        );
+
+        let skf = SecretKeyFactory::random();
+        let encrypted =
+            EncryptedSecretKey::from_secret_key_factory(&skf, b"passphrase", &PARAMS).unwrap();
+        let skf_back = encrypted.decrypt_secret_key_factory(b"passphrase").unwrap();
+        assert_eq!(skf.to_array(), skf_back.to_array());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_json_roundtrip() {
+        let sk = SecretKey::random();
+        let encrypted = EncryptedSecretKey::from_secret_key(&sk, b"passphrase", &PARAMS).unwrap();
+
+        // JSON has no bytestrings, so the container is serialized as a sequence of numbers.
+        let json = serde_json::to_string(&encrypted).unwrap();
+        assert!(json.starts_with('['));
+        let encrypted_back: EncryptedSecretKey = serde_json::from_str(&json).unwrap();
+        assert_eq!(encrypted, encrypted_back);
+        assert!(encrypted_back.decrypt_secret_key(b"passphrase").unwrap() == sk);
+
+        assert!(serde_json::from_str::<EncryptedSecretKey>("[1, 0]").is_err());
+    }
+}
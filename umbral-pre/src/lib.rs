@@ -11,6 +11,12 @@
 //! # Usage
 //!
 //! ```
+//! # #[cfg(all(
+//! #     feature = "dem",
+//! #     feature = "role-alice",
+//! #     feature = "role-ursula",
+//! #     feature = "role-bob"
+//! # ))] {
 //! use umbral_pre::*;
 //!
 //! // As in any public-key cryptosystem, users need a pair of public and private keys.
@@ -64,14 +70,14 @@
 //! // and perform the reencryption
 //!
 //! // Ursula 0
-//! let metadata0 = b"metadata0";
+//! let metadata0 = Metadata::new().with_extra(b"metadata0");
 //! let verified_kfrag0 = kfrag0.verify(&verifying_pk, Some(&alice_pk), Some(&bob_pk)).unwrap();
-//! let verified_cfrag0 = reencrypt(&capsule, &verified_kfrag0, Some(metadata0));
+//! let verified_cfrag0 = reencrypt(&capsule, &verified_kfrag0, Some(&metadata0));
 //!
 //! // Ursula 1
-//! let metadata1 = b"metadata1";
+//! let metadata1 = Metadata::new().with_extra(b"metadata1");
 //! let verified_kfrag1 = kfrag1.verify(&verifying_pk, Some(&alice_pk), Some(&bob_pk)).unwrap();
-//! let verified_cfrag1 = reencrypt(&capsule, &verified_kfrag1, Some(metadata1));
+//! let verified_cfrag1 = reencrypt(&capsule, &verified_kfrag1, Some(&metadata1));
 //!
 //! // ...
 //!
@@ -84,15 +90,16 @@
 //!
 //! // Bob must check that cfrags are valid
 //! let verified_cfrag0 = cfrag0
-//!     .verify(&capsule, &verifying_pk, &alice_pk, &bob_pk, Some(metadata0))
+//!     .verify(&capsule, &verifying_pk, &alice_pk, &bob_pk, Some(&metadata0))
 //!     .unwrap();
 //! let verified_cfrag1 = cfrag1
-//!     .verify(&capsule, &verifying_pk, &alice_pk, &bob_pk, Some(metadata1))
+//!     .verify(&capsule, &verifying_pk, &alice_pk, &bob_pk, Some(&metadata1))
 //!     .unwrap();
 //!
 //! let plaintext_bob = decrypt_reencrypted(
 //!     &bob_sk, &alice_pk, &capsule, &[verified_cfrag0, verified_cfrag1], &ciphertext).unwrap();
 //! assert_eq!(&plaintext_bob as &[u8], plaintext);
+//! # }
 //! ```
 //!
 //! [umbral]: https://github.com/nucypher/umbral-doc/blob/master/umbral-doc.pdf
@@ -103,28 +110,209 @@
 #![no_std]
 
 extern crate alloc;
+#[cfg(feature = "std")]
+extern crate std;
 
+#[cfg(feature = "dem")]
+mod archive;
 pub mod bench; // Re-export some internals for benchmarks.
+#[cfg(feature = "bls")]
+pub mod bls;
+#[cfg(feature = "dem")]
+pub mod bridge;
+#[cfg(feature = "role-ursula")]
+mod bulk;
 mod capsule;
 mod capsule_frag;
+pub mod compat;
+#[cfg(feature = "cose")]
+mod cose;
 mod curve;
+#[cfg(feature = "dem")]
 mod dem;
+#[cfg(feature = "diagnostics")]
+mod diagnostics;
+mod encoding;
+mod error;
+mod fingerprint;
+#[cfg(all(feature = "dem", feature = "role-bob"))]
+pub mod flow;
+#[cfg(feature = "rfc9380")]
+mod hash_to_curve;
 mod hashing;
 mod hashing_ds;
 mod key_frag;
 mod keys;
-mod params;
+#[cfg(feature = "keystore")]
+mod keystore;
+mod metadata;
+#[cfg(feature = "dem")]
+mod migration;
+#[cfg(feature = "bip39")]
+mod mnemonic;
+#[cfg(feature = "dem")]
+mod multi;
+#[cfg(feature = "padding")]
+pub mod padding;
+pub mod params;
+#[cfg(feature = "role-bob")]
+mod partial_opening;
+#[cfg(feature = "pem")]
+mod pem;
+#[cfg(feature = "policy")]
+mod policy;
 mod pre;
+pub mod prelude;
+#[cfg(feature = "protobuf")]
+pub mod protobuf;
+#[cfg(feature = "proxy")]
+mod proxy;
+#[cfg(feature = "qr")]
+pub mod qr;
+#[cfg(any(feature = "role-ursula", feature = "role-bob"))]
+mod receipt;
+#[cfg(any(feature = "role-alice", feature = "role-bob"))]
+mod reception;
+#[cfg(feature = "ristretto")]
+pub mod ristretto;
+#[cfg(feature = "schnorr")]
+mod schnorr;
+#[cfg(feature = "serde-secrets")]
+mod secret_serde;
+mod secure_memory;
+#[cfg(feature = "role-alice")]
+mod signing_policy;
+#[cfg(feature = "sim")]
+pub mod sim;
+#[cfg(feature = "role-bob")]
+mod subsets;
+#[cfg(feature = "test-vectors")]
+pub mod test_vectors;
+#[cfg(feature = "threshold")]
+mod threshold;
 mod traits;
+#[cfg(feature = "uri")]
+mod uri;
 
-pub use capsule::{Capsule, OpenReencryptedError};
-pub use capsule_frag::{CapsuleFrag, CapsuleFragVerificationError, VerifiedCapsuleFrag};
-pub use dem::{DecryptionError, EncryptionError};
-pub use key_frag::{KeyFrag, KeyFragVerificationError, VerifiedKeyFrag};
-pub use keys::{PublicKey, SecretKey, SecretKeyFactory, SecretKeyFactoryError, Signature, Signer};
+#[cfg(feature = "dem")]
+pub use archive::{
+    export_kfrags_encrypted, export_kfrags_encrypted_with_rng, Archive, ArchiveError,
+    ArchivePolicy, KeyFragArchive, KeyFragBundle, KeyFragsRoot,
+};
+#[cfg(feature = "role-ursula")]
+pub use bulk::{
+    reencrypt_bulk, reencrypt_bulk_with_rng, BulkReencryption, BulkReencryptionError,
+    BulkReencryptor, CpuReencryptor, ScalarBytes, MULTIPLICATIONS_PER_CAPSULE,
+};
+pub use capsule::{Capsule, CapsuleFormatError, HintedCapsule, OpenReencryptedError};
+pub use capsule_frag::{
+    AuditableCapsuleFrag, CapsuleFrag, CapsuleFragVerificationError, CheckOutcome,
+    FaultyCapsuleFrag, PartialVerificationReport, ReencryptionCheck, VerifiedCapsuleFrag,
+};
+#[cfg(feature = "cose")]
+pub use cose::{CoseEnvelope, CoseError};
+pub use curve::CompressedPoint;
+#[cfg(feature = "dem")]
+pub use dem::{
+    ciphertext_overhead, encrypted_size, DecryptionError, DemAlgorithm, EncryptionError,
+};
+pub use error::Error;
+pub use fingerprint::Fingerprint;
+#[cfg(feature = "role-alice")]
+pub use key_frag::KeyFragGenerator;
+pub use key_frag::{
+    KeyFrag, KeyFragID, KeyFragVerificationError, KeyFragVerificationToken, PolicyFingerprint,
+    RefreshKey, ValidityWindow, VerifiedKeyFrag,
+};
+pub use keys::{
+    ExternalSigner, PrecomputedPublicKey, PublicKey, SecretKey, SecretKeyFactory,
+    SecretKeyFactoryError, Signature, SignatureScheme, Signer, SignerError,
+};
+#[cfg(feature = "keystore")]
+pub use keystore::{EncryptedSecretKey, KeystoreError, KeystoreParams};
+pub use metadata::{
+    CanonicalizationError, JsonCanonicalizer, Metadata, MetadataCanonicalizer, RawMetadata,
+    StructuredMetadata,
+};
+#[cfg(feature = "dem")]
+pub use migration::{
+    detect_dem_version, migrate_ciphertext, migrate_ciphertext_with_rng, DemVersion, MigrationError,
+};
+#[cfg(feature = "bip39")]
+pub use mnemonic::{generate_mnemonic, generate_mnemonic_with_rng, MnemonicError};
+#[cfg(all(feature = "dem", feature = "role-bob"))]
+pub use multi::decrypt_multi_reencrypted;
+#[cfg(feature = "dem")]
+pub use multi::{decrypt_multi_original, encrypt_multi, encrypt_multi_with_rng, RecipientKey};
+#[cfg(feature = "role-bob")]
+pub use partial_opening::PartialOpening;
+#[cfg(feature = "pem")]
+pub use pem::KeyFormatError;
+#[cfg(feature = "policy")]
+pub use policy::{Policy, PolicyError, PolicyId};
 pub use pre::{
-    decrypt_original, decrypt_reencrypted, encrypt, generate_kfrags, reencrypt, ReencryptionError,
+    audit_capsule, check_key_original, decapsulate_original, derive_key, encapsulate,
+    encapsulate_precomputed, encapsulate_precomputed_with_rng, encapsulate_with_key_hint,
+    encapsulate_with_key_hint_with_rng, encapsulate_with_rng, encapsulate_with_seed, key_check,
+    DeriveKeyError, KeyCheck, KeySeed, RefreshError,
+};
+#[cfg(feature = "role-bob")]
+pub use pre::{decapsulate_reencrypted, CombinedCapsule};
+#[cfg(feature = "dem")]
+pub use pre::{
+    decrypt_original, decrypt_original_limited, decrypt_original_verified,
+    decrypt_original_with_key_check, encrypt, encrypt_precomputed, encrypt_precomputed_with_rng,
+    encrypt_signed, encrypt_signed_with_rng, encrypt_with_algorithm,
+    encrypt_with_algorithm_with_rng, encrypt_with_key_check, encrypt_with_key_check_with_rng,
+    encrypt_with_key_hint, encrypt_with_key_hint_with_rng, encrypt_with_rng, ReencryptionError,
+};
+#[cfg(all(feature = "dem", feature = "role-bob"))]
+pub use pre::{decrypt_reencrypted, decrypt_reencrypted_limited, decrypt_reencrypted_verified};
+#[cfg(feature = "role-alice")]
+pub use pre::{
+    generate_kfrags, generate_kfrags_for_reception_key, generate_kfrags_for_reception_key_with_rng,
+    generate_kfrags_iter, generate_kfrags_iter_with_rng, generate_kfrags_with_label,
+    generate_kfrags_with_rng, generate_kfrags_with_validity,
+    generate_kfrags_with_validity_with_rng, generate_refreshable_kfrags,
+    generate_refreshable_kfrags_with_rng, generate_single_kfrag, refresh_kfrags,
+    refresh_kfrags_with_rng, regenerate_kfrags, regenerate_kfrags_with_rng, try_generate_kfrags,
+    try_generate_kfrags_with_label, try_generate_kfrags_with_rng,
+    try_generate_kfrags_with_validity, try_generate_kfrags_with_validity_with_rng,
+    try_generate_refreshable_kfrags, try_generate_refreshable_kfrags_with_rng,
+    try_generate_single_kfrag,
+};
+#[cfg(feature = "role-ursula")]
+pub use pre::{
+    reencrypt, reencrypt_auditable, reencrypt_auditable_with_rng, reencrypt_batch,
+    reencrypt_batch_with_rng, reencrypt_canonicalized, reencrypt_canonicalized_with_rng,
+    reencrypt_with_receipt, reencrypt_with_receipt_with_rng, reencrypt_with_rng,
+};
+#[cfg(feature = "proxy")]
+pub use proxy::{
+    InMemoryReencryptionService, ReencryptionFuture, ReencryptionMessageError, ReencryptionRequest,
+    ReencryptionResponse, ReencryptionService, ReencryptionServiceError, ReplayGuard,
+};
+#[cfg(any(feature = "role-ursula", feature = "role-bob"))]
+pub use receipt::{ReencryptionReceipt, ReencryptionReceiptError};
+#[cfg(any(feature = "role-alice", feature = "role-bob"))]
+pub use reception::{
+    generate_reception_keys, generate_reception_keys_with_rng, ReceptionKey,
+    ReceptionKeyVerificationError, VerifiedReceptionKey,
+};
+#[cfg(feature = "role-alice")]
+pub use signing_policy::{SigningEvent, SigningPolicy, SigningPolicyError};
+#[cfg(feature = "role-bob")]
+pub use subsets::{best_subset, subsets, CapsuleFragSubsets};
+#[cfg(all(feature = "threshold", feature = "dem"))]
+pub use threshold::decrypt_threshold;
+#[cfg(feature = "threshold")]
+pub use threshold::{
+    decapsulate_threshold, generate_decryption_shares, generate_decryption_shares_with_rng,
+    DecryptionShare, PartialDecryption, ShareGenerationError, ThresholdDecryptionError,
 };
 pub use traits::{
     DeserializableFromArray, DeserializationError, RepresentableAsArray, SerializableToArray,
+    VersionedSerialization,
 };
+#[cfg(feature = "uri")]
+pub use uri::{CapabilityUri, UriError};
@@ -0,0 +1,716 @@
+//! Structured reencryption metadata with a canonical serialization.
+//!
+//! The [`Metadata`] given to [`reencrypt()`](crate::reencrypt) is hashed into the proof
+//! of the capsule fragment in its canonical encoding ([`Metadata::to_bytes()`]),
+//! and must be given to [`CapsuleFrag::verify()`](crate::CapsuleFrag::verify) as well,
+//! so the proxy and the receiver cannot disagree on the encoding.
+//!
+//! Applications with their own structured metadata (JSON, CBOR) can instead pass
+//! a [`MetadataCanonicalizer`] to [`reencrypt_canonicalized()`](crate::reencrypt_canonicalized)
+//! and [`CapsuleFrag::verify_canonicalized()`](crate::CapsuleFrag::verify_canonicalized),
+//! so that semantically equal metadata encoded differently by the two sides
+//! (e.g. with the keys of a JSON object in a different order) is hashed the same.
+
+use alloc::boxed::Box;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::convert::TryFrom;
+use core::fmt;
+
+use crate::traits::DeserializationError;
+
+const FORMAT_VERSION: u8 = 1;
+
+/// Reencryption metadata: identifiers of the policy and the proxy,
+/// the time of the request, and arbitrary application data.
+/// All fields are optional.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Metadata {
+    policy_id: Option<Box<[u8]>>,
+    ursula_id: Option<Box<[u8]>>,
+    timestamp: Option<u64>,
+    extra: Option<Box<[u8]>>,
+}
+
+fn push_bytes(out: &mut Vec<u8>, field: &Option<Box<[u8]>>) {
+    match field {
+        Some(bytes) => {
+            out.push(1);
+            let len = u32::try_from(bytes.len()).expect("metadata field is too long");
+            out.extend_from_slice(&len.to_be_bytes());
+            out.extend_from_slice(bytes);
+        }
+        None => out.push(0),
+    }
+}
+
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, size: usize) -> Result<&'a [u8], DeserializationError> {
+        if self.0.len() < size {
+            return Err(DeserializationError::NotEnoughBytes);
+        }
+        let (head, tail) = self.0.split_at(size);
+        self.0 = tail;
+        Ok(head)
+    }
+
+    fn take_u32(&mut self) -> Result<u32, DeserializationError> {
+        let mut buf = [0u8; 4];
+        buf.copy_from_slice(self.take(4)?);
+        Ok(u32::from_be_bytes(buf))
+    }
+
+    fn take_u64(&mut self) -> Result<u64, DeserializationError> {
+        let mut buf = [0u8; 8];
+        buf.copy_from_slice(self.take(8)?);
+        Ok(u64::from_be_bytes(buf))
+    }
+
+    fn take_flag(&mut self) -> Result<bool, DeserializationError> {
+        match self.take(1)?[0] {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(DeserializationError::ConstructionFailure),
+        }
+    }
+
+    fn take_bytes(&mut self) -> Result<Option<Box<[u8]>>, DeserializationError> {
+        if !self.take_flag()? {
+            return Ok(None);
+        }
+        let len = self.take_u32()? as usize;
+        Ok(Some(self.take(len)?.into()))
+    }
+}
+
+impl Metadata {
+    /// Creates empty metadata.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the policy identifier (normally a [`PolicyId`](crate::PolicyId)).
+    pub fn with_policy_id(self, policy_id: &[u8]) -> Self {
+        Self {
+            policy_id: Some(policy_id.into()),
+            ..self
+        }
+    }
+
+    /// Sets the identifier of the reencrypting proxy.
+    pub fn with_ursula_id(self, ursula_id: &[u8]) -> Self {
+        Self {
+            ursula_id: Some(ursula_id.into()),
+            ..self
+        }
+    }
+
+    /// Sets the time of the request (in an application-defined unit, e.g. UNIX seconds).
+    pub fn with_timestamp(self, timestamp: u64) -> Self {
+        Self {
+            timestamp: Some(timestamp),
+            ..self
+        }
+    }
+
+    /// Sets arbitrary application data.
+    pub fn with_extra(self, extra: &[u8]) -> Self {
+        Self {
+            extra: Some(extra.into()),
+            ..self
+        }
+    }
+
+    /// Returns the policy identifier.
+    pub fn policy_id(&self) -> Option<&[u8]> {
+        self.policy_id.as_deref()
+    }
+
+    /// Returns the identifier of the reencrypting proxy.
+    pub fn ursula_id(&self) -> Option<&[u8]> {
+        self.ursula_id.as_deref()
+    }
+
+    /// Returns the time of the request.
+    pub fn timestamp(&self) -> Option<u64> {
+        self.timestamp
+    }
+
+    /// Returns the application data.
+    pub fn extra(&self) -> Option<&[u8]> {
+        self.extra.as_deref()
+    }
+
+    /// Returns the canonical encoding of the metadata
+    /// (the bytes hashed into the proof by [`reencrypt()`](crate::reencrypt)),
+    /// e.g. to send it along with a reencryption request.
+    ///
+    /// Panics if any of the fields is longer than `u32::MAX` bytes.
+    pub fn to_bytes(&self) -> Box<[u8]> {
+        let mut out = Vec::new();
+        out.push(FORMAT_VERSION);
+        push_bytes(&mut out, &self.policy_id);
+        push_bytes(&mut out, &self.ursula_id);
+        match self.timestamp {
+            Some(timestamp) => {
+                out.push(1);
+                out.extend_from_slice(&timestamp.to_be_bytes());
+            }
+            None => out.push(0),
+        }
+        push_bytes(&mut out, &self.extra);
+        out.into_boxed_slice()
+    }
+
+    /// Restores the metadata from its canonical encoding.
+    ///
+    /// Only canonical encodings are accepted, so that the result encodes back
+    /// to the same bytes.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, DeserializationError> {
+        let mut reader = Reader(bytes);
+        if reader.take(1)?[0] != FORMAT_VERSION {
+            return Err(DeserializationError::ConstructionFailure);
+        }
+        let policy_id = reader.take_bytes()?;
+        let ursula_id = reader.take_bytes()?;
+        let timestamp = if reader.take_flag()? {
+            Some(reader.take_u64()?)
+        } else {
+            None
+        };
+        let extra = reader.take_bytes()?;
+        if !reader.0.is_empty() {
+            return Err(DeserializationError::TooManyBytes);
+        }
+        Ok(Self {
+            policy_id,
+            ursula_id,
+            timestamp,
+            extra,
+        })
+    }
+}
+
+/// Errors that can happen when canonicalizing metadata.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum CanonicalizationError {
+    /// The metadata is not a valid encoding in the format of the canonicalizer.
+    Malformed,
+    /// The metadata contains the same key twice in a map, so it has no canonical form.
+    DuplicateKey,
+}
+
+impl fmt::Display for CanonicalizationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Malformed => write!(f, "malformed metadata"),
+            Self::DuplicateKey => write!(f, "duplicate key in metadata"),
+        }
+    }
+}
+
+/// A conversion of metadata to a canonical byte encoding, applied before it is hashed
+/// into the reencryption proof.
+///
+/// The canonicalizer must map semantically equal metadata to the same bytes,
+/// and the proxy and the receiver must use the same one.
+pub trait MetadataCanonicalizer {
+    /// Returns the canonical encoding of the metadata.
+    fn canonicalize(&self, metadata: &[u8]) -> Result<Box<[u8]>, CanonicalizationError>;
+}
+
+/// Uses the metadata bytes as they are
+/// (e.g. for the metadata of applications that do not use [`Metadata`]).
+#[derive(Clone, Copy, Debug, Default)]
+pub struct RawMetadata;
+
+impl MetadataCanonicalizer for RawMetadata {
+    fn canonicalize(&self, metadata: &[u8]) -> Result<Box<[u8]>, CanonicalizationError> {
+        Ok(metadata.into())
+    }
+}
+
+/// Accepts only the canonical encoding of [`Metadata`], and leaves it as is.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct StructuredMetadata;
+
+impl MetadataCanonicalizer for StructuredMetadata {
+    fn canonicalize(&self, metadata: &[u8]) -> Result<Box<[u8]>, CanonicalizationError> {
+        Metadata::from_bytes(metadata)
+            .map(|metadata| metadata.to_bytes())
+            .or(Err(CanonicalizationError::Malformed))
+    }
+}
+
+/// Canonicalizes JSON following the JSON Canonicalization Scheme (RFC 8785),
+/// except for the numbers: the insignificant whitespace is removed,
+/// the members of the objects are sorted by their keys (as UTF-16 code units),
+/// and the strings are written with the minimal escaping.
+///
+/// Numbers are checked for validity but kept as they are written
+/// (that is, `1.0` and `1` remain different),
+/// so applications should encode them consistently, e.g. as integers.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct JsonCanonicalizer;
+
+impl MetadataCanonicalizer for JsonCanonicalizer {
+    fn canonicalize(&self, metadata: &[u8]) -> Result<Box<[u8]>, CanonicalizationError> {
+        let text = core::str::from_utf8(metadata).or(Err(CanonicalizationError::Malformed))?;
+        let mut parser = JsonParser {
+            text,
+            pos: 0,
+            depth: 0,
+        };
+        let value = parser.parse_value()?;
+        parser.skip_whitespace();
+        if parser.pos != text.len() {
+            return Err(CanonicalizationError::Malformed);
+        }
+        let mut out = String::new();
+        value.write(&mut out);
+        Ok(out.into_bytes().into_boxed_slice())
+    }
+}
+
+/// Returns the canonical encoding of the optional metadata, as hashed into the reencryption proof.
+pub(crate) fn encode_metadata(metadata: Option<&Metadata>) -> Option<Box<[u8]>> {
+    metadata.map(Metadata::to_bytes)
+}
+
+/// Applies the canonicalizer to the optional metadata.
+pub(crate) fn canonicalize(
+    canonicalizer: &impl MetadataCanonicalizer,
+    metadata: Option<&[u8]>,
+) -> Result<Option<Box<[u8]>>, CanonicalizationError> {
+    metadata
+        .map(|metadata| canonicalizer.canonicalize(metadata))
+        .transpose()
+}
+
+enum JsonValue<'a> {
+    Literal(&'a str),
+    String(String),
+    Array(Vec<JsonValue<'a>>),
+    Object(Vec<(String, JsonValue<'a>)>),
+}
+
+// Bounds the recursion on untrusted input.
+const MAX_JSON_DEPTH: usize = 64;
+
+struct JsonParser<'a> {
+    text: &'a str,
+    pos: usize,
+    depth: usize,
+}
+
+impl<'a> JsonParser<'a> {
+    fn peek(&self) -> Option<u8> {
+        self.text.as_bytes().get(self.pos).copied()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(b' ') | Some(b'\t') | Some(b'\n') | Some(b'\r') = self.peek() {
+            self.pos += 1;
+        }
+    }
+
+    fn expect(&mut self, c: u8) -> Result<(), CanonicalizationError> {
+        if self.peek() != Some(c) {
+            return Err(CanonicalizationError::Malformed);
+        }
+        self.pos += 1;
+        Ok(())
+    }
+
+    fn skip_digits(&mut self) -> usize {
+        let start = self.pos;
+        while let Some(b'0'..=b'9') = self.peek() {
+            self.pos += 1;
+        }
+        self.pos - start
+    }
+
+    fn parse_value(&mut self) -> Result<JsonValue<'a>, CanonicalizationError> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some(b'{') => self.nested(Self::parse_object),
+            Some(b'[') => self.nested(Self::parse_array),
+            Some(b'"') => self.parse_string().map(JsonValue::String),
+            Some(b'-') | Some(b'0'..=b'9') => self.parse_number(),
+            _ => {
+                let rest = &self.text[self.pos..];
+                let literal = ["true", "false", "null"]
+                    .iter()
+                    .find(|literal| rest.starts_with(*literal))
+                    .ok_or(CanonicalizationError::Malformed)?;
+                self.pos += literal.len();
+                Ok(JsonValue::Literal(literal))
+            }
+        }
+    }
+
+    fn nested(
+        &mut self,
+        parse: fn(&mut Self) -> Result<JsonValue<'a>, CanonicalizationError>,
+    ) -> Result<JsonValue<'a>, CanonicalizationError> {
+        if self.depth == MAX_JSON_DEPTH {
+            return Err(CanonicalizationError::Malformed);
+        }
+        self.depth += 1;
+        let result = parse(self);
+        self.depth -= 1;
+        result
+    }
+
+    fn parse_number(&mut self) -> Result<JsonValue<'a>, CanonicalizationError> {
+        let start = self.pos;
+        if self.peek() == Some(b'-') {
+            self.pos += 1;
+        }
+        let int_start = self.pos;
+        let int_digits = self.skip_digits();
+        if int_digits == 0 || (int_digits > 1 && self.text.as_bytes()[int_start] == b'0') {
+            return Err(CanonicalizationError::Malformed);
+        }
+        if self.peek() == Some(b'.') {
+            self.pos += 1;
+            if self.skip_digits() == 0 {
+                return Err(CanonicalizationError::Malformed);
+            }
+        }
+        if let Some(b'e') | Some(b'E') = self.peek() {
+            self.pos += 1;
+            if let Some(b'+') | Some(b'-') = self.peek() {
+                self.pos += 1;
+            }
+            if self.skip_digits() == 0 {
+                return Err(CanonicalizationError::Malformed);
+            }
+        }
+        Ok(JsonValue::Literal(&self.text[start..self.pos]))
+    }
+
+    fn parse_hex4(&mut self) -> Result<u32, CanonicalizationError> {
+        let digits = self
+            .text
+            .get(self.pos..self.pos + 4)
+            .ok_or(CanonicalizationError::Malformed)?;
+        self.pos += 4;
+        // `from_str_radix` would also accept a sign.
+        if !digits.bytes().all(|c| c.is_ascii_hexdigit()) {
+            return Err(CanonicalizationError::Malformed);
+        }
+        u32::from_str_radix(digits, 16).or(Err(CanonicalizationError::Malformed))
+    }
+
+    fn parse_string(&mut self) -> Result<String, CanonicalizationError> {
+        self.expect(b'"')?;
+        let mut result = String::new();
+        loop {
+            let c = self.text[self.pos..]
+                .chars()
+                .next()
+                .ok_or(CanonicalizationError::Malformed)?;
+            self.pos += c.len_utf8();
+            match c {
+                '"' => return Ok(result),
+                '\\' => {
+                    let escaped = self.peek().ok_or(CanonicalizationError::Malformed)?;
+                    self.pos += 1;
+                    let c = match escaped {
+                        b'"' => '"',
+                        b'\\' => '\\',
+                        b'/' => '/',
+                        b'b' => '\u{8}',
+                        b'f' => '\u{c}',
+                        b'n' => '\n',
+                        b'r' => '\r',
+                        b't' => '\t',
+                        b'u' => self.parse_escaped_char()?,
+                        _ => return Err(CanonicalizationError::Malformed),
+                    };
+                    result.push(c);
+                }
+                c if (c as u32) < 0x20 => return Err(CanonicalizationError::Malformed),
+                c => result.push(c),
+            }
+        }
+    }
+
+    // Parses the part of a `\u` escape after the `u`, including the low surrogate if needed.
+    fn parse_escaped_char(&mut self) -> Result<char, CanonicalizationError> {
+        let code = self.parse_hex4()?;
+        let code = match code {
+            0xd800..=0xdbff => {
+                self.expect(b'\\')?;
+                self.expect(b'u')?;
+                let low = self.parse_hex4()?;
+                if !(0xdc00..=0xdfff).contains(&low) {
+                    return Err(CanonicalizationError::Malformed);
+                }
+                0x10000 + ((code - 0xd800) << 10) + (low - 0xdc00)
+            }
+            _ => code,
+        };
+        core::char::from_u32(code).ok_or(CanonicalizationError::Malformed)
+    }
+
+    fn parse_array(&mut self) -> Result<JsonValue<'a>, CanonicalizationError> {
+        self.expect(b'[')?;
+        let mut items = Vec::new();
+        self.skip_whitespace();
+        if self.peek() == Some(b']') {
+            self.pos += 1;
+            return Ok(JsonValue::Array(items));
+        }
+        loop {
+            items.push(self.parse_value()?);
+            self.skip_whitespace();
+            match self.peek() {
+                Some(b',') => self.pos += 1,
+                Some(b']') => {
+                    self.pos += 1;
+                    return Ok(JsonValue::Array(items));
+                }
+                _ => return Err(CanonicalizationError::Malformed),
+            }
+        }
+    }
+
+    fn parse_object(&mut self) -> Result<JsonValue<'a>, CanonicalizationError> {
+        self.expect(b'{')?;
+        let mut members = Vec::new();
+        self.skip_whitespace();
+        if self.peek() == Some(b'}') {
+            self.pos += 1;
+        } else {
+            loop {
+                self.skip_whitespace();
+                let key = self.parse_string()?;
+                self.skip_whitespace();
+                self.expect(b':')?;
+                let value = self.parse_value()?;
+                members.push((key, value));
+                self.skip_whitespace();
+                match self.peek() {
+                    Some(b',') => self.pos += 1,
+                    Some(b'}') => {
+                        self.pos += 1;
+                        break;
+                    }
+                    _ => return Err(CanonicalizationError::Malformed),
+                }
+            }
+        }
+
+        members.sort_by(|(key1, _), (key2, _)| key1.encode_utf16().cmp(key2.encode_utf16()));
+        if members.windows(2).any(|pair| pair[0].0 == pair[1].0) {
+            return Err(CanonicalizationError::DuplicateKey);
+        }
+        Ok(JsonValue::Object(members))
+    }
+}
+
+fn write_json_string(out: &mut String, s: &str) {
+    const DIGITS: &[u8; 16] = b"0123456789abcdef";
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\u{8}' => out.push_str("\\b"),
+            '\u{c}' => out.push_str("\\f"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => {
+                out.push_str("\\u00");
+                out.push(DIGITS[(c as usize) >> 4] as char);
+                out.push(DIGITS[(c as usize) & 0xf] as char);
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+impl<'a> JsonValue<'a> {
+    fn write(&self, out: &mut String) {
+        match self {
+            Self::Literal(literal) => out.push_str(literal),
+            Self::String(s) => write_json_string(out, s),
+            Self::Array(items) => {
+                out.push('[');
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        out.push(',');
+                    }
+                    item.write(out);
+                }
+                out.push(']');
+            }
+            Self::Object(members) => {
+                out.push('{');
+                for (i, (key, value)) in members.iter().enumerate() {
+                    if i > 0 {
+                        out.push(',');
+                    }
+                    write_json_string(out, key);
+                    out.push(':');
+                    value.write(out);
+                }
+                out.push('}');
+            }
+        }
+    }
+}
+
+#[cfg(all(test, feature = "role-alice", feature = "role-ursula"))]
+mod tests {
+
+    use super::{
+        CanonicalizationError, JsonCanonicalizer, Metadata, MetadataCanonicalizer, RawMetadata,
+    };
+    use crate::{
+        encapsulate, generate_kfrags, reencrypt, reencrypt_canonicalized,
+        CapsuleFragVerificationError, DeserializationError, PublicKey, ReencryptionCheck,
+        SecretKey, Signer,
+    };
+
+    #[test]
+    fn test_metadata() {
+        let metadata = Metadata::new()
+            .with_policy_id(b"policy")
+            .with_ursula_id(b"ursula-1")
+            .with_timestamp(1_600_000_000);
+        let bytes = metadata.to_bytes();
+        let metadata_back = Metadata::from_bytes(&bytes).unwrap();
+        assert_eq!(metadata_back, metadata);
+        assert_eq!(metadata_back.policy_id(), Some(&b"policy"[..]));
+        assert_eq!(metadata_back.extra(), None);
+
+        let mut long_bytes = bytes.to_vec();
+        long_bytes.push(0);
+        assert_eq!(
+            Metadata::from_bytes(&long_bytes),
+            Err(DeserializationError::TooManyBytes)
+        );
+        assert_eq!(
+            Metadata::from_bytes(&bytes[..bytes.len() - 1]),
+            Err(DeserializationError::NotEnoughBytes)
+        );
+
+        let delegating_sk = SecretKey::random();
+        let delegating_pk = PublicKey::from_secret_key(&delegating_sk);
+        let signing_sk = SecretKey::random();
+        let signer = Signer::new(&signing_sk);
+        let verifying_pk = PublicKey::from_secret_key(&signing_sk);
+        let receiving_pk = PublicKey::from_secret_key(&SecretKey::random());
+
+        let (capsule, _key_seed) = encapsulate(&delegating_pk);
+        let kfrags = generate_kfrags(&delegating_sk, &receiving_pk, &signer, 2, 3, true, true);
+        let cfrag = reencrypt(&capsule, &kfrags[0], Some(&metadata)).cfrag;
+
+        assert!(cfrag
+            .verify(
+                &capsule,
+                &verifying_pk,
+                &delegating_pk,
+                &receiving_pk,
+                Some(&metadata_back)
+            )
+            .is_ok());
+
+        // The canonical encoding is what gets hashed into the proof.
+        assert!(cfrag
+            .verify_canonicalized(
+                &capsule,
+                &verifying_pk,
+                &delegating_pk,
+                &receiving_pk,
+                Some(&bytes),
+                &RawMetadata
+            )
+            .is_ok());
+
+        let other_metadata = metadata.with_timestamp(1_600_000_001);
+        assert_eq!(
+            cfrag.verify(
+                &capsule,
+                &verifying_pk,
+                &delegating_pk,
+                &receiving_pk,
+                Some(&other_metadata)
+            ),
+            Err(CapsuleFragVerificationError::IncorrectReencryption(
+                ReencryptionCheck::PointE
+            ))
+        );
+    }
+
+    #[test]
+    fn test_json_canonicalizer() {
+        let ursula_json = br#"{"policy": "p1", "ts": 1600000000, "tags": ["a", "\u00e9"]}"#;
+        let bob_json =
+            b"{ \"tags\":[\"a\",\"\xc3\xa9\"],\"ts\":1600000000,\"policy\":\"p\\u0031\" }";
+        assert_eq!(
+            &JsonCanonicalizer.canonicalize(ursula_json).unwrap() as &[u8],
+            "{\"policy\":\"p1\",\"tags\":[\"a\",\"\u{e9}\"],\"ts\":1600000000}".as_bytes()
+        );
+        assert_eq!(
+            JsonCanonicalizer.canonicalize(bob_json),
+            JsonCanonicalizer.canonicalize(ursula_json)
+        );
+
+        assert_eq!(
+            JsonCanonicalizer.canonicalize(br#"{"a": 1, "a": 2}"#),
+            Err(CanonicalizationError::DuplicateKey)
+        );
+        for malformed in [&b"{\"a\": 1,}"[..], b"[01]", b"\"\\ud800\"", b"nul", b"1 2"].iter() {
+            assert_eq!(
+                JsonCanonicalizer.canonicalize(malformed),
+                Err(CanonicalizationError::Malformed)
+            );
+        }
+
+        let delegating_sk = SecretKey::random();
+        let delegating_pk = PublicKey::from_secret_key(&delegating_sk);
+        let signing_sk = SecretKey::random();
+        let signer = Signer::new(&signing_sk);
+        let verifying_pk = PublicKey::from_secret_key(&signing_sk);
+        let receiving_pk = PublicKey::from_secret_key(&SecretKey::random());
+
+        let (capsule, _key_seed) = encapsulate(&delegating_pk);
+        let kfrags = generate_kfrags(&delegating_sk, &receiving_pk, &signer, 2, 3, true, true);
+        let cfrag =
+            reencrypt_canonicalized(&capsule, &kfrags[0], Some(ursula_json), &JsonCanonicalizer)
+                .unwrap()
+                .cfrag;
+
+        assert!(cfrag
+            .verify_canonicalized(
+                &capsule,
+                &verifying_pk,
+                &delegating_pk,
+                &receiving_pk,
+                Some(bob_json),
+                &JsonCanonicalizer
+            )
+            .is_ok());
+        assert_eq!(
+            cfrag.verify_canonicalized(
+                &capsule,
+                &verifying_pk,
+                &delegating_pk,
+                &receiving_pk,
+                Some(b"{"),
+                &JsonCanonicalizer
+            ),
+            Err(CapsuleFragVerificationError::MalformedMetadata(
+                CanonicalizationError::Malformed
+            ))
+        );
+    }
+}
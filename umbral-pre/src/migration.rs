@@ -0,0 +1,210 @@
+//! Detection of the DEM version of ciphertexts and migration between versions.
+//!
+//! Ciphertexts produced by [`encrypt()`](crate::encrypt) carry only the identifier
+//! of the AEAD algorithm, and the ones produced before it was introduced carry nothing,
+//! so the version is detected by attempting the authenticated decryption
+//! with each of the known DEMs.
+//! The migration decrypts and encrypts again under the same capsule,
+//! so the plaintext does not leave the crate,
+//! and the capsule and the key fragments created for it remain valid.
+
+use alloc::boxed::Box;
+use core::fmt;
+
+use rand_core::{CryptoRng, OsRng, RngCore};
+use zeroize::Zeroizing;
+
+use crate::capsule::Capsule;
+use crate::dem::{
+    decrypt_with_algorithm, encrypt_with_header, DecryptionError, Dem, DemAlgorithm,
+    EncryptionError, XChaCha20Poly1305Dem,
+};
+use crate::keys::SecretKey;
+use crate::pre::decapsulate_original;
+use crate::traits::SerializableToArray;
+
+/// Versions of the DEM used to encrypt the payload under the key seed of a capsule.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DemVersion {
+    /// XChaCha20-Poly1305 with the key derived from the key seed with HKDF-SHA256,
+    /// a random nonce prepended to the ciphertext, and the capsule as the authenticated data.
+    XChaCha20Poly1305,
+    /// The given AEAD algorithm, with the same key derivation, nonce and authenticated data,
+    /// and the identifier of the algorithm prepended to the ciphertext.
+    WithHeader(DemAlgorithm),
+}
+
+impl DemVersion {
+    /// The version used by [`encrypt()`](crate::encrypt).
+    pub const CURRENT: Self = Self::WithHeader(DemAlgorithm::DEFAULT);
+
+    const ALL: &'static [Self] = &[
+        Self::WithHeader(DemAlgorithm::XChaCha20Poly1305),
+        #[cfg(feature = "aes-gcm")]
+        Self::WithHeader(DemAlgorithm::Aes256Gcm),
+        Self::XChaCha20Poly1305,
+    ];
+
+    fn encrypt(
+        &self,
+        rng: &mut (impl CryptoRng + RngCore),
+        key_seed: &[u8],
+        plaintext: &[u8],
+        capsule: &Capsule,
+    ) -> Result<Box<[u8]>, EncryptionError> {
+        match self {
+            Self::XChaCha20Poly1305 => {
+                XChaCha20Poly1305Dem::new(key_seed).encrypt(rng, plaintext, &capsule.to_array())
+            }
+            Self::WithHeader(algorithm) => {
+                encrypt_with_header(*algorithm, rng, key_seed, plaintext, &capsule.to_array())
+            }
+        }
+    }
+
+    fn decrypt(
+        &self,
+        key_seed: &[u8],
+        ciphertext: &[u8],
+        capsule: &Capsule,
+    ) -> Result<Box<[u8]>, DecryptionError> {
+        match self {
+            Self::XChaCha20Poly1305 => {
+                XChaCha20Poly1305Dem::new(key_seed).decrypt(ciphertext, &capsule.to_array())
+            }
+            Self::WithHeader(algorithm) => decrypt_with_algorithm(
+                *algorithm,
+                key_seed,
+                ciphertext,
+                &capsule.to_array(),
+                usize::MAX,
+            ),
+        }
+    }
+}
+
+/// Errors that can happen when migrating a ciphertext.
+#[derive(Debug, PartialEq)]
+pub enum MigrationError {
+    /// None of the known DEM versions decrypts the ciphertext
+    /// (it is corrupted, or the key or the capsule is wrong).
+    UnknownVersion,
+    /// Failed to encrypt with the target version.
+    Encryption(EncryptionError),
+}
+
+impl fmt::Display for MigrationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnknownVersion => write!(f, "no known DEM version decrypts the ciphertext"),
+            Self::Encryption(err) => write!(f, "failed to encrypt: {}", err),
+        }
+    }
+}
+
+fn decrypt_any(
+    key_seed: &[u8],
+    ciphertext: &[u8],
+    capsule: &Capsule,
+) -> Option<(DemVersion, Zeroizing<Box<[u8]>>)> {
+    DemVersion::ALL.iter().find_map(|version| {
+        version
+            .decrypt(key_seed, ciphertext, capsule)
+            .ok()
+            .map(|plaintext| (*version, Zeroizing::new(plaintext)))
+    })
+}
+
+/// Returns the DEM version the ciphertext was encrypted with,
+/// or `None` if none of the known versions decrypts it.
+pub fn detect_dem_version(
+    decrypting_sk: &SecretKey,
+    capsule: &Capsule,
+    ciphertext: impl AsRef<[u8]>,
+) -> Option<DemVersion> {
+    let key_seed = decapsulate_original(decrypting_sk, capsule);
+    decrypt_any(&key_seed, ciphertext.as_ref(), capsule).map(|(version, _)| version)
+}
+
+/// Re-encrypts the ciphertext of the given capsule with the `target` DEM version.
+///
+/// Returns the ciphertext unchanged if it is already of the target version.
+pub fn migrate_ciphertext(
+    decrypting_sk: &SecretKey,
+    capsule: &Capsule,
+    ciphertext: impl AsRef<[u8]>,
+    target: DemVersion,
+) -> Result<Box<[u8]>, MigrationError> {
+    migrate_ciphertext_with_rng(&mut OsRng, decrypting_sk, capsule, ciphertext, target)
+}
+
+/// Same as [`migrate_ciphertext()`], but uses the given RNG instead of the default one.
+pub fn migrate_ciphertext_with_rng(
+    rng: &mut (impl CryptoRng + RngCore),
+    decrypting_sk: &SecretKey,
+    capsule: &Capsule,
+    ciphertext: impl AsRef<[u8]>,
+    target: DemVersion,
+) -> Result<Box<[u8]>, MigrationError> {
+    let ciphertext = ciphertext.as_ref();
+    let key_seed = decapsulate_original(decrypting_sk, capsule);
+    let (version, plaintext) =
+        decrypt_any(&key_seed, ciphertext, capsule).ok_or(MigrationError::UnknownVersion)?;
+    if version == target {
+        return Ok(ciphertext.into());
+    }
+    target
+        .encrypt(rng, &key_seed, &plaintext, capsule)
+        .map_err(MigrationError::Encryption)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::{detect_dem_version, migrate_ciphertext, DemVersion, MigrationError};
+    use crate::{decrypt_original, encrypt, PublicKey, SecretKey};
+
+    #[test]
+    fn test_migrate_ciphertext() {
+        let sk = SecretKey::random();
+        let pk = PublicKey::from_secret_key(&sk);
+        let plaintext = b"peace at dawn";
+        let (capsule, ciphertext) = encrypt(&pk, plaintext).unwrap();
+
+        assert_eq!(
+            detect_dem_version(&sk, &capsule, &ciphertext),
+            Some(DemVersion::CURRENT)
+        );
+
+        let migrated = migrate_ciphertext(&sk, &capsule, &ciphertext, DemVersion::CURRENT).unwrap();
+        assert_eq!(
+            &decrypt_original(&sk, &capsule, &migrated).unwrap() as &[u8],
+            plaintext
+        );
+
+        // Ciphertexts from before the algorithm header are migrated to the headered form,
+        // and can still be decrypted directly.
+        let legacy =
+            migrate_ciphertext(&sk, &capsule, &ciphertext, DemVersion::XChaCha20Poly1305).unwrap();
+        assert_eq!(
+            detect_dem_version(&sk, &capsule, &legacy),
+            Some(DemVersion::XChaCha20Poly1305)
+        );
+        assert_eq!(
+            &decrypt_original(&sk, &capsule, &legacy).unwrap() as &[u8],
+            plaintext
+        );
+        let migrated = migrate_ciphertext(&sk, &capsule, &legacy, DemVersion::CURRENT).unwrap();
+        assert_eq!(
+            detect_dem_version(&sk, &capsule, &migrated),
+            Some(DemVersion::CURRENT)
+        );
+
+        let other_sk = SecretKey::random();
+        assert_eq!(detect_dem_version(&other_sk, &capsule, &ciphertext), None);
+        assert_eq!(
+            migrate_ciphertext(&other_sk, &capsule, &ciphertext, DemVersion::CURRENT),
+            Err(MigrationError::UnknownVersion)
+        );
+    }
+}
@@ -0,0 +1,266 @@
+//! BIP39 mnemonic phrases for [`SecretKeyFactory`] seeds, enabled with the `bip39` feature.
+//!
+//! The factory seed is derived from the phrase and an optional passphrase
+//! exactly as the BIP39 seed (PBKDF2-HMAC-SHA512 with 2048 rounds),
+//! so the same phrase can be backed up and restored with standard wallet tooling.
+//! Since this derivation is one-way, a factory cannot be converted back into a phrase;
+//! the phrase must be generated first with [`generate_mnemonic()`] and then stored.
+
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt;
+
+use generic_array::GenericArray;
+use hmac::{Hmac, Mac, NewMac};
+use rand_core::{CryptoRng, OsRng, RngCore};
+use sha2::{Digest, Sha256, Sha512};
+use typenum::{U32, U64};
+use zeroize::{Zeroize, Zeroizing};
+
+use crate::keys::SecretKeyFactory;
+use crate::traits::DeserializableFromArray;
+
+const ENGLISH_WORDLIST: &str = include_str!("bip39_english.txt");
+
+const PBKDF2_ROUNDS: u32 = 2048;
+
+const BITS_PER_WORD: usize = 11;
+
+/// Errors that can happen when decoding a mnemonic phrase.
+#[derive(Debug, PartialEq)]
+pub enum MnemonicError {
+    /// The number of words is not 12, 15, 18, 21 or 24.
+    InvalidWordCount,
+    /// One of the words is not in the BIP39 English wordlist.
+    UnknownWord,
+    /// The checksum encoded in the phrase does not match.
+    InvalidChecksum,
+}
+
+impl fmt::Display for MnemonicError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidWordCount => write!(f, "invalid number of words"),
+            Self::UnknownWord => write!(f, "unknown word"),
+            Self::InvalidChecksum => write!(f, "invalid checksum"),
+        }
+    }
+}
+
+fn wordlist() -> Vec<&'static str> {
+    ENGLISH_WORDLIST.lines().collect()
+}
+
+fn get_bit(bytes: &[u8], i: usize) -> bool {
+    bytes[i / 8] & (0x80 >> (i % 8)) != 0
+}
+
+fn entropy_to_phrase(entropy: &[u8]) -> String {
+    let words = wordlist();
+    let checksum = Sha256::digest(entropy);
+    let entropy_bits = entropy.len() * 8;
+    let total_bits = entropy_bits + entropy_bits / 32;
+
+    let bit = |i: usize| {
+        if i < entropy_bits {
+            get_bit(entropy, i)
+        } else {
+            get_bit(&checksum, i - entropy_bits)
+        }
+    };
+
+    let mut phrase = String::new();
+    for word in 0..total_bits / BITS_PER_WORD {
+        let index = (0..BITS_PER_WORD).fold(0, |acc, j| {
+            (acc << 1) | bit(word * BITS_PER_WORD + j) as usize
+        });
+        if word > 0 {
+            phrase.push(' ');
+        }
+        phrase.push_str(words[index]);
+    }
+    phrase
+}
+
+fn check_phrase(words: &[&str]) -> Result<(), MnemonicError> {
+    if !matches!(words.len(), 12 | 15 | 18 | 21 | 24) {
+        return Err(MnemonicError::InvalidWordCount);
+    }
+
+    let wordlist = wordlist();
+    let total_bits = words.len() * BITS_PER_WORD;
+    let entropy_bits = total_bits * 32 / 33;
+
+    let mut bytes = Zeroizing::new(vec![0u8; total_bits / 8 + 1]);
+    for (word_idx, word) in words.iter().enumerate() {
+        let index = wordlist
+            .binary_search(word)
+            .or(Err(MnemonicError::UnknownWord))?;
+        for j in 0..BITS_PER_WORD {
+            if (index >> (BITS_PER_WORD - 1 - j)) & 1 == 1 {
+                let i = word_idx * BITS_PER_WORD + j;
+                bytes[i / 8] |= 0x80 >> (i % 8);
+            }
+        }
+    }
+
+    let checksum = Sha256::digest(&bytes[..entropy_bits / 8]);
+    if (entropy_bits..total_bits)
+        .all(|i| get_bit(&bytes, i) == get_bit(&checksum, i - entropy_bits))
+    {
+        Ok(())
+    } else {
+        Err(MnemonicError::InvalidChecksum)
+    }
+}
+
+// PBKDF2-HMAC-SHA512 with a single output block (which is all BIP39 needs).
+fn pbkdf2_hmac_sha512(password: &[u8], salt: &[u8]) -> GenericArray<u8, U64> {
+    // HMAC accepts keys of any length, so this cannot fail.
+    let prf = Hmac::<Sha512>::new_from_slice(password).unwrap();
+
+    let mut mac = prf.clone();
+    mac.update(salt);
+    mac.update(&1u32.to_be_bytes());
+    let mut block = mac.finalize().into_bytes();
+    let mut result = block;
+
+    for _ in 1..PBKDF2_ROUNDS {
+        let mut mac = prf.clone();
+        mac.update(&block);
+        block.zeroize();
+        block = mac.finalize().into_bytes();
+        for (r, b) in result.iter_mut().zip(block.iter()) {
+            *r ^= b;
+        }
+    }
+    block.zeroize();
+
+    result
+}
+
+/// Generates a random 24-word BIP39 mnemonic phrase (using the English wordlist).
+pub fn generate_mnemonic() -> String {
+    generate_mnemonic_with_rng(&mut OsRng)
+}
+
+/// Same as [`generate_mnemonic()`], but uses the given RNG instead of the default one.
+pub fn generate_mnemonic_with_rng(rng: &mut (impl CryptoRng + RngCore)) -> String {
+    let mut entropy = Zeroizing::new(GenericArray::<u8, U32>::default());
+    rng.fill_bytes(&mut entropy);
+    entropy_to_phrase(&entropy)
+}
+
+impl SecretKeyFactory {
+    /// Creates a factory from a BIP39 mnemonic phrase and a passphrase
+    /// (use an empty string if there is none).
+    ///
+    /// The resulting seed is the same as the one BIP39-compatible wallets produce.
+    /// The words can be separated by any whitespace.
+    /// Non-ASCII passphrases must be NFKD-normalized by the caller.
+    pub fn from_mnemonic(phrase: &str, passphrase: &str) -> Result<Self, MnemonicError> {
+        let words: Vec<&str> = phrase.split_whitespace().collect();
+        check_phrase(&words)?;
+
+        let normalized_phrase = Zeroizing::new(words.join(" "));
+        let salt = Zeroizing::new([b"mnemonic", passphrase.as_bytes()].concat());
+        let mut seed = pbkdf2_hmac_sha512(normalized_phrase.as_bytes(), &salt);
+
+        // Cannot fail, any 64 bytes make a valid seed.
+        let factory = SecretKeyFactory::from_array(&seed).unwrap();
+        seed.zeroize();
+        Ok(factory)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use alloc::format;
+    use alloc::string::String;
+    use alloc::vec::Vec;
+
+    use rand_core::OsRng;
+
+    use super::{generate_mnemonic_with_rng, MnemonicError};
+    use crate::{SecretKeyFactory, SerializableToArray};
+
+    fn hex(bytes: &[u8]) -> String {
+        bytes.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    #[test]
+    fn test_bip39_vectors() {
+        // From the reference BIP39 test vectors (passphrase "TREZOR").
+        let vectors = [
+            (
+                concat!(
+                    "abandon abandon abandon abandon abandon abandon abandon abandon ",
+                    "abandon abandon abandon abandon abandon abandon abandon abandon ",
+                    "abandon abandon abandon abandon abandon abandon abandon art"
+                ),
+                concat!(
+                    "bda85446c68413707090a52022edd26a1c9462295029f2e60cd7c4f2bbd30971",
+                    "70af7a4d73245cafa9c3cca8d561a7c3de6f5d4a10be8ed2a5e608d68f92fcc8"
+                ),
+            ),
+            (
+                concat!(
+                    "legal winner thank year wave sausage worth useful legal winner thank year ",
+                    "wave sausage worth useful legal winner thank year wave sausage worth title"
+                ),
+                concat!(
+                    "bc09fca1804f7e69da93c2f2028eb238c227f2e9dda30cd63699232578480a40",
+                    "21b146ad717fbb7e451ce9eb835f43620bf5c514db0f8add49f5d121449d3e87"
+                ),
+            ),
+            (
+                concat!(
+                    "zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo ",
+                    "zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo vote"
+                ),
+                concat!(
+                    "dd48c104698c30cfe2b6142103248622fb7bb0ff692eebb00089b32d22484e16",
+                    "13912f0a5b694407be899ffd31ed3992c456cdf60f5d4564b8ba3f05a69890ad"
+                ),
+            ),
+        ];
+
+        for (phrase, seed) in vectors.iter() {
+            let factory = SecretKeyFactory::from_mnemonic(phrase, "TREZOR").unwrap();
+            assert_eq!(hex(&factory.to_array()), *seed);
+        }
+    }
+
+    #[test]
+    fn test_generate_and_restore() {
+        let phrase = generate_mnemonic_with_rng(&mut OsRng);
+        assert_eq!(phrase.split(' ').count(), 24);
+
+        let factory1 = SecretKeyFactory::from_mnemonic(&phrase, "").unwrap();
+        let factory2 = SecretKeyFactory::from_mnemonic(&phrase, "").unwrap();
+        let factory3 = SecretKeyFactory::from_mnemonic(&phrase, "passphrase").unwrap();
+        assert!(factory1 == factory2);
+        assert!(factory1 != factory3);
+
+        assert_eq!(
+            SecretKeyFactory::from_mnemonic("abandon abandon", "").err(),
+            Some(MnemonicError::InvalidWordCount)
+        );
+
+        let mut words: Vec<&str> = phrase.split(' ').collect();
+        words[0] = "umbral";
+        assert_eq!(
+            SecretKeyFactory::from_mnemonic(&words.join(" "), "").err(),
+            Some(MnemonicError::UnknownWord)
+        );
+
+        // A valid phrase would end with "art".
+        let phrase = ["abandon"; 24].join(" ");
+        assert_eq!(
+            SecretKeyFactory::from_mnemonic(&phrase, "").err(),
+            Some(MnemonicError::InvalidChecksum)
+        );
+    }
+}
@@ -0,0 +1,207 @@
+//! Encryption of a single plaintext for several recipients.
+//!
+//! The plaintext is encrypted once under a random data key,
+//! and the data key is encrypted for each recipient under a separate capsule.
+//! Each recipient's capsule can be opened directly with their secret key,
+//! or reencrypted to a delegatee just like a capsule created by [`encrypt()`](crate::encrypt).
+
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+
+use generic_array::sequence::Concat;
+use generic_array::GenericArray;
+use rand_core::{CryptoRng, OsRng, RngCore};
+use typenum::{op, U32};
+use zeroize::Zeroizing;
+
+use crate::capsule::Capsule;
+#[cfg(feature = "role-bob")]
+use crate::capsule_frag::VerifiedCapsuleFrag;
+use crate::dem::{DecryptionError, Dem, EncryptionError, NonceSize, TagSize, XChaCha20Poly1305Dem};
+use crate::keys::{PublicKey, SecretKey};
+use crate::pre::{decapsulate_original, encapsulate_with_rng};
+#[cfg(feature = "role-bob")]
+use crate::pre::{decapsulate_reencrypted, ReencryptionError};
+use crate::traits::{
+    DeserializableFromArray, DeserializationError, RepresentableAsArray, SerializableToArray,
+};
+
+type DataKeySize = U32;
+type WrappedKeySize = op!(NonceSize + DataKeySize + TagSize);
+
+/// The data key of a multi-recipient ciphertext encrypted for one of the recipients.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RecipientKey {
+    capsule: Capsule,
+    wrapped_key: GenericArray<u8, WrappedKeySize>,
+}
+
+impl RecipientKey {
+    /// Returns the capsule of the recipient,
+    /// which can be reencrypted with [`reencrypt()`](crate::reencrypt).
+    pub fn capsule(&self) -> &Capsule {
+        &self.capsule
+    }
+
+    fn unwrap_key(&self, key_seed: &[u8]) -> Result<Zeroizing<Box<[u8]>>, DecryptionError> {
+        XChaCha20Poly1305Dem::new(key_seed)
+            .decrypt(&self.wrapped_key, &self.capsule.to_array())
+            .map(Zeroizing::new)
+    }
+}
+
+type CapsuleSize = <Capsule as RepresentableAsArray>::Size;
+
+impl RepresentableAsArray for RecipientKey {
+    type Size = op!(CapsuleSize + WrappedKeySize);
+}
+
+impl SerializableToArray for RecipientKey {
+    fn to_array(&self) -> GenericArray<u8, Self::Size> {
+        self.capsule.to_array().concat(self.wrapped_key)
+    }
+}
+
+impl DeserializableFromArray for RecipientKey {
+    fn from_array(arr: &GenericArray<u8, Self::Size>) -> Result<Self, DeserializationError> {
+        let (capsule, wrapped_key) = Capsule::take(*arr)?;
+        Ok(Self {
+            capsule,
+            wrapped_key,
+        })
+    }
+}
+
+/// Encrypts the plaintext once for all the given recipients.
+///
+/// Returns a [`RecipientKey`] for each recipient (in the same order) and the ciphertext.
+pub fn encrypt_multi(
+    pks: &[&PublicKey],
+    plaintext: &[u8],
+) -> Result<(Box<[RecipientKey]>, Box<[u8]>), EncryptionError> {
+    encrypt_multi_with_rng(&mut OsRng, pks, plaintext)
+}
+
+/// Same as [`encrypt_multi()`], but uses the given RNG instead of the default one.
+pub fn encrypt_multi_with_rng(
+    rng: &mut (impl CryptoRng + RngCore),
+    pks: &[&PublicKey],
+    plaintext: &[u8],
+) -> Result<(Box<[RecipientKey]>, Box<[u8]>), EncryptionError> {
+    let mut data_key = Zeroizing::new(GenericArray::<u8, DataKeySize>::default());
+    rng.fill_bytes(&mut data_key);
+    let ciphertext = XChaCha20Poly1305Dem::new(&data_key).encrypt(rng, plaintext, &[])?;
+
+    let mut recipient_keys = Vec::with_capacity(pks.len());
+    for pk in pks {
+        let (capsule, key_seed) = encapsulate_with_rng(rng, pk);
+        // The data key has a fixed size, so the encryption cannot fail,
+        // and the result has the expected length.
+        let wrapped_key = XChaCha20Poly1305Dem::new(&key_seed)
+            .encrypt(rng, &data_key, &capsule.to_array())
+            .unwrap();
+        recipient_keys.push(RecipientKey {
+            capsule,
+            wrapped_key: GenericArray::clone_from_slice(&wrapped_key),
+        });
+    }
+
+    Ok((recipient_keys.into_boxed_slice(), ciphertext))
+}
+
+/// Decrypts a ciphertext created by [`encrypt_multi()`]
+/// using the secret key of one of the recipients.
+pub fn decrypt_multi_original(
+    decrypting_sk: &SecretKey,
+    recipient_key: &RecipientKey,
+    ciphertext: impl AsRef<[u8]>,
+) -> Result<Box<[u8]>, DecryptionError> {
+    let key_seed = decapsulate_original(decrypting_sk, &recipient_key.capsule);
+    let data_key = recipient_key.unwrap_key(&key_seed)?;
+    XChaCha20Poly1305Dem::new(&data_key).decrypt(ciphertext, &[])
+}
+
+/// Decrypts a ciphertext created by [`encrypt_multi()`]
+/// using capsule fragments of the recipient's capsule.
+///
+/// `delegating_pk` is the public key of the recipient that delegated the decryption rights.
+#[cfg(feature = "role-bob")]
+pub fn decrypt_multi_reencrypted(
+    decrypting_sk: &SecretKey,
+    delegating_pk: &PublicKey,
+    recipient_key: &RecipientKey,
+    verified_cfrags: &[VerifiedCapsuleFrag],
+    ciphertext: impl AsRef<[u8]>,
+) -> Result<Box<[u8]>, ReencryptionError> {
+    let key_seed = decapsulate_reencrypted(
+        decrypting_sk,
+        delegating_pk,
+        &recipient_key.capsule,
+        verified_cfrags,
+    )
+    .map_err(ReencryptionError::OnOpen)?;
+    let data_key = recipient_key
+        .unwrap_key(&key_seed)
+        .map_err(ReencryptionError::OnDecryption)?;
+    XChaCha20Poly1305Dem::new(&data_key)
+        .decrypt(ciphertext, &[])
+        .map_err(ReencryptionError::OnDecryption)
+}
+
+#[cfg(all(
+    test,
+    feature = "role-alice",
+    feature = "role-ursula",
+    feature = "role-bob"
+))]
+mod tests {
+
+    use alloc::vec::Vec;
+
+    use super::{decrypt_multi_original, decrypt_multi_reencrypted, encrypt_multi, RecipientKey};
+    use crate::{
+        generate_kfrags, reencrypt, DecryptionError, DeserializableFromArray, PublicKey, SecretKey,
+        SerializableToArray, Signer,
+    };
+
+    #[test]
+    fn test_encrypt_multi() {
+        let sks: Vec<SecretKey> = (0..3).map(|_| SecretKey::random()).collect();
+        let pks: Vec<PublicKey> = sks.iter().map(PublicKey::from_secret_key).collect();
+        let pk_refs: Vec<&PublicKey> = pks.iter().collect();
+
+        let plaintext = b"peace at dawn";
+        let (recipient_keys, ciphertext) = encrypt_multi(&pk_refs, plaintext).unwrap();
+        assert_eq!(recipient_keys.len(), 3);
+
+        for (sk, recipient_key) in sks.iter().zip(recipient_keys.iter()) {
+            let recipient_key = RecipientKey::from_array(&recipient_key.to_array()).unwrap();
+            let plaintext_back = decrypt_multi_original(sk, &recipient_key, &ciphertext).unwrap();
+            assert_eq!(&plaintext_back as &[u8], plaintext);
+        }
+
+        assert_eq!(
+            decrypt_multi_original(&sks[0], &recipient_keys[1], &ciphertext),
+            Err(DecryptionError::AuthenticationFailed)
+        );
+
+        // The second recipient delegates decryption.
+        let receiving_sk = SecretKey::random();
+        let receiving_pk = PublicKey::from_secret_key(&receiving_sk);
+        let signer = Signer::new(&SecretKey::random());
+        let kfrags = generate_kfrags(&sks[1], &receiving_pk, &signer, 2, 3, true, true);
+        let cfrags: Vec<_> = kfrags[..2]
+            .iter()
+            .map(|kfrag| reencrypt(recipient_keys[1].capsule(), kfrag, None))
+            .collect();
+        let plaintext_back = decrypt_multi_reencrypted(
+            &receiving_sk,
+            &pks[1],
+            &recipient_keys[1],
+            &cfrags,
+            &ciphertext,
+        )
+        .unwrap();
+        assert_eq!(&plaintext_back as &[u8], plaintext);
+    }
+}
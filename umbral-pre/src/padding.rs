@@ -0,0 +1,121 @@
+//! Padding of serialized objects to fixed bucket sizes.
+//!
+//! Serialized key and capsule fragments have constant sizes,
+//! but the messages they travel in (along with ciphertexts and metadata) do not,
+//! and the sizes observed on the network can reveal the policy parameters.
+//! Padding every message to one of a few fixed sizes removes this side channel.
+//!
+//! The padding is the ISO/IEC 7816-4 one: a `0x80` byte followed by zeros,
+//! so it is unambiguous for any message.
+
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use core::fmt;
+
+/// Default bucket sizes: 256 bytes to 64 KiB.
+pub const DEFAULT_BUCKETS: &[usize] = &[256, 1024, 4096, 16384, 65536];
+
+const PADDING_START: u8 = 0x80;
+
+/// Errors that can happen when padding or unpadding messages.
+#[derive(Debug, PartialEq)]
+pub enum PaddingError {
+    /// The message (with at least one byte of padding) does not fit in any of the buckets.
+    MessageTooLarge,
+    /// The padded message does not end with a valid padding.
+    InvalidPadding,
+}
+
+impl fmt::Display for PaddingError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::MessageTooLarge => write!(f, "message does not fit in any bucket"),
+            Self::InvalidPadding => write!(f, "invalid padding"),
+        }
+    }
+}
+
+/// Returns the size of `message_len` bytes padded with [`pad()`],
+/// or `None` if it does not fit in any of the `buckets`.
+///
+/// The buckets do not have to be sorted; the smallest fitting one is chosen.
+pub fn padded_size(message_len: usize, buckets: &[usize]) -> Option<usize> {
+    buckets
+        .iter()
+        .copied()
+        .filter(|bucket| *bucket > message_len)
+        .min()
+}
+
+/// Pads the message to the smallest of `buckets` that can hold it
+/// and at least one byte of padding.
+pub fn pad(message: &[u8], buckets: &[usize]) -> Result<Box<[u8]>, PaddingError> {
+    let size = padded_size(message.len(), buckets).ok_or(PaddingError::MessageTooLarge)?;
+    let mut result = Vec::with_capacity(size);
+    result.extend_from_slice(message);
+    result.push(PADDING_START);
+    result.resize(size, 0);
+    Ok(result.into_boxed_slice())
+}
+
+/// Strips the padding added by [`pad()`], returning the original message.
+///
+/// The bucket sizes are not checked; the caller may verify
+/// that `padded.len()` is one of the expected ones.
+pub fn unpad(padded: &[u8]) -> Result<&[u8], PaddingError> {
+    let end = padded
+        .iter()
+        .rposition(|byte| *byte != 0)
+        .ok_or(PaddingError::InvalidPadding)?;
+    if padded[end] != PADDING_START {
+        return Err(PaddingError::InvalidPadding);
+    }
+    Ok(&padded[..end])
+}
+
+#[cfg(all(test, feature = "role-alice", feature = "role-ursula"))]
+mod tests {
+
+    use super::{pad, padded_size, unpad, PaddingError, DEFAULT_BUCKETS};
+    use crate::{
+        encapsulate, generate_kfrags, reencrypt, PublicKey, SecretKey, SerializableToArray, Signer,
+    };
+
+    #[test]
+    fn test_pad_and_unpad() {
+        let delegating_sk = SecretKey::random();
+        let delegating_pk = PublicKey::from_secret_key(&delegating_sk);
+        let receiving_pk = PublicKey::from_secret_key(&SecretKey::random());
+        let signer = Signer::new(&SecretKey::random());
+
+        let (capsule, _key_seed) = encapsulate(&delegating_pk);
+        let kfrags = generate_kfrags(&delegating_sk, &receiving_pk, &signer, 2, 3, true, true);
+        let cfrag = reencrypt(&capsule, &kfrags[0], None);
+
+        // Fragments and a request with a capsule end up with the same size.
+        let kfrag_bytes = kfrags[0].to_array();
+        let cfrag_bytes = cfrag.to_array();
+        let padded_kfrag = pad(&kfrag_bytes, DEFAULT_BUCKETS).unwrap();
+        let padded_cfrag = pad(&cfrag_bytes, DEFAULT_BUCKETS).unwrap();
+        let padded_capsule = pad(&capsule.to_array(), DEFAULT_BUCKETS).unwrap();
+        assert_eq!(padded_kfrag.len(), 1024);
+        assert_eq!(padded_cfrag.len(), 1024);
+        assert_eq!(padded_capsule.len(), 256);
+        assert_eq!(unpad(&padded_kfrag).unwrap(), &kfrag_bytes[..]);
+        assert_eq!(unpad(&padded_cfrag).unwrap(), &cfrag_bytes[..]);
+
+        // Messages ending in zeros or in the padding byte are recovered correctly.
+        for message in [&[][..], &[0, 0], &[0x80], &[1; 255]].iter() {
+            let padded = pad(message, &[256, 16]).unwrap();
+            assert_eq!(
+                padded.len(),
+                padded_size(message.len(), &[256, 16]).unwrap()
+            );
+            assert_eq!(unpad(&padded).unwrap(), *message);
+        }
+
+        assert_eq!(pad(&[1; 256], &[256]), Err(PaddingError::MessageTooLarge));
+        assert_eq!(unpad(&[1, 0, 0]), Err(PaddingError::InvalidPadding));
+        assert_eq!(unpad(&[0, 0]), Err(PaddingError::InvalidPadding));
+    }
+}
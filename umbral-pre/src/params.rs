@@ -1,5 +1,78 @@
+//! Scheme parameters and their derivation.
+//!
+//! The only parameter is the secondary generator `U`, which is obtained by hashing
+//! a fixed label with a fixed domain separation tag.
+//! The functions here allow one to reproduce the derivation and compare the result
+//! with the value used by the library.
+//!
+//! With the `rfc9380` feature the hashing is the standard `hash_to_curve`
+//! (suite `secp256k1_XMD:SHA-256_SSWU_RO_`) instead of try-and-increment,
+//! so `U` has a different value.
+
+use generic_array::GenericArray;
+
 use crate::curve::CurvePoint;
+#[cfg(feature = "rfc9380")]
+use crate::hash_to_curve::hash_to_curve;
+#[cfg(not(feature = "rfc9380"))]
 use crate::hashing::unsafe_hash_to_point;
+use crate::keys::PublicKey;
+use crate::traits::{RepresentableAsArray, SerializableToArray};
+
+type PointSize = <PublicKey as RepresentableAsArray>::Size;
+
+/// The domain separation tag used to derive the point `U`.
+pub const PARAMETERS_DST: &[u8] = b"PARAMETERS";
+
+/// The data hashed to derive the point `U`.
+pub const POINT_U_LABEL: &[u8] = b"POINT_U";
+
+/// The expected value of the point `U` (in the compressed SEC1 representation).
+#[cfg(not(feature = "rfc9380"))]
+pub const POINT_U_BYTES: [u8; 33] = [
+    2, 154, 202, 37, 14, 157, 55, 124, 230, 80, 46, 93, 98, 71, 197, 223, 218, 236, 196, 193, 109,
+    152, 203, 58, 7, 0, 164, 21, 174, 202, 172, 212, 110,
+];
+
+/// The expected value of the point `U` (in the compressed SEC1 representation).
+#[cfg(feature = "rfc9380")]
+pub const POINT_U_BYTES: [u8; 33] = [
+    3, 7, 147, 144, 208, 251, 226, 32, 252, 52, 170, 184, 236, 191, 73, 9, 128, 152, 3, 104, 32,
+    250, 219, 152, 192, 49, 67, 229, 93, 184, 236, 115, 205,
+];
+
+#[cfg(not(feature = "rfc9380"))]
+fn derive_point_u_internal() -> CurvePoint {
+    // Only fails with a minuscule probability,
+    // and since the inputs are fixed here, we can just ignore the panic branch,
+    // because we know it succeeds.
+
+    // Technically, we don't need the DST here now since it's a custom hashing function
+    // used for exactly one purpose (and, really, on only one value).
+    // But in view of possible replacement with the standard hash-to-curve (see #35),
+    // which will need a DST, we're using a DST here as well.
+    unsafe_hash_to_point(PARAMETERS_DST, POINT_U_LABEL).unwrap()
+}
+
+#[cfg(feature = "rfc9380")]
+fn derive_point_u_internal() -> CurvePoint {
+    hash_to_curve(PARAMETERS_DST, POINT_U_LABEL)
+}
+
+/// Derives the point `U` from [`PARAMETERS_DST`] and [`POINT_U_LABEL`]
+/// using the try-and-increment hash-to-curve (or the RFC 9380 one with the `rfc9380` feature),
+/// and returns it in the compressed SEC1 representation.
+pub fn derive_point_u() -> GenericArray<u8, PointSize> {
+    derive_point_u_internal().to_array()
+}
+
+/// Recomputes the point `U` and checks that it matches both [`POINT_U_BYTES`]
+/// and the value used in [`Parameters`].
+/// Can be called on startup to confirm no unexpected parameters are in use.
+pub fn check_parameters() -> bool {
+    let derived = derive_point_u();
+    derived.as_slice() == POINT_U_BYTES && Parameters::new().u.to_array() == derived
+}
 
 /// An object containing shared scheme parameters.
 #[derive(Clone, Copy, Debug, PartialEq)]
@@ -13,25 +86,27 @@ impl Parameters {
         // The goal is to find two distinct points `g` and `u` for which `log_g(u)` is unknown.
         // `g` is fixed to be the generator because it has to be the same
         // as the one used for secret/public keys, and it is standardized (for a given curve).
+        Self {
+            u: derive_point_u_internal(),
+        }
+    }
 
-        // Only fails with a minuscule probability,
-        // and since `g` is fixed here, we can just ignore the panic branch,
-        // because we know it succeeds.
-
-        // Technically, we don't need the DST here now since it's a custom hashing function
-        // used for exactly one purpose (and, really, on only one value).
-        // But in view of possible replacement with the standard hash-to-curve (see #35),
-        // which will need a DST, we're using a DST here as well.
-        let u = unsafe_hash_to_point(b"PARAMETERS", b"POINT_U").unwrap();
+    /// Returns the point `U` in the compressed SEC1 representation.
+    pub fn u_bytes(&self) -> GenericArray<u8, PointSize> {
+        self.u.to_array()
+    }
+}
 
-        Self { u }
+impl Default for Parameters {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
 #[cfg(test)]
 mod tests {
 
-    use super::Parameters;
+    use super::{check_parameters, derive_point_u, Parameters, POINT_U_BYTES};
 
     #[test]
     fn test_default() {
@@ -39,4 +114,11 @@ mod tests {
         let p2 = Parameters::new();
         assert_eq!(p1, p2);
     }
+
+    #[test]
+    fn test_derivation() {
+        assert!(check_parameters());
+        assert_eq!(derive_point_u().as_slice(), POINT_U_BYTES);
+        assert_eq!(Parameters::new().u_bytes(), derive_point_u());
+    }
 }
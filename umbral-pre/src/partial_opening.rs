@@ -0,0 +1,279 @@
+//! Opening of a reencrypted capsule spread over time.
+//!
+//! [`PartialOpening`] keeps the parts of the already verified capsule fragments
+//! needed for the combination, so the receiving party can persist it
+//! (e.g. across restarts of the application) and open the capsule
+//! when the remaining fragments arrive, without verifying the earlier ones again.
+
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+
+use crate::capsule::{Capsule, OpenReencryptedError};
+use crate::capsule_frag::VerifiedCapsuleFrag;
+use crate::curve::CurvePoint;
+use crate::hashing_ds::hash_to_capsule_fingerprint;
+use crate::key_frag::KeyFragID;
+use crate::keys::{PublicKey, SecretKey};
+use crate::pre::KeySeed;
+use crate::traits::{
+    DeserializableFromArray, DeserializationError, RepresentableAsArray, SerializableToArray,
+};
+
+use typenum::Unsigned;
+
+const FORMAT_VERSION: u8 = 1;
+
+#[derive(Clone, Debug, PartialEq)]
+struct Contribution {
+    kfrag_id: KeyFragID,
+    point_e1: CurvePoint,
+    point_v1: CurvePoint,
+}
+
+/// The state of a capsule opening with some of the capsule fragments collected.
+///
+/// Only the parts of the fragments used in the combination are kept
+/// (no secret values are stored), so the state can be persisted
+/// with the same care as the fragments themselves.
+/// The fragments must be verified against the capsule of the opening
+/// before they are added.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PartialOpening {
+    capsule: Capsule,
+    precursor: Option<CurvePoint>,
+    contributions: Vec<Contribution>,
+}
+
+fn take<T: DeserializableFromArray>(bytes: &mut &[u8]) -> Result<T, DeserializationError> {
+    let size = <T as RepresentableAsArray>::Size::USIZE;
+    if bytes.len() < size {
+        return Err(DeserializationError::NotEnoughBytes);
+    }
+    let (head, tail) = bytes.split_at(size);
+    *bytes = tail;
+    T::from_bytes(head)
+}
+
+impl PartialOpening {
+    /// Starts an opening of the given capsule with no fragments.
+    pub fn new(capsule: &Capsule) -> Self {
+        Self {
+            capsule: *capsule,
+            precursor: None,
+            contributions: Vec::new(),
+        }
+    }
+
+    /// Returns the capsule being opened.
+    pub fn capsule(&self) -> &Capsule {
+        &self.capsule
+    }
+
+    /// Returns the number of the fragments added so far.
+    pub fn len(&self) -> usize {
+        self.contributions.len()
+    }
+
+    /// Returns `true` if no fragments were added yet.
+    pub fn is_empty(&self) -> bool {
+        self.contributions.is_empty()
+    }
+
+    /// Adds a verified capsule fragment to the opening.
+    ///
+    /// Returns [`OpenReencryptedError::MismatchedCapsule`] if the fragment
+    /// was verified against a different capsule,
+    /// [`OpenReencryptedError::MismatchedCapsuleFrags`] if the fragment
+    /// originates from a different set of key fragments than the ones added before,
+    /// and [`OpenReencryptedError::RepeatingCapsuleFrags`] if a fragment
+    /// for the same key fragment was already added.
+    pub fn add(
+        &mut self,
+        verified_cfrag: &VerifiedCapsuleFrag,
+    ) -> Result<(), OpenReencryptedError> {
+        if verified_cfrag.capsule_fingerprint != hash_to_capsule_fingerprint(&self.capsule) {
+            return Err(OpenReencryptedError::MismatchedCapsule);
+        }
+        let cfrag = &verified_cfrag.cfrag;
+        match self.precursor {
+            Some(precursor) if precursor != cfrag.precursor => {
+                return Err(OpenReencryptedError::MismatchedCapsuleFrags)
+            }
+            _ => {}
+        }
+        if self
+            .contributions
+            .iter()
+            .any(|contribution| contribution.kfrag_id == cfrag.kfrag_id)
+        {
+            return Err(OpenReencryptedError::RepeatingCapsuleFrags);
+        }
+
+        self.precursor = Some(cfrag.precursor);
+        self.contributions.push(Contribution {
+            kfrag_id: cfrag.kfrag_id,
+            point_e1: cfrag.point_e1,
+            point_v1: cfrag.point_v1,
+        });
+        Ok(())
+    }
+
+    /// Recovers the key seed from the fragments added so far
+    /// (same as [`decapsulate_reencrypted()`](crate::decapsulate_reencrypted)).
+    ///
+    /// Fails with [`OpenReencryptedError::NotEnoughCapsuleFrags`]
+    /// if fewer than `threshold` fragments were added; the opening can be continued then.
+    pub fn finish(
+        &self,
+        decrypting_sk: &SecretKey,
+        delegating_pk: &PublicKey,
+    ) -> Result<KeySeed, OpenReencryptedError> {
+        let precursor = self
+            .precursor
+            .as_ref()
+            .ok_or(OpenReencryptedError::NoCapsuleFrags)?;
+        let kfrag_ids: Vec<_> = self.contributions.iter().map(|c| c.kfrag_id).collect();
+        let e1s: Vec<_> = self.contributions.iter().map(|c| c.point_e1).collect();
+        let v1s: Vec<_> = self.contributions.iter().map(|c| c.point_v1).collect();
+        self.capsule
+            .open_reencrypted_parts(
+                decrypting_sk,
+                delegating_pk,
+                precursor,
+                &kfrag_ids,
+                &e1s,
+                &v1s,
+            )
+            .map(|key_seed| key_seed.to_array())
+            .map_err(|err| match err {
+                OpenReencryptedError::ValidationFailed => {
+                    OpenReencryptedError::NotEnoughCapsuleFrags
+                }
+                err => err,
+            })
+    }
+
+    /// Returns the byte representation of the state.
+    pub fn to_bytes(&self) -> Box<[u8]> {
+        let mut out = Vec::new();
+        out.push(FORMAT_VERSION);
+        out.extend_from_slice(&self.capsule.to_array());
+        out.extend_from_slice(&(self.contributions.len() as u32).to_be_bytes());
+        if let Some(precursor) = &self.precursor {
+            out.extend_from_slice(&precursor.to_array());
+        }
+        for contribution in self.contributions.iter() {
+            out.extend_from_slice(&contribution.kfrag_id.to_array());
+            out.extend_from_slice(&contribution.point_e1.to_array());
+            out.extend_from_slice(&contribution.point_v1.to_array());
+        }
+        out.into_boxed_slice()
+    }
+
+    /// Restores the state from its byte representation.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, DeserializationError> {
+        let (version, mut bytes) = bytes
+            .split_first()
+            .ok_or(DeserializationError::NotEnoughBytes)?;
+        if *version != FORMAT_VERSION {
+            return Err(DeserializationError::ConstructionFailure);
+        }
+        let capsule = take::<Capsule>(&mut bytes)?;
+        if bytes.len() < 4 {
+            return Err(DeserializationError::NotEnoughBytes);
+        }
+        let mut count = [0u8; 4];
+        count.copy_from_slice(&bytes[..4]);
+        bytes = &bytes[4..];
+        let count = u32::from_be_bytes(count) as usize;
+
+        let precursor = if count > 0 {
+            Some(take::<CurvePoint>(&mut bytes)?)
+        } else {
+            None
+        };
+        // Not preallocating, since `count` is not checked yet.
+        let mut contributions = Vec::new();
+        for _ in 0..count {
+            contributions.push(Contribution {
+                kfrag_id: take(&mut bytes)?,
+                point_e1: take(&mut bytes)?,
+                point_v1: take(&mut bytes)?,
+            });
+        }
+        if !bytes.is_empty() {
+            return Err(DeserializationError::TooManyBytes);
+        }
+
+        Ok(Self {
+            capsule,
+            precursor,
+            contributions,
+        })
+    }
+}
+
+#[cfg(all(test, feature = "role-alice", feature = "role-ursula"))]
+mod tests {
+
+    use alloc::vec::Vec;
+
+    use super::PartialOpening;
+    use crate::{
+        decapsulate_reencrypted, encapsulate, generate_kfrags, reencrypt, OpenReencryptedError,
+        PublicKey, SecretKey, Signer,
+    };
+
+    #[test]
+    fn test_partial_opening() {
+        let delegating_sk = SecretKey::random();
+        let delegating_pk = PublicKey::from_secret_key(&delegating_sk);
+        let receiving_sk = SecretKey::random();
+        let receiving_pk = PublicKey::from_secret_key(&receiving_sk);
+        let signer = Signer::new(&SecretKey::random());
+
+        let (capsule, key_seed) = encapsulate(&delegating_pk);
+        let kfrags = generate_kfrags(&delegating_sk, &receiving_pk, &signer, 2, 3, true, true);
+        let vcfrags: Vec<_> = kfrags
+            .iter()
+            .map(|kfrag| reencrypt(&capsule, kfrag, None))
+            .collect();
+
+        let mut opening = PartialOpening::new(&capsule);
+        assert_eq!(
+            opening.finish(&receiving_sk, &delegating_pk),
+            Err(OpenReencryptedError::NoCapsuleFrags)
+        );
+
+        opening.add(&vcfrags[0]).unwrap();
+        assert_eq!(
+            opening.add(&vcfrags[0]),
+            Err(OpenReencryptedError::RepeatingCapsuleFrags)
+        );
+        assert_eq!(
+            opening.finish(&receiving_sk, &delegating_pk),
+            Err(OpenReencryptedError::NotEnoughCapsuleFrags)
+        );
+
+        // Persist the state and continue later.
+        let bytes = opening.to_bytes();
+        let mut opening = PartialOpening::from_bytes(&bytes).unwrap();
+        assert_eq!(opening.len(), 1);
+        opening.add(&vcfrags[2]).unwrap();
+        let key_seed_reenc = opening.finish(&receiving_sk, &delegating_pk).unwrap();
+        assert_eq!(key_seed_reenc, key_seed);
+        assert_eq!(
+            decapsulate_reencrypted(&receiving_sk, &delegating_pk, &capsule, &vcfrags[..2]),
+            Ok(key_seed)
+        );
+
+        assert!(PartialOpening::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+
+        let other_kfrags =
+            generate_kfrags(&delegating_sk, &receiving_pk, &signer, 2, 3, true, true);
+        assert_eq!(
+            opening.add(&reencrypt(&capsule, &other_kfrags[0], None)),
+            Err(OpenReencryptedError::MismatchedCapsuleFrags)
+        );
+    }
+}
@@ -0,0 +1,579 @@
+//! Identifiers and bundles of delegation policies.
+//!
+//! A [`Policy`] groups what the delegating party hands over to the network for a policy:
+//! the key fragments, the threshold, the keys they were created for,
+//! and optionally the assignments of the fragments to the proxies
+//! (encrypted for the receiving party). The bundle is signed by the delegating party.
+
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use core::fmt;
+
+use generic_array::GenericArray;
+#[cfg(feature = "dem")]
+use rand_core::{CryptoRng, OsRng, RngCore};
+use typenum::{Unsigned, U32};
+
+use crate::capsule::Capsule;
+#[cfg(feature = "dem")]
+use crate::dem::{DecryptionError, EncryptionError};
+use crate::hashing_ds::hash_to_policy_id;
+use crate::key_frag::{KeyFrag, KeyFragVerificationError, VerifiedKeyFrag};
+#[cfg(feature = "dem")]
+use crate::keys::SecretKey;
+use crate::keys::{PublicKey, Signature, Signer};
+#[cfg(feature = "dem")]
+use crate::pre::{decrypt_original, encrypt_with_rng};
+use crate::traits::{
+    DeserializableFromArray, DeserializationError, RepresentableAsArray, SerializableToArray,
+};
+
+const MAGIC: &[u8; 4] = b"UMBP";
+const FORMAT_VERSION: u8 = 2;
+
+type PolicyIdSize = U32;
+
+/// An identifier of a delegation policy:
+/// the delegating and the receiving keys, and an application-defined label
+/// (so that the same pair of parties can have several policies).
+///
+/// The identifier is the SHA-256 hash of the domain separation tag `POLICY_ID`,
+/// the compressed delegating and receiving keys, and the label,
+/// with the tag and the label prefixed by their lengths (as 32-bit big-endian integers).
+/// It is collision-resistant, and does not reveal the keys.
+///
+/// This is the identifier to pass wherever the library takes one
+/// (e.g. [`Metadata::with_policy_id()`](crate::Metadata::with_policy_id)
+/// and [`CapabilityUri::new()`](crate::CapabilityUri::new)),
+/// so that all the parts of an application reference policies the same way.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PolicyId(GenericArray<u8, PolicyIdSize>);
+
+impl PolicyId {
+    /// Derives the identifier of the policy delegating the decryption rights
+    /// of `delegating_pk` to `receiving_pk`.
+    pub fn from_keys(delegating_pk: &PublicKey, receiving_pk: &PublicKey, label: &[u8]) -> Self {
+        Self(hash_to_policy_id(delegating_pk, receiving_pk, label))
+    }
+}
+
+impl AsRef<[u8]> for PolicyId {
+    fn as_ref(&self) -> &[u8] {
+        self.0.as_ref()
+    }
+}
+
+impl RepresentableAsArray for PolicyId {
+    type Size = PolicyIdSize;
+}
+
+impl SerializableToArray for PolicyId {
+    fn to_array(&self) -> GenericArray<u8, Self::Size> {
+        self.0
+    }
+}
+
+impl DeserializableFromArray for PolicyId {
+    fn from_array(arr: &GenericArray<u8, Self::Size>) -> Result<Self, DeserializationError> {
+        Ok(Self(*arr))
+    }
+}
+
+/// Errors that can happen when creating, deserializing or opening a [`Policy`].
+#[derive(Debug, PartialEq)]
+pub enum PolicyError {
+    /// The policy is truncated or its fields cannot be parsed.
+    InvalidFormat,
+    /// The policy has a format version this library does not support.
+    UnsupportedVersion(u8),
+    /// The threshold is zero or greater than the number of key fragments
+    /// (or there are more than 65535 fragments).
+    InvalidThreshold,
+    /// The numbers of key fragments and assignments are different.
+    AssignmentCountMismatch,
+    /// The policy has no assignments.
+    NoAssignments,
+    /// The policy was not signed with its verifying key.
+    IncorrectSignature,
+    /// One of the key fragments failed verification.
+    KeyFragVerification(KeyFragVerificationError),
+    /// Failed to encrypt the assignments.
+    #[cfg(feature = "dem")]
+    Encryption(EncryptionError),
+    /// Failed to decrypt the assignments.
+    #[cfg(feature = "dem")]
+    Decryption(DecryptionError),
+}
+
+impl fmt::Display for PolicyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidFormat => write!(f, "invalid policy format"),
+            Self::UnsupportedVersion(version) => {
+                write!(f, "unsupported policy version {}", version)
+            }
+            Self::InvalidThreshold => write!(f, "invalid policy threshold"),
+            Self::AssignmentCountMismatch => {
+                write!(f, "the numbers of key fragments and assignments differ")
+            }
+            Self::NoAssignments => write!(f, "the policy has no assignments"),
+            Self::IncorrectSignature => write!(f, "incorrect policy signature"),
+            Self::KeyFragVerification(err) => {
+                write!(f, "key fragment verification failed: {}", err)
+            }
+            #[cfg(feature = "dem")]
+            Self::Encryption(err) => write!(f, "failed to encrypt the assignments: {}", err),
+            #[cfg(feature = "dem")]
+            Self::Decryption(err) => write!(f, "failed to decrypt the assignments: {}", err),
+        }
+    }
+}
+
+fn take<T: DeserializableFromArray>(rest: &mut &[u8]) -> Result<T, PolicyError> {
+    let size = <T as RepresentableAsArray>::Size::to_usize();
+    if rest.len() < size {
+        return Err(PolicyError::InvalidFormat);
+    }
+    let (head, tail) = rest.split_at(size);
+    *rest = tail;
+    T::from_bytes(head).or(Err(PolicyError::InvalidFormat))
+}
+
+fn take_bytes<'a>(rest: &mut &'a [u8], size: usize) -> Result<&'a [u8], PolicyError> {
+    if rest.len() < size {
+        return Err(PolicyError::InvalidFormat);
+    }
+    let (head, tail) = rest.split_at(size);
+    *rest = tail;
+    Ok(head)
+}
+
+/// The proxy assignments of a policy, encrypted for the receiving party.
+#[derive(Clone, Debug, PartialEq)]
+struct EncryptedAssignments {
+    capsule: Capsule,
+    ciphertext: Box<[u8]>,
+}
+
+// The signed contents of a policy.
+#[derive(Clone, Debug, PartialEq)]
+struct UnsignedPolicy {
+    id: PolicyId,
+    delegating_pk: PublicKey,
+    receiving_pk: PublicKey,
+    verifying_pk: PublicKey,
+    threshold: usize,
+    kfrags: Box<[VerifiedKeyFrag]>,
+    assignments: Option<EncryptedAssignments>,
+}
+
+impl UnsignedPolicy {
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(MAGIC);
+        bytes.push(FORMAT_VERSION);
+        bytes.extend_from_slice(&self.id.to_array());
+        bytes.extend_from_slice(&self.delegating_pk.to_array());
+        bytes.extend_from_slice(&self.receiving_pk.to_array());
+        bytes.extend_from_slice(&self.verifying_pk.to_array());
+        bytes.extend_from_slice(&(self.threshold as u16).to_be_bytes());
+        bytes.extend_from_slice(&(self.kfrags.len() as u16).to_be_bytes());
+        for kfrag in self.kfrags.iter() {
+            bytes.extend_from_slice(&kfrag.to_array());
+        }
+        match &self.assignments {
+            Some(assignments) => {
+                bytes.push(1);
+                bytes.extend_from_slice(&assignments.capsule.to_array());
+                bytes.extend_from_slice(&(assignments.ciphertext.len() as u32).to_be_bytes());
+                bytes.extend_from_slice(&assignments.ciphertext);
+            }
+            None => bytes.push(0),
+        }
+        bytes
+    }
+
+    fn sign(self, signature: impl FnOnce(&[u8]) -> Signature) -> Policy {
+        let signature = signature(&self.to_bytes());
+        Policy {
+            contents: self,
+            signature,
+        }
+    }
+}
+
+/// A delegation policy: the verified key fragments with the threshold
+/// and the keys they were created for, signed by the delegating party.
+///
+/// The serialized policy has the following layout:
+///
+/// | Field                                  | Size (bytes)          |
+/// | -------------------------------------- | --------------------- |
+/// | Magic (`UMBP`)                         | 4                     |
+/// | Format version (`2`)                   | 1                     |
+/// | Policy identifier                      | 32                    |
+/// | Delegating public key                  | 33                    |
+/// | Receiving public key                   | 33                    |
+/// | Verifying public key                   | 33                    |
+/// | Threshold                              | 2 (big-endian)        |
+/// | Number of key fragments `n`            | 2 (big-endian)        |
+/// | Key fragments: `n` times               | 276                   |
+/// | Assignments flag (`0` or `1`)          | 1                     |
+/// | Assignments capsule                    | 98 (if assignments)   |
+/// | Assignments ciphertext size            | 4 (if assignments)    |
+/// | Assignments ciphertext                 | (if assignments)      |
+/// | Signature of all the preceding bytes   | 64                    |
+#[derive(Clone, Debug, PartialEq)]
+pub struct Policy {
+    contents: UnsignedPolicy,
+    signature: Signature,
+}
+
+impl Policy {
+    /// Bundles the key fragments created by [`generate_kfrags()`](crate::generate_kfrags)
+    /// for the given keys, and signs the bundle with the same `signer`.
+    ///
+    /// The identifier of the policy is derived with [`PolicyId::from_keys()`].
+    /// Fragments with a [`ValidityWindow`](crate::ValidityWindow) are not supported,
+    /// since [`from_bytes()`](`Self::from_bytes`) verifies them without a time.
+    /// Panics if the signature is refused by the usage policy of the signer.
+    pub fn new(
+        signer: &Signer,
+        label: &[u8],
+        delegating_pk: &PublicKey,
+        receiving_pk: &PublicKey,
+        threshold: usize,
+        kfrags: &[VerifiedKeyFrag],
+    ) -> Result<Self, PolicyError> {
+        if threshold == 0 || threshold > kfrags.len() || kfrags.len() > u16::MAX as usize {
+            return Err(PolicyError::InvalidThreshold);
+        }
+        let contents = UnsignedPolicy {
+            id: PolicyId::from_keys(delegating_pk, receiving_pk, label),
+            delegating_pk: *delegating_pk,
+            receiving_pk: *receiving_pk,
+            verifying_pk: signer.verifying_key(),
+            threshold,
+            kfrags: kfrags.into(),
+            assignments: None,
+        };
+        Ok(contents.sign(|message| signer.sign(message)))
+    }
+
+    /// Adds the assignments of the key fragments to the proxies
+    /// (e.g. their addresses, in the order of the fragments),
+    /// encrypted for the receiving party, and signs the policy again.
+    ///
+    /// Panics if the signature is refused by the usage policy of the signer.
+    #[cfg(feature = "dem")]
+    pub fn with_assignments(
+        self,
+        signer: &Signer,
+        assignments: &[&[u8]],
+    ) -> Result<Self, PolicyError> {
+        self.with_assignments_with_rng(&mut OsRng, signer, assignments)
+    }
+
+    /// Same as [`with_assignments()`](`Self::with_assignments`),
+    /// but uses the given RNG instead of the default one.
+    #[cfg(feature = "dem")]
+    pub fn with_assignments_with_rng(
+        self,
+        rng: &mut (impl CryptoRng + RngCore),
+        signer: &Signer,
+        assignments: &[&[u8]],
+    ) -> Result<Self, PolicyError> {
+        let mut contents = self.contents;
+        if assignments.len() != contents.kfrags.len() {
+            return Err(PolicyError::AssignmentCountMismatch);
+        }
+        let mut plaintext = Vec::new();
+        for assignment in assignments.iter() {
+            plaintext.extend_from_slice(&(assignment.len() as u32).to_be_bytes());
+            plaintext.extend_from_slice(assignment);
+        }
+        let (capsule, ciphertext) = encrypt_with_rng(rng, &contents.receiving_pk, &plaintext)
+            .map_err(PolicyError::Encryption)?;
+        contents.assignments = Some(EncryptedAssignments {
+            capsule,
+            ciphertext,
+        });
+        Ok(contents.sign(|message| signer.sign_with_rng(rng, message)))
+    }
+
+    /// Returns the identifier of the policy.
+    pub fn id(&self) -> &PolicyId {
+        &self.contents.id
+    }
+
+    /// Returns the public key of the delegating party.
+    pub fn delegating_pk(&self) -> &PublicKey {
+        &self.contents.delegating_pk
+    }
+
+    /// Returns the public key of the receiving party.
+    pub fn receiving_pk(&self) -> &PublicKey {
+        &self.contents.receiving_pk
+    }
+
+    /// Returns the public key the policy and the key fragments are signed with.
+    pub fn verifying_pk(&self) -> &PublicKey {
+        &self.contents.verifying_pk
+    }
+
+    /// Returns the number of capsule fragments needed to open a capsule.
+    pub fn threshold(&self) -> usize {
+        self.contents.threshold
+    }
+
+    /// Returns the key fragments of the policy.
+    pub fn kfrags(&self) -> &[VerifiedKeyFrag] {
+        &self.contents.kfrags
+    }
+
+    /// Returns `true` if the policy carries the assignments of the fragments to the proxies.
+    pub fn has_assignments(&self) -> bool {
+        self.contents.assignments.is_some()
+    }
+
+    /// Decrypts the assignments of the key fragments to the proxies
+    /// (in the order of [`kfrags()`](`Self::kfrags`)) with the secret key of the receiving party.
+    #[cfg(feature = "dem")]
+    pub fn decrypt_assignments(
+        &self,
+        receiving_sk: &SecretKey,
+    ) -> Result<Box<[Box<[u8]>]>, PolicyError> {
+        let assignments = self
+            .contents
+            .assignments
+            .as_ref()
+            .ok_or(PolicyError::NoAssignments)?;
+        let plaintext =
+            decrypt_original(receiving_sk, &assignments.capsule, &assignments.ciphertext)
+                .map_err(PolicyError::Decryption)?;
+        self.parse_assignments(&plaintext)
+    }
+
+    #[cfg(feature = "dem")]
+    fn parse_assignments(&self, mut plaintext: &[u8]) -> Result<Box<[Box<[u8]>]>, PolicyError> {
+        let mut result = Vec::with_capacity(self.contents.kfrags.len());
+        while !plaintext.is_empty() {
+            let size = take_bytes(&mut plaintext, 4)?;
+            let size = u32::from_be_bytes([size[0], size[1], size[2], size[3]]) as usize;
+            result.push(take_bytes(&mut plaintext, size)?.into());
+        }
+        if result.len() != self.contents.kfrags.len() {
+            return Err(PolicyError::AssignmentCountMismatch);
+        }
+        Ok(result.into_boxed_slice())
+    }
+
+    /// Serializes the signed policy.
+    pub fn to_bytes(&self) -> Box<[u8]> {
+        let mut bytes = self.contents.to_bytes();
+        bytes.extend_from_slice(&self.signature.to_array());
+        bytes.into_boxed_slice()
+    }
+
+    /// Deserializes the policy, checking its signature and verifying the key fragments
+    /// with [`KeyFrag::verify()`] against the keys of the policy.
+    ///
+    /// The signature only proves that the policy was created by the owner of the verifying key;
+    /// the application must check that it is the expected one
+    /// (see [`verifying_pk()`](`Self::verifying_pk`)).
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, PolicyError> {
+        let signature_size = <Signature as RepresentableAsArray>::Size::to_usize();
+        if bytes.len() < MAGIC.len() + 1 + signature_size || &bytes[..MAGIC.len()] != MAGIC {
+            return Err(PolicyError::InvalidFormat);
+        }
+        let version = bytes[MAGIC.len()];
+        if version != FORMAT_VERSION {
+            return Err(PolicyError::UnsupportedVersion(version));
+        }
+
+        let (body, mut signature_bytes) = bytes.split_at(bytes.len() - signature_size);
+        let signature = take::<Signature>(&mut signature_bytes)?;
+
+        let mut rest = &body[MAGIC.len() + 1..];
+        let id = take::<PolicyId>(&mut rest)?;
+        let delegating_pk = take::<PublicKey>(&mut rest)?;
+        let receiving_pk = take::<PublicKey>(&mut rest)?;
+        let verifying_pk = take::<PublicKey>(&mut rest)?;
+
+        if !signature.verify(&verifying_pk, body) {
+            return Err(PolicyError::IncorrectSignature);
+        }
+
+        let threshold = take_bytes(&mut rest, 2)?;
+        let threshold = u16::from_be_bytes([threshold[0], threshold[1]]) as usize;
+        let num_kfrags = take_bytes(&mut rest, 2)?;
+        let num_kfrags = u16::from_be_bytes([num_kfrags[0], num_kfrags[1]]) as usize;
+        if threshold == 0 || threshold > num_kfrags {
+            return Err(PolicyError::InvalidThreshold);
+        }
+
+        let mut kfrags = Vec::with_capacity(num_kfrags);
+        for _ in 0..num_kfrags {
+            let kfrag = take::<KeyFrag>(&mut rest)?;
+            kfrags.push(
+                kfrag
+                    .verify(&verifying_pk, Some(&delegating_pk), Some(&receiving_pk))
+                    .map_err(PolicyError::KeyFragVerification)?,
+            );
+        }
+
+        let flag = take_bytes(&mut rest, 1)?;
+        let assignments = match flag[0] {
+            0 => None,
+            1 => {
+                let capsule = take::<Capsule>(&mut rest)?;
+                let size = take_bytes(&mut rest, 4)?;
+                let size = u32::from_be_bytes([size[0], size[1], size[2], size[3]]) as usize;
+                let ciphertext = take_bytes(&mut rest, size)?.into();
+                Some(EncryptedAssignments {
+                    capsule,
+                    ciphertext,
+                })
+            }
+            _ => return Err(PolicyError::InvalidFormat),
+        };
+        if !rest.is_empty() {
+            return Err(PolicyError::InvalidFormat);
+        }
+
+        Ok(Self {
+            contents: UnsignedPolicy {
+                id,
+                delegating_pk,
+                receiving_pk,
+                verifying_pk,
+                threshold,
+                kfrags: kfrags.into_boxed_slice(),
+                assignments,
+            },
+            signature,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::PolicyId;
+    #[cfg(feature = "role-alice")]
+    use super::{Policy, PolicyError};
+    #[cfg(feature = "role-alice")]
+    use crate::{generate_kfrags, KeyFrag, RepresentableAsArray, Signer};
+    use crate::{DeserializableFromArray, PublicKey, SecretKey, SerializableToArray};
+    #[cfg(feature = "role-alice")]
+    use typenum::Unsigned;
+
+    #[test]
+    fn test_from_keys() {
+        let delegating_pk = PublicKey::from_secret_key(&SecretKey::random());
+        let receiving_pk = PublicKey::from_secret_key(&SecretKey::random());
+
+        let policy_id = PolicyId::from_keys(&delegating_pk, &receiving_pk, b"label");
+        assert_eq!(
+            policy_id,
+            PolicyId::from_keys(&delegating_pk, &receiving_pk, b"label")
+        );
+
+        // Any change of the inputs results in a different identifier.
+        assert_ne!(
+            policy_id,
+            PolicyId::from_keys(&receiving_pk, &delegating_pk, b"label")
+        );
+        assert_ne!(
+            policy_id,
+            PolicyId::from_keys(&delegating_pk, &receiving_pk, b"label2")
+        );
+
+        let policy_id_back = PolicyId::from_array(&policy_id.to_array()).unwrap();
+        assert_eq!(policy_id, policy_id_back);
+    }
+
+    #[cfg(feature = "role-alice")]
+    #[test]
+    fn test_policy() {
+        let delegating_sk = SecretKey::random();
+        let delegating_pk = PublicKey::from_secret_key(&delegating_sk);
+        let receiving_sk = SecretKey::random();
+        let receiving_pk = PublicKey::from_secret_key(&receiving_sk);
+        let signer = Signer::new(&SecretKey::random());
+
+        let kfrags = generate_kfrags(&delegating_sk, &receiving_pk, &signer, 2, 3, true, true);
+        assert_eq!(
+            Policy::new(&signer, b"label", &delegating_pk, &receiving_pk, 4, &kfrags),
+            Err(PolicyError::InvalidThreshold)
+        );
+        let policy =
+            Policy::new(&signer, b"label", &delegating_pk, &receiving_pk, 2, &kfrags).unwrap();
+        assert_eq!(
+            policy.id(),
+            &PolicyId::from_keys(&delegating_pk, &receiving_pk, b"label")
+        );
+        assert!(!policy.has_assignments());
+
+        let bytes = policy.to_bytes();
+        // The layout in the module documentation.
+        let kfrag_size = <KeyFrag as RepresentableAsArray>::Size::to_usize();
+        assert_eq!(
+            bytes.len(),
+            4 + 1 + 32 + 33 * 3 + 2 + 2 + 3 * kfrag_size + 1 + 64
+        );
+        let policy_back = Policy::from_bytes(&bytes).unwrap();
+        assert_eq!(policy_back, policy);
+        assert_eq!(policy_back.threshold(), 2);
+        assert_eq!(policy_back.kfrags(), &kfrags[..]);
+
+        // Any modification breaks the signature.
+        let mut modified = bytes.to_vec();
+        modified[4 + 1 + 32 + 33 * 3 + 1] = 3;
+        assert_eq!(
+            Policy::from_bytes(&modified),
+            Err(PolicyError::IncorrectSignature)
+        );
+        assert_eq!(
+            Policy::from_bytes(&bytes[..bytes.len() - 1]),
+            Err(PolicyError::IncorrectSignature)
+        );
+    }
+
+    #[cfg(all(feature = "role-alice", feature = "dem"))]
+    #[test]
+    fn test_policy_assignments() {
+        let delegating_sk = SecretKey::random();
+        let delegating_pk = PublicKey::from_secret_key(&delegating_sk);
+        let receiving_sk = SecretKey::random();
+        let receiving_pk = PublicKey::from_secret_key(&receiving_sk);
+        let signer = Signer::new(&SecretKey::random());
+
+        let kfrags = generate_kfrags(&delegating_sk, &receiving_pk, &signer, 2, 3, true, true);
+        let policy =
+            Policy::new(&signer, b"label", &delegating_pk, &receiving_pk, 2, &kfrags).unwrap();
+
+        let assignments: [&[u8]; 3] = [b"ursula-1", b"ursula-2", b""];
+        assert_eq!(
+            policy
+                .clone()
+                .with_assignments(&signer, &assignments[..2])
+                .map(|_| ()),
+            Err(PolicyError::AssignmentCountMismatch)
+        );
+        assert_eq!(
+            policy.decrypt_assignments(&receiving_sk),
+            Err(PolicyError::NoAssignments)
+        );
+        let policy = policy.with_assignments(&signer, &assignments).unwrap();
+        let policy = Policy::from_bytes(&policy.to_bytes()).unwrap();
+        assert!(policy.has_assignments());
+
+        let decrypted = policy.decrypt_assignments(&receiving_sk).unwrap();
+        assert_eq!(decrypted.len(), 3);
+        for (decrypted, assignment) in decrypted.iter().zip(assignments.iter()) {
+            assert_eq!(&decrypted[..], *assignment);
+        }
+        assert!(policy.decrypt_assignments(&delegating_sk).is_err());
+    }
+}
@@ -1,16 +1,340 @@
 //! The high-level functional reencryption API.
 
-use crate::capsule::{Capsule, OpenReencryptedError};
+#[cfg(any(feature = "dem", feature = "role-bob"))]
+use crate::capsule::OpenReencryptedError;
+use crate::capsule::{Capsule, HintedCapsule};
+#[cfg(feature = "role-ursula")]
+use crate::capsule_frag::AuditableCapsuleFrag;
+#[cfg(feature = "role-bob")]
+use crate::capsule_frag::CapsuleFrag;
+#[cfg(any(feature = "role-ursula", feature = "role-bob"))]
 use crate::capsule_frag::VerifiedCapsuleFrag;
-use crate::dem::{DecryptionError, EncryptionError, DEM};
-use crate::key_frag::{KeyFragBase, VerifiedKeyFrag};
-use crate::keys::{PublicKey, SecretKey, Signer};
-use crate::traits::SerializableToArray;
+#[cfg(feature = "role-bob")]
+use crate::curve::CurvePoint;
+#[cfg(feature = "dem")]
+use crate::dem::{
+    decrypt_with_header, encrypt_with_header, plaintext_size_with_header, DecryptionError,
+    DemAlgorithm, EncryptionError,
+};
+use crate::hashing::{kdf_into, KDF_MAX_OUTPUT_SIZE};
+#[cfg(feature = "role-bob")]
+use crate::hashing_ds::hash_to_capsule_fingerprint;
+#[cfg(feature = "dem")]
+use crate::hashing_ds::signed_plaintext_message;
+use crate::hashing_ds::{hash_to_capsule_scalars, hash_to_key_check};
+#[cfg(any(feature = "role-alice", feature = "role-ursula"))]
+use crate::key_frag::VerifiedKeyFrag;
+#[cfg(feature = "role-alice")]
+use crate::key_frag::{
+    KeyFragBase, KeyFragGenerator, LabeledKeyFragBase, RefreshKey, ValidityWindow,
+};
+#[cfg(feature = "role-alice")]
+use crate::keys::expect_signed;
+#[cfg(feature = "dem")]
+use crate::keys::Signature;
+#[cfg(any(feature = "dem", feature = "role-alice", feature = "role-ursula"))]
+use crate::keys::Signer;
+use crate::keys::{PrecomputedPublicKey, PublicKey, SecretKey, SignerError};
+#[cfg(feature = "role-ursula")]
+use crate::metadata::{
+    canonicalize, encode_metadata, CanonicalizationError, Metadata, MetadataCanonicalizer,
+};
+#[cfg(feature = "role-ursula")]
+use crate::receipt::ReencryptionReceipt;
+#[cfg(feature = "role-alice")]
+use crate::reception::VerifiedReceptionKey;
+#[cfg(feature = "dem")]
+use crate::traits::DeserializableFromArray;
+use crate::traits::{RepresentableAsArray, SerializableToArray};
 
 use alloc::boxed::Box;
+use alloc::vec;
 use alloc::vec::Vec;
+#[cfg(feature = "role-bob")]
+use core::borrow::Borrow;
+use core::fmt;
+
+use generic_array::GenericArray;
+use rand_core::{CryptoRng, OsRng, RngCore};
+#[cfg(feature = "dem")]
+use typenum::Unsigned;
+use typenum::U8;
+use zeroize::Zeroize;
+#[cfg(any(feature = "dem", feature = "role-bob"))]
+use zeroize::Zeroizing;
+
+/// The key seed produced by the KEM, from which the DEM key is derived.
+pub type KeySeed = GenericArray<u8, <PublicKey as RepresentableAsArray>::Size>;
+
+/// Generates a random key seed and encapsulates it for later reencryption.
+/// Returns the KEM [`Capsule`] and the key seed.
+///
+/// Used in place of [`encrypt()`](`crate::encrypt`) when the DEM is implemented separately
+/// (e.g. in builds without the `dem` feature).
+pub fn encapsulate(pk: &PublicKey) -> (Capsule, KeySeed) {
+    encapsulate_with_rng(&mut OsRng, pk)
+}
+
+/// Same as [`encapsulate()`], but uses the given RNG instead of the default one.
+pub fn encapsulate_with_rng(
+    rng: &mut (impl CryptoRng + RngCore),
+    pk: &PublicKey,
+) -> (Capsule, KeySeed) {
+    let (capsule, key_seed) = Capsule::from_public_key(rng, pk);
+    (capsule, key_seed.to_array())
+}
+
+/// Same as [`encapsulate()`], but bundles the capsule with the fingerprint of `pk`
+/// (see [`HintedCapsule`]).
+pub fn encapsulate_with_key_hint(pk: &PublicKey) -> (HintedCapsule, KeySeed) {
+    encapsulate_with_key_hint_with_rng(&mut OsRng, pk)
+}
+
+/// Same as [`encapsulate_with_key_hint()`], but uses the given RNG instead of the default one.
+pub fn encapsulate_with_key_hint_with_rng(
+    rng: &mut (impl CryptoRng + RngCore),
+    pk: &PublicKey,
+) -> (HintedCapsule, KeySeed) {
+    let (hinted, key_seed) = Capsule::from_public_key_with_hint(rng, pk);
+    (hinted, key_seed.to_array())
+}
+
+/// Same as [`encapsulate()`], but uses the precomputed tables of the key,
+/// which is faster when many capsules are created for it.
+pub fn encapsulate_precomputed(pk: &PrecomputedPublicKey) -> (Capsule, KeySeed) {
+    encapsulate_precomputed_with_rng(&mut OsRng, pk)
+}
+
+/// Same as [`encapsulate_precomputed()`], but uses the given RNG instead of the default one.
+pub fn encapsulate_precomputed_with_rng(
+    rng: &mut (impl CryptoRng + RngCore),
+    pk: &PrecomputedPublicKey,
+) -> (Capsule, KeySeed) {
+    let (capsule, key_seed) = Capsule::from_precomputed_public_key(rng, pk);
+    (capsule, key_seed.to_array())
+}
+
+/// Same as [`encapsulate()`], but derives the ephemeral values of the capsule from `seed`,
+/// so that the capsule can later be checked with [`audit_capsule()`].
+///
+/// Intended for commit-reveal protocols: the seed must be unpredictable and never reused,
+/// and anyone it is revealed to can recover the key seed.
+pub fn encapsulate_with_seed(seed: &[u8], pk: &PublicKey) -> (Capsule, KeySeed) {
+    let (mut priv_r, mut priv_u) = hash_to_capsule_scalars(seed, pk);
+    let (capsule, key_seed) = Capsule::from_scalars(pk, &priv_r, &priv_u);
+    priv_r.zeroize();
+    priv_u.zeroize();
+    (capsule, key_seed.to_array())
+}
+
+/// Checks that `capsule` was created by [`encapsulate_with_seed()`]
+/// with the given seed and public key.
+pub fn audit_capsule(seed: &[u8], pk: &PublicKey, capsule: &Capsule) -> bool {
+    let (capsule_from_seed, _key_seed) = encapsulate_with_seed(seed, pk);
+    capsule_from_seed == *capsule
+}
+
+/// A short public value derived from the key seed,
+/// allowing one to check whether a secret key opens a capsule without attempting decryption.
+///
+/// The capsule itself carries no binding to the public key it was created for,
+/// so without it using a wrong key is only detected as a DEM authentication failure,
+/// indistinguishable from a corrupted ciphertext.
+pub type KeyCheck = GenericArray<u8, U8>;
+
+/// Derives the [`KeyCheck`] value from the key seed returned by [`encapsulate()`].
+pub fn key_check(key_seed: &KeySeed) -> KeyCheck {
+    hash_to_key_check(key_seed)
+}
+
+/// Checks if the secret key opens the capsule,
+/// given the [`KeyCheck`] value stored along with it.
+pub fn check_key_original(
+    decrypting_sk: &SecretKey,
+    capsule: &Capsule,
+    key_check: &KeyCheck,
+) -> bool {
+    hash_to_key_check(&decapsulate_original(decrypting_sk, capsule)) == *key_check
+}
+
+/// Errors that can happen when deriving keys with [`derive_key()`].
+#[derive(Debug, PartialEq)]
+pub enum DeriveKeyError {
+    /// The requested length is zero or exceeds the maximum of `255 * 32` bytes.
+    InvalidLength,
+}
+
+impl fmt::Display for DeriveKeyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidLength => write!(f, "invalid derived key length"),
+        }
+    }
+}
+
+/// Derives a symmetric key of the given length from the key seed
+/// (as returned by [`encapsulate()`] or the `decapsulate_*()` functions),
+/// using HKDF-SHA256 with a domain-separated `info`.
+///
+/// Keys derived with different `info` are independent of each other
+/// and of the key used by the built-in DEM,
+/// so this can be used to obtain several keys (e.g. separate encryption and MAC keys)
+/// from one capsule.
+pub fn derive_key(
+    key_seed: &KeySeed,
+    length: usize,
+    info: &[u8],
+) -> Result<Box<[u8]>, DeriveKeyError> {
+    if length == 0 || length > KDF_MAX_OUTPUT_SIZE {
+        return Err(DeriveKeyError::InvalidLength);
+    }
+    let full_info: Vec<u8> = b"DERIVE_KEY/".iter().chain(info.iter()).cloned().collect();
+    let mut key = vec![0u8; length].into_boxed_slice();
+    kdf_into(key_seed, None, &full_info, &mut key);
+    Ok(key)
+}
+
+/// Recovers the key seed from the capsule using the original encryptor's secret key.
+pub fn decapsulate_original(decrypting_sk: &SecretKey, capsule: &Capsule) -> KeySeed {
+    capsule.open_original(decrypting_sk).to_array()
+}
+
+/// Recovers the key seed from the capsule using previously reencrypted capsule fragments.
+///
+/// See [`decrypt_reencrypted()`](`crate::decrypt_reencrypted`) for the description
+/// of the parameters, and [`Capsule::combine()`] for the errors.
+#[cfg(feature = "role-bob")]
+pub fn decapsulate_reencrypted(
+    decrypting_sk: &SecretKey,
+    delegating_pk: &PublicKey,
+    capsule: &Capsule,
+    verified_cfrags: impl IntoIterator<Item = impl Borrow<VerifiedCapsuleFrag>>,
+) -> Result<KeySeed, OpenReencryptedError> {
+    capsule
+        .combine(decrypting_sk, delegating_pk, verified_cfrags)
+        .map(|combined| *combined.key_seed)
+}
+
+/// A capsule opened with the reencrypted capsule fragments,
+/// returned by [`Capsule::combine()`].
+///
+/// Keeps the recovered key seed (zeroized on drop),
+/// so the ciphertexts produced with the capsule can be decrypted
+/// without combining the fragments again.
+#[cfg(feature = "role-bob")]
+#[derive(Clone)] // Debug is implemented manually, to avoid exposing the key accidentally.
+pub struct CombinedCapsule {
+    capsule: Capsule,
+    key_seed: Zeroizing<KeySeed>,
+}
+
+#[cfg(feature = "role-bob")]
+impl Capsule {
+    /// Combines the reencrypted capsule fragments, recovering the key seed of the capsule.
+    ///
+    /// The fragments can be given in any order, and there can be more of them than the threshold.
+    /// Repeated fragments (created with the same key fragment) are only used once,
+    /// and if the fragments come from several sets of key fragments
+    /// (e.g. some proxies still hold the fragments of a previous policy),
+    /// only the largest set is used (the one appearing first, if there are several).
+    ///
+    /// The fragments are checked to be verified against this capsule
+    /// ([`OpenReencryptedError::ForeignCapsuleFrag`] reports the first one that is not),
+    /// so a failed combination means [`OpenReencryptedError::NotEnoughCapsuleFrags`].
+    pub fn combine(
+        &self,
+        decrypting_sk: &SecretKey,
+        delegating_pk: &PublicKey,
+        verified_cfrags: impl IntoIterator<Item = impl Borrow<VerifiedCapsuleFrag>>,
+    ) -> Result<CombinedCapsule, OpenReencryptedError> {
+        let capsule_fingerprint = hash_to_capsule_fingerprint(self);
+
+        let mut cfrags = Vec::<CapsuleFrag>::new();
+        for (position, vcfrag) in verified_cfrags.into_iter().enumerate() {
+            let vcfrag = vcfrag.borrow();
+            if vcfrag.capsule_fingerprint != capsule_fingerprint {
+                return Err(OpenReencryptedError::ForeignCapsuleFrag(position));
+            }
+            // A repeated fragment carries the same share, so the copies are skipped.
+            let cfrag = &vcfrag.cfrag;
+            if cfrags
+                .iter()
+                .all(|other| other.kfrag_id != cfrag.kfrag_id || other.precursor != cfrag.precursor)
+            {
+                cfrags.push(cfrag.clone());
+            }
+        }
+
+        // Each set of key fragments is identified by its precursor.
+        let mut largest_set: Option<CurvePoint> = None;
+        let mut largest_size = 0;
+        for cfrag in cfrags.iter() {
+            let size = cfrags
+                .iter()
+                .filter(|other| other.precursor == cfrag.precursor)
+                .count();
+            if size > largest_size {
+                largest_set = Some(cfrag.precursor);
+                largest_size = size;
+            }
+        }
+        if let Some(precursor) = largest_set {
+            cfrags.retain(|cfrag| cfrag.precursor == precursor);
+        }
+
+        let key_seed = self
+            .open_reencrypted(decrypting_sk, delegating_pk, &cfrags)
+            .map_err(|err| match err {
+                OpenReencryptedError::ValidationFailed => {
+                    OpenReencryptedError::NotEnoughCapsuleFrags
+                }
+                err => err,
+            })?;
+        Ok(CombinedCapsule {
+            capsule: *self,
+            key_seed: Zeroizing::new(key_seed.to_array()),
+        })
+    }
+}
+
+#[cfg(feature = "role-bob")]
+impl CombinedCapsule {
+    /// Returns the capsule.
+    pub fn capsule(&self) -> &Capsule {
+        &self.capsule
+    }
+
+    /// Returns the recovered key seed
+    /// (same as [`decapsulate_reencrypted()`] would).
+    pub fn key_seed(&self) -> &KeySeed {
+        &self.key_seed
+    }
+
+    /// Decrypts a ciphertext produced with the capsule
+    /// (same as [`decrypt_reencrypted()`], without combining the fragments).
+    #[cfg(feature = "dem")]
+    pub fn decrypt(&self, ciphertext: impl AsRef<[u8]>) -> Result<Box<[u8]>, DecryptionError> {
+        decrypt_with_header(
+            &self.key_seed,
+            ciphertext.as_ref(),
+            &self.capsule.to_array(),
+            None,
+        )
+    }
+}
+
+#[cfg(feature = "role-bob")]
+impl fmt::Debug for CombinedCapsule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CombinedCapsule")
+            .field("capsule", &self.capsule)
+            .field("key_seed", &"<redacted>")
+            .finish()
+    }
+}
 
 /// Errors that can happen when decrypting a reencrypted ciphertext.
+#[cfg(feature = "dem")]
 #[derive(Debug, PartialEq)]
 pub enum ReencryptionError {
     /// An error when opening a capsule. See [`OpenReencryptedError`] for the options.
@@ -19,27 +343,275 @@ pub enum ReencryptionError {
     OnDecryption(DecryptionError),
 }
 
+#[cfg(feature = "dem")]
+impl fmt::Display for ReencryptionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::OnOpen(err) => write!(f, "failed to open the capsule: {}", err),
+            Self::OnDecryption(err) => write!(f, "failed to decrypt the ciphertext: {}", err),
+        }
+    }
+}
+
 /// Encrypts the given plaintext message using a DEM scheme,
 /// and encapsulates the key for later reencryption.
 /// Returns the KEM [`Capsule`] and the ciphertext.
+///
+/// The ciphertext starts with the identifier of the DEM algorithm
+/// ([`DemAlgorithm::DEFAULT`]), so that the decryption functions can select it.
+#[cfg(feature = "dem")]
 pub fn encrypt(pk: &PublicKey, plaintext: &[u8]) -> Result<(Capsule, Box<[u8]>), EncryptionError> {
-    let (capsule, key_seed) = Capsule::from_public_key(pk);
-    let dem = DEM::new(&key_seed.to_array());
-    let capsule_bytes = capsule.to_array();
-    dem.encrypt(plaintext, &capsule_bytes)
+    encrypt_with_rng(&mut OsRng, pk, plaintext)
+}
+
+/// Same as [`encrypt()`], but uses the given RNG instead of the default one.
+#[cfg(feature = "dem")]
+pub fn encrypt_with_rng(
+    rng: &mut (impl CryptoRng + RngCore),
+    pk: &PublicKey,
+    plaintext: &[u8],
+) -> Result<(Capsule, Box<[u8]>), EncryptionError> {
+    encrypt_with_algorithm_with_rng(rng, DemAlgorithm::DEFAULT, pk, plaintext)
+}
+
+/// Same as [`encrypt()`], but uses the given DEM algorithm.
+#[cfg(feature = "dem")]
+pub fn encrypt_with_algorithm(
+    algorithm: DemAlgorithm,
+    pk: &PublicKey,
+    plaintext: &[u8],
+) -> Result<(Capsule, Box<[u8]>), EncryptionError> {
+    encrypt_with_algorithm_with_rng(&mut OsRng, algorithm, pk, plaintext)
+}
+
+/// Same as [`encrypt_with_algorithm()`], but uses the given RNG instead of the default one.
+#[cfg(feature = "dem")]
+pub fn encrypt_with_algorithm_with_rng(
+    rng: &mut (impl CryptoRng + RngCore),
+    algorithm: DemAlgorithm,
+    pk: &PublicKey,
+    plaintext: &[u8],
+) -> Result<(Capsule, Box<[u8]>), EncryptionError> {
+    let (capsule, key_seed) = encapsulate_with_rng(rng, pk);
+    encrypt_with_header(algorithm, rng, &key_seed, plaintext, &capsule.to_array())
         .map(|ciphertext| (capsule, ciphertext))
 }
 
+/// Same as [`encrypt()`], but bundles the capsule with the fingerprint of `pk`
+/// (see [`HintedCapsule`]).
+#[cfg(feature = "dem")]
+pub fn encrypt_with_key_hint(
+    pk: &PublicKey,
+    plaintext: &[u8],
+) -> Result<(HintedCapsule, Box<[u8]>), EncryptionError> {
+    encrypt_with_key_hint_with_rng(&mut OsRng, pk, plaintext)
+}
+
+/// Same as [`encrypt_with_key_hint()`], but uses the given RNG instead of the default one.
+#[cfg(feature = "dem")]
+pub fn encrypt_with_key_hint_with_rng(
+    rng: &mut (impl CryptoRng + RngCore),
+    pk: &PublicKey,
+    plaintext: &[u8],
+) -> Result<(HintedCapsule, Box<[u8]>), EncryptionError> {
+    let (hinted, key_seed) = encapsulate_with_key_hint_with_rng(rng, pk);
+    encrypt_with_header(
+        DemAlgorithm::DEFAULT,
+        rng,
+        &key_seed,
+        plaintext,
+        &hinted.capsule().to_array(),
+    )
+    .map(|ciphertext| (hinted, ciphertext))
+}
+
+/// Same as [`encrypt()`], but uses the precomputed tables of the key,
+/// which is faster when many capsules are created for it.
+#[cfg(feature = "dem")]
+pub fn encrypt_precomputed(
+    pk: &PrecomputedPublicKey,
+    plaintext: &[u8],
+) -> Result<(Capsule, Box<[u8]>), EncryptionError> {
+    encrypt_precomputed_with_rng(&mut OsRng, pk, plaintext)
+}
+
+/// Same as [`encrypt_precomputed()`], but uses the given RNG instead of the default one.
+#[cfg(feature = "dem")]
+pub fn encrypt_precomputed_with_rng(
+    rng: &mut (impl CryptoRng + RngCore),
+    pk: &PrecomputedPublicKey,
+    plaintext: &[u8],
+) -> Result<(Capsule, Box<[u8]>), EncryptionError> {
+    let (capsule, key_seed) = encapsulate_precomputed_with_rng(rng, pk);
+    encrypt_with_header(
+        DemAlgorithm::DEFAULT,
+        rng,
+        &key_seed,
+        plaintext,
+        &capsule.to_array(),
+    )
+    .map(|ciphertext| (capsule, ciphertext))
+}
+
 /// Attempts to decrypt the ciphertext using the original encryptor's
 /// secret key.
+#[cfg(feature = "dem")]
 pub fn decrypt_original(
     decrypting_sk: &SecretKey,
     capsule: &Capsule,
     ciphertext: impl AsRef<[u8]>,
 ) -> Result<Box<[u8]>, DecryptionError> {
-    let key_seed = capsule.open_original(decrypting_sk);
-    let dem = DEM::new(&key_seed.to_array());
-    dem.decrypt(ciphertext, &capsule.to_array())
+    let key_seed = decapsulate_original(decrypting_sk, capsule);
+    decrypt_with_header(&key_seed, ciphertext.as_ref(), &capsule.to_array(), None)
+}
+
+/// Same as [`encrypt()`], but also returns the [`KeyCheck`] value for the capsule,
+/// which can be stored along with it and passed to
+/// [`decrypt_original_with_key_check()`].
+#[cfg(feature = "dem")]
+pub fn encrypt_with_key_check(
+    pk: &PublicKey,
+    plaintext: &[u8],
+) -> Result<(Capsule, Box<[u8]>, KeyCheck), EncryptionError> {
+    encrypt_with_key_check_with_rng(&mut OsRng, pk, plaintext)
+}
+
+/// Same as [`encrypt_with_key_check()`], but uses the given RNG instead of the default one.
+#[cfg(feature = "dem")]
+pub fn encrypt_with_key_check_with_rng(
+    rng: &mut (impl CryptoRng + RngCore),
+    pk: &PublicKey,
+    plaintext: &[u8],
+) -> Result<(Capsule, Box<[u8]>, KeyCheck), EncryptionError> {
+    let (capsule, key_seed) = encapsulate_with_rng(rng, pk);
+    encrypt_with_header(
+        DemAlgorithm::DEFAULT,
+        rng,
+        &key_seed,
+        plaintext,
+        &capsule.to_array(),
+    )
+    .map(|ciphertext| (capsule, ciphertext, key_check(&key_seed)))
+}
+
+/// Same as [`decrypt_original()`], but first checks the secret key against the given
+/// [`KeyCheck`] value, returning [`DecryptionError::WrongKey`] if it does not match.
+/// In that case [`DecryptionError::AuthenticationFailed`] means that the ciphertext
+/// was modified.
+#[cfg(feature = "dem")]
+pub fn decrypt_original_with_key_check(
+    decrypting_sk: &SecretKey,
+    capsule: &Capsule,
+    key_check: &KeyCheck,
+    ciphertext: impl AsRef<[u8]>,
+) -> Result<Box<[u8]>, DecryptionError> {
+    let key_seed = decapsulate_original(decrypting_sk, capsule);
+    if hash_to_key_check(&key_seed) != *key_check {
+        return Err(DecryptionError::WrongKey);
+    }
+    decrypt_with_header(&key_seed, ciphertext.as_ref(), &capsule.to_array(), None)
+}
+
+/// Same as [`decrypt_original()`], but refuses to decrypt ciphertexts
+/// containing more than `max_plaintext_size` bytes of plaintext.
+///
+/// The check is performed before any allocation or authentication,
+/// so it is safe to use on ciphertexts of untrusted length.
+#[cfg(feature = "dem")]
+pub fn decrypt_original_limited(
+    decrypting_sk: &SecretKey,
+    capsule: &Capsule,
+    ciphertext: impl AsRef<[u8]>,
+    max_plaintext_size: usize,
+) -> Result<Box<[u8]>, DecryptionError> {
+    let key_seed = decapsulate_original(decrypting_sk, capsule);
+    decrypt_with_header(
+        &key_seed,
+        ciphertext.as_ref(),
+        &capsule.to_array(),
+        Some(max_plaintext_size),
+    )
+}
+
+/// Encrypts the given plaintext message like [`encrypt()`],
+/// including a signature of it (bound to the capsule) made with `signer`
+/// in the authenticated payload.
+///
+/// The receiving party checks the origin of the plaintext by decrypting it with
+/// [`decrypt_original_verified()`] or [`decrypt_reencrypted_verified()`]
+/// and the verifying key of the signer.
+/// The other decryption functions return the signature prepended to the plaintext.
+///
+/// Panics if the signature is refused by the usage policy of the signer.
+#[cfg(feature = "dem")]
+pub fn encrypt_signed(
+    pk: &PublicKey,
+    signer: &Signer,
+    plaintext: &[u8],
+) -> Result<(Capsule, Box<[u8]>), EncryptionError> {
+    encrypt_signed_with_rng(&mut OsRng, pk, signer, plaintext)
+}
+
+/// Same as [`encrypt_signed()`], but uses the given RNG instead of the default one.
+#[cfg(feature = "dem")]
+pub fn encrypt_signed_with_rng(
+    rng: &mut (impl CryptoRng + RngCore),
+    pk: &PublicKey,
+    signer: &Signer,
+    plaintext: &[u8],
+) -> Result<(Capsule, Box<[u8]>), EncryptionError> {
+    let (capsule, key_seed) = encapsulate_with_rng(rng, pk);
+    let signature = signer.sign_with_rng(rng, &signed_plaintext_message(&capsule, plaintext));
+    let mut payload = Zeroizing::new(Vec::with_capacity(
+        SignatureSize::to_usize() + plaintext.len(),
+    ));
+    payload.extend_from_slice(&signature.to_array());
+    payload.extend_from_slice(plaintext);
+    encrypt_with_header(
+        DemAlgorithm::DEFAULT,
+        rng,
+        &key_seed,
+        &payload,
+        &capsule.to_array(),
+    )
+    .map(|ciphertext| (capsule, ciphertext))
+}
+
+#[cfg(feature = "dem")]
+type SignatureSize = <Signature as RepresentableAsArray>::Size;
+
+/// Splits the decrypted payload of [`encrypt_signed()`] and checks the signature.
+#[cfg(feature = "dem")]
+fn open_signed_payload(
+    capsule: &Capsule,
+    payload: &[u8],
+    verifying_pk: &PublicKey,
+) -> Result<Box<[u8]>, DecryptionError> {
+    let signature_size = SignatureSize::to_usize();
+    if payload.len() < signature_size {
+        return Err(DecryptionError::IncorrectSignature);
+    }
+    let (signature_bytes, plaintext) = payload.split_at(signature_size);
+    let signature =
+        Signature::from_bytes(signature_bytes).map_err(|_| DecryptionError::IncorrectSignature)?;
+    if !signature.verify(verifying_pk, &signed_plaintext_message(capsule, plaintext)) {
+        return Err(DecryptionError::IncorrectSignature);
+    }
+    Ok(plaintext.into())
+}
+
+/// Same as [`decrypt_original()`] for a ciphertext created by [`encrypt_signed()`],
+/// but also checks that the plaintext was signed with the given verifying key,
+/// returning [`DecryptionError::IncorrectSignature`] otherwise.
+#[cfg(feature = "dem")]
+pub fn decrypt_original_verified(
+    decrypting_sk: &SecretKey,
+    capsule: &Capsule,
+    ciphertext: impl AsRef<[u8]>,
+    verifying_pk: &PublicKey,
+) -> Result<Box<[u8]>, DecryptionError> {
+    let payload = Zeroizing::new(decrypt_original(decrypting_sk, capsule, ciphertext)?.into_vec());
+    open_signed_payload(capsule, &payload, verifying_pk)
 }
 
 /// Creates `num_kfrags` fragments of `delegating_sk`,
@@ -58,7 +630,12 @@ pub fn decrypt_original(
 /// by supplying them to [`KeyFrag::verify()`](`crate::KeyFrag::verify`).
 ///
 /// Returns a boxed slice of `num_kfrags` KeyFrags
+///
+/// With the `parallel` feature enabled, the fragments are created in parallel.
+///
+/// Panics if the signer fails (see [`try_generate_kfrags()`]).
 #[allow(clippy::too_many_arguments)]
+#[cfg(feature = "role-alice")]
 pub fn generate_kfrags(
     delegating_sk: &SecretKey,
     receiving_pk: &PublicKey,
@@ -68,34 +645,870 @@ pub fn generate_kfrags(
     sign_delegating_key: bool,
     sign_receiving_key: bool,
 ) -> Box<[VerifiedKeyFrag]> {
-    let base = KeyFragBase::new(delegating_sk, receiving_pk, signer, threshold);
+    expect_signed(try_generate_kfrags(
+        delegating_sk,
+        receiving_pk,
+        signer,
+        threshold,
+        num_kfrags,
+        sign_delegating_key,
+        sign_receiving_key,
+    ))
+}
+
+/// Same as [`generate_kfrags()`], but uses the given RNG instead of the default one.
+/// The fragments are always created sequentially, since the RNG cannot be shared between threads.
+///
+/// Panics if the signer fails (see [`try_generate_kfrags_with_rng()`]).
+#[allow(clippy::too_many_arguments)]
+#[cfg(feature = "role-alice")]
+pub fn generate_kfrags_with_rng(
+    rng: &mut (impl CryptoRng + RngCore),
+    delegating_sk: &SecretKey,
+    receiving_pk: &PublicKey,
+    signer: &Signer,
+    threshold: usize,
+    num_kfrags: usize,
+    sign_delegating_key: bool,
+    sign_receiving_key: bool,
+) -> Box<[VerifiedKeyFrag]> {
+    expect_signed(try_generate_kfrags_with_rng(
+        rng,
+        delegating_sk,
+        receiving_pk,
+        signer,
+        threshold,
+        num_kfrags,
+        sign_delegating_key,
+        sign_receiving_key,
+    ))
+}
+
+/// Same as [`generate_kfrags()`], but returns an error if the signer fails,
+/// that is, if a signature is refused by its usage policy,
+/// or if its external signing backend returns an error.
+#[allow(clippy::too_many_arguments)]
+#[cfg(feature = "role-alice")]
+pub fn try_generate_kfrags(
+    delegating_sk: &SecretKey,
+    receiving_pk: &PublicKey,
+    signer: &Signer,
+    threshold: usize,
+    num_kfrags: usize,
+    sign_delegating_key: bool,
+    sign_receiving_key: bool,
+) -> Result<Box<[VerifiedKeyFrag]>, SignerError> {
+    #[cfg(feature = "parallel")]
+    {
+        let base = KeyFragBase::new(&mut OsRng, delegating_sk, receiving_pk, signer, threshold);
+        VerifiedKeyFrag::from_base_parallel(
+            &base,
+            num_kfrags,
+            sign_delegating_key,
+            sign_receiving_key,
+        )
+    }
+
+    #[cfg(not(feature = "parallel"))]
+    try_generate_kfrags_with_rng(
+        &mut OsRng,
+        delegating_sk,
+        receiving_pk,
+        signer,
+        threshold,
+        num_kfrags,
+        sign_delegating_key,
+        sign_receiving_key,
+    )
+}
+
+/// Same as [`try_generate_kfrags()`], but uses the given RNG instead of the default one.
+/// The fragments are always created sequentially, since the RNG cannot be shared between threads.
+#[allow(clippy::too_many_arguments)]
+#[cfg(feature = "role-alice")]
+pub fn try_generate_kfrags_with_rng(
+    rng: &mut (impl CryptoRng + RngCore),
+    delegating_sk: &SecretKey,
+    receiving_pk: &PublicKey,
+    signer: &Signer,
+    threshold: usize,
+    num_kfrags: usize,
+    sign_delegating_key: bool,
+    sign_receiving_key: bool,
+) -> Result<Box<[VerifiedKeyFrag]>, SignerError> {
+    let base = KeyFragBase::new(rng, delegating_sk, receiving_pk, signer, threshold);
 
     let mut result = Vec::<VerifiedKeyFrag>::new();
     for _ in 0..num_kfrags {
         result.push(VerifiedKeyFrag::from_base(
+            rng,
             &base,
             sign_delegating_key,
             sign_receiving_key,
-        ));
+        )?);
+    }
+
+    Ok(result.into_boxed_slice())
+}
+
+/// Same as [`generate_kfrags()`], but creates the fragments lazily, one per iteration,
+/// so that they can be distributed as they are created
+/// without keeping all of them in memory.
+#[cfg(feature = "role-alice")]
+pub fn generate_kfrags_iter(
+    delegating_sk: &SecretKey,
+    receiving_pk: &PublicKey,
+    signer: &Signer,
+    threshold: usize,
+    num_kfrags: usize,
+    sign_delegating_key: bool,
+    sign_receiving_key: bool,
+) -> KeyFragGenerator<OsRng> {
+    let base = KeyFragBase::new(&mut OsRng, delegating_sk, receiving_pk, signer, threshold);
+    KeyFragGenerator::new(
+        OsRng,
+        base,
+        num_kfrags,
+        sign_delegating_key,
+        sign_receiving_key,
+    )
+}
+
+/// Same as [`generate_kfrags_iter()`], but uses the given RNG instead of the default one.
+#[allow(clippy::too_many_arguments)]
+#[cfg(feature = "role-alice")]
+pub fn generate_kfrags_iter_with_rng<'a, R: CryptoRng + RngCore>(
+    rng: &'a mut R,
+    delegating_sk: &SecretKey,
+    receiving_pk: &PublicKey,
+    signer: &Signer,
+    threshold: usize,
+    num_kfrags: usize,
+    sign_delegating_key: bool,
+    sign_receiving_key: bool,
+) -> KeyFragGenerator<&'a mut R> {
+    let base = KeyFragBase::new(rng, delegating_sk, receiving_pk, signer, threshold);
+    KeyFragGenerator::new(
+        rng,
+        base,
+        num_kfrags,
+        sign_delegating_key,
+        sign_receiving_key,
+    )
+}
+
+/// Same as [`generate_kfrags()`], but the fragments can only be used within `validity`.
+///
+/// The window is covered by the signature of the fragments, and is checked by
+/// [`KeyFrag::verify_at()`](`crate::KeyFrag::verify_at`) against the time given by the proxy;
+/// [`KeyFrag::verify()`](`crate::KeyFrag::verify`) rejects such fragments.
+///
+/// Panics if the signer fails (see [`try_generate_kfrags_with_validity()`]).
+#[allow(clippy::too_many_arguments)]
+#[cfg(feature = "role-alice")]
+pub fn generate_kfrags_with_validity(
+    delegating_sk: &SecretKey,
+    receiving_pk: &PublicKey,
+    signer: &Signer,
+    threshold: usize,
+    num_kfrags: usize,
+    sign_delegating_key: bool,
+    sign_receiving_key: bool,
+    validity: ValidityWindow,
+) -> Box<[VerifiedKeyFrag]> {
+    expect_signed(try_generate_kfrags_with_validity(
+        delegating_sk,
+        receiving_pk,
+        signer,
+        threshold,
+        num_kfrags,
+        sign_delegating_key,
+        sign_receiving_key,
+        validity,
+    ))
+}
+
+/// Same as [`generate_kfrags_with_validity()`], but uses the given RNG instead of the default one.
+///
+/// Panics if the signer fails (see [`try_generate_kfrags_with_validity_with_rng()`]).
+#[allow(clippy::too_many_arguments)]
+#[cfg(feature = "role-alice")]
+pub fn generate_kfrags_with_validity_with_rng(
+    rng: &mut (impl CryptoRng + RngCore),
+    delegating_sk: &SecretKey,
+    receiving_pk: &PublicKey,
+    signer: &Signer,
+    threshold: usize,
+    num_kfrags: usize,
+    sign_delegating_key: bool,
+    sign_receiving_key: bool,
+    validity: ValidityWindow,
+) -> Box<[VerifiedKeyFrag]> {
+    expect_signed(try_generate_kfrags_with_validity_with_rng(
+        rng,
+        delegating_sk,
+        receiving_pk,
+        signer,
+        threshold,
+        num_kfrags,
+        sign_delegating_key,
+        sign_receiving_key,
+        validity,
+    ))
+}
+
+/// Same as [`generate_kfrags_with_validity()`], but returns an error if the signer fails.
+#[allow(clippy::too_many_arguments)]
+#[cfg(feature = "role-alice")]
+pub fn try_generate_kfrags_with_validity(
+    delegating_sk: &SecretKey,
+    receiving_pk: &PublicKey,
+    signer: &Signer,
+    threshold: usize,
+    num_kfrags: usize,
+    sign_delegating_key: bool,
+    sign_receiving_key: bool,
+    validity: ValidityWindow,
+) -> Result<Box<[VerifiedKeyFrag]>, SignerError> {
+    try_generate_kfrags_with_validity_with_rng(
+        &mut OsRng,
+        delegating_sk,
+        receiving_pk,
+        signer,
+        threshold,
+        num_kfrags,
+        sign_delegating_key,
+        sign_receiving_key,
+        validity,
+    )
+}
+
+/// Same as [`try_generate_kfrags_with_validity()`], but uses the given RNG instead of the default one.
+#[allow(clippy::too_many_arguments)]
+#[cfg(feature = "role-alice")]
+pub fn try_generate_kfrags_with_validity_with_rng(
+    rng: &mut (impl CryptoRng + RngCore),
+    delegating_sk: &SecretKey,
+    receiving_pk: &PublicKey,
+    signer: &Signer,
+    threshold: usize,
+    num_kfrags: usize,
+    sign_delegating_key: bool,
+    sign_receiving_key: bool,
+    validity: ValidityWindow,
+) -> Result<Box<[VerifiedKeyFrag]>, SignerError> {
+    let base = KeyFragBase::new(rng, delegating_sk, receiving_pk, signer, threshold)
+        .with_validity(validity);
+
+    (0..num_kfrags)
+        .map(|_| VerifiedKeyFrag::from_base(rng, &base, sign_delegating_key, sign_receiving_key))
+        .collect::<Result<Vec<_>, _>>()
+        .map(Vec::into_boxed_slice)
+}
+
+/// Same as [`generate_kfrags()`], but the fragments are derived deterministically
+/// from `delegating_sk`, `receiving_pk`, `threshold` and `label` instead of the RNG.
+///
+/// The identifier of the `i`-th fragment is derived from `label` and `i`
+/// (and the delegating and receiving keys), so a proxy can recognize
+/// a re-submitted fragment by its [`id()`](`crate::KeyFrag::id`).
+/// Calling the function again with the same arguments produces the identical set,
+/// so the delegating party can restore the fragments after losing its local state.
+/// The signatures are only reproducible if `signer` is local
+/// (see [`Signer::from_external()`](`crate::Signer::from_external`)).
+///
+/// Each policy must have its own label: the same label with the same keys
+/// always results in the same re-encryption key shares.
+///
+/// Panics if the signer fails (see [`try_generate_kfrags_with_label()`]).
+#[allow(clippy::too_many_arguments)]
+#[cfg(feature = "role-alice")]
+pub fn generate_kfrags_with_label(
+    delegating_sk: &SecretKey,
+    receiving_pk: &PublicKey,
+    signer: &Signer,
+    threshold: usize,
+    num_kfrags: usize,
+    sign_delegating_key: bool,
+    sign_receiving_key: bool,
+    label: &[u8],
+) -> Box<[VerifiedKeyFrag]> {
+    expect_signed(try_generate_kfrags_with_label(
+        delegating_sk,
+        receiving_pk,
+        signer,
+        threshold,
+        num_kfrags,
+        sign_delegating_key,
+        sign_receiving_key,
+        label,
+    ))
+}
+
+/// Same as [`generate_kfrags_with_label()`], but returns an error if the signer fails.
+#[allow(clippy::too_many_arguments)]
+#[cfg(feature = "role-alice")]
+pub fn try_generate_kfrags_with_label(
+    delegating_sk: &SecretKey,
+    receiving_pk: &PublicKey,
+    signer: &Signer,
+    threshold: usize,
+    num_kfrags: usize,
+    sign_delegating_key: bool,
+    sign_receiving_key: bool,
+    label: &[u8],
+) -> Result<Box<[VerifiedKeyFrag]>, SignerError> {
+    let base = LabeledKeyFragBase::new(delegating_sk, receiving_pk, signer, threshold, label);
+    (0..num_kfrags)
+        .map(|index| base.kfrag(index, sign_delegating_key, sign_receiving_key))
+        .collect::<Result<Vec<_>, _>>()
+        .map(Vec::into_boxed_slice)
+}
+
+/// Recreates the fragment number `index` (counting from 0) of the set
+/// returned by [`generate_kfrags_with_label()`] for the same arguments,
+/// without creating the other ones
+/// (e.g. to replace a fragment lost by a proxy without redistributing the whole set).
+///
+/// `label` acts as the seed of the secret polynomial,
+/// so it must be the same as the one the set was created with.
+/// `num_kfrags` does not affect the fragment; it is only used to check the index.
+/// Returns `None` if `index` is not less than `num_kfrags`.
+///
+/// Panics if the signer fails (see [`try_generate_single_kfrag()`]).
+#[allow(clippy::too_many_arguments)]
+#[cfg(feature = "role-alice")]
+pub fn generate_single_kfrag(
+    delegating_sk: &SecretKey,
+    receiving_pk: &PublicKey,
+    signer: &Signer,
+    threshold: usize,
+    num_kfrags: usize,
+    index: usize,
+    sign_delegating_key: bool,
+    sign_receiving_key: bool,
+    label: &[u8],
+) -> Option<VerifiedKeyFrag> {
+    try_generate_single_kfrag(
+        delegating_sk,
+        receiving_pk,
+        signer,
+        threshold,
+        num_kfrags,
+        index,
+        sign_delegating_key,
+        sign_receiving_key,
+        label,
+    )
+    .map(expect_signed)
+}
+
+/// Same as [`generate_single_kfrag()`], but returns an error if the signer fails.
+#[allow(clippy::too_many_arguments)]
+#[cfg(feature = "role-alice")]
+pub fn try_generate_single_kfrag(
+    delegating_sk: &SecretKey,
+    receiving_pk: &PublicKey,
+    signer: &Signer,
+    threshold: usize,
+    num_kfrags: usize,
+    index: usize,
+    sign_delegating_key: bool,
+    sign_receiving_key: bool,
+    label: &[u8],
+) -> Option<Result<VerifiedKeyFrag, SignerError>> {
+    if index >= num_kfrags {
+        return None;
+    }
+    let base = LabeledKeyFragBase::new(delegating_sk, receiving_pk, signer, threshold, label);
+    Some(base.kfrag(index, sign_delegating_key, sign_receiving_key))
+}
+
+/// Same as [`generate_kfrags()`], but for a single-use reception key
+/// pre-published by the receiving party (see [`ReceptionKey`](crate::ReceptionKey)),
+/// which is consumed.
+///
+/// The fragments can only be used with the secret counterpart of the reception key,
+/// and its public key takes the place of the receiving key in their verification.
+/// The precursor of the fragments is still generated by the delegating party,
+/// which needs its secret to compute the shared secret with the reception key,
+/// so the fragments are created and verified as usual otherwise.
+#[allow(clippy::too_many_arguments)]
+#[cfg(feature = "role-alice")]
+pub fn generate_kfrags_for_reception_key(
+    delegating_sk: &SecretKey,
+    reception_key: VerifiedReceptionKey,
+    signer: &Signer,
+    threshold: usize,
+    num_kfrags: usize,
+    sign_delegating_key: bool,
+    sign_receiving_key: bool,
+) -> Box<[VerifiedKeyFrag]> {
+    generate_kfrags(
+        delegating_sk,
+        reception_key.public_key(),
+        signer,
+        threshold,
+        num_kfrags,
+        sign_delegating_key,
+        sign_receiving_key,
+    )
+}
+
+/// Same as [`generate_kfrags_for_reception_key()`],
+/// but uses the given RNG instead of the default one.
+#[allow(clippy::too_many_arguments)]
+#[cfg(feature = "role-alice")]
+pub fn generate_kfrags_for_reception_key_with_rng(
+    rng: &mut (impl CryptoRng + RngCore),
+    delegating_sk: &SecretKey,
+    reception_key: VerifiedReceptionKey,
+    signer: &Signer,
+    threshold: usize,
+    num_kfrags: usize,
+    sign_delegating_key: bool,
+    sign_receiving_key: bool,
+) -> Box<[VerifiedKeyFrag]> {
+    generate_kfrags_with_rng(
+        rng,
+        delegating_sk,
+        reception_key.public_key(),
+        signer,
+        threshold,
+        num_kfrags,
+        sign_delegating_key,
+        sign_receiving_key,
+    )
+}
+
+/// Same as [`generate_kfrags()`], but also returns the [`RefreshKey`]
+/// allowing one to refresh the fragments later with [`refresh_kfrags()`].
+///
+/// The refresh key must be kept secret by the delegator:
+/// together with `threshold` fragments it allows one to recover `delegating_sk`.
+///
+/// Panics if the signer fails (see [`try_generate_refreshable_kfrags()`]).
+#[allow(clippy::too_many_arguments)]
+#[cfg(feature = "role-alice")]
+pub fn generate_refreshable_kfrags(
+    delegating_sk: &SecretKey,
+    receiving_pk: &PublicKey,
+    signer: &Signer,
+    threshold: usize,
+    num_kfrags: usize,
+    sign_delegating_key: bool,
+    sign_receiving_key: bool,
+) -> (Box<[VerifiedKeyFrag]>, RefreshKey) {
+    expect_signed(try_generate_refreshable_kfrags(
+        delegating_sk,
+        receiving_pk,
+        signer,
+        threshold,
+        num_kfrags,
+        sign_delegating_key,
+        sign_receiving_key,
+    ))
+}
+
+/// Same as [`generate_refreshable_kfrags()`], but uses the given RNG instead of the default one.
+///
+/// Panics if the signer fails (see [`try_generate_refreshable_kfrags_with_rng()`]).
+#[allow(clippy::too_many_arguments)]
+#[cfg(feature = "role-alice")]
+pub fn generate_refreshable_kfrags_with_rng(
+    rng: &mut (impl CryptoRng + RngCore),
+    delegating_sk: &SecretKey,
+    receiving_pk: &PublicKey,
+    signer: &Signer,
+    threshold: usize,
+    num_kfrags: usize,
+    sign_delegating_key: bool,
+    sign_receiving_key: bool,
+) -> (Box<[VerifiedKeyFrag]>, RefreshKey) {
+    expect_signed(try_generate_refreshable_kfrags_with_rng(
+        rng,
+        delegating_sk,
+        receiving_pk,
+        signer,
+        threshold,
+        num_kfrags,
+        sign_delegating_key,
+        sign_receiving_key,
+    ))
+}
+
+/// Same as [`generate_refreshable_kfrags()`], but returns an error if the signer fails.
+#[allow(clippy::too_many_arguments)]
+#[cfg(feature = "role-alice")]
+pub fn try_generate_refreshable_kfrags(
+    delegating_sk: &SecretKey,
+    receiving_pk: &PublicKey,
+    signer: &Signer,
+    threshold: usize,
+    num_kfrags: usize,
+    sign_delegating_key: bool,
+    sign_receiving_key: bool,
+) -> Result<(Box<[VerifiedKeyFrag]>, RefreshKey), SignerError> {
+    try_generate_refreshable_kfrags_with_rng(
+        &mut OsRng,
+        delegating_sk,
+        receiving_pk,
+        signer,
+        threshold,
+        num_kfrags,
+        sign_delegating_key,
+        sign_receiving_key,
+    )
+}
+
+/// Same as [`try_generate_refreshable_kfrags()`], but uses the given RNG instead of the default one.
+#[allow(clippy::too_many_arguments)]
+#[cfg(feature = "role-alice")]
+pub fn try_generate_refreshable_kfrags_with_rng(
+    rng: &mut (impl CryptoRng + RngCore),
+    delegating_sk: &SecretKey,
+    receiving_pk: &PublicKey,
+    signer: &Signer,
+    threshold: usize,
+    num_kfrags: usize,
+    sign_delegating_key: bool,
+    sign_receiving_key: bool,
+) -> Result<(Box<[VerifiedKeyFrag]>, RefreshKey), SignerError> {
+    let base = KeyFragBase::new(rng, delegating_sk, receiving_pk, signer, threshold);
+
+    let kfrags = (0..num_kfrags)
+        .map(|_| VerifiedKeyFrag::from_base(rng, &base, sign_delegating_key, sign_receiving_key))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok((kfrags.into_boxed_slice(), base.refresh_key()))
+}
+
+/// Errors that can happen when refreshing key fragments.
+#[derive(Debug, PartialEq)]
+pub enum RefreshError {
+    /// Some of the key fragments were not created under the given [`RefreshKey`].
+    MismatchedKeyFrags,
+    /// The key fragments are fewer than the threshold they were created with,
+    /// or some of them are repeated.
+    InsufficientKeyFrags,
+    /// The signer failed to sign the new key fragments.
+    Signing(SignerError),
+}
+
+impl fmt::Display for RefreshError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::MismatchedKeyFrags => {
+                write!(f, "key fragments do not match the refresh key")
+            }
+            Self::InsufficientKeyFrags => {
+                write!(f, "not enough distinct key fragments to recover the key")
+            }
+            Self::Signing(err) => write!(f, "failed to sign the key fragments: {}", err),
+        }
+    }
+}
+
+/// Re-randomizes the key fragments, returning new shares of the same re-encryption key
+/// (a proactive secret sharing refresh).
+///
+/// The receiving party can decrypt with `threshold` of the new fragments
+/// just as with the old ones, but old and new fragments cannot be combined,
+/// so the fragments compromised before the refresh become useless
+/// once the proxies replace them.
+/// `threshold` must be the same as the one the fragments were created with,
+/// and `signer` must be the original signer (the new fragments are signed anew).
+#[cfg(feature = "role-alice")]
+pub fn refresh_kfrags(
+    refresh_key: &RefreshKey,
+    signer: &Signer,
+    kfrags: &[VerifiedKeyFrag],
+    threshold: usize,
+) -> Result<Box<[VerifiedKeyFrag]>, RefreshError> {
+    refresh_kfrags_with_rng(&mut OsRng, refresh_key, signer, kfrags, threshold)
+}
+
+/// Same as [`refresh_kfrags()`], but uses the given RNG instead of the default one.
+#[cfg(feature = "role-alice")]
+pub fn refresh_kfrags_with_rng(
+    rng: &mut (impl CryptoRng + RngCore),
+    refresh_key: &RefreshKey,
+    signer: &Signer,
+    kfrags: &[VerifiedKeyFrag],
+    threshold: usize,
+) -> Result<Box<[VerifiedKeyFrag]>, RefreshError> {
+    if !kfrags.iter().all(|kfrag| refresh_key.matches(kfrag)) {
+        return Err(RefreshError::MismatchedKeyFrags);
+    }
+
+    let base = KeyFragBase::for_refresh(rng, refresh_key, signer, threshold);
+    kfrags
+        .iter()
+        .map(|kfrag| VerifiedKeyFrag::refreshed(rng, &base, kfrag))
+        .collect::<Result<Vec<_>, _>>()
+        .map(Vec::into_boxed_slice)
+        .map_err(RefreshError::Signing)
+}
+
+/// Regenerates a new set of `num_kfrags` key fragments with a new `threshold`
+/// for the same delegation as `kfrags`, from the refresh key
+/// (e.g. to add proxies to a running policy when the delegating secret key is kept offline).
+///
+/// This is not a distributed resharing among the proxies: the fragments are combined
+/// into the delegating secret key in the process (it is zeroized right after
+/// the new fragments are created), so this must only be run by the delegating party itself.
+/// Whoever holds `kfrags` and `refresh_key` can already recover the key,
+/// and the fragments must never be collected anywhere else.
+/// (The proxies cannot reshare their fragments among themselves,
+/// since the indices of the new shares depend on the secret part of `refresh_key`.)
+///
+/// `kfrags` must include at least as many distinct fragments as the original threshold.
+/// The receiving party decrypts with the new fragments just as with the old ones,
+/// but old and new fragments cannot be combined.
+/// `signer` must be the original signer (the new fragments are signed anew).
+#[allow(clippy::too_many_arguments)]
+#[cfg(feature = "role-alice")]
+pub fn regenerate_kfrags(
+    refresh_key: &RefreshKey,
+    signer: &Signer,
+    kfrags: &[VerifiedKeyFrag],
+    threshold: usize,
+    num_kfrags: usize,
+    sign_delegating_key: bool,
+    sign_receiving_key: bool,
+) -> Result<Box<[VerifiedKeyFrag]>, RefreshError> {
+    regenerate_kfrags_with_rng(
+        &mut OsRng,
+        refresh_key,
+        signer,
+        kfrags,
+        threshold,
+        num_kfrags,
+        sign_delegating_key,
+        sign_receiving_key,
+    )
+}
+
+/// Same as [`regenerate_kfrags()`], but uses the given RNG instead of the default one.
+#[allow(clippy::too_many_arguments)]
+#[cfg(feature = "role-alice")]
+pub fn regenerate_kfrags_with_rng(
+    rng: &mut (impl CryptoRng + RngCore),
+    refresh_key: &RefreshKey,
+    signer: &Signer,
+    kfrags: &[VerifiedKeyFrag],
+    threshold: usize,
+    num_kfrags: usize,
+    sign_delegating_key: bool,
+    sign_receiving_key: bool,
+) -> Result<Box<[VerifiedKeyFrag]>, RefreshError> {
+    if !kfrags.iter().all(|kfrag| refresh_key.matches(kfrag)) {
+        return Err(RefreshError::MismatchedKeyFrags);
+    }
+
+    let base = KeyFragBase::for_regeneration(rng, refresh_key, signer, kfrags, threshold)
+        .ok_or(RefreshError::InsufficientKeyFrags)?;
+    (0..num_kfrags)
+        .map(|_| VerifiedKeyFrag::from_base(rng, &base, sign_delegating_key, sign_receiving_key))
+        .collect::<Result<Vec<_>, _>>()
+        .map(Vec::into_boxed_slice)
+        .map_err(RefreshError::Signing)
+}
+
+/// Reencrypts a [`Capsule`] object with a key fragment, creating a capsule fragment.
+///
+/// Having `threshold` (see [`generate_kfrags()`](`crate::generate_kfrags()`))
+/// distinct fragments (along with the original capsule and the corresponding secret key)
+/// allows one to decrypt the original plaintext.
+///
+/// One can call [`KeyFrag::verify()`](`crate::KeyFrag::verify`)
+/// before reencryption to check its integrity.
+///
+/// `metadata` is hashed into the proof of the fragment in its canonical encoding
+/// (see [`Metadata::to_bytes()`]), and must be given to
+/// [`CapsuleFrag::verify()`](`crate::CapsuleFrag::verify`) as well.
+/// Metadata in other encodings can be attached with [`reencrypt_canonicalized()`]
+/// (e.g. with [`RawMetadata`](`crate::RawMetadata`) to hash the given bytes as they are).
+#[cfg(feature = "role-ursula")]
+pub fn reencrypt(
+    capsule: &Capsule,
+    verified_kfrag: &VerifiedKeyFrag,
+    metadata: Option<&Metadata>,
+) -> VerifiedCapsuleFrag {
+    reencrypt_with_rng(&mut OsRng, capsule, verified_kfrag, metadata)
+}
+
+/// Same as [`reencrypt()`], but uses the given RNG instead of the default one.
+#[cfg(feature = "role-ursula")]
+pub fn reencrypt_with_rng(
+    rng: &mut (impl CryptoRng + RngCore),
+    capsule: &Capsule,
+    verified_kfrag: &VerifiedKeyFrag,
+    metadata: Option<&Metadata>,
+) -> VerifiedCapsuleFrag {
+    VerifiedCapsuleFrag::reencrypted(
+        rng,
+        capsule,
+        &verified_kfrag.kfrag,
+        encode_metadata(metadata).as_deref(),
+    )
+}
+
+/// Same as [`reencrypt()`], but also returns a [`ReencryptionReceipt`]
+/// for the created fragment, signed with the proxy's own `signer`,
+/// that the receiving party or an auditor can verify
+/// with [`ReencryptionReceipt::verify()`].
+///
+/// The `timestamp` (e.g. the current time in seconds since the Unix epoch) is recorded in the receipt.
+///
+/// Panics if the signature is refused by the usage policy of the signer.
+#[cfg(feature = "role-ursula")]
+pub fn reencrypt_with_receipt(
+    capsule: &Capsule,
+    verified_kfrag: &VerifiedKeyFrag,
+    metadata: Option<&Metadata>,
+    signer: &Signer,
+    timestamp: u64,
+) -> (VerifiedCapsuleFrag, ReencryptionReceipt) {
+    reencrypt_with_receipt_with_rng(
+        &mut OsRng,
+        capsule,
+        verified_kfrag,
+        metadata,
+        signer,
+        timestamp,
+    )
+}
+
+/// Same as [`reencrypt_with_receipt()`], but uses the given RNG instead of the default one.
+#[cfg(feature = "role-ursula")]
+pub fn reencrypt_with_receipt_with_rng(
+    rng: &mut (impl CryptoRng + RngCore),
+    capsule: &Capsule,
+    verified_kfrag: &VerifiedKeyFrag,
+    metadata: Option<&Metadata>,
+    signer: &Signer,
+    timestamp: u64,
+) -> (VerifiedCapsuleFrag, ReencryptionReceipt) {
+    let verified_cfrag = reencrypt_with_rng(rng, capsule, verified_kfrag, metadata);
+    let receipt =
+        ReencryptionReceipt::new_with_rng(rng, signer, capsule, &verified_cfrag.cfrag, timestamp);
+    (verified_cfrag, receipt)
+}
+
+/// Reencrypts several capsules with the same key fragment,
+/// returning the capsule fragments in the same order.
+///
+/// The result is the same as calling [`reencrypt()`] for each capsule.
+/// Reencryption does not involve any values derived from the key fragment alone
+/// (no Diffie-Hellman or hashing of the precursor happens on the proxy side),
+/// and the randomness of the proofs must not be reused between the capsules,
+/// so the gain comes from processing the capsules in parallel
+/// when the `parallel` feature is enabled.
+#[cfg(feature = "role-ursula")]
+pub fn reencrypt_batch(
+    capsules: &[Capsule],
+    verified_kfrag: &VerifiedKeyFrag,
+    metadata: Option<&Metadata>,
+) -> Box<[VerifiedCapsuleFrag]> {
+    #[cfg(feature = "parallel")]
+    {
+        VerifiedCapsuleFrag::reencrypted_parallel(
+            capsules,
+            &verified_kfrag.kfrag,
+            encode_metadata(metadata).as_deref(),
+        )
     }
 
-    result.into_boxed_slice()
+    #[cfg(not(feature = "parallel"))]
+    reencrypt_batch_with_rng(&mut OsRng, capsules, verified_kfrag, metadata)
+}
+
+/// Same as [`reencrypt_batch()`], but uses the given RNG instead of the default one.
+/// The capsules are always processed sequentially, since the RNG cannot be shared between threads.
+#[cfg(feature = "role-ursula")]
+pub fn reencrypt_batch_with_rng(
+    rng: &mut (impl CryptoRng + RngCore),
+    capsules: &[Capsule],
+    verified_kfrag: &VerifiedKeyFrag,
+    metadata: Option<&Metadata>,
+) -> Box<[VerifiedCapsuleFrag]> {
+    let metadata = encode_metadata(metadata);
+    capsules
+        .iter()
+        .map(|capsule| {
+            VerifiedCapsuleFrag::reencrypted(
+                rng,
+                capsule,
+                &verified_kfrag.kfrag,
+                metadata.as_deref(),
+            )
+        })
+        .collect::<Vec<_>>()
+        .into_boxed_slice()
+}
+
+/// Same as [`reencrypt()`], but canonicalizes the metadata with the given canonicalizer
+/// before hashing it into the proof.
+///
+/// The resulting fragment should be verified with
+/// [`CapsuleFrag::verify_canonicalized()`](`crate::CapsuleFrag::verify_canonicalized`)
+/// and the same canonicalizer.
+#[cfg(feature = "role-ursula")]
+pub fn reencrypt_canonicalized(
+    capsule: &Capsule,
+    verified_kfrag: &VerifiedKeyFrag,
+    metadata: Option<&[u8]>,
+    canonicalizer: &impl MetadataCanonicalizer,
+) -> Result<VerifiedCapsuleFrag, CanonicalizationError> {
+    reencrypt_canonicalized_with_rng(&mut OsRng, capsule, verified_kfrag, metadata, canonicalizer)
+}
+
+/// Same as [`reencrypt_canonicalized()`], but uses the given RNG instead of the default one.
+#[cfg(feature = "role-ursula")]
+pub fn reencrypt_canonicalized_with_rng(
+    rng: &mut (impl CryptoRng + RngCore),
+    capsule: &Capsule,
+    verified_kfrag: &VerifiedKeyFrag,
+    metadata: Option<&[u8]>,
+    canonicalizer: &impl MetadataCanonicalizer,
+) -> Result<VerifiedCapsuleFrag, CanonicalizationError> {
+    let metadata = canonicalize(canonicalizer, metadata)?;
+    Ok(VerifiedCapsuleFrag::reencrypted(
+        rng,
+        capsule,
+        &verified_kfrag.kfrag,
+        metadata.as_deref(),
+    ))
 }
 
-/// Reencrypts a [`Capsule`] object with a key fragment, creating a capsule fragment.
-///
-/// Having `threshold` (see [`generate_kfrags()`](`crate::generate_kfrags()`))
-/// distinct fragments (along with the original capsule and the corresponding secret key)
-/// allows one to decrypt the original plaintext.
+/// Reencrypts a [`Capsule`] object with a key fragment, creating a capsule fragment
+/// that can be verified by a third party without the receiving party's key.
 ///
-/// One can call [`KeyFrag::verify()`](`crate::KeyFrag::verify`)
-/// before reencryption to check its integrity.
-pub fn reencrypt(
+/// See [`AuditableCapsuleFrag::verify_public`].
+#[cfg(feature = "role-ursula")]
+pub fn reencrypt_auditable(
     capsule: &Capsule,
     verified_kfrag: &VerifiedKeyFrag,
-    metadata: Option<&[u8]>,
-) -> VerifiedCapsuleFrag {
-    VerifiedCapsuleFrag::reencrypted(capsule, &verified_kfrag.kfrag, metadata)
+    metadata: Option<&Metadata>,
+) -> AuditableCapsuleFrag {
+    reencrypt_auditable_with_rng(&mut OsRng, capsule, verified_kfrag, metadata)
+}
+
+/// Same as [`reencrypt_auditable()`], but uses the given RNG instead of the default one.
+#[cfg(feature = "role-ursula")]
+pub fn reencrypt_auditable_with_rng(
+    rng: &mut (impl CryptoRng + RngCore),
+    capsule: &Capsule,
+    verified_kfrag: &VerifiedKeyFrag,
+    metadata: Option<&Metadata>,
+) -> AuditableCapsuleFrag {
+    AuditableCapsuleFrag::reencrypted(
+        rng,
+        capsule,
+        &verified_kfrag.kfrag,
+        encode_metadata(metadata).as_deref(),
+    )
 }
 
 /// Decrypts the ciphertext using previously reencrypted capsule fragments.
@@ -106,40 +1519,126 @@ pub fn reencrypt(
 /// `delegating_pk` is the public key of the encrypting party.
 /// Used to check the validity of decryption.
 ///
+/// `verified_cfrags` can be any collection of fragments, owned or borrowed
+/// (see [`Capsule::combine()`] for how they are selected).
+///
 /// One can call [`CapsuleFrag::verify()`](`crate::CapsuleFrag::verify`)
-/// before reencryption to check its integrity.
+/// before reencryption to check its integrity,
+/// or [`CapsuleFrag::find_faulty()`](`crate::CapsuleFrag::find_faulty`)
+/// to find out which of the received fragments are invalid.
+#[cfg(all(feature = "dem", feature = "role-bob"))]
 pub fn decrypt_reencrypted(
     decrypting_sk: &SecretKey,
     delegating_pk: &PublicKey,
     capsule: &Capsule,
-    verified_cfrags: &[VerifiedCapsuleFrag],
+    verified_cfrags: impl IntoIterator<Item = impl Borrow<VerifiedCapsuleFrag>>,
     ciphertext: impl AsRef<[u8]>,
 ) -> Result<Box<[u8]>, ReencryptionError> {
-    let cfrags: Vec<_> = verified_cfrags
-        .iter()
-        .cloned()
-        .map(|vcfrag| vcfrag.cfrag)
-        .collect();
-    let key_seed = capsule
-        .open_reencrypted(decrypting_sk, delegating_pk, &cfrags)
-        .map_err(ReencryptionError::OnOpen)?;
-    let dem = DEM::new(&key_seed.to_array());
-    dem.decrypt(&ciphertext, &capsule.to_array())
+    capsule
+        .combine(decrypting_sk, delegating_pk, verified_cfrags)
+        .map_err(ReencryptionError::OnOpen)?
+        .decrypt(ciphertext)
         .map_err(ReencryptionError::OnDecryption)
 }
 
-#[cfg(test)]
+/// Same as [`decrypt_reencrypted()`], but refuses to decrypt ciphertexts
+/// containing more than `max_plaintext_size` bytes of plaintext.
+///
+/// The check is performed before opening the capsule
+/// and before any allocation or authentication,
+/// so it is safe to use on ciphertexts of untrusted length.
+#[cfg(all(feature = "dem", feature = "role-bob"))]
+pub fn decrypt_reencrypted_limited(
+    decrypting_sk: &SecretKey,
+    delegating_pk: &PublicKey,
+    capsule: &Capsule,
+    verified_cfrags: impl IntoIterator<Item = impl Borrow<VerifiedCapsuleFrag>>,
+    ciphertext: impl AsRef<[u8]>,
+    max_plaintext_size: usize,
+) -> Result<Box<[u8]>, ReencryptionError> {
+    let plaintext_size =
+        plaintext_size_with_header(ciphertext.as_ref()).map_err(ReencryptionError::OnDecryption)?;
+    if plaintext_size > max_plaintext_size {
+        return Err(ReencryptionError::OnDecryption(
+            DecryptionError::PlaintextTooLarge,
+        ));
+    }
+    let key_seed = decapsulate_reencrypted(decrypting_sk, delegating_pk, capsule, verified_cfrags)
+        .map_err(ReencryptionError::OnOpen)?;
+    decrypt_with_header(
+        &key_seed,
+        ciphertext.as_ref(),
+        &capsule.to_array(),
+        Some(max_plaintext_size),
+    )
+    .map_err(ReencryptionError::OnDecryption)
+}
+
+/// Same as [`decrypt_reencrypted()`] for a ciphertext created by [`encrypt_signed()`],
+/// but also checks that the plaintext was signed with the given verifying key,
+/// returning [`DecryptionError::IncorrectSignature`] otherwise.
+#[cfg(all(feature = "dem", feature = "role-bob"))]
+pub fn decrypt_reencrypted_verified(
+    decrypting_sk: &SecretKey,
+    delegating_pk: &PublicKey,
+    capsule: &Capsule,
+    verified_cfrags: impl IntoIterator<Item = impl Borrow<VerifiedCapsuleFrag>>,
+    ciphertext: impl AsRef<[u8]>,
+    verifying_pk: &PublicKey,
+) -> Result<Box<[u8]>, ReencryptionError> {
+    let payload = Zeroizing::new(
+        decrypt_reencrypted(
+            decrypting_sk,
+            delegating_pk,
+            capsule,
+            verified_cfrags,
+            ciphertext,
+        )?
+        .into_vec(),
+    );
+    open_signed_payload(capsule, &payload, verifying_pk).map_err(ReencryptionError::OnDecryption)
+}
+
+#[cfg(all(
+    test,
+    feature = "role-alice",
+    feature = "role-ursula",
+    feature = "role-bob"
+))]
 mod tests {
 
     use alloc::vec::Vec;
 
+    use rand_core::{CryptoRng, Error, OsRng, RngCore};
+
+    #[cfg(feature = "dem")]
+    use crate::{
+        CapsuleFrag, DecryptionError, Metadata, OpenReencryptedError, ReencryptionError,
+        VerifiedCapsuleFrag,
+    };
     use crate::{
-        CapsuleFrag, DeserializableFromArray, KeyFrag, PublicKey, SecretKey, SerializableToArray,
-        Signer, VerifiedCapsuleFrag,
+        DeserializableFromArray, KeyFrag, PublicKey, RefreshKey, SecretKey, SerializableToArray,
+        Signer,
     };
 
-    use super::{decrypt_original, decrypt_reencrypted, encrypt, generate_kfrags, reencrypt};
+    use super::{
+        audit_capsule, check_key_original, decapsulate_original, decapsulate_reencrypted,
+        derive_key, encapsulate, encapsulate_with_rng, encapsulate_with_seed, generate_kfrags_iter,
+        generate_kfrags_iter_with_rng, generate_kfrags_with_label, generate_kfrags_with_rng,
+        generate_refreshable_kfrags, generate_single_kfrag, key_check, reencrypt_batch,
+        reencrypt_batch_with_rng, reencrypt_with_rng, refresh_kfrags, regenerate_kfrags,
+        DeriveKeyError, RefreshError,
+    };
+    #[cfg(feature = "dem")]
+    use super::{
+        decrypt_original, decrypt_original_limited, decrypt_original_verified,
+        decrypt_original_with_key_check, decrypt_reencrypted, decrypt_reencrypted_limited,
+        decrypt_reencrypted_verified, encrypt, encrypt_signed, encrypt_with_header,
+        encrypt_with_key_check, encrypt_with_key_check_with_rng, generate_kfrags, reencrypt,
+        DemAlgorithm,
+    };
 
+    #[cfg(feature = "dem")]
     #[test]
     fn test_simple_api() {
         /*
@@ -175,6 +1674,15 @@ mod tests {
         let plaintext_alice = decrypt_original(&delegating_sk, &capsule, &ciphertext).unwrap();
         assert_eq!(&plaintext_alice as &[u8], plaintext);
 
+        let plaintext_alice =
+            decrypt_original_limited(&delegating_sk, &capsule, &ciphertext, plaintext.len())
+                .unwrap();
+        assert_eq!(&plaintext_alice as &[u8], plaintext);
+        assert_eq!(
+            decrypt_original_limited(&delegating_sk, &capsule, &ciphertext, plaintext.len() - 1),
+            Err(DecryptionError::PlaintextTooLarge)
+        );
+
         // Split Re-Encryption Key Generation (aka Delegation)
         let verified_kfrags = generate_kfrags(
             &delegating_sk,
@@ -204,7 +1712,7 @@ mod tests {
             })
             .collect();
 
-        let metadata = b"metadata";
+        let metadata = &Metadata::new().with_extra(b"metadata");
         let verified_cfrags: Vec<VerifiedCapsuleFrag> = verified_kfrags[0..threshold]
             .iter()
             .map(|vkfrag| reencrypt(&capsule, &vkfrag, Some(metadata)))
@@ -242,5 +1750,625 @@ mod tests {
         )
         .unwrap();
         assert_eq!(&plaintext_bob as &[u8], plaintext);
+
+        assert_eq!(
+            decrypt_reencrypted_limited(
+                &receiving_sk,
+                &delegating_pk,
+                &capsule,
+                &verified_cfrags,
+                &ciphertext,
+                plaintext.len() - 1,
+            ),
+            Err(ReencryptionError::OnDecryption(
+                DecryptionError::PlaintextTooLarge
+            ))
+        );
+
+        // Insufficient or foreign capsule fragments are reported as such
+        let decrypt = |capsule, verified_cfrags: &[VerifiedCapsuleFrag]| {
+            decrypt_reencrypted(
+                &receiving_sk,
+                &delegating_pk,
+                capsule,
+                verified_cfrags,
+                &ciphertext,
+            )
+        };
+        assert_eq!(
+            decrypt(&capsule, &verified_cfrags[..1]),
+            Err(ReencryptionError::OnOpen(
+                OpenReencryptedError::NotEnoughCapsuleFrags
+            ))
+        );
+        assert_eq!(
+            decrypt(
+                &capsule,
+                &[verified_cfrags[0].clone(), verified_cfrags[0].clone()]
+            ),
+            Err(ReencryptionError::OnOpen(
+                OpenReencryptedError::NotEnoughCapsuleFrags
+            ))
+        );
+        let (other_capsule, _ciphertext) = encrypt(&delegating_pk, plaintext).unwrap();
+        let other_cfrag = reencrypt(&other_capsule, &verified_kfrags[threshold], None);
+        assert_eq!(
+            decrypt(&capsule, &[verified_cfrags[0].clone(), other_cfrag]),
+            Err(ReencryptionError::OnOpen(
+                OpenReencryptedError::ForeignCapsuleFrag(1)
+            ))
+        );
+
+        // Repeated fragments and fragments of other policies are skipped,
+        // and the fragments can be given by value
+        let stale_kfrags = generate_kfrags(
+            &delegating_sk,
+            &receiving_pk,
+            &signer,
+            threshold,
+            num_frags,
+            true,
+            true,
+        );
+        let plaintext_bob = decrypt_reencrypted(
+            &receiving_sk,
+            &delegating_pk,
+            &capsule,
+            [
+                reencrypt(&capsule, &stale_kfrags[0], None),
+                verified_cfrags[1].clone(),
+                verified_cfrags[1].clone(),
+                verified_cfrags[0].clone(),
+                reencrypt(&capsule, &verified_kfrags[threshold], None),
+            ],
+            &ciphertext,
+        )
+        .unwrap();
+        assert_eq!(&plaintext_bob as &[u8], plaintext);
+    }
+
+    // A deterministic (and, of course, insecure) RNG, for testing purposes only.
+    struct TestRng(u64);
+
+    impl RngCore for TestRng {
+        fn next_u32(&mut self) -> u32 {
+            self.next_u64() as u32
+        }
+
+        fn next_u64(&mut self) -> u64 {
+            // xorshift64
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+
+        fn fill_bytes(&mut self, dest: &mut [u8]) {
+            rand_core::impls::fill_bytes_via_next(self, dest)
+        }
+
+        fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), Error> {
+            self.fill_bytes(dest);
+            Ok(())
+        }
+    }
+
+    impl CryptoRng for TestRng {}
+
+    #[cfg(feature = "dem")]
+    #[test]
+    fn test_combine() {
+        let delegating_sk = SecretKey::random();
+        let delegating_pk = PublicKey::from_secret_key(&delegating_sk);
+        let receiving_sk = SecretKey::random();
+        let receiving_pk = PublicKey::from_secret_key(&receiving_sk);
+        let signer = Signer::new(&SecretKey::random());
+
+        let (capsule, key_seed) = encapsulate(&delegating_pk);
+        let ciphertexts: Vec<_> = [&b"first"[..], b"second"]
+            .iter()
+            .map(|plaintext| {
+                encrypt_with_header(
+                    DemAlgorithm::DEFAULT,
+                    &mut OsRng,
+                    &key_seed,
+                    plaintext,
+                    &capsule.to_array(),
+                )
+                .unwrap()
+            })
+            .collect();
+
+        let kfrags = generate_kfrags(&delegating_sk, &receiving_pk, &signer, 2, 3, true, true);
+        let verified_cfrags: Vec<_> = kfrags
+            .iter()
+            .map(|kfrag| reencrypt(&capsule, kfrag, None))
+            .collect();
+
+        // The fragments are combined once for all the ciphertexts of the capsule.
+        let combined = capsule
+            .combine(&receiving_sk, &delegating_pk, &verified_cfrags[1..])
+            .unwrap();
+        assert_eq!(combined.key_seed(), &key_seed);
+        assert_eq!(
+            &combined.decrypt(&ciphertexts[0]).unwrap() as &[u8],
+            b"first"
+        );
+        assert_eq!(
+            &combined.decrypt(&ciphertexts[1]).unwrap() as &[u8],
+            b"second"
+        );
+
+        assert_eq!(
+            capsule
+                .combine(&receiving_sk, &delegating_pk, &verified_cfrags[..1])
+                .map(|combined| *combined.key_seed()),
+            Err(OpenReencryptedError::NotEnoughCapsuleFrags)
+        );
+    }
+
+    #[cfg(feature = "dem")]
+    #[test]
+    fn test_key_check() {
+        let sk = SecretKey::random();
+        let pk = PublicKey::from_secret_key(&sk);
+        let wrong_sk = SecretKey::random();
+
+        let plaintext = b"peace at dawn";
+        let (capsule, ciphertext, key_check) = encrypt_with_key_check(&pk, plaintext).unwrap();
+
+        assert!(check_key_original(&sk, &capsule, &key_check));
+        assert!(!check_key_original(&wrong_sk, &capsule, &key_check));
+
+        let plaintext_back =
+            decrypt_original_with_key_check(&sk, &capsule, &key_check, &ciphertext).unwrap();
+        assert_eq!(&plaintext_back as &[u8], plaintext);
+
+        assert_eq!(
+            decrypt_original_with_key_check(&wrong_sk, &capsule, &key_check, &ciphertext),
+            Err(DecryptionError::WrongKey)
+        );
+
+        let mut modified_ciphertext = ciphertext.to_vec();
+        let last = modified_ciphertext.len() - 1;
+        modified_ciphertext[last] ^= 1;
+        assert_eq!(
+            decrypt_original_with_key_check(&sk, &capsule, &key_check, &modified_ciphertext),
+            Err(DecryptionError::AuthenticationFailed)
+        );
+
+        // The same RNG gives the same results.
+        let (capsule, ciphertext, key_check) =
+            encrypt_with_key_check_with_rng(&mut TestRng(1), &pk, plaintext).unwrap();
+        assert_eq!(
+            encrypt_with_key_check_with_rng(&mut TestRng(1), &pk, plaintext).unwrap(),
+            (capsule, ciphertext.clone(), key_check)
+        );
+        let plaintext_back =
+            decrypt_original_with_key_check(&sk, &capsule, &key_check, &ciphertext).unwrap();
+        assert_eq!(&plaintext_back as &[u8], plaintext);
+    }
+
+    #[cfg(feature = "dem")]
+    #[test]
+    fn test_encrypt_signed() {
+        let delegating_sk = SecretKey::random();
+        let delegating_pk = PublicKey::from_secret_key(&delegating_sk);
+        let signer = Signer::new(&SecretKey::random());
+        let verifying_pk = signer.verifying_key();
+        let other_verifying_pk = Signer::new(&SecretKey::random()).verifying_key();
+
+        let receiving_sk = SecretKey::random();
+        let receiving_pk = PublicKey::from_secret_key(&receiving_sk);
+
+        let plaintext = b"peace at dawn";
+        let (capsule, ciphertext) = encrypt_signed(&delegating_pk, &signer, plaintext).unwrap();
+
+        let plaintext_back =
+            decrypt_original_verified(&delegating_sk, &capsule, &ciphertext, &verifying_pk)
+                .unwrap();
+        assert_eq!(&plaintext_back as &[u8], plaintext);
+        assert_eq!(
+            decrypt_original_verified(&delegating_sk, &capsule, &ciphertext, &other_verifying_pk),
+            Err(DecryptionError::IncorrectSignature)
+        );
+
+        // A ciphertext without a signature is rejected.
+        let (unsigned_capsule, unsigned_ciphertext) = encrypt(&delegating_pk, plaintext).unwrap();
+        assert_eq!(
+            decrypt_original_verified(
+                &delegating_sk,
+                &unsigned_capsule,
+                &unsigned_ciphertext,
+                &verifying_pk
+            ),
+            Err(DecryptionError::IncorrectSignature)
+        );
+
+        let kfrags = generate_kfrags(&delegating_sk, &receiving_pk, &signer, 2, 3, true, true);
+        let vcfrags: Vec<_> = kfrags[..2]
+            .iter()
+            .map(|kfrag| reencrypt(&capsule, kfrag, None))
+            .collect();
+
+        let plaintext_back = decrypt_reencrypted_verified(
+            &receiving_sk,
+            &delegating_pk,
+            &capsule,
+            &vcfrags,
+            &ciphertext,
+            &verifying_pk,
+        )
+        .unwrap();
+        assert_eq!(&plaintext_back as &[u8], plaintext);
+        assert_eq!(
+            decrypt_reencrypted_verified(
+                &receiving_sk,
+                &delegating_pk,
+                &capsule,
+                &vcfrags,
+                &ciphertext,
+                &other_verifying_pk,
+            ),
+            Err(ReencryptionError::OnDecryption(
+                DecryptionError::IncorrectSignature
+            ))
+        );
+    }
+
+    #[test]
+    fn test_audit_capsule() {
+        let sk = SecretKey::random();
+        let pk = PublicKey::from_secret_key(&sk);
+        let other_pk = PublicKey::from_secret_key(&SecretKey::random());
+        let seed = b"committed seed";
+
+        let (capsule, key_seed) = encapsulate_with_seed(seed, &pk);
+        assert_eq!(encapsulate_with_seed(seed, &pk), (capsule, key_seed));
+        assert_eq!(decapsulate_original(&sk, &capsule), key_seed);
+
+        assert!(audit_capsule(seed, &pk, &capsule));
+        assert!(!audit_capsule(b"another seed", &pk, &capsule));
+        assert!(!audit_capsule(seed, &other_pk, &capsule));
+    }
+
+    #[test]
+    fn test_refresh_kfrags() {
+        let delegating_sk = SecretKey::random();
+        let delegating_pk = PublicKey::from_secret_key(&delegating_sk);
+        let signer = Signer::new(&SecretKey::random());
+        let receiving_sk = SecretKey::random();
+        let receiving_pk = PublicKey::from_secret_key(&receiving_sk);
+
+        let (capsule, key_seed) = encapsulate_with_rng(&mut OsRng, &delegating_pk);
+        let (kfrags, refresh_key) =
+            generate_refreshable_kfrags(&delegating_sk, &receiving_pk, &signer, 2, 3, true, true);
+        let refresh_key_back = RefreshKey::from_array(&refresh_key.to_array()).unwrap();
+        assert!(refresh_key_back == refresh_key);
+        let refresh_key = refresh_key_back;
+        let new_kfrags = refresh_kfrags(&refresh_key, &signer, &kfrags, 2).unwrap();
+
+        for (kfrag, new_kfrag) in kfrags.iter().zip(new_kfrags.iter()) {
+            assert!(kfrag.to_array() != new_kfrag.to_array());
+            assert_eq!(kfrag.policy_fingerprint(), new_kfrag.policy_fingerprint());
+            let new_kfrag = KeyFrag::from_array(&new_kfrag.to_array()).unwrap();
+            assert!(new_kfrag
+                .verify(
+                    &signer.verifying_key(),
+                    Some(&delegating_pk),
+                    Some(&receiving_pk)
+                )
+                .is_ok());
+        }
+
+        let cfrags: Vec<_> = new_kfrags[1..]
+            .iter()
+            .map(|kfrag| reencrypt_with_rng(&mut OsRng, &capsule, kfrag, None))
+            .collect();
+        let key_seed_reenc =
+            decapsulate_reencrypted(&receiving_sk, &delegating_pk, &capsule, &cfrags).unwrap();
+        assert_eq!(key_seed, key_seed_reenc);
+
+        // Old and new fragments do not combine.
+        let mixed_cfrags = [
+            reencrypt_with_rng(&mut OsRng, &capsule, &kfrags[0], None),
+            reencrypt_with_rng(&mut OsRng, &capsule, &new_kfrags[1], None),
+        ];
+        assert!(
+            decapsulate_reencrypted(&receiving_sk, &delegating_pk, &capsule, &mixed_cfrags)
+                .is_err()
+        );
+
+        let other_kfrags = generate_kfrags_with_rng(
+            &mut OsRng,
+            &delegating_sk,
+            &receiving_pk,
+            &signer,
+            2,
+            3,
+            true,
+            true,
+        );
+        assert_eq!(
+            refresh_kfrags(&refresh_key, &signer, &other_kfrags, 2),
+            Err(RefreshError::MismatchedKeyFrags)
+        );
+    }
+
+    #[test]
+    fn test_regenerate_kfrags() {
+        let delegating_sk = SecretKey::random();
+        let delegating_pk = PublicKey::from_secret_key(&delegating_sk);
+        let signer = Signer::new(&SecretKey::random());
+        let receiving_sk = SecretKey::random();
+        let receiving_pk = PublicKey::from_secret_key(&receiving_sk);
+
+        let (capsule, key_seed) = encapsulate_with_rng(&mut OsRng, &delegating_pk);
+        let (kfrags, refresh_key) =
+            generate_refreshable_kfrags(&delegating_sk, &receiving_pk, &signer, 2, 3, true, true);
+
+        // 2-of-3 to 3-of-5
+        let new_kfrags =
+            regenerate_kfrags(&refresh_key, &signer, &kfrags[1..], 3, 5, true, true).unwrap();
+        assert_eq!(new_kfrags.len(), 5);
+
+        let cfrags: Vec<_> = new_kfrags[2..]
+            .iter()
+            .map(|kfrag| {
+                let kfrag = KeyFrag::from_array(&kfrag.to_array()).unwrap();
+                let kfrag = kfrag
+                    .verify(
+                        &signer.verifying_key(),
+                        Some(&delegating_pk),
+                        Some(&receiving_pk),
+                    )
+                    .unwrap();
+                reencrypt_with_rng(&mut OsRng, &capsule, &kfrag, None)
+            })
+            .collect();
+        let key_seed_reenc =
+            decapsulate_reencrypted(&receiving_sk, &delegating_pk, &capsule, &cfrags).unwrap();
+        assert_eq!(key_seed, key_seed_reenc);
+
+        assert!(
+            decapsulate_reencrypted(&receiving_sk, &delegating_pk, &capsule, &cfrags[1..]).is_err()
+        );
+
+        assert_eq!(
+            regenerate_kfrags(&refresh_key, &signer, &kfrags[..1], 3, 5, true, true),
+            Err(RefreshError::InsufficientKeyFrags)
+        );
+        let repeated_kfrags = [kfrags[0].clone(), kfrags[0].clone()];
+        assert_eq!(
+            regenerate_kfrags(&refresh_key, &signer, &repeated_kfrags, 3, 5, true, true),
+            Err(RefreshError::InsufficientKeyFrags)
+        );
+    }
+
+    #[test]
+    fn test_derive_key() {
+        let pk = PublicKey::from_secret_key(&SecretKey::random());
+        let (_capsule, key_seed) = encapsulate_with_rng(&mut TestRng(1), &pk);
+
+        let enc_key = derive_key(&key_seed, 32, b"encryption").unwrap();
+        let mac_key = derive_key(&key_seed, 64, b"mac").unwrap();
+        assert_eq!(enc_key.len(), 32);
+        assert_eq!(mac_key.len(), 64);
+        assert_eq!(enc_key, derive_key(&key_seed, 32, b"encryption").unwrap());
+        assert_ne!(&enc_key[..], &mac_key[..32]);
+
+        assert_eq!(
+            derive_key(&key_seed, 0, b"encryption"),
+            Err(DeriveKeyError::InvalidLength)
+        );
+        assert_eq!(
+            derive_key(&key_seed, 255 * 32 + 1, b"encryption"),
+            Err(DeriveKeyError::InvalidLength)
+        );
+    }
+
+    #[test]
+    fn test_with_rng() {
+        let delegating_sk = SecretKey::random_with_rng(&mut TestRng(1));
+        let delegating_pk = PublicKey::from_secret_key(&delegating_sk);
+        assert!(delegating_sk == SecretKey::random_with_rng(&mut TestRng(1)));
+
+        let signer = Signer::new(&SecretKey::random_with_rng(&mut TestRng(2)));
+        let receiving_sk = SecretKey::random_with_rng(&mut TestRng(3));
+        let receiving_pk = PublicKey::from_secret_key(&receiving_sk);
+
+        let (capsule, key_seed) = encapsulate_with_rng(&mut TestRng(4), &delegating_pk);
+        let (capsule2, key_seed2) = encapsulate_with_rng(&mut TestRng(4), &delegating_pk);
+        assert_eq!(capsule, capsule2);
+        assert_eq!(key_seed, key_seed2);
+        assert_eq!(decapsulate_original(&delegating_sk, &capsule), key_seed);
+        assert!(check_key_original(
+            &delegating_sk,
+            &capsule,
+            &key_check(&key_seed)
+        ));
+
+        let make_kfrags = || {
+            generate_kfrags_with_rng(
+                &mut TestRng(5),
+                &delegating_sk,
+                &receiving_pk,
+                &signer,
+                2,
+                3,
+                true,
+                true,
+            )
+        };
+        let kfrags = make_kfrags();
+        assert_eq!(kfrags, make_kfrags());
+
+        let mut rng = TestRng(6);
+        let vcfrags: Vec<_> = kfrags[0..2]
+            .iter()
+            .map(|vkfrag| reencrypt_with_rng(&mut rng, &capsule, vkfrag, None))
+            .collect();
+        assert_eq!(
+            vcfrags[0],
+            reencrypt_with_rng(&mut TestRng(6), &capsule, &kfrags[0], None)
+        );
+
+        let key_seed_bob =
+            decapsulate_reencrypted(&receiving_sk, &delegating_pk, &capsule, &vcfrags).unwrap();
+        assert_eq!(key_seed_bob, key_seed);
+    }
+
+    #[test]
+    fn test_reencrypt_batch() {
+        let delegating_sk = SecretKey::random();
+        let delegating_pk = PublicKey::from_secret_key(&delegating_sk);
+        let receiving_sk = SecretKey::random();
+        let receiving_pk = PublicKey::from_secret_key(&receiving_sk);
+        let signer = Signer::new(&SecretKey::random());
+
+        let (capsules, key_seeds): (Vec<_>, Vec<_>) =
+            (0..4).map(|_| encapsulate(&delegating_pk)).unzip();
+        let kfrags = generate_kfrags_with_rng(
+            &mut OsRng,
+            &delegating_sk,
+            &receiving_pk,
+            &signer,
+            2,
+            3,
+            true,
+            true,
+        );
+
+        let batch0 = reencrypt_batch(&capsules, &kfrags[0], None);
+        let batch1 = reencrypt_batch(&capsules, &kfrags[1], None);
+        assert_eq!(batch0.len(), capsules.len());
+        for (i, capsule) in capsules.iter().enumerate() {
+            let vcfrags = [batch0[i].clone(), batch1[i].clone()];
+            assert_eq!(
+                decapsulate_reencrypted(&receiving_sk, &delegating_pk, capsule, &vcfrags),
+                Ok(key_seeds[i])
+            );
+        }
+
+        // With an RNG, the capsules are reencrypted the same way as one by one.
+        let batch = reencrypt_batch_with_rng(&mut TestRng(1), &capsules, &kfrags[2], None);
+        let mut rng = TestRng(1);
+        for (vcfrag, capsule) in batch.iter().zip(capsules.iter()) {
+            assert_eq!(
+                vcfrag,
+                &reencrypt_with_rng(&mut rng, capsule, &kfrags[2], None)
+            );
+        }
+    }
+
+    #[test]
+    fn test_generate_kfrags_iter() {
+        let delegating_sk = SecretKey::random();
+        let delegating_pk = PublicKey::from_secret_key(&delegating_sk);
+        let signer = Signer::new(&SecretKey::random());
+        let receiving_sk = SecretKey::random();
+        let receiving_pk = PublicKey::from_secret_key(&receiving_sk);
+
+        // Produces the same fragments as the eager version given the same randomness.
+        let mut rng = TestRng(5);
+        let kfrags_iter = generate_kfrags_iter_with_rng(
+            &mut rng,
+            &delegating_sk,
+            &receiving_pk,
+            &signer,
+            2,
+            3,
+            true,
+            true,
+        );
+        assert_eq!(kfrags_iter.len(), 3);
+        let kfrags: Vec<_> = kfrags_iter.collect();
+        let expected_kfrags = generate_kfrags_with_rng(
+            &mut TestRng(5),
+            &delegating_sk,
+            &receiving_pk,
+            &signer,
+            2,
+            3,
+            true,
+            true,
+        );
+        assert_eq!(kfrags, expected_kfrags.to_vec());
+
+        let (capsule, key_seed) = encapsulate(&delegating_pk);
+        let vcfrags: Vec<_> =
+            generate_kfrags_iter(&delegating_sk, &receiving_pk, &signer, 2, 1000, true, true)
+                .take(2)
+                .map(|vkfrag| reencrypt_with_rng(&mut OsRng, &capsule, &vkfrag, None))
+                .collect();
+        let key_seed_bob =
+            decapsulate_reencrypted(&receiving_sk, &delegating_pk, &capsule, &vcfrags).unwrap();
+        assert_eq!(key_seed_bob, key_seed);
+    }
+
+    #[test]
+    fn test_generate_kfrags_with_label() {
+        let delegating_sk = SecretKey::random();
+        let delegating_pk = PublicKey::from_secret_key(&delegating_sk);
+        let signer = Signer::new(&SecretKey::random());
+        let receiving_sk = SecretKey::random();
+        let receiving_pk = PublicKey::from_secret_key(&receiving_sk);
+
+        let generate = |threshold, num_kfrags, label: &[u8]| {
+            generate_kfrags_with_label(
+                &delegating_sk,
+                &receiving_pk,
+                &signer,
+                threshold,
+                num_kfrags,
+                true,
+                true,
+                label,
+            )
+        };
+
+        // The same arguments produce the identical set
+        let kfrags = generate(2, 3, b"policy");
+        assert_eq!(kfrags, generate(2, 3, b"policy"));
+
+        // The fragments do not depend on the total number of them
+        assert_eq!(kfrags[..2], generate(2, 2, b"policy")[..]);
+
+        // Other labels or thresholds produce unrelated sets
+        let other_kfrags = generate(2, 3, b"other policy");
+        let other_threshold_kfrags = generate(3, 3, b"policy");
+        for kfrag in kfrags.iter() {
+            assert!(other_kfrags.iter().all(|other| other.id() != kfrag.id()));
+            assert!(other_threshold_kfrags
+                .iter()
+                .all(|other| other.clone().unverify() != kfrag.clone().unverify()));
+        }
+
+        // A single fragment can be recreated
+        let regenerated = |index| {
+            generate_single_kfrag(
+                &delegating_sk,
+                &receiving_pk,
+                &signer,
+                2,
+                3,
+                index,
+                true,
+                true,
+                b"policy",
+            )
+        };
+        assert_eq!(regenerated(1), Some(kfrags[1].clone()));
+        assert_eq!(regenerated(3), None);
+
+        // The fragments are valid
+        let (capsule, key_seed) = encapsulate(&delegating_pk);
+        let vcfrags: Vec<_> = kfrags[1..]
+            .iter()
+            .map(|vkfrag| reencrypt_with_rng(&mut OsRng, &capsule, vkfrag, None))
+            .collect();
+        let key_seed_bob =
+            decapsulate_reencrypted(&receiving_sk, &delegating_pk, &capsule, &vcfrags).unwrap();
+        assert_eq!(key_seed_bob, key_seed);
     }
 }
@@ -0,0 +1,61 @@
+//! A curated set of imports for the common use of the crate.
+//!
+//! ```
+//! use umbral_pre::prelude::*;
+//! ```
+//!
+//! brings into scope the key and fragment types, the main functions,
+//! and the serialization traits (so that `to_array()`/`from_bytes()` and the like can be called).
+//! Importing the traits from here rather than by their paths means that
+//! a future reorganization of the traits will not require changes in the downstream code.
+
+#[cfg(feature = "role-bob")]
+pub use crate::decapsulate_reencrypted;
+#[cfg(all(feature = "dem", feature = "role-bob"))]
+pub use crate::decrypt_reencrypted;
+pub use crate::error::Error;
+#[cfg(feature = "role-alice")]
+pub use crate::generate_kfrags;
+#[cfg(feature = "role-ursula")]
+pub use crate::reencrypt;
+pub use crate::traits::{
+    DeserializableFromArray, RepresentableAsArray, SerializableToArray, VersionedSerialization,
+};
+pub use crate::{
+    decapsulate_original, encapsulate, Capsule, CapsuleFrag, KeyFrag, KeySeed, Metadata, PublicKey,
+    SecretKey, SecretKeyFactory, Signature, Signer, VerifiedCapsuleFrag, VerifiedKeyFrag,
+};
+#[cfg(feature = "dem")]
+pub use crate::{decrypt_original, encrypt};
+
+/// A `Result` with the crate's [`Error`] as the default error type.
+///
+/// The second parameter allows it to coexist with the standard `Result` under a glob import.
+pub type Result<T, E = Error> = core::result::Result<T, E>;
+
+#[cfg(all(test, feature = "role-alice"))]
+mod tests {
+
+    use super::*;
+
+    fn roundtrip(kfrag: &VerifiedKeyFrag, verifying_pk: &PublicKey) -> Result<VerifiedKeyFrag> {
+        let kfrag = KeyFrag::from_bytes(&kfrag.to_array())?;
+        Ok(kfrag.verify(verifying_pk, None, None)?)
+    }
+
+    #[test]
+    fn test_prelude() {
+        let delegating_sk = SecretKey::random();
+        let receiving_pk = PublicKey::from_secret_key(&SecretKey::random());
+        let signing_sk = SecretKey::random();
+        let signer = Signer::new(&signing_sk);
+
+        let kfrags = generate_kfrags(&delegating_sk, &receiving_pk, &signer, 2, 3, false, false);
+        assert!(roundtrip(&kfrags[0], &signer.verifying_key()).is_ok());
+
+        // The standard two-parameter form still works.
+        let wrong_pk = PublicKey::from_secret_key(&delegating_sk);
+        let result: Result<VerifiedKeyFrag, Error> = roundtrip(&kfrags[0], &wrong_pk);
+        assert!(result.is_err());
+    }
+}
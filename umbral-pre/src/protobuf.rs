@@ -0,0 +1,252 @@
+//! Protocol buffer messages for the objects exchanged between the parties,
+//! enabled with the `protobuf` feature.
+//!
+//! The messages are defined in `proto/umbral.proto` (shipped with the crate),
+//! which services written in other languages can compile with their own protobuf toolchain.
+//! The structs here are the [`prost`] counterparts of these definitions,
+//! along with the conversions from and to the types of this crate.
+//! They are encoded and decoded with the methods of [`prost::Message`].
+//!
+//! The cryptographic objects are carried as their canonical byte representations
+//! (see [`SerializableToArray`]), so a decoded message still has to be deserialized,
+//! and its fragments verified, as usual.
+
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use core::convert::TryFrom;
+
+use crate::capsule::Capsule as UmbralCapsule;
+use crate::capsule_frag::{CapsuleFrag as UmbralCapsuleFrag, VerifiedCapsuleFrag};
+use crate::key_frag::{KeyFrag as UmbralKeyFrag, KeyFragID, VerifiedKeyFrag};
+use crate::metadata::Metadata;
+use crate::traits::{DeserializableFromArray, DeserializationError, SerializableToArray};
+
+/// An encapsulated symmetric key.
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct Capsule {
+    /// The serialized [`Capsule`](crate::Capsule).
+    #[prost(bytes = "vec", tag = "1")]
+    pub data: Vec<u8>,
+}
+
+/// A fragment of the reencryption key.
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct KeyFrag {
+    /// The serialized [`KeyFrag`](crate::KeyFrag).
+    #[prost(bytes = "vec", tag = "1")]
+    pub data: Vec<u8>,
+}
+
+/// A reencrypted fragment of a capsule.
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct CapsuleFrag {
+    /// The serialized [`CapsuleFrag`](crate::CapsuleFrag).
+    #[prost(bytes = "vec", tag = "1")]
+    pub data: Vec<u8>,
+}
+
+/// A request to reencrypt capsules with the key fragment held by a proxy.
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct ReencryptionRequest {
+    /// The capsules to reencrypt.
+    #[prost(message, repeated, tag = "1")]
+    pub capsules: Vec<Capsule>,
+    /// The serialized [`KeyFragID`].
+    #[prost(bytes = "vec", tag = "2")]
+    pub kfrag_id: Vec<u8>,
+    /// The canonical encoding of the [`Metadata`] to attach to the capsule fragments.
+    #[prost(bytes = "vec", optional, tag = "3")]
+    pub metadata: Option<Vec<u8>>,
+}
+
+/// The capsule fragments, in the order of the capsules of the request.
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct ReencryptionResponse {
+    /// The capsule fragments.
+    #[prost(message, repeated, tag = "1")]
+    pub cfrags: Vec<CapsuleFrag>,
+}
+
+impl From<&UmbralCapsule> for Capsule {
+    fn from(capsule: &UmbralCapsule) -> Self {
+        Self {
+            data: capsule.to_array().to_vec(),
+        }
+    }
+}
+
+impl TryFrom<&Capsule> for UmbralCapsule {
+    type Error = DeserializationError;
+
+    fn try_from(message: &Capsule) -> Result<Self, Self::Error> {
+        Self::from_bytes(&message.data)
+    }
+}
+
+impl From<&UmbralKeyFrag> for KeyFrag {
+    fn from(kfrag: &UmbralKeyFrag) -> Self {
+        Self {
+            data: kfrag.to_array().to_vec(),
+        }
+    }
+}
+
+impl From<&VerifiedKeyFrag> for KeyFrag {
+    fn from(kfrag: &VerifiedKeyFrag) -> Self {
+        Self {
+            data: kfrag.to_array().to_vec(),
+        }
+    }
+}
+
+impl TryFrom<&KeyFrag> for UmbralKeyFrag {
+    type Error = DeserializationError;
+
+    fn try_from(message: &KeyFrag) -> Result<Self, Self::Error> {
+        Self::from_bytes(&message.data)
+    }
+}
+
+impl From<&UmbralCapsuleFrag> for CapsuleFrag {
+    fn from(cfrag: &UmbralCapsuleFrag) -> Self {
+        Self {
+            data: cfrag.to_array().to_vec(),
+        }
+    }
+}
+
+impl From<&VerifiedCapsuleFrag> for CapsuleFrag {
+    fn from(cfrag: &VerifiedCapsuleFrag) -> Self {
+        Self {
+            data: cfrag.to_array().to_vec(),
+        }
+    }
+}
+
+impl TryFrom<&CapsuleFrag> for UmbralCapsuleFrag {
+    type Error = DeserializationError;
+
+    fn try_from(message: &CapsuleFrag) -> Result<Self, Self::Error> {
+        Self::from_bytes(&message.data)
+    }
+}
+
+impl ReencryptionRequest {
+    /// Creates a request to reencrypt the capsules with the key fragment with the given ID.
+    pub fn new(
+        capsules: &[UmbralCapsule],
+        kfrag_id: &KeyFragID,
+        metadata: Option<&Metadata>,
+    ) -> Self {
+        Self {
+            capsules: capsules.iter().map(Capsule::from).collect(),
+            kfrag_id: kfrag_id.to_array().to_vec(),
+            metadata: metadata.map(|metadata| metadata.to_bytes().into_vec()),
+        }
+    }
+
+    /// Deserializes the capsules of the request.
+    pub fn capsules(&self) -> Result<Box<[UmbralCapsule]>, DeserializationError> {
+        self.capsules.iter().map(UmbralCapsule::try_from).collect()
+    }
+
+    /// Deserializes the ID of the key fragment.
+    pub fn kfrag_id(&self) -> Result<KeyFragID, DeserializationError> {
+        KeyFragID::from_bytes(&self.kfrag_id)
+    }
+
+    /// Deserializes the metadata of the request.
+    pub fn metadata(&self) -> Result<Option<Metadata>, DeserializationError> {
+        self.metadata
+            .as_deref()
+            .map(Metadata::from_bytes)
+            .transpose()
+    }
+}
+
+impl ReencryptionResponse {
+    /// Creates a response with the given capsule fragments.
+    pub fn new(cfrags: &[VerifiedCapsuleFrag]) -> Self {
+        Self {
+            cfrags: cfrags.iter().map(CapsuleFrag::from).collect(),
+        }
+    }
+
+    /// Deserializes the capsule fragments of the response
+    /// (which must then be verified, see [`CapsuleFrag::verify()`](crate::CapsuleFrag::verify)).
+    pub fn cfrags(&self) -> Result<Box<[UmbralCapsuleFrag]>, DeserializationError> {
+        self.cfrags
+            .iter()
+            .map(UmbralCapsuleFrag::try_from)
+            .collect()
+    }
+}
+
+#[cfg(all(test, feature = "role-alice", feature = "role-ursula"))]
+mod tests {
+
+    use alloc::vec::Vec;
+    use core::convert::TryFrom;
+
+    use prost::Message;
+
+    use super::{KeyFrag, ReencryptionRequest, ReencryptionResponse};
+    use crate::{
+        encapsulate, generate_kfrags, reencrypt, DeserializationError, Metadata, PublicKey,
+        SecretKey, Signer,
+    };
+
+    #[test]
+    fn test_messages() {
+        let delegating_sk = SecretKey::random();
+        let delegating_pk = PublicKey::from_secret_key(&delegating_sk);
+        let receiving_pk = PublicKey::from_secret_key(&SecretKey::random());
+        let signer = Signer::new(&SecretKey::random());
+        let verifying_pk = signer.verifying_key();
+
+        let kfrags = generate_kfrags(&delegating_sk, &receiving_pk, &signer, 2, 3, true, true);
+        let kfrag_message = KeyFrag::decode(KeyFrag::from(&kfrags[0]).encode_to_vec()).unwrap();
+        let kfrag = crate::KeyFrag::try_from(&kfrag_message).unwrap();
+        let kfrag = kfrag
+            .verify(&verifying_pk, Some(&delegating_pk), Some(&receiving_pk))
+            .unwrap();
+
+        let capsules: Vec<_> = (0..2).map(|_| encapsulate(&delegating_pk).0).collect();
+        let metadata = Metadata::new().with_extra(b"metadata");
+        let request = ReencryptionRequest::new(&capsules, kfrag.id(), Some(&metadata));
+        let request = ReencryptionRequest::decode(request.encode_to_vec()).unwrap();
+        assert_eq!(request.capsules().unwrap().as_ref(), capsules.as_slice());
+        assert_eq!(&request.kfrag_id().unwrap(), kfrag.id());
+        let request_metadata = request.metadata().unwrap();
+        assert_eq!(request_metadata.as_ref(), Some(&metadata));
+
+        let vcfrags: Vec<_> = capsules
+            .iter()
+            .map(|capsule| reencrypt(capsule, &kfrag, request_metadata.as_ref()))
+            .collect();
+        let response = ReencryptionResponse::new(&vcfrags);
+        let response = ReencryptionResponse::decode(response.encode_to_vec()).unwrap();
+        let cfrags = response.cfrags().unwrap();
+        for (cfrag, (capsule, vcfrag)) in cfrags.iter().zip(capsules.iter().zip(vcfrags.iter())) {
+            assert_eq!(cfrag, &vcfrag.clone().unverify());
+            assert!(cfrag
+                .clone()
+                .verify(
+                    capsule,
+                    &verifying_pk,
+                    &delegating_pk,
+                    &receiving_pk,
+                    Some(&metadata)
+                )
+                .is_ok());
+        }
+
+        // Truncated objects are rejected on conversion.
+        let mut kfrag_message = kfrag_message;
+        kfrag_message.data.pop();
+        assert_eq!(
+            crate::KeyFrag::try_from(&kfrag_message),
+            Err(DeserializationError::NotEnoughBytes)
+        );
+    }
+}
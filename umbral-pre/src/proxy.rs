@@ -0,0 +1,677 @@
+//! An asynchronous interface to the reencrypting proxies ("Ursulas"),
+//! enabled with the `proxy` feature.
+//!
+//! [`ReencryptionService`] is the interface applications implement on top of their network layer
+//! (a client for a remote Ursula), so that the rest of the application can be written
+//! and tested against it. [`InMemoryReencryptionService`] is the reference implementation,
+//! holding the key fragments locally, for tests.
+//!
+//! The trait is object-safe and does not depend on an async runtime:
+//! its method returns a boxed future (what `async fn` in a trait would desugar to).
+//!
+//! [`ReencryptionRequest`] and [`ReencryptionResponse`] are the messages exchanged with an Ursula,
+//! with a canonical encoding: the request is signed by the receiving party ("Bob")
+//! and carries a random nonce, so that the proxy can reject a replayed request
+//! (see [`ReplayGuard`]); the response is signed by the proxy and bound to the request.
+
+use alloc::boxed::Box;
+use alloc::collections::{BTreeMap, BTreeSet, VecDeque};
+use alloc::vec::Vec;
+use core::fmt;
+use core::future::Future;
+use core::pin::Pin;
+
+use generic_array::GenericArray;
+use rand_core::{CryptoRng, OsRng, RngCore};
+use typenum::{Unsigned, U32};
+
+use crate::capsule::Capsule;
+use crate::capsule_frag::{CapsuleFrag, CapsuleFragVerificationError, VerifiedCapsuleFrag};
+use crate::hashing_ds::{reencryption_request_message, reencryption_response_message};
+use crate::key_frag::{KeyFragID, VerifiedKeyFrag};
+use crate::keys::{PublicKey, Signature, Signer};
+use crate::metadata::Metadata;
+#[cfg(feature = "role-ursula")]
+use crate::pre::reencrypt;
+use crate::traits::{
+    DeserializableFromArray, DeserializationError, RepresentableAsArray, SerializableToArray,
+};
+
+/// Errors that can be returned by a [`ReencryptionService`].
+#[derive(Debug, PartialEq)]
+pub enum ReencryptionServiceError {
+    /// The service does not hold the key fragment with the requested ID.
+    UnknownKeyFrag,
+    /// The service could not be reached, or did not respond.
+    Unavailable,
+    /// The capsule fragment returned by the service failed verification.
+    InvalidCapsuleFrag(CapsuleFragVerificationError),
+}
+
+impl fmt::Display for ReencryptionServiceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnknownKeyFrag => write!(f, "unknown key fragment"),
+            Self::Unavailable => write!(f, "the reencryption service is unavailable"),
+            Self::InvalidCapsuleFrag(err) => {
+                write!(f, "the returned capsule fragment is invalid: {}", err)
+            }
+        }
+    }
+}
+
+/// The future returned by [`ReencryptionService::reencrypt()`].
+pub type ReencryptionFuture<'a> = Pin<
+    Box<dyn Future<Output = Result<VerifiedCapsuleFrag, ReencryptionServiceError>> + Send + 'a>,
+>;
+
+/// A service reencrypting capsules with the key fragments it holds.
+///
+/// An implementation talking to a remote Ursula is expected to verify the received
+/// capsule fragment with [`CapsuleFrag::verify()`](crate::CapsuleFrag::verify)
+/// (reporting a failure as [`ReencryptionServiceError::InvalidCapsuleFrag`])
+/// before returning it.
+pub trait ReencryptionService {
+    /// Reencrypts the capsule with the key fragment with the given ID,
+    /// attaching `metadata` to the capsule fragment.
+    fn reencrypt<'a>(
+        &'a self,
+        capsule: &'a Capsule,
+        kfrag_id: &'a KeyFragID,
+        metadata: Option<&'a Metadata>,
+    ) -> ReencryptionFuture<'a>;
+}
+
+/// A [`ReencryptionService`] holding the key fragments in memory
+/// and reencrypting immediately.
+#[derive(Clone, Debug, Default)]
+pub struct InMemoryReencryptionService {
+    kfrags: BTreeMap<GenericArray<u8, U32>, VerifiedKeyFrag>,
+}
+
+impl InMemoryReencryptionService {
+    /// Creates a service with no key fragments.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds the key fragment to the service (replacing the one with the same ID, if any).
+    pub fn insert(&mut self, kfrag: VerifiedKeyFrag) {
+        self.kfrags.insert(kfrag.id().to_array(), kfrag);
+    }
+
+    /// Removes the key fragment with the given ID from the service, returning it.
+    pub fn remove(&mut self, kfrag_id: &KeyFragID) -> Option<VerifiedKeyFrag> {
+        self.kfrags.remove(&kfrag_id.to_array())
+    }
+
+    /// Returns the number of the key fragments held by the service.
+    pub fn len(&self) -> usize {
+        self.kfrags.len()
+    }
+
+    /// Returns `true` if the service holds no key fragments.
+    pub fn is_empty(&self) -> bool {
+        self.kfrags.is_empty()
+    }
+}
+
+impl<'k> core::iter::FromIterator<&'k VerifiedKeyFrag> for InMemoryReencryptionService {
+    fn from_iter<I: IntoIterator<Item = &'k VerifiedKeyFrag>>(kfrags: I) -> Self {
+        let mut service = Self::new();
+        for kfrag in kfrags {
+            service.insert(kfrag.clone());
+        }
+        service
+    }
+}
+
+#[cfg(feature = "role-ursula")]
+impl ReencryptionService for InMemoryReencryptionService {
+    fn reencrypt<'a>(
+        &'a self,
+        capsule: &'a Capsule,
+        kfrag_id: &'a KeyFragID,
+        metadata: Option<&'a Metadata>,
+    ) -> ReencryptionFuture<'a> {
+        Box::pin(async move {
+            let kfrag = self
+                .kfrags
+                .get(&kfrag_id.to_array())
+                .ok_or(ReencryptionServiceError::UnknownKeyFrag)?;
+            Ok(reencrypt(capsule, kfrag, metadata))
+        })
+    }
+}
+
+const FORMAT_VERSION: u8 = 1;
+
+type Nonce = GenericArray<u8, U32>;
+
+fn take<T: DeserializableFromArray>(bytes: &mut &[u8]) -> Result<T, DeserializationError> {
+    let size = <T as RepresentableAsArray>::Size::USIZE;
+    if bytes.len() < size {
+        return Err(DeserializationError::NotEnoughBytes);
+    }
+    let (head, tail) = bytes.split_at(size);
+    *bytes = tail;
+    T::from_bytes(head)
+}
+
+fn take_version(bytes: &mut &[u8]) -> Result<(), DeserializationError> {
+    let (version, tail) = bytes
+        .split_first()
+        .ok_or(DeserializationError::NotEnoughBytes)?;
+    if *version != FORMAT_VERSION {
+        return Err(DeserializationError::ConstructionFailure);
+    }
+    *bytes = tail;
+    Ok(())
+}
+
+/// Errors that can be returned when verifying a [`ReencryptionRequest`]
+/// or a [`ReencryptionResponse`].
+#[derive(Debug, PartialEq)]
+pub enum ReencryptionMessageError {
+    /// The request was not signed by the given receiving party.
+    IncorrectRequestSignature,
+    /// A request with the same nonce has already been seen (see [`ReplayGuard`]).
+    ReplayedRequest,
+    /// The response was not signed by the given proxy for the given request.
+    IncorrectResponseSignature,
+    /// The response contains a capsule fragment created with another key fragment
+    /// than the one requested.
+    MismatchedKeyFrag,
+}
+
+impl fmt::Display for ReencryptionMessageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::IncorrectRequestSignature => {
+                write!(f, "incorrect reencryption request signature")
+            }
+            Self::ReplayedRequest => write!(f, "the reencryption request was replayed"),
+            Self::IncorrectResponseSignature => {
+                write!(f, "incorrect reencryption response signature")
+            }
+            Self::MismatchedKeyFrag => write!(
+                f,
+                "the capsule fragment was created with another key fragment than requested"
+            ),
+        }
+    }
+}
+
+/// A request of the receiving party to reencrypt a capsule with the given key fragment,
+/// signed by the receiving party.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ReencryptionRequest {
+    capsule: Capsule,
+    kfrag_id: KeyFragID,
+    metadata: Option<Metadata>,
+    nonce: Nonce,
+    bob_signature: Signature,
+}
+
+impl ReencryptionRequest {
+    /// Creates a request to reencrypt `capsule` with the key fragment `kfrag_id`,
+    /// attaching `metadata` to the capsule fragment, with a random nonce.
+    ///
+    /// Panics if the signature is refused by the usage policy of the signer,
+    /// or if the metadata is longer than `u32::MAX` bytes.
+    pub fn new(
+        bob_signer: &Signer,
+        capsule: &Capsule,
+        kfrag_id: &KeyFragID,
+        metadata: Option<&Metadata>,
+    ) -> Self {
+        Self::new_with_rng(&mut OsRng, bob_signer, capsule, kfrag_id, metadata)
+    }
+
+    /// Same as [`new()`](`Self::new`), but uses the given RNG instead of the default one.
+    pub fn new_with_rng(
+        rng: &mut (impl CryptoRng + RngCore),
+        bob_signer: &Signer,
+        capsule: &Capsule,
+        kfrag_id: &KeyFragID,
+        metadata: Option<&Metadata>,
+    ) -> Self {
+        let mut nonce = Nonce::default();
+        rng.fill_bytes(&mut nonce);
+        let metadata = metadata.cloned();
+        let body = Self::encode_body(capsule, kfrag_id, &metadata, &nonce);
+        let message = reencryption_request_message(&body);
+        Self {
+            capsule: *capsule,
+            kfrag_id: *kfrag_id,
+            metadata,
+            nonce,
+            bob_signature: bob_signer.sign_with_rng(rng, &message),
+        }
+    }
+
+    fn encode_body(
+        capsule: &Capsule,
+        kfrag_id: &KeyFragID,
+        metadata: &Option<Metadata>,
+        nonce: &Nonce,
+    ) -> Vec<u8> {
+        let mut out = Vec::new();
+        out.push(FORMAT_VERSION);
+        out.extend_from_slice(&capsule.to_array());
+        out.extend_from_slice(&kfrag_id.to_array());
+        out.extend_from_slice(nonce);
+        match metadata {
+            Some(metadata) => {
+                let metadata = metadata.to_bytes();
+                out.push(1);
+                out.extend_from_slice(&(metadata.len() as u32).to_be_bytes());
+                out.extend_from_slice(&metadata);
+            }
+            None => out.push(0),
+        }
+        out
+    }
+
+    fn message(&self) -> GenericArray<u8, U32> {
+        let body = Self::encode_body(&self.capsule, &self.kfrag_id, &self.metadata, &self.nonce);
+        reencryption_request_message(&body)
+    }
+
+    /// Returns the capsule to reencrypt.
+    pub fn capsule(&self) -> &Capsule {
+        &self.capsule
+    }
+
+    /// Returns the ID of the key fragment to reencrypt with.
+    pub fn kfrag_id(&self) -> &KeyFragID {
+        &self.kfrag_id
+    }
+
+    /// Returns the metadata to attach to the capsule fragment.
+    pub fn metadata(&self) -> Option<&Metadata> {
+        self.metadata.as_ref()
+    }
+
+    /// Returns the nonce of the request.
+    pub fn nonce(&self) -> &[u8] {
+        &self.nonce
+    }
+
+    /// Verifies that the request was signed by the receiving party with the given verifying key.
+    ///
+    /// Does not protect from replays by itself; a proxy should also pass the request
+    /// through a [`ReplayGuard`].
+    pub fn verify(&self, bob_verifying_pk: &PublicKey) -> Result<(), ReencryptionMessageError> {
+        if !self.bob_signature.verify(bob_verifying_pk, &self.message()) {
+            return Err(ReencryptionMessageError::IncorrectRequestSignature);
+        }
+        Ok(())
+    }
+
+    /// Returns the canonical encoding of the request.
+    pub fn to_bytes(&self) -> Box<[u8]> {
+        let mut out = Self::encode_body(&self.capsule, &self.kfrag_id, &self.metadata, &self.nonce);
+        out.extend_from_slice(&self.bob_signature.to_array());
+        out.into_boxed_slice()
+    }
+
+    /// Restores the request from its canonical encoding.
+    ///
+    /// Only canonical encodings are accepted, so that the result encodes back
+    /// to the same bytes.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, DeserializationError> {
+        let mut bytes = bytes;
+        take_version(&mut bytes)?;
+        let capsule = take::<Capsule>(&mut bytes)?;
+        let kfrag_id = take::<KeyFragID>(&mut bytes)?;
+        if bytes.len() < U32::USIZE {
+            return Err(DeserializationError::NotEnoughBytes);
+        }
+        let (nonce, mut bytes) = bytes.split_at(U32::USIZE);
+        let nonce = Nonce::clone_from_slice(nonce);
+
+        let (flag, tail) = bytes
+            .split_first()
+            .ok_or(DeserializationError::NotEnoughBytes)?;
+        bytes = tail;
+        let metadata = match flag {
+            0 => None,
+            1 => {
+                if bytes.len() < 4 {
+                    return Err(DeserializationError::NotEnoughBytes);
+                }
+                let mut len = [0u8; 4];
+                len.copy_from_slice(&bytes[..4]);
+                let len = u32::from_be_bytes(len) as usize;
+                bytes = &bytes[4..];
+                if bytes.len() < len {
+                    return Err(DeserializationError::NotEnoughBytes);
+                }
+                let (metadata, tail) = bytes.split_at(len);
+                bytes = tail;
+                Some(Metadata::from_bytes(metadata)?)
+            }
+            _ => return Err(DeserializationError::ConstructionFailure),
+        };
+
+        let bob_signature = take::<Signature>(&mut bytes)?;
+        if !bytes.is_empty() {
+            return Err(DeserializationError::TooManyBytes);
+        }
+        Ok(Self {
+            capsule,
+            kfrag_id,
+            metadata,
+            nonce,
+            bob_signature,
+        })
+    }
+}
+
+/// The capsule fragment created by a proxy for a [`ReencryptionRequest`],
+/// signed by the proxy.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ReencryptionResponse {
+    cfrag: CapsuleFrag,
+    ursula_signature: Signature,
+}
+
+impl ReencryptionResponse {
+    /// Creates a response to `request` with the capsule fragment created for it,
+    /// signed with the long-term `ursula_signer` of the proxy.
+    ///
+    /// Panics if the signature is refused by the usage policy of the signer.
+    pub fn new(
+        ursula_signer: &Signer,
+        request: &ReencryptionRequest,
+        cfrag: VerifiedCapsuleFrag,
+    ) -> Self {
+        Self::new_with_rng(&mut OsRng, ursula_signer, request, cfrag)
+    }
+
+    /// Same as [`new()`](`Self::new`), but uses the given RNG instead of the default one.
+    pub fn new_with_rng(
+        rng: &mut (impl CryptoRng + RngCore),
+        ursula_signer: &Signer,
+        request: &ReencryptionRequest,
+        cfrag: VerifiedCapsuleFrag,
+    ) -> Self {
+        let cfrag = cfrag.unverify();
+        let message = reencryption_response_message(&request.message(), &cfrag);
+        Self {
+            cfrag,
+            ursula_signature: ursula_signer.sign_with_rng(rng, &message),
+        }
+    }
+
+    /// Verifies that the response was signed by the proxy with the given verifying key
+    /// for the given request, and returns the capsule fragment.
+    ///
+    /// The capsule fragment must then be verified with
+    /// [`CapsuleFrag::verify()`](crate::CapsuleFrag::verify)
+    /// (with the metadata of the request).
+    pub fn verify(
+        &self,
+        ursula_verifying_pk: &PublicKey,
+        request: &ReencryptionRequest,
+    ) -> Result<&CapsuleFrag, ReencryptionMessageError> {
+        if self.cfrag.kfrag_id != request.kfrag_id {
+            return Err(ReencryptionMessageError::MismatchedKeyFrag);
+        }
+        let message = reencryption_response_message(&request.message(), &self.cfrag);
+        if !self.ursula_signature.verify(ursula_verifying_pk, &message) {
+            return Err(ReencryptionMessageError::IncorrectResponseSignature);
+        }
+        Ok(&self.cfrag)
+    }
+
+    /// Returns the canonical encoding of the response.
+    pub fn to_bytes(&self) -> Box<[u8]> {
+        let mut out = Vec::new();
+        out.push(FORMAT_VERSION);
+        out.extend_from_slice(&self.cfrag.to_array());
+        out.extend_from_slice(&self.ursula_signature.to_array());
+        out.into_boxed_slice()
+    }
+
+    /// Restores the response from its canonical encoding.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, DeserializationError> {
+        let mut bytes = bytes;
+        take_version(&mut bytes)?;
+        let cfrag = take::<CapsuleFrag>(&mut bytes)?;
+        let ursula_signature = take::<Signature>(&mut bytes)?;
+        if !bytes.is_empty() {
+            return Err(DeserializationError::TooManyBytes);
+        }
+        Ok(Self {
+            cfrag,
+            ursula_signature,
+        })
+    }
+}
+
+/// Remembers the nonces of the recent requests, so that a proxy can reject a replayed one.
+///
+/// Holds at most `capacity` nonces, forgetting the oldest ones first;
+/// a deployment that cannot bound the time a request is kept around by an attacker
+/// should also reject stale requests (e.g. by a timestamp in the [`Metadata`](crate::Metadata)).
+#[derive(Clone, Debug)]
+pub struct ReplayGuard {
+    capacity: usize,
+    seen: BTreeSet<Nonce>,
+    order: VecDeque<Nonce>,
+}
+
+impl ReplayGuard {
+    /// Creates a guard remembering at most `capacity` nonces.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            seen: BTreeSet::new(),
+            order: VecDeque::new(),
+        }
+    }
+
+    /// Records the nonce of the request, returning an error if it has been seen before.
+    ///
+    /// The request should be verified first with [`ReencryptionRequest::verify()`],
+    /// so that a forged request cannot take the place of a legitimate one.
+    pub fn check(&mut self, request: &ReencryptionRequest) -> Result<(), ReencryptionMessageError> {
+        if self.seen.contains(&request.nonce) {
+            return Err(ReencryptionMessageError::ReplayedRequest);
+        }
+        if self.capacity == 0 {
+            return Ok(());
+        }
+        if self.order.len() == self.capacity {
+            if let Some(oldest) = self.order.pop_front() {
+                self.seen.remove(&oldest);
+            }
+        }
+        self.seen.insert(request.nonce);
+        self.order.push_back(request.nonce);
+        Ok(())
+    }
+
+    /// Returns the number of the remembered nonces.
+    pub fn len(&self) -> usize {
+        self.order.len()
+    }
+
+    /// Returns `true` if no nonces are remembered.
+    pub fn is_empty(&self) -> bool {
+        self.order.is_empty()
+    }
+}
+
+#[cfg(all(
+    test,
+    feature = "role-alice",
+    feature = "role-ursula",
+    feature = "role-bob"
+))]
+mod tests {
+
+    use alloc::boxed::Box;
+    use alloc::sync::Arc;
+    use alloc::vec::Vec;
+    use core::future::Future;
+    use core::pin::Pin;
+    use core::task::{Context, Poll};
+    use std::task::Wake;
+
+    use super::{
+        InMemoryReencryptionService, ReencryptionMessageError, ReencryptionRequest,
+        ReencryptionResponse, ReencryptionService, ReencryptionServiceError, ReplayGuard,
+    };
+    use crate::{
+        decapsulate_reencrypted, encapsulate, generate_kfrags, reencrypt, Metadata, PublicKey,
+        SecretKey, Signer,
+    };
+
+    struct NoopWaker;
+
+    impl Wake for NoopWaker {
+        fn wake(self: Arc<Self>) {}
+    }
+
+    // A minimal executor, enough for the futures that do not wait on anything.
+    fn block_on<F: Future>(future: F) -> F::Output {
+        let mut future = Box::pin(future);
+        let waker = Arc::new(NoopWaker).into();
+        let mut context = Context::from_waker(&waker);
+        loop {
+            if let Poll::Ready(output) = Pin::as_mut(&mut future).poll(&mut context) {
+                return output;
+            }
+        }
+    }
+
+    #[test]
+    fn test_in_memory_service() {
+        let delegating_sk = SecretKey::random();
+        let delegating_pk = PublicKey::from_secret_key(&delegating_sk);
+        let receiving_sk = SecretKey::random();
+        let receiving_pk = PublicKey::from_secret_key(&receiving_sk);
+        let signer = Signer::new(&SecretKey::random());
+
+        let (capsule, key_seed) = encapsulate(&delegating_pk);
+        let kfrags = generate_kfrags(&delegating_sk, &receiving_pk, &signer, 2, 3, true, true);
+
+        let mut service: InMemoryReencryptionService = kfrags[..2].iter().collect();
+        assert_eq!(service.len(), 2);
+
+        // Used through a trait object, as an application would.
+        let vcfrags = {
+            let service: &dyn ReencryptionService = &service;
+            kfrags[..2]
+                .iter()
+                .map(|kfrag| block_on(service.reencrypt(&capsule, kfrag.id(), None)).unwrap())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            decapsulate_reencrypted(&receiving_sk, &delegating_pk, &capsule, &vcfrags),
+            Ok(key_seed)
+        );
+
+        assert_eq!(
+            block_on(service.reencrypt(&capsule, kfrags[2].id(), None)),
+            Err(ReencryptionServiceError::UnknownKeyFrag)
+        );
+        assert!(service.remove(kfrags[0].id()).is_some());
+        assert_eq!(
+            block_on(service.reencrypt(&capsule, kfrags[0].id(), None)),
+            Err(ReencryptionServiceError::UnknownKeyFrag)
+        );
+    }
+
+    #[test]
+    fn test_request_response() {
+        let delegating_sk = SecretKey::random();
+        let delegating_pk = PublicKey::from_secret_key(&delegating_sk);
+        let receiving_sk = SecretKey::random();
+        let receiving_pk = PublicKey::from_secret_key(&receiving_sk);
+        let signer = Signer::new(&SecretKey::random());
+        let verifying_pk = signer.verifying_key();
+        let bob_signer = Signer::new(&receiving_sk);
+        let ursula_signer = Signer::new(&SecretKey::random());
+
+        let (capsule, _key_seed) = encapsulate(&delegating_pk);
+        let kfrags = generate_kfrags(&delegating_sk, &receiving_pk, &signer, 2, 3, true, true);
+        let metadata = &Metadata::new().with_extra(b"metadata");
+
+        // Bob
+        let request =
+            ReencryptionRequest::new(&bob_signer, &capsule, kfrags[0].id(), Some(metadata));
+        let request_bytes = request.to_bytes();
+
+        // Ursula
+        let request = ReencryptionRequest::from_bytes(&request_bytes).unwrap();
+        assert_eq!(request.to_bytes(), request_bytes);
+        assert_eq!(request.verify(&receiving_pk), Ok(()));
+        assert_eq!(
+            request.verify(&verifying_pk),
+            Err(ReencryptionMessageError::IncorrectRequestSignature)
+        );
+
+        let mut guard = ReplayGuard::new(2);
+        assert_eq!(guard.check(&request), Ok(()));
+        assert_eq!(
+            guard.check(&request),
+            Err(ReencryptionMessageError::ReplayedRequest)
+        );
+
+        let vcfrag = reencrypt(request.capsule(), &kfrags[0], request.metadata());
+        let response = ReencryptionResponse::new(&ursula_signer, &request, vcfrag);
+        let response = ReencryptionResponse::from_bytes(&response.to_bytes()).unwrap();
+
+        // Bob
+        let cfrag = response
+            .verify(&ursula_signer.verifying_key(), &request)
+            .unwrap();
+        assert!(cfrag
+            .clone()
+            .verify(
+                &capsule,
+                &verifying_pk,
+                &delegating_pk,
+                &receiving_pk,
+                Some(metadata)
+            )
+            .is_ok());
+
+        // The response is bound to the request it answers
+        let other_request =
+            ReencryptionRequest::new(&bob_signer, &capsule, kfrags[0].id(), Some(metadata));
+        assert_eq!(
+            response.verify(&ursula_signer.verifying_key(), &other_request),
+            Err(ReencryptionMessageError::IncorrectResponseSignature)
+        );
+        let other_request =
+            ReencryptionRequest::new(&bob_signer, &capsule, kfrags[1].id(), Some(metadata));
+        assert_eq!(
+            response.verify(&ursula_signer.verifying_key(), &other_request),
+            Err(ReencryptionMessageError::MismatchedKeyFrag)
+        );
+
+        // Tampered metadata invalidates the signature
+        let mut tampered = request_bytes.to_vec();
+        let len = tampered.len();
+        tampered[len - 65] ^= 1;
+        let tampered = ReencryptionRequest::from_bytes(&tampered).unwrap();
+        assert_eq!(
+            tampered.verify(&receiving_pk),
+            Err(ReencryptionMessageError::IncorrectRequestSignature)
+        );
+
+        // The oldest nonces are forgotten when the guard is full
+        let requests: Vec<_> = (0..2)
+            .map(|_| ReencryptionRequest::new(&bob_signer, &capsule, kfrags[0].id(), None))
+            .collect();
+        assert_eq!(guard.check(&requests[0]), Ok(()));
+        assert_eq!(guard.check(&requests[1]), Ok(()));
+        assert_eq!(guard.len(), 2);
+        assert_eq!(guard.check(&request), Ok(()));
+    }
+}
@@ -0,0 +1,293 @@
+//! Chunked encoding of serialized objects for transfer via QR codes.
+//!
+//! A payload (e.g. the result of
+//! [`to_versioned_bytes()`](crate::VersionedSerialization::to_versioned_bytes)
+//! for a key fragment or a capsule) is split into chunks,
+//! each prefixed with a header and encoded in Base45 ([RFC 9285]),
+//! which fits the alphanumeric mode of QR codes.
+//! The chunks can be scanned in any order and reassembled with [`ChunkAssembler`].
+//!
+//! The header of a chunk (before the encoding) is:
+//!
+//! | Field                              | Size |
+//! | ---------------------------------- | ---- |
+//! | Format version                     | 1    |
+//! | Chunk index                        | 1    |
+//! | Number of chunks                   | 1    |
+//! | Digest of the payload              | 8    |
+//!
+//! The digest ties the chunks of one payload together and detects corruption.
+//!
+//! [RFC 9285]: https://www.rfc-editor.org/rfc/rfc9285
+
+use alloc::boxed::Box;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt;
+
+use generic_array::GenericArray;
+use typenum::U8;
+
+use crate::hashing_ds::hash_to_chunked_payload_digest;
+
+/// The default number of payload bytes in a chunk.
+/// Results in chunks of about 200 characters, which fit a version 8 QR code
+/// with the medium error correction level.
+pub const DEFAULT_CHUNK_SIZE: usize = 128;
+
+const FORMAT_VERSION: u8 = 1;
+const HEADER_SIZE: usize = 11;
+const MAX_CHUNKS: usize = 255;
+
+const BASE45_ALPHABET: &[u8; 45] = b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZ $%*+-./:";
+
+/// Errors that can happen when encoding or reassembling chunks.
+#[derive(Debug, PartialEq)]
+pub enum ChunkError {
+    /// The payload needs more than 255 chunks of the given size (or the size is zero).
+    PayloadTooLarge,
+    /// The chunk is not valid Base45.
+    InvalidEncoding,
+    /// The chunk header is malformed or has an unsupported version.
+    InvalidHeader,
+    /// The chunk belongs to a different payload than the previous ones.
+    MismatchedChunk,
+    /// Not all the chunks have been added yet.
+    MissingChunks,
+    /// The reassembled payload does not match the digest in the headers.
+    DigestMismatch,
+}
+
+impl fmt::Display for ChunkError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::PayloadTooLarge => write!(f, "payload does not fit in 255 chunks"),
+            Self::InvalidEncoding => write!(f, "invalid Base45 encoding"),
+            Self::InvalidHeader => write!(f, "invalid chunk header"),
+            Self::MismatchedChunk => write!(f, "chunk belongs to a different payload"),
+            Self::MissingChunks => write!(f, "some chunks are missing"),
+            Self::DigestMismatch => write!(f, "payload digest mismatch"),
+        }
+    }
+}
+
+fn base45_encode(data: &[u8]) -> String {
+    let mut result = String::with_capacity(data.len() / 2 * 3 + 2);
+    for pair in data.chunks(2) {
+        let (mut value, digits) = match pair {
+            [a, b] => ((*a as usize) * 256 + (*b as usize), 3),
+            [a] => (*a as usize, 2),
+            _ => unreachable!(),
+        };
+        for _ in 0..digits {
+            result.push(BASE45_ALPHABET[value % 45] as char);
+            value /= 45;
+        }
+    }
+    result
+}
+
+fn base45_decode(s: &str) -> Option<Vec<u8>> {
+    let values = s
+        .bytes()
+        .map(|c| BASE45_ALPHABET.iter().position(|a| *a == c))
+        .collect::<Option<Vec<_>>>()?;
+    let mut result = Vec::with_capacity(values.len() / 3 * 2 + 1);
+    for group in values.chunks(3) {
+        match group {
+            [c, d, e] => {
+                let value = c + d * 45 + e * 45 * 45;
+                if value > 0xffff {
+                    return None;
+                }
+                result.push((value >> 8) as u8);
+                result.push(value as u8);
+            }
+            [c, d] => {
+                let value = c + d * 45;
+                if value > 0xff {
+                    return None;
+                }
+                result.push(value as u8);
+            }
+            _ => return None,
+        }
+    }
+    Some(result)
+}
+
+/// Splits the payload into Base45-encoded chunks
+/// with at most `chunk_size` bytes of the payload in each.
+pub fn encode_chunks(payload: &[u8], chunk_size: usize) -> Result<Vec<String>, ChunkError> {
+    if chunk_size == 0 {
+        return Err(ChunkError::PayloadTooLarge);
+    }
+    // An empty payload still takes one chunk.
+    let num_chunks = core::cmp::max(payload.chunks(chunk_size).len(), 1);
+    if num_chunks > MAX_CHUNKS {
+        return Err(ChunkError::PayloadTooLarge);
+    }
+
+    let digest = hash_to_chunked_payload_digest(payload);
+    let chunks = (0..num_chunks)
+        .map(|index| {
+            let start = index * chunk_size;
+            let end = core::cmp::min(start + chunk_size, payload.len());
+            let mut chunk = Vec::with_capacity(HEADER_SIZE + end - start);
+            chunk.push(FORMAT_VERSION);
+            chunk.push(index as u8);
+            chunk.push(num_chunks as u8);
+            chunk.extend_from_slice(&digest);
+            chunk.extend_from_slice(&payload[start..end]);
+            base45_encode(&chunk)
+        })
+        .collect();
+    Ok(chunks)
+}
+
+/// Collects the chunks produced by [`encode_chunks()`] (in any order)
+/// and reassembles the payload.
+#[derive(Debug, Default)]
+pub struct ChunkAssembler {
+    digest: Option<GenericArray<u8, U8>>,
+    chunks: Vec<Option<Box<[u8]>>>,
+}
+
+impl ChunkAssembler {
+    /// Creates an empty assembler.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a chunk. Adding the same chunk again has no effect.
+    pub fn add(&mut self, chunk: &str) -> Result<(), ChunkError> {
+        let bytes = base45_decode(chunk).ok_or(ChunkError::InvalidEncoding)?;
+        if bytes.len() < HEADER_SIZE || bytes[0] != FORMAT_VERSION {
+            return Err(ChunkError::InvalidHeader);
+        }
+        let index = bytes[1] as usize;
+        let num_chunks = bytes[2] as usize;
+        if num_chunks == 0 || index >= num_chunks {
+            return Err(ChunkError::InvalidHeader);
+        }
+        let digest = GenericArray::<u8, U8>::clone_from_slice(&bytes[3..HEADER_SIZE]);
+
+        match &self.digest {
+            Some(expected) => {
+                if *expected != digest || self.chunks.len() != num_chunks {
+                    return Err(ChunkError::MismatchedChunk);
+                }
+            }
+            None => {
+                self.digest = Some(digest);
+                self.chunks.resize(num_chunks, None);
+            }
+        }
+
+        self.chunks[index] = Some(bytes[HEADER_SIZE..].into());
+        Ok(())
+    }
+
+    /// Returns the number of chunks added so far and the total number of chunks
+    /// (or zero if no chunks were added yet).
+    pub fn progress(&self) -> (usize, usize) {
+        let added = self.chunks.iter().filter(|chunk| chunk.is_some()).count();
+        (added, self.chunks.len())
+    }
+
+    /// Returns `true` if all the chunks have been added.
+    pub fn is_complete(&self) -> bool {
+        let (added, total) = self.progress();
+        total > 0 && added == total
+    }
+
+    /// Reassembles the payload and checks it against the digest in the headers.
+    pub fn finish(&self) -> Result<Box<[u8]>, ChunkError> {
+        if !self.is_complete() {
+            return Err(ChunkError::MissingChunks);
+        }
+        let mut payload = Vec::new();
+        for chunk in self.chunks.iter().flatten() {
+            payload.extend_from_slice(chunk);
+        }
+        if self.digest != Some(hash_to_chunked_payload_digest(&payload)) {
+            return Err(ChunkError::DigestMismatch);
+        }
+        Ok(payload.into_boxed_slice())
+    }
+}
+
+/// Reassembles the payload from the complete set of chunks (in any order).
+pub fn decode_chunks<S: AsRef<str>>(chunks: &[S]) -> Result<Box<[u8]>, ChunkError> {
+    let mut assembler = ChunkAssembler::new();
+    for chunk in chunks {
+        assembler.add(chunk.as_ref())?;
+    }
+    assembler.finish()
+}
+
+#[cfg(all(test, feature = "role-alice"))]
+mod tests {
+
+    use alloc::string::String;
+    use alloc::vec::Vec;
+
+    use super::{
+        base45_decode, base45_encode, decode_chunks, encode_chunks, ChunkAssembler, ChunkError,
+        DEFAULT_CHUNK_SIZE,
+    };
+    use crate::{
+        generate_kfrags, KeyFrag, PublicKey, SecretKey, SerializableToArray, Signer,
+        VersionedSerialization,
+    };
+
+    #[test]
+    fn test_chunks() {
+        // Examples from RFC 9285
+        assert_eq!(base45_encode(b"AB"), "BB8");
+        assert_eq!(base45_encode(b"Hello!!"), "%69 VD92EX0");
+        assert_eq!(base45_decode("QED8WEX0").unwrap(), b"ietf!");
+        assert_eq!(base45_decode("GGW"), None);
+
+        let delegating_sk = SecretKey::random();
+        let receiving_pk = PublicKey::from_secret_key(&SecretKey::random());
+        let signer = Signer::new(&SecretKey::random());
+        let kfrags = generate_kfrags(&delegating_sk, &receiving_pk, &signer, 2, 3, true, true);
+        let payload = kfrags[0].to_versioned_bytes();
+
+        let chunks = encode_chunks(&payload, DEFAULT_CHUNK_SIZE).unwrap();
+        assert_eq!(chunks.len(), 3);
+        assert!(chunks.iter().all(|chunk| chunk.len() <= 210));
+
+        // Chunks can come in any order, and repeatedly.
+        let mut assembler = ChunkAssembler::new();
+        for index in [2, 0, 2].iter() {
+            assembler.add(&chunks[*index]).unwrap();
+        }
+        assert_eq!(assembler.progress(), (2, 3));
+        assert_eq!(assembler.finish(), Err(ChunkError::MissingChunks));
+        assembler.add(&chunks[1]).unwrap();
+        let kfrag = KeyFrag::from_versioned_bytes(assembler.finish().unwrap()).unwrap();
+        assert_eq!(kfrag.to_array(), kfrags[0].to_array());
+
+        let other_payload = kfrags[1].to_versioned_bytes();
+        let other_chunks = encode_chunks(&other_payload, DEFAULT_CHUNK_SIZE).unwrap();
+        assert_eq!(
+            assembler.add(&other_chunks[0]),
+            Err(ChunkError::MismatchedChunk)
+        );
+
+        let mut corrupted_chunks: Vec<String> = chunks.clone();
+        let last = corrupted_chunks[2].pop().unwrap();
+        corrupted_chunks[2].push(if last == '0' { '1' } else { '0' });
+        assert!(decode_chunks(&corrupted_chunks).is_err());
+
+        assert!(decode_chunks(&encode_chunks(b"", 10).unwrap())
+            .unwrap()
+            .is_empty());
+        assert_eq!(
+            encode_chunks(&[0; 256], 1),
+            Err(ChunkError::PayloadTooLarge)
+        );
+    }
+}
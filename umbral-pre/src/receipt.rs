@@ -0,0 +1,245 @@
+//! Signed receipts of reencryption, issued by the proxies.
+//!
+//! A [`ReencryptionReceipt`] is a statement of a proxy, signed with its own long-term key,
+//! that it has reencrypted a given capsule with a given key fragment,
+//! producing a given capsule fragment at a given time.
+//! It can be kept by the receiving party or handed to an auditor
+//! (e.g. as a proof of the service for billing or for a dispute),
+//! and verified with the verifying key of the proxy.
+//!
+//! The receipt only records what the proxy claims to have done;
+//! the correctness of the fragment itself is checked with
+//! [`CapsuleFrag::verify()`](crate::CapsuleFrag::verify).
+
+use core::fmt;
+
+use generic_array::sequence::{Concat, Split};
+use generic_array::GenericArray;
+use rand_core::{CryptoRng, OsRng, RngCore};
+use typenum::{op, U32, U8};
+
+use crate::capsule::Capsule;
+use crate::capsule_frag::CapsuleFrag;
+use crate::hashing_ds::{
+    hash_to_capsule_fingerprint, hash_to_cfrag_digest, reencryption_receipt_message,
+};
+use crate::key_frag::KeyFragID;
+use crate::keys::{PublicKey, Signature, Signer};
+use crate::traits::{
+    DeserializableFromArray, DeserializationError, RepresentableAsArray, SerializableToArray,
+};
+
+/// Possible errors that can be returned by [`ReencryptionReceipt::verify`].
+#[derive(Debug, PartialEq)]
+pub enum ReencryptionReceiptError {
+    /// The receipt was issued for a different capsule.
+    MismatchedCapsule,
+    /// The receipt was issued for a different capsule fragment.
+    MismatchedCapsuleFrag,
+    /// The receipt was not signed by the given proxy.
+    IncorrectSignature,
+}
+
+impl fmt::Display for ReencryptionReceiptError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::MismatchedCapsule => write!(f, "the receipt was issued for another capsule"),
+            Self::MismatchedCapsuleFrag => {
+                write!(f, "the receipt was issued for another capsule fragment")
+            }
+            Self::IncorrectSignature => write!(f, "incorrect reencryption receipt signature"),
+        }
+    }
+}
+
+/// A statement of a proxy that it has reencrypted a capsule,
+/// signed with the proxy's own key.
+///
+/// Created by [`reencrypt_with_receipt()`](crate::reencrypt_with_receipt)
+/// or [`ReencryptionReceipt::new()`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct ReencryptionReceipt {
+    capsule_fingerprint: GenericArray<u8, DigestSize>,
+    kfrag_id: KeyFragID,
+    cfrag_digest: GenericArray<u8, DigestSize>,
+    timestamp: u64,
+    signature: Signature,
+}
+
+type DigestSize = U32;
+type TimestampSize = U8;
+type KeyFragIDSize = <KeyFragID as RepresentableAsArray>::Size;
+type SignatureSize = <Signature as RepresentableAsArray>::Size;
+
+impl RepresentableAsArray for ReencryptionReceipt {
+    type Size = op!(DigestSize + KeyFragIDSize + DigestSize + TimestampSize + SignatureSize);
+}
+
+impl SerializableToArray for ReencryptionReceipt {
+    fn to_array(&self) -> GenericArray<u8, Self::Size> {
+        self.capsule_fingerprint
+            .concat(self.kfrag_id.to_array())
+            .concat(self.cfrag_digest)
+            .concat(GenericArray::<u8, TimestampSize>::from(
+                self.timestamp.to_be_bytes(),
+            ))
+            .concat(self.signature.to_array())
+    }
+}
+
+impl DeserializableFromArray for ReencryptionReceipt {
+    fn from_array(arr: &GenericArray<u8, Self::Size>) -> Result<Self, DeserializationError> {
+        let (capsule_fingerprint, rest): (GenericArray<u8, DigestSize>, GenericArray<u8, _>) =
+            (*arr).split();
+        let (kfrag_id, rest) = KeyFragID::take(rest)?;
+        let (cfrag_digest, rest): (GenericArray<u8, DigestSize>, GenericArray<u8, _>) =
+            rest.split();
+        let (timestamp, rest): (GenericArray<u8, TimestampSize>, GenericArray<u8, _>) =
+            rest.split();
+        let signature = Signature::take_last(rest)?;
+        let mut timestamp_bytes = [0u8; 8];
+        timestamp_bytes.copy_from_slice(&timestamp);
+        Ok(Self {
+            capsule_fingerprint,
+            kfrag_id,
+            cfrag_digest,
+            timestamp: u64::from_be_bytes(timestamp_bytes),
+            signature,
+        })
+    }
+}
+
+impl ReencryptionReceipt {
+    /// Creates a receipt for the reencryption of `capsule` into `cfrag` at the given time,
+    /// signed with the long-term `signer` of the proxy.
+    ///
+    /// The timestamp is only recorded and signed;
+    /// its unit (e.g. seconds since the Unix epoch) is up to the application.
+    ///
+    /// Panics if the signature is refused by the usage policy of the signer.
+    pub fn new(signer: &Signer, capsule: &Capsule, cfrag: &CapsuleFrag, timestamp: u64) -> Self {
+        Self::new_with_rng(&mut OsRng, signer, capsule, cfrag, timestamp)
+    }
+
+    /// Same as [`new()`](`Self::new`), but uses the given RNG instead of the default one.
+    pub fn new_with_rng(
+        rng: &mut (impl CryptoRng + RngCore),
+        signer: &Signer,
+        capsule: &Capsule,
+        cfrag: &CapsuleFrag,
+        timestamp: u64,
+    ) -> Self {
+        let capsule_fingerprint = hash_to_capsule_fingerprint(capsule);
+        let kfrag_id = cfrag.kfrag_id;
+        let cfrag_digest = hash_to_cfrag_digest(cfrag);
+        let message =
+            reencryption_receipt_message(&capsule_fingerprint, &kfrag_id, &cfrag_digest, timestamp);
+        Self {
+            capsule_fingerprint,
+            kfrag_id,
+            cfrag_digest,
+            timestamp,
+            signature: signer.sign_with_rng(rng, &message),
+        }
+    }
+
+    /// Returns the identifier of the key fragment used for the reencryption.
+    pub fn kfrag_id(&self) -> &KeyFragID {
+        &self.kfrag_id
+    }
+
+    /// Returns the time of the reencryption, as given by the proxy.
+    pub fn timestamp(&self) -> u64 {
+        self.timestamp
+    }
+
+    /// Verifies that the receipt was issued for the given capsule and capsule fragment
+    /// and signed by the proxy with the given verifying key.
+    pub fn verify(
+        &self,
+        proxy_verifying_pk: &PublicKey,
+        capsule: &Capsule,
+        cfrag: &CapsuleFrag,
+    ) -> Result<(), ReencryptionReceiptError> {
+        if hash_to_capsule_fingerprint(capsule) != self.capsule_fingerprint {
+            return Err(ReencryptionReceiptError::MismatchedCapsule);
+        }
+        if cfrag.kfrag_id != self.kfrag_id || hash_to_cfrag_digest(cfrag) != self.cfrag_digest {
+            return Err(ReencryptionReceiptError::MismatchedCapsuleFrag);
+        }
+        let message = reencryption_receipt_message(
+            &self.capsule_fingerprint,
+            &self.kfrag_id,
+            &self.cfrag_digest,
+            self.timestamp,
+        );
+        if !self.signature.verify(proxy_verifying_pk, &message) {
+            return Err(ReencryptionReceiptError::IncorrectSignature);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(all(test, feature = "role-alice", feature = "role-ursula"))]
+mod tests {
+
+    use super::{ReencryptionReceipt, ReencryptionReceiptError};
+    use crate::{
+        encapsulate, generate_kfrags, reencrypt, reencrypt_with_receipt, DeserializableFromArray,
+        PublicKey, SecretKey, SerializableToArray, Signer,
+    };
+
+    #[test]
+    fn test_reencryption_receipt() {
+        let delegating_sk = SecretKey::random();
+        let delegating_pk = PublicKey::from_secret_key(&delegating_sk);
+        let signer = Signer::new(&SecretKey::random());
+        let receiving_pk = PublicKey::from_secret_key(&SecretKey::random());
+
+        let (capsule, _key_seed) = encapsulate(&delegating_pk);
+        let (other_capsule, _key_seed) = encapsulate(&delegating_pk);
+        let kfrags = generate_kfrags(&delegating_sk, &receiving_pk, &signer, 2, 3, true, true);
+
+        let ursula_signer = Signer::new(&SecretKey::random());
+        let ursula_pk = ursula_signer.verifying_key();
+        let (verified_cfrag, receipt) =
+            reencrypt_with_receipt(&capsule, &kfrags[0], None, &ursula_signer, 1234);
+        let cfrag = verified_cfrag.unverify();
+
+        assert_eq!(receipt.kfrag_id(), kfrags[0].id());
+        assert_eq!(receipt.timestamp(), 1234);
+        assert_eq!(receipt.verify(&ursula_pk, &capsule, &cfrag), Ok(()));
+
+        let receipt_back = ReencryptionReceipt::from_array(&receipt.to_array()).unwrap();
+        assert_eq!(receipt_back, receipt);
+
+        // Wrong proxy
+        assert_eq!(
+            receipt.verify(&signer.verifying_key(), &capsule, &cfrag),
+            Err(ReencryptionReceiptError::IncorrectSignature)
+        );
+
+        // Wrong capsule
+        assert_eq!(
+            receipt.verify(&ursula_pk, &other_capsule, &cfrag),
+            Err(ReencryptionReceiptError::MismatchedCapsule)
+        );
+
+        // Another fragment of the same capsule
+        let other_cfrag = reencrypt(&capsule, &kfrags[1], None).unverify();
+        assert_eq!(
+            receipt.verify(&ursula_pk, &capsule, &other_cfrag),
+            Err(ReencryptionReceiptError::MismatchedCapsuleFrag)
+        );
+
+        // A receipt with a changed timestamp is not valid
+        let mut bytes = receipt.to_array();
+        bytes[32 * 3 + 7] ^= 1;
+        let forged = ReencryptionReceipt::from_array(&bytes).unwrap();
+        assert_eq!(forged.timestamp(), 1235);
+        assert_eq!(
+            forged.verify(&ursula_pk, &capsule, &cfrag),
+            Err(ReencryptionReceiptError::IncorrectSignature)
+        );
+    }
+}
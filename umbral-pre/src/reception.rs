@@ -0,0 +1,240 @@
+//! Single-use reception keys, pre-published by the receiving party.
+//!
+//! The receiving party generates a batch of [`ReceptionKey`] objects,
+//! each signed with its long-term signing key, and publishes them.
+//! The delegating party verifies one of them and consumes it to create the key fragments
+//! (see [`generate_kfrags_for_reception_key()`](crate::generate_kfrags_for_reception_key)),
+//! so that no interaction with the receiving party is needed.
+//!
+//! The reception key takes the place of the receiving key in the key exchange
+//! with the precursor of the fragments, so the fragments can only be used with its secret key.
+//! Once the receiving party deletes it, the capsules reencrypted under the policy
+//! cannot be opened even if its long-term keys are compromised (forward secrecy per policy).
+//!
+//! The library cannot ensure that a reception key is used only once;
+//! the delegating party must not consume the same key for several policies.
+//!
+//! The reception key is not used as the precursor of the fragments:
+//! the delegating party must know the secret of the precursor to compute
+//! the shared secret with the receiving key, so the precursor is still generated by it.
+//! Accordingly, the key fragments are created and verified as usual,
+//! with the reception key as the receiving key; the only additional check
+//! is the signature of the reception key ([`ReceptionKey::verify()`]),
+//! which the delegating party performs before consuming it.
+
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use core::fmt;
+
+use generic_array::sequence::Concat;
+use generic_array::GenericArray;
+use rand_core::{CryptoRng, OsRng, RngCore};
+use typenum::op;
+
+use crate::hashing_ds::reception_key_signature_message;
+use crate::keys::{PublicKey, SecretKey, Signature, Signer};
+use crate::traits::{
+    DeserializableFromArray, DeserializationError, RepresentableAsArray, SerializableToArray,
+};
+
+/// Possible errors that can be returned by [`ReceptionKey::verify`].
+#[derive(Debug, PartialEq)]
+pub enum ReceptionKeyVerificationError {
+    /// The reception key was not signed by the given receiving party.
+    IncorrectSignature,
+}
+
+impl fmt::Display for ReceptionKeyVerificationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::IncorrectSignature => write!(f, "incorrect reception key signature"),
+        }
+    }
+}
+
+/// A single-use public key of the receiving party, signed with its long-term key.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ReceptionKey {
+    public_key: PublicKey,
+    signature: Signature,
+}
+
+type PublicKeySize = <PublicKey as RepresentableAsArray>::Size;
+type SignatureSize = <Signature as RepresentableAsArray>::Size;
+
+impl RepresentableAsArray for ReceptionKey {
+    type Size = op!(PublicKeySize + SignatureSize);
+}
+
+impl SerializableToArray for ReceptionKey {
+    fn to_array(&self) -> GenericArray<u8, Self::Size> {
+        self.public_key.to_array().concat(self.signature.to_array())
+    }
+}
+
+impl DeserializableFromArray for ReceptionKey {
+    fn from_array(arr: &GenericArray<u8, Self::Size>) -> Result<Self, DeserializationError> {
+        let (public_key, rest) = PublicKey::take(*arr)?;
+        let signature = Signature::take_last(rest)?;
+        Ok(Self {
+            public_key,
+            signature,
+        })
+    }
+}
+
+impl ReceptionKey {
+    /// Creates a reception key for the given secret key,
+    /// signed with the long-term `signer` of the receiving party.
+    ///
+    /// Panics if the signature is refused by the usage policy of the signer.
+    pub fn new(reception_sk: &SecretKey, signer: &Signer) -> Self {
+        Self::new_with_rng(&mut OsRng, reception_sk, signer)
+    }
+
+    /// Same as [`new()`](`Self::new`), but uses the given RNG instead of the default one.
+    pub fn new_with_rng(
+        rng: &mut (impl CryptoRng + RngCore),
+        reception_sk: &SecretKey,
+        signer: &Signer,
+    ) -> Self {
+        let public_key = PublicKey::from_secret_key(reception_sk);
+        let message = reception_key_signature_message(&public_key);
+        Self {
+            public_key,
+            signature: signer.sign_with_rng(rng, &message),
+        }
+    }
+
+    /// Verifies that the reception key was signed by the receiving party
+    /// with the given long-term verifying key.
+    pub fn verify(
+        &self,
+        receiving_verifying_pk: &PublicKey,
+    ) -> Result<VerifiedReceptionKey, ReceptionKeyVerificationError> {
+        let message = reception_key_signature_message(&self.public_key);
+        if !self.signature.verify(receiving_verifying_pk, &message) {
+            return Err(ReceptionKeyVerificationError::IncorrectSignature);
+        }
+        Ok(VerifiedReceptionKey(self.clone()))
+    }
+}
+
+/// Verified reception key, good for key fragment generation.
+#[derive(Clone, Debug, PartialEq)]
+pub struct VerifiedReceptionKey(ReceptionKey);
+
+impl VerifiedReceptionKey {
+    /// Returns the public key to be used as the receiving key of the policy
+    /// (e.g. in [`KeyFrag::verify()`](crate::KeyFrag::verify)
+    /// and [`CapsuleFrag::verify()`](crate::CapsuleFrag::verify)).
+    pub fn public_key(&self) -> &PublicKey {
+        &self.0.public_key
+    }
+
+    /// Returns the unverified reception key, e.g. to send it over to another party.
+    pub fn unverify(self) -> ReceptionKey {
+        self.0
+    }
+}
+
+/// Generates `num_keys` reception keys signed with the long-term `signer` of the receiving party.
+/// Returns the secret keys (to be stored until the corresponding policies expire)
+/// along with the reception keys (to be published).
+///
+/// Panics if the signatures are refused by the usage policy of the signer.
+pub fn generate_reception_keys(
+    signer: &Signer,
+    num_keys: usize,
+) -> Box<[(SecretKey, ReceptionKey)]> {
+    generate_reception_keys_with_rng(&mut OsRng, signer, num_keys)
+}
+
+/// Same as [`generate_reception_keys()`], but uses the given RNG instead of the default one.
+pub fn generate_reception_keys_with_rng(
+    rng: &mut (impl CryptoRng + RngCore),
+    signer: &Signer,
+    num_keys: usize,
+) -> Box<[(SecretKey, ReceptionKey)]> {
+    let mut result = Vec::with_capacity(num_keys);
+    for _ in 0..num_keys {
+        let reception_sk = SecretKey::random_with_rng(rng);
+        let reception_key = ReceptionKey::new_with_rng(rng, &reception_sk, signer);
+        result.push((reception_sk, reception_key));
+    }
+    result.into_boxed_slice()
+}
+
+#[cfg(all(
+    test,
+    feature = "role-alice",
+    feature = "role-ursula",
+    feature = "role-bob"
+))]
+mod tests {
+
+    use alloc::vec::Vec;
+
+    use super::{generate_reception_keys, ReceptionKey, ReceptionKeyVerificationError};
+    use crate::{
+        decapsulate_reencrypted, encapsulate, generate_kfrags_for_reception_key, reencrypt,
+        DeserializableFromArray, PublicKey, SecretKey, SerializableToArray, Signer,
+    };
+
+    #[test]
+    fn test_reception_keys() {
+        let delegating_sk = SecretKey::random();
+        let delegating_pk = PublicKey::from_secret_key(&delegating_sk);
+        let delegating_signer = Signer::new(&SecretKey::random());
+
+        // Bob pre-publishes the reception keys.
+        let receiving_signer = Signer::new(&SecretKey::random());
+        let receiving_verifying_pk = receiving_signer.verifying_key();
+        let reception_keys = generate_reception_keys(&receiving_signer, 3);
+
+        // Alice picks one and checks it comes from Bob.
+        let (reception_sk, reception_key) = &reception_keys[1];
+        let reception_key_back = ReceptionKey::from_array(&reception_key.to_array()).unwrap();
+        assert_eq!(&reception_key_back, reception_key);
+        assert_eq!(
+            reception_key.verify(&delegating_signer.verifying_key()),
+            Err(ReceptionKeyVerificationError::IncorrectSignature)
+        );
+        let verified_reception_key = reception_key_back.verify(&receiving_verifying_pk).unwrap();
+        let reception_pk = *verified_reception_key.public_key();
+
+        let (capsule, key_seed) = encapsulate(&delegating_pk);
+        let kfrags = generate_kfrags_for_reception_key(
+            &delegating_sk,
+            verified_reception_key,
+            &delegating_signer,
+            2,
+            3,
+            true,
+            true,
+        );
+
+        // The fragments are bound to the reception key.
+        let verifying_pk = delegating_signer.verifying_key();
+        let kfrag = kfrags[0].clone().unverify();
+        assert!(kfrag
+            .verify(&verifying_pk, Some(&delegating_pk), Some(&reception_pk))
+            .is_ok());
+
+        let vcfrags: Vec<_> = kfrags[..2]
+            .iter()
+            .map(|kfrag| reencrypt(&capsule, kfrag, None))
+            .collect();
+
+        // Only the secret counterpart of the reception key opens the capsule.
+        assert_eq!(
+            decapsulate_reencrypted(reception_sk, &delegating_pk, &capsule, &vcfrags),
+            Ok(key_seed)
+        );
+        let (other_reception_sk, _reception_key) = &reception_keys[0];
+        assert!(
+            decapsulate_reencrypted(other_reception_sk, &delegating_pk, &capsule, &vcfrags)
+                .is_err()
+        );
+    }
+}
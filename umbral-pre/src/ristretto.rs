@@ -0,0 +1,1221 @@
+//! The Umbral scheme over the ristretto255 group (enabled by the `ristretto` feature),
+//! for deployments whose identity keys are Ed25519/X25519-based.
+//!
+//! The construction is the same as the one of the crate root,
+//! with its own types (all of them are not interchangeable with the secp256k1 ones):
+//!
+//! * points are encoded in 32 bytes (the canonical ristretto255 encoding),
+//!   scalars in 32 bytes (little-endian, as in `curve25519-dalek`);
+//! * the point `U` of the parameters is the hash to the group (`from_uniform_bytes()`)
+//!   of a SHA-512 digest, and the hashes to scalars are wide SHA-512 digests
+//!   (prefixed with the same domain separation tags) reduced modulo the group order;
+//! * key fragments are signed with Schnorr signatures over ristretto255,
+//!   always covering both the delegating and the receiving key;
+//! * capsule fragments are checked against the original capsule only on verification
+//!   (no capsule binding in [`VerifiedCapsuleFrag`]).
+//!
+//! Only the core operations are provided: the KEM, key fragment generation, reencryption,
+//! verification of the fragments, and (with the `dem` feature) the high-level encryption.
+
+#[cfg(any(feature = "dem", feature = "role-alice"))]
+use alloc::boxed::Box;
+#[cfg(any(feature = "role-alice", feature = "role-bob"))]
+use alloc::vec::Vec;
+use core::fmt;
+
+use curve25519_dalek::constants::RISTRETTO_BASEPOINT_TABLE;
+use curve25519_dalek::ristretto::{CompressedRistretto, RistrettoPoint};
+use curve25519_dalek::scalar::Scalar;
+use curve25519_dalek::traits::IsIdentity;
+use digest::Digest;
+use generic_array::sequence::Concat;
+use generic_array::GenericArray;
+use rand_core::{CryptoRng, OsRng, RngCore};
+use sha2::Sha512;
+use subtle::{Choice, ConstantTimeEq};
+use typenum::{op, U32};
+use zeroize::Zeroize;
+
+#[cfg(feature = "role-bob")]
+use crate::capsule::OpenReencryptedError;
+use crate::capsule_frag::{CapsuleFragVerificationError, ReencryptionCheck};
+#[cfg(feature = "dem")]
+use crate::dem::{
+    decrypt_with_header, encrypt_with_header, DecryptionError, DemAlgorithm, EncryptionError,
+};
+use crate::hashing::Hash;
+use crate::key_frag::{KeyFragID, KeyFragVerificationError};
+use crate::metadata::{encode_metadata, Metadata};
+#[cfg(all(feature = "dem", feature = "role-bob"))]
+use crate::pre::ReencryptionError;
+use crate::traits::{
+    DeserializableFromArray, DeserializationError, RepresentableAsArray, SerializableToArray,
+};
+
+type ScalarSize = U32;
+type PointSize = U32;
+
+impl RepresentableAsArray for Scalar {
+    type Size = ScalarSize;
+}
+
+impl SerializableToArray for Scalar {
+    fn to_array(&self) -> GenericArray<u8, Self::Size> {
+        GenericArray::<u8, Self::Size>::from(self.to_bytes())
+    }
+}
+
+impl DeserializableFromArray for Scalar {
+    fn from_array(arr: &GenericArray<u8, Self::Size>) -> Result<Self, DeserializationError> {
+        let mut bytes = [0u8; 32];
+        bytes.copy_from_slice(arr);
+        Scalar::from_canonical_bytes(bytes).ok_or(DeserializationError::InvalidScalar)
+    }
+}
+
+impl RepresentableAsArray for RistrettoPoint {
+    type Size = PointSize;
+}
+
+impl SerializableToArray for RistrettoPoint {
+    fn to_array(&self) -> GenericArray<u8, Self::Size> {
+        GenericArray::<u8, Self::Size>::from(self.compress().to_bytes())
+    }
+}
+
+impl DeserializableFromArray for RistrettoPoint {
+    fn from_array(arr: &GenericArray<u8, Self::Size>) -> Result<Self, DeserializationError> {
+        let mut bytes = [0u8; 32];
+        bytes.copy_from_slice(arr);
+        match CompressedRistretto(bytes).decompress() {
+            Some(point) if !point.is_identity() => Ok(point),
+            _ => Err(DeserializationError::InvalidPoint),
+        }
+    }
+}
+
+fn mul_generator(scalar: &Scalar) -> RistrettoPoint {
+    &RISTRETTO_BASEPOINT_TABLE * scalar
+}
+
+fn random_nonzero_scalar(rng: &mut (impl CryptoRng + RngCore)) -> Scalar {
+    loop {
+        let mut bytes = [0u8; 64];
+        rng.fill_bytes(&mut bytes);
+        let scalar = Scalar::from_bytes_mod_order_wide(&bytes);
+        bytes.zeroize();
+        // Variable-time: a retry happens with a negligible probability.
+        if scalar != Scalar::zero() {
+            return scalar;
+        }
+    }
+}
+
+// Same as `ScalarDigest` of the crate root, with a wide SHA-512 digest.
+struct ScalarDigest(Hash<Sha512>);
+
+impl ScalarDigest {
+    fn new_with_dst(dst: &[u8]) -> Self {
+        Self(Hash::from_digest_with_dst(Sha512::new(), dst))
+    }
+
+    fn chain_bytes<T: AsRef<[u8]>>(self, bytes: T) -> Self {
+        Self(self.0.chain_bytes(bytes))
+    }
+
+    fn chain_point(self, point: &RistrettoPoint) -> Self {
+        self.chain_bytes(point.compress().as_bytes())
+    }
+
+    fn finalize(self) -> Scalar {
+        let mut bytes = [0u8; 64];
+        bytes.copy_from_slice(&self.0.digest().finalize());
+        Scalar::from_bytes_mod_order_wide(&bytes)
+    }
+}
+
+/// Returns the point `U` of the parameters, with an unknown discrete logarithm.
+fn point_u() -> RistrettoPoint {
+    let mut bytes = [0u8; 64];
+    bytes.copy_from_slice(
+        &Hash::from_digest_with_dst(Sha512::new(), b"PARAMETERS")
+            .chain_bytes(b"POINT_U")
+            .digest()
+            .finalize(),
+    );
+    RistrettoPoint::from_uniform_bytes(&bytes)
+}
+
+fn hash_capsule_points(capsule_e: &RistrettoPoint, capsule_v: &RistrettoPoint) -> Scalar {
+    ScalarDigest::new_with_dst(b"CAPSULE_POINTS")
+        .chain_point(capsule_e)
+        .chain_point(capsule_v)
+        .finalize()
+}
+
+#[cfg(any(feature = "role-alice", feature = "role-bob"))]
+fn hash_to_polynomial_arg(
+    precursor: &RistrettoPoint,
+    pubkey: &RistrettoPoint,
+    dh_point: &RistrettoPoint,
+    kfrag_id: &KeyFragID,
+) -> Scalar {
+    ScalarDigest::new_with_dst(b"POLYNOMIAL_ARG")
+        .chain_point(precursor)
+        .chain_point(pubkey)
+        .chain_point(dh_point)
+        .chain_bytes(kfrag_id)
+        .finalize()
+}
+
+#[cfg(any(feature = "role-alice", feature = "role-bob"))]
+fn hash_to_shared_secret(
+    precursor: &RistrettoPoint,
+    pubkey: &RistrettoPoint,
+    dh_point: &RistrettoPoint,
+) -> Scalar {
+    ScalarDigest::new_with_dst(b"SHARED_SECRET")
+        .chain_point(precursor)
+        .chain_point(pubkey)
+        .chain_point(dh_point)
+        .finalize()
+}
+
+fn hash_to_cfrag_verification(points: &[RistrettoPoint], metadata: Option<&[u8]>) -> Scalar {
+    let mut digest = ScalarDigest::new_with_dst(b"CFRAG_VERIFICATION");
+    for point in points {
+        digest = digest.chain_point(point);
+    }
+    match metadata {
+        Some(s) => digest.chain_bytes(s),
+        None => digest,
+    }
+    .finalize()
+}
+
+/// A secret key.
+#[derive(Clone)] // Debug is implemented manually, to avoid exposing the key accidentally.
+pub struct SecretKey(Scalar);
+
+impl fmt::Debug for SecretKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("SecretKey(<redacted>)")
+    }
+}
+
+impl ConstantTimeEq for SecretKey {
+    fn ct_eq(&self, other: &Self) -> Choice {
+        self.0.ct_eq(&other.0)
+    }
+}
+
+impl PartialEq for SecretKey {
+    fn eq(&self, other: &Self) -> bool {
+        self.ct_eq(other).into()
+    }
+}
+
+impl Drop for SecretKey {
+    fn drop(&mut self) {
+        self.0.zeroize();
+    }
+}
+
+impl SecretKey {
+    /// Generates a secret key using the default RNG and returns it.
+    pub fn random() -> Self {
+        Self::random_with_rng(&mut OsRng)
+    }
+
+    /// Generates a secret key using the given RNG and returns it.
+    pub fn random_with_rng(rng: &mut (impl CryptoRng + RngCore)) -> Self {
+        Self(random_nonzero_scalar(rng))
+    }
+}
+
+impl RepresentableAsArray for SecretKey {
+    type Size = ScalarSize;
+}
+
+impl SerializableToArray for SecretKey {
+    fn to_array(&self) -> GenericArray<u8, Self::Size> {
+        self.0.to_array()
+    }
+}
+
+impl DeserializableFromArray for SecretKey {
+    fn from_array(arr: &GenericArray<u8, Self::Size>) -> Result<Self, DeserializationError> {
+        // Rejects zero and the non-canonical values.
+        let scalar = Scalar::from_array(arr)?;
+        if scalar == Scalar::zero() {
+            return Err(DeserializationError::InvalidScalar);
+        }
+        Ok(Self(scalar))
+    }
+}
+
+/// A public key.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PublicKey(RistrettoPoint);
+
+impl PublicKey {
+    /// Creates a public key from a secret key.
+    pub fn from_secret_key(secret_key: &SecretKey) -> Self {
+        Self(mul_generator(&secret_key.0))
+    }
+}
+
+impl RepresentableAsArray for PublicKey {
+    type Size = PointSize;
+}
+
+impl SerializableToArray for PublicKey {
+    fn to_array(&self) -> GenericArray<u8, Self::Size> {
+        self.0.to_array()
+    }
+}
+
+impl DeserializableFromArray for PublicKey {
+    fn from_array(arr: &GenericArray<u8, Self::Size>) -> Result<Self, DeserializationError> {
+        RistrettoPoint::from_array(arr).map(Self)
+    }
+}
+
+/// A Schnorr signature over ristretto255.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Signature {
+    point_r: RistrettoPoint,
+    scalar_s: Scalar,
+}
+
+impl RepresentableAsArray for Signature {
+    type Size = op!(PointSize + ScalarSize);
+}
+
+impl SerializableToArray for Signature {
+    fn to_array(&self) -> GenericArray<u8, Self::Size> {
+        self.point_r.to_array().concat(self.scalar_s.to_array())
+    }
+}
+
+impl DeserializableFromArray for Signature {
+    fn from_array(arr: &GenericArray<u8, Self::Size>) -> Result<Self, DeserializationError> {
+        let (point_r, rest) = RistrettoPoint::take(*arr)?;
+        let scalar_s = Scalar::take_last(rest)?;
+        Ok(Self { point_r, scalar_s })
+    }
+}
+
+fn signature_challenge(
+    point_r: &RistrettoPoint,
+    verifying_pk: &PublicKey,
+    message: &[u8],
+) -> Scalar {
+    ScalarDigest::new_with_dst(b"SIGNATURE_CHALLENGE")
+        .chain_point(point_r)
+        .chain_point(&verifying_pk.0)
+        .chain_bytes(message)
+        .finalize()
+}
+
+impl Signature {
+    /// Verifies that the given message was signed with the secret counterpart of the given key.
+    pub fn verify(&self, verifying_pk: &PublicKey, message: &[u8]) -> bool {
+        let e = signature_challenge(&self.point_r, verifying_pk, message);
+        mul_generator(&self.scalar_s) == self.point_r + verifying_pk.0 * e
+    }
+}
+
+/// An object used to sign messages.
+/// For security reasons cannot be serialized.
+#[derive(Clone)] // No Debug derivation, to avoid exposing the key accidentally.
+pub struct Signer(SecretKey);
+
+impl Signer {
+    /// Creates a new signer out of a secret key.
+    pub fn new(sk: &SecretKey) -> Self {
+        Self(sk.clone())
+    }
+
+    /// Signs the given message.
+    pub fn sign(&self, message: &[u8]) -> Signature {
+        self.sign_with_rng(&mut OsRng, message)
+    }
+
+    /// Signs the given message using the given RNG.
+    pub fn sign_with_rng(&self, rng: &mut (impl CryptoRng + RngCore), message: &[u8]) -> Signature {
+        // The nonce is derived from the key and the message along with fresh randomness,
+        // so that a weak RNG does not leak the key.
+        let SecretKey(secret) = &self.0;
+        let mut aux_rand = [0u8; 32];
+        rng.fill_bytes(&mut aux_rand);
+        let mut k = ScalarDigest::new_with_dst(b"SIGNATURE_NONCE")
+            .chain_bytes(secret.as_bytes())
+            .chain_bytes(aux_rand)
+            .chain_bytes(message)
+            .finalize();
+        let point_r = mul_generator(&k);
+        let e = signature_challenge(&point_r, &self.verifying_key(), message);
+        let scalar_s = k + e * secret;
+        k.zeroize();
+        Signature { point_r, scalar_s }
+    }
+
+    /// Returns the public key that can be used to verify the signatures produced by this signer.
+    pub fn verifying_key(&self) -> PublicKey {
+        PublicKey::from_secret_key(&self.0)
+    }
+}
+
+/// Encapsulated symmetric key used to encrypt the plaintext.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Capsule {
+    point_e: RistrettoPoint,
+    point_v: RistrettoPoint,
+    signature: Scalar,
+}
+
+impl RepresentableAsArray for Capsule {
+    type Size = op!(PointSize + PointSize + ScalarSize);
+}
+
+impl SerializableToArray for Capsule {
+    fn to_array(&self) -> GenericArray<u8, Self::Size> {
+        self.point_e
+            .to_array()
+            .concat(self.point_v.to_array())
+            .concat(self.signature.to_array())
+    }
+}
+
+impl DeserializableFromArray for Capsule {
+    fn from_array(arr: &GenericArray<u8, Self::Size>) -> Result<Self, DeserializationError> {
+        let (point_e, rest) = RistrettoPoint::take(*arr)?;
+        let (point_v, rest) = RistrettoPoint::take(rest)?;
+        let signature = Scalar::take_last(rest)?;
+        let capsule = Self {
+            point_e,
+            point_v,
+            signature,
+        };
+        if !capsule.verify() {
+            return Err(DeserializationError::ConstructionFailure);
+        }
+        Ok(capsule)
+    }
+}
+
+impl Capsule {
+    fn verify(&self) -> bool {
+        let h = hash_capsule_points(&self.point_e, &self.point_v);
+        mul_generator(&self.signature) == self.point_v + self.point_e * h
+    }
+
+    fn from_public_key(
+        rng: &mut (impl CryptoRng + RngCore),
+        pk: &PublicKey,
+    ) -> (Self, RistrettoPoint) {
+        let mut priv_r = random_nonzero_scalar(rng);
+        let mut priv_u = random_nonzero_scalar(rng);
+
+        let point_e = mul_generator(&priv_r);
+        let point_v = mul_generator(&priv_u);
+        let h = hash_capsule_points(&point_e, &point_v);
+        let signature = priv_u + priv_r * h;
+
+        let shared_key = pk.0 * (priv_r + priv_u);
+        priv_r.zeroize();
+        priv_u.zeroize();
+
+        let capsule = Self {
+            point_e,
+            point_v,
+            signature,
+        };
+        (capsule, shared_key)
+    }
+
+    fn open_original(&self, sk: &SecretKey) -> RistrettoPoint {
+        (self.point_e + self.point_v) * sk.0
+    }
+
+    #[cfg(feature = "role-bob")]
+    fn open_reencrypted(
+        &self,
+        receiving_sk: &SecretKey,
+        delegating_pk: &PublicKey,
+        cfrags: &[&CapsuleFrag],
+    ) -> Result<RistrettoPoint, OpenReencryptedError> {
+        if cfrags.is_empty() {
+            return Err(OpenReencryptedError::NoCapsuleFrags);
+        }
+
+        let precursor = cfrags[0].precursor;
+        if !cfrags.iter().all(|cfrag| cfrag.precursor == precursor) {
+            return Err(OpenReencryptedError::MismatchedCapsuleFrags);
+        }
+        for (i, cfrag) in cfrags.iter().enumerate() {
+            if cfrags[..i]
+                .iter()
+                .any(|other| other.kfrag_id == cfrag.kfrag_id)
+            {
+                return Err(OpenReencryptedError::RepeatingCapsuleFrags);
+            }
+        }
+
+        let pub_key = PublicKey::from_secret_key(receiving_sk).0;
+        let dh_point = precursor * receiving_sk.0;
+
+        let xs: Vec<_> = cfrags
+            .iter()
+            .map(|cfrag| hash_to_polynomial_arg(&precursor, &pub_key, &dh_point, &cfrag.kfrag_id))
+            .collect();
+        let lambdas = lambda_coeffs(&xs).ok_or(OpenReencryptedError::RepeatingCapsuleFrags)?;
+
+        let mut e_prime = RistrettoPoint::default();
+        let mut v_prime = RistrettoPoint::default();
+        for (lambda, cfrag) in lambdas.iter().zip(cfrags.iter()) {
+            e_prime += cfrag.point_e1 * lambda;
+            v_prime += cfrag.point_v1 * lambda;
+        }
+
+        let d = hash_to_shared_secret(&precursor, &pub_key, &dh_point);
+        // Variable-time: only exits early if `d` is zero, which happens with a negligible probability.
+        if d == Scalar::zero() {
+            return Err(OpenReencryptedError::ZeroHash);
+        }
+
+        let h = hash_capsule_points(&self.point_e, &self.point_v);
+        if delegating_pk.0 * (self.signature * d.invert()) != e_prime * h + v_prime {
+            return Err(OpenReencryptedError::ValidationFailed);
+        }
+
+        Ok((e_prime + v_prime) * d)
+    }
+}
+
+/// Computes the Lagrange coefficients `prod_{j != i} xs[j] / (xs[j] - xs[i])`
+/// for the interpolation at zero, for all `i`.
+/// Returns `None` if some of `xs` are equal.
+///
+/// All the denominators are inverted at once, without branching on their values.
+#[cfg(feature = "role-bob")]
+fn lambda_coeffs(xs: &[Scalar]) -> Option<Vec<Scalar>> {
+    let mut numerators = Vec::with_capacity(xs.len());
+    let mut denominators = Vec::with_capacity(xs.len());
+    for (i, x_i) in xs.iter().enumerate() {
+        let mut numerator = Scalar::one();
+        let mut denominator = Scalar::one();
+        for (j, x_j) in xs.iter().enumerate() {
+            // The branch depends on the positions only.
+            if j != i {
+                numerator *= x_j;
+                denominator *= x_j - x_i;
+            }
+        }
+        numerators.push(numerator);
+        denominators.push(denominator);
+    }
+
+    // The only data-dependent branch: whether all the points are distinct.
+    if denominators
+        .iter()
+        .any(|denominator| *denominator == Scalar::zero())
+    {
+        return None;
+    }
+    Scalar::batch_invert(&mut denominators);
+
+    Some(
+        numerators
+            .iter()
+            .zip(denominators.iter())
+            .map(|(numerator, inv_denominator)| numerator * inv_denominator)
+            .collect(),
+    )
+}
+
+type KeyFragIDSize = <KeyFragID as RepresentableAsArray>::Size;
+type SignatureSize = <Signature as RepresentableAsArray>::Size;
+
+/// The message signed in a key fragment.
+fn kfrag_signature_message(
+    kfrag_id: &KeyFragID,
+    commitment: &RistrettoPoint,
+    precursor: &RistrettoPoint,
+    delegating_pk: &PublicKey,
+    receiving_pk: &PublicKey,
+) -> GenericArray<u8, op!(KeyFragIDSize + PointSize + PointSize + PointSize + PointSize)> {
+    kfrag_id
+        .to_array()
+        .concat(commitment.to_array())
+        .concat(precursor.to_array())
+        .concat(delegating_pk.to_array())
+        .concat(receiving_pk.to_array())
+}
+
+/// A fragment of the encrypting party's key used to create a [`CapsuleFrag`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct KeyFrag {
+    id: KeyFragID,
+    key: Scalar,
+    precursor: RistrettoPoint,
+    commitment: RistrettoPoint,
+    signature: Signature,
+}
+
+impl RepresentableAsArray for KeyFrag {
+    type Size = op!(KeyFragIDSize + ScalarSize + PointSize + PointSize + SignatureSize);
+}
+
+impl SerializableToArray for KeyFrag {
+    fn to_array(&self) -> GenericArray<u8, Self::Size> {
+        self.id
+            .to_array()
+            .concat(self.key.to_array())
+            .concat(self.precursor.to_array())
+            .concat(self.commitment.to_array())
+            .concat(self.signature.to_array())
+    }
+}
+
+impl DeserializableFromArray for KeyFrag {
+    fn from_array(arr: &GenericArray<u8, Self::Size>) -> Result<Self, DeserializationError> {
+        let (id, rest) = KeyFragID::take(*arr)?;
+        let (key, rest) = Scalar::take(rest)?;
+        let (precursor, rest) = RistrettoPoint::take(rest)?;
+        let (commitment, rest) = RistrettoPoint::take(rest)?;
+        let signature = Signature::take_last(rest)?;
+        Ok(Self {
+            id,
+            key,
+            precursor,
+            commitment,
+            signature,
+        })
+    }
+}
+
+impl KeyFrag {
+    /// Verifies the integrity of the key fragment, given the signing key,
+    /// the delegating key and the receiving key (always included in the signature).
+    pub fn verify(
+        &self,
+        verifying_pk: &PublicKey,
+        delegating_pk: &PublicKey,
+        receiving_pk: &PublicKey,
+    ) -> Result<VerifiedKeyFrag, KeyFragVerificationError> {
+        if point_u() * self.key != self.commitment {
+            return Err(KeyFragVerificationError::IncorrectCommitment);
+        }
+
+        let message = kfrag_signature_message(
+            &self.id,
+            &self.commitment,
+            &self.precursor,
+            delegating_pk,
+            receiving_pk,
+        );
+        if !self.signature.verify(verifying_pk, &message) {
+            return Err(KeyFragVerificationError::IncorrectSignature);
+        }
+
+        Ok(VerifiedKeyFrag(self.clone()))
+    }
+
+    /// Returns the identifier of the fragment.
+    pub fn id(&self) -> &KeyFragID {
+        &self.id
+    }
+}
+
+/// Verified key fragment, good for reencryption.
+#[derive(Clone, Debug, PartialEq)]
+pub struct VerifiedKeyFrag(KeyFrag);
+
+impl RepresentableAsArray for VerifiedKeyFrag {
+    type Size = <KeyFrag as RepresentableAsArray>::Size;
+}
+
+impl SerializableToArray for VerifiedKeyFrag {
+    fn to_array(&self) -> GenericArray<u8, Self::Size> {
+        self.0.to_array()
+    }
+}
+
+impl VerifiedKeyFrag {
+    /// Returns the identifier of the fragment.
+    pub fn id(&self) -> &KeyFragID {
+        &self.0.id
+    }
+
+    /// Returns the unverified fragment, e.g. to send it over to a proxy.
+    pub fn unverify(self) -> KeyFrag {
+        self.0
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct CapsuleFragProof {
+    point_e2: RistrettoPoint,
+    point_v2: RistrettoPoint,
+    kfrag_commitment: RistrettoPoint,
+    kfrag_pok: RistrettoPoint,
+    signature: Scalar,
+    kfrag_signature: Signature,
+}
+
+impl RepresentableAsArray for CapsuleFragProof {
+    type Size = op!(PointSize + PointSize + PointSize + PointSize + ScalarSize + SignatureSize);
+}
+
+impl SerializableToArray for CapsuleFragProof {
+    fn to_array(&self) -> GenericArray<u8, Self::Size> {
+        self.point_e2
+            .to_array()
+            .concat(self.point_v2.to_array())
+            .concat(self.kfrag_commitment.to_array())
+            .concat(self.kfrag_pok.to_array())
+            .concat(self.signature.to_array())
+            .concat(self.kfrag_signature.to_array())
+    }
+}
+
+impl DeserializableFromArray for CapsuleFragProof {
+    fn from_array(arr: &GenericArray<u8, Self::Size>) -> Result<Self, DeserializationError> {
+        let (point_e2, rest) = RistrettoPoint::take(*arr)?;
+        let (point_v2, rest) = RistrettoPoint::take(rest)?;
+        let (kfrag_commitment, rest) = RistrettoPoint::take(rest)?;
+        let (kfrag_pok, rest) = RistrettoPoint::take(rest)?;
+        let (signature, rest) = Scalar::take(rest)?;
+        let kfrag_signature = Signature::take_last(rest)?;
+        Ok(Self {
+            point_e2,
+            point_v2,
+            kfrag_commitment,
+            kfrag_pok,
+            signature,
+            kfrag_signature,
+        })
+    }
+}
+
+/// A reencrypted fragment of a [`Capsule`] created by a proxy.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CapsuleFrag {
+    point_e1: RistrettoPoint,
+    point_v1: RistrettoPoint,
+    kfrag_id: KeyFragID,
+    precursor: RistrettoPoint,
+    proof: CapsuleFragProof,
+}
+
+type CapsuleFragProofSize = <CapsuleFragProof as RepresentableAsArray>::Size;
+
+impl RepresentableAsArray for CapsuleFrag {
+    type Size = op!(PointSize + PointSize + KeyFragIDSize + PointSize + CapsuleFragProofSize);
+}
+
+impl SerializableToArray for CapsuleFrag {
+    fn to_array(&self) -> GenericArray<u8, Self::Size> {
+        self.point_e1
+            .to_array()
+            .concat(self.point_v1.to_array())
+            .concat(self.kfrag_id.to_array())
+            .concat(self.precursor.to_array())
+            .concat(self.proof.to_array())
+    }
+}
+
+impl DeserializableFromArray for CapsuleFrag {
+    fn from_array(arr: &GenericArray<u8, Self::Size>) -> Result<Self, DeserializationError> {
+        let (point_e1, rest) = RistrettoPoint::take(*arr)?;
+        let (point_v1, rest) = RistrettoPoint::take(rest)?;
+        let (kfrag_id, rest) = KeyFragID::take(rest)?;
+        let (precursor, rest) = RistrettoPoint::take(rest)?;
+        let proof = CapsuleFragProof::take_last(rest)?;
+        Ok(Self {
+            point_e1,
+            point_v1,
+            kfrag_id,
+            precursor,
+            proof,
+        })
+    }
+}
+
+impl CapsuleFrag {
+    #[cfg(feature = "role-ursula")]
+    #[allow(clippy::many_single_char_names)]
+    fn reencrypted(
+        rng: &mut (impl CryptoRng + RngCore),
+        capsule: &Capsule,
+        kfrag: &KeyFrag,
+        metadata: Option<&[u8]>,
+    ) -> Self {
+        let rk = kfrag.key;
+        let mut t = random_nonzero_scalar(rng);
+
+        let e = capsule.point_e;
+        let v = capsule.point_v;
+        let u = point_u();
+
+        let e1 = e * rk;
+        let v1 = v * rk;
+        let e2 = e * t;
+        let v2 = v * t;
+        let u1 = kfrag.commitment;
+        let u2 = u * t;
+
+        let h = hash_to_cfrag_verification(&[e, e1, e2, v, v1, v2, u, u1, u2], metadata);
+        let z3 = t + h * rk;
+        t.zeroize();
+
+        Self {
+            point_e1: e1,
+            point_v1: v1,
+            kfrag_id: kfrag.id,
+            precursor: kfrag.precursor,
+            proof: CapsuleFragProof {
+                point_e2: e2,
+                point_v2: v2,
+                kfrag_commitment: u1,
+                kfrag_pok: u2,
+                signature: z3,
+                kfrag_signature: kfrag.signature,
+            },
+        }
+    }
+
+    /// Verifies the integrity of the capsule fragment, given the original capsule,
+    /// the encrypting party's key, the decrypting party's key, and the signing key.
+    #[allow(clippy::many_single_char_names)]
+    pub fn verify(
+        &self,
+        capsule: &Capsule,
+        verifying_pk: &PublicKey,
+        delegating_pk: &PublicKey,
+        receiving_pk: &PublicKey,
+        metadata: Option<&Metadata>,
+    ) -> Result<VerifiedCapsuleFrag, CapsuleFragVerificationError> {
+        let message = kfrag_signature_message(
+            &self.kfrag_id,
+            &self.proof.kfrag_commitment,
+            &self.precursor,
+            delegating_pk,
+            receiving_pk,
+        );
+        if !self.proof.kfrag_signature.verify(verifying_pk, &message) {
+            return Err(CapsuleFragVerificationError::IncorrectKeyFragSignature);
+        }
+
+        let e = capsule.point_e;
+        let v = capsule.point_v;
+        let u = point_u();
+
+        let e1 = self.point_e1;
+        let v1 = self.point_v1;
+        let e2 = self.proof.point_e2;
+        let v2 = self.proof.point_v2;
+        let u1 = self.proof.kfrag_commitment;
+        let u2 = self.proof.kfrag_pok;
+        let z3 = self.proof.signature;
+
+        let metadata = encode_metadata(metadata);
+        let h = hash_to_cfrag_verification(&[e, e1, e2, v, v1, v2, u, u1, u2], metadata.as_deref());
+
+        let checks = [
+            (e * z3 == e2 + e1 * h, ReencryptionCheck::PointE),
+            (v * z3 == v2 + v1 * h, ReencryptionCheck::PointV),
+            (u * z3 == u2 + u1 * h, ReencryptionCheck::KeyFragCommitment),
+        ];
+        if let Some((_passed, check)) = checks.iter().find(|(passed, _)| !passed) {
+            return Err(CapsuleFragVerificationError::IncorrectReencryption(*check));
+        }
+
+        Ok(VerifiedCapsuleFrag(self.clone()))
+    }
+
+    /// Returns the identifier of the key fragment this fragment was created with.
+    pub fn kfrag_id(&self) -> &KeyFragID {
+        &self.kfrag_id
+    }
+}
+
+/// Verified capsule fragment, good for decryption.
+#[derive(Clone, Debug, PartialEq)]
+pub struct VerifiedCapsuleFrag(CapsuleFrag);
+
+impl RepresentableAsArray for VerifiedCapsuleFrag {
+    type Size = <CapsuleFrag as RepresentableAsArray>::Size;
+}
+
+impl SerializableToArray for VerifiedCapsuleFrag {
+    fn to_array(&self) -> GenericArray<u8, Self::Size> {
+        self.0.to_array()
+    }
+}
+
+impl VerifiedCapsuleFrag {
+    /// Returns the identifier of the key fragment this fragment was created with.
+    pub fn kfrag_id(&self) -> &KeyFragID {
+        &self.0.kfrag_id
+    }
+
+    /// Returns the unverified fragment, e.g. to send it over to another party.
+    pub fn unverify(self) -> CapsuleFrag {
+        self.0
+    }
+}
+
+/// The key seed produced by the KEM, from which the DEM key is derived.
+pub type KeySeed = GenericArray<u8, PointSize>;
+
+/// Generates a random key seed and encapsulates it for later reencryption.
+/// Returns the KEM [`Capsule`] and the key seed.
+pub fn encapsulate(pk: &PublicKey) -> (Capsule, KeySeed) {
+    encapsulate_with_rng(&mut OsRng, pk)
+}
+
+/// Same as [`encapsulate()`], but uses the given RNG instead of the default one.
+pub fn encapsulate_with_rng(
+    rng: &mut (impl CryptoRng + RngCore),
+    pk: &PublicKey,
+) -> (Capsule, KeySeed) {
+    let (capsule, key_seed) = Capsule::from_public_key(rng, pk);
+    (capsule, key_seed.to_array())
+}
+
+/// Attempts to open the capsule using the original encryptor's secret key.
+pub fn decapsulate_original(decrypting_sk: &SecretKey, capsule: &Capsule) -> KeySeed {
+    capsule.open_original(decrypting_sk).to_array()
+}
+
+/// Attempts to open the capsule using the capsule fragments created by the proxies
+/// (at least `threshold` of them, see [`generate_kfrags()`]).
+#[cfg(feature = "role-bob")]
+pub fn decapsulate_reencrypted(
+    receiving_sk: &SecretKey,
+    delegating_pk: &PublicKey,
+    capsule: &Capsule,
+    verified_cfrags: &[VerifiedCapsuleFrag],
+) -> Result<KeySeed, OpenReencryptedError> {
+    let cfrags: Vec<_> = verified_cfrags.iter().map(|vcfrag| &vcfrag.0).collect();
+    capsule
+        .open_reencrypted(receiving_sk, delegating_pk, &cfrags)
+        .map(|key_seed| key_seed.to_array())
+}
+
+/// Creates `num_kfrags` fragments of `delegating_sk`,
+/// `threshold` of which are needed to open a capsule reencrypted for `receiving_pk`.
+///
+/// The fragments are signed with `signer`, along with the delegating and the receiving keys.
+///
+/// Panics if `threshold` is zero.
+#[cfg(feature = "role-alice")]
+pub fn generate_kfrags(
+    delegating_sk: &SecretKey,
+    receiving_pk: &PublicKey,
+    signer: &Signer,
+    threshold: usize,
+    num_kfrags: usize,
+) -> Box<[VerifiedKeyFrag]> {
+    generate_kfrags_with_rng(
+        &mut OsRng,
+        delegating_sk,
+        receiving_pk,
+        signer,
+        threshold,
+        num_kfrags,
+    )
+}
+
+/// Same as [`generate_kfrags()`], but uses the given RNG instead of the default one.
+#[cfg(feature = "role-alice")]
+pub fn generate_kfrags_with_rng(
+    rng: &mut (impl CryptoRng + RngCore),
+    delegating_sk: &SecretKey,
+    receiving_pk: &PublicKey,
+    signer: &Signer,
+    threshold: usize,
+    num_kfrags: usize,
+) -> Box<[VerifiedKeyFrag]> {
+    assert!(threshold > 0, "the threshold must be positive");
+
+    let delegating_pk = PublicKey::from_secret_key(delegating_sk);
+    let u = point_u();
+
+    let (mut d, precursor, dh_point) = loop {
+        // The precursor point is used as an ephemeral public key in a DH key exchange,
+        // and the resulting shared secret 'dh_point' is used to derive other secret values
+        let mut private_precursor = random_nonzero_scalar(rng);
+        let precursor = mul_generator(&private_precursor);
+        let dh_point = receiving_pk.0 * private_precursor;
+        private_precursor.zeroize();
+
+        let d = hash_to_shared_secret(&precursor, &receiving_pk.0, &dh_point);
+        // Variable-time: a retry happens with a negligible probability.
+        if d != Scalar::zero() {
+            break (d, precursor, dh_point);
+        }
+    };
+
+    // Coefficients of the generating polynomial
+    let mut coefficients = Vec::<Scalar>::with_capacity(threshold);
+    coefficients.push(delegating_sk.0 * d.invert());
+    d.zeroize();
+    for _i in 1..threshold {
+        coefficients.push(random_nonzero_scalar(rng));
+    }
+
+    let mut kfrags = Vec::with_capacity(num_kfrags);
+    for _ in 0..num_kfrags {
+        let id = KeyFragID::random(rng);
+        let share_index = hash_to_polynomial_arg(&precursor, &receiving_pk.0, &dh_point, &id);
+
+        // Evaluates the polynomial at the share index (Horner's method).
+        let mut key = Scalar::zero();
+        for coefficient in coefficients.iter().rev() {
+            key = key * share_index + coefficient;
+        }
+
+        let commitment = u * key;
+        let message =
+            kfrag_signature_message(&id, &commitment, &precursor, &delegating_pk, receiving_pk);
+        let signature = signer.sign_with_rng(rng, &message);
+
+        kfrags.push(VerifiedKeyFrag(KeyFrag {
+            id,
+            key,
+            precursor,
+            commitment,
+            signature,
+        }));
+    }
+
+    for coefficient in coefficients.iter_mut() {
+        coefficient.zeroize();
+    }
+
+    kfrags.into_boxed_slice()
+}
+
+/// Reencrypts a [`Capsule`] object with a key fragment, creating a capsule fragment.
+#[cfg(feature = "role-ursula")]
+pub fn reencrypt(
+    capsule: &Capsule,
+    verified_kfrag: &VerifiedKeyFrag,
+    metadata: Option<&Metadata>,
+) -> VerifiedCapsuleFrag {
+    reencrypt_with_rng(&mut OsRng, capsule, verified_kfrag, metadata)
+}
+
+/// Same as [`reencrypt()`], but uses the given RNG instead of the default one.
+#[cfg(feature = "role-ursula")]
+pub fn reencrypt_with_rng(
+    rng: &mut (impl CryptoRng + RngCore),
+    capsule: &Capsule,
+    verified_kfrag: &VerifiedKeyFrag,
+    metadata: Option<&Metadata>,
+) -> VerifiedCapsuleFrag {
+    VerifiedCapsuleFrag(CapsuleFrag::reencrypted(
+        rng,
+        capsule,
+        &verified_kfrag.0,
+        encode_metadata(metadata).as_deref(),
+    ))
+}
+
+/// Encrypts the given plaintext message using the default DEM scheme,
+/// and encapsulates the key for later reencryption.
+/// Returns the KEM [`Capsule`] and the ciphertext.
+#[cfg(feature = "dem")]
+pub fn encrypt(pk: &PublicKey, plaintext: &[u8]) -> Result<(Capsule, Box<[u8]>), EncryptionError> {
+    encrypt_with_rng(&mut OsRng, pk, plaintext)
+}
+
+/// Same as [`encrypt()`], but uses the given RNG instead of the default one.
+#[cfg(feature = "dem")]
+pub fn encrypt_with_rng(
+    rng: &mut (impl CryptoRng + RngCore),
+    pk: &PublicKey,
+    plaintext: &[u8],
+) -> Result<(Capsule, Box<[u8]>), EncryptionError> {
+    let (capsule, key_seed) = encapsulate_with_rng(rng, pk);
+    encrypt_with_header(
+        DemAlgorithm::DEFAULT,
+        rng,
+        &key_seed,
+        plaintext,
+        &capsule.to_array(),
+    )
+    .map(|ciphertext| (capsule, ciphertext))
+}
+
+/// Attempts to decrypt the ciphertext using the original encryptor's secret key.
+#[cfg(feature = "dem")]
+pub fn decrypt_original(
+    decrypting_sk: &SecretKey,
+    capsule: &Capsule,
+    ciphertext: impl AsRef<[u8]>,
+) -> Result<Box<[u8]>, DecryptionError> {
+    let key_seed = decapsulate_original(decrypting_sk, capsule);
+    decrypt_with_header(&key_seed, ciphertext.as_ref(), &capsule.to_array(), None)
+}
+
+/// Decrypts the ciphertext using previously reencrypted capsule fragments.
+#[cfg(all(feature = "dem", feature = "role-bob"))]
+pub fn decrypt_reencrypted(
+    receiving_sk: &SecretKey,
+    delegating_pk: &PublicKey,
+    capsule: &Capsule,
+    verified_cfrags: &[VerifiedCapsuleFrag],
+    ciphertext: impl AsRef<[u8]>,
+) -> Result<Box<[u8]>, ReencryptionError> {
+    let key_seed = decapsulate_reencrypted(receiving_sk, delegating_pk, capsule, verified_cfrags)
+        .map_err(ReencryptionError::OnOpen)?;
+    decrypt_with_header(&key_seed, ciphertext.as_ref(), &capsule.to_array(), None)
+        .map_err(ReencryptionError::OnDecryption)
+}
+
+#[cfg(all(
+    test,
+    feature = "role-alice",
+    feature = "role-ursula",
+    feature = "role-bob"
+))]
+mod tests {
+
+    use alloc::vec::Vec;
+
+    use super::{
+        decapsulate_original, decapsulate_reencrypted, encapsulate, generate_kfrags, reencrypt,
+        Capsule, CapsuleFrag, KeyFrag, PublicKey, SecretKey, Signer,
+    };
+    #[cfg(feature = "dem")]
+    use super::{decrypt_original, decrypt_reencrypted, encrypt};
+    use crate::{
+        CapsuleFragVerificationError, DeserializableFromArray, KeyFragVerificationError,
+        OpenReencryptedError, SerializableToArray,
+    };
+
+    #[test]
+    fn test_reencryption() {
+        let delegating_sk = SecretKey::random();
+        let delegating_pk = PublicKey::from_secret_key(&delegating_sk);
+        let signer = Signer::new(&SecretKey::random());
+        let verifying_pk = signer.verifying_key();
+
+        let receiving_sk = SecretKey::random();
+        let receiving_pk = PublicKey::from_secret_key(&receiving_sk);
+
+        let (capsule, key_seed) = encapsulate(&delegating_pk);
+        assert_eq!(decapsulate_original(&delegating_sk, &capsule), key_seed);
+
+        let capsule_back = Capsule::from_array(&capsule.to_array()).unwrap();
+        assert_eq!(capsule_back, capsule);
+
+        let kfrags = generate_kfrags(&delegating_sk, &receiving_pk, &signer, 2, 3);
+
+        // The key fragments are bound to the keys of the policy.
+        let kfrag = KeyFrag::from_array(&kfrags[0].clone().unverify().to_array()).unwrap();
+        assert!(kfrag
+            .verify(&verifying_pk, &delegating_pk, &receiving_pk)
+            .is_ok());
+        assert_eq!(
+            kfrag.verify(&verifying_pk, &receiving_pk, &delegating_pk),
+            Err(KeyFragVerificationError::IncorrectSignature)
+        );
+
+        let cfrags: Vec<_> = kfrags
+            .iter()
+            .map(|kfrag| reencrypt(&capsule, kfrag, None).unverify())
+            .collect();
+
+        let cfrag = CapsuleFrag::from_array(&cfrags[0].to_array()).unwrap();
+        assert_eq!(cfrag, cfrags[0]);
+        assert_eq!(
+            cfrag
+                .verify(
+                    &capsule,
+                    &delegating_pk,
+                    &delegating_pk,
+                    &receiving_pk,
+                    None
+                )
+                .map(|_| ()),
+            Err(CapsuleFragVerificationError::IncorrectKeyFragSignature)
+        );
+
+        let verified_cfrags: Vec<_> = cfrags
+            .iter()
+            .map(|cfrag| {
+                cfrag
+                    .verify(&capsule, &verifying_pk, &delegating_pk, &receiving_pk, None)
+                    .unwrap()
+            })
+            .collect();
+
+        // Any two fragments are enough; one is not.
+        assert_eq!(
+            decapsulate_reencrypted(
+                &receiving_sk,
+                &delegating_pk,
+                &capsule,
+                &verified_cfrags[1..]
+            ),
+            Ok(key_seed)
+        );
+        assert_eq!(
+            decapsulate_reencrypted(
+                &receiving_sk,
+                &delegating_pk,
+                &capsule,
+                &verified_cfrags[..1]
+            ),
+            Err(OpenReencryptedError::ValidationFailed)
+        );
+        assert_eq!(
+            decapsulate_reencrypted(
+                &receiving_sk,
+                &delegating_pk,
+                &capsule,
+                &[verified_cfrags[0].clone(), verified_cfrags[0].clone()]
+            ),
+            Err(OpenReencryptedError::RepeatingCapsuleFrags)
+        );
+    }
+
+    #[cfg(feature = "dem")]
+    #[test]
+    fn test_encryption() {
+        let delegating_sk = SecretKey::random();
+        let delegating_pk = PublicKey::from_secret_key(&delegating_sk);
+        let signer = Signer::new(&SecretKey::random());
+
+        let receiving_sk = SecretKey::random();
+        let receiving_pk = PublicKey::from_secret_key(&receiving_sk);
+
+        let plaintext = b"peace at dawn";
+        let (capsule, ciphertext) = encrypt(&delegating_pk, plaintext).unwrap();
+
+        let plaintext_back = decrypt_original(&delegating_sk, &capsule, &ciphertext).unwrap();
+        assert_eq!(&plaintext_back as &[u8], plaintext);
+
+        let kfrags = generate_kfrags(&delegating_sk, &receiving_pk, &signer, 2, 3);
+        let verified_cfrags: Vec<_> = kfrags[..2]
+            .iter()
+            .map(|kfrag| reencrypt(&capsule, kfrag, None))
+            .collect();
+
+        let plaintext_back = decrypt_reencrypted(
+            &receiving_sk,
+            &delegating_pk,
+            &capsule,
+            &verified_cfrags,
+            &ciphertext,
+        )
+        .unwrap();
+        assert_eq!(&plaintext_back as &[u8], plaintext);
+    }
+}
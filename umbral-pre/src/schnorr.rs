@@ -0,0 +1,190 @@
+//! BIP-340 Schnorr signatures over secp256k1,
+//! used instead of ECDSA if the `schnorr` feature is enabled.
+//!
+//! The signed 32-byte message is the SHA-256 digest of the given message,
+//! same as for ECDSA; the public keys are used in their x-only form,
+//! as defined by BIP-340.
+
+use digest::Digest;
+use generic_array::sequence::Concat;
+use generic_array::GenericArray;
+use rand_core::{CryptoRng, RngCore};
+use sha2::Sha256;
+use subtle::{Choice, ConditionallySelectable};
+use typenum::{U32, U64};
+use zeroize::Zeroize;
+
+use crate::curve::{CurvePoint, CurveScalar};
+use crate::keys::{digest_for_signing, PublicKey, SecretKey};
+use crate::traits::{DeserializableFromArray, SerializableToArray};
+
+// The tagged hash from BIP-340: `SHA256(SHA256(tag) || SHA256(tag) || ...)`.
+fn tagged_hash(tag: &[u8]) -> Sha256 {
+    let tag_hash = Sha256::digest(tag);
+    Sha256::new().chain(&tag_hash).chain(&tag_hash)
+}
+
+// Returns the x coordinate of the point, and whether its y coordinate is odd.
+fn x_only(point: &CurvePoint) -> (GenericArray<u8, U32>, Choice) {
+    let compressed = point.to_array();
+    let is_odd = Choice::from(compressed[0] & 1);
+    (*GenericArray::from_slice(&compressed[1..]), is_odd)
+}
+
+fn negate(scalar: &CurveScalar) -> CurveScalar {
+    &CurveScalar::default() - scalar
+}
+
+fn challenge(
+    r_x: &[u8],
+    pk_x: &GenericArray<u8, U32>,
+    message: &GenericArray<u8, U32>,
+) -> CurveScalar {
+    CurveScalar::from_digest(
+        tagged_hash(b"BIP0340/challenge")
+            .chain(r_x)
+            .chain(pk_x)
+            .chain(message),
+    )
+}
+
+fn sign_prehashed(
+    secret: &CurveScalar,
+    message: &GenericArray<u8, U32>,
+    aux_rand: &GenericArray<u8, U32>,
+) -> GenericArray<u8, U64> {
+    let (pk_x, pk_is_odd) = x_only(&(&CurvePoint::generator() * secret));
+    let mut d = CurveScalar::conditional_select(secret, &negate(secret), pk_is_odd);
+
+    let mut t = d.to_array();
+    let aux_hash = tagged_hash(b"BIP0340/aux").chain(aux_rand).finalize();
+    for (t_byte, aux_byte) in t.iter_mut().zip(aux_hash.iter()) {
+        *t_byte ^= aux_byte;
+    }
+
+    // A zero nonce, which BIP-340 requires to reject, happens with a negligible probability.
+    let mut k = CurveScalar::from_digest(
+        tagged_hash(b"BIP0340/nonce")
+            .chain(&t)
+            .chain(&pk_x)
+            .chain(message),
+    );
+    let (r_x, r_is_odd) = x_only(&(&CurvePoint::generator() * &k));
+    k = CurveScalar::conditional_select(&k, &negate(&k), r_is_odd);
+
+    let e = challenge(&r_x, &pk_x, message);
+    let s = &k + &(&e * &d);
+
+    t.zeroize();
+    k.zeroize();
+    d.zeroize();
+
+    r_x.concat(s.to_array())
+}
+
+fn verify_prehashed(
+    point: &CurvePoint,
+    message: &GenericArray<u8, U32>,
+    signature: &GenericArray<u8, U64>,
+) -> bool {
+    let (r_x, s_bytes) = signature.split_at(32);
+    let s = match CurveScalar::from_bytes(s_bytes) {
+        Ok(s) => s,
+        Err(_) => return false,
+    };
+
+    // The key is lifted to the point with the even y coordinate,
+    // so `R = s * G - e * P` becomes `s * G + e * P` if `point` has an odd y.
+    let (pk_x, pk_is_odd) = x_only(point);
+    let e = challenge(r_x, &pk_x, message);
+    let e = CurveScalar::conditional_select(&negate(&e), &e, pk_is_odd);
+    let r = &(&CurvePoint::generator() * &s) + &(point * &e);
+    if r.is_identity() {
+        return false;
+    }
+
+    let (expected_r_x, r_is_odd) = x_only(&r);
+    !bool::from(r_is_odd) && expected_r_x.as_slice() == r_x
+}
+
+/// Signs the SHA-256 digest of the message.
+pub(crate) fn sign(
+    rng: &mut (impl CryptoRng + RngCore),
+    sk: &SecretKey,
+    message: &[u8],
+) -> GenericArray<u8, U64> {
+    let mut aux_rand = GenericArray::<u8, U32>::default();
+    rng.fill_bytes(&mut aux_rand);
+    let mut secret = sk.to_secret_scalar();
+    let signature = sign_prehashed(&secret, &digest_for_signing(message).finalize(), &aux_rand);
+    secret.zeroize();
+    signature
+}
+
+/// Verifies a signature of the SHA-256 digest of the message.
+pub(crate) fn verify(pk: &PublicKey, message: &[u8], signature: &GenericArray<u8, U64>) -> bool {
+    verify_prehashed(
+        &pk.to_point(),
+        &digest_for_signing(message).finalize(),
+        signature,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+
+    use alloc::vec::Vec;
+
+    use generic_array::GenericArray;
+
+    use super::{sign_prehashed, verify_prehashed};
+    use crate::curve::{CurvePoint, CurveScalar};
+    use crate::DeserializableFromArray;
+
+    fn from_hex(s: &str) -> Vec<u8> {
+        (0..s.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&s[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_bip340_vectors() {
+        // Test vectors 0 and 1 from BIP-340.
+        let vectors = [
+            (
+                "0000000000000000000000000000000000000000000000000000000000000003",
+                "0000000000000000000000000000000000000000000000000000000000000000",
+                "0000000000000000000000000000000000000000000000000000000000000000",
+                concat!(
+                    "E907831F80848D1069A5371B402410364BDF1C5F8307B0084C55F1CE2DCA8215",
+                    "25F66A4A85EA8B71E482A74F382D2CE5EBEEE8FDB2172F477DF4900D310536C0"
+                ),
+            ),
+            (
+                "B7E151628AED2A6ABF7158809CF4F3C762E7160F38B4DA56A784D9045190CFEF",
+                "0000000000000000000000000000000000000000000000000000000000000001",
+                "243F6A8885A308D313198A2E03707344A4093822299F31D0082EFA98EC4E6C89",
+                concat!(
+                    "6896BD60EEAE296DB48A229FF71DFE071BDE413E6D43F917DC8DCF8C78DE3341",
+                    "8906D11AC976ABCCB20B091292BFF4EA897EFCB639EA871CFA95F6DE339E4B0A"
+                ),
+            ),
+        ];
+
+        for (secret, aux_rand, message, signature) in vectors.iter() {
+            let secret = CurveScalar::from_bytes(&from_hex(secret)).unwrap();
+            let point = &CurvePoint::generator() * &secret;
+            let message = GenericArray::clone_from_slice(&from_hex(message));
+            let aux_rand = GenericArray::clone_from_slice(&from_hex(aux_rand));
+            let expected = GenericArray::clone_from_slice(&from_hex(signature));
+
+            assert_eq!(sign_prehashed(&secret, &message, &aux_rand), expected);
+            assert!(verify_prehashed(&point, &message, &expected));
+
+            let mut tampered = expected;
+            tampered[63] ^= 1;
+            assert!(!verify_prehashed(&point, &message, &tampered));
+        }
+    }
+}
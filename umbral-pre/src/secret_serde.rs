@@ -0,0 +1,134 @@
+//! `serde` support for secret types, enabled with the `serde-secrets` feature.
+//!
+//! Secret objects are serialized as bytestrings of their array representation.
+//! The intermediate array is zeroized after being handed to the serializer.
+
+use alloc::vec::Vec;
+use core::fmt;
+use core::marker::PhantomData;
+
+use generic_array::GenericArray;
+use serde::de::{Error, Expected, SeqAccess, Visitor};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use typenum::Unsigned;
+use zeroize::Zeroizing;
+
+use crate::keys::{SecretKey, SecretKeyFactory};
+use crate::traits::{DeserializableFromArray, DeserializationError, SerializableToArray};
+
+fn serialize_secret<T, S>(obj: &T, serializer: S) -> Result<S::Ok, S::Error>
+where
+    T: SerializableToArray,
+    S: Serializer,
+{
+    let bytes = Zeroizing::new(obj.to_array());
+    serializer.serialize_bytes(&bytes)
+}
+
+fn to_serde_error<E: Error>(
+    err: DeserializationError,
+    length: usize,
+    expected: &dyn Expected,
+) -> E {
+    match err {
+        DeserializationError::ConstructionFailure
+        | DeserializationError::UnsupportedVersion(_)
+        | DeserializationError::InvalidEncoding
+        | DeserializationError::InvalidPoint
+        | DeserializationError::InvalidScalar => E::custom("failed to construct the object"),
+        DeserializationError::NotEnoughBytes | DeserializationError::TooManyBytes => {
+            E::invalid_length(length, expected)
+        }
+    }
+}
+
+struct SecretBytesVisitor<T>(PhantomData<T>);
+
+impl<'de, T: DeserializableFromArray> Visitor<'de> for SecretBytesVisitor<T> {
+    type Value = T;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(formatter, "{} bytes", T::Size::to_usize())
+    }
+
+    fn visit_bytes<E: Error>(self, v: &[u8]) -> Result<Self::Value, E> {
+        // `from_bytes()` does not copy the data, so no extra secret buffers are created here.
+        T::from_bytes(v).map_err(|err| to_serde_error(err, v.len(), &self))
+    }
+
+    fn visit_byte_buf<E: Error>(self, v: Vec<u8>) -> Result<Self::Value, E> {
+        let v = Zeroizing::new(v);
+        self.visit_bytes(&v)
+    }
+
+    // Formats without a native bytestring type (e.g. JSON) represent bytes as a sequence.
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+        let mut arr = Zeroizing::new(GenericArray::<u8, T::Size>::default());
+        for (i, byte) in arr.iter_mut().enumerate() {
+            *byte = seq
+                .next_element()?
+                .ok_or_else(|| A::Error::invalid_length(i, &self))?;
+        }
+        if seq.next_element::<u8>()?.is_some() {
+            return Err(A::Error::invalid_length(T::Size::to_usize() + 1, &self));
+        }
+        T::from_array(&arr).map_err(|err| to_serde_error(err, arr.len(), &self))
+    }
+}
+
+fn deserialize_secret<'de, T, D>(deserializer: D) -> Result<T, D::Error>
+where
+    T: DeserializableFromArray,
+    D: Deserializer<'de>,
+{
+    deserializer.deserialize_bytes(SecretBytesVisitor::<T>(PhantomData))
+}
+
+impl Serialize for SecretKey {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serialize_secret(self, serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for SecretKey {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserialize_secret(deserializer)
+    }
+}
+
+impl Serialize for SecretKeyFactory {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serialize_secret(self, serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for SecretKeyFactory {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserialize_secret(deserializer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::{SecretKey, SecretKeyFactory};
+
+    #[test]
+    fn test_serde_json_roundtrip() {
+        // JSON has no bytestrings, so the bytes are serialized as a sequence of numbers.
+        let sk = SecretKey::random();
+        let json = serde_json::to_string(&sk).unwrap();
+        assert!(json.starts_with('['));
+        let restored: SecretKey = serde_json::from_str(&json).unwrap();
+        assert!(restored == sk);
+
+        let factory = SecretKeyFactory::random();
+        let json = serde_json::to_string(&factory).unwrap();
+        let restored: SecretKeyFactory = serde_json::from_str(&json).unwrap();
+        assert!(restored == factory);
+
+        assert!(serde_json::from_str::<SecretKey>("[1, 2, 3]").is_err());
+        let too_long = json.replacen('[', "[0,", 1);
+        assert!(serde_json::from_str::<SecretKeyFactory>(&too_long).is_err());
+    }
+}
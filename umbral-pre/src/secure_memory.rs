@@ -0,0 +1,133 @@
+//! Storage for the long-lived secrets (secret keys and DEM keys).
+//!
+//! With the `secure-memory` feature the secrets are kept in heap allocations
+//! locked in RAM (with `mlock()` or `VirtualLock()`), so that they are never written
+//! to the swap. Otherwise they are stored as they are.
+//!
+//! Guard pages around the allocations are not used, since they cannot be set up
+//! without `unsafe` code, which this crate forbids.
+
+#[cfg(feature = "secure-memory")]
+use alloc::boxed::Box;
+#[cfg(feature = "secure-memory")]
+use core::ops::Deref;
+
+/// The container for a secret of type `T` (dereferences to `T`).
+#[cfg(feature = "secure-memory")]
+pub(crate) type Secret<T> = Locked<T>;
+
+/// The container for a secret of type `T` (dereferences to `T`).
+#[cfg(not(feature = "secure-memory"))]
+pub(crate) type Secret<T> = T;
+
+/// Moves the value into a [`Secret`] container.
+///
+/// The value is built by the caller and moved to the heap, so it passes through the stack,
+/// and the copies left there are not wiped (this cannot be avoided without `unsafe` code).
+/// Callers should create the value right before this call, in a function without
+/// other long-lived frames, so that the stack area is reused soon.
+#[cfg(feature = "secure-memory")]
+pub(crate) fn secret<T>(value: T) -> Secret<T> {
+    Locked::new(value)
+}
+
+/// Moves the value into a [`Secret`] container.
+#[cfg(not(feature = "secure-memory"))]
+pub(crate) fn secret<T>(value: T) -> Secret<T> {
+    value
+}
+
+// `mlock()` works with whole pages, and does not count how many times a page was locked,
+// so each value gets its own page(s), and unlocking it does not unlock any other secret.
+// (On platforms with pages larger than 4 KiB the pages may still be shared.)
+#[cfg(feature = "secure-memory")]
+#[repr(align(4096))]
+struct Page<T>(Option<T>);
+
+/// A value in its own heap allocation locked in RAM.
+///
+/// On drop, the value is dropped in place (the types the crate stores this way
+/// zeroize themselves when dropped), then the allocation is unlocked, and only then freed.
+#[cfg(feature = "secure-memory")]
+pub(crate) struct Locked<T> {
+    // Always `Some`, except during the drop.
+    page: Box<Page<T>>,
+    // `None` if the lock could not be acquired (e.g. the limit on the locked memory
+    // of the process is exhausted); the value can still be used then.
+    guard: Option<region::LockGuard>,
+}
+
+#[cfg(feature = "secure-memory")]
+impl<T> Locked<T> {
+    fn new(value: T) -> Self {
+        let page = Box::new(Page(Some(value)));
+        let guard = region::lock(&*page as *const Page<T>, core::mem::size_of::<Page<T>>()).ok();
+        Self { page, guard }
+    }
+}
+
+#[cfg(feature = "secure-memory")]
+impl<T> Drop for Locked<T> {
+    fn drop(&mut self) {
+        // Assigning drops the old value where it is, in the locked page, instead of moving it out.
+        self.page.0 = None;
+        // Unlock while the allocation is still owned; the box is freed after this function.
+        drop(self.guard.take());
+    }
+}
+
+#[cfg(feature = "secure-memory")]
+impl<T> Deref for Locked<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        self.page
+            .0
+            .as_ref()
+            .expect("the value is only removed on drop")
+    }
+}
+
+#[cfg(feature = "secure-memory")]
+impl<T: Clone> Clone for Locked<T> {
+    fn clone(&self) -> Self {
+        Self::new(T::clone(self))
+    }
+}
+
+#[cfg(all(test, feature = "secure-memory"))]
+mod tests {
+
+    use core::cell::Cell;
+
+    use super::secret;
+
+    #[test]
+    fn test_locked() {
+        let value = secret([1u8; 32]);
+        assert_eq!(*value, [1u8; 32]);
+        assert_eq!(*value.clone(), [1u8; 32]);
+        assert_eq!(&*value as *const _ as usize % 4096, 0);
+    }
+
+    // Records the address it was dropped at.
+    struct DropTracker<'a>(&'a Cell<usize>);
+
+    impl Drop for DropTracker<'_> {
+        fn drop(&mut self) {
+            self.0.set(self as *const Self as usize);
+        }
+    }
+
+    #[test]
+    fn test_locked_drop() {
+        let dropped_at = Cell::new(0);
+        let value = secret(DropTracker(&dropped_at));
+        let address = &*value as *const DropTracker<'_> as usize;
+        assert_eq!(dropped_at.get(), 0);
+
+        // The value is dropped (and thus wiped) in the locked page, not moved out of it first.
+        drop(value);
+        assert_eq!(dropped_at.get(), address);
+    }
+}
@@ -0,0 +1,248 @@
+//! Usage policies restricting what a [`Signer`](crate::Signer) can be used for.
+//!
+//! The delegating party's signing key only needs to sign key fragments.
+//! Attaching a [`SigningPolicy`] to the signer makes it refuse other messages
+//! (unless they start with one of the allowed prefixes),
+//! optionally limits the number of signatures per period of time,
+//! and reports every attempt to an audit callback,
+//! so that a compromised application layer cannot silently use the key for anything else.
+
+use alloc::boxed::Box;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::fmt;
+use core::sync::atomic::{AtomicU64, Ordering};
+
+/// Errors returned when a signature is refused by a [`SigningPolicy`].
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum SigningPolicyError {
+    /// The message is not a key fragment and does not start with any of the allowed prefixes.
+    MessageNotAllowed,
+    /// The maximum number of signatures in the current period has been reached.
+    RateLimitExceeded,
+}
+
+impl fmt::Display for SigningPolicyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::MessageNotAllowed => write!(f, "the message is not allowed by the policy"),
+            Self::RateLimitExceeded => write!(f, "signature rate limit exceeded"),
+        }
+    }
+}
+
+/// A signing attempt, as reported to the audit callback of a [`SigningPolicy`].
+#[derive(Debug)]
+pub struct SigningEvent<'a> {
+    /// The message to sign.
+    pub message: &'a [u8],
+    /// `true` if the message is a key fragment signed during its creation.
+    pub key_fragment: bool,
+    /// Whether the policy allowed the signature.
+    pub result: Result<(), SigningPolicyError>,
+}
+
+type AuditCallback = dyn Fn(&SigningEvent<'_>) + Send + Sync;
+type Clock = dyn Fn() -> u64 + Send + Sync;
+
+struct RateLimit {
+    max_signatures: u32,
+    period: u64,
+    clock: Arc<Clock>,
+    // The index of the current period (upper 32 bits)
+    // and the number of signatures made in it (lower 32 bits),
+    // so that both are updated atomically.
+    state: AtomicU64,
+}
+
+impl RateLimit {
+    fn acquire(&self) -> Result<(), SigningPolicyError> {
+        let window = ((self.clock)() / self.period) as u32;
+        let mut state = self.state.load(Ordering::Acquire);
+        loop {
+            let count = if (state >> 32) as u32 == window {
+                state as u32
+            } else {
+                0
+            };
+            if count >= self.max_signatures {
+                return Err(SigningPolicyError::RateLimitExceeded);
+            }
+            let new_state = (window as u64) << 32 | (count + 1) as u64;
+            match self.state.compare_exchange_weak(
+                state,
+                new_state,
+                Ordering::AcqRel,
+                Ordering::Acquire,
+            ) {
+                Ok(_) => return Ok(()),
+                Err(current) => state = current,
+            }
+        }
+    }
+}
+
+/// A usage policy for a [`Signer`](crate::Signer), attached with
+/// [`Signer::with_policy()`](crate::Signer::with_policy).
+///
+/// By default only key fragments are signed.
+/// The state of the rate limit is shared between the clones of the signer.
+pub struct SigningPolicy {
+    allowed_prefixes: Vec<Box<[u8]>>,
+    rate_limit: Option<RateLimit>,
+    audit: Option<Arc<AuditCallback>>,
+}
+
+impl SigningPolicy {
+    /// Creates a policy allowing only the signatures of key fragments, without a rate limit.
+    pub fn new() -> Self {
+        Self {
+            allowed_prefixes: Vec::new(),
+            rate_limit: None,
+            audit: None,
+        }
+    }
+
+    /// Also allows signing the messages starting with the given prefix
+    /// (e.g. the domain separation tag of an application protocol).
+    pub fn with_allowed_prefix(mut self, prefix: &[u8]) -> Self {
+        self.allowed_prefixes.push(prefix.into());
+        self
+    }
+
+    /// Limits the number of signatures to `max_signatures` per `period`.
+    ///
+    /// `clock` returns the current time in the same (application-defined) unit as `period`,
+    /// e.g. UNIX seconds. The periods are aligned to the multiples of `period`.
+    /// Note that each key fragment takes two signatures.
+    ///
+    /// Panics if `period` is zero.
+    pub fn with_rate_limit(
+        self,
+        max_signatures: u32,
+        period: u64,
+        clock: impl Fn() -> u64 + Send + Sync + 'static,
+    ) -> Self {
+        assert!(period > 0, "the period must be positive");
+        Self {
+            rate_limit: Some(RateLimit {
+                max_signatures,
+                period,
+                clock: Arc::new(clock),
+                state: AtomicU64::new(0),
+            }),
+            ..self
+        }
+    }
+
+    /// Calls `callback` on every signing attempt, including the refused ones.
+    pub fn with_audit(self, callback: impl Fn(&SigningEvent<'_>) + Send + Sync + 'static) -> Self {
+        Self {
+            audit: Some(Arc::new(callback)),
+            ..self
+        }
+    }
+
+    /// Checks the signing attempt against the policy, counting it towards the rate limit
+    /// if it is allowed, and reports it to the audit callback.
+    pub(crate) fn check(
+        &self,
+        message: &[u8],
+        key_fragment: bool,
+    ) -> Result<(), SigningPolicyError> {
+        let result = if key_fragment
+            || self
+                .allowed_prefixes
+                .iter()
+                .any(|prefix| message.starts_with(prefix))
+        {
+            self.rate_limit
+                .as_ref()
+                .map_or(Ok(()), |rate_limit| rate_limit.acquire())
+        } else {
+            Err(SignerError::Policy(SigningPolicyError::MessageNotAllowed))
+        };
+
+        if let Some(audit) = &self.audit {
+            audit(&SigningEvent {
+                message,
+                key_fragment,
+                result,
+            });
+        }
+
+        result
+    }
+}
+
+impl Default for SigningPolicy {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Debug for SigningPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SigningPolicy")
+            .field("allowed_prefixes", &self.allowed_prefixes)
+            .field(
+                "max_signatures",
+                &self.rate_limit.as_ref().map(|limit| limit.max_signatures),
+            )
+            .field(
+                "period",
+                &self.rate_limit.as_ref().map(|limit| limit.period),
+            )
+            .field("audit", &self.audit.is_some())
+            .finish()
+    }
+}
+
+#[cfg(all(test, feature = "role-alice"))]
+mod tests {
+
+    use alloc::sync::Arc;
+    use core::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+
+    use super::{SigningPolicy, SigningPolicyError};
+    use crate::{generate_kfrags, PublicKey, SecretKey, Signer, SignerError};
+
+    #[test]
+    fn test_signing_policy() {
+        let now = Arc::new(AtomicU64::new(1000));
+        let refused = Arc::new(AtomicUsize::new(0));
+        let clock = now.clone();
+        let audit_refused = refused.clone();
+        let policy = SigningPolicy::new()
+            .with_allowed_prefix(b"APP_DST")
+            .with_rate_limit(4, 60, move || clock.load(Ordering::SeqCst))
+            .with_audit(move |event| {
+                if event.result.is_err() {
+                    audit_refused.fetch_add(1, Ordering::SeqCst);
+                }
+            });
+
+        let delegating_sk = SecretKey::random();
+        let signer = Signer::new(&SecretKey::random()).with_policy(policy);
+        let receiving_pk = PublicKey::from_secret_key(&SecretKey::random());
+
+        assert_eq!(
+            signer.try_sign(b"arbitrary message"),
+            Err(SignerError::Policy(SigningPolicyError::MessageNotAllowed))
+        );
+
+        // Two key fragments take the whole limit of the period.
+        let kfrags = generate_kfrags(&delegating_sk, &receiving_pk, &signer, 2, 2, true, true);
+        assert_eq!(kfrags.len(), 2);
+        assert_eq!(
+            signer.try_sign(b"APP_DST message"),
+            Err(SignerError::Policy(SigningPolicyError::RateLimitExceeded))
+        );
+
+        now.store(1080, Ordering::SeqCst);
+        let signature = signer.try_sign(b"APP_DST message").unwrap();
+        assert!(signature.verify(&signer.verifying_key(), b"APP_DST message"));
+
+        assert_eq!(refused.load(Ordering::SeqCst), 2);
+    }
+}
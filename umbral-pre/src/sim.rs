@@ -0,0 +1,392 @@
+//! In-memory simulation of a network of reencrypting proxies ("Ursulas"),
+//! enabled with the `sim` feature.
+//!
+//! Intended for integration-testing the retry and verification logic of applications.
+//! Each simulated Ursula holds a key fragment and can be configured to misbehave:
+//! return capsule fragments that fail verification, respond with a delay, or not respond at all.
+//! Delays are not real; they are reported along with the responses,
+//! and the responses are returned in the order of arrival.
+//!
+//! [`simulate_policy()`] estimates the outcome of a threshold policy
+//! without running the cryptographic operations, for choosing the policy parameters.
+
+use alloc::vec::Vec;
+
+use rand_core::{CryptoRng, RngCore};
+
+use crate::capsule::Capsule;
+use crate::capsule_frag::CapsuleFrag;
+use crate::key_frag::VerifiedKeyFrag;
+use crate::keys::{PublicKey, SecretKey};
+use crate::metadata::Metadata;
+use crate::pre::reencrypt_with_rng;
+
+/// The way a simulated Ursula handles reencryption requests.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Behavior {
+    /// Reencrypts the given capsule with its key fragment.
+    Honest,
+    /// Returns a capsule fragment created for an unrelated capsule,
+    /// which fails verification.
+    Byzantine,
+}
+
+/// Configuration of a simulated Ursula.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct UrsulaConfig {
+    /// Reencryption behavior.
+    pub behavior: Behavior,
+    /// Minimum simulated response latency (in milliseconds).
+    pub min_latency_ms: u32,
+    /// Maximum simulated response latency (in milliseconds).
+    pub max_latency_ms: u32,
+    /// Probability of a request being dropped (from 0 to 1).
+    pub drop_rate: f64,
+}
+
+impl Default for UrsulaConfig {
+    fn default() -> Self {
+        Self {
+            behavior: Behavior::Honest,
+            min_latency_ms: 0,
+            max_latency_ms: 0,
+            drop_rate: 0.,
+        }
+    }
+}
+
+/// A response from a simulated Ursula.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SimResponse {
+    /// The index of the responding Ursula in the network.
+    pub ursula: usize,
+    /// Simulated latency of the response (in milliseconds).
+    pub latency_ms: u32,
+    /// The returned capsule fragment (not verified, as if received over the network).
+    pub cfrag: CapsuleFrag,
+}
+
+struct SimUrsula {
+    kfrag: VerifiedKeyFrag,
+    config: UrsulaConfig,
+}
+
+impl SimUrsula {
+    fn respond(
+        &self,
+        rng: &mut (impl CryptoRng + RngCore),
+        index: usize,
+        capsule: &Capsule,
+        metadata: Option<&Metadata>,
+    ) -> Option<SimResponse> {
+        if uniform_unit(rng) < self.config.drop_rate {
+            return None;
+        }
+
+        let min = self.config.min_latency_ms;
+        let max = self.config.max_latency_ms;
+        let latency_ms = if max > min {
+            // The modulo bias is irrelevant for a simulation.
+            min + (rng.next_u64() % ((max - min) as u64 + 1)) as u32
+        } else {
+            min
+        };
+
+        let verified_cfrag = match self.config.behavior {
+            Behavior::Honest => reencrypt_with_rng(rng, capsule, &self.kfrag, metadata),
+            Behavior::Byzantine => {
+                let unrelated_pk = PublicKey::from_secret_key(&SecretKey::random_with_rng(rng));
+                let unrelated_capsule = Capsule::from_public_key(rng, &unrelated_pk).0;
+                reencrypt_with_rng(rng, &unrelated_capsule, &self.kfrag, metadata)
+            }
+        };
+
+        Some(SimResponse {
+            ursula: index,
+            latency_ms,
+            cfrag: verified_cfrag.cfrag,
+        })
+    }
+}
+
+/// An in-memory network of simulated Ursulas, one per key fragment.
+pub struct SimNetwork {
+    ursulas: Vec<SimUrsula>,
+}
+
+impl SimNetwork {
+    /// Creates a network where each Ursula holds one of `kfrags`
+    /// and is configured with `config`.
+    pub fn new(kfrags: &[VerifiedKeyFrag], config: UrsulaConfig) -> Self {
+        Self {
+            ursulas: kfrags
+                .iter()
+                .map(|kfrag| SimUrsula {
+                    kfrag: kfrag.clone(),
+                    config,
+                })
+                .collect(),
+        }
+    }
+
+    /// Returns the number of Ursulas in the network.
+    pub fn len(&self) -> usize {
+        self.ursulas.len()
+    }
+
+    /// Returns `true` if the network has no Ursulas.
+    pub fn is_empty(&self) -> bool {
+        self.ursulas.is_empty()
+    }
+
+    /// Replaces the configuration of the Ursula with the given index.
+    ///
+    /// Panics if `index` is out of bounds.
+    pub fn configure(&mut self, index: usize, config: UrsulaConfig) {
+        self.ursulas[index].config = config;
+    }
+
+    /// Sends a reencryption request to the Ursula with the given index.
+    /// Returns `None` if the request was dropped.
+    ///
+    /// Panics if `index` is out of bounds.
+    pub fn request(
+        &self,
+        rng: &mut (impl CryptoRng + RngCore),
+        index: usize,
+        capsule: &Capsule,
+        metadata: Option<&Metadata>,
+    ) -> Option<SimResponse> {
+        self.ursulas[index].respond(rng, index, capsule, metadata)
+    }
+
+    /// Sends a reencryption request to every Ursula in the network.
+    /// Returns the responses that were not dropped, ordered by their latency.
+    pub fn broadcast(
+        &self,
+        rng: &mut (impl CryptoRng + RngCore),
+        capsule: &Capsule,
+        metadata: Option<&Metadata>,
+    ) -> Vec<SimResponse> {
+        let mut responses: Vec<_> = self
+            .ursulas
+            .iter()
+            .enumerate()
+            .filter_map(|(index, ursula)| ursula.respond(rng, index, capsule, metadata))
+            .collect();
+        responses.sort_by_key(|response| response.latency_ms);
+        responses
+    }
+}
+
+/// The distribution of Ursulas' response latencies used by [`simulate_policy()`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LatencyModel {
+    /// Every response takes the same time (in milliseconds).
+    Constant(u32),
+    /// The response time is uniformly distributed between the bounds (in milliseconds).
+    Uniform {
+        /// Minimum latency.
+        min_ms: u32,
+        /// Maximum latency.
+        max_ms: u32,
+    },
+}
+
+impl LatencyModel {
+    fn sample(&self, rng: &mut (impl CryptoRng + RngCore)) -> u32 {
+        match *self {
+            Self::Constant(latency_ms) => latency_ms,
+            Self::Uniform { min_ms, max_ms } => {
+                if max_ms > min_ms {
+                    min_ms + (rng.next_u64() % ((max_ms - min_ms) as u64 + 1)) as u32
+                } else {
+                    min_ms
+                }
+            }
+        }
+    }
+}
+
+/// The result of [`simulate_policy()`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PolicySimulation {
+    /// The probability that at least `threshold` Ursulas respond (computed exactly).
+    pub success_probability: f64,
+    /// The mean time until the `threshold`-th response, over the successful trials
+    /// (in milliseconds), or `None` if no trial succeeded.
+    pub expected_latency_ms: Option<f64>,
+}
+
+fn uniform_unit(rng: &mut (impl CryptoRng + RngCore)) -> f64 {
+    // `as f64` is lossless for `u32`.
+    rng.next_u32() as f64 / (u32::MAX as f64 + 1.)
+}
+
+/// Estimates the outcome of requesting reencryption from a `threshold`-of-`shares` policy,
+/// where each Ursula fails to respond with probability `failure_rate`
+/// and responds with a latency drawn from `latency_model`.
+///
+/// The latency is estimated from `trials` random trials,
+/// and includes only the network; the time of the operations themselves
+/// (verifying `threshold` capsule fragments and opening the capsule)
+/// can be measured with [`bench::calibrate()`](crate::bench) (requires the `std` feature).
+pub fn simulate_policy(
+    rng: &mut (impl CryptoRng + RngCore),
+    threshold: usize,
+    shares: usize,
+    failure_rate: f64,
+    latency_model: LatencyModel,
+    trials: usize,
+) -> PolicySimulation {
+    // P(at least `threshold` successes out of `shares`), summing the binomial terms.
+    let p = 1. - failure_rate;
+    let mut success_probability = 0.;
+    let mut binomial = 1.;
+    for k in 0..=shares {
+        if k > 0 {
+            binomial = binomial * (shares - k + 1) as f64 / k as f64;
+        }
+        if k >= threshold {
+            let mut term = binomial;
+            for _ in 0..k {
+                term *= p;
+            }
+            for _ in k..shares {
+                term *= failure_rate;
+            }
+            success_probability += term;
+        }
+    }
+
+    let mut latencies = Vec::with_capacity(shares);
+    let mut total_latency = 0.;
+    let mut successes = 0usize;
+    for _ in 0..trials {
+        latencies.clear();
+        for _ in 0..shares {
+            if uniform_unit(rng) >= failure_rate {
+                latencies.push(latency_model.sample(rng));
+            }
+        }
+        if threshold > 0 && latencies.len() >= threshold {
+            latencies.sort_unstable();
+            total_latency += latencies[threshold - 1] as f64;
+            successes += 1;
+        }
+    }
+
+    PolicySimulation {
+        success_probability,
+        expected_latency_ms: if successes > 0 {
+            Some(total_latency / successes as f64)
+        } else {
+            None
+        },
+    }
+}
+
+#[cfg(all(test, feature = "role-alice", feature = "role-bob"))]
+mod tests {
+
+    use alloc::vec::Vec;
+
+    use rand_core::OsRng;
+
+    use super::{simulate_policy, Behavior, LatencyModel, SimNetwork, UrsulaConfig};
+    use crate::{
+        decapsulate_reencrypted, encapsulate, generate_kfrags, PublicKey, SecretKey, Signer,
+    };
+
+    #[test]
+    fn test_network() {
+        let delegating_sk = SecretKey::random();
+        let delegating_pk = PublicKey::from_secret_key(&delegating_sk);
+
+        let signing_sk = SecretKey::random();
+        let signer = Signer::new(&signing_sk);
+        let verifying_pk = PublicKey::from_secret_key(&signing_sk);
+
+        let receiving_sk = SecretKey::random();
+        let receiving_pk = PublicKey::from_secret_key(&receiving_sk);
+
+        let (capsule, key_seed) = encapsulate(&delegating_pk);
+        let kfrags = generate_kfrags(&delegating_sk, &receiving_pk, &signer, 2, 4, true, true);
+
+        let honest = UrsulaConfig {
+            min_latency_ms: 10,
+            max_latency_ms: 100,
+            ..UrsulaConfig::default()
+        };
+        let mut network = SimNetwork::new(&kfrags, honest);
+        network.configure(
+            0,
+            UrsulaConfig {
+                behavior: Behavior::Byzantine,
+                ..honest
+            },
+        );
+        network.configure(
+            1,
+            UrsulaConfig {
+                drop_rate: 1.,
+                ..honest
+            },
+        );
+
+        assert!(network.request(&mut OsRng, 1, &capsule, None).is_none());
+
+        let responses = network.broadcast(&mut OsRng, &capsule, None);
+        assert_eq!(responses.len(), 3);
+        assert!(responses
+            .windows(2)
+            .all(|pair| pair[0].latency_ms <= pair[1].latency_ms));
+        assert!(responses
+            .iter()
+            .all(|response| (10..=100).contains(&response.latency_ms)));
+
+        let mut verified_cfrags = Vec::new();
+        for response in responses {
+            let result =
+                response
+                    .cfrag
+                    .verify(&capsule, &verifying_pk, &delegating_pk, &receiving_pk, None);
+            assert_eq!(result.is_err(), response.ursula == 0);
+            if let Ok(verified_cfrag) = result {
+                verified_cfrags.push(verified_cfrag);
+            }
+        }
+
+        let key_seed_reenc =
+            decapsulate_reencrypted(&receiving_sk, &delegating_pk, &capsule, &verified_cfrags)
+                .unwrap();
+        assert_eq!(key_seed, key_seed_reenc);
+    }
+
+    #[test]
+    fn test_simulate_policy() {
+        let result = simulate_policy(&mut OsRng, 3, 3, 0., LatencyModel::Constant(50), 10);
+        assert_eq!(result.success_probability, 1.);
+        assert_eq!(result.expected_latency_ms, Some(50.));
+
+        // 2-of-3 with each Ursula failing half of the time: P = 3/8 + 1/8.
+        let result = simulate_policy(
+            &mut OsRng,
+            2,
+            3,
+            0.5,
+            LatencyModel::Uniform {
+                min_ms: 10,
+                max_ms: 100,
+            },
+            1000,
+        );
+        assert!((result.success_probability - 0.5).abs() < 1e-12);
+        let latency = result.expected_latency_ms.unwrap();
+        assert!((10. ..=100.).contains(&latency));
+
+        let result = simulate_policy(&mut OsRng, 2, 3, 1., LatencyModel::Constant(50), 10);
+        assert_eq!(result.success_probability, 0.);
+        assert_eq!(result.expected_latency_ms, None);
+    }
+}
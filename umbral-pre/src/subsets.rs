@@ -0,0 +1,208 @@
+//! Selection of threshold-sized subsets of collected capsule fragments.
+//!
+//! A set of fragments can only be combined if all of them come from the same
+//! [`generate_kfrags`](crate::generate_kfrags) call and none of them is repeated.
+//! When more than `threshold` fragments are collected (possibly from different policies,
+//! or with duplicates), [`decapsulate_reencrypted`](crate::decapsulate_reencrypted)
+//! combines all the usable ones; these functions allow one to pick a combinable subset
+//! of exactly `threshold` fragments instead (e.g. the cheapest one).
+
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+
+use crate::capsule_frag::VerifiedCapsuleFrag;
+
+fn is_combinable(cfrags: &[VerifiedCapsuleFrag], indices: &[usize]) -> bool {
+    let first = &cfrags[indices[0]].cfrag;
+    indices.iter().enumerate().all(|(i, idx)| {
+        let cfrag = &cfrags[*idx].cfrag;
+        cfrag.precursor == first.precursor
+            && indices[..i]
+                .iter()
+                .all(|other| cfrags[*other].cfrag.kfrag_id != cfrag.kfrag_id)
+    })
+}
+
+fn select(cfrags: &[VerifiedCapsuleFrag], indices: &[usize]) -> Box<[VerifiedCapsuleFrag]> {
+    indices
+        .iter()
+        .map(|idx| cfrags[*idx].clone())
+        .collect::<Vec<_>>()
+        .into_boxed_slice()
+}
+
+/// An iterator over the combinable `threshold`-sized subsets of capsule fragments,
+/// returned by [`subsets()`].
+pub struct CapsuleFragSubsets<'a> {
+    cfrags: &'a [VerifiedCapsuleFrag],
+    indices: Option<Vec<usize>>,
+}
+
+impl<'a> CapsuleFragSubsets<'a> {
+    // Advances `indices` to the next combination in the lexicographic order.
+    fn advance(&mut self) {
+        let n = self.cfrags.len();
+        if let Some(indices) = self.indices.as_mut() {
+            let k = indices.len();
+            for i in (0..k).rev() {
+                if indices[i] < n - k + i {
+                    indices[i] += 1;
+                    for j in i + 1..k {
+                        indices[j] = indices[j - 1] + 1;
+                    }
+                    return;
+                }
+            }
+        }
+        self.indices = None;
+    }
+}
+
+impl<'a> Iterator for CapsuleFragSubsets<'a> {
+    type Item = Box<[VerifiedCapsuleFrag]>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let indices = self.indices.clone()?;
+            self.advance();
+            if is_combinable(self.cfrags, &indices) {
+                return Some(select(self.cfrags, &indices));
+            }
+        }
+    }
+}
+
+/// Returns an iterator over all subsets of `cfrags` of size `threshold`
+/// that can be combined together (that is, come from the same set of key fragments
+/// and have no repetitions).
+///
+/// The subsets are returned in the lexicographic order of the fragment positions in `cfrags`,
+/// so if `cfrags` is sorted by preference (e.g. by the response time),
+/// the preferred subsets come first.
+/// The number of subsets grows combinatorially, so this is only practical for small inputs;
+/// see [`best_subset()`] for a direct selection.
+pub fn subsets(cfrags: &[VerifiedCapsuleFrag], threshold: usize) -> CapsuleFragSubsets<'_> {
+    let indices = if threshold > 0 && threshold <= cfrags.len() {
+        Some((0..threshold).collect())
+    } else {
+        None
+    };
+    CapsuleFragSubsets { cfrags, indices }
+}
+
+/// Returns a combinable subset of `cfrags` of size `threshold`
+/// with the lowest total cost, or `None` if there is no such subset.
+///
+/// `costs` are arbitrary hints (e.g. response latencies or fees), one for each fragment.
+/// Panics if `costs` and `cfrags` have different lengths.
+pub fn best_subset(
+    cfrags: &[VerifiedCapsuleFrag],
+    costs: &[u32],
+    threshold: usize,
+) -> Option<Box<[VerifiedCapsuleFrag]>> {
+    assert!(
+        cfrags.len() == costs.len(),
+        "`costs` must have the same length as `cfrags`"
+    );
+    if threshold == 0 {
+        return None;
+    }
+
+    let mut order: Vec<usize> = (0..cfrags.len()).collect();
+    order.sort_by_key(|idx| costs[*idx]);
+
+    // For each set of key fragments (identified by the precursor),
+    // greedily take the cheapest distinct fragments.
+    let mut best: Option<(u64, Vec<usize>)> = None;
+    for (pos, idx) in order.iter().enumerate() {
+        let precursor = cfrags[*idx].cfrag.precursor;
+        let seen_before = order[..pos]
+            .iter()
+            .any(|other| cfrags[*other].cfrag.precursor == precursor);
+        if seen_before {
+            continue;
+        }
+
+        let mut selected = Vec::<usize>::with_capacity(threshold);
+        for candidate in order[pos..].iter() {
+            let cfrag = &cfrags[*candidate].cfrag;
+            if cfrag.precursor == precursor
+                && selected
+                    .iter()
+                    .all(|other| cfrags[*other].cfrag.kfrag_id != cfrag.kfrag_id)
+            {
+                selected.push(*candidate);
+                if selected.len() == threshold {
+                    break;
+                }
+            }
+        }
+
+        if selected.len() == threshold {
+            let total: u64 = selected.iter().map(|idx| costs[*idx] as u64).sum();
+            let is_better = match &best {
+                Some((best_total, _)) => total < *best_total,
+                None => true,
+            };
+            if is_better {
+                best = Some((total, selected));
+            }
+        }
+    }
+
+    best.map(|(_, indices)| select(cfrags, &indices))
+}
+
+#[cfg(all(
+    test,
+    feature = "role-alice",
+    feature = "role-ursula",
+    feature = "role-bob"
+))]
+mod tests {
+
+    use alloc::vec::Vec;
+
+    use super::{best_subset, subsets};
+    use crate::{
+        decapsulate_reencrypted, encapsulate, generate_kfrags, reencrypt, PublicKey, SecretKey,
+        Signer, VerifiedCapsuleFrag,
+    };
+
+    #[test]
+    fn test_subsets() {
+        let delegating_sk = SecretKey::random();
+        let delegating_pk = PublicKey::from_secret_key(&delegating_sk);
+        let signer = Signer::new(&SecretKey::random());
+        let receiving_sk = SecretKey::random();
+        let receiving_pk = PublicKey::from_secret_key(&receiving_sk);
+
+        let (capsule, key_seed) = encapsulate(&delegating_pk);
+        let kfrags = generate_kfrags(&delegating_sk, &receiving_pk, &signer, 2, 3, true, true);
+        let other_kfrags =
+            generate_kfrags(&delegating_sk, &receiving_pk, &signer, 2, 3, true, true);
+
+        // Two fragments from the first set (one of them repeated),
+        // and one from another set.
+        let cfrag0 = reencrypt(&capsule, &kfrags[0], None);
+        let cfrag1 = reencrypt(&capsule, &kfrags[1], None);
+        let other_cfrag = reencrypt(&capsule, &other_kfrags[0], None);
+        let cfrags: Vec<VerifiedCapsuleFrag> =
+            [cfrag0.clone(), other_cfrag, cfrag0.clone(), cfrag1.clone()].to_vec();
+
+        let all: Vec<_> = subsets(&cfrags, 2).collect();
+        assert_eq!(all.len(), 2);
+        assert_eq!(&*all[0], &[cfrag0.clone(), cfrag1.clone()]);
+        assert_eq!(&*all[1], &[cfrag0.clone(), cfrag1.clone()]);
+        assert_eq!(subsets(&cfrags, 3).count(), 0);
+        assert_eq!(subsets(&cfrags, 5).count(), 0);
+
+        let best = best_subset(&cfrags, &[10, 1, 5, 20], 2).unwrap();
+        assert_eq!(&*best, &[cfrag0, cfrag1]);
+        assert!(best_subset(&cfrags, &[10, 1, 5, 20], 3).is_none());
+
+        let key_seed_reenc =
+            decapsulate_reencrypted(&receiving_sk, &delegating_pk, &capsule, &best).unwrap();
+        assert_eq!(key_seed, key_seed_reenc);
+    }
+}
@@ -0,0 +1,331 @@
+//! Deterministic generation of known-answer test vectors.
+//!
+//! All the randomness is taken from the ChaCha20 (RFC 8439) keystream
+//! with the key set to a fixed seed and an all-zero nonce,
+//! so the same seed always results in byte-for-byte identical objects.
+//! Implementations in other languages can use the published vectors below
+//! to verify their compatibility with this crate.
+//! The published vectors use the default ECDSA signatures,
+//! and do not match the output of a build with the `schnorr` feature.
+//!
+//! The RNG is consumed in the following order:
+//! the delegating key, the signing key, the receiving key,
+//! the encryption (capsule and then DEM nonce), the kfrag generation,
+//! and the reencryption with each kfrag in turn.
+//!
+//! WARNING: never use this mode to produce keys or ciphertexts used in practice.
+
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+
+use chacha20::cipher::{NewCipher, StreamCipher};
+use chacha20::{ChaCha20, Key, Nonce};
+use rand_core::{impls, CryptoRng, Error, RngCore};
+
+use crate::capsule_frag::VerifiedCapsuleFrag;
+use crate::keys::{PublicKey, SecretKey, Signer};
+use crate::pre::{encrypt_with_rng, generate_kfrags_with_rng};
+use crate::traits::SerializableToArray;
+
+/// A seeded RNG producing a reproducible stream of random bytes
+/// (the ChaCha20 keystream for the key `seed` and an all-zero nonce).
+pub struct DeterministicRng(ChaCha20);
+
+impl DeterministicRng {
+    /// Creates an RNG from the given seed.
+    pub fn from_seed(seed: &[u8; 32]) -> Self {
+        let nonce = Nonce::default();
+        Self(ChaCha20::new(Key::from_slice(seed), &nonce))
+    }
+}
+
+impl RngCore for DeterministicRng {
+    fn next_u32(&mut self) -> u32 {
+        impls::next_u32_via_fill(self)
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        impls::next_u64_via_fill(self)
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        for byte in dest.iter_mut() {
+            *byte = 0;
+        }
+        self.0.apply_keystream(dest)
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), Error> {
+        self.fill_bytes(dest);
+        Ok(())
+    }
+}
+
+impl CryptoRng for DeterministicRng {}
+
+/// Serialized objects produced in a single deterministic reencryption round.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TestVectors {
+    /// The delegating secret key.
+    pub delegating_sk: Box<[u8]>,
+    /// The delegating public key.
+    pub delegating_pk: Box<[u8]>,
+    /// The signing secret key.
+    pub signing_sk: Box<[u8]>,
+    /// The verifying public key.
+    pub verifying_pk: Box<[u8]>,
+    /// The receiving secret key.
+    pub receiving_sk: Box<[u8]>,
+    /// The receiving public key.
+    pub receiving_pk: Box<[u8]>,
+    /// The capsule.
+    pub capsule: Box<[u8]>,
+    /// The ciphertext.
+    pub ciphertext: Box<[u8]>,
+    /// The key fragments (created with both the delegating and the receiving keys signed).
+    pub kfrags: Box<[Box<[u8]>]>,
+    /// The capsule fragments, one for each key fragment.
+    pub cfrags: Box<[Box<[u8]>]>,
+}
+
+/// Runs a full reencryption round with the randomness taken from
+/// [`DeterministicRng`] initialized with `seed`, and returns the serialized results.
+///
+/// `metadata` is bound to the capsule fragments as is, without the [`Metadata`](crate::Metadata)
+/// encoding, so the capsule fragments should be verified with
+/// [`CapsuleFrag::verify_canonicalized`](crate::CapsuleFrag::verify_canonicalized)
+/// and [`RawMetadata`](crate::RawMetadata).
+pub fn generate(
+    seed: &[u8; 32],
+    plaintext: &[u8],
+    metadata: Option<&[u8]>,
+    threshold: usize,
+    num_kfrags: usize,
+) -> TestVectors {
+    let mut rng = DeterministicRng::from_seed(seed);
+
+    let delegating_sk = SecretKey::random_with_rng(&mut rng);
+    let delegating_pk = PublicKey::from_secret_key(&delegating_sk);
+
+    let signing_sk = SecretKey::random_with_rng(&mut rng);
+    let signer = Signer::new(&signing_sk);
+    let verifying_pk = PublicKey::from_secret_key(&signing_sk);
+
+    let receiving_sk = SecretKey::random_with_rng(&mut rng);
+    let receiving_pk = PublicKey::from_secret_key(&receiving_sk);
+
+    // The only possible error here is the plaintext being too large for the DEM,
+    // which is not something we expect in test vectors.
+    let (capsule, ciphertext) = encrypt_with_rng(&mut rng, &delegating_pk, plaintext).unwrap();
+
+    let vkfrags = generate_kfrags_with_rng(
+        &mut rng,
+        &delegating_sk,
+        &receiving_pk,
+        &signer,
+        threshold,
+        num_kfrags,
+        true,
+        true,
+    );
+
+    let cfrags: Vec<_> = vkfrags
+        .iter()
+        .map(|vkfrag| {
+            VerifiedCapsuleFrag::reencrypted(&mut rng, &capsule, &vkfrag.kfrag, metadata)
+                .to_array()
+                .to_vec()
+                .into_boxed_slice()
+        })
+        .collect();
+
+    let kfrags: Vec<_> = vkfrags
+        .iter()
+        .map(|vkfrag| vkfrag.to_array().to_vec().into_boxed_slice())
+        .collect();
+
+    TestVectors {
+        delegating_sk: delegating_sk.to_array().to_vec().into_boxed_slice(),
+        delegating_pk: delegating_pk.to_array().to_vec().into_boxed_slice(),
+        signing_sk: signing_sk.to_array().to_vec().into_boxed_slice(),
+        verifying_pk: verifying_pk.to_array().to_vec().into_boxed_slice(),
+        receiving_sk: receiving_sk.to_array().to_vec().into_boxed_slice(),
+        receiving_pk: receiving_pk.to_array().to_vec().into_boxed_slice(),
+        capsule: capsule.to_array().to_vec().into_boxed_slice(),
+        ciphertext,
+        kfrags: kfrags.into_boxed_slice(),
+        cfrags: cfrags.into_boxed_slice(),
+    }
+}
+
+/// The seed of the published known-answer test.
+pub const KAT_SEED: &[u8; 32] = b"umbral-pre known-answer test vec";
+
+/// The plaintext of the published known-answer test.
+pub const KAT_PLAINTEXT: &[u8] = b"peace at dawn";
+
+/// The metadata of the published known-answer test.
+pub const KAT_METADATA: &[u8] = b"metadata";
+
+/// The threshold of the published known-answer test.
+pub const KAT_THRESHOLD: usize = 2;
+
+/// The number of key fragments in the published known-answer test.
+pub const KAT_NUM_KFRAGS: usize = 3;
+
+/// The expected delegating public key (hex-encoded).
+pub const KAT_DELEGATING_PK: &str = concat!(
+    "026e7801d974415d69e58b904f8bdaad7eef28646da90939d80c5002963fbac5",
+    "ef",
+);
+
+/// The expected verifying public key (hex-encoded).
+pub const KAT_VERIFYING_PK: &str = concat!(
+    "036c2f91f5710a3e9273ab04740b2defd45bf51ed7fdd44430414150ff924657",
+    "dc",
+);
+
+/// The expected receiving public key (hex-encoded).
+pub const KAT_RECEIVING_PK: &str = concat!(
+    "02b5333d88857bedd729aff6df3a13f675553d582ba2fe71062887a3d1d18e83",
+    "62",
+);
+
+/// The expected capsule (hex-encoded).
+pub const KAT_CAPSULE: &str = concat!(
+    "031b81ae2b0dd9847f7b3ff5364f9ca83de44afccd57fa6bf5aed54b64a05f43",
+    "9e02d114e1edc93cb35088528ad1f43fb63adba5204bb80495bf0c5d91e3a4fc",
+    "94f16d1c17d967a4b2b10f385b4fb7fa208b3119e1b16d3b53cf47e76314338f",
+    "ef7e",
+);
+
+/// The expected ciphertext (hex-encoded), starting with the DEM algorithm identifier.
+pub const KAT_CIPHERTEXT: &str = concat!(
+    "01",
+    "cf0c2f40f8d8356606c5cce34065fdff0461b67f9dd9968f87cc7b82141dfdc0",
+    "7e43b72f2b6d9c84913031534965d0ebc3913c66ea",
+);
+
+/// The expected first key fragment (hex-encoded).
+pub const KAT_KFRAG0: &str = concat!(
+    "fd2884151d208eee08ed25f79ad74f343e3da0641ee6ee1e2e368beb6891d319",
+    "cac7e21c18ffc0294b5015454521db60c662e1495064f6fe2cdbd994dae2826e",
+    "0217f51a31c690be7ffc9c9a1e89b08e50005fc0aaad5734712ff1c602b6b022",
+    "ec037147ab3e74f34f2760f31f79483343fa6d32f08921848806d87251ee4490",
+    "513db45254f2750b27c98c5cb0cd9800f82890356e3edd490f5cc176a0561fe3",
+    "f0670e68043b07f06217d2383009eb121447cb4bd5d9f5ce1bb1121e3356200c",
+    "01213ed6b338c0e025620c0980637359f54902beebd1e7ebd11b6a379ceaf848",
+    "1fbc4541d3a8f9cae1c6c0acba469e502f226193a2255cd0208f2272f1e896fb",
+    "3e6f0101",
+    "0000000000000000ffffffffffffffff",
+);
+
+/// The expected first capsule fragment (hex-encoded).
+pub const KAT_CFRAG0: &str = concat!(
+    "038ed05e81c4af6586dea4faccf0053a3516aaebfeab880692e6c455f6131c26",
+    "7b022d6907d9154b361df398a934fd1ae44d88186eda4b4ee101ed93db352cee",
+    "2058fd2884151d208eee08ed25f79ad74f343e3da0641ee6ee1e2e368beb6891",
+    "d3190217f51a31c690be7ffc9c9a1e89b08e50005fc0aaad5734712ff1c602b6",
+    "b022ec0220b032c83df820acd8422f569d42704371f2de896665c5dcaac6b5e1",
+    "78df6e92026ca877cb38d6d4edb11b8d71b07f2fadd9f137f0db069a963f2cbd",
+    "106d479f55037147ab3e74f34f2760f31f79483343fa6d32f08921848806d872",
+    "51ee4490513d02a0ed1e7e08a101103811ac84867d3547d5a3dde5173338d26c",
+    "c096781252cb44b89a1b32acb3ee3ba2ad1d53258047bc85020f5e86d8ce4f8f",
+    "cfa908a70af7853ed6b338c0e025620c0980637359f54902beebd1e7ebd11b6a",
+    "379ceaf8481fbc4541d3a8f9cae1c6c0acba469e502f226193a2255cd0208f22",
+    "72f1e896fb3e6f",
+);
+
+// The known answers contain ECDSA signatures and legacy SHA-256 hashes.
+#[cfg(all(
+    test,
+    feature = "role-bob",
+    not(feature = "schnorr"),
+    not(feature = "rfc9380"),
+    not(feature = "sha3"),
+    not(feature = "blake3")
+))]
+mod tests {
+
+    use alloc::string::String;
+    use alloc::vec::Vec;
+    use core::fmt::Write;
+
+    use super::{
+        generate, KAT_CAPSULE, KAT_CFRAG0, KAT_CIPHERTEXT, KAT_DELEGATING_PK, KAT_KFRAG0,
+        KAT_METADATA, KAT_NUM_KFRAGS, KAT_PLAINTEXT, KAT_RECEIVING_PK, KAT_SEED, KAT_THRESHOLD,
+        KAT_VERIFYING_PK,
+    };
+    use crate::{
+        decrypt_reencrypted, Capsule, CapsuleFrag, DeserializableFromArray, KeyFrag, PublicKey,
+        RawMetadata, SecretKey,
+    };
+
+    fn to_hex(bytes: &[u8]) -> String {
+        let mut out = String::new();
+        for byte in bytes {
+            write!(out, "{:02x}", byte).unwrap();
+        }
+        out
+    }
+
+    #[test]
+    fn test_known_answer() {
+        let vectors = generate(
+            KAT_SEED,
+            KAT_PLAINTEXT,
+            Some(KAT_METADATA),
+            KAT_THRESHOLD,
+            KAT_NUM_KFRAGS,
+        );
+
+        assert_eq!(to_hex(&vectors.delegating_pk), KAT_DELEGATING_PK);
+        assert_eq!(to_hex(&vectors.verifying_pk), KAT_VERIFYING_PK);
+        assert_eq!(to_hex(&vectors.receiving_pk), KAT_RECEIVING_PK);
+        assert_eq!(to_hex(&vectors.capsule), KAT_CAPSULE);
+        assert_eq!(to_hex(&vectors.ciphertext), KAT_CIPHERTEXT);
+        assert_eq!(to_hex(&vectors.kfrags[0]), KAT_KFRAG0);
+        assert_eq!(to_hex(&vectors.cfrags[0]), KAT_CFRAG0);
+
+        // Check that the vectors are consistent
+        let delegating_pk = PublicKey::from_bytes(&vectors.delegating_pk).unwrap();
+        let verifying_pk = PublicKey::from_bytes(&vectors.verifying_pk).unwrap();
+        let receiving_sk = SecretKey::from_bytes(&vectors.receiving_sk).unwrap();
+        let receiving_pk = PublicKey::from_secret_key(&receiving_sk);
+        let capsule = Capsule::from_bytes(&vectors.capsule).unwrap();
+
+        for kfrag in vectors.kfrags.iter() {
+            KeyFrag::from_bytes(kfrag)
+                .unwrap()
+                .verify(&verifying_pk, Some(&delegating_pk), Some(&receiving_pk))
+                .unwrap();
+        }
+
+        let verified_cfrags: Vec<_> = vectors.cfrags[0..KAT_THRESHOLD]
+            .iter()
+            .map(|cfrag| {
+                CapsuleFrag::from_bytes(cfrag)
+                    .unwrap()
+                    .verify_canonicalized(
+                        &capsule,
+                        &verifying_pk,
+                        &delegating_pk,
+                        &receiving_pk,
+                        Some(KAT_METADATA),
+                        &RawMetadata,
+                    )
+                    .unwrap()
+            })
+            .collect();
+
+        let plaintext = decrypt_reencrypted(
+            &receiving_sk,
+            &delegating_pk,
+            &capsule,
+            &verified_cfrags,
+            &vectors.ciphertext,
+        )
+        .unwrap();
+        assert_eq!(&plaintext as &[u8], KAT_PLAINTEXT);
+    }
+}
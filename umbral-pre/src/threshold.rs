@@ -0,0 +1,382 @@
+//! Threshold decryption without reencryption.
+//!
+//! The delegating secret key is split into `num_shares` [`DecryptionShare`] objects
+//! with Shamir's secret sharing, any `threshold` of which can decrypt a capsule created for
+//! the delegating public key. Each share holder produces a [`PartialDecryption`] of the capsule,
+//! and anyone holding `threshold` of them can recover the key seed
+//! with [`decapsulate_threshold()`] (or decrypt the ciphertext with `decrypt_threshold()`).
+//! No secret key of the combining party is involved, unlike the reencryption path.
+//!
+//! The partial decryptions carry no proof of correctness:
+//! an incorrect one makes the combination fail in the same way as too few of them,
+//! so the share holders must be trusted to produce them honestly.
+
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use core::fmt;
+
+use generic_array::sequence::Concat;
+use generic_array::GenericArray;
+use rand_core::{CryptoRng, OsRng, RngCore};
+use typenum::op;
+use zeroize::Zeroize;
+
+use crate::capsule::Capsule;
+use crate::curve::{CurvePoint, CurveScalar};
+#[cfg(feature = "dem")]
+use crate::dem::{decrypt_with_header, DecryptionError};
+use crate::key_frag::poly_eval;
+use crate::keys::{PublicKey, SecretKey};
+use crate::pre::KeySeed;
+use crate::traits::{
+    DeserializableFromArray, DeserializationError, RepresentableAsArray, SerializableToArray,
+};
+
+/// Errors that can happen when combining partial decryptions.
+#[derive(Debug, PartialEq)]
+pub enum ThresholdDecryptionError {
+    /// An empty list of partial decryptions is given.
+    NoPartialDecryptions,
+    /// Some of the partial decryptions were made with the same share.
+    RepeatingPartialDecryptions,
+    /// The partial decryptions do not open the capsule: there are fewer of them than the threshold,
+    /// or some of them were made for a different capsule, with shares of a different key,
+    /// or are incorrect.
+    NotEnoughPartialDecryptions,
+    /// An error when decrypting the ciphertext. See [`DecryptionError`] for the options.
+    #[cfg(feature = "dem")]
+    OnDecryption(DecryptionError),
+}
+
+impl fmt::Display for ThresholdDecryptionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NoPartialDecryptions => write!(f, "no partial decryptions given"),
+            Self::RepeatingPartialDecryptions => {
+                write!(f, "some of the partial decryptions are repeated")
+            }
+            Self::NotEnoughPartialDecryptions => write!(f, "not enough partial decryptions"),
+            #[cfg(feature = "dem")]
+            Self::OnDecryption(err) => write!(f, "failed to decrypt the ciphertext: {}", err),
+        }
+    }
+}
+
+/// Errors that can happen when splitting a secret key into decryption shares.
+#[derive(Debug, PartialEq)]
+pub enum ShareGenerationError {
+    /// The threshold is zero or greater than the number of shares.
+    InvalidThreshold,
+}
+
+impl fmt::Display for ShareGenerationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidThreshold => write!(
+                f,
+                "the threshold must be between 1 and the number of shares"
+            ),
+        }
+    }
+}
+
+type ScalarSize = <CurveScalar as RepresentableAsArray>::Size;
+type PointSize = <CurvePoint as RepresentableAsArray>::Size;
+
+/// A share of the delegating secret key, allowing one to produce partial decryptions.
+///
+/// The share is zeroized when dropped.
+#[derive(Clone, PartialEq)] // Debug is implemented manually, to avoid exposing the share.
+pub struct DecryptionShare {
+    index: CurveScalar,
+    share: CurveScalar,
+}
+
+impl fmt::Debug for DecryptionShare {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("DecryptionShare(<redacted>)")
+    }
+}
+
+impl Drop for DecryptionShare {
+    fn drop(&mut self) {
+        self.share.zeroize();
+    }
+}
+
+impl RepresentableAsArray for DecryptionShare {
+    type Size = op!(ScalarSize + ScalarSize);
+}
+
+impl SerializableToArray for DecryptionShare {
+    fn to_array(&self) -> GenericArray<u8, Self::Size> {
+        self.index.to_array().concat(self.share.to_array())
+    }
+}
+
+impl DeserializableFromArray for DecryptionShare {
+    fn from_array(arr: &GenericArray<u8, Self::Size>) -> Result<Self, DeserializationError> {
+        let (index, rest) = CurveScalar::take(*arr)?;
+        let share = CurveScalar::take_last(rest)?;
+        if index.is_zero() {
+            return Err(DeserializationError::ConstructionFailure);
+        }
+        Ok(Self { index, share })
+    }
+}
+
+impl DecryptionShare {
+    /// Produces the partial decryption of the capsule with this share.
+    pub fn decrypt_partial(&self, capsule: &Capsule) -> PartialDecryption {
+        PartialDecryption {
+            index: self.index,
+            point_e1: &capsule.point_e * &self.share,
+            point_v1: &capsule.point_v * &self.share,
+        }
+    }
+}
+
+/// A partial decryption of a capsule, made with a [`DecryptionShare`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct PartialDecryption {
+    index: CurveScalar,
+    point_e1: CurvePoint,
+    point_v1: CurvePoint,
+}
+
+impl RepresentableAsArray for PartialDecryption {
+    type Size = op!(ScalarSize + PointSize + PointSize);
+}
+
+impl SerializableToArray for PartialDecryption {
+    fn to_array(&self) -> GenericArray<u8, Self::Size> {
+        self.index
+            .to_array()
+            .concat(self.point_e1.to_array())
+            .concat(self.point_v1.to_array())
+    }
+}
+
+impl DeserializableFromArray for PartialDecryption {
+    fn from_array(arr: &GenericArray<u8, Self::Size>) -> Result<Self, DeserializationError> {
+        let (index, rest) = CurveScalar::take(*arr)?;
+        let (point_e1, rest) = CurvePoint::take(rest)?;
+        let point_v1 = CurvePoint::take_last(rest)?;
+        if index.is_zero() {
+            return Err(DeserializationError::ConstructionFailure);
+        }
+        Ok(Self {
+            index,
+            point_e1,
+            point_v1,
+        })
+    }
+}
+
+/// Splits `delegating_sk` into `num_shares` decryption shares,
+/// any `threshold` of which are needed to decrypt a capsule
+/// created for the corresponding public key.
+///
+/// Returns [`ShareGenerationError::InvalidThreshold`] unless `1 <= threshold <= num_shares`.
+pub fn generate_decryption_shares(
+    delegating_sk: &SecretKey,
+    threshold: usize,
+    num_shares: usize,
+) -> Result<Box<[DecryptionShare]>, ShareGenerationError> {
+    generate_decryption_shares_with_rng(&mut OsRng, delegating_sk, threshold, num_shares)
+}
+
+/// Same as [`generate_decryption_shares()`], but uses the given RNG instead of the default one.
+pub fn generate_decryption_shares_with_rng(
+    rng: &mut (impl CryptoRng + RngCore),
+    delegating_sk: &SecretKey,
+    threshold: usize,
+    num_shares: usize,
+) -> Result<Box<[DecryptionShare]>, ShareGenerationError> {
+    // With a zero threshold every share would be the secret key itself.
+    if threshold == 0 || threshold > num_shares {
+        return Err(ShareGenerationError::InvalidThreshold);
+    }
+
+    // Coefficients of the generating polynomial, with the secret key as the constant term
+    let mut coefficients = Vec::<CurveScalar>::with_capacity(threshold);
+    coefficients.push(delegating_sk.to_secret_scalar());
+    for _i in 1..threshold {
+        coefficients.push(CurveScalar::random_nonzero(rng));
+    }
+
+    // As with the key fragments, the indices are random,
+    // so that a share reveals nothing about its position among the others.
+    let mut shares = Vec::<DecryptionShare>::with_capacity(num_shares);
+    while shares.len() < num_shares {
+        let index = CurveScalar::random_nonzero(rng);
+        if shares.iter().any(|share| share.index == index) {
+            continue;
+        }
+        shares.push(DecryptionShare {
+            index,
+            share: poly_eval(&coefficients, &index),
+        });
+    }
+
+    coefficients.zeroize();
+    Ok(shares.into_boxed_slice())
+}
+
+/// Recovers the key seed of the capsule from at least `threshold` partial decryptions
+/// made with the shares of the secret key corresponding to `delegating_pk`
+/// (the same key seed as the one returned by [`encapsulate()`](crate::encapsulate)).
+///
+/// `delegating_pk` is used to check the validity of the result.
+pub fn decapsulate_threshold(
+    delegating_pk: &PublicKey,
+    capsule: &Capsule,
+    partial_decryptions: &[PartialDecryption],
+) -> Result<KeySeed, ThresholdDecryptionError> {
+    if partial_decryptions.is_empty() {
+        return Err(ThresholdDecryptionError::NoPartialDecryptions);
+    }
+
+    let xs: Vec<_> = partial_decryptions.iter().map(|pd| pd.index).collect();
+    let mut sorted_xs: Vec<_> = xs.iter().map(|x| x.to_array()).collect();
+    sorted_xs.sort_unstable();
+    if sorted_xs.windows(2).any(|pair| pair[0] == pair[1]) {
+        return Err(ThresholdDecryptionError::RepeatingPartialDecryptions);
+    }
+
+    let e1s: Vec<_> = partial_decryptions.iter().map(|pd| pd.point_e1).collect();
+    let v1s: Vec<_> = partial_decryptions.iter().map(|pd| pd.point_v1).collect();
+    capsule
+        .open_threshold_parts(delegating_pk, &xs, &e1s, &v1s)
+        .map(|key_seed| key_seed.to_array())
+        .ok_or(ThresholdDecryptionError::NotEnoughPartialDecryptions)
+}
+
+/// Decrypts the ciphertext (created by [`encrypt()`](crate::encrypt) for `delegating_pk`)
+/// using at least `threshold` partial decryptions of its capsule.
+#[cfg(feature = "dem")]
+pub fn decrypt_threshold(
+    delegating_pk: &PublicKey,
+    capsule: &Capsule,
+    partial_decryptions: &[PartialDecryption],
+    ciphertext: impl AsRef<[u8]>,
+) -> Result<Box<[u8]>, ThresholdDecryptionError> {
+    let key_seed = decapsulate_threshold(delegating_pk, capsule, partial_decryptions)?;
+    decrypt_with_header(&key_seed, ciphertext.as_ref(), &capsule.to_array(), None)
+        .map_err(ThresholdDecryptionError::OnDecryption)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use alloc::vec::Vec;
+
+    use super::{
+        decapsulate_threshold, generate_decryption_shares, DecryptionShare, PartialDecryption,
+        ShareGenerationError, ThresholdDecryptionError,
+    };
+    use crate::{
+        decapsulate_original, encapsulate, DeserializableFromArray, PublicKey, SecretKey,
+        SerializableToArray,
+    };
+
+    #[test]
+    fn test_threshold_decryption() {
+        let delegating_sk = SecretKey::random();
+        let delegating_pk = PublicKey::from_secret_key(&delegating_sk);
+
+        let (capsule, key_seed) = encapsulate(&delegating_pk);
+        let shares = generate_decryption_shares(&delegating_sk, 3, 5).unwrap();
+
+        let partials: Vec<_> = shares
+            .iter()
+            .map(|share| share.decrypt_partial(&capsule))
+            .collect();
+
+        // Any `threshold` partial decryptions recover the key seed.
+        assert_eq!(
+            decapsulate_threshold(&delegating_pk, &capsule, &partials[..3]),
+            Ok(key_seed)
+        );
+        assert_eq!(
+            decapsulate_threshold(&delegating_pk, &capsule, &partials[2..]),
+            Ok(key_seed)
+        );
+        assert_eq!(decapsulate_original(&delegating_sk, &capsule), key_seed);
+
+        assert_eq!(
+            decapsulate_threshold(&delegating_pk, &capsule, &[]),
+            Err(ThresholdDecryptionError::NoPartialDecryptions)
+        );
+        assert_eq!(
+            decapsulate_threshold(&delegating_pk, &capsule, &partials[..2]),
+            Err(ThresholdDecryptionError::NotEnoughPartialDecryptions)
+        );
+        let repeating = [
+            partials[0].clone(),
+            partials[1].clone(),
+            partials[0].clone(),
+        ];
+        assert_eq!(
+            decapsulate_threshold(&delegating_pk, &capsule, &repeating),
+            Err(ThresholdDecryptionError::RepeatingPartialDecryptions)
+        );
+
+        // Partial decryptions of a different capsule cannot be mixed in.
+        let (other_capsule, _key_seed) = encapsulate(&delegating_pk);
+        let mixed = [
+            partials[0].clone(),
+            partials[1].clone(),
+            shares[2].decrypt_partial(&other_capsule),
+        ];
+        assert_eq!(
+            decapsulate_threshold(&delegating_pk, &capsule, &mixed),
+            Err(ThresholdDecryptionError::NotEnoughPartialDecryptions)
+        );
+
+        // Serialization round-trips.
+        let share_back = DecryptionShare::from_array(&shares[0].to_array()).unwrap();
+        assert_eq!(share_back, shares[0]);
+        let partial_back = PartialDecryption::from_array(&partials[0].to_array()).unwrap();
+        assert_eq!(partial_back, partials[0]);
+    }
+
+    #[test]
+    fn test_invalid_threshold() {
+        let delegating_sk = SecretKey::random();
+        assert_eq!(
+            generate_decryption_shares(&delegating_sk, 0, 3),
+            Err(ShareGenerationError::InvalidThreshold)
+        );
+        assert_eq!(
+            generate_decryption_shares(&delegating_sk, 4, 3),
+            Err(ShareGenerationError::InvalidThreshold)
+        );
+        assert_eq!(
+            generate_decryption_shares(&delegating_sk, 3, 3)
+                .unwrap()
+                .len(),
+            3
+        );
+    }
+
+    #[cfg(feature = "dem")]
+    #[test]
+    fn test_decrypt_threshold() {
+        let delegating_sk = SecretKey::random();
+        let delegating_pk = PublicKey::from_secret_key(&delegating_sk);
+
+        let plaintext = b"peace at dawn";
+        let (capsule, ciphertext) = crate::encrypt(&delegating_pk, plaintext).unwrap();
+        let shares = generate_decryption_shares(&delegating_sk, 2, 3).unwrap();
+        let partials: Vec<_> = shares
+            .iter()
+            .skip(1)
+            .map(|share| share.decrypt_partial(&capsule))
+            .collect();
+
+        let plaintext_back =
+            super::decrypt_threshold(&delegating_pk, &capsule, &partials, &ciphertext).unwrap();
+        assert_eq!(&plaintext_back as &[u8], plaintext);
+    }
+}
@@ -1,10 +1,34 @@
+use alloc::boxed::Box;
+use alloc::string::String;
+use alloc::vec::Vec;
 use core::cmp::Ordering;
+use core::convert::TryFrom;
+use core::fmt;
+use core::hash::{Hash, Hasher};
 use core::ops::Sub;
 use generic_array::sequence::Split;
 use generic_array::{ArrayLength, GenericArray};
 use typenum::{Diff, Unsigned, U1};
 
+use crate::encoding::{decode_base64, encode_base64};
+use crate::hashing::HASH_FUNCTION_ID;
+use crate::keys::SIGNATURE_SCHEME;
+#[cfg(feature = "dem")]
+use crate::multi::RecipientKey;
+#[cfg(feature = "policy")]
+use crate::PolicyId;
+use crate::{
+    AuditableCapsuleFrag, Capsule, CapsuleFrag, KeyFrag, KeyFragID, PublicKey, RefreshKey,
+    SecretKey, SecretKeyFactory, Signature, ValidityWindow,
+};
+
 /// Errors that can happen during object deserialization.
+///
+/// A curve point is only accepted in the canonical compressed SEC1 encoding
+/// of a point on the curve other than the identity (otherwise [`InvalidPoint`](Self::InvalidPoint)
+/// is returned), and a scalar only in the canonical encoding of a value less than the curve order
+/// (otherwise [`InvalidScalar`](Self::InvalidScalar) is returned).
+/// This applies to every object containing points or scalars.
 #[derive(Debug, PartialEq)]
 pub enum DeserializationError {
     /// Failed to construct the object from a given bytestring (with the correct length).
@@ -13,6 +37,31 @@ pub enum DeserializationError {
     NotEnoughBytes,
     /// The given bytestring is too long.
     TooManyBytes,
+    /// The versioned envelope has a format version this library does not support.
+    UnsupportedVersion(u8),
+    /// The string is not a valid text encoding (hex or Base64) of a bytestring.
+    InvalidEncoding,
+    /// The bytes are not a canonical encoding of a curve point, the point is not on the curve,
+    /// or it is the identity.
+    InvalidPoint,
+    /// The bytes are not a canonical encoding of a scalar (the value is not less than the curve order).
+    InvalidScalar,
+}
+
+impl fmt::Display for DeserializationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::ConstructionFailure => write!(f, "failed to construct the object"),
+            Self::NotEnoughBytes => write!(f, "not enough bytes"),
+            Self::TooManyBytes => write!(f, "too many bytes"),
+            Self::UnsupportedVersion(version) => {
+                write!(f, "unsupported format version: {}", version)
+            }
+            Self::InvalidEncoding => write!(f, "invalid text encoding"),
+            Self::InvalidPoint => write!(f, "invalid curve point"),
+            Self::InvalidScalar => write!(f, "invalid scalar"),
+        }
+    }
 }
 
 /// A trait denoting that the object can be represented as an array of bytes
@@ -31,6 +80,17 @@ pub trait RepresentableAsArray: Sized {
 pub trait SerializableToArray: RepresentableAsArray {
     /// Produces a byte array with the object's contents.
     fn to_array(&self) -> GenericArray<u8, Self::Size>;
+
+    /// Same as [`to_array()`](`Self::to_array()`), but returns a boxed slice
+    /// (for the callers that do not need the size at compile time).
+    fn to_bytes(&self) -> Box<[u8]> {
+        self.to_array().as_slice().into()
+    }
+
+    /// Encodes the object's contents as standard Base64 (with padding).
+    fn to_base64(&self) -> String {
+        encode_base64(&self.to_array())
+    }
 }
 
 /// A trait denoting that the object can be deserialized from an array of bytes
@@ -52,6 +112,13 @@ pub trait DeserializableFromArray: RepresentableAsArray {
         }
     }
 
+    /// Attempts to produce the object back from the Base64 encoding
+    /// produced by [`to_base64()`](`SerializableToArray::to_base64()`).
+    fn from_base64(s: &str) -> Result<Self, DeserializationError> {
+        let bytes = decode_base64(s).ok_or(DeserializationError::InvalidEncoding)?;
+        Self::from_bytes(&bytes)
+    }
+
     /// Used to implement [`from_array()`](`Self::from_array()`) for structs whose fields
     /// implement [`SerializableToArray`].
     ///
@@ -78,6 +145,125 @@ pub trait DeserializableFromArray: RepresentableAsArray {
     }
 }
 
+/// The base of the envelope version for the legacy hashing.
+#[cfg(not(feature = "rfc9380"))]
+const HASHING_VERSION: u8 = 1;
+
+/// The objects produced with the RFC 9380 hashing are not compatible with the legacy ones,
+/// so they get their own envelope version.
+#[cfg(feature = "rfc9380")]
+const HASHING_VERSION: u8 = 2;
+
+/// The current version of the envelope produced by
+/// [`to_versioned_bytes()`](`VersionedSerialization::to_versioned_bytes()`).
+///
+/// Every build option that changes the objects (the hashing method, the hash function
+/// and the signature scheme) gets its own version, so that the envelopes of an incompatible
+/// build are rejected at deserialization. The default build has version 1,
+/// and the `rfc9380` one has version 2; `sha3` and `blake3` add 4 and 8, and `schnorr` adds 16.
+pub(crate) const ENVELOPE_VERSION: u8 =
+    HASHING_VERSION + 4 * HASH_FUNCTION_ID + 16 * (SIGNATURE_SCHEME as u8);
+
+/// A trait denoting that the object can be serialized into a versioned envelope:
+/// a format version byte and a type tag byte followed by the object's array representation.
+///
+/// Unlike the bare [`to_array()`](`SerializableToArray::to_array()`) layout,
+/// the envelope can be told apart from the data of a different type or a future format,
+/// so it is the preferred representation for storage and transmission.
+pub trait VersionedSerialization: SerializableToArray + DeserializableFromArray {
+    /// The byte identifying the object type in the envelope.
+    const TYPE_TAG: u8;
+
+    /// Produces the versioned envelope with the object's contents.
+    fn to_versioned_bytes(&self) -> Box<[u8]> {
+        let mut result = Vec::with_capacity(2 + Self::Size::to_usize());
+        result.push(ENVELOPE_VERSION);
+        result.push(Self::TYPE_TAG);
+        result.extend_from_slice(&self.to_array());
+        result.into_boxed_slice()
+    }
+
+    /// Attempts to produce the object back from the versioned envelope.
+    fn from_versioned_bytes(bytes: impl AsRef<[u8]>) -> Result<Self, DeserializationError> {
+        let bytes = bytes.as_ref();
+        if bytes.len() < 2 {
+            return Err(DeserializationError::NotEnoughBytes);
+        }
+        if bytes[0] != ENVELOPE_VERSION {
+            return Err(DeserializationError::UnsupportedVersion(bytes[0]));
+        }
+        if bytes[1] != Self::TYPE_TAG {
+            return Err(DeserializationError::ConstructionFailure);
+        }
+        Self::from_bytes(&bytes[2..])
+    }
+}
+
+// A blanket implementation for all `DeserializableFromArray` types would conflict
+// with the blanket `TryFrom` implementation in `core`.
+macro_rules! impl_try_from_bytes {
+    ($($type:ty),* $(,)?) => {
+        $(
+            impl TryFrom<&[u8]> for $type {
+                type Error = DeserializationError;
+
+                fn try_from(bytes: &[u8]) -> Result<Self, Self::Error> {
+                    Self::from_bytes(bytes)
+                }
+            }
+        )*
+    };
+}
+
+impl_try_from_bytes!(
+    Capsule,
+    CapsuleFrag,
+    AuditableCapsuleFrag,
+    KeyFrag,
+    KeyFragID,
+    ValidityWindow,
+    RefreshKey,
+    PublicKey,
+    SecretKey,
+    SecretKeyFactory,
+    Signature,
+);
+#[cfg(feature = "policy")]
+impl_try_from_bytes!(PolicyId);
+#[cfg(feature = "dem")]
+impl_try_from_bytes!(RecipientKey);
+
+// Ordering and hashing follow the byte representation,
+// which is canonical for these types (and consistent with `PartialEq`),
+// so that they can be used as keys in maps and sets.
+macro_rules! impl_ord_hash {
+    ($($type:ty),* $(,)?) => {
+        $(
+            impl Eq for $type {}
+
+            impl PartialOrd for $type {
+                fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+                    Some(self.cmp(other))
+                }
+            }
+
+            impl Ord for $type {
+                fn cmp(&self, other: &Self) -> Ordering {
+                    self.to_array().cmp(&other.to_array())
+                }
+            }
+
+            impl Hash for $type {
+                fn hash<H: Hasher>(&self, state: &mut H) {
+                    self.to_array().hash(state)
+                }
+            }
+        )*
+    };
+}
+
+impl_ord_hash!(PublicKey, Capsule, KeyFrag, CapsuleFrag);
+
 impl RepresentableAsArray for bool {
     type Size = U1;
 }
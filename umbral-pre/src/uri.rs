@@ -0,0 +1,198 @@
+//! Capability URIs referencing delegatable objects in links and QR codes.
+
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use core::fmt;
+use core::str::FromStr;
+
+use generic_array::GenericArray;
+use typenum::U32;
+
+use crate::capsule::Capsule;
+use crate::hashing_ds::{hash_to_capsule_fingerprint, hash_to_key_fingerprint};
+use crate::keys::PublicKey;
+
+const SCHEME: &str = "umbral:";
+const FORMAT_VERSION: &str = "1";
+
+/// Errors that can happen when parsing a [`CapabilityUri`].
+#[derive(Debug, PartialEq)]
+pub enum UriError {
+    /// The string does not start with `umbral:`.
+    InvalidScheme,
+    /// The URI has a format version this library does not support.
+    UnsupportedVersion,
+    /// The URI components are missing or malformed.
+    InvalidFormat,
+}
+
+impl fmt::Display for UriError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidScheme => write!(f, "not an umbral: URI"),
+            Self::UnsupportedVersion => write!(f, "unsupported URI format version"),
+            Self::InvalidFormat => write!(f, "invalid URI format"),
+        }
+    }
+}
+
+/// A reference to a delegatable object: the fingerprint of its capsule,
+/// the identifier of the policy, and the hash of the verifying key of the policy.
+/// Contains no secrets, and can be put in links and QR codes.
+///
+/// The canonical form is
+/// `umbral:1/<capsule fingerprint>/<policy id>/<verifying key hash>`,
+/// with every component in lowercase hex.
+/// It is produced by the `Display` implementation and parsed by `FromStr`,
+/// which only accepts the canonical form.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CapabilityUri {
+    capsule_fingerprint: GenericArray<u8, U32>,
+    policy_id: Box<[u8]>,
+    verifying_key_hash: GenericArray<u8, U32>,
+}
+
+impl CapabilityUri {
+    /// Creates a URI referencing the given capsule under the given policy
+    /// (normally identified by a [`PolicyId`](crate::PolicyId)).
+    pub fn new(capsule: &Capsule, policy_id: &[u8], verifying_pk: &PublicKey) -> Self {
+        Self {
+            capsule_fingerprint: hash_to_capsule_fingerprint(capsule),
+            policy_id: policy_id.into(),
+            verifying_key_hash: hash_to_key_fingerprint(verifying_pk),
+        }
+    }
+
+    /// Returns the policy identifier.
+    pub fn policy_id(&self) -> &[u8] {
+        &self.policy_id
+    }
+
+    /// Returns `true` if the URI references the given capsule.
+    pub fn matches_capsule(&self, capsule: &Capsule) -> bool {
+        self.capsule_fingerprint == hash_to_capsule_fingerprint(capsule)
+    }
+
+    /// Returns `true` if the URI references a policy signed with the given key.
+    pub fn matches_verifying_key(&self, verifying_pk: &PublicKey) -> bool {
+        self.verifying_key_hash == hash_to_key_fingerprint(verifying_pk)
+    }
+}
+
+fn write_hex(f: &mut fmt::Formatter<'_>, bytes: &[u8]) -> fmt::Result {
+    for byte in bytes {
+        write!(f, "{:02x}", byte)?;
+    }
+    Ok(())
+}
+
+fn hex_digit(c: u8) -> Option<u8> {
+    match c {
+        b'0'..=b'9' => Some(c - b'0'),
+        b'a'..=b'f' => Some(c - b'a' + 10),
+        _ => None,
+    }
+}
+
+fn parse_hex(s: &str) -> Option<Vec<u8>> {
+    let pairs = s.as_bytes().chunks_exact(2);
+    if !pairs.remainder().is_empty() {
+        return None;
+    }
+    pairs
+        .map(|pair| Some(hex_digit(pair[0])? << 4 | hex_digit(pair[1])?))
+        .collect()
+}
+
+fn parse_digest(s: &str) -> Option<GenericArray<u8, U32>> {
+    let bytes = parse_hex(s)?;
+    if bytes.len() != 32 {
+        return None;
+    }
+    Some(GenericArray::clone_from_slice(&bytes))
+}
+
+impl fmt::Display for CapabilityUri {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{}/", SCHEME, FORMAT_VERSION)?;
+        write_hex(f, &self.capsule_fingerprint)?;
+        write!(f, "/")?;
+        write_hex(f, &self.policy_id)?;
+        write!(f, "/")?;
+        write_hex(f, &self.verifying_key_hash)
+    }
+}
+
+impl FromStr for CapabilityUri {
+    type Err = UriError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if !s.starts_with(SCHEME) {
+            return Err(UriError::InvalidScheme);
+        }
+        let mut parts = s[SCHEME.len()..].split('/');
+        if parts.next() != Some(FORMAT_VERSION) {
+            return Err(UriError::UnsupportedVersion);
+        }
+        let components: Vec<&str> = parts.collect();
+        if components.len() != 3 {
+            return Err(UriError::InvalidFormat);
+        }
+        let capsule_fingerprint = parse_digest(components[0]).ok_or(UriError::InvalidFormat)?;
+        let policy_id = parse_hex(components[1]).ok_or(UriError::InvalidFormat)?;
+        let verifying_key_hash = parse_digest(components[2]).ok_or(UriError::InvalidFormat)?;
+        Ok(Self {
+            capsule_fingerprint,
+            policy_id: policy_id.into_boxed_slice(),
+            verifying_key_hash,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use alloc::string::ToString;
+
+    use super::{CapabilityUri, UriError};
+    use crate::{encapsulate, PublicKey, SecretKey};
+
+    #[test]
+    fn test_uri() {
+        let delegating_pk = PublicKey::from_secret_key(&SecretKey::random());
+        let verifying_pk = PublicKey::from_secret_key(&SecretKey::random());
+        let (capsule, _key_seed) = encapsulate(&delegating_pk);
+
+        let uri = CapabilityUri::new(&capsule, b"policy", &verifying_pk);
+        let uri_str = uri.to_string();
+        assert!(uri_str.starts_with("umbral:1/"));
+        assert!(uri_str.contains("/706f6c696379/"));
+
+        let uri_back: CapabilityUri = uri_str.parse().unwrap();
+        assert_eq!(uri_back, uri);
+        assert!(uri_back.matches_capsule(&capsule));
+        assert!(uri_back.matches_verifying_key(&verifying_pk));
+        assert!(!uri_back.matches_verifying_key(&delegating_pk));
+        assert_eq!(uri_back.policy_id(), b"policy");
+
+        assert_eq!(
+            "http://example.com".parse::<CapabilityUri>(),
+            Err(UriError::InvalidScheme)
+        );
+        assert_eq!(
+            uri_str.replacen(":1/", ":2/", 1).parse::<CapabilityUri>(),
+            Err(UriError::UnsupportedVersion)
+        );
+        assert_eq!(
+            uri_str
+                .to_uppercase()
+                .replacen("UMBRAL", "umbral", 1)
+                .parse::<CapabilityUri>(),
+            Err(UriError::InvalidFormat)
+        );
+        assert_eq!(
+            (uri_str.clone() + "/").parse::<CapabilityUri>(),
+            Err(UriError::InvalidFormat)
+        );
+    }
+}